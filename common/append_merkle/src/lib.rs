@@ -3,6 +3,7 @@ mod sha3;
 
 use anyhow::{anyhow, bail, Result};
 use ethereum_types::H256;
+use rayon::prelude::*;
 use ssz::{Decode, Encode};
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -13,6 +14,10 @@ use std::marker::PhantomData;
 pub use proof::{Proof, RangeProof};
 pub use sha3::Sha3Algorithm;
 
+/// Minimum number of nodes in a layer to compute their parents in parallel, which speeds up
+/// building a large tree, e.g. the flow tree upon startup.
+const PARALLEL_HASH_MIN_NODES: usize = 4096;
+
 pub struct AppendMerkleTree<E: HashElement, A: Algorithm<E>> {
     /// Keep all the nodes in the latest version. `layers[0]` is the layer of leaves.
     layers: Vec<Vec<E>>,
@@ -230,6 +235,14 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
     pub fn check_root(&self, root: &E) -> bool {
         self.delta_nodes_map.contains_key(root)
     }
+
+    /// Returns the root committed with `tx_seq`, or `None` if it is not kept, e.g. committed
+    /// before the tree is reconstructed, or the tree is empty then.
+    pub fn tx_seq_root(&self, tx_seq: u64) -> Option<&E> {
+        self.tx_seq_to_root_map
+            .get(&tx_seq)
+            .filter(|root| **root != E::null())
+    }
 }
 
 impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
@@ -282,34 +295,31 @@ impl<E: HashElement, A: Algorithm<E>> AppendMerkleTree<E, A> {
             if end_index % 2 == 1 && end_index != self.layers[height].len() {
                 end_index += 1;
             }
-            let mut i = 0;
-            let mut iter = self.layers[height][start_index..end_index].chunks_exact(2);
+            // If either child is null (unknown), we cannot compute the parent hash.
+            // Note that if we are recompute a range of an existing tree,
+            // we do not need to keep these possibly null parent. This is only saved
+            // for the case of constructing a new tree from the leaves.
+            let compute_parent = |children: &[E]| match children {
+                [left, right] if *left != E::null() && *right != E::null() => {
+                    A::parent(left, right)
+                }
+                [single] if *single != E::null() => A::parent_single(single),
+                _ => E::null(),
+            };
+            let children = &self.layers[height][start_index..end_index];
+            // The last one is the single child of the right most parent if the length is odd.
+            let parents: Vec<E> = if children.len() >= PARALLEL_HASH_MIN_NODES {
+                children.par_chunks(2).map(compute_parent).collect()
+            } else {
+                children.chunks(2).map(compute_parent).collect()
+            };
             // We cannot modify the parent layer while iterating the child layer,
             // so just keep the changes and update them later.
-            let mut parent_update = Vec::new();
-            while let Some([left, right]) = iter.next() {
-                // If either left or right is null (unknown), we cannot compute the parent hash.
-                // Note that if we are recompute a range of an existing tree,
-                // we do not need to keep these possibly null parent. This is only saved
-                // for the case of constructing a new tree from the leaves.
-                let parent = if *left == E::null() || *right == E::null() {
-                    E::null()
-                } else {
-                    A::parent(left, right)
-                };
-                parent_update.push((next_layer_start_index + i, parent));
-                i += 1;
-            }
-            if let [r] = iter.remainder() {
-                // Same as above.
-                let parent = if *r == E::null() {
-                    E::null()
-                } else {
-                    A::parent_single(r)
-                };
-                parent_update.push((next_layer_start_index + i, parent));
-            }
-            if !parent_update.is_empty() {
+            let parent_update = parents
+                .into_iter()
+                .enumerate()
+                .map(|(i, parent)| (next_layer_start_index + i, parent));
+            if start_index < end_index {
                 self.before_extend_layer(height + 1);
             }
             // `parent_update` is in increasing order by `parent_index`, so
@@ -504,7 +514,7 @@ macro_rules! ensure_eq {
 #[cfg(test)]
 mod tests {
     use crate::sha3::Sha3Algorithm;
-    use crate::AppendMerkleTree;
+    use crate::{Algorithm, AppendMerkleTree, PARALLEL_HASH_MIN_NODES};
     use ethereum_types::H256;
 
     #[test]
@@ -531,7 +541,38 @@ mod tests {
             merkle.append_list(data[data.len() - 6..].to_vec());
             merkle.commit(Some(2));
             verify(&data, &merkle);
+            assert_eq!(merkle.tx_seq_root(2), Some(merkle.root()));
+            assert_ne!(merkle.tx_seq_root(1), Some(merkle.root()));
+            assert_eq!(merkle.tx_seq_root(3), None);
+        }
+    }
+
+    #[test]
+    fn test_parallel_recompute() {
+        // large enough to compute the lower layers in parallel
+        let leaves: Vec<H256> = (0..3 * PARALLEL_HASH_MIN_NODES + 1)
+            .map(|_| H256::random())
+            .collect();
+        let mut layer = leaves.clone();
+        while layer.len() > 1 {
+            layer = layer
+                .chunks(2)
+                .map(|children| match children {
+                    [left, right] => Sha3Algorithm::parent(left, right),
+                    [single] => Sha3Algorithm::parent_single(single),
+                    _ => unreachable!(),
+                })
+                .collect();
         }
+
+        let merkle = AppendMerkleTree::<H256, Sha3Algorithm>::new(leaves.clone(), None);
+        assert_eq!(merkle.root(), &layer[0]);
+
+        // the same as the subtrees of single leaves
+        let subtrees = leaves.into_iter().map(|leaf| (1, leaf)).collect();
+        let merkle =
+            AppendMerkleTree::<H256, Sha3Algorithm>::new_with_subtrees(subtrees, None).unwrap();
+        assert_eq!(merkle.root(), &layer[0]);
     }
 
     fn verify(data: &Vec<H256>, merkle: &AppendMerkleTree<H256, Sha3Algorithm>) {
@@ -37,7 +37,8 @@ impl<T: HashElement> Proof<T> {
     fn validate_integrity<A: Algorithm<T>>(&self) -> bool {
         let size = self.lemma.len();
 
-        if size < 2 {
+        // the proof may be decoded from untrusted input
+        if size < 2 || self.path.len() != size - 2 || self.path.len() >= usize::BITS as usize {
             return false;
         }
         let mut h = self.item();
@@ -123,7 +124,10 @@ impl<E: HashElement> RangeProof<E> {
         if range_leaves.is_empty() {
             bail!("Empty range");
         }
-        let end_position = start_position + range_leaves.len() - 1;
+        let end_position = match start_position.checked_add(range_leaves.len() - 1) {
+            Some(end_position) => end_position,
+            None => bail!("Range position overflow"),
+        };
         ensure_eq!(self.left_proof.item(), range_leaves[0]);
         ensure_eq!(
             self.right_proof.item(),
@@ -158,10 +162,50 @@ impl<E: HashElement> RangeProof<E> {
             }
             children_layer = parent_layer;
         }
-        assert_eq!(children_layer.len(), 1);
-        let computed_root = children_layer.pop().unwrap();
+        ensure_eq!(children_layer.len(), 1);
+        let computed_root = children_layer.pop().expect("not empty");
         ensure_eq!(computed_root, self.root());
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppendMerkleTree, Sha3Algorithm};
+    use ethereum_types::H256;
+
+    #[test]
+    fn test_malformed_proof() {
+        let leaf = H256::random();
+
+        // lemma and path length mismatch
+        let proof = Proof {
+            lemma: vec![leaf; 4],
+            path: vec![true],
+        };
+        assert!(proof.validate::<Sha3Algorithm>(&leaf, 0).is_err());
+
+        // position overflow
+        let proof = Proof {
+            lemma: vec![leaf; 66],
+            path: vec![false; 64],
+        };
+        assert!(proof.validate::<Sha3Algorithm>(&leaf, 0).is_err());
+    }
+
+    #[test]
+    fn test_range_position_overflow() {
+        let data: Vec<H256> = (0..4).map(|_| H256::random()).collect();
+        let mut merkle = AppendMerkleTree::<H256, Sha3Algorithm>::new(vec![H256::zero()], None);
+        merkle.append_list(data.clone());
+        merkle.commit(Some(0));
+
+        let range_proof = merkle.gen_range_proof(1, 4).unwrap();
+        assert!(range_proof
+            .validate::<Sha3Algorithm>(&data[..3], usize::MAX)
+            .is_err());
+        assert!(range_proof.validate::<Sha3Algorithm>(&data[..3], 1).is_ok());
+    }
+}
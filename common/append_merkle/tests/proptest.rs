@@ -0,0 +1,192 @@
+//! Property-based tests that drive `AppendMerkleTree` with random operation
+//! sequences and compare it against a naive tree built from the full leaf list.
+
+use append_merkle::{Algorithm, AppendMerkleTree, HashElement, Sha3Algorithm};
+use ethereum_types::H256;
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+type Tree = AppendMerkleTree<H256, Sha3Algorithm>;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Append(H256),
+    AppendList(Vec<H256>),
+    /// Append a subtree of `depth` whose real leaves are the given list.
+    AppendSubtree(usize, Vec<H256>),
+    FillLeaf(Index),
+    UpdateLast(H256),
+    Commit,
+}
+
+fn leaf() -> impl Strategy<Value = H256> {
+    any::<[u8; 32]>()
+        .prop_map(H256::from)
+        .prop_filter("leaf must not be null", |h| !h.is_null())
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => leaf().prop_map(Op::Append),
+        2 => prop::collection::vec(leaf(), 1..8).prop_map(Op::AppendList),
+        2 => (1usize..=4)
+            .prop_flat_map(|depth| {
+                prop::collection::vec(leaf(), 1 << (depth - 1))
+                    .prop_map(move |leaves| Op::AppendSubtree(depth, leaves))
+            }),
+        3 => any::<Index>().prop_map(Op::FillLeaf),
+        2 => leaf().prop_map(Op::UpdateLast),
+        2 => Just(Op::Commit),
+    ]
+}
+
+/// Compute the root the straightforward way: hash each layer pairwise and pad an
+/// odd node with `end_pad`, until a single node is left.
+fn reference_root(leaves: &[H256]) -> H256 {
+    let mut layer = leaves.to_vec();
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha3Algorithm::parent(left, right),
+                [single] => Sha3Algorithm::parent_single(single),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    layer[0]
+}
+
+/// The expected state of the tree.
+struct Model {
+    /// The real value of every leaf, including the ones only known to the tree
+    /// through a subtree root.
+    leaves: Vec<H256>,
+    /// Whether the tree knows the value of the leaf.
+    filled: Vec<bool>,
+    /// Whether the last leaf was appended as a real leaf. `update_last` is only
+    /// meaningful for such a leaf.
+    last_is_leaf: bool,
+    next_tx_seq: u64,
+}
+
+impl Model {
+    fn new(first: H256) -> Self {
+        Self {
+            leaves: vec![first],
+            filled: vec![true],
+            last_is_leaf: true,
+            next_tx_seq: 0,
+        }
+    }
+
+    fn push_leaves(&mut self, leaves: &[H256], filled: bool) {
+        self.leaves.extend_from_slice(leaves);
+        self.filled.extend(std::iter::repeat(filled).take(leaves.len()));
+    }
+
+    fn apply(&mut self, tree: &mut Tree, op: Op) {
+        match op {
+            Op::Append(leaf) => {
+                tree.append(leaf);
+                self.push_leaves(&[leaf], true);
+                self.last_is_leaf = true;
+            }
+            Op::AppendList(list) => {
+                tree.append_list(list.clone());
+                self.push_leaves(&list, true);
+                self.last_is_leaf = true;
+            }
+            Op::AppendSubtree(depth, list) => {
+                let aligned = self.leaves.len() % list.len() == 0;
+                let result = tree.append_subtree(depth, reference_root(&list));
+                assert_eq!(result.is_ok(), aligned, "{:?}", result);
+                if aligned {
+                    // A depth-1 subtree is a single leaf whose root is the leaf itself.
+                    self.push_leaves(&list, depth == 1);
+                    self.last_is_leaf = depth == 1;
+                }
+            }
+            Op::FillLeaf(index) => {
+                let unfilled: Vec<usize> = (0..self.leaves.len())
+                    .filter(|i| !self.filled[*i])
+                    .collect();
+                if !unfilled.is_empty() {
+                    let i = *index.get(&unfilled);
+                    tree.fill_leaf(i, self.leaves[i]);
+                    self.filled[i] = true;
+                }
+            }
+            Op::UpdateLast(leaf) => {
+                if self.last_is_leaf {
+                    tree.update_last(leaf);
+                    *self.leaves.last_mut().unwrap() = leaf;
+                }
+            }
+            Op::Commit => {
+                tree.commit(Some(self.next_tx_seq));
+                self.next_tx_seq += 1;
+                assert!(tree.check_root(tree.root()));
+            }
+        }
+    }
+
+    /// Check every proof the tree is able to produce.
+    ///
+    /// A proof for a filled leaf may still contain `null` siblings if a subtree
+    /// around it is only partially filled, so only proofs with known lemmas are
+    /// required to validate.
+    fn check_proofs(&self, tree: &Tree) {
+        for (i, leaf) in self.leaves.iter().enumerate() {
+            let result = tree.gen_proof(i);
+            if !self.filled[i] {
+                assert!(result.is_err());
+                continue;
+            }
+            let proof = result.unwrap();
+            if proof.lemma().iter().any(|node| node.is_null()) {
+                continue;
+            }
+            assert_eq!(proof.root(), *tree.root());
+            proof.validate::<Sha3Algorithm>(leaf, i).unwrap();
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn ops_match_reference_tree(
+        first in leaf(),
+        ops in prop::collection::vec(op(), 1..40),
+    ) {
+        let mut tree = Tree::new(vec![first], None);
+        let mut model = Model::new(first);
+
+        for op in ops {
+            model.apply(&mut tree, op);
+            prop_assert_eq!(tree.leaves(), model.leaves.len());
+            prop_assert_eq!(*tree.root(), reference_root(&model.leaves));
+            model.check_proofs(&tree);
+        }
+
+        // Once all the leaves are known, every proof must validate.
+        for i in 0..model.leaves.len() {
+            if !model.filled[i] {
+                tree.fill_leaf(i, model.leaves[i]);
+                model.filled[i] = true;
+            }
+        }
+        prop_assert_eq!(*tree.root(), reference_root(&model.leaves));
+        tree.commit(Some(model.next_tx_seq));
+        for (i, leaf) in model.leaves.iter().enumerate() {
+            let proof = tree.gen_proof(i).unwrap();
+            prop_assert!(proof.lemma().iter().all(|node| !node.is_null()));
+            proof.validate::<Sha3Algorithm>(leaf, i).unwrap();
+            prop_assert!(tree.check_root(&proof.root()));
+        }
+        let range_proof = tree.gen_range_proof(0, model.leaves.len()).unwrap();
+        range_proof
+            .validate::<Sha3Algorithm>(&model.leaves, 0)
+            .unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+#![no_main]
+
+use append_merkle::{Algorithm, Sha3Algorithm};
+use ethereum_types::H256;
+use libfuzzer_sys::fuzz_target;
+use shared_types::{ChunkArrayWithProof, CHUNK_SIZE};
+use ssz::Decode;
+
+fuzz_target!(|data: &[u8]| {
+    let response = match ChunkArrayWithProof::from_ssz_bytes(data) {
+        Ok(response) => response,
+        Err(_) => return,
+    };
+
+    let chunks = &response.chunks;
+    chunks.first_chunk();
+    chunks.last_chunk();
+    if let Some(end_index) = chunks.end_index() {
+        chunks.sub_array(chunks.start_index, end_index);
+    }
+
+    // validated in the same way as the chunks responses from peers
+    if chunks.data.is_empty() || chunks.data.len() % CHUNK_SIZE != 0 {
+        return;
+    }
+    let leaves: Vec<H256> = chunks
+        .data
+        .chunks_exact(CHUNK_SIZE)
+        .map(<Sha3Algorithm as Algorithm<H256>>::leaf)
+        .collect();
+    let _ = response
+        .proof
+        .validate::<Sha3Algorithm>(&leaves, chunks.start_index as usize);
+});
@@ -0,0 +1,49 @@
+#![no_main]
+
+use append_merkle::Sha3Algorithm;
+use ethereum_types::H256;
+use libfuzzer_sys::fuzz_target;
+use shared_types::{FlowProof, FlowRangeProof};
+use ssz::{Decode, Encode};
+use ssz_derive::Encode as DeriveEncode;
+
+/// Same layout as `FlowProof`, whose fields are private.
+#[derive(DeriveEncode)]
+struct RawProof {
+    lemma: Vec<H256>,
+    path: Vec<bool>,
+}
+
+fn proof(lemma: Vec<[u8; 32]>, path: Vec<bool>) -> FlowProof {
+    let raw = RawProof {
+        lemma: lemma.into_iter().map(H256::from).collect(),
+        path,
+    };
+    FlowProof::from_ssz_bytes(&raw.as_ssz_bytes()).expect("same ssz layout")
+}
+
+type Input = (
+    Vec<[u8; 32]>,
+    Vec<bool>,
+    Vec<[u8; 32]>,
+    Vec<bool>,
+    Vec<[u8; 32]>,
+    usize,
+);
+
+fuzz_target!(|input: Input| {
+    let (left_lemma, left_path, right_lemma, right_path, leaves, start_position) = input;
+
+    let range_proof = FlowRangeProof {
+        left_proof: proof(left_lemma, left_path),
+        right_proof: proof(right_lemma, right_path),
+    };
+    let leaves: Vec<H256> = leaves.into_iter().map(H256::from).collect();
+
+    if range_proof
+        .validate::<Sha3Algorithm>(&leaves, start_position)
+        .is_ok()
+    {
+        range_proof.root();
+    }
+});
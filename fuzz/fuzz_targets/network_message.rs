@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use network::rpc::{fuzz_decode, Protocol};
+use network::types::{GossipEncoding, GossipKind};
+use network::{GossipTopic, PubsubMessage, TopicHash};
+
+const PROTOCOLS: [Protocol; 8] = [
+    Protocol::Status,
+    Protocol::Goodbye,
+    Protocol::Ping,
+    Protocol::DataByHash,
+    Protocol::GetChunks,
+    Protocol::OfferFile,
+    Protocol::GetErasureShard,
+    Protocol::GetPendingChunks,
+];
+
+fuzz_target!(|data: &[u8]| {
+    // RPC requests and responses
+    for protocol in PROTOCOLS {
+        fuzz_decode(protocol, data);
+    }
+
+    // gossip messages, which are decompressed by gossipsub already
+    for kind in [
+        GossipKind::Example,
+        GossipKind::FindFile,
+        GossipKind::AnnounceFile,
+        GossipKind::AnnounceStorage,
+        GossipKind::NewTx,
+    ] {
+        let topic: String = GossipTopic::new(kind, GossipEncoding::SSZSnappy).into();
+        if let Ok(PubsubMessage::AnnounceFile(announcement)) =
+            PubsubMessage::decode(&TopicHash::from_raw(topic), data)
+        {
+            announcement.available_ranges();
+        }
+    }
+});
@@ -435,6 +435,40 @@ impl MemoryChunkPool {
         Ok(true)
     }
 
+    /// Resumes the upload of a file, of which the chunks before `next_index` have already been
+    /// written into store, e.g. by an interrupted upload whose cached file has been garbage
+    /// collected. So that, the remaining segments could be uploaded in sequence.
+    pub async fn resume_file(&self, tx: &Transaction, next_index: usize) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner.garbage_collect();
+
+        let expiration_timeout = inner.expiration_timeout;
+        let file = inner
+            .files
+            .entry(tx.data_merkle_root)
+            .or_insert_with(|| MemoryCachedFile::new(expiration_timeout));
+
+        // Do not break the file that is being uploaded.
+        if file.writing || file.segments.is_some() || file.next_index >= next_index {
+            return Ok(());
+        }
+
+        file.update_with_tx(tx);
+        file.next_index = next_index;
+        let all_uploaded = file.next_index >= file.total_chunks;
+        inner.update_expiration_time(&tx.data_merkle_root);
+
+        // All chunks written but the transaction not finalized yet, e.g. failed to finalize.
+        if all_uploaded {
+            if let Err(e) = self.sender.send(tx.data_merkle_root) {
+                // Channel receiver will not be dropped until program exit.
+                bail!(anyhow!("channel send error: {}", e));
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn remove_file(&self, root: &DataRoot) -> Option<MemoryCachedFile> {
         let mut inner = self.inner.lock().await;
 
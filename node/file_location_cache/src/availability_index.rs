@@ -0,0 +1,173 @@
+use crate::Config;
+use hashlink::LinkedHashMap;
+use network::{Multiaddr, PeerId};
+use shared_types::{timestamp_now, DataRoot};
+use std::collections::HashMap;
+
+/// A peer that announced to store a file.
+#[derive(Clone, Debug)]
+struct Provider {
+    addr: Multiaddr,
+    /// Timestamp of the latest announcement.
+    timestamp: u32,
+}
+
+/// Index of peers that announced to store files, which is mapped from the file data root.
+pub(crate) struct AvailabilityIndex {
+    /// Maximum number of data roots in index.
+    max_data_roots: usize,
+
+    /// Maximum number of providers for each data root.
+    max_providers_per_root: usize,
+
+    /// Timeout in seconds to expire the announced provider, since the file may be removed
+    /// from the announced storage node.
+    timeout_secs: u32,
+
+    /// All indexed data roots, where the front one is the least recently announced.
+    roots: LinkedHashMap<DataRoot, HashMap<PeerId, Provider>>,
+}
+
+impl AvailabilityIndex {
+    pub fn new(config: &Config) -> Self {
+        assert!(config.max_data_roots > 0);
+        assert!(config.max_entries_per_file > 0);
+
+        AvailabilityIndex {
+            max_data_roots: config.max_data_roots,
+            max_providers_per_root: config.max_entries_per_file,
+            timeout_secs: config.entry_expiration_time_secs,
+            roots: Default::default(),
+        }
+    }
+
+    fn is_expired(&self, provider: &Provider, now: u32) -> bool {
+        provider.timestamp + self.timeout_secs <= now
+    }
+
+    /// Inserts the data roots announced by `peer_id`.
+    pub fn insert(&mut self, peer_id: PeerId, addr: Multiaddr, roots: &[DataRoot], timestamp: u32) {
+        let now = timestamp_now();
+
+        for root in roots {
+            let mut providers = self.roots.remove(root).unwrap_or_default();
+
+            // ignore older announcement
+            if let Some(existing) = providers.get(&peer_id) {
+                if timestamp <= existing.timestamp {
+                    self.roots.insert(*root, providers);
+                    continue;
+                }
+            }
+
+            providers.retain(|_, provider| !self.is_expired(provider, now));
+            providers.insert(
+                peer_id,
+                Provider {
+                    addr: addr.clone(),
+                    timestamp,
+                },
+            );
+
+            // remove the oldest provider if capacity exceeded
+            if providers.len() > self.max_providers_per_root {
+                if let Some(oldest) = providers
+                    .iter()
+                    .min_by_key(|(_, provider)| provider.timestamp)
+                    .map(|(peer_id, _)| *peer_id)
+                {
+                    providers.remove(&oldest);
+                }
+            }
+
+            self.roots.insert(*root, providers);
+        }
+
+        while self.roots.len() > self.max_data_roots {
+            self.roots.pop_front();
+        }
+    }
+
+    /// Returns all the unexpired providers of the specified file by `data_root`.
+    pub fn providers(&mut self, data_root: &DataRoot) -> Vec<(PeerId, Multiaddr)> {
+        let now = timestamp_now();
+
+        let providers = match self.roots.get(data_root) {
+            Some(providers) => providers,
+            None => return vec![],
+        };
+
+        let result: Vec<(PeerId, Multiaddr)> = providers
+            .iter()
+            .filter(|(_, provider)| !self.is_expired(provider, now))
+            .map(|(peer_id, provider)| (*peer_id, provider.addr.clone()))
+            .collect();
+
+        // remove entry if all providers expired
+        if result.is_empty() {
+            self.roots.remove(data_root);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::libp2p::identity;
+
+    fn create_index(max_data_roots: usize, max_entries_per_file: usize) -> AvailabilityIndex {
+        AvailabilityIndex::new(&Config {
+            max_data_roots,
+            max_entries_per_file,
+            ..Default::default()
+        })
+    }
+
+    fn random_peer() -> PeerId {
+        identity::Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_insert_and_get_providers() {
+        let mut index = create_index(16, 2);
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let (root1, root2) = (DataRoot::repeat_byte(1), DataRoot::repeat_byte(2));
+        let now = timestamp_now();
+
+        let (peer1, peer2, peer3) = (random_peer(), random_peer(), random_peer());
+        index.insert(peer1, addr.clone(), &[root1, root2], now - 2);
+        index.insert(peer2, addr.clone(), &[root1], now - 1);
+        assert_eq!(index.providers(&root1).len(), 2);
+        assert_eq!(index.providers(&root2), vec![(peer1, addr.clone())]);
+        assert!(index.providers(&DataRoot::repeat_byte(3)).is_empty());
+
+        // the oldest provider removed if capacity exceeded
+        index.insert(peer3, addr.clone(), &[root1], now);
+        let providers: Vec<PeerId> = index.providers(&root1).into_iter().map(|p| p.0).collect();
+        assert_eq!(providers.len(), 2);
+        assert!(!providers.contains(&peer1));
+
+        // expired providers are ignored
+        let expired = now - Config::default().entry_expiration_time_secs;
+        index.insert(random_peer(), addr, &[DataRoot::repeat_byte(4)], expired);
+        assert!(index.providers(&DataRoot::repeat_byte(4)).is_empty());
+    }
+
+    #[test]
+    fn test_max_data_roots() {
+        let mut index = create_index(2, 4);
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let peer_id = random_peer();
+        let now = timestamp_now();
+
+        for i in 1..=3 {
+            index.insert(peer_id, addr.clone(), &[DataRoot::repeat_byte(i)], now);
+        }
+
+        assert!(index.providers(&DataRoot::repeat_byte(1)).is_empty());
+        assert_eq!(index.providers(&DataRoot::repeat_byte(2)).len(), 1);
+        assert_eq!(index.providers(&DataRoot::repeat_byte(3)).len(), 1);
+    }
+}
@@ -1,10 +1,13 @@
+use crate::availability_index::AvailabilityIndex;
+use crate::metrics;
+use crate::replication::ReplicationTracker;
 use crate::Config;
 use network::types::SignedAnnounceFile;
-use network::PeerId;
+use network::{Multiaddr, PeerId};
 use parking_lot::Mutex;
 use priority_queue::PriorityQueue;
 use rand::seq::IteratorRandom;
-use shared_types::timestamp_now;
+use shared_types::{timestamp_now, DataRoot};
 use std::cmp::Reverse;
 use std::collections::HashMap;
 
@@ -234,17 +237,33 @@ impl FileCache {
 
 pub struct FileLocationCache {
     cache: Mutex<FileCache>,
+
+    /// Providers of files announced by data roots.
+    availability: Mutex<AvailabilityIndex>,
+
+    /// Distinct nodes that announced to store each file.
+    replication: Mutex<ReplicationTracker>,
+
+    /// Files with fewer replicas are under-replicated.
+    min_replicas: usize,
 }
 
 impl Default for FileLocationCache {
     fn default() -> Self {
-        FileLocationCache {
-            cache: Mutex::new(FileCache::new(Default::default())),
-        }
+        FileLocationCache::new(Config::default())
     }
 }
 
 impl FileLocationCache {
+    pub fn new(config: Config) -> Self {
+        FileLocationCache {
+            availability: Mutex::new(AvailabilityIndex::new(&config)),
+            replication: Mutex::new(ReplicationTracker::new(&config)),
+            min_replicas: config.min_replicas,
+            cache: Mutex::new(FileCache::new(config)),
+        }
+    }
+
     pub fn insert(&self, announcement: SignedAnnounceFile) {
         self.cache.lock().insert(announcement);
     }
@@ -256,4 +275,63 @@ impl FileLocationCache {
     pub fn get_all(&self, tx_seq: u64) -> Vec<SignedAnnounceFile> {
         self.cache.lock().all(tx_seq).unwrap_or_default()
     }
+
+    /// Indexes the data roots of files stored by the announced peer.
+    pub fn insert_data_roots(
+        &self,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        data_roots: &[DataRoot],
+        timestamp: u32,
+    ) {
+        self.availability
+            .lock()
+            .insert(peer_id, addr, data_roots, timestamp);
+        self.insert_replicas(peer_id, data_roots, timestamp);
+    }
+
+    /// Counts `peer_id` as a replica of the files of `data_roots`, e.g. the local node that
+    /// announces its newly stored files.
+    pub fn insert_replicas(&self, peer_id: PeerId, data_roots: &[DataRoot], timestamp: u32) {
+        self.replication
+            .lock()
+            .insert(peer_id, data_roots, timestamp);
+    }
+
+    /// Returns the number of distinct nodes that announced to store the file of `data_root`.
+    pub fn replication_count(&self, data_root: &DataRoot) -> usize {
+        self.replication.lock().count(data_root)
+    }
+
+    /// Returns the tracked files with fewer replicas than the configured minimum, sorted by the
+    /// replica counts, and updates the replication metrics.
+    pub fn under_replicated(&self) -> Vec<(DataRoot, usize)> {
+        let counts = self.replication.lock().counts();
+
+        metrics::set_gauge(&metrics::REPLICATION_TRACKED_FILES, counts.len() as i64);
+        let min_count = counts.iter().map(|(_, count)| *count).min().unwrap_or(0);
+        metrics::set_gauge(&metrics::REPLICATION_MIN_COUNT, min_count as i64);
+
+        let mut under_replicated: Vec<(DataRoot, usize)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count < self.min_replicas)
+            .collect();
+        under_replicated.sort_by_key(|(_, count)| *count);
+        metrics::set_gauge(
+            &metrics::REPLICATION_UNDER_REPLICATED_FILES,
+            under_replicated.len() as i64,
+        );
+
+        under_replicated
+    }
+
+    /// Minimum number of replicas of a file not to be under-replicated.
+    pub fn min_replicas(&self) -> usize {
+        self.min_replicas
+    }
+
+    /// Returns the known peers that store the file of specified `data_root`.
+    pub fn get_providers(&self, data_root: &DataRoot) -> Vec<(PeerId, Multiaddr)> {
+        self.availability.lock().providers(data_root)
+    }
 }
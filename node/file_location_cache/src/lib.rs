@@ -1,4 +1,10 @@
+#[macro_use]
+extern crate lazy_static;
+
+mod availability_index;
 mod file_location_cache;
+mod metrics;
+mod replication;
 
 pub use crate::file_location_cache::FileLocationCache;
 
@@ -6,6 +12,14 @@ pub struct Config {
     pub max_entries_total: usize,
     pub max_entries_per_file: usize,
     pub entry_expiration_time_secs: u32,
+    /// Maximum number of data roots in the file availability index.
+    pub max_data_roots: usize,
+    /// Files announced by fewer distinct nodes are reported as under-replicated.
+    pub min_replicas: usize,
+    /// Maximum number of replicas counted for each file.
+    pub max_replicas_per_file: usize,
+    /// Replicas that are not announced again in time are no longer counted.
+    pub replica_expiration_time_secs: u32,
 }
 
 impl Default for Config {
@@ -14,6 +28,10 @@ impl Default for Config {
             max_entries_total: 4096,
             max_entries_per_file: 4,
             entry_expiration_time_secs: 3600,
+            max_data_roots: 65536,
+            min_replicas: 3,
+            max_replicas_per_file: 64,
+            replica_expiration_time_secs: 86400,
         }
     }
 }
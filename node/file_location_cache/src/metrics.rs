@@ -0,0 +1,16 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref REPLICATION_TRACKED_FILES: Result<IntGauge> = try_create_int_gauge(
+        "file_replication_tracked_files",
+        "Number of files with replicas announced in the network"
+    );
+    pub static ref REPLICATION_UNDER_REPLICATED_FILES: Result<IntGauge> = try_create_int_gauge(
+        "file_replication_under_replicated_files",
+        "Number of tracked files announced by fewer nodes than the minimum replicas"
+    );
+    pub static ref REPLICATION_MIN_COUNT: Result<IntGauge> = try_create_int_gauge(
+        "file_replication_min_count",
+        "Minimum number of replicas among the tracked files"
+    );
+}
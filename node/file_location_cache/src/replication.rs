@@ -0,0 +1,166 @@
+use crate::Config;
+use hashlink::LinkedHashMap;
+use network::PeerId;
+use shared_types::{timestamp_now, DataRoot};
+use std::collections::HashMap;
+
+/// Tracks the distinct nodes that announced to store each file, to monitor how many replicas of
+/// the finalized files there are in the network.
+pub(crate) struct ReplicationTracker {
+    /// Maximum number of tracked data roots.
+    max_data_roots: usize,
+
+    /// Maximum number of replicas counted for each data root.
+    max_replicas_per_root: usize,
+
+    /// Timeout in seconds to expire the announced replica.
+    timeout_secs: u32,
+
+    /// Timestamp of the latest announcement of each replica, where the front data root is the
+    /// least recently announced.
+    roots: LinkedHashMap<DataRoot, HashMap<PeerId, u32>>,
+}
+
+impl ReplicationTracker {
+    pub fn new(config: &Config) -> Self {
+        assert!(config.max_data_roots > 0);
+        assert!(config.max_replicas_per_file > 0);
+
+        ReplicationTracker {
+            max_data_roots: config.max_data_roots,
+            max_replicas_per_root: config.max_replicas_per_file,
+            timeout_secs: config.replica_expiration_time_secs,
+            roots: Default::default(),
+        }
+    }
+
+    fn is_expired(&self, timestamp: u32, now: u32) -> bool {
+        timestamp + self.timeout_secs <= now
+    }
+
+    /// Records the replicas of the data roots announced by `peer_id`.
+    pub fn insert(&mut self, peer_id: PeerId, roots: &[DataRoot], timestamp: u32) {
+        let now = timestamp_now();
+        if self.is_expired(timestamp, now) {
+            return;
+        }
+
+        for root in roots {
+            let mut replicas = self.roots.remove(root).unwrap_or_default();
+            replicas.retain(|_, announced| !self.is_expired(*announced, now));
+
+            let announced = replicas.entry(peer_id).or_default();
+            *announced = timestamp.max(*announced);
+
+            // replace the oldest replica if capacity exceeded, which saturates the count
+            if replicas.len() > self.max_replicas_per_root {
+                if let Some(oldest) = replicas
+                    .iter()
+                    .min_by_key(|(_, announced)| **announced)
+                    .map(|(peer_id, _)| *peer_id)
+                {
+                    replicas.remove(&oldest);
+                }
+            }
+
+            self.roots.insert(*root, replicas);
+        }
+
+        while self.roots.len() > self.max_data_roots {
+            self.roots.pop_front();
+        }
+    }
+
+    /// Returns the number of unexpired replicas of the specified file by `data_root`.
+    pub fn count(&self, data_root: &DataRoot) -> usize {
+        let now = timestamp_now();
+
+        match self.roots.get(data_root) {
+            Some(replicas) => replicas
+                .values()
+                .filter(|announced| !self.is_expired(**announced, now))
+                .count(),
+            None => 0,
+        }
+    }
+
+    /// Removes the expired replicas, and returns the replica counts of all the tracked files.
+    pub fn counts(&mut self) -> Vec<(DataRoot, usize)> {
+        let now = timestamp_now();
+        let timeout_secs = self.timeout_secs;
+
+        self.roots.retain(|_, replicas| {
+            replicas.retain(|_, announced| *announced + timeout_secs > now);
+            !replicas.is_empty()
+        });
+
+        self.roots
+            .iter()
+            .map(|(root, replicas)| (*root, replicas.len()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::libp2p::identity;
+
+    fn create_tracker(max_data_roots: usize, max_replicas_per_file: usize) -> ReplicationTracker {
+        ReplicationTracker::new(&Config {
+            max_data_roots,
+            max_replicas_per_file,
+            ..Default::default()
+        })
+    }
+
+    fn random_peer() -> PeerId {
+        identity::Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_count_distinct_replicas() {
+        let mut tracker = create_tracker(16, 2);
+        let (root1, root2) = (DataRoot::repeat_byte(1), DataRoot::repeat_byte(2));
+        let now = timestamp_now();
+
+        let (peer1, peer2) = (random_peer(), random_peer());
+        tracker.insert(peer1, &[root1, root2], now - 2);
+        // announced again by the same peer
+        tracker.insert(peer1, &[root1], now - 1);
+        assert_eq!(tracker.count(&root1), 1);
+
+        tracker.insert(peer2, &[root1], now);
+        assert_eq!(tracker.count(&root1), 2);
+        assert_eq!(tracker.count(&root2), 1);
+        assert_eq!(tracker.count(&DataRoot::repeat_byte(3)), 0);
+
+        // the count saturates if capacity exceeded
+        tracker.insert(random_peer(), &[root1], now);
+        assert_eq!(tracker.count(&root1), 2);
+
+        // expired replicas are ignored
+        let expired = now - Config::default().replica_expiration_time_secs;
+        tracker.insert(random_peer(), &[root2], expired);
+        assert_eq!(tracker.count(&root2), 1);
+
+        let mut counts = tracker.counts();
+        counts.sort();
+        assert_eq!(counts, vec![(root1, 2), (root2, 1)]);
+    }
+
+    #[test]
+    fn test_max_data_roots() {
+        let mut tracker = create_tracker(2, 4);
+        let peer_id = random_peer();
+        let now = timestamp_now();
+
+        for i in 1..=3 {
+            tracker.insert(peer_id, &[DataRoot::repeat_byte(i)], now);
+        }
+
+        assert_eq!(tracker.count(&DataRoot::repeat_byte(1)), 0);
+        assert_eq!(tracker.count(&DataRoot::repeat_byte(2)), 1);
+        assert_eq!(tracker.counts().len(), 2);
+    }
+}
@@ -0,0 +1,119 @@
+//! HTTP server that exposes the Prometheus metrics of all node services.
+//!
+//! Services register their metrics in the global registry of `lighthouse_metrics`, and this
+//! server encodes all of them on `GET /metrics`. Gauges that are derived from the state of a
+//! service, rather than updated along with it, are refreshed on each scrape.
+
+#[macro_use]
+extern crate tracing;
+
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use lighthouse_metrics::{Encoder, TextEncoder};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use storage_async::Store;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub enabled: bool,
+    pub listen_address: SocketAddr,
+}
+
+/// A wrapper around all the items required to serve the metrics.
+///
+/// Metrics derived from a component are skipped if it is `None`.
+#[derive(Clone)]
+pub struct Context {
+    pub config: Config,
+    pub log_store: Option<Store>,
+}
+
+/// Starts the metrics server. Returns the bound address and the future to drive the server.
+pub fn serve(ctx: Context) -> Result<(SocketAddr, impl Future<Output = ()>), hyper::Error> {
+    let listen_address = ctx.config.listen_address;
+
+    let make_service = make_service_fn(move |_conn: &AddrStream| {
+        let ctx = ctx.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, ctx.clone()))) }
+    });
+
+    let server = Server::try_bind(&listen_address)?.serve(make_service);
+    let addr = server.local_addr();
+
+    Ok((addr, async move {
+        if let Err(e) = server.await {
+            error!(error = %e, "Metrics server failed");
+        }
+    }))
+}
+
+async fn handle(req: Request<Body>, ctx: Context) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Ok(response(
+            StatusCode::NOT_FOUND,
+            "text/plain",
+            "Not found".into(),
+        ));
+    }
+
+    Ok(match gather_prometheus_metrics(&ctx).await {
+        Ok(text) => response(StatusCode::OK, TextEncoder::new().format_type(), text),
+        Err(e) => {
+            error!(%e, "Failed to gather metrics");
+            response(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", e)
+        }
+    })
+}
+
+/// Refreshes the scraped metrics and encodes all registered metrics in the Prometheus text
+/// format.
+pub async fn gather_prometheus_metrics(ctx: &Context) -> Result<String, String> {
+    network::scrape_discovery_metrics();
+
+    if let Some(store) = &ctx.log_store {
+        match store.next_tx_seq().await {
+            Ok(seq) => storage::metrics::set_gauge(&storage::metrics::NEXT_TX_SEQ, seq as i64),
+            Err(e) => warn!(%e, "Failed to read next tx seq for metrics"),
+        }
+    }
+
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&lighthouse_metrics::gather(), &mut buffer)
+        .map_err(|e| format!("Unable to encode metrics: {:?}", e))?;
+
+    String::from_utf8(buffer).map_err(|e| format!("Unable to encode metrics as utf8: {:?}", e))
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gather_prometheus_metrics, Config, Context};
+
+    #[tokio::test]
+    async fn test_gather_prometheus_metrics() {
+        storage::metrics::inc_counter(&storage::metrics::FINALIZED_TX_COUNT);
+
+        let ctx = Context {
+            config: Config {
+                enabled: true,
+                listen_address: "127.0.0.1:0".parse().unwrap(),
+            },
+            log_store: None,
+        };
+
+        let text = gather_prometheus_metrics(&ctx).await.unwrap();
+        assert!(text.contains("store_finalized_tx_total"));
+        assert!(text.contains("discovery_sessions"));
+    }
+}
@@ -0,0 +1,217 @@
+//! Client side encryption of files, so that storage nodes only see the ciphertext.
+//!
+//! Files are encrypted with XChaCha20-Poly1305 in the STREAM construction, where the plaintext
+//! is split into blocks and each block is sealed with its own tag. The encrypted file starts with
+//! a header entry, followed by blocks of exactly `BLOCK_ENTRIES` entries except the last one, so
+//! that any entry range of the encrypted file could be downloaded and decrypted independently.
+//!
+//! The data root of the encrypted file is computed as usual, e.g. with `split_into_segments`, and
+//! submitted to the log contract instead of the data root of the plaintext.
+
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::stream::{NewStream, StreamBE32, StreamPrimitive};
+use chacha20poly1305::aead::NewAead;
+use chacha20poly1305::{Key, XChaCha20Poly1305};
+use hkdf::Hkdf;
+use hmac::Hmac;
+use sha2::Sha256;
+use shared_types::CHUNK_SIZE;
+
+/// Version of the encryption format, which is the first byte of the header.
+pub const ENCRYPTION_VERSION: u8 = 1;
+
+/// Size of the header entry, which holds the version and the stream nonce.
+pub const HEADER_SIZE: usize = CHUNK_SIZE;
+
+/// Number of entries of each encrypted block.
+pub const BLOCK_ENTRIES: usize = 64;
+
+/// Size of the authentication tag of each block.
+pub const TAG_SIZE: usize = 16;
+
+/// Size of each encrypted block, except the last one.
+pub const CIPHER_BLOCK_SIZE: usize = BLOCK_ENTRIES * CHUNK_SIZE;
+
+/// Size of each plaintext block, except the last one.
+pub const PLAIN_BLOCK_SIZE: usize = CIPHER_BLOCK_SIZE - TAG_SIZE;
+
+/// Size of the nonce prefix of the STREAM construction, the rest of the XChaCha20 nonce is the
+/// block counter and the last block flag.
+const STREAM_NONCE_SIZE: usize = 19;
+
+/// Number of PBKDF2 rounds to derive a key from a passphrase.
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+type Stream = StreamBE32<XChaCha20Poly1305>;
+
+/// A 256-bit symmetric key to encrypt files.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        EncryptionKey(key)
+    }
+
+    /// Generates a random key.
+    pub fn random() -> Self {
+        EncryptionKey(rand::random())
+    }
+
+    /// Derives a key from a passphrase with PBKDF2-HMAC-SHA256. The `salt` should be unique for
+    /// each user, and kept along with the encrypted files.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+        EncryptionKey(key)
+    }
+
+    /// Derives a subkey with HKDF-SHA256 for the given `context`, e.g. a file name, so that a
+    /// leaked file key does not reveal the other files.
+    pub fn derive(&self, context: &[u8]) -> Self {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &self.0)
+            .expand(context, &mut key)
+            .expect("32 bytes is a valid length for HKDF-SHA256");
+        EncryptionKey(key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn stream(&self, nonce: &[u8]) -> Stream {
+        let aead = XChaCha20Poly1305::new(Key::from_slice(&self.0));
+        Stream::from_aead(aead, nonce.into())
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// Returns the size of the encrypted file of `plain_size` bytes.
+pub fn encrypted_size(plain_size: usize) -> usize {
+    let full_blocks = plain_size / PLAIN_BLOCK_SIZE;
+    HEADER_SIZE + full_blocks * CIPHER_BLOCK_SIZE + (plain_size % PLAIN_BLOCK_SIZE) + TAG_SIZE
+}
+
+/// Encrypts the file data with a random nonce.
+pub fn encrypt(key: &EncryptionKey, data: &[u8]) -> Result<Vec<u8>> {
+    let nonce: [u8; STREAM_NONCE_SIZE] = rand::random();
+    let stream = key.stream(&nonce);
+
+    let mut encrypted = Vec::with_capacity(encrypted_size(data.len()));
+    encrypted.push(ENCRYPTION_VERSION);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.resize(HEADER_SIZE, 0);
+
+    // The last block is always present, which is empty if the data fills up all blocks, so
+    // that truncation of whole blocks is detected.
+    let num_blocks = data.len() / PLAIN_BLOCK_SIZE + 1;
+    for (position, block) in data
+        .chunks(PLAIN_BLOCK_SIZE)
+        .chain(std::iter::once(&[][..]))
+        .take(num_blocks)
+        .enumerate()
+    {
+        let last_block = position + 1 == num_blocks;
+        let sealed = stream
+            .encrypt(position as u32, last_block, block)
+            .map_err(|_| anyhow!("failed to encrypt block {}", position))?;
+        encrypted.extend_from_slice(&sealed);
+    }
+
+    Ok(encrypted)
+}
+
+/// Decrypts the encrypted file, which may be padded with zeros to whole chunks as downloaded
+/// from the node, if `size` of the encrypted file is provided.
+pub fn decrypt(key: &EncryptionKey, encrypted: &[u8], size: Option<usize>) -> Result<Vec<u8>> {
+    let encrypted = match size {
+        Some(size) if size <= encrypted.len() => &encrypted[..size],
+        Some(size) => bail!("encrypted data too short: {} < {}", encrypted.len(), size),
+        None => encrypted,
+    };
+
+    if encrypted.len() < HEADER_SIZE + TAG_SIZE {
+        bail!("encrypted data too short: {}", encrypted.len());
+    }
+    if encrypted[0] != ENCRYPTION_VERSION {
+        bail!("unsupported encryption version: {}", encrypted[0]);
+    }
+    let stream = key.stream(&encrypted[1..1 + STREAM_NONCE_SIZE]);
+
+    let body = &encrypted[HEADER_SIZE..];
+    let num_blocks = body.len() / CIPHER_BLOCK_SIZE + 1;
+    if body.len() % CIPHER_BLOCK_SIZE < TAG_SIZE {
+        bail!("invalid encrypted data length: {}", encrypted.len());
+    }
+
+    let mut data = Vec::with_capacity(body.len() - num_blocks * TAG_SIZE);
+    for (position, block) in body.chunks(CIPHER_BLOCK_SIZE).enumerate() {
+        let last_block = position + 1 == num_blocks;
+        let opened = stream
+            .decrypt(position as u32, last_block, block)
+            .map_err(|_| anyhow!("failed to decrypt block {}, wrong key or data", position))?;
+        data.extend_from_slice(&opened);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = EncryptionKey::random();
+
+        for size in [
+            0,
+            1,
+            PLAIN_BLOCK_SIZE - 1,
+            PLAIN_BLOCK_SIZE,
+            PLAIN_BLOCK_SIZE + 1,
+            3 * PLAIN_BLOCK_SIZE + 100,
+        ] {
+            let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+            let encrypted = encrypt(&key, &data).unwrap();
+            assert_eq!(encrypted.len(), encrypted_size(size));
+            assert_eq!(decrypt(&key, &encrypted, None).unwrap(), data);
+
+            // blocks are aligned to entries
+            assert_eq!(
+                (encrypted.len() - HEADER_SIZE - TAG_SIZE) / CIPHER_BLOCK_SIZE,
+                size / PLAIN_BLOCK_SIZE
+            );
+
+            // padded to whole chunks as downloaded
+            let mut padded = encrypted.clone();
+            padded.resize(padded.len() + 100, 0);
+            assert_eq!(decrypt(&key, &padded, Some(encrypted.len())).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let key = EncryptionKey::from_passphrase("passphrase", b"salt");
+        let data = vec![7u8; 2 * PLAIN_BLOCK_SIZE + 10];
+        let encrypted = encrypt(&key, &data).unwrap();
+
+        let wrong_key = key.derive(b"file");
+        assert_ne!(wrong_key, key);
+        assert!(decrypt(&wrong_key, &encrypted, None).is_err());
+
+        let mut tampered = encrypted.clone();
+        tampered[HEADER_SIZE + CIPHER_BLOCK_SIZE] ^= 1;
+        assert!(decrypt(&key, &tampered, None).is_err());
+
+        // truncated at a block boundary
+        let truncated = &encrypted[..HEADER_SIZE + 2 * CIPHER_BLOCK_SIZE];
+        assert!(decrypt(&key, truncated, None).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Typed async client of the ionian node RPC API.
+//!
+//! ```ignore
+//! let client = Client::new("http://127.0.0.1:5678")?;
+//! let root = client.upload_file("file.dat").await?;
+//! client.wait_finalized(root).await?;
+//! client.download_file(root, "file.dat.copy").await?;
+//! ```
+//!
+//! Files could be encrypted before upload, so that storage nodes only see the ciphertext:
+//!
+//! ```ignore
+//! let key = EncryptionKey::from_passphrase("passphrase", b"salt");
+//! let encrypted = encrypt(&key, &tokio::fs::read("file.dat").await?)?;
+//! // submit the transaction with the data root of the encrypted data
+//! let root = client.upload_data(&encrypted).await?;
+//! client.wait_finalized(root).await?;
+//! client.download_encrypted_file(root, "file.dat.copy", &key).await?;
+//! ```
+
+mod encryption;
+mod segment;
+
+use anyhow::{anyhow, bail, Result};
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
+use jsonrpsee::rpc_params;
+use shared_types::rpc::{
+    ChunkRange, FileId, FileInfo, FileQuery, ProofVerification, SegmentPage, SegmentWithProof,
+    Status, UploadSession,
+};
+use shared_types::{bytes_to_chunks, sub_merkle_tree, DataRoot, FileMetadata, FlowRangeProof};
+use std::path::Path;
+use std::time::Duration;
+
+pub use encryption::{decrypt, encrypt, encrypted_size, EncryptionKey};
+pub use segment::split_into_segments;
+
+/// Default number of chunks per segment of the node.
+pub const DEFAULT_CHUNKS_PER_SEGMENT: usize = 1024;
+
+/// Interval to poll the file status while waiting for it to be finalized.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Client {
+    rpc: HttpClient,
+    /// Should be the same as `rpc_chunks_per_segment` of the node.
+    chunks_per_segment: usize,
+}
+
+impl Client {
+    /// Creates a client of the node HTTP RPC server at `url`.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Client {
+            rpc: HttpClientBuilder::default().build(url)?,
+            chunks_per_segment: DEFAULT_CHUNKS_PER_SEGMENT,
+        })
+    }
+
+    /// Sets the number of chunks per segment, which is configured by the node.
+    pub fn with_chunks_per_segment(mut self, chunks_per_segment: usize) -> Self {
+        self.chunks_per_segment = chunks_per_segment;
+        self
+    }
+
+    pub async fn get_status(&self) -> Result<Status> {
+        Ok(self.rpc.request("ionian_getStatus", None).await?)
+    }
+
+    pub async fn get_file_info(&self, data_root: DataRoot) -> Result<Option<FileInfo>> {
+        Ok(self
+            .rpc
+            .request("ionian_getFileInfo", rpc_params![data_root])
+            .await?)
+    }
+
+    /// Searches the files from `start_seq` on the node, and returns at most `limit` ones. The next
+    /// page starts from the sequence number of the last one plus 1.
+    pub async fn search_files(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        query: &FileQuery,
+    ) -> Result<Vec<FileInfo>> {
+        Ok(self
+            .rpc
+            .request("ionian_searchFiles", rpc_params![start_seq, limit, query])
+            .await?)
+    }
+
+    /// Returns the number of distinct nodes that announced to store the file recently.
+    pub async fn get_replication_count(&self, data_root: DataRoot) -> Result<usize> {
+        Ok(self
+            .rpc
+            .request("ionian_getReplicationCount", rpc_params![data_root])
+            .await?)
+    }
+
+    /// Pins the file on the node to protect it from pruning. Returns `false` if already pinned.
+    pub async fn pin_file(&self, data_root: DataRoot) -> Result<bool> {
+        Ok(self
+            .rpc
+            .request("ionian_pinFile", rpc_params![data_root])
+            .await?)
+    }
+
+    /// Unpins the file on the node. Returns `false` if not pinned.
+    pub async fn unpin_file(&self, data_root: DataRoot) -> Result<bool> {
+        Ok(self
+            .rpc
+            .request("ionian_unpinFile", rpc_params![data_root])
+            .await?)
+    }
+
+    /// Verifies the range proof of file chunks on the node. The proof could also be verified
+    /// locally with `FileRangeProof::verify` at the returned flow start index.
+    pub async fn verify_proof(
+        &self,
+        root: DataRoot,
+        proof: &FlowRangeProof,
+        data: &ChunkRange,
+    ) -> Result<Option<ProofVerification>> {
+        Ok(self
+            .rpc
+            .request("ionian_verifyProof", rpc_params![root, proof, data])
+            .await?)
+    }
+
+    /// Requests the node to sync the file from peers, and returns its transaction sequence number.
+    pub async fn request_file_sync(&self, file: FileId) -> Result<u64> {
+        Ok(self
+            .rpc
+            .request("ionian_requestFileSync", rpc_params![file])
+            .await?)
+    }
+
+    /// Begins or resumes the upload of a file, along with its metadata if any.
+    pub async fn begin_upload(
+        &self,
+        data_root: DataRoot,
+        size: u64,
+        metadata: Option<&FileMetadata>,
+    ) -> Result<UploadSession> {
+        Ok(self
+            .rpc
+            .request("ionian_beginUpload", rpc_params![data_root, size, metadata])
+            .await?)
+    }
+
+    pub async fn upload_segment(&self, segment: &SegmentWithProof) -> Result<()> {
+        Ok(self
+            .rpc
+            .request("ionian_uploadSegment", rpc_params![segment])
+            .await?)
+    }
+
+    /// Uploads the file at `path`, and returns its data root. The file could be uploaded before its
+    /// transaction is synced by the node, which stages the data until then.
+    pub async fn upload_file(&self, path: impl AsRef<Path>) -> Result<DataRoot> {
+        let data = tokio::fs::read(path).await?;
+        self.upload_data(&data).await
+    }
+
+    /// Uploads the file data, e.g. encrypted with `encrypt`, and returns its data root.
+    pub async fn upload_data(&self, data: &[u8]) -> Result<DataRoot> {
+        self.upload_data_with_metadata(data, None).await
+    }
+
+    /// Uploads the file data along with its metadata, e.g. the MIME type for gateways to serve
+    /// the file, and returns its data root.
+    pub async fn upload_data_with_metadata(
+        &self,
+        data: &[u8],
+        metadata: Option<&FileMetadata>,
+    ) -> Result<DataRoot> {
+        let (data_root, segments) = split_into_segments(data, self.chunks_per_segment)?;
+
+        if let Some(info) = self.get_file_info(data_root).await? {
+            if info.finalized && metadata.is_none() {
+                return Ok(data_root);
+            }
+        }
+
+        // Only upload the segments missing on the node, e.g. when resuming an interrupted upload.
+        let session = self
+            .begin_upload(data_root, data.len() as u64, metadata)
+            .await?;
+        if session.chunks_per_segment != self.chunks_per_segment {
+            bail!(
+                "chunks per segment mismatch: node {}, client {}",
+                session.chunks_per_segment,
+                self.chunks_per_segment
+            );
+        }
+
+        for (start, end) in session.missing_segments {
+            for segment in &segments[start as usize..end as usize] {
+                self.upload_segment(segment).await?;
+            }
+        }
+
+        Ok(data_root)
+    }
+
+    /// Downloads the file of `data_root` to `path`, and verifies the downloaded data against the
+    /// data root.
+    pub async fn download_file(&self, data_root: DataRoot, path: impl AsRef<Path>) -> Result<()> {
+        let data = self.download_data(data_root).await?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    /// Downloads the encrypted file of `data_root`, and writes the decrypted data to `path`.
+    pub async fn download_encrypted_file(
+        &self,
+        data_root: DataRoot,
+        path: impl AsRef<Path>,
+        key: &EncryptionKey,
+    ) -> Result<()> {
+        let encrypted = self.download_data(data_root).await?;
+        let data = decrypt(key, &encrypted, None)?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    /// Downloads the file data of `data_root`, and verifies it against the data root.
+    pub async fn download_data(&self, data_root: DataRoot) -> Result<Vec<u8>> {
+        let info = self
+            .get_file_info(data_root)
+            .await?
+            .ok_or_else(|| anyhow!("file not found: {:?}", data_root))?;
+        if !info.finalized {
+            bail!("file not finalized: {:?}", data_root);
+        }
+
+        let size = info.tx.size as usize;
+        let end_index = bytes_to_chunks(size) as u32;
+        let mut data = Vec::with_capacity(end_index as usize * shared_types::CHUNK_SIZE);
+        let mut next_index = Some(0);
+
+        while let Some(start_index) = next_index {
+            let page: SegmentPage = self
+                .rpc
+                .request::<Option<SegmentPage>>(
+                    "ionian_downloadRange",
+                    rpc_params![data_root, start_index, end_index],
+                )
+                .await?
+                .ok_or_else(|| anyhow!("file data not found: {:?}", data_root))?;
+            data.extend_from_slice(&page.data);
+            next_index = page.next_index;
+        }
+
+        if sub_merkle_tree(&data)?.root() != data_root.0 {
+            bail!("downloaded data mismatch with data root {:?}", data_root);
+        }
+
+        data.truncate(size);
+
+        Ok(data)
+    }
+
+    /// Waits until the file of `data_root` is finalized. Wrap the call with a timeout to give up
+    /// waiting, since the transaction may not have been synced by the node yet.
+    pub async fn wait_finalized(&self, data_root: DataRoot) -> Result<FileInfo> {
+        loop {
+            if let Some(info) = self.get_file_info(data_root).await? {
+                if info.finalized {
+                    return Ok(info);
+                }
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{bail, Result};
+use ethereum_types::H256;
+use shared_types::rpc::SegmentWithProof;
+use shared_types::{
+    bytes_to_chunks, sub_merkle_tree, DataRoot, FileMerkleTree, FileProof, CHUNK_SIZE,
+};
+
+/// Splits the file data into segments of `chunks_per_segment` chunks, each with the merkle proof
+/// of the segment root in the file merkle tree. Returns the file data root and the segments.
+///
+/// The last chunk is padded with zeros as required by the node.
+pub fn split_into_segments(
+    data: &[u8],
+    chunks_per_segment: usize,
+) -> Result<(DataRoot, Vec<SegmentWithProof>)> {
+    if data.is_empty() {
+        bail!("file is empty");
+    }
+    if !chunks_per_segment.is_power_of_two() {
+        bail!("chunks per segment should be a power of 2");
+    }
+
+    let mut padded = data.to_vec();
+    padded.resize(bytes_to_chunks(data.len()) * CHUNK_SIZE, 0);
+    let data_root: DataRoot = sub_merkle_tree(&padded)?.root().into();
+
+    // Segments are complete subtrees of the file merkle tree, except the last one, so the tree
+    // built on segment roots has the same root as the file.
+    let segment_size = chunks_per_segment * CHUNK_SIZE;
+    let segment_roots = padded
+        .chunks(segment_size)
+        .map(|segment| Ok(sub_merkle_tree(segment)?.root()))
+        .collect::<Result<Vec<_>>>()?;
+    let segment_tree = FileMerkleTree::new(segment_roots);
+    if segment_tree.root() != data_root.0 {
+        bail!("segment merkle root mismatch");
+    }
+
+    let segments = padded
+        .chunks(segment_size)
+        .enumerate()
+        .map(|(index, segment)| {
+            let proof = segment_tree.gen_proof(index);
+            SegmentWithProof {
+                root: data_root,
+                data: segment.to_vec(),
+                index: index as u32,
+                proof: FileProof {
+                    lemma: proof.lemma().iter().map(|h| H256::from(*h)).collect(),
+                    path: proof.path().to_vec(),
+                },
+            }
+        })
+        .collect();
+
+    Ok((data_root, segments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_into_segments;
+    use shared_types::CHUNK_SIZE;
+
+    #[test]
+    fn test_split_into_segments() {
+        let chunks_per_segment = 4;
+        let data: Vec<u8> = (0..CHUNK_SIZE * 9 + 10).map(|i| i as u8).collect();
+
+        let (data_root, segments) = split_into_segments(&data, chunks_per_segment).unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[2].data.len(), CHUNK_SIZE * 2);
+
+        for segment in segments {
+            assert_eq!(segment.root, data_root);
+            segment.validate(data.len(), chunks_per_segment).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_split_single_segment() {
+        let data = vec![1u8; 100];
+
+        let (data_root, segments) = split_into_segments(&data, 1024).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].proof.lemma, vec![data_root]);
+        segments[0].validate(data.len(), 1024).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, bail, Result};
+use ethereum_types::U256;
+
+/// Version of the batch encoding, which is the first byte of the transaction data.
+pub const KV_BATCH_VERSION: u8 = 1;
+
+/// Value length that marks the deletion of a key.
+const DELETE_MARKER: u32 = u32::MAX;
+
+/// An update of a key in a stream, which deletes the key if `value` is `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KvUpdate {
+    pub stream_id: U256,
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// The updates in the data of a transaction, which are applied atomically in order.
+///
+/// The data is encoded as the version byte and the number of updates as `u32`, followed by the
+/// updates. Each update is the 32-byte stream id, the key and the value, where the key and the
+/// value are prefixed with their lengths as `u32`, and the value length is `u32::MAX` for a
+/// deletion. All integers are big endian.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KvBatch {
+    pub updates: Vec<KvUpdate>,
+}
+
+impl KvBatch {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![KV_BATCH_VERSION];
+        data.extend_from_slice(&(self.updates.len() as u32).to_be_bytes());
+
+        for update in &self.updates {
+            let mut stream_id = [0u8; 32];
+            update.stream_id.to_big_endian(&mut stream_id);
+            data.extend_from_slice(&stream_id);
+            data.extend_from_slice(&(update.key.len() as u32).to_be_bytes());
+            data.extend_from_slice(&update.key);
+            match &update.value {
+                Some(value) => {
+                    data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+                    data.extend_from_slice(value);
+                }
+                None => data.extend_from_slice(&DELETE_MARKER.to_be_bytes()),
+            }
+        }
+
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data };
+
+        let version = reader.read(1)?[0];
+        if version != KV_BATCH_VERSION {
+            bail!("unsupported kv batch version: {}", version);
+        }
+
+        let num_updates = reader.read_u32()?;
+        // Each update takes at least 40 bytes, which bounds the allocation below.
+        if num_updates as usize > reader.data.len() / 40 {
+            bail!("too many kv updates: {}", num_updates);
+        }
+
+        let mut updates = Vec::with_capacity(num_updates as usize);
+        for _ in 0..num_updates {
+            let stream_id = U256::from_big_endian(reader.read(32)?);
+            let key_len = reader.read_u32()?;
+            let key = reader.read(key_len as usize)?.to_vec();
+            let value = match reader.read_u32()? {
+                DELETE_MARKER => None,
+                value_len => Some(reader.read(value_len as usize)?.to_vec()),
+            };
+            updates.push(KvUpdate {
+                stream_id,
+                key,
+                value,
+            });
+        }
+
+        if !reader.data.is_empty() {
+            bail!(
+                "trailing data after kv updates: {} bytes",
+                reader.data.len()
+            );
+        }
+
+        Ok(KvBatch { updates })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(anyhow!(
+                "kv batch data too short: {} < {}",
+                self.data.len(),
+                len
+            ));
+        }
+        let (read, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(read)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.read(4)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let batch = KvBatch {
+            updates: vec![
+                KvUpdate {
+                    stream_id: U256::from(1),
+                    key: b"key".to_vec(),
+                    value: Some(b"value".to_vec()),
+                },
+                KvUpdate {
+                    stream_id: U256::MAX,
+                    key: vec![],
+                    value: Some(vec![]),
+                },
+                KvUpdate {
+                    stream_id: U256::from(1),
+                    key: b"key".to_vec(),
+                    value: None,
+                },
+            ],
+        };
+
+        let data = batch.encode();
+        assert_eq!(KvBatch::decode(&data).unwrap(), batch);
+
+        assert!(KvBatch::decode(&data[..data.len() - 1]).is_err());
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(KvBatch::decode(&trailing).is_err());
+
+        let mut wrong_version = data;
+        wrong_version[0] = 0;
+        assert!(KvBatch::decode(&wrong_version).is_err());
+    }
+}
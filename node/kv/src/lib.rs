@@ -0,0 +1,17 @@
+//! Key-value store on top of the log.
+//!
+//! The transactions tagged with the subscribed streams are interpreted as batches of key-value
+//! updates, see `KvBatch` for the encoding. Once the data of such a transaction is finalized, its
+//! updates are applied to a materialized index in the order of the log, so that every node
+//! subscribing to the same streams serves the same values.
+
+#[macro_use]
+extern crate tracing;
+
+mod batch;
+mod service;
+mod store;
+
+pub use batch::{KvBatch, KvUpdate, KV_BATCH_VERSION};
+pub use service::{KvConfig, KvService};
+pub use store::{KvStore, KvTxResult, KvValue};
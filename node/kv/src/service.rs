@@ -0,0 +1,125 @@
+use crate::store::{KvStore, KvTxResult};
+use anyhow::{anyhow, Result};
+use ethereum_types::U256;
+use shared_types::bytes_to_chunks;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use storage_async::{Store, StoreEvent};
+use task_executor::TaskExecutor;
+use tokio::sync::broadcast;
+
+/// Interval to check the new transactions, which are not notified by the store events.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transactions larger than this are not read, and result in `KvTxResult::DecodeError`.
+const MAX_TX_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct KvConfig {
+    pub db_dir: PathBuf,
+    /// Streams of the transactions to interpret as key-value updates.
+    pub streams: Vec<U256>,
+}
+
+/// Applies the finalized transactions of the subscribed streams to the `KvStore` in order, and
+/// reverts them upon chain reorgs.
+pub struct KvService {
+    streams: Vec<U256>,
+    kv_store: Arc<KvStore>,
+    store: Store,
+    store_events: broadcast::Receiver<StoreEvent>,
+}
+
+impl KvService {
+    pub fn spawn(
+        executor: TaskExecutor,
+        config: KvConfig,
+        store: Store,
+    ) -> Result<Arc<KvStore>, String> {
+        let kv_store = Arc::new(
+            KvStore::rocksdb(&config.db_dir)
+                .map_err(|e| format!("Unable to start KV store: {:?}", e))?,
+        );
+        info!(streams = ?config.streams, "KV store started");
+
+        let service = KvService {
+            streams: config.streams,
+            kv_store: kv_store.clone(),
+            store_events: store.subscribe_events(),
+            store,
+        };
+
+        executor.spawn(async move { Box::pin(service.start()).await }, "kv");
+
+        Ok(kv_store)
+    }
+
+    async fn start(mut self) {
+        loop {
+            if let Err(e) = self.process_txs().await {
+                warn!(%e, "Failed to process KV transactions");
+            }
+
+            tokio::select! {
+                event = self.store_events.recv() => match event {
+                    Ok(StoreEvent::Reverted { tx_seq }) => {
+                        // the log keeps `tx_seq`, or nothing if `u64::MAX`
+                        if let Err(e) = self.kv_store.revert_to(tx_seq.wrapping_add(1)) {
+                            error!(%tx_seq, %e, "Failed to revert KV store");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(%n, "KV service lagged behind store events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    }
+
+    /// Processes the transactions in order, until the next one is missing or not finalized.
+    async fn process_txs(&self) -> Result<()> {
+        loop {
+            let tx_seq = self.kv_store.next_tx_seq()?;
+            let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+                Some(tx) => tx,
+                None => return Ok(()),
+            };
+
+            if !tx.stream_ids.iter().any(|id| self.streams.contains(id)) {
+                self.kv_store.skip_tx(tx_seq)?;
+                continue;
+            }
+
+            // the in-place data is available along with the transaction
+            if tx.data.is_empty() && !self.store.check_tx_completed(tx_seq).await? {
+                return Ok(());
+            }
+
+            let data = if !tx.data.is_empty() {
+                tx.data.clone()
+            } else if tx.size == 0 || tx.size > MAX_TX_SIZE {
+                vec![]
+            } else {
+                let mut data = self
+                    .store
+                    .get_chunks_by_tx_and_index_range(tx_seq, 0, bytes_to_chunks(tx.size as usize))
+                    .await?
+                    .ok_or_else(|| anyhow!("finalized data missing: tx_seq={}", tx_seq))?
+                    .data;
+                data.truncate(tx.size as usize);
+                data
+            };
+
+            let result = self.kv_store.apply_tx(&tx, &data)?;
+            if result == KvTxResult::Committed {
+                debug!(%tx_seq, "KV transaction committed");
+            } else {
+                debug!(%tx_seq, ?result, "KV transaction ignored");
+            }
+        }
+    }
+}
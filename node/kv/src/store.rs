@@ -0,0 +1,333 @@
+use crate::batch::KvBatch;
+use anyhow::{anyhow, bail, Result};
+use ethereum_types::U256;
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use serde::{Deserialize, Serialize};
+use shared_types::Transaction;
+use ssz::{Decode, Encode};
+use std::path::Path;
+use std::sync::Arc;
+use storage::IonianKeyValueDB;
+
+/// Versions of the values, keyed by the stream id, the key and the tx seq of the update.
+pub const COL_VALUE: u32 = 0;
+/// Results of the processed transactions of the subscribed streams.
+pub const COL_TX_RESULT: u32 = 1;
+/// Keys of the values written by each transaction, to revert the transaction.
+pub const COL_TX_KEYS: u32 = 2;
+pub const COL_MISC: u32 = 3;
+pub const COL_NUM: u32 = 4;
+
+const NEXT_TX_SEQ_KEY: &str = "next_tx_seq";
+
+/// The result of applying a transaction of the subscribed streams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KvTxResult {
+    /// All the updates of the transaction are applied.
+    Committed,
+    /// The transaction data is not a valid batch of updates, which is ignored.
+    DecodeError,
+    /// Some updates are not in the streams of the transaction, so the transaction is ignored.
+    InvalidStream,
+}
+
+impl KvTxResult {
+    fn to_byte(self) -> u8 {
+        match self {
+            KvTxResult::Committed => 0,
+            KvTxResult::DecodeError => 1,
+            KvTxResult::InvalidStream => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            0 => KvTxResult::Committed,
+            1 => KvTxResult::DecodeError,
+            2 => KvTxResult::InvalidStream,
+            _ => bail!("invalid kv tx result: {}", byte),
+        })
+    }
+}
+
+/// A version of the value of a key, which is written by the transaction `version`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KvValue {
+    pub version: u64,
+    pub data: Vec<u8>,
+}
+
+/// The materialized key-value index of the transactions in the subscribed streams, which are
+/// applied in the order of the log.
+pub struct KvStore {
+    kvdb: Arc<dyn IonianKeyValueDB>,
+}
+
+impl KvStore {
+    pub fn rocksdb(path: impl AsRef<Path>) -> Result<Self> {
+        let db_config = DatabaseConfig::with_columns(COL_NUM);
+        let db = Arc::new(Database::open(&db_config, path)?);
+        Ok(KvStore { kvdb: db })
+    }
+
+    pub fn memorydb() -> Self {
+        KvStore {
+            kvdb: Arc::new(kvdb_memorydb::create(COL_NUM)),
+        }
+    }
+
+    /// The sequence number of the next transaction to process.
+    pub fn next_tx_seq(&self) -> Result<u64> {
+        match self.kvdb.get(COL_MISC, NEXT_TX_SEQ_KEY.as_bytes())? {
+            Some(value) => Ok(u64::from_be_bytes(value.as_slice().try_into()?)),
+            None => Ok(0),
+        }
+    }
+
+    /// Skips the transaction `tx_seq`, which is not in the subscribed streams.
+    pub fn skip_tx(&self, tx_seq: u64) -> Result<()> {
+        self.check_next_tx_seq(tx_seq)?;
+        Ok(self.kvdb.put(
+            COL_MISC,
+            NEXT_TX_SEQ_KEY.as_bytes(),
+            &(tx_seq + 1).to_be_bytes(),
+        )?)
+    }
+
+    /// Applies the updates in the finalized data of the transaction atomically, and returns the
+    /// result of the transaction.
+    pub fn apply_tx(&self, tx: &Transaction, data: &[u8]) -> Result<KvTxResult> {
+        self.check_next_tx_seq(tx.seq)?;
+
+        let mut db_tx = self.kvdb.transaction();
+        let mut keys = vec![];
+        let result = match KvBatch::decode(data) {
+            Err(_) => KvTxResult::DecodeError,
+            Ok(batch)
+                if batch
+                    .updates
+                    .iter()
+                    .any(|update| !tx.stream_ids.contains(&update.stream_id)) =>
+            {
+                KvTxResult::InvalidStream
+            }
+            Ok(batch) => {
+                for update in batch.updates {
+                    let key = value_key(update.stream_id, &update.key, tx.seq);
+                    // The first byte tells a deletion from an empty value.
+                    let value = match update.value {
+                        Some(value) => [&[1u8][..], &value].concat(),
+                        None => vec![0u8],
+                    };
+                    db_tx.put(COL_VALUE, &key, &value);
+                    keys.push(key);
+                }
+                KvTxResult::Committed
+            }
+        };
+
+        let tx_seq = tx.seq.to_be_bytes();
+        db_tx.put(COL_TX_RESULT, &tx_seq, &[result.to_byte()]);
+        db_tx.put(COL_TX_KEYS, &tx_seq, &keys.as_ssz_bytes());
+        db_tx.put(
+            COL_MISC,
+            NEXT_TX_SEQ_KEY.as_bytes(),
+            &(tx.seq + 1).to_be_bytes(),
+        );
+        self.kvdb.write(db_tx)?;
+
+        Ok(result)
+    }
+
+    /// Returns the latest value of the key in the stream, or the value as of the transaction
+    /// `version` if provided. Returns `None` if the key does not exist or is deleted.
+    pub fn get_value(
+        &self,
+        stream_id: U256,
+        key: &[u8],
+        version: Option<u64>,
+    ) -> Result<Option<KvValue>> {
+        let prefix = value_key_prefix(stream_id, key);
+        let mut latest = None;
+        // The versions are in ascending order.
+        for (k, v) in self.kvdb.iter_with_prefix(COL_VALUE, &prefix) {
+            let tx_seq = decode_tx_seq(&k[prefix.len()..])?;
+            if matches!(version, Some(version) if tx_seq > version) {
+                break;
+            }
+            latest = Some((tx_seq, v));
+        }
+
+        Ok(match latest {
+            Some((version, value)) if value.first() == Some(&1) => Some(KvValue {
+                version,
+                data: value[1..].to_vec(),
+            }),
+            _ => None,
+        })
+    }
+
+    /// Returns the result of the transaction `tx_seq`, or `None` if it is not processed yet or
+    /// not in the subscribed streams.
+    pub fn get_tx_result(&self, tx_seq: u64) -> Result<Option<KvTxResult>> {
+        match self.kvdb.get(COL_TX_RESULT, &tx_seq.to_be_bytes())? {
+            Some(value) => Ok(Some(KvTxResult::from_byte(
+                *value.first().ok_or_else(|| anyhow!("empty kv tx result"))?,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reverts the transactions since `tx_seq`, e.g. reverted by a chain reorg.
+    pub fn revert_to(&self, tx_seq: u64) -> Result<()> {
+        let next_tx_seq = self.next_tx_seq()?;
+        if tx_seq >= next_tx_seq {
+            return Ok(());
+        }
+
+        let mut db_tx = self.kvdb.transaction();
+        for seq in tx_seq..next_tx_seq {
+            let seq = seq.to_be_bytes();
+            if let Some(keys) = self.kvdb.get(COL_TX_KEYS, &seq)? {
+                let keys = Vec::<Vec<u8>>::from_ssz_bytes(&keys)
+                    .map_err(|e| anyhow!("invalid kv tx keys: {:?}", e))?;
+                for key in keys {
+                    db_tx.delete(COL_VALUE, &key);
+                }
+            }
+            db_tx.delete(COL_TX_KEYS, &seq);
+            db_tx.delete(COL_TX_RESULT, &seq);
+        }
+        db_tx.put(COL_MISC, NEXT_TX_SEQ_KEY.as_bytes(), &tx_seq.to_be_bytes());
+        self.kvdb.write(db_tx)?;
+
+        Ok(())
+    }
+
+    fn check_next_tx_seq(&self, tx_seq: u64) -> Result<()> {
+        let next_tx_seq = self.next_tx_seq()?;
+        if tx_seq != next_tx_seq {
+            bail!(
+                "kv transactions out of order: expected {}, got {}",
+                next_tx_seq,
+                tx_seq
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The keys are length prefixed, so that a key is never the prefix of another one.
+fn value_key_prefix(stream_id: U256, key: &[u8]) -> Vec<u8> {
+    let mut prefix = vec![0u8; 32];
+    stream_id.to_big_endian(&mut prefix);
+    prefix.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    prefix.extend_from_slice(key);
+    prefix
+}
+
+fn value_key(stream_id: U256, key: &[u8], tx_seq: u64) -> Vec<u8> {
+    let mut value_key = value_key_prefix(stream_id, key);
+    value_key.extend_from_slice(&tx_seq.to_be_bytes());
+    value_key
+}
+
+fn decode_tx_seq(data: &[u8]) -> Result<u64> {
+    Ok(u64::from_be_bytes(data.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::KvUpdate;
+
+    fn kv_tx(
+        seq: u64,
+        stream_id: u64,
+        updates: &[(&[u8], Option<&[u8]>)],
+    ) -> (Transaction, Vec<u8>) {
+        let tx = Transaction {
+            stream_ids: vec![U256::from(stream_id)],
+            data: vec![],
+            data_merkle_root: Default::default(),
+            merkle_nodes: vec![],
+            start_entry_index: 0,
+            size: 0,
+            seq,
+            sender: Default::default(),
+            block_timestamp: 0,
+        };
+        let batch = KvBatch {
+            updates: updates
+                .iter()
+                .map(|(key, value)| KvUpdate {
+                    stream_id: U256::from(stream_id),
+                    key: key.to_vec(),
+                    value: value.map(|value| value.to_vec()),
+                })
+                .collect(),
+        };
+        (tx, batch.encode())
+    }
+
+    #[test]
+    fn test_apply_and_revert() {
+        let store = KvStore::memorydb();
+        let stream_id = U256::from(1);
+
+        let (tx, data) = kv_tx(0, 1, &[(b"a", Some(b"1")), (b"ab", Some(b"2"))]);
+        assert_eq!(store.apply_tx(&tx, &data).unwrap(), KvTxResult::Committed);
+        store.skip_tx(1).unwrap();
+        let (tx, data) = kv_tx(2, 1, &[(b"a", Some(b"3")), (b"ab", None)]);
+        assert_eq!(store.apply_tx(&tx, &data).unwrap(), KvTxResult::Committed);
+        assert_eq!(store.next_tx_seq().unwrap(), 3);
+
+        let value = store.get_value(stream_id, b"a", None).unwrap().unwrap();
+        assert_eq!((value.version, value.data), (2, b"3".to_vec()));
+        let value = store.get_value(stream_id, b"a", Some(1)).unwrap().unwrap();
+        assert_eq!((value.version, value.data), (0, b"1".to_vec()));
+        assert_eq!(store.get_value(stream_id, b"ab", None).unwrap(), None);
+        assert!(store
+            .get_value(stream_id, b"ab", Some(0))
+            .unwrap()
+            .is_some());
+        assert_eq!(store.get_value(U256::from(2), b"a", None).unwrap(), None);
+
+        // out of order
+        let (tx, data) = kv_tx(4, 1, &[]);
+        assert!(store.apply_tx(&tx, &data).is_err());
+
+        store.revert_to(1).unwrap();
+        assert_eq!(store.next_tx_seq().unwrap(), 1);
+        assert_eq!(store.get_tx_result(2).unwrap(), None);
+        let value = store.get_value(stream_id, b"a", None).unwrap().unwrap();
+        assert_eq!((value.version, value.data), (0, b"1".to_vec()));
+        assert!(store.get_value(stream_id, b"ab", None).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_invalid_tx() {
+        let store = KvStore::memorydb();
+
+        let (tx, _) = kv_tx(0, 1, &[]);
+        assert_eq!(
+            store.apply_tx(&tx, b"invalid").unwrap(),
+            KvTxResult::DecodeError
+        );
+
+        let (mut tx, data) = kv_tx(1, 1, &[(b"a", Some(b"1"))]);
+        tx.stream_ids = vec![U256::from(2)];
+        assert_eq!(
+            store.apply_tx(&tx, &data).unwrap(),
+            KvTxResult::InvalidStream
+        );
+        assert_eq!(store.get_value(U256::from(1), b"a", None).unwrap(), None);
+
+        assert_eq!(
+            store.get_tx_result(1).unwrap(),
+            Some(KvTxResult::InvalidStream)
+        );
+        assert_eq!(store.get_tx_result(2).unwrap(), None);
+    }
+}
@@ -1,2 +1,6 @@
 use ethers::prelude::abigen;
 abigen!(IonianFlow, "./src/contracts/Flow.json");
+
+mod version;
+
+pub use version::{FlowContractV1, FlowContractVersion, SubmissionDecoder};
@@ -0,0 +1,118 @@
+//! Layouts of the submission event in the versions of the flow contract. The node could follow a
+//! flow contract upgraded to a new layout without a lockstep release, as long as the new version
+//! is supported here.
+
+use crate::contracts::SubmissionFilter;
+use anyhow::{anyhow, bail, Result};
+use append_merkle::{Algorithm, Sha3Algorithm};
+use ethers::abi::RawLog;
+use ethers::contract::EthEvent;
+use ethers::prelude::{EthLogDecode, Log, U256};
+use ethers::types::H256;
+use shared_types::{DataRoot, Transaction};
+
+/// Decodes the submission events of a version of the flow contract.
+pub trait FlowContractVersion: Send + Sync {
+    fn version(&self) -> u32;
+
+    /// The topic of the submission event, which tells the version of a log.
+    fn submission_topic(&self) -> H256;
+
+    fn decode_submission(&self, log: RawLog) -> Result<Transaction>;
+}
+
+/// The flow contract described by `Flow.json`.
+pub struct FlowContractV1;
+
+impl FlowContractVersion for FlowContractV1 {
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn submission_topic(&self) -> H256 {
+        SubmissionFilter::signature()
+    }
+
+    fn decode_submission(&self, log: RawLog) -> Result<Transaction> {
+        let e = SubmissionFilter::decode_log(&log)?;
+        if e.submission.1.is_empty() {
+            bail!("no merkle nodes: seq={}", e.submission_index);
+        }
+        let tx = Transaction {
+            stream_ids: vec![],
+            data: vec![],
+            data_merkle_root: nodes_to_root(&e.submission.1),
+            merkle_nodes: e
+                .submission
+                .1
+                .iter()
+                // the submission height is the height of the root node starting from height 0.
+                .map(|(root, height)| (height.as_usize() + 1, root.into()))
+                .collect(),
+            start_entry_index: e.start_pos.as_u64(),
+            size: e.submission.0.as_u64(),
+            seq: e.submission_index.as_u64(),
+            sender: e.sender.0.into(),
+            // not in the event, and set from the block by the caller
+            block_timestamp: 0,
+        };
+        tx.validate()?;
+        Ok(tx)
+    }
+}
+
+fn nodes_to_root(node_list: &Vec<([u8; 32], U256)>) -> DataRoot {
+    let mut root: DataRoot = node_list.last().expect("not empty").0.into();
+    for (next_node, _) in node_list[..node_list.len() - 1].iter().rev() {
+        root = Sha3Algorithm::parent(&next_node.into(), &root);
+    }
+    root
+}
+
+/// Decodes the submission events of the configured version, or of any supported version if the
+/// version is auto-detected, in which case each log is decoded by the version of its topic.
+pub struct SubmissionDecoder {
+    versions: Vec<Box<dyn FlowContractVersion>>,
+}
+
+impl SubmissionDecoder {
+    pub fn new(version: Option<u32>) -> Result<Self> {
+        let supported: Vec<Box<dyn FlowContractVersion>> = vec![Box::new(FlowContractV1)];
+        let versions = match version {
+            Some(version) => supported
+                .into_iter()
+                .filter(|v| v.version() == version)
+                .collect(),
+            None => supported,
+        };
+
+        if versions.is_empty() {
+            bail!("unsupported flow contract version {:?}", version);
+        }
+
+        Ok(SubmissionDecoder { versions })
+    }
+
+    /// The topics of the submission events to filter logs.
+    pub fn topics(&self) -> Vec<H256> {
+        self.versions.iter().map(|v| v.submission_topic()).collect()
+    }
+
+    pub fn decode(&self, log: Log) -> Result<Transaction> {
+        let topic = log
+            .topics
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("log without topics"))?;
+        let version = self
+            .versions
+            .iter()
+            .find(|v| v.submission_topic() == topic)
+            .ok_or_else(|| anyhow!("unknown submission event topic {:?}", topic))?;
+
+        version.decode_submission(RawLog {
+            topics: log.topics,
+            data: log.data.to_vec(),
+        })
+    }
+}
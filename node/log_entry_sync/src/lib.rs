@@ -1,6 +1,9 @@
 extern crate core;
+#[macro_use]
+extern crate lazy_static;
 
-pub(crate) mod contracts;
+pub mod contracts;
+mod metrics;
 pub(crate) mod rpc_proxy;
 mod sync_manager;
 
@@ -0,0 +1,12 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref FLOW_ROOT_DIVERGED: Result<IntGauge> = try_create_int_gauge(
+        "log_sync_flow_root_diverged",
+        "Set to 1 when the local flow root diverges from the root committed on-chain"
+    );
+    pub static ref FLOW_ROOT_DIVERGENCES: Result<IntCounter> = try_create_int_counter(
+        "log_sync_flow_root_divergences_total",
+        "Count of flow root verifications that found a divergence from the chain"
+    );
+}
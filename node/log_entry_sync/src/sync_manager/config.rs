@@ -1,16 +1,40 @@
 use crate::rpc_proxy::ContractAddress;
+use shared_types::StreamFilter;
 use std::time::Duration;
 
 const DEFAULT_FETCH_BATCH_SIZE: usize = 10;
 const DEFAULT_SYNC_PERIOD_MS: u64 = 500;
+const DEFAULT_MAX_BLOCK_LAG: u64 = 20;
+const DEFAULT_CONFIRMATION_BLOCK_COUNT: u64 = 12;
+const DEFAULT_CATCH_UP_CONCURRENCY: usize = 8;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+const DEFAULT_ROOT_VERIFY_INTERVAL_SECS: u64 = 300;
 
 pub struct LogSyncConfig {
     pub rpc_endpoint_url: String,
+    /// Endpoints to fail over in order, if `rpc_endpoint_url` errors or falls behind.
+    pub fallback_rpc_endpoint_urls: Vec<String>,
+    /// WebSocket endpoint to subscribe to new logs, which are synced at `sync_period` otherwise.
+    pub ws_endpoint_url: Option<String>,
+    /// Maximum number of blocks an endpoint could fall behind the others before failover.
+    pub max_block_lag: u64,
+    /// Maximum number of concurrent requests to each endpoint.
+    pub max_concurrent_requests: usize,
     pub contract_address: ContractAddress,
+    /// Version of the flow contract, or `None` to detect from the submission events.
+    pub contract_version: Option<u32>,
 
     pub fetch_batch_size: usize,
     pub sync_period: Duration,
     pub start_block_number: u64,
+    /// Number of confirmations before the transactions of a block are synced.
+    pub confirmation_block_count: u64,
+    /// Maximum number of block ranges to fetch logs in parallel while catching up.
+    pub catch_up_concurrency: usize,
+    /// Only the new transactions of the subscribed streams are announced to peers.
+    pub stream_filter: StreamFilter,
+    /// Interval to verify the local flow root against the chain, or `None` to disable.
+    pub root_verify_interval: Option<Duration>,
 }
 
 impl LogSyncConfig {
@@ -21,10 +45,26 @@ impl LogSyncConfig {
     ) -> Self {
         Self {
             rpc_endpoint_url,
+            fallback_rpc_endpoint_urls: vec![],
+            ws_endpoint_url: None,
+            max_block_lag: DEFAULT_MAX_BLOCK_LAG,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
             contract_address,
+            contract_version: None,
             fetch_batch_size: DEFAULT_FETCH_BATCH_SIZE,
             sync_period: Duration::from_millis(DEFAULT_SYNC_PERIOD_MS),
             start_block_number,
+            confirmation_block_count: DEFAULT_CONFIRMATION_BLOCK_COUNT,
+            catch_up_concurrency: DEFAULT_CATCH_UP_CONCURRENCY,
+            stream_filter: Default::default(),
+            root_verify_interval: Some(Duration::from_secs(DEFAULT_ROOT_VERIFY_INTERVAL_SECS)),
         }
     }
+
+    /// Returns the endpoints in the order of priority.
+    pub fn rpc_endpoint_urls(&self) -> Vec<String> {
+        let mut urls = vec![self.rpc_endpoint_url.clone()];
+        urls.extend(self.fallback_rpc_endpoint_urls.iter().cloned());
+        urls
+    }
 }
@@ -0,0 +1,206 @@
+//! A JSON-RPC client over multiple blockchain endpoints in the order of priority, which fails over
+//! to the next healthy endpoint once the active one errors or falls behind the others, so that
+//! the log sync keeps ingesting new transactions.
+
+use async_trait::async_trait;
+use ethers::prelude::{Http, JsonRpcClient, U64};
+use ethers::providers::HttpClientError;
+use jsonrpsee::tracing::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::cmp;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Interval to probe the health of the endpoints.
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Backoff before a failed endpoint is used or probed again, which doubles upon each consecutive
+/// failure.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default)]
+struct EndpointHealth {
+    consecutive_failures: u32,
+    /// The endpoint is not used until then.
+    backoff_until: Option<Instant>,
+    /// The latest block number probed from the endpoint.
+    block_number: Option<u64>,
+}
+
+impl EndpointHealth {
+    fn in_backoff(&self, now: Instant) -> bool {
+        self.backoff_until.map_or(false, |until| now < until)
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    client: Http,
+    /// Limits the concurrent requests, since some providers reject bursts of requests.
+    request_limit: Semaphore,
+    health: Mutex<EndpointHealth>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    endpoints: Vec<Endpoint>,
+    /// Index of the endpoint that serves the requests.
+    active: AtomicUsize,
+    /// Maximum number of blocks an endpoint could fall behind the others to be healthy.
+    max_block_lag: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FailoverClient {
+    inner: Arc<Inner>,
+}
+
+impl FailoverClient {
+    pub fn new(
+        urls: &[String],
+        max_block_lag: u64,
+        max_concurrent_requests: usize,
+    ) -> anyhow::Result<Self> {
+        if urls.is_empty() {
+            anyhow::bail!("no blockchain RPC endpoint");
+        }
+
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                Ok(Endpoint {
+                    client: Http::from_str(url)?,
+                    request_limit: Semaphore::new(cmp::max(max_concurrent_requests, 1)),
+                    health: Default::default(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(FailoverClient {
+            inner: Arc::new(Inner {
+                endpoints,
+                active: AtomicUsize::new(0),
+                max_block_lag,
+            }),
+        })
+    }
+
+    pub fn num_endpoints(&self) -> usize {
+        self.inner.endpoints.len()
+    }
+
+    /// Queries the latest block number of the endpoints out of backoff, and then switches to
+    /// the healthy endpoint of the highest priority.
+    pub async fn probe(&self) {
+        for (index, endpoint) in self.inner.endpoints.iter().enumerate() {
+            if endpoint.health.lock().unwrap().in_backoff(Instant::now()) {
+                continue;
+            }
+
+            match endpoint
+                .client
+                .request::<_, U64>("eth_blockNumber", ())
+                .await
+            {
+                Ok(block_number) => {
+                    let mut health = endpoint.health.lock().unwrap();
+                    health.consecutive_failures = 0;
+                    health.backoff_until = None;
+                    health.block_number = Some(block_number.as_u64());
+                }
+                Err(e) => {
+                    debug!(%index, ?e, "Blockchain RPC endpoint probe failed");
+                    self.inner.on_failure(index);
+                }
+            }
+        }
+
+        self.inner.select();
+    }
+}
+
+impl Inner {
+    fn on_failure(&self, index: usize) {
+        {
+            let mut health = self.endpoints[index].health.lock().unwrap();
+            health.consecutive_failures += 1;
+            let backoff = MIN_BACKOFF
+                .checked_mul(1 << cmp::min(health.consecutive_failures - 1, 16))
+                .map_or(MAX_BACKOFF, |backoff| cmp::min(backoff, MAX_BACKOFF));
+            health.backoff_until = Some(Instant::now() + backoff);
+        }
+
+        self.select();
+    }
+
+    /// Switches to the first endpoint neither in backoff nor behind the others. The active
+    /// endpoint is kept if no endpoint is healthy.
+    fn select(&self) {
+        let now = Instant::now();
+        let healths: Vec<(bool, Option<u64>)> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = endpoint.health.lock().unwrap();
+                (health.in_backoff(now), health.block_number)
+            })
+            .collect();
+
+        let best_block = healths
+            .iter()
+            .filter(|(in_backoff, _)| !in_backoff)
+            .filter_map(|(_, block_number)| *block_number)
+            .max();
+        let is_healthy = |(in_backoff, block_number): &(bool, Option<u64>)| {
+            !in_backoff
+                && match (best_block, block_number) {
+                    (Some(best), Some(number)) => best - number <= self.max_block_lag,
+                    _ => true,
+                }
+        };
+
+        let active = self.active.load(Ordering::Relaxed);
+        match healths.iter().position(is_healthy) {
+            Some(index) if index != active => {
+                if self.active.swap(index, Ordering::Relaxed) == active {
+                    info!(from = %active, to = %index, "Switch blockchain RPC endpoint");
+                }
+            }
+            Some(_) => {}
+            None => warn!("No healthy blockchain RPC endpoint"),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FailoverClient {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let index = self.inner.active.load(Ordering::Relaxed);
+        let endpoint = &self.inner.endpoints[index];
+        // the semaphore is never closed
+        let _permit = endpoint.request_limit.acquire().await.ok();
+        let result = endpoint.client.request(method, params).await;
+
+        // errors returned by the blockchain node, e.g. an unknown filter after failover, do not
+        // imply the endpoint is unhealthy
+        if let Err(e) = &result {
+            if !matches!(e, HttpClientError::JsonRpcError(_)) && self.num_endpoints() > 1 {
+                warn!(%index, ?e, "Blockchain RPC endpoint failed");
+                self.inner.on_failure(index);
+            }
+        }
+
+        result
+    }
+}
@@ -1,217 +1,434 @@
-use crate::contracts::{IonianFlow, SubmissionFilter};
+use crate::contracts::SubmissionDecoder;
 use crate::rpc_proxy::ContractAddress;
-use crate::sync_manager::{repeat_run_and_log, RETRY_WAIT_MS};
-use anyhow::{anyhow, Result};
-use append_merkle::{Algorithm, Sha3Algorithm};
-use ethers::abi::RawLog;
-use ethers::prelude::{BlockNumber, EthLogDecode, Http, Log, Middleware, Provider, U256};
-use ethers::providers::FilterKind;
+use crate::sync_manager::config::LogSyncConfig;
+use crate::sync_manager::failover::{FailoverClient, PROBE_INTERVAL};
+use crate::sync_manager::{subscription, RETRY_WAIT_MS};
+use anyhow::{anyhow, bail, Result};
+use ethers::prelude::{Filter, Log, Middleware, Provider};
 use ethers::types::H256;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use jsonrpsee::tracing::{debug, error, info};
-use shared_types::{DataRoot, Transaction};
+use shared_types::Transaction;
+use std::cmp;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::mem;
 use std::sync::Arc;
 use std::time::Duration;
+use storage::log_store::{LogSyncCheckpoint, MAX_LOG_SYNC_CHECKPOINTS};
 use task_executor::TaskExecutor;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::{watch, Notify};
 
 const LOG_PAGE_SIZE: u64 = 1000;
 
 pub struct LogEntryFetcher {
     contract_address: ContractAddress,
-    provider: Arc<Provider<Http>>,
+    decoder: Arc<SubmissionDecoder>,
+    provider: Arc<Provider<FailoverClient>>,
 }
 
 impl LogEntryFetcher {
-    pub async fn new(url: &str, contract_address: ContractAddress) -> Result<Self> {
-        let provider = Arc::new(Provider::try_from(url)?);
+    /// Connects to the blockchain with `urls` in the order of priority. The health of the
+    /// endpoints is probed periodically if there are fallback endpoints.
+    pub async fn new(
+        urls: &[String],
+        contract_address: ContractAddress,
+        contract_version: Option<u32>,
+        max_block_lag: u64,
+        max_concurrent_requests: usize,
+        executor: &TaskExecutor,
+    ) -> Result<Self> {
+        let client = FailoverClient::new(urls, max_block_lag, max_concurrent_requests)?;
+        if client.num_endpoints() > 1 {
+            let client = client.clone();
+            executor.spawn(
+                async move {
+                    loop {
+                        tokio::time::sleep(PROBE_INTERVAL).await;
+                        client.probe().await;
+                    }
+                },
+                "log_sync_probe",
+            );
+        }
+
+        let provider = Arc::new(Provider::new(client));
         // TODO: `error` types are removed from the ABI json file.
         Ok(Self {
             contract_address,
+            decoder: Arc::new(SubmissionDecoder::new(contract_version)?),
             provider,
         })
     }
 
-    pub fn start_recover(
+    pub fn provider(&self) -> Arc<Provider<FailoverClient>> {
+        self.provider.clone()
+    }
+
+    /// Starts syncing the logs of the blocks with at least `confirmation_block_count`
+    /// confirmations from `start`. The logs before the confirmed chain head at the time of
+    /// catching up are sent to the first receiver, which is then closed, and the following logs
+    /// are sent to the second receiver. The latest block number of the chain is published to
+    /// `chain_head`.
+    ///
+    /// If a WebSocket endpoint is configured, the sync is also triggered by the subscription,
+    /// besides polling.
+    ///
+    /// While catching up, the logs of multiple pages of blocks are fetched in parallel, and then
+    /// sent in the order of blocks.
+    ///
+    /// The synced blocks are checked against the chain before each sync. If a block is reorged
+    /// despite the confirmations, the sync reverts to the last block still on the chain.
+    pub fn start_sync(
         &self,
-        start_block_number: u64,
+        start: SyncStart,
+        config: &LogSyncConfig,
+        chain_head: watch::Sender<Option<u64>>,
         executor: &TaskExecutor,
-    ) -> UnboundedReceiver<LogFetchProgress> {
-        let provider = self.provider.clone();
+    ) -> (
+        UnboundedReceiver<LogFetchProgress>,
+        UnboundedReceiver<LogFetchProgress>,
+    ) {
         let (recover_tx, recover_rx) = tokio::sync::mpsc::unbounded_channel();
-        let contract = IonianFlow::new(self.contract_address, provider.clone());
+        let (watch_tx, watch_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let sync_period = config.sync_period;
+        let new_block = Arc::new(Notify::new());
+        if let Some(url) = &config.ws_endpoint_url {
+            subscription::spawn(
+                executor,
+                url.clone(),
+                self.contract_address,
+                self.decoder.topics(),
+                config.confirmation_block_count,
+                new_block.clone(),
+            );
+        }
+
+        let history = VecDeque::from(start.checkpoints);
+        let mut sync = ConfirmedSync {
+            provider: self.provider.clone(),
+            contract_address: self.contract_address,
+            decoder: self.decoder.clone(),
+            confirmation_block_count: config.confirmation_block_count,
+            catch_up_concurrency: cmp::max(config.catch_up_concurrency, 1),
+            next_block_number: start.block_number,
+            next_tx_seq: start.next_tx_seq,
+            history,
+            recover_tx: Some(recover_tx),
+            watch_tx,
+            chain_head,
+        };
+
         executor.spawn(
             async move {
-                let mut progress = start_block_number;
-                let mut filter = contract.submission_filter().from_block(progress).filter;
-                let mut stream = provider.get_logs_paginated(&filter, LOG_PAGE_SIZE);
-                debug!("start_recover starts, start={}", start_block_number);
-                while let Some(maybe_log) = stream.next().await {
-                    match maybe_log {
-                        Ok(log) => {
-                            let sync_progress =
-                                if log.block_hash.is_some() && log.block_number.is_some() {
-                                    let synced_block = LogFetchProgress::SyncedBlock((
-                                        log.block_number.unwrap().as_u64(),
-                                        log.block_hash.unwrap(),
-                                    ));
-                                    progress = log.block_number.unwrap().as_u64();
-                                    Some(synced_block)
-                                } else {
-                                    None
-                                };
-
-                            match SubmissionFilter::decode_log(&RawLog {
-                                topics: log.topics,
-                                data: log.data.to_vec(),
-                            }) {
-                                Ok(event) => {
-                                    if let Err(e) = recover_tx
-                                        .send(submission_event_to_transaction(event))
-                                        .and_then(|_| match sync_progress {
-                                            Some(b) => recover_tx.send(b),
-                                            None => Ok(()),
-                                        })
-                                    {
-                                        error!("send error: e={:?}", e);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("log decode error: e={:?}", e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("log query error: e={:?}", e);
-                            filter = filter.from_block(progress);
-                            stream = provider.get_logs_paginated(&filter, LOG_PAGE_SIZE);
-                            tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
+                debug!("log sync starts, start={}", start.block_number);
+                loop {
+                    if let Err(e) = sync.sync_once().await {
+                        error!("log sync error: e={:?}", e);
+                        tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
+                    } else if sync.recover_tx.is_none() {
+                        tokio::select! {
+                            _ = tokio::time::sleep(sync_period) => {}
+                            _ = new_block.notified() => {}
                         }
                     }
                 }
             },
-            "log recover",
+            "log sync",
         );
-        recover_rx
+
+        (recover_rx, watch_rx)
     }
+}
 
-    pub fn start_watch(
-        &self,
-        start_block_number: u64,
-        executor: &TaskExecutor,
-    ) -> UnboundedReceiver<LogFetchProgress> {
-        let (watch_tx, watch_rx) = tokio::sync::mpsc::unbounded_channel();
-        let contract = IonianFlow::new(self.contract_address, self.provider.clone());
-        let provider = self.provider.clone();
-        executor.spawn(
-            async move {
-                let mut filter = contract
-                    .submission_filter()
-                    .from_block(start_block_number)
-                    .filter;
-                debug!("start_watch starts, start={}", start_block_number);
-                let mut filter_id =
-                    repeat_run_and_log(|| provider.new_filter(FilterKind::Logs(&filter))).await;
-                let mut progress = start_block_number;
+#[derive(Debug)]
+pub enum LogFetchProgress {
+    /// All the logs up to the block are synced.
+    SyncedBlock(LogSyncCheckpoint),
+    Transaction(Transaction),
+    /// The transactions from the sequence number are reorged out of the chain.
+    Reverted(u64),
+}
 
-                loop {
-                    match Self::watch_loop(provider.as_ref(), filter_id, &watch_tx).await {
-                        Err(e) => {
-                            error!("log sync watch error: e={:?}", e);
-                            filter = filter.from_block(progress);
-                            filter_id = repeat_run_and_log(|| {
-                                provider.new_filter(FilterKind::Logs(&filter))
-                            })
-                            .await;
-                        }
-                        Ok(Some(p)) => {
-                            progress = p;
-                            info!("log sync to block number {:?}", progress);
-                        }
-                        Ok(None) => {
-                            error!(
-                                "log sync gets entries without progress? old_progress={}",
-                                progress
-                            )
-                        }
-                    }
-                    tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
+/// Where the log sync starts from.
+pub struct SyncStart {
+    pub block_number: u64,
+    pub next_tx_seq: u64,
+    /// The recently synced blocks in ascending order, to check if they are reorged before the
+    /// sync continues.
+    pub checkpoints: Vec<LogSyncCheckpoint>,
+}
+
+/// The submission events of a page of blocks, where `to` is the last block.
+struct SyncedPage {
+    to: u64,
+    block_hash: H256,
+    txs: Vec<Transaction>,
+}
+
+/// State of the log sync task.
+struct ConfirmedSync {
+    provider: Arc<Provider<FailoverClient>>,
+    contract_address: ContractAddress,
+    decoder: Arc<SubmissionDecoder>,
+    confirmation_block_count: u64,
+    /// Maximum number of pages fetched in parallel while catching up.
+    catch_up_concurrency: usize,
+    next_block_number: u64,
+    next_tx_seq: u64,
+    /// The recently synced blocks in ascending order, to revert to upon a chain reorg.
+    history: VecDeque<LogSyncCheckpoint>,
+    /// `None` once the sync catches up with the confirmed chain head.
+    recover_tx: Option<UnboundedSender<LogFetchProgress>>,
+    watch_tx: UnboundedSender<LogFetchProgress>,
+    chain_head: watch::Sender<Option<u64>>,
+}
+
+impl ConfirmedSync {
+    /// Syncs the next pages of the confirmed blocks, or reverts to the last block on the chain if
+    /// the last synced block is reorged.
+    async fn sync_once(&mut self) -> Result<()> {
+        let latest_block_number = self.provider.get_block_number().await?.as_u64();
+        // Ignore the error if there is no subscriber.
+        let _ = self.chain_head.send(Some(latest_block_number));
+
+        if self.revert_reorged_blocks().await? {
+            return Ok(());
+        }
+
+        let confirmed_block_number =
+            match latest_block_number.checked_sub(self.confirmation_block_count) {
+                Some(number) if number >= self.next_block_number => number,
+                _ => {
+                    self.recover_tx = None;
+                    return Ok(());
                 }
-            },
-            "log watch",
-        );
-        watch_rx
+            };
+
+        let num_pages = match self.recover_tx {
+            Some(_) => self.catch_up_concurrency,
+            None => 1,
+        };
+        let pages: Vec<(u64, u64)> = (0..num_pages as u64)
+            .map(|i| self.next_block_number + i * LOG_PAGE_SIZE)
+            .take_while(|from| *from <= confirmed_block_number)
+            .map(|from| {
+                (
+                    from,
+                    cmp::min(confirmed_block_number, from + LOG_PAGE_SIZE - 1),
+                )
+            })
+            .collect();
+
+        // `buffered` keeps the order of pages
+        let synced_pages: Vec<SyncedPage> = futures::stream::iter(
+            pages
+                .into_iter()
+                .map(|(from, to)| self.fetch_page(from, to)),
+        )
+        .buffered(num_pages)
+        .try_collect()
+        .await?;
+
+        for page in synced_pages {
+            self.apply_page(page)?;
+        }
+
+        if self.next_block_number > confirmed_block_number && self.recover_tx.take().is_some() {
+            info!(
+                "log sync catches up to block number {}",
+                confirmed_block_number
+            );
+        }
+
+        Ok(())
     }
 
-    async fn watch_loop(
-        provider: &Provider<Http>,
-        filter_id: U256,
-        watch_tx: &UnboundedSender<LogFetchProgress>,
-    ) -> Result<Option<u64>> {
-        let latest_block = provider
-            .get_block(BlockNumber::Latest)
-            .await?
-            .ok_or_else(|| anyhow!("None for latest block"))?;
-        let logs: Vec<Log> = provider.get_filter_changes(filter_id).await?;
+    async fn fetch_page(&self, from: u64, to: u64) -> Result<SyncedPage> {
+        let block_hash = self.block_hash(to).await?;
+        let filter = Filter::new()
+            .address(self.contract_address)
+            .topic0(self.decoder.topics())
+            .from_block(from)
+            .to_block(to);
+        let logs: Vec<Log> = self.provider.get_logs(&filter).await?;
+
+        // the logs are in the ascending order of blocks, so each block is fetched once
+        let mut txs = Vec::with_capacity(logs.len());
+        let mut last_block: Option<(u64, u64)> = None;
         for log in logs {
-            // Reverted log should not be processed. Since we revert back to a previous tx_seq
-            // directly, we also do not need to notify with reverted logs.
-            if !log.removed.unwrap_or(false) {
-                // TODO(zz): Log parse error means logs might be lost here.
-                let tx = SubmissionFilter::decode_log(&RawLog {
-                    topics: log.topics,
-                    data: log.data.to_vec(),
-                })?;
-                watch_tx.send(submission_event_to_transaction(tx))?;
-            }
+            let block_number = log
+                .block_number
+                .ok_or_else(|| anyhow!("log without block number: {:?}", log))?
+                .as_u64();
+            let block_timestamp = match last_block {
+                Some((number, timestamp)) if number == block_number => timestamp,
+                _ => self.block_timestamp(block_number).await?,
+            };
+            last_block = Some((block_number, block_timestamp));
+            let mut tx = self.decoder.decode(log)?;
+            tx.block_timestamp = block_timestamp;
+            txs.push(tx);
+        }
+
+        // the logs may belong to another fork if the block is reorged during the query
+        if self.block_hash(to).await? != block_hash {
+            bail!("block {} reorged during log sync", to);
         }
-        let progress = if latest_block.hash.is_some() && latest_block.number.is_some() {
-            Some((
-                latest_block.number.unwrap().as_u64(),
-                latest_block.hash.unwrap(),
-            ))
-        } else {
-            None
+
+        Ok(SyncedPage {
+            to,
+            block_hash,
+            txs,
+        })
+    }
+
+    fn apply_page(&mut self, page: SyncedPage) -> Result<()> {
+        let sender = self.recover_tx.as_ref().unwrap_or(&self.watch_tx);
+        let mut next_tx_seq = self.next_tx_seq;
+        for tx in page.txs {
+            next_tx_seq = tx.seq + 1;
+            sender.send(LogFetchProgress::Transaction(tx))?;
+        }
+        let checkpoint = LogSyncCheckpoint {
+            block_number: page.to,
+            block_hash: page.block_hash,
+            next_tx_seq,
         };
-        if let Some(p) = &progress {
-            watch_tx.send(LogFetchProgress::SyncedBlock(*p))?;
+        sender.send(LogFetchProgress::SyncedBlock(checkpoint))?;
+
+        self.next_block_number = page.to + 1;
+        self.next_tx_seq = next_tx_seq;
+        self.history.push_back(checkpoint);
+        if self.history.len() > MAX_LOG_SYNC_CHECKPOINTS {
+            self.history.pop_front();
         }
-        Ok(progress.map(|p| p.0))
+        Ok(())
     }
 
-    pub fn provider(&self) -> &Provider<Http> {
-        self.provider.as_ref()
+    /// Returns true if the last synced block is reorged, in which case the sync is reverted to
+    /// the last block still on the chain.
+    async fn revert_reorged_blocks(&mut self) -> Result<bool> {
+        let mut history = mem::take(&mut self.history);
+        let reverted =
+            pop_reorged_checkpoints(&mut history, |block_number| self.block_hash(block_number))
+                .await;
+        self.history = history;
+        if !reverted? {
+            return Ok(false);
+        }
+
+        let checkpoint = self
+            .history
+            .back()
+            .copied()
+            .ok_or_else(|| anyhow!("empty log sync history"))?;
+        info!(
+            "revert log sync for chain reorg: block_number={} next_tx_seq={}",
+            checkpoint.block_number, checkpoint.next_tx_seq
+        );
+
+        let sender = self.recover_tx.as_ref().unwrap_or(&self.watch_tx);
+        sender.send(LogFetchProgress::Reverted(checkpoint.next_tx_seq))?;
+        sender.send(LogFetchProgress::SyncedBlock(checkpoint))?;
+        self.next_block_number = checkpoint.block_number + 1;
+        self.next_tx_seq = checkpoint.next_tx_seq;
+        Ok(true)
     }
-}
 
-#[derive(Debug)]
-pub enum LogFetchProgress {
-    SyncedBlock((u64, H256)),
-    Transaction(Transaction),
+    async fn block_hash(&self, block_number: u64) -> Result<H256> {
+        self.provider
+            .get_block(block_number)
+            .await?
+            .and_then(|block| block.hash)
+            .ok_or_else(|| anyhow!("block {} not found", block_number))
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        self.provider
+            .get_block(block_number)
+            .await?
+            .map(|block| block.timestamp.as_u64())
+            .ok_or_else(|| anyhow!("block {} not found", block_number))
+    }
 }
 
-fn submission_event_to_transaction(e: SubmissionFilter) -> LogFetchProgress {
-    LogFetchProgress::Transaction(Transaction {
-        stream_ids: vec![],
-        data: vec![],
-        data_merkle_root: nodes_to_root(&e.submission.1),
-        merkle_nodes: e
-            .submission
-            .1
-            .iter()
-            // the submission height is the height of the root node starting from height 0.
-            .map(|(root, height)| (height.as_usize() + 1, root.into()))
-            .collect(),
-        start_entry_index: e.start_pos.as_u64(),
-        size: e.submission.0.as_u64(),
-        seq: e.submission_index.as_u64(),
-    })
+/// Pops the reorged checkpoints from the history, whose blocks do not match the hashes given by
+/// `block_hash`, and returns true if any is popped. The last checkpoint is never popped, so that
+/// a reorg deeper than the history keeps failing rather than continuing on the stale fork.
+async fn pop_reorged_checkpoints<F, Fut>(
+    history: &mut VecDeque<LogSyncCheckpoint>,
+    block_hash: F,
+) -> Result<bool>
+where
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = Result<H256>>,
+{
+    let mut reverted = false;
+    while let Some(checkpoint) = history.back().copied() {
+        if block_hash(checkpoint.block_number).await? == checkpoint.block_hash {
+            break;
+        }
+        if history.len() == 1 {
+            bail!(
+                "chain reorg deeper than the log sync history: block_number={}",
+                checkpoint.block_number
+            );
+        }
+        history.pop_back();
+        reverted = true;
+    }
+    Ok(reverted)
 }
 
-fn nodes_to_root(node_list: &Vec<([u8; 32], U256)>) -> DataRoot {
-    let mut root: DataRoot = node_list.last().expect("not empty").0.into();
-    for (next_node, _) in node_list[..node_list.len() - 1].iter().rev() {
-        root = Sha3Algorithm::parent(&next_node.into(), &root);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future;
+
+    fn checkpoint(block_number: u64, fork: u64) -> LogSyncCheckpoint {
+        LogSyncCheckpoint {
+            block_number,
+            block_hash: H256::from_low_u64_be(block_number << 8 | fork),
+            next_tx_seq: block_number,
+        }
+    }
+
+    /// The block hashes of a chain that forks from the stale one after `fork_block`.
+    fn chain(fork_block: u64) -> impl Fn(u64) -> future::Ready<Result<H256>> {
+        move |block_number| {
+            let fork = if block_number > fork_block { 1 } else { 0 };
+            future::ready(Ok(checkpoint(block_number, fork).block_hash))
+        }
+    }
+
+    #[test]
+    fn test_pop_reorged_checkpoints() {
+        let mut history: VecDeque<_> = (1..=5).map(|n| checkpoint(n * 10, 0)).collect();
+
+        // nothing reorged
+        assert!(!block_on(pop_reorged_checkpoints(&mut history, chain(50))).unwrap());
+        assert_eq!(history.len(), 5);
+
+        // reverted to the last checkpoint before the fork
+        assert!(block_on(pop_reorged_checkpoints(&mut history, chain(35))).unwrap());
+        assert_eq!(history.back(), Some(&checkpoint(30, 0)));
+    }
+
+    #[test]
+    fn test_reorg_deeper_than_history() {
+        let mut history: VecDeque<_> = (1..=3).map(|n| checkpoint(n * 10, 0)).collect();
+
+        // the last checkpoint is kept, so that the sync keeps failing
+        for _ in 0..2 {
+            assert!(block_on(pop_reorged_checkpoints(&mut history, chain(5))).is_err());
+            assert_eq!(history, VecDeque::from(vec![checkpoint(10, 0)]));
+        }
+
+        // the sync continues once the chain switches back
+        assert!(!block_on(pop_reorged_checkpoints(&mut history, chain(10))).unwrap());
     }
-    root
 }
@@ -1,38 +1,48 @@
 use crate::sync_manager::config::LogSyncConfig;
-use crate::sync_manager::log_entry_fetcher::{LogEntryFetcher, LogFetchProgress};
-use anyhow::{bail, Result};
-use ethers::prelude::Middleware;
+use crate::sync_manager::log_entry_fetcher::{LogEntryFetcher, LogFetchProgress, SyncStart};
+use crate::sync_manager::root_verifier::RootVerifier;
+use anyhow::Result;
 use futures::FutureExt;
 use jsonrpsee::tracing::{debug, error, trace};
-use shared_types::Transaction;
+use network::{types::NewTx, NetworkMessage, PubsubMessage};
+use shared_types::{timestamp_now, Transaction};
 use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::future::Future;
-use std::sync::Arc;
-use std::time::Duration;
-use storage::log_store::Store;
+use storage_async::Store;
 use task_executor::{ShutdownReason, TaskExecutor};
-use tokio::sync::mpsc::UnboundedReceiver;
-use tokio::sync::RwLock;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
 
 const RETRY_WAIT_MS: u64 = 500;
 
 pub struct LogSyncManager {
-    #[allow(unused)]
     config: LogSyncConfig,
     log_fetcher: LogEntryFetcher,
-    store: Arc<RwLock<dyn Store>>,
+    store: Store,
+
+    /// Channel to publish the newly synced transactions, if the network is enabled.
+    network_send: Option<UnboundedSender<NetworkMessage>>,
 
     next_tx_seq: u64,
 }
 
 impl LogSyncManager {
+    /// Spawns the log sync task. Returns the receiver of the latest block number of the chain,
+    /// which is `None` until retrieved from the blockchain, and the receiver of whether the local
+    /// flow root matches the chain, which is `None` until verified.
+    ///
+    /// Transactions newly observed from the chain are announced to peers via `network_send`, but
+    /// not the ones recovered from the historical logs.
     pub async fn spawn(
         config: LogSyncConfig,
         executor: TaskExecutor,
-        store: Arc<RwLock<dyn Store>>,
-    ) -> Result<()> {
-        let next_tx_seq = store.read().await.next_tx_seq()?;
+        store: Store,
+        network_send: Option<UnboundedSender<NetworkMessage>>,
+    ) -> Result<(watch::Receiver<Option<u64>>, watch::Receiver<Option<bool>>)> {
+        let next_tx_seq = store.next_tx_seq().await?;
+        let (chain_head_send, chain_head_recv) = watch::channel(None);
+        let (root_status_send, root_status_recv) = watch::channel(None);
 
         let executor_clone = executor.clone();
         let mut shutdown_sender = executor.shutdown_sender();
@@ -45,81 +55,99 @@ impl LogSyncManager {
                         .expect("shutdown send error")
                 },
                 async move {
-                    let log_fetcher =
-                        LogEntryFetcher::new(&config.rpc_endpoint_url, config.contract_address)
-                            .await?;
+                    let log_fetcher = LogEntryFetcher::new(
+                        &config.rpc_endpoint_urls(),
+                        config.contract_address,
+                        config.contract_version,
+                        config.max_block_lag,
+                        config.max_concurrent_requests,
+                        &executor_clone,
+                    )
+                    .await?;
+                    if let Some(interval) = config.root_verify_interval {
+                        RootVerifier::spawn(
+                            &executor_clone,
+                            log_fetcher.provider(),
+                            config.contract_address,
+                            store.clone(),
+                            interval,
+                            root_status_send,
+                        );
+                    }
                     let mut log_sync_manager = Self {
                         config,
                         log_fetcher,
                         next_tx_seq,
                         store,
+                        network_send,
                     };
 
-                    // Load previous progress from db and check if chain reorg happens after restart.
-                    // TODO(zz): Handle reorg instead of return.
-                    let start_block_number =
-                        match log_sync_manager.store.read().await.get_sync_progress()? {
-                            // No previous progress, so just use config.
-                            None => log_sync_manager.config.start_block_number,
-                            Some((block_number, block_hash)) => {
-                                match log_sync_manager
-                                    .log_fetcher
-                                    .provider()
-                                    .get_block(block_number)
-                                    .await
-                                {
-                                    Ok(Some(b)) => {
-                                        if b.hash == Some(block_hash) {
-                                            block_number
-                                        } else {
-                                            error!(
-                                                "log sync progress check hash fails, \
-                                            block_number={:?} expect={:?} get={:?}",
-                                                block_number, block_hash, b.hash
-                                            );
-                                            bail!("log sync data mismatch");
-                                        }
-                                    }
-                                    e => {
-                                        error!("log sync progress check rpc fails, e={:?}", e);
-                                        bail!("log sync start error");
-                                    }
-                                }
-                            }
-                        };
-
-                    // Start watching before recovery to ensure that no log is skipped.
-                    // TODO(zz): Rate limit to avoid OOM during recovery.
-                    let watch_rx = log_sync_manager
-                        .log_fetcher
-                        .start_watch(start_block_number, &executor_clone);
-                    let recover_rx = log_sync_manager
-                        .log_fetcher
-                        .start_recover(start_block_number, &executor_clone);
-                    log_sync_manager.handle_data(recover_rx).await?;
+                    let start = log_sync_manager.load_sync_start().await?;
+                    let (recover_rx, watch_rx) = log_sync_manager.log_fetcher.start_sync(
+                        start,
+                        &log_sync_manager.config,
+                        chain_head_send,
+                        &executor_clone,
+                    );
+                    log_sync_manager.handle_data(recover_rx, false).await?;
                     // Syncing `watch_rx` is supposed to block forever.
-                    log_sync_manager.handle_data(watch_rx).await?;
+                    log_sync_manager.handle_data(watch_rx, true).await?;
                     Ok(())
                 },
             )
             .map(|_| ()),
             "log_sync",
         );
+        Ok((chain_head_recv, root_status_recv))
+    }
+
+    /// Loads the previous progress from db, whose blocks are checked against the chain before
+    /// the sync continues. The transactions after the last checkpoint are reverted, since they
+    /// are synced again.
+    async fn load_sync_start(&mut self) -> Result<SyncStart> {
+        let checkpoints = self.store.get_log_sync_checkpoints().await?;
+        if let Some(last) = checkpoints.last().copied() {
+            self.revert_to(last.next_tx_seq).await?;
+            return Ok(SyncStart {
+                block_number: last.block_number + 1,
+                next_tx_seq: last.next_tx_seq,
+                checkpoints,
+            });
+        }
+
+        // The progress persisted before the checkpoints are introduced, whose block may be
+        // partially synced.
+        let block_number = match self.store.get_sync_progress().await? {
+            Some((block_number, _)) => block_number,
+            // No previous progress, so just use config.
+            None => self.config.start_block_number,
+        };
+        Ok(SyncStart {
+            block_number,
+            next_tx_seq: self.next_tx_seq,
+            checkpoints: vec![],
+        })
+    }
+
+    /// Reverts the transactions from `tx_seq`, which are reorged out of the chain.
+    async fn revert_to(&mut self, tx_seq: u64) -> Result<()> {
+        if tx_seq < self.next_tx_seq {
+            debug!("revert for chain reorg: seq={}", tx_seq);
+            // TODO(zz): `wrapping_sub` here is a hack to handle the case of tx_seq=0.
+            self.store.revert_to(tx_seq.wrapping_sub(1)).await?;
+            self.next_tx_seq = tx_seq;
+        }
         Ok(())
     }
 
     async fn put_tx(&mut self, tx: Transaction) -> bool {
         match tx.seq.cmp(&self.next_tx_seq) {
             Ordering::Less => {
-                // FIXME(zz): Handle reorg after restart.
-                debug!("revert for chain reorg: seq={}", tx.seq);
-                // TODO(zz): `wrapping_sub` here is a hack to handle the case of tx_seq=0.
-                if let Err(e) = self.store.write().await.revert_to(tx.seq.wrapping_sub(1)) {
+                if let Err(e) = self.revert_to(tx.seq).await {
                     error!("revert_to fails: e={:?}", e);
                     return false;
                 }
-                self.next_tx_seq = tx.seq;
-                if let Err(e) = self.store.write().await.put_tx(tx) {
+                if let Err(e) = self.store.put_tx(tx).await {
                     error!("put_tx error: e={:?}", e);
                     false
                 } else {
@@ -129,7 +157,7 @@ impl LogSyncManager {
             }
             Ordering::Equal => {
                 debug!("log entry sync get entry: {:?}", tx);
-                if let Err(e) = self.store.write().await.put_tx(tx) {
+                if let Err(e) = self.store.put_tx(tx).await {
                     error!("put_tx error: e={:?}", e);
                     false
                 } else {
@@ -147,24 +175,67 @@ impl LogSyncManager {
         }
     }
 
-    async fn handle_data(&mut self, mut rx: UnboundedReceiver<LogFetchProgress>) -> Result<()> {
+    async fn handle_data(
+        &mut self,
+        mut rx: UnboundedReceiver<LogFetchProgress>,
+        announce: bool,
+    ) -> Result<()> {
         while let Some(data) = rx.recv().await {
             trace!("handle_data: data={:?}", data);
             match data {
-                LogFetchProgress::SyncedBlock(progress) => {
-                    self.store.read().await.put_sync_progress(progress)?;
+                LogFetchProgress::SyncedBlock(checkpoint) => {
+                    self.store.put_log_sync_checkpoint(checkpoint).await?;
+                }
+                LogFetchProgress::Reverted(tx_seq) => {
+                    self.revert_to(tx_seq).await?;
                 }
                 LogFetchProgress::Transaction(tx) => {
+                    let new_tx = NewTx {
+                        tx_seq: tx.seq,
+                        data_root: tx.data_merkle_root,
+                        size: tx.size,
+                        timestamp: timestamp_now(),
+                    };
+                    // All transactions are stored to build the flow, but the files of other
+                    // streams are not synced, so there is no need to locate them in advance.
+                    let subscribed = self.config.stream_filter.matches(&tx);
+                    let tx_seq = tx.seq;
+
                     if !self.put_tx(tx).await {
                         // Unexpected error.
                         error!("log sync write error");
                         break;
                     }
+
+                    // The file uploaded before the transaction is synced is finalized at once.
+                    match self.store.promote_tx(tx_seq).await {
+                        Ok(true) => debug!("promote pending tx: seq={}", tx_seq),
+                        Ok(false) => {}
+                        Err(e) => error!("promote_tx error: seq={} e={:?}", tx_seq, e),
+                    }
+
+                    if announce && subscribed {
+                        self.announce_new_tx(new_tx);
+                    }
                 }
             }
         }
         Ok(())
     }
+
+    /// Publishes the newly synced transaction to peers, so that peers whose log sync lags behind
+    /// could start to locate the file earlier.
+    fn announce_new_tx(&self, new_tx: NewTx) {
+        if let Some(network_send) = &self.network_send {
+            debug!("announce new tx: seq={}", new_tx.tx_seq);
+            let msg = NetworkMessage::Publish {
+                messages: vec![PubsubMessage::NewTx(new_tx)],
+            };
+            if let Err(e) = network_send.send(msg) {
+                error!("announce new tx fails: e={:?}", e);
+            }
+        }
+    }
 }
 
 async fn run_and_log<R, E>(
@@ -184,18 +255,8 @@ where
     }
 }
 
-async fn repeat_run_and_log<R, E, F>(f: impl Fn() -> F) -> R
-where
-    E: Debug,
-    F: Future<Output = std::result::Result<R, E>> + Send,
-{
-    loop {
-        if let Some(r) = run_and_log(|| {}, f()).await {
-            break r;
-        }
-        tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
-    }
-}
-
 pub(crate) mod config;
+mod failover;
 mod log_entry_fetcher;
+mod root_verifier;
+mod subscription;
@@ -0,0 +1,124 @@
+use crate::contracts::IonianFlow;
+use crate::metrics;
+use crate::rpc_proxy::ContractAddress;
+use crate::sync_manager::failover::FailoverClient;
+use anyhow::Result;
+use ethers::prelude::Provider;
+use jsonrpsee::tracing::{debug, error, info, warn};
+use shared_types::DataRoot;
+use std::sync::Arc;
+use std::time::Duration;
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::watch;
+
+/// Periodically compares the local flow root with the root committed on-chain at the synced
+/// block, so that a local divergence, e.g. due to a bug or a corrupted database, is detected
+/// rather than silently serving and mining on a wrong flow.
+///
+/// The result is published to a channel, where `Some(false)` means the roots diverge, and `None`
+/// means the roots have not been compared yet.
+pub struct RootVerifier {
+    flow_contract: IonianFlow<Provider<FailoverClient>>,
+    store: Store,
+    status_send: watch::Sender<Option<bool>>,
+}
+
+impl RootVerifier {
+    pub fn spawn(
+        executor: &TaskExecutor,
+        provider: Arc<Provider<FailoverClient>>,
+        contract_address: ContractAddress,
+        store: Store,
+        interval: Duration,
+        status_send: watch::Sender<Option<bool>>,
+    ) {
+        let verifier = RootVerifier {
+            flow_contract: IonianFlow::new(contract_address, provider),
+            store,
+            status_send,
+        };
+        executor.spawn(
+            async move { verifier.start(interval).await },
+            "flow_root_verifier",
+        );
+    }
+
+    async fn start(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+
+        loop {
+            interval.tick().await;
+
+            match self.verify().await {
+                Ok(Some(consistent)) => self.on_verified(consistent),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to verify the flow root: {:?}", e),
+            }
+        }
+    }
+
+    /// Compares the roots after the last transaction of the synced block. Returns `None` if the
+    /// local root is not available to compare, e.g. the flow is empty or changed meanwhile.
+    async fn verify(&self) -> Result<Option<bool>> {
+        let block_number = match self.store.get_sync_progress().await? {
+            Some((block_number, _)) => block_number,
+            None => return Ok(None),
+        };
+
+        let num_submissions = self
+            .flow_contract
+            .num_submissions()
+            .block(block_number)
+            .call()
+            .await?
+            .as_u64();
+        if num_submissions == 0 {
+            return Ok(None);
+        }
+        let onchain_root =
+            DataRoot::from(self.flow_contract.root().block(block_number).call().await?);
+
+        let tx_seq = num_submissions - 1;
+        let local_root = match self.store.get_flow_root_by_tx_seq(tx_seq).await? {
+            Some(root) => root,
+            // The roots of the transactions synced before the startup are not kept, so compare
+            // with the current root if no transaction is synced after the block.
+            None => {
+                if self.store.next_tx_seq().await? != num_submissions {
+                    return Ok(None);
+                }
+                let (root, _) = self.store.get_context().await?;
+                if self.store.next_tx_seq().await? != num_submissions {
+                    return Ok(None);
+                }
+                root
+            }
+        };
+
+        if local_root == onchain_root {
+            debug!(%block_number, %tx_seq, "Flow root verified");
+            Ok(Some(true))
+        } else {
+            error!(
+                %block_number,
+                %tx_seq,
+                ?local_root,
+                ?onchain_root,
+                "Local flow root diverges from the root committed on-chain"
+            );
+            Ok(Some(false))
+        }
+    }
+
+    fn on_verified(&self, consistent: bool) {
+        if consistent && *self.status_send.borrow() == Some(false) {
+            info!("Local flow root matches the root committed on-chain again");
+        }
+        metrics::set_gauge(&metrics::FLOW_ROOT_DIVERGED, !consistent as i64);
+        if !consistent {
+            metrics::inc_counter(&metrics::FLOW_ROOT_DIVERGENCES);
+        }
+        let _ = self.status_send.send(Some(consistent));
+    }
+}
@@ -0,0 +1,86 @@
+//! Subscribes to the submission logs, or the new blocks if confirmations are required, over
+//! WebSocket, which wakes up the log sync right away instead of at the next poll. Polling keeps
+//! running in case the subscription is lost.
+
+use crate::rpc_proxy::ContractAddress;
+use anyhow::Result;
+use ethers::prelude::{Filter, Middleware, Provider, Ws};
+use ethers::types::H256;
+use futures::StreamExt;
+use jsonrpsee::tracing::{info, warn};
+use std::cmp;
+use std::sync::Arc;
+use std::time::Duration;
+use task_executor::TaskExecutor;
+use tokio::sync::Notify;
+
+/// Backoff to reconnect the WebSocket, which doubles upon each consecutive failure.
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+pub fn spawn(
+    executor: &TaskExecutor,
+    url: String,
+    contract_address: ContractAddress,
+    topics: Vec<H256>,
+    confirmation_block_count: u64,
+    notify: Arc<Notify>,
+) {
+    executor.spawn(
+        async move {
+            let mut backoff = MIN_RECONNECT_BACKOFF;
+            loop {
+                match subscribe(
+                    &url,
+                    contract_address,
+                    &topics,
+                    confirmation_block_count,
+                    &notify,
+                )
+                .await
+                {
+                    Ok(()) => {
+                        warn!("log sync subscription closed, fallback to polling");
+                        backoff = MIN_RECONNECT_BACKOFF;
+                    }
+                    Err(e) => warn!("log sync subscription fails, e={:?}", e),
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+            }
+        },
+        "log_sync_subscription",
+    );
+}
+
+/// Notifies `notify` upon each new submission log or new block, until the subscription closes.
+async fn subscribe(
+    url: &str,
+    contract_address: ContractAddress,
+    topics: &[H256],
+    confirmation_block_count: u64,
+    notify: &Notify,
+) -> Result<()> {
+    let provider = Provider::<Ws>::connect(url).await?;
+
+    // the logs are synced only after the confirmations, which is triggered by new blocks
+    if confirmation_block_count == 0 {
+        let filter = Filter::new()
+            .address(contract_address)
+            .topic0(topics.to_vec());
+        let mut stream = provider.subscribe_logs(&filter).await?;
+        info!("log sync subscribes to submission logs");
+        while stream.next().await.is_some() {
+            notify.notify_one();
+        }
+    } else {
+        let mut stream = provider.subscribe_blocks().await?;
+        info!("log sync subscribes to new blocks");
+        while stream.next().await.is_some() {
+            notify.notify_one();
+        }
+    }
+
+    Ok(())
+}
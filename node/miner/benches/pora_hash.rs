@@ -0,0 +1,38 @@
+//! Measures the throughput of the PoRA hashers on this machine.
+//!
+//! cargo bench -p miner --bench pora_hash
+
+use ethers::types::{H256, U256};
+use miner::hasher::{available_hashers, SECTOR_SIZE};
+use std::time::{Duration, Instant};
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+
+const BENCH_DURATION: Duration = Duration::from_secs(3);
+
+fn main() {
+    let seed = H256::from(rand::random::<[u8; 32]>());
+    let chunk_data: Vec<u8> = (0..PORA_CHUNK_SIZE * SECTOR_SIZE)
+        .map(|_| rand::random())
+        .collect();
+
+    for hasher in available_hashers() {
+        let start = Instant::now();
+        let mut probes = 0u64;
+        while start.elapsed() < BENCH_DURATION {
+            // no answer with the zero target, so that all the sectors are hashed
+            assert_eq!(
+                hasher.find_answer(&seed, 0, &chunk_data, &U256::zero()),
+                None
+            );
+            probes += 1;
+        }
+
+        let secs = start.elapsed().as_secs_f64();
+        println!(
+            "{:>8}: {:>10.1} probes/s {:>10.1} MB/s",
+            hasher.name(),
+            probes as f64 / secs,
+            (probes * chunk_data.len() as u64) as f64 / secs / 1e6
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::config::{make_signer, MineServiceMiddleware};
+use crate::{metrics, MinerKey};
+use ethers::abi::{self, Token};
+use ethers::prelude::{Bytes, Http, Middleware, Provider, TransactionRequest};
+use ethers::types::{Address, H256, U256};
+use ethers::utils::id;
+use storage_async::{Store, StoreEvent};
+use task_executor::TaskExecutor;
+use tokio::sync::broadcast;
+
+/// Parameters of the attestation method, which takes the transaction sequence number and the
+/// data root of the finalized file.
+pub const ATTEST_METHOD_PARAMS: &str = "(uint256,bytes32)";
+
+/// Reports the files finalized locally to a contract, so that the replication of a file could be
+/// verified on chain.
+#[derive(Clone, Debug)]
+pub struct AttesterConfig {
+    /// Key to sign the attestation transactions, which is the miner key if configured.
+    pub key: MinerKey,
+    pub rpc_endpoint_url: String,
+    pub contract_address: Address,
+    /// Signature of the attestation method, e.g. `attest(uint256,bytes32)`.
+    pub method: String,
+}
+
+/// Sends an attestation transaction for each file finalized in the store, one after another so
+/// that the nonces are assigned in order.
+pub struct Attester {
+    signer: MineServiceMiddleware,
+    contract_address: Address,
+    selector: [u8; 4],
+    store: Store,
+    store_events: broadcast::Receiver<StoreEvent>,
+}
+
+impl Attester {
+    pub async fn spawn(
+        executor: TaskExecutor,
+        config: AttesterConfig,
+        store: Store,
+    ) -> Result<(), String> {
+        if !config.method.ends_with(ATTEST_METHOD_PARAMS) {
+            return Err(format!(
+                "Attestation method {} should take parameters {}",
+                config.method, ATTEST_METHOD_PARAMS
+            ));
+        }
+
+        let provider = Provider::<Http>::try_from(&config.rpc_endpoint_url)
+            .map_err(|e| format!("Cannot parse blockchain endpoint: {:?}", e))?;
+        let signer = make_signer(provider, &config.key).await?;
+        info!(contract = ?config.contract_address, method = %config.method, "Attester configured");
+
+        let attester = Attester {
+            signer,
+            contract_address: config.contract_address,
+            selector: id(&config.method),
+            store_events: store.subscribe_events(),
+            store,
+        };
+
+        executor.spawn(async move { Box::pin(attester.start()).await }, "attester");
+
+        Ok(())
+    }
+
+    async fn start(mut self) {
+        loop {
+            match self.store_events.recv().await {
+                Ok(StoreEvent::Finalized { tx_seq }) => {
+                    if let Err(e) = self.attest(tx_seq).await {
+                        warn!(%tx_seq, %e, "Failed to attest finalized file");
+                        metrics::inc_counter(&metrics::ATTESTATIONS_FAILED);
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(%n, "Attester lagged behind store events, some files are not attested");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    async fn attest(&self, tx_seq: u64) -> Result<(), String> {
+        let tx = self
+            .store
+            .get_tx_by_seq_number(tx_seq)
+            .await
+            .map_err(|e| format!("Failed to get transaction: {:?}", e))?
+            .ok_or_else(|| "Transaction not found".to_string())?;
+
+        let request = TransactionRequest::new()
+            .to(self.contract_address)
+            .data(encode_call(self.selector, tx_seq, tx.data_merkle_root));
+        let pending_transaction = self
+            .signer
+            .send_transaction(request, None)
+            .await
+            .map_err(|e| format!("Failed to send attestation transaction: {:?}", e))?;
+        let tx_hash = *pending_transaction;
+        let receipt = pending_transaction
+            .await
+            .map_err(|e| format!("Failed to wait for attestation transaction: {:?}", e))?
+            .ok_or_else(|| format!("Attestation transaction dropped, tx_hash = {:?}", tx_hash))?;
+        if receipt.status != Some(1.into()) {
+            return Err(format!(
+                "Attestation transaction reverted, tx_hash = {:?}",
+                tx_hash
+            ));
+        }
+
+        debug!(%tx_seq, ?tx_hash, "Finalized file attested");
+        metrics::inc_counter(&metrics::ATTESTATIONS_SENT);
+        Ok(())
+    }
+}
+
+fn encode_call(selector: [u8; 4], tx_seq: u64, data_root: H256) -> Bytes {
+    let params = abi::encode(&[
+        Token::Uint(U256::from(tx_seq)),
+        Token::FixedBytes(data_root.as_bytes().to_vec()),
+    ]);
+    [&selector[..], &params[..]].concat().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_call() {
+        let selector = id("attest(uint256,bytes32)");
+        let data = encode_call(selector, 5, H256::repeat_byte(7));
+
+        assert_eq!(data.len(), 4 + 32 * 2);
+        assert_eq!(&data[..4], &selector[..]);
+        assert_eq!(U256::from_big_endian(&data[4..36]), U256::from(5));
+        assert_eq!(&data[36..], H256::repeat_byte(7).as_bytes());
+    }
+}
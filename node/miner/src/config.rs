@@ -0,0 +1,114 @@
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::prelude::{Http, Middleware, Provider, SignerMiddleware};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, H256};
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::PathBuf;
+
+pub(crate) type MineServiceMiddleware = SignerMiddleware<Provider<Http>, LocalWallet>;
+
+/// Source of the private key to sign the mine transactions.
+#[derive(Clone)]
+pub enum MinerKey {
+    /// The private key itself.
+    Raw(H256),
+    /// A file of the hex encoded private key.
+    File(PathBuf),
+    /// An encrypted JSON keystore.
+    Keystore { path: PathBuf, password: String },
+}
+
+impl Debug for MinerKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // never log the secrets
+        match self {
+            MinerKey::Raw(_) => write!(f, "Raw"),
+            MinerKey::File(path) => write!(f, "File({:?})", path),
+            MinerKey::Keystore { path, .. } => write!(f, "Keystore({:?})", path),
+        }
+    }
+}
+
+impl MinerKey {
+    pub fn load(&self) -> Result<LocalWallet, String> {
+        let key = match self {
+            MinerKey::Raw(key) => *key,
+            MinerKey::File(path) => fs::read_to_string(path)
+                .map_err(|e| format!("Cannot read key file {:?}: {:?}", path, e))?
+                .trim()
+                .parse::<H256>()
+                .map_err(|e| format!("Cannot parse private key in {:?}: {:?}", path, e))?,
+            MinerKey::Keystore { path, password } => {
+                return LocalWallet::decrypt_keystore(path, password)
+                    .map_err(|e| format!("Cannot decrypt keystore {:?}: {:?}", path, e));
+            }
+        };
+
+        let secret_key = SigningKey::from_bytes(key.as_bytes())
+            .map_err(|e| format!("Cannot parse private key: {:?}", e))?;
+        Ok(LocalWallet::from(secret_key))
+    }
+}
+
+/// Mining parameters of the node, which is configured only if both the miner id and the miner
+/// key are provided.
+#[derive(Clone, Debug)]
+pub struct MinerConfig {
+    /// Whether to start mining on startup, otherwise mining could be started by the admin RPC.
+    pub enabled: bool,
+    pub miner_id: H256,
+    pub miner_key: MinerKey,
+    /// Address to receive the mining rewards, which is the address of the miner key by default.
+    pub beneficiary: Option<Address>,
+    /// Maximum number of mining probes per second, unlimited if `None`.
+    pub probes_per_sec: Option<u64>,
+    pub rpc_endpoint_url: String,
+    pub mine_address: Address,
+    pub flow_address: Address,
+}
+
+impl MinerConfig {
+    pub(crate) fn make_provider(&self) -> Result<Provider<Http>, String> {
+        Provider::<Http>::try_from(&self.rpc_endpoint_url)
+            .map_err(|e| format!("Cannot parse blockchain endpoint: {:?}", e))
+    }
+}
+
+/// Connects to the blockchain with `key` to sign the submission transactions.
+pub(crate) async fn make_signer(
+    provider: Provider<Http>,
+    key: &MinerKey,
+) -> Result<MineServiceMiddleware, String> {
+    let chain_id = provider
+        .get_chainid()
+        .await
+        .map_err(|e| format!("Unable to get chain id: {:?}", e))?;
+    let wallet = key.load()?.with_chain_id(chain_id.as_u64());
+    Ok(SignerMiddleware::new(provider, wallet))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_key_file() {
+        let key = H256::repeat_byte(1);
+        let wallet = MinerKey::Raw(key).load().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("miner_key");
+        fs::write(&path, format!("{:?}\n", key)).unwrap();
+        let key_file = MinerKey::File(path);
+        assert_eq!(key_file.load().unwrap().address(), wallet.address());
+
+        // the secrets are not logged
+        let keystore = MinerKey::Keystore {
+            path: "keystore".into(),
+            password: "secret".into(),
+        };
+        assert!(!format!("{:?}", keystore).contains("secret"));
+        assert!(!format!("{:?}", MinerKey::Raw(key)).contains("0101"));
+    }
+}
@@ -0,0 +1,4 @@
+use ethers::prelude::abigen;
+abigen!(PoraMine, "./src/contracts/PoraMine.json");
+
+pub use log_entry_sync::contracts::{IonianFlow, MineContext, NewEpochFilter};
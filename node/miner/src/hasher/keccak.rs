@@ -0,0 +1,171 @@
+//! Keccak-256 of multiple sectors in lockstep. Each lane of the state arrays belongs to a
+//! sector, so that the compiler vectorizes the permutation across lanes when SIMD instructions
+//! are enabled.
+
+#![allow(clippy::needless_range_loop)]
+
+use crate::pora::SECTOR_SIZE;
+use ethers::types::H256;
+
+/// Number of 64-bit words absorbed per permutation, i.e., (1600 - 256 * 2) / 64.
+const RATE_WORDS: usize = 17;
+
+/// The hashed message of a sector is `seed || position || sector`.
+const MESSAGE_WORDS: usize = (32 + 32 + SECTOR_SIZE) / 8;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+const RHO: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+const PI: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+#[inline(always)]
+fn keccak_f<const N: usize>(state: &mut [[u64; N]; 25]) {
+    for round_constant in ROUND_CONSTANTS {
+        // theta
+        let mut parity = [[0u64; N]; 5];
+        for (x, parity) in parity.iter_mut().enumerate() {
+            for l in 0..N {
+                parity[l] = state[x][l]
+                    ^ state[x + 5][l]
+                    ^ state[x + 10][l]
+                    ^ state[x + 15][l]
+                    ^ state[x + 20][l];
+            }
+        }
+        for x in 0..5 {
+            for l in 0..N {
+                let d = parity[(x + 4) % 5][l] ^ parity[(x + 1) % 5][l].rotate_left(1);
+                for y in 0..5 {
+                    state[y * 5 + x][l] ^= d;
+                }
+            }
+        }
+
+        // rho and pi
+        let mut last = state[1];
+        for i in 0..24 {
+            let current = state[PI[i]];
+            for l in 0..N {
+                state[PI[i]][l] = last[l].rotate_left(RHO[i]);
+            }
+            last = current;
+        }
+
+        // chi
+        for y in 0..5 {
+            let mut row = [[0u64; N]; 5];
+            row.copy_from_slice(&state[y * 5..y * 5 + 5]);
+            for x in 0..5 {
+                for l in 0..N {
+                    state[y * 5 + x][l] = row[x][l] ^ (!row[(x + 1) % 5][l] & row[(x + 2) % 5][l]);
+                }
+            }
+        }
+
+        // iota
+        for l in 0..N {
+            state[0][l] ^= round_constant;
+        }
+    }
+}
+
+fn read_word(bytes: &[u8], index: usize) -> u64 {
+    u64::from_le_bytes(bytes[index * 8..index * 8 + 8].try_into().unwrap())
+}
+
+/// Returns the Keccak-256 hashes of `N` consecutive sectors starting at the flow `position`,
+/// which equal `pora::sector_quality` in big endian.
+#[inline(always)]
+pub fn hash_sectors<const N: usize>(seed: &H256, position: u64, sectors: &[u8]) -> [[u8; 32]; N] {
+    assert_eq!(sectors.len(), N * SECTOR_SIZE);
+
+    let mut message = [[0u64; N]; MESSAGE_WORDS];
+    for l in 0..N {
+        for (i, word) in message.iter_mut().take(4).enumerate() {
+            word[l] = read_word(seed.as_bytes(), i);
+        }
+        // the position is a big endian uint256
+        message[7][l] = u64::from_le_bytes((position + l as u64).to_be_bytes());
+        let sector = &sectors[l * SECTOR_SIZE..(l + 1) * SECTOR_SIZE];
+        for (i, word) in message.iter_mut().skip(8).enumerate() {
+            word[l] = read_word(sector, i);
+        }
+    }
+
+    // the message length is not a multiple of the rate, so the padding fits in the last block
+    let mut state = [[0u64; N]; 25];
+    for block in message.chunks(RATE_WORDS) {
+        for (i, word) in block.iter().enumerate() {
+            for l in 0..N {
+                state[i][l] ^= word[l];
+            }
+        }
+        if block.len() < RATE_WORDS {
+            for l in 0..N {
+                state[block.len()][l] ^= 0x01;
+                state[RATE_WORDS - 1][l] ^= 0x80 << 56;
+            }
+        }
+        keccak_f(&mut state);
+    }
+
+    let mut hashes = [[0u8; 32]; N];
+    for (l, hash) in hashes.iter_mut().enumerate() {
+        for i in 0..4 {
+            hash[i * 8..i * 8 + 8].copy_from_slice(&state[i][l].to_le_bytes());
+        }
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pora::sector_quality;
+    use ethers::types::U256;
+
+    #[test]
+    fn test_hash_sectors() {
+        let seed = H256::from(rand::random::<[u8; 32]>());
+        let sectors: Vec<u8> = (0..4 * SECTOR_SIZE).map(|_| rand::random()).collect();
+
+        let hashes = hash_sectors::<4>(&seed, 1000, &sectors);
+        for (l, hash) in hashes.iter().enumerate() {
+            let sector = &sectors[l * SECTOR_SIZE..(l + 1) * SECTOR_SIZE];
+            assert_eq!(
+                U256::from_big_endian(hash),
+                sector_quality(&seed, 1000 + l as u64, sector)
+            );
+        }
+    }
+}
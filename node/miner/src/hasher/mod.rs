@@ -0,0 +1,211 @@
+//! Implementations of the PoRA hash loop, which scans the sectors of a recalled chunk for an
+//! answer. The SIMD implementations hash several sectors at once, since the scalar Keccak caps
+//! the mining throughput well below the disk bandwidth.
+
+mod keccak;
+
+use crate::pora;
+use ethers::types::{H256, U256};
+
+pub use crate::pora::SECTOR_SIZE;
+
+pub trait PoraHasher: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the offset of the first sector in the recalled chunk that meets the target.
+    fn find_answer(
+        &self,
+        seed: &H256,
+        chunk_start: u64,
+        chunk_data: &[u8],
+        target: &U256,
+    ) -> Option<usize>;
+}
+
+/// Hashes one sector at a time.
+pub struct ScalarHasher;
+
+impl PoraHasher for ScalarHasher {
+    fn name(&self) -> &'static str {
+        "scalar"
+    }
+
+    fn find_answer(
+        &self,
+        seed: &H256,
+        chunk_start: u64,
+        chunk_data: &[u8],
+        target: &U256,
+    ) -> Option<usize> {
+        pora::find_answer(seed, chunk_start, chunk_data, target)
+    }
+}
+
+/// Hashes `N` sectors at a time, and the remaining sectors of the chunk one by one.
+#[inline(always)]
+fn find_answer_lanes<const N: usize>(
+    seed: &H256,
+    chunk_start: u64,
+    chunk_data: &[u8],
+    target: &U256,
+) -> Option<usize> {
+    let mut groups = chunk_data.chunks_exact(N * SECTOR_SIZE);
+    for (i, group) in groups.by_ref().enumerate() {
+        let hashes = keccak::hash_sectors::<N>(seed, chunk_start + (i * N) as u64, group);
+        if let Some(lane) = hashes
+            .iter()
+            .position(|hash| U256::from_big_endian(hash) <= *target)
+        {
+            return Some(i * N + lane);
+        }
+    }
+
+    let tail_offset = chunk_data.len() / (N * SECTOR_SIZE) * N;
+    pora::find_answer(
+        seed,
+        chunk_start + tail_offset as u64,
+        groups.remainder(),
+        target,
+    )
+    .map(|offset| tail_offset + offset)
+}
+
+/// Hashes 4 sectors at a time with AVX2, which is detected at runtime.
+#[cfg(target_arch = "x86_64")]
+pub struct Avx2Hasher {
+    _private: (),
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Avx2Hasher {
+    /// Returns `None` if the CPU does not support AVX2.
+    pub fn new() -> Option<Self> {
+        is_x86_feature_detected!("avx2").then(|| Avx2Hasher { _private: () })
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn find_answer_avx2(
+    seed: &H256,
+    chunk_start: u64,
+    chunk_data: &[u8],
+    target: &U256,
+) -> Option<usize> {
+    find_answer_lanes::<4>(seed, chunk_start, chunk_data, target)
+}
+
+#[cfg(target_arch = "x86_64")]
+impl PoraHasher for Avx2Hasher {
+    fn name(&self) -> &'static str {
+        "avx2"
+    }
+
+    fn find_answer(
+        &self,
+        seed: &H256,
+        chunk_start: u64,
+        chunk_data: &[u8],
+        target: &U256,
+    ) -> Option<usize> {
+        // safe since AVX2 support is checked on construction
+        unsafe { find_answer_avx2(seed, chunk_start, chunk_data, target) }
+    }
+}
+
+/// Hashes 8 sectors at a time with AVX-512. Enabling AVX-512 at runtime is not supported by the
+/// stable toolchain, so this is only available if the node is built with
+/// `RUSTFLAGS="-C target-feature=+avx512f"`.
+#[cfg(all(target_arch = "x86_64", target_feature = "avx512f"))]
+pub struct Avx512Hasher;
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx512f"))]
+impl PoraHasher for Avx512Hasher {
+    fn name(&self) -> &'static str {
+        "avx512"
+    }
+
+    fn find_answer(
+        &self,
+        seed: &H256,
+        chunk_start: u64,
+        chunk_data: &[u8],
+        target: &U256,
+    ) -> Option<usize> {
+        find_answer_lanes::<8>(seed, chunk_start, chunk_data, target)
+    }
+}
+
+/// Returns all the hashers supported by this machine, the fastest first.
+pub fn available_hashers() -> Vec<Box<dyn PoraHasher>> {
+    let mut hashers: Vec<Box<dyn PoraHasher>> = vec![];
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx512f"))]
+    hashers.push(Box::new(Avx512Hasher));
+
+    #[cfg(target_arch = "x86_64")]
+    if let Some(hasher) = Avx2Hasher::new() {
+        hashers.push(Box::new(hasher));
+    }
+
+    hashers.push(Box::new(ScalarHasher));
+    hashers
+}
+
+/// Returns the fastest hasher supported by this machine.
+pub fn default_hasher() -> Box<dyn PoraHasher> {
+    available_hashers().remove(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashers_consistent() {
+        let seed = H256::from(rand::random::<[u8; 32]>());
+        // not a multiple of the lanes
+        let chunk_data: Vec<u8> = (0..37 * SECTOR_SIZE).map(|_| rand::random()).collect();
+        let qualities: Vec<U256> = chunk_data
+            .chunks_exact(SECTOR_SIZE)
+            .enumerate()
+            .map(|(offset, sector)| pora::sector_quality(&seed, 100 + offset as u64, sector))
+            .collect();
+
+        let mut hashers = available_hashers();
+        hashers.push(Box::new(LanesHasher));
+        for hasher in hashers {
+            for quality in qualities.iter() {
+                assert_eq!(
+                    hasher.find_answer(&seed, 100, &chunk_data, quality),
+                    ScalarHasher.find_answer(&seed, 100, &chunk_data, quality),
+                    "hasher {}",
+                    hasher.name()
+                );
+            }
+            assert_eq!(
+                hasher.find_answer(&seed, 100, &chunk_data, &U256::zero()),
+                None
+            );
+        }
+    }
+
+    /// The lanes implementation without SIMD, which runs on any machine.
+    struct LanesHasher;
+
+    impl PoraHasher for LanesHasher {
+        fn name(&self) -> &'static str {
+            "lanes"
+        }
+
+        fn find_answer(
+            &self,
+            seed: &H256,
+            chunk_start: u64,
+            chunk_data: &[u8],
+            target: &U256,
+        ) -> Option<usize> {
+            find_answer_lanes::<4>(seed, chunk_start, chunk_data, target)
+        }
+    }
+}
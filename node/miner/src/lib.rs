@@ -1,8 +1,23 @@
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate tracing;
 
+mod attester;
+mod config;
 mod context;
+mod contracts;
+pub mod hasher;
+mod metrics;
+mod mine;
+mod pora;
 mod service;
+mod stats;
+mod submitter;
+mod watcher;
 
+pub use attester::{Attester, AttesterConfig};
+pub use config::{MinerConfig, MinerKey};
 pub(crate) use context::MinerNetworkContext;
 pub use service::{MinerMessage, MinerService};
+pub use stats::MinerStats;
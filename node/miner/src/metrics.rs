@@ -0,0 +1,42 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref MINER_PROBES: Result<IntCounter> = try_create_int_counter(
+        "miner_probes_total",
+        "Count of PoRA mining probes, each of which scans a recalled chunk"
+    );
+    pub static ref MINER_ANSWERS_FOUND: Result<IntCounter> = try_create_int_counter(
+        "miner_answers_found_total",
+        "Count of valid PoRA answers found"
+    );
+    pub static ref MINER_SUBMISSIONS_SENT: Result<IntCounter> = try_create_int_counter(
+        "miner_submissions_sent_total",
+        "Count of mine transactions sent"
+    );
+    pub static ref MINER_SUBMISSIONS_RESENT: Result<IntCounter> = try_create_int_counter(
+        "miner_submissions_resent_total",
+        "Count of mine transactions replaced with a higher gas price"
+    );
+    pub static ref MINER_SUBMISSIONS_ACCEPTED: Result<IntCounter> = try_create_int_counter(
+        "miner_submissions_accepted_total",
+        "Count of mine transactions executed successfully"
+    );
+    pub static ref MINER_SUBMISSIONS_FAILED: Result<IntCounter> = try_create_int_counter(
+        "miner_submissions_failed_total",
+        "Count of answers failed to send or reverted on chain"
+    );
+    pub static ref MINER_EPOCH: Result<IntGauge> =
+        try_create_int_gauge("miner_epoch", "Epoch of the current mining context");
+    pub static ref MINER_TARGET_BITS: Result<IntGauge> = try_create_int_gauge(
+        "miner_target_bits",
+        "Number of significant bits of the PoRA target loaded from chain, lower is harder"
+    );
+    pub static ref ATTESTATIONS_SENT: Result<IntCounter> = try_create_int_counter(
+        "attestations_sent_total",
+        "Count of finalized files attested on chain"
+    );
+    pub static ref ATTESTATIONS_FAILED: Result<IntCounter> = try_create_int_counter(
+        "attestations_failed_total",
+        "Count of finalized files failed to attest on chain"
+    );
+}
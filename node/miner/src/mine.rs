@@ -0,0 +1,217 @@
+use crate::contracts::PoraAnswer;
+use crate::hasher::{self, PoraHasher};
+use crate::pora::{self, SECTOR_SIZE};
+use crate::stats::MineCounters;
+use crate::watcher::PoraPuzzle;
+use crate::MinerConfig;
+use anyhow::{anyhow, bail, Result};
+use ethers::types::{Address, H256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::{mpsc, watch};
+
+/// Time to wait before retrying if the local flow is not ready to mine.
+const MINE_RETRY_WAIT: Duration = Duration::from_secs(5);
+
+/// Caps the number of mining probes in each second.
+struct ProbeLimiter {
+    probes_per_sec: u64,
+    window_start: Instant,
+    probes: u64,
+}
+
+impl ProbeLimiter {
+    fn new(probes_per_sec: u64) -> Self {
+        ProbeLimiter {
+            probes_per_sec,
+            window_start: Instant::now(),
+            probes: 0,
+        }
+    }
+
+    /// Waits until a probe is allowed.
+    async fn acquire(&mut self) {
+        if self.probes >= self.probes_per_sec {
+            let window_end = self.window_start + Duration::from_secs(1);
+            tokio::time::sleep_until(window_end.into()).await;
+        }
+
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.probes = 0;
+        }
+
+        self.probes += 1;
+    }
+}
+
+/// Samples random positions in the flow, and sends the valid answers to the submitter.
+pub struct PoraService {
+    miner_id: H256,
+    store: Store,
+    hasher: Box<dyn PoraHasher>,
+    probe_limiter: Option<ProbeLimiter>,
+    counters: Arc<MineCounters>,
+    enabled_recv: watch::Receiver<bool>,
+    puzzle_recv: watch::Receiver<Option<PoraPuzzle>>,
+    answer_send: mpsc::UnboundedSender<PoraAnswer>,
+}
+
+impl PoraService {
+    pub fn spawn(
+        executor: TaskExecutor,
+        config: &MinerConfig,
+        store: Store,
+        counters: Arc<MineCounters>,
+        enabled_recv: watch::Receiver<bool>,
+        puzzle_recv: watch::Receiver<Option<PoraPuzzle>>,
+        answer_send: mpsc::UnboundedSender<PoraAnswer>,
+    ) {
+        let hasher = hasher::default_hasher();
+        info!(hasher = hasher.name(), "Use PoRA hasher");
+
+        let pora = PoraService {
+            miner_id: config.miner_id,
+            store,
+            hasher,
+            probe_limiter: config.probes_per_sec.map(ProbeLimiter::new),
+            counters,
+            enabled_recv,
+            puzzle_recv,
+            answer_send,
+        };
+
+        executor.spawn(async move { Box::pin(pora.start()).await }, "pora_mine");
+    }
+
+    async fn start(mut self) {
+        loop {
+            if !*self.enabled_recv.borrow() {
+                if self.enabled_recv.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            let puzzle = self.puzzle_recv.borrow().clone();
+            let puzzle = match puzzle {
+                Some(puzzle) => puzzle,
+                None => {
+                    if self.puzzle_recv.changed().await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(limiter) = self.probe_limiter.as_mut() {
+                limiter.acquire().await;
+            }
+
+            match self.mine_once(&puzzle).await {
+                Ok(Some(_)) if self.puzzle_recv.borrow().as_ref() != Some(&puzzle) => {
+                    debug!("Drop the PoRA answer of a stale mining context");
+                }
+                Ok(Some(answer)) => {
+                    info!(recall_position = %answer.recall_position, "PoRA answer found");
+                    self.counters.on_answer_found();
+                    if self.answer_send.send(answer).is_err() {
+                        return;
+                    }
+
+                    // one answer for each mining context
+                    if self.puzzle_recv.changed().await.is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => tokio::task::yield_now().await,
+                Err(e) => {
+                    debug!(%e, "Not ready to mine");
+                    tokio::time::sleep(MINE_RETRY_WAIT).await;
+                }
+            }
+        }
+    }
+
+    /// Makes a mining attempt with a random nonce. Returns `None` if the recalled chunk is not
+    /// stored locally or no sector meets the target.
+    async fn mine_once(&self, puzzle: &PoraPuzzle) -> Result<Option<PoraAnswer>> {
+        let flow_root = H256::from(puzzle.context.flow_root);
+        let (local_flow_root, _) = self.store.get_context().await?;
+        if local_flow_root != flow_root {
+            bail!(
+                "local flow root {:?} mismatches the mining context {:?}",
+                local_flow_root,
+                flow_root
+            );
+        }
+
+        let num_chunks = puzzle.context.flow_length.as_u64() / PORA_CHUNK_SIZE as u64;
+        if num_chunks == 0 {
+            bail!("no complete PoRA chunk in the flow");
+        }
+
+        self.counters.on_probe();
+
+        let nonce = H256::from(rand::random::<[u8; 32]>());
+        let seed = pora::seed(&self.miner_id, &nonce, &H256::from(puzzle.context.digest));
+        let chunk_index = pora::recall_chunk(&seed, num_chunks);
+        let chunk_start = chunk_index * PORA_CHUNK_SIZE as u64;
+
+        // fallback to the entry batches if the chunk is not sealed
+        let chunk = match self.store.get_sealed_chunk(chunk_index).await? {
+            Some(chunk) => chunk,
+            None => match self
+                .store
+                .get_chunks_by_flow_index_range(chunk_start, chunk_start + PORA_CHUNK_SIZE as u64)
+                .await?
+            {
+                Some(chunk) => chunk,
+                None => return Ok(None),
+            },
+        };
+
+        // the first entry of the flow is not stored
+        if chunk.data.len() != PORA_CHUNK_SIZE * SECTOR_SIZE {
+            return Ok(None);
+        }
+
+        let offset = match self
+            .hasher
+            .find_answer(&seed, chunk_start, &chunk.data, &puzzle.target)
+        {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        let position = chunk_start + offset as u64;
+        let proof = self
+            .store
+            .get_chunks_with_proof_by_flow_index_range(position, position + 1)
+            .await?
+            .ok_or_else(|| anyhow!("recalled sector {} missing", position))?
+            .proof
+            .left_proof;
+
+        // the flow may grow during mining
+        if proof.root() != flow_root {
+            bail!("flow root changed during mining");
+        }
+
+        Ok(Some(PoraAnswer {
+            context_digest: puzzle.context.digest,
+            nonce: nonce.0,
+            miner_id: self.miner_id.0,
+            // filled by the submitter
+            beneficiary: Address::zero(),
+            recall_position: position.into(),
+            recall_data: chunk.data[offset * SECTOR_SIZE..(offset + 1) * SECTOR_SIZE]
+                .to_vec()
+                .into(),
+            merkle_proof: proof.lemma().iter().map(|hash| hash.0).collect(),
+        }))
+    }
+}
@@ -0,0 +1,86 @@
+//! The puzzle of PoRA (proof of random access). A mining attempt picks a random nonce, which
+//! decides the recalled PoRA chunk in the flow. The miner then scans the sectors (entries) of the
+//! chunk, and any sector whose quality does not exceed the target is a valid answer.
+
+use ethers::types::{H256, U256};
+use ethers::utils::keccak256;
+use storage::log_store::log_manager::ENTRY_SIZE;
+
+/// A sector is the unit of the mining answer, i.e., a flow entry.
+pub const SECTOR_SIZE: usize = ENTRY_SIZE;
+
+/// The seed of a mining attempt.
+pub fn seed(miner_id: &H256, nonce: &H256, context_digest: &H256) -> H256 {
+    keccak256(
+        [
+            miner_id.as_bytes(),
+            nonce.as_bytes(),
+            context_digest.as_bytes(),
+        ]
+        .concat(),
+    )
+    .into()
+}
+
+/// The index of the recalled PoRA chunk among `num_chunks` chunks in the flow.
+pub fn recall_chunk(seed: &H256, num_chunks: u64) -> u64 {
+    (U256::from_big_endian(seed.as_bytes()) % U256::from(num_chunks)).as_u64()
+}
+
+/// The quality of the sector at the flow `position`, where lower is better.
+pub fn sector_quality(seed: &H256, position: u64, sector: &[u8]) -> U256 {
+    let mut position_bytes = [0u8; 32];
+    U256::from(position).to_big_endian(&mut position_bytes);
+    U256::from_big_endian(&keccak256(
+        [seed.as_bytes(), &position_bytes, sector].concat(),
+    ))
+}
+
+/// Returns the offset of the first sector in the recalled chunk that meets the target.
+pub fn find_answer(
+    seed: &H256,
+    chunk_start: u64,
+    chunk_data: &[u8],
+    target: &U256,
+) -> Option<usize> {
+    chunk_data
+        .chunks_exact(SECTOR_SIZE)
+        .enumerate()
+        .position(|(offset, sector)| {
+            sector_quality(seed, chunk_start + offset as u64, sector) <= *target
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recall_chunk() {
+        let nonce = H256::repeat_byte(1);
+        let seed = seed(&H256::zero(), &nonce, &H256::repeat_byte(2));
+        assert_ne!(
+            seed,
+            super::seed(&H256::zero(), &nonce, &H256::repeat_byte(3))
+        );
+
+        for num_chunks in 1..100 {
+            assert!(recall_chunk(&seed, num_chunks) < num_chunks);
+        }
+    }
+
+    #[test]
+    fn test_find_answer() {
+        let seed = H256::repeat_byte(1);
+        let chunk_data = vec![7u8; SECTOR_SIZE * 4];
+
+        assert_eq!(find_answer(&seed, 0, &chunk_data, &U256::MAX), Some(0));
+        assert_eq!(find_answer(&seed, 0, &chunk_data, &U256::zero()), None);
+
+        // the quality depends on the sector position
+        let target = sector_quality(&seed, 102, &chunk_data[..SECTOR_SIZE]);
+        let offset = find_answer(&seed, 100, &chunk_data, &target).unwrap();
+        assert!(offset <= 2);
+        assert!(sector_quality(&seed, 100 + offset as u64, &chunk_data[..SECTOR_SIZE]) <= target);
+    }
+}
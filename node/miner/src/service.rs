@@ -1,12 +1,42 @@
-use crate::MinerNetworkContext;
+use crate::config::{make_signer, MineServiceMiddleware};
+use crate::mine::PoraService;
+use crate::stats::{MineCounters, ProbeRate};
+use crate::submitter::Submitter;
+use crate::watcher::{MineContextWatcher, PoraPuzzle};
+use crate::{MinerConfig, MinerKey, MinerNetworkContext, MinerStats};
+use ethers::prelude::{Http, Provider};
+use ethers::signers::Signer;
 use network::NetworkMessage;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use storage_async::Store;
+use tokio::sync::{mpsc, oneshot, watch};
 
 const HEARTBEAT_INTERVAL_SEC: u64 = 10;
 
 #[derive(Debug)]
 pub enum MinerMessage {
-    Test,
+    /// Starts or stops mining.
+    SetMiningEnabled {
+        enabled: bool,
+        result: oneshot::Sender<Result<(), String>>,
+    },
+
+    /// Replaces the key to sign the mine transactions, which applies to the next submission.
+    RotateKey {
+        key: MinerKey,
+        result: oneshot::Sender<Result<(), String>>,
+    },
+
+    /// Queries the mining statistics.
+    GetStats { result: oneshot::Sender<MinerStats> },
+}
+
+/// Handles to control the running mining components.
+struct MineControl {
+    provider: Provider<Http>,
+    enabled_send: watch::Sender<bool>,
+    signer_send: watch::Sender<Arc<MineServiceMiddleware>>,
+    puzzle_recv: watch::Receiver<Option<PoraPuzzle>>,
 }
 
 pub struct MinerService {
@@ -19,45 +49,157 @@ pub struct MinerService {
 
     /// Heartbeat interval for periodically checking on-chain data.
     heartbeat: tokio::time::Interval,
+
+    /// `None` if the miner is not configured.
+    mine_control: Option<MineControl>,
+
+    counters: Arc<MineCounters>,
+    probe_rate: ProbeRate,
 }
 
 impl MinerService {
-    pub fn spawn(
+    /// Spawns the miner service. The mining components are started only if `config` is
+    /// provided, and they mine only if mining is enabled.
+    pub async fn spawn(
         executor: task_executor::TaskExecutor,
         network_send: mpsc::UnboundedSender<NetworkMessage>,
-    ) -> mpsc::UnboundedSender<MinerMessage> {
+        config: Option<MinerConfig>,
+        store: Store,
+    ) -> Result<mpsc::UnboundedSender<MinerMessage>, String> {
         let (miner_send, miner_recv) = mpsc::unbounded_channel::<MinerMessage>();
 
         let heartbeat =
             tokio::time::interval(tokio::time::Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
 
+        let counters = Arc::new(MineCounters::default());
+        let mine_control = match config {
+            Some(config) => {
+                Some(Self::spawn_mine_components(&executor, config, store, counters.clone()).await?)
+            }
+            None => None,
+        };
+
         let mut miner = MinerService {
             msg_recv: miner_recv,
             network: MinerNetworkContext::new(network_send),
             heartbeat,
+            mine_control,
+            counters,
+            probe_rate: ProbeRate::new(),
         };
 
         debug!("Starting miner service");
         executor.spawn(async move { Box::pin(miner.main()).await }, "miner");
 
-        miner_send
+        Ok(miner_send)
+    }
+
+    async fn spawn_mine_components(
+        executor: &task_executor::TaskExecutor,
+        config: MinerConfig,
+        store: Store,
+        counters: Arc<MineCounters>,
+    ) -> Result<MineControl, String> {
+        let provider = config.make_provider()?;
+        let signer = Arc::new(make_signer(provider.clone(), &config.miner_key).await?);
+        info!(miner_id = ?config.miner_id, address = ?signer.address(), enabled = %config.enabled, "Miner configured");
+
+        let (enabled_send, enabled_recv) = watch::channel(config.enabled);
+        let (signer_send, signer_recv) = watch::channel(signer);
+
+        let puzzle_recv =
+            MineContextWatcher::spawn(executor.clone(), Arc::new(provider.clone()), &config);
+        let answer_send = Submitter::spawn(
+            executor.clone(),
+            signer_recv,
+            puzzle_recv.clone(),
+            &config,
+            counters.clone(),
+        );
+        PoraService::spawn(
+            executor.clone(),
+            &config,
+            store,
+            counters,
+            enabled_recv,
+            puzzle_recv.clone(),
+            answer_send,
+        );
+
+        Ok(MineControl {
+            provider,
+            enabled_send,
+            signer_send,
+            puzzle_recv,
+        })
     }
 
     async fn main(&mut self) {
         loop {
             tokio::select! {
-                // handle a message from the network
+                // handle a message from the admin RPC
                 maybe_msg = self.msg_recv.recv() => {
                     if let Some(msg) = maybe_msg {
-                        warn!("Miner received message {:?}", msg);
+                        self.on_miner_msg(msg).await;
                     }
                 }
 
                 // periodic checks
                 _ = self.heartbeat.tick() => {
-                    // EMPTY
+                    self.probe_rate.sample(self.counters.probes());
                 }
             }
         }
     }
+
+    async fn on_miner_msg(&mut self, msg: MinerMessage) {
+        match msg {
+            MinerMessage::SetMiningEnabled { enabled, result } => {
+                let _ = result.send(self.set_mining_enabled(enabled));
+            }
+            MinerMessage::RotateKey { key, result } => {
+                let _ = result.send(self.rotate_key(key).await);
+            }
+            MinerMessage::GetStats { result } => {
+                let _ = result.send(self.stats());
+            }
+        }
+    }
+
+    fn mine_control(&self) -> Result<&MineControl, String> {
+        self.mine_control
+            .as_ref()
+            .ok_or_else(|| "Miner is not configured".into())
+    }
+
+    fn set_mining_enabled(&self, enabled: bool) -> Result<(), String> {
+        let control = self.mine_control()?;
+        if *control.enabled_send.borrow() != enabled {
+            info!(%enabled, "Set mining enabled");
+            let _ = control.enabled_send.send(enabled);
+        }
+        Ok(())
+    }
+
+    async fn rotate_key(&self, key: MinerKey) -> Result<(), String> {
+        let control = self.mine_control()?;
+        let signer = make_signer(control.provider.clone(), &key).await?;
+        info!(address = ?signer.address(), "Miner key rotated");
+        let _ = control.signer_send.send(Arc::new(signer));
+        Ok(())
+    }
+
+    fn stats(&self) -> MinerStats {
+        let mut stats = MinerStats::new(&self.counters, &self.probe_rate);
+
+        if let Some(control) = self.mine_control.as_ref() {
+            stats.enabled = *control.enabled_send.borrow();
+            if let Some(puzzle) = control.puzzle_recv.borrow().as_ref() {
+                stats.epoch = Some(puzzle.context.epoch.low_u64());
+                stats.target = Some(puzzle.target);
+            }
+        }
+
+        stats
+    }
 }
@@ -0,0 +1,122 @@
+use crate::metrics;
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Counters shared by the mining components, which are also reported as metrics.
+#[derive(Default)]
+pub(crate) struct MineCounters {
+    probes: AtomicU64,
+    answers_found: AtomicU64,
+    submissions_sent: AtomicU64,
+    submissions_resent: AtomicU64,
+    submissions_accepted: AtomicU64,
+    submissions_failed: AtomicU64,
+    last_submission_error: Mutex<Option<String>>,
+}
+
+impl MineCounters {
+    pub fn on_probe(&self) {
+        self.probes.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_PROBES);
+    }
+
+    pub fn on_answer_found(&self) {
+        self.answers_found.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_ANSWERS_FOUND);
+    }
+
+    pub fn on_submission_sent(&self) {
+        self.submissions_sent.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_SUBMISSIONS_SENT);
+    }
+
+    pub fn on_submission_resent(&self) {
+        self.submissions_resent.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_SUBMISSIONS_RESENT);
+    }
+
+    pub fn on_submission_accepted(&self) {
+        self.submissions_accepted.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_SUBMISSIONS_ACCEPTED);
+    }
+
+    pub fn on_submission_failed(&self, error: String) {
+        self.submissions_failed.fetch_add(1, Ordering::Relaxed);
+        metrics::inc_counter(&metrics::MINER_SUBMISSIONS_FAILED);
+        *self.last_submission_error.lock().unwrap() = Some(error);
+    }
+
+    pub fn probes(&self) -> u64 {
+        self.probes.load(Ordering::Relaxed)
+    }
+}
+
+/// Measures the probe rate between the heartbeats of the miner service.
+pub(crate) struct ProbeRate {
+    last_sample: Instant,
+    last_probes: u64,
+    probes_per_sec: f64,
+}
+
+impl ProbeRate {
+    pub fn new() -> Self {
+        ProbeRate {
+            last_sample: Instant::now(),
+            last_probes: 0,
+            probes_per_sec: 0.0,
+        }
+    }
+
+    pub fn sample(&mut self, probes: u64) {
+        let secs = self.last_sample.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            self.probes_per_sec = (probes - self.last_probes) as f64 / secs;
+        }
+        self.last_sample = Instant::now();
+        self.last_probes = probes;
+    }
+
+    pub fn probes_per_sec(&self) -> f64 {
+        self.probes_per_sec
+    }
+}
+
+/// Mining statistics since the node started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinerStats {
+    pub enabled: bool,
+    pub probes: u64,
+    /// Probe rate measured in the last heartbeat interval.
+    pub probes_per_sec: f64,
+    pub answers_found: u64,
+    pub submissions_sent: u64,
+    /// Number of transactions replaced with a higher gas price, which are not counted as sent.
+    pub submissions_resent: u64,
+    pub submissions_accepted: u64,
+    pub submissions_failed: u64,
+    pub last_submission_error: Option<String>,
+    /// Epoch of the current mining context, `None` until loaded from chain.
+    pub epoch: Option<u64>,
+    /// PoRA target of the current mining context, where lower is harder.
+    pub target: Option<U256>,
+}
+
+impl MinerStats {
+    pub(crate) fn new(counters: &MineCounters, probe_rate: &ProbeRate) -> Self {
+        MinerStats {
+            probes: counters.probes(),
+            probes_per_sec: probe_rate.probes_per_sec(),
+            answers_found: counters.answers_found.load(Ordering::Relaxed),
+            submissions_sent: counters.submissions_sent.load(Ordering::Relaxed),
+            submissions_resent: counters.submissions_resent.load(Ordering::Relaxed),
+            submissions_accepted: counters.submissions_accepted.load(Ordering::Relaxed),
+            submissions_failed: counters.submissions_failed.load(Ordering::Relaxed),
+            last_submission_error: counters.last_submission_error.lock().unwrap().clone(),
+            ..Default::default()
+        }
+    }
+}
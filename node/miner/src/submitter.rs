@@ -0,0 +1,227 @@
+use crate::config::{MineServiceMiddleware, MinerConfig};
+use crate::contracts::{PoraAnswer, PoraMine};
+use crate::stats::MineCounters;
+use crate::watcher::PoraPuzzle;
+use ethers::prelude::{BlockNumber, Middleware, TransactionReceipt};
+use ethers::signers::Signer;
+use ethers::types::{Address, H256, U256};
+use std::sync::Arc;
+use std::time::Duration;
+use task_executor::TaskExecutor;
+use tokio::sync::{mpsc, watch};
+
+/// Time to wait for a submission transaction to be mined, before it is replaced by a transaction
+/// with a higher gas price.
+const SUBMISSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Percentage to bump the gas price of a replacement transaction. Most blockchain nodes require
+/// at least 10% to replace a pending transaction.
+const GAS_PRICE_BUMP_PERCENT: u64 = 20;
+
+/// Maximum number of transactions sent for an answer, including the replacements.
+const MAX_SUBMISSION_ATTEMPTS: usize = 5;
+
+/// Tracks the nonce of the miner account locally, so that a submission does not depend on the
+/// previous transaction to show up in the pending pool of the blockchain node.
+#[derive(Default)]
+struct NonceTracker {
+    /// The account and its next nonce, which is reloaded once the miner key is rotated.
+    next: Option<(Address, U256)>,
+}
+
+impl NonceTracker {
+    async fn next_nonce(&mut self, signer: &MineServiceMiddleware) -> Result<U256, String> {
+        let address = signer.address();
+        if let Some((account, nonce)) = self.next {
+            if account == address {
+                return Ok(nonce);
+            }
+        }
+
+        let nonce = signer
+            .get_transaction_count(address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(|e| format!("Failed to get the nonce of the miner account: {:?}", e))?;
+        self.next = Some((address, nonce));
+        Ok(nonce)
+    }
+
+    fn on_nonce_used(&mut self, address: Address, nonce: U256) {
+        self.next = Some((address, nonce + 1));
+    }
+
+    /// Reloads the nonce from the blockchain for the next submission, which is required if a
+    /// transaction is abandoned or rejected.
+    fn reset(&mut self) {
+        self.next = None;
+    }
+}
+
+/// Submits the valid answers to the mine contract as transactions. A transaction not mined in
+/// time is replaced by a transaction of the same nonce and a higher gas price, until the answer
+/// is confirmed, the attempts are exhausted or the mining context changes.
+pub struct Submitter {
+    answer_recv: mpsc::UnboundedReceiver<PoraAnswer>,
+    /// The latest signer, which is replaced once the miner key is rotated.
+    signer_recv: watch::Receiver<Arc<MineServiceMiddleware>>,
+    /// The latest mining context, to drop the answers of a previous epoch.
+    puzzle_recv: watch::Receiver<Option<PoraPuzzle>>,
+    mine_address: Address,
+    beneficiary: Option<Address>,
+    nonce_tracker: NonceTracker,
+    counters: Arc<MineCounters>,
+}
+
+impl Submitter {
+    pub fn spawn(
+        executor: TaskExecutor,
+        signer_recv: watch::Receiver<Arc<MineServiceMiddleware>>,
+        puzzle_recv: watch::Receiver<Option<PoraPuzzle>>,
+        config: &MinerConfig,
+        counters: Arc<MineCounters>,
+    ) -> mpsc::UnboundedSender<PoraAnswer> {
+        let (answer_send, answer_recv) = mpsc::unbounded_channel();
+
+        let submitter = Submitter {
+            answer_recv,
+            signer_recv,
+            puzzle_recv,
+            mine_address: config.mine_address,
+            beneficiary: config.beneficiary,
+            nonce_tracker: NonceTracker::default(),
+            counters,
+        };
+
+        executor.spawn(
+            async move { Box::pin(submitter.start()).await },
+            "mine_submitter",
+        );
+
+        answer_send
+    }
+
+    async fn start(mut self) {
+        while let Some(answer) = self.answer_recv.recv().await {
+            if !self.is_current(&answer.context_digest) {
+                debug!("Skip the PoRA answer of a stale mining context");
+                continue;
+            }
+
+            if let Err(e) = self.submit(answer).await {
+                warn!(%e, "Failed to submit PoRA answer");
+                self.counters.on_submission_failed(e);
+            }
+        }
+    }
+
+    fn is_current(&self, context_digest: &[u8; 32]) -> bool {
+        match self.puzzle_recv.borrow().as_ref() {
+            Some(puzzle) => &puzzle.context.digest == context_digest,
+            None => false,
+        }
+    }
+
+    async fn submit(&mut self, mut answer: PoraAnswer) -> Result<(), String> {
+        let signer = self.signer_recv.borrow().clone();
+        let context_digest = answer.context_digest;
+        answer.beneficiary = self.beneficiary.unwrap_or_else(|| signer.address());
+
+        let nonce = self.nonce_tracker.next_nonce(&signer).await?;
+        let mut gas_price = signer
+            .get_gas_price()
+            .await
+            .map_err(|e| format!("Failed to get gas price: {:?}", e))?;
+
+        let mine_contract = PoraMine::new(self.mine_address, signer.clone());
+        let mut tx = mine_contract.submit(answer).tx;
+        tx.set_nonce(nonce);
+        tx.set_gas_price(gas_price);
+        // the answer is rejected early if the gas estimation reverts
+        signer
+            .fill_transaction(&mut tx, None)
+            .await
+            .map_err(|e| format!("Failed to estimate mine transaction: {:?}", e))?;
+
+        let mut tx_hashes = vec![];
+        for attempt in 0..MAX_SUBMISSION_ATTEMPTS {
+            if attempt > 0 {
+                gas_price = gas_price * (100 + GAS_PRICE_BUMP_PERCENT) / 100;
+                tx.set_gas_price(gas_price);
+            }
+
+            let pending_transaction = match signer.send_transaction(tx.clone(), None).await {
+                Ok(pending_transaction) => pending_transaction,
+                Err(e) => {
+                    // the replaced transaction may be mined in the meantime
+                    if let Some(receipt) = find_receipt(&signer, &tx_hashes).await {
+                        return self.on_receipt(receipt);
+                    }
+                    self.nonce_tracker.reset();
+                    return Err(format!("Failed to send mine transaction: {:?}", e));
+                }
+            };
+
+            self.nonce_tracker.on_nonce_used(signer.address(), nonce);
+            tx_hashes.push(*pending_transaction);
+            if attempt == 0 {
+                self.counters.on_submission_sent();
+            } else {
+                self.counters.on_submission_resent();
+            }
+            debug!(tx_hash = ?*pending_transaction, %nonce, %gas_price, "Mine transaction sent");
+
+            match tokio::time::timeout(SUBMISSION_TIMEOUT, pending_transaction).await {
+                Ok(Ok(Some(receipt))) => return self.on_receipt(receipt),
+                // dropped from the pending pool
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => warn!(?e, "Failed to wait for mine transaction"),
+                Err(_) => debug!(%attempt, "Mine transaction not mined in time"),
+            }
+
+            if let Some(receipt) = find_receipt(&signer, &tx_hashes).await {
+                return self.on_receipt(receipt);
+            }
+
+            // the answer would be rejected by the contract anyway
+            if !self.is_current(&context_digest) {
+                self.nonce_tracker.reset();
+                return Err("Mining context changed before the answer is mined".into());
+            }
+        }
+
+        self.nonce_tracker.reset();
+        Err(format!(
+            "Mine transaction not mined after {} attempts",
+            MAX_SUBMISSION_ATTEMPTS
+        ))
+    }
+
+    fn on_receipt(&self, receipt: TransactionReceipt) -> Result<(), String> {
+        if receipt.status != Some(1.into()) {
+            return Err(format!(
+                "Mine transaction reverted, tx_hash = {:?}",
+                receipt.transaction_hash
+            ));
+        }
+
+        info!(tx_hash = ?receipt.transaction_hash, "PoRA answer submitted");
+        self.counters.on_submission_accepted();
+        Ok(())
+    }
+}
+
+/// Returns the receipt of any transaction mined, among the transactions replacing each other.
+async fn find_receipt(
+    signer: &MineServiceMiddleware,
+    tx_hashes: &[H256],
+) -> Option<TransactionReceipt> {
+    for tx_hash in tx_hashes {
+        match signer.get_transaction_receipt(*tx_hash).await {
+            Ok(Some(receipt)) => return Some(receipt),
+            Ok(None) => {}
+            Err(e) => debug!(?e, ?tx_hash, "Failed to get mine transaction receipt"),
+        }
+    }
+
+    None
+}
@@ -0,0 +1,165 @@
+use crate::config::MinerConfig;
+use crate::contracts::{IonianFlow, MineContext, NewEpochFilter, NewPoraTargetFilter, PoraMine};
+use crate::metrics;
+use ethers::contract::EthEvent;
+use ethers::prelude::{Filter, Http, Log, Middleware, Provider};
+use ethers::providers::FilterKind;
+use ethers::types::{U256, U64};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use task_executor::TaskExecutor;
+use tokio::sync::watch;
+
+/// Interval to poll the mining context events from the contracts.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Interval to reload the mining context without events, in case the events are missed, e.g.
+/// the filter expires on the blockchain node.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The on-chain mining parameters, which change once a new epoch starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoraPuzzle {
+    pub context: MineContext,
+    pub target: U256,
+}
+
+/// Watches the events of a new epoch from the flow contract and the events of a new target from
+/// the mine contract, and then reloads the mining context. The context and the target are loaded
+/// from the same block, and published together to switch the puzzle atomically.
+pub struct MineContextWatcher {
+    provider: Arc<Provider<Http>>,
+    flow_contract: IonianFlow<Provider<Http>>,
+    mine_contract: PoraMine<Provider<Http>>,
+    puzzle_send: watch::Sender<Option<PoraPuzzle>>,
+}
+
+impl MineContextWatcher {
+    /// Spawns the watcher task. Returns the receiver of the latest puzzle, which is `None` until
+    /// retrieved from the blockchain.
+    pub fn spawn(
+        executor: TaskExecutor,
+        provider: Arc<Provider<Http>>,
+        config: &MinerConfig,
+    ) -> watch::Receiver<Option<PoraPuzzle>> {
+        let (puzzle_send, puzzle_recv) = watch::channel(None);
+
+        let watcher = MineContextWatcher {
+            provider: provider.clone(),
+            flow_contract: IonianFlow::new(config.flow_address, provider.clone()),
+            mine_contract: PoraMine::new(config.mine_address, provider),
+            puzzle_send,
+        };
+
+        executor.spawn(
+            async move { Box::pin(watcher.start()).await },
+            "mine_context_watcher",
+        );
+
+        puzzle_recv
+    }
+
+    async fn start(self) {
+        let mut interval = tokio::time::interval(EVENT_POLL_INTERVAL);
+        let mut filter_id = None;
+        let mut last_reload: Option<Instant> = None;
+
+        loop {
+            interval.tick().await;
+
+            let changed = match self.poll_events(&mut filter_id).await {
+                Ok(changed) => changed,
+                Err(e) => {
+                    // install a new filter and reload, since events may be missed
+                    warn!(%e, "Failed to poll mining context events");
+                    filter_id = None;
+                    continue;
+                }
+            };
+            let reload_due = last_reload.map_or(true, |t| t.elapsed() >= RELOAD_INTERVAL);
+            if !changed && !reload_due {
+                continue;
+            }
+
+            let puzzle = match self.query_puzzle().await {
+                Ok(puzzle) => puzzle,
+                Err(e) => {
+                    warn!(%e, "Failed to query mining context");
+                    continue;
+                }
+            };
+            last_reload = Some(Instant::now());
+
+            if self.puzzle_send.borrow().as_ref() == Some(&puzzle) {
+                continue;
+            }
+
+            info!(epoch = %puzzle.context.epoch, flow_length = %puzzle.context.flow_length, target = %puzzle.target, "New mining context");
+            metrics::set_gauge(&metrics::MINER_EPOCH, puzzle.context.epoch.low_u64() as i64);
+            metrics::set_gauge(&metrics::MINER_TARGET_BITS, puzzle.target.bits() as i64);
+            if self.puzzle_send.send(Some(puzzle)).is_err() {
+                // the miner is stopped
+                return;
+            }
+        }
+    }
+
+    /// Returns true if the mining context may have changed since the last poll, which is also the
+    /// case when a new filter is installed.
+    async fn poll_events(&self, filter_id: &mut Option<U256>) -> Result<bool, String> {
+        let id = match filter_id {
+            Some(id) => *id,
+            None => {
+                let filter = Filter::new()
+                    .address(vec![
+                        self.flow_contract.address(),
+                        self.mine_contract.address(),
+                    ])
+                    .topic0(vec![
+                        NewEpochFilter::signature(),
+                        NewPoraTargetFilter::signature(),
+                    ]);
+                let id = self
+                    .provider
+                    .new_filter(FilterKind::Logs(&filter))
+                    .await
+                    .map_err(|e| format!("Failed to install event filter: {:?}", e))?;
+                *filter_id = Some(id);
+                return Ok(true);
+            }
+        };
+
+        // reverted events also change the mining context
+        let logs: Vec<Log> = self
+            .provider
+            .get_filter_changes(id)
+            .await
+            .map_err(|e| format!("Failed to get filter changes: {:?}", e))?;
+        Ok(!logs.is_empty())
+    }
+
+    async fn query_puzzle(&self) -> Result<PoraPuzzle, String> {
+        let block_number: U64 = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(|e| format!("Failed to query block number: {:?}", e))?;
+
+        let context = self
+            .flow_contract
+            .get_context()
+            .block(block_number)
+            .call()
+            .await
+            .map_err(|e| format!("Failed to query flow context: {:?}", e))?;
+        let target = self
+            .mine_contract
+            .pora_target()
+            .block(block_number)
+            .call()
+            .await
+            .map_err(|e| format!("Failed to query PoRA target: {:?}", e))?;
+
+        Ok(PoraPuzzle { context, target })
+    }
+}
@@ -0,0 +1,205 @@
+//! Bandwidth throttling of the sync protocol, which prevents a storage node from saturating the
+//! uplink or downlink of the host when serving or downloading file chunks.
+//!
+//! Both the global and per-peer caps are enforced with token buckets, which allow a burst of up to
+//! one second of traffic. Instead of dropping messages, the limiter reserves the bandwidth and
+//! returns how long the message should be delayed to conform to the caps.
+
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Duration of traffic allowed in a burst.
+const BURST: Duration = Duration::from_secs(1);
+
+/// Idle per-peer buckets are pruned when the number of buckets exceeds this limit.
+const MAX_IDLE_PEER_BUCKETS: usize = 1024;
+
+/// Bandwidth caps in bytes per second, where `None` means unlimited.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BandwidthConfig {
+    pub upload_bytes_per_sec: Option<u64>,
+    pub download_bytes_per_sec: Option<u64>,
+    pub peer_upload_bytes_per_sec: Option<u64>,
+    pub peer_download_bytes_per_sec: Option<u64>,
+}
+
+/// Token bucket with the virtual scheduling implementation.
+#[derive(Debug)]
+struct Bucket {
+    /// Theoretical arrival time of the next message, i.e. when the bucket becomes full.
+    tat: Instant,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Self {
+        Bucket { tat: now }
+    }
+
+    /// Consumes tokens of `bytes` unconditionally, and returns how long to wait before the bytes
+    /// could be transferred.
+    fn reserve(&mut self, now: Instant, bytes: u64, bytes_per_sec: u64) -> Duration {
+        let cost = Duration::from_secs_f64(bytes as f64 / bytes_per_sec.max(1) as f64);
+        let tat = self.tat.max(now) + cost;
+        self.tat = tat;
+
+        match tat.checked_sub(BURST) {
+            Some(allowed_at) => allowed_at.saturating_duration_since(now),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn is_idle(&self, now: Instant) -> bool {
+        self.tat <= now
+    }
+}
+
+/// Buckets for a single direction of traffic.
+#[derive(Debug, Default)]
+struct DirectionLimiter {
+    global: Option<Bucket>,
+    peers: HashMap<PeerId, Bucket>,
+}
+
+impl DirectionLimiter {
+    fn reserve(
+        &mut self,
+        now: Instant,
+        peer_id: PeerId,
+        bytes: u64,
+        global_rate: Option<u64>,
+        peer_rate: Option<u64>,
+    ) -> Duration {
+        let mut delay = Duration::ZERO;
+
+        if let Some(rate) = global_rate {
+            let bucket = self.global.get_or_insert_with(|| Bucket::new(now));
+            delay = delay.max(bucket.reserve(now, bytes, rate));
+        }
+
+        if let Some(rate) = peer_rate {
+            if self.peers.len() >= MAX_IDLE_PEER_BUCKETS && !self.peers.contains_key(&peer_id) {
+                self.peers.retain(|_, bucket| !bucket.is_idle(now));
+            }
+
+            let bucket = self
+                .peers
+                .entry(peer_id)
+                .or_insert_with(|| Bucket::new(now));
+            delay = delay.max(bucket.reserve(now, bytes, rate));
+        }
+
+        delay
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: BandwidthConfig,
+    upload: DirectionLimiter,
+    download: DirectionLimiter,
+}
+
+/// Bandwidth limiter of the sync protocol, whose caps could be changed at runtime.
+#[derive(Debug, Default)]
+pub struct BandwidthLimiter {
+    inner: Mutex<Inner>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(config: BandwidthConfig) -> Self {
+        BandwidthLimiter {
+            inner: Mutex::new(Inner {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn config(&self) -> BandwidthConfig {
+        self.inner.lock().config.clone()
+    }
+
+    /// Updates the caps, which resets all the buckets.
+    pub fn set_config(&self, config: BandwidthConfig) {
+        *self.inner.lock() = Inner {
+            config,
+            ..Default::default()
+        };
+    }
+
+    /// Reserves bandwidth to send `bytes` to `peer_id`, and returns how long to delay sending.
+    pub fn reserve_upload(&self, peer_id: PeerId, bytes: u64) -> Duration {
+        let mut inner = self.inner.lock();
+        let (global, peer) = (
+            inner.config.upload_bytes_per_sec,
+            inner.config.peer_upload_bytes_per_sec,
+        );
+        inner
+            .upload
+            .reserve(Instant::now(), peer_id, bytes, global, peer)
+    }
+
+    /// Reserves bandwidth to receive `bytes` from `peer_id`, and returns how long to delay the
+    /// request of the bytes.
+    pub fn reserve_download(&self, peer_id: PeerId, bytes: u64) -> Duration {
+        let mut inner = self.inner.lock();
+        let (global, peer) = (
+            inner.config.download_bytes_per_sec,
+            inner.config.peer_download_bytes_per_sec,
+        );
+        inner
+            .download
+            .reserve(Instant::now(), peer_id, bytes, global, peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    fn random_peer() -> PeerId {
+        identity::Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_bucket_burst() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(now);
+
+        // one second of traffic is allowed in a burst
+        assert_eq!(bucket.reserve(now, 500, 1000), Duration::ZERO);
+        assert_eq!(bucket.reserve(now, 500, 1000), Duration::ZERO);
+        assert_eq!(bucket.reserve(now, 500, 1000), Duration::from_millis(500));
+
+        // replenished over time
+        let later = now + Duration::from_secs(3);
+        assert_eq!(bucket.reserve(later, 1000, 1000), Duration::ZERO);
+        assert!(bucket.is_idle(later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_global_and_peer_caps() {
+        let limiter = BandwidthLimiter::new(BandwidthConfig {
+            upload_bytes_per_sec: Some(3000),
+            peer_upload_bytes_per_sec: Some(1000),
+            ..Default::default()
+        });
+        let (peer1, peer2) = (random_peer(), random_peer());
+
+        assert_eq!(limiter.reserve_upload(peer1, 1000), Duration::ZERO);
+        // exceeds the per-peer cap
+        assert!(limiter.reserve_upload(peer1, 1000) > Duration::ZERO);
+        // the other peer is only limited by the global cap
+        assert_eq!(limiter.reserve_upload(peer2, 1000), Duration::ZERO);
+        // download is unlimited
+        assert_eq!(limiter.reserve_download(peer1, 1 << 30), Duration::ZERO);
+
+        limiter.set_config(Default::default());
+        assert_eq!(limiter.reserve_upload(peer1, 1 << 30), Duration::ZERO);
+    }
+}
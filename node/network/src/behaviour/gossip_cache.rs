@@ -24,6 +24,10 @@ pub struct GossipCache {
     find_file: Option<Duration>,
     /// Timeout for AnnounceFile.
     announce_file: Option<Duration>,
+    /// Timeout for AnnounceStorage.
+    announce_storage: Option<Duration>,
+    /// Timeout for NewTx.
+    new_tx: Option<Duration>,
 }
 
 #[derive(Default)]
@@ -35,6 +39,10 @@ pub struct GossipCacheBuilder {
     find_file: Option<Duration>,
     /// Timeout for AnnounceFile messages.
     announce_file: Option<Duration>,
+    /// Timeout for AnnounceStorage messages.
+    announce_storage: Option<Duration>,
+    /// Timeout for NewTx messages.
+    new_tx: Option<Duration>,
 }
 
 #[allow(dead_code)]
@@ -64,12 +72,26 @@ impl GossipCacheBuilder {
         self
     }
 
+    /// Timeout for AnnounceStorage messages.
+    pub fn announce_storage_timeout(mut self, timeout: Duration) -> Self {
+        self.announce_storage = Some(timeout);
+        self
+    }
+
+    /// Timeout for NewTx messages.
+    pub fn new_tx_timeout(mut self, timeout: Duration) -> Self {
+        self.new_tx = Some(timeout);
+        self
+    }
+
     pub fn build(self) -> GossipCache {
         let GossipCacheBuilder {
             default_timeout,
             example,
             find_file,
             announce_file,
+            announce_storage,
+            new_tx,
         } = self;
 
         GossipCache {
@@ -78,6 +100,8 @@ impl GossipCacheBuilder {
             example: example.or(default_timeout),
             find_file: find_file.or(default_timeout),
             announce_file: announce_file.or(default_timeout),
+            announce_storage: announce_storage.or(default_timeout),
+            new_tx: new_tx.or(default_timeout),
         }
     }
 }
@@ -95,6 +119,8 @@ impl GossipCache {
             GossipKind::Example => self.example,
             GossipKind::FindFile => self.find_file,
             GossipKind::AnnounceFile => self.announce_file,
+            GossipKind::AnnounceStorage => self.announce_storage,
+            GossipKind::NewTx => self.new_tx,
         };
 
         let expire_timeout = match expire_timeout {
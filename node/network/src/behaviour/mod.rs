@@ -6,7 +6,9 @@ use crate::peer_manager::{
     ConnectionDirection, PeerManager, PeerManagerEvent,
 };
 use crate::rpc::methods::DataByHashRequest;
-use crate::rpc::methods::GetChunksRequest;
+use crate::rpc::methods::{
+    GetChunksRequest, GetErasureShardRequest, GetPendingChunksRequest, OfferFileRequest,
+};
 use crate::rpc::*;
 use crate::service::Context as ServiceContext;
 use crate::types::{GossipEncoding, GossipKind, GossipTopic, SnappyTransform};
@@ -30,11 +32,13 @@ use libp2p::{
     },
     NetworkBehaviour, PeerId,
 };
-use shared_types::ChunkArrayWithProof;
+use shared_types::{ChunkArrayWithProof, ErasureShard, ProvisionalChunks};
+use ssz::Encode;
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use self::gossip_cache::GossipCache;
@@ -79,6 +83,8 @@ pub enum BehaviourEvent<AppReqId: ReqId> {
         id: AppReqId,
         /// The peer to which this request was sent.
         peer_id: PeerId,
+        /// The error that occurred.
+        error: RPCError,
     },
     RequestReceived {
         /// The peer that sent the request.
@@ -235,10 +241,30 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             .with_peer_score(params, thresholds)
             .expect("Valid score params and thresholds");
 
+        let mut trusted_peers: HashMap<PeerId, Vec<Multiaddr>> = config
+            .trusted_peers
+            .iter()
+            .map(|peer_id| (PeerId::from(peer_id.clone()), vec![]))
+            .collect();
+        for multiaddr in &config.trusted_peers_multiaddr {
+            if let Some(MProtocol::P2p(hash)) = multiaddr.iter().last() {
+                if let Ok(peer_id) = PeerId::from_multihash(hash) {
+                    trusted_peers
+                        .entry(peer_id)
+                        .or_default()
+                        .push(multiaddr.clone());
+                }
+            }
+        }
+
         let peer_manager_cfg = PeerManagerCfg {
             discovery_enabled: !config.disable_discovery,
             metrics_enabled: config.metrics_enabled,
             target_peer_count: config.target_peers,
+            max_peer_count: Some(config.max_peers()),
+            max_inbound_peer_count: Some(config.max_inbound_peers()),
+            trusted_peers: trusted_peers.clone(),
+            ping_timeout: config.ping_timeout,
             ..Default::default()
         };
 
@@ -252,7 +278,10 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         Ok(Behaviour {
             // Sub-behaviours
             gossipsub,
-            eth2_rpc: RPC::new(),
+            eth2_rpc: RPC::new(
+                trusted_peers.into_keys().collect(),
+                config.peer_chunk_bytes_per_min,
+            ),
             discovery,
             identify: Identify::new(identify_config),
             // Auxiliary fields
@@ -425,6 +454,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
 
     /// Send a request to a peer over RPC.
     pub fn send_request(&mut self, peer_id: PeerId, request_id: AppReqId, request: Request) {
+        self.peer_manager
+            .on_rpc_sent(&peer_id, request.ssz_bytes_len());
         self.eth2_rpc
             .send_request(peer_id, RequestId::Application(request_id), request.into())
     }
@@ -436,6 +467,8 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
         id: PeerRequestId,
         response: Response,
     ) {
+        self.peer_manager
+            .on_rpc_sent(&peer_id, response.ssz_bytes_len());
         self.eth2_rpc.send_response(peer_id, id, response.into())
     }
 
@@ -502,7 +535,16 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
 
     // RPC Propagation methods
     /// Queues the response to be sent upwards as long at it was requested outside the Behaviour.
-    fn propagate_response(&mut self, id: RequestId<AppReqId>, peer_id: PeerId, response: Response) {
+    fn propagate_response(
+        &mut self,
+        id: RequestId<AppReqId>,
+        peer_id: PeerId,
+        response: Response,
+        latency: Option<Duration>,
+    ) {
+        self.peer_manager
+            .on_rpc_received(&peer_id, response.ssz_bytes_len(), latency);
+
         match id {
             RequestId::Application(id) => self.add_event(BehaviourEvent::ResponseReceived {
                 peer_id,
@@ -515,6 +557,9 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
 
     /// Convenience function to propagate a request.
     fn propagate_request(&mut self, id: PeerRequestId, peer_id: PeerId, request: Request) {
+        self.peer_manager
+            .on_rpc_received(&peer_id, request.ssz_bytes_len(), None);
+
         // Increment metrics
         match &request {
             Request::Status(_) => {
@@ -526,6 +571,15 @@ impl<AppReqId: ReqId> Behaviour<AppReqId> {
             Request::GetChunks { .. } => {
                 metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["get_chunks"])
             }
+            Request::OfferFile { .. } => {
+                metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["offer_file"])
+            }
+            Request::GetErasureShard { .. } => {
+                metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["get_erasure_shard"])
+            }
+            Request::GetPendingChunks { .. } => {
+                metrics::inc_counter_vec(&metrics::TOTAL_RPC_REQUESTS, &["get_pending_chunks"])
+            }
         }
         self.add_event(BehaviourEvent::RequestReceived {
             peer_id,
@@ -697,7 +751,7 @@ where
                         );
                         // inform failures of requests comming outside the behaviour
                         if let RequestId::Application(id) = id {
-                            self.add_event(BehaviourEvent::RPCFailed { peer_id, id });
+                            self.add_event(BehaviourEvent::RPCFailed { peer_id, id, error });
                         }
                     }
                 }
@@ -738,9 +792,22 @@ where
                     InboundRequest::GetChunks(req) => {
                         self.propagate_request(peer_request_id, peer_id, Request::GetChunks(req))
                     }
+                    InboundRequest::OfferFile(req) => {
+                        self.propagate_request(peer_request_id, peer_id, Request::OfferFile(req))
+                    }
+                    InboundRequest::GetErasureShard(req) => self.propagate_request(
+                        peer_request_id,
+                        peer_id,
+                        Request::GetErasureShard(req),
+                    ),
+                    InboundRequest::GetPendingChunks(req) => self.propagate_request(
+                        peer_request_id,
+                        peer_id,
+                        Request::GetPendingChunks(req),
+                    ),
                 }
             }
-            Ok(RPCReceived::Response(id, resp)) => {
+            Ok(RPCReceived::Response(id, resp, latency)) => {
                 match resp {
                     /* Behaviour managed protocols */
                     RPCResponse::Pong(ping) => self.peer_manager.pong_response(&peer_id, ping.data),
@@ -749,13 +816,22 @@ where
                         // inform the peer manager that we have received a status from a peer
                         self.peer_manager.peer_statusd(&peer_id);
                         // propagate the STATUS message upwards
-                        self.propagate_response(id, peer_id, Response::Status(msg));
-                    }
-                    RPCResponse::DataByHash(resp) => {
-                        self.propagate_response(id, peer_id, Response::DataByHash(Some(resp)))
+                        self.propagate_response(id, peer_id, Response::Status(msg), latency);
                     }
+                    RPCResponse::DataByHash(resp) => self.propagate_response(
+                        id,
+                        peer_id,
+                        Response::DataByHash(Some(resp)),
+                        latency,
+                    ),
                     RPCResponse::Chunks(resp) => {
-                        self.propagate_response(id, peer_id, Response::Chunks(resp))
+                        self.propagate_response(id, peer_id, Response::Chunks(resp), latency)
+                    }
+                    RPCResponse::ErasureShard(resp) => {
+                        self.propagate_response(id, peer_id, Response::ErasureShard(resp), latency)
+                    }
+                    RPCResponse::PendingChunks(resp) => {
+                        self.propagate_response(id, peer_id, Response::PendingChunks(resp), latency)
                     }
                 }
             }
@@ -763,7 +839,7 @@ where
                 let response = match termination {
                     ResponseTermination::DataByHash => Response::DataByHash(None),
                 };
-                self.propagate_response(id, peer_id, response);
+                self.propagate_response(id, peer_id, response, None);
             }
         }
     }
@@ -926,6 +1002,12 @@ impl<AppReqId: ReqId> NetworkBehaviourEventProcess<PeerManagerEvent> for Behavio
                 // send a ping request to this peer
                 self.ping(peer_id);
             }
+            PeerManagerEvent::DialPeer(peer_id) => {
+                debug!(%peer_id, "Dialing trusted peer");
+                self.peer_manager.inject_dialing(&peer_id, None);
+                self.internal_events
+                    .push_back(InternalBehaviourMessage::DialPeer(peer_id));
+            }
             PeerManagerEvent::DisconnectPeer(peer_id, reason) => {
                 debug!(%peer_id, %reason, "Peer Manager disconnecting peer");
                 // send one goodbye
@@ -951,6 +1033,26 @@ pub enum Request {
     DataByHash(DataByHashRequest),
     /// A GetChunks request.
     GetChunks(GetChunksRequest),
+    /// An OfferFile request, which has no response.
+    OfferFile(OfferFileRequest),
+    /// A GetErasureShard request.
+    GetErasureShard(GetErasureShardRequest),
+    /// A GetPendingChunks request.
+    GetPendingChunks(GetPendingChunksRequest),
+}
+
+impl Request {
+    /// Returns the size of the request in SSZ encoding.
+    pub fn ssz_bytes_len(&self) -> usize {
+        match self {
+            Request::Status(s) => s.ssz_bytes_len(),
+            Request::DataByHash(r) => r.hashes.ssz_bytes_len(),
+            Request::GetChunks(r) => r.ssz_bytes_len(),
+            Request::OfferFile(r) => r.ssz_bytes_len(),
+            Request::GetErasureShard(r) => r.ssz_bytes_len(),
+            Request::GetPendingChunks(r) => r.ssz_bytes_len(),
+        }
+    }
 }
 
 impl std::convert::From<Request> for OutboundRequest {
@@ -959,6 +1061,9 @@ impl std::convert::From<Request> for OutboundRequest {
             Request::Status(s) => OutboundRequest::Status(s),
             Request::DataByHash(r) => OutboundRequest::DataByHash(r),
             Request::GetChunks(r) => OutboundRequest::GetChunks(r),
+            Request::OfferFile(r) => OutboundRequest::OfferFile(r),
+            Request::GetErasureShard(r) => OutboundRequest::GetErasureShard(r),
+            Request::GetPendingChunks(r) => OutboundRequest::GetPendingChunks(r),
         }
     }
 }
@@ -977,6 +1082,23 @@ pub enum Response {
     DataByHash(Option<Box<IonianData>>),
     /// A response to a GET_CHUNKS request.
     Chunks(ChunkArrayWithProof),
+    /// A response to a GET_ERASURE_SHARD request.
+    ErasureShard(ErasureShard),
+    /// A response to a GET_PENDING_CHUNKS request.
+    PendingChunks(ProvisionalChunks),
+}
+
+impl Response {
+    /// Returns the size of the response in SSZ encoding, which is zero for a stream termination.
+    pub fn ssz_bytes_len(&self) -> usize {
+        match self {
+            Response::Status(s) => s.ssz_bytes_len(),
+            Response::DataByHash(r) => r.as_ref().map_or(0, |data| data.ssz_bytes_len()),
+            Response::Chunks(c) => c.ssz_bytes_len(),
+            Response::ErasureShard(s) => s.ssz_bytes_len(),
+            Response::PendingChunks(c) => c.ssz_bytes_len(),
+        }
+    }
 }
 
 impl std::convert::From<Response> for RPCCodedResponse {
@@ -988,6 +1110,8 @@ impl std::convert::From<Response> for RPCCodedResponse {
                 None => RPCCodedResponse::StreamTermination(ResponseTermination::DataByHash),
             },
             Response::Chunks(c) => RPCCodedResponse::Success(RPCResponse::Chunks(c)),
+            Response::ErasureShard(s) => RPCCodedResponse::Success(RPCResponse::ErasureShard(s)),
+            Response::PendingChunks(c) => RPCCodedResponse::Success(RPCResponse::PendingChunks(c)),
         }
     }
 }
@@ -1,3 +1,6 @@
+use crate::bandwidth::BandwidthConfig;
+use crate::peer_manager::config::DEFAULT_PING_TIMEOUT;
+use crate::peer_manager::{default_max_inbound_peers, default_max_peers};
 use crate::types::GossipKind;
 use crate::{Enr, PeerIdSerialized};
 use directory::{
@@ -11,6 +14,7 @@ use libp2p::gossipsub::{
 use libp2p::Multiaddr;
 use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use shared_types::ShardConfig;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -70,6 +74,18 @@ pub struct Config {
     /// Target number of connected peers.
     pub target_peers: usize,
 
+    /// Maximum number of connected peers, which is no less than `target_peers`. Defaults to
+    /// `target_peers` with some excess allowed.
+    pub max_peers: Option<usize>,
+
+    /// Maximum number of inbound connected peers, which is no more than the maximum number of
+    /// peers. Defaults to the maximum number of peers with some slots reserved for outbound peers.
+    pub max_inbound_peers: Option<usize>,
+
+    /// Time in seconds to wait for peers to respond to ping, after which the unresponsive peers
+    /// are disconnected.
+    pub ping_timeout: u64,
+
     /// Gossipsub configuration parameters.
     #[serde(skip)]
     pub gs_config: GossipsubConfig,
@@ -90,13 +106,17 @@ pub struct Config {
     /// List of trusted libp2p nodes which are not scored.
     pub trusted_peers: Vec<PeerIdSerialized>,
 
+    /// Addresses of the trusted peers on Multiaddr format with the `/p2p/<peer id>` suffix. The
+    /// trusted peers are always dialed, never pruned or banned, and exempt from RPC rate limits.
+    pub trusted_peers_multiaddr: Vec<Multiaddr>,
+
     /// Client version
     pub client_version: String,
 
     /// Disables the discovery protocol from starting.
     pub disable_discovery: bool,
 
-    /// Attempt to construct external port mappings with UPnP.
+    /// Attempt to construct external port mappings with UPnP or NAT-PMP.
     pub upnp_enabled: bool,
 
     /// Subscribe to all subnets for the duration of the runtime.
@@ -122,6 +142,29 @@ pub struct Config {
 
     /// Whether metrics are enabled.
     pub metrics_enabled: bool,
+
+    /// Bandwidth caps of the sync protocol.
+    pub sync_bandwidth: BandwidthConfig,
+
+    /// Bytes of chunk data served to each peer per minute, beyond which the requests are answered
+    /// as busy.
+    pub peer_chunk_bytes_per_min: u64,
+
+    /// Allow and deny lists of IP ranges for the libp2p connections and discv5 packets.
+    pub ip_filter: IpFilterConfig,
+
+    /// Identifier of the blockchain that hosts the log contract, which is exchanged with peers
+    /// in the status handshake.
+    pub chain_id: u64,
+
+    /// Shard of the file data stored by the node, which is advertised in the ENR.
+    pub shard_config: ShardConfig,
+
+    /// Remaining storage capacity in bytes advertised in the ENR, if any.
+    pub enr_capacity: Option<u64>,
+
+    /// Port of the public RPC service advertised in the ENR, if any.
+    pub enr_rpc_port: Option<u16>,
 }
 
 impl Default for Config {
@@ -183,12 +226,16 @@ impl Default for Config {
             enr_udp_port: None,
             enr_tcp_port: None,
             target_peers: 50,
+            max_peers: None,
+            max_inbound_peers: None,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
             gs_config,
             discv5_config,
             boot_nodes_enr: vec![],
             boot_nodes_multiaddr: vec![],
             libp2p_nodes: vec![],
             trusted_peers: vec![],
+            trusted_peers_multiaddr: vec![],
             client_version: ionian_version::version_with_platform(),
             disable_discovery: false,
             upnp_enabled: true,
@@ -199,10 +246,34 @@ impl Default for Config {
             shutdown_after_sync: false,
             topics: Vec::new(),
             metrics_enabled: false,
+            sync_bandwidth: Default::default(),
+            peer_chunk_bytes_per_min: 256 * 1024 * 1024,
+            ip_filter: Default::default(),
+            chain_id: 0,
+            shard_config: Default::default(),
+            enr_capacity: None,
+            enr_rpc_port: None,
         }
     }
 }
 
+impl Config {
+    /// Maximum number of connected peers.
+    pub fn max_peers(&self) -> usize {
+        self.max_peers
+            .unwrap_or_else(|| default_max_peers(self.target_peers))
+            .max(self.target_peers)
+    }
+
+    /// Maximum number of inbound connected peers.
+    pub fn max_inbound_peers(&self) -> usize {
+        let max_peers = self.max_peers();
+        self.max_inbound_peers
+            .unwrap_or_else(|| default_max_inbound_peers(self.target_peers, max_peers))
+            .min(max_peers)
+    }
+}
+
 /// Controls sizes of gossipsub meshes to tune a Lighthouse node's bandwidth/performance.
 pub struct NetworkLoad {
     pub name: &'static str,
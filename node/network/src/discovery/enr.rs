@@ -4,15 +4,108 @@ pub use discv5::enr::{self, CombinedKey, EnrBuilder};
 
 use super::enr_ext::CombinedKeyExt;
 use super::ENR_FILENAME;
+use crate::rpc::PROTOCOL_VERSION;
 use crate::types::Enr;
 use crate::NetworkConfig;
 use discv5::enr::EnrKey;
 use libp2p::core::identity::Keypair;
+use serde_derive::{Deserialize, Serialize};
+use shared_types::ShardConfig;
+use ssz::{Decode, Encode};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 use std::str::FromStr;
 
+/// The ENR field specifying the shard config of the node.
+pub const SHARD_CONFIG_ENR_KEY: &str = "shard";
+/// The ENR field specifying the remaining storage capacity of the node in bytes.
+pub const CAPACITY_ENR_KEY: &str = "capacity";
+/// The ENR field specifying the port of the public RPC service of the node, or 0 if not served.
+pub const RPC_PORT_ENR_KEY: &str = "rpc";
+/// The ENR field specifying the version of the network protocols supported by the node.
+pub const PROTOCOL_VERSION_ENR_KEY: &str = "protocol";
+
+/// Storage fields of the local ENR to update at runtime. Fields of `None` are left unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EnrUpdate {
+    pub shard_config: Option<ShardConfig>,
+    pub capacity: Option<u64>,
+    /// Port of the public RPC service, or 0 if no longer served.
+    pub rpc_port: Option<u16>,
+    pub protocol_version: Option<u32>,
+}
+
+impl EnrUpdate {
+    /// Returns the ENR fields to update along with their encoded values.
+    pub fn fields(&self) -> Result<Vec<(&'static str, Vec<u8>)>, String> {
+        let mut fields = vec![];
+        if let Some(shard_config) = &self.shard_config {
+            if !shard_config.is_valid() {
+                return Err(format!("Invalid shard config: {:?}", shard_config));
+            }
+            fields.push((SHARD_CONFIG_ENR_KEY, shard_config.as_ssz_bytes()));
+        }
+        if let Some(capacity) = self.capacity {
+            fields.push((CAPACITY_ENR_KEY, capacity.as_ssz_bytes()));
+        }
+        if let Some(rpc_port) = self.rpc_port {
+            fields.push((RPC_PORT_ENR_KEY, rpc_port.as_ssz_bytes()));
+        }
+        if let Some(protocol_version) = self.protocol_version {
+            fields.push((PROTOCOL_VERSION_ENR_KEY, protocol_version.as_ssz_bytes()));
+        }
+        Ok(fields)
+    }
+}
+
+/// Extension trait for the storage fields of ENRs.
+pub trait StorageEnr {
+    /// The shard config of the node, or `None` if not specified or malformed.
+    fn shard_config(&self) -> Option<ShardConfig>;
+
+    /// The remaining storage capacity of the node in bytes, or `None` if not specified.
+    fn capacity(&self) -> Option<u64>;
+
+    /// The port of the public RPC service of the node, or `None` if not served.
+    fn rpc_port(&self) -> Option<u16>;
+
+    /// The version of the network protocols supported by the node, or `None` if not specified.
+    fn protocol_version(&self) -> Option<u32>;
+
+    /// Whether the node stores any data of the given shard. Nodes without shard config are
+    /// regarded as storing all the data.
+    fn intersect_shard(&self, shard_config: &ShardConfig) -> bool {
+        self.shard_config()
+            .map_or(true, |config| config.intersect(shard_config))
+    }
+}
+
+impl StorageEnr for Enr {
+    fn shard_config(&self) -> Option<ShardConfig> {
+        let bytes = self.get(SHARD_CONFIG_ENR_KEY)?;
+        ShardConfig::from_ssz_bytes(bytes)
+            .ok()
+            .filter(ShardConfig::is_valid)
+    }
+
+    fn capacity(&self) -> Option<u64> {
+        let bytes = self.get(CAPACITY_ENR_KEY)?;
+        u64::from_ssz_bytes(bytes).ok()
+    }
+
+    fn rpc_port(&self) -> Option<u16> {
+        let bytes = self.get(RPC_PORT_ENR_KEY)?;
+        u16::from_ssz_bytes(bytes).ok().filter(|port| *port != 0)
+    }
+
+    fn protocol_version(&self) -> Option<u32> {
+        let bytes = self.get(PROTOCOL_VERSION_ENR_KEY)?;
+        u32::from_ssz_bytes(bytes).ok()
+    }
+}
+
 /// Either use the given ENR or load an ENR from file if it exists and matches the current NodeId
 /// and sequence number.
 /// If an ENR exists, with the same NodeId, this function checks to see if the loaded ENR from
@@ -101,6 +194,15 @@ pub fn create_enr_builder_from_config<T: EnrKey>(
 pub fn build_enr(enr_key: &CombinedKey, config: &NetworkConfig) -> Result<Enr, String> {
     let mut builder = create_enr_builder_from_config(config, true);
 
+    builder.add_value(SHARD_CONFIG_ENR_KEY, &config.shard_config.as_ssz_bytes());
+    if let Some(capacity) = config.enr_capacity {
+        builder.add_value(CAPACITY_ENR_KEY, &capacity.as_ssz_bytes());
+    }
+    if let Some(rpc_port) = config.enr_rpc_port {
+        builder.add_value(RPC_PORT_ENR_KEY, &rpc_port.as_ssz_bytes());
+    }
+    builder.add_value(PROTOCOL_VERSION_ENR_KEY, &PROTOCOL_VERSION.as_ssz_bytes());
+
     builder
         .build(enr_key)
         .map_err(|e| format!("Could not build Local ENR: {:?}", e))
@@ -115,6 +217,15 @@ fn compare_enr(local_enr: &Enr, disk_enr: &Enr) -> bool {
         && local_enr.tcp() == disk_enr.tcp()
         // take preference over disk udp port if one is not specified
         && (local_enr.udp().is_none() || local_enr.udp() == disk_enr.udp())
+        // storage fields must match
+        && [
+            SHARD_CONFIG_ENR_KEY,
+            CAPACITY_ENR_KEY,
+            RPC_PORT_ENR_KEY,
+            PROTOCOL_VERSION_ENR_KEY,
+        ]
+        .iter()
+        .all(|key| local_enr.get(key) == disk_enr.get(key))
 }
 
 /// Loads enr from the given directory
@@ -150,3 +261,85 @@ pub fn save_enr_to_disk(dir: &Path, enr: &Enr) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_test_enr(shard_config: ShardConfig, enr_capacity: Option<u64>) -> Enr {
+        let config = NetworkConfig {
+            shard_config,
+            enr_capacity,
+            ..Default::default()
+        };
+        let key = CombinedKey::generate_secp256k1();
+        build_enr(&key, &config).unwrap()
+    }
+
+    #[test]
+    fn test_storage_enr() {
+        let shard = ShardConfig::new(1, 4).unwrap();
+        let enr = build_test_enr(shard, Some(1024));
+        assert_eq!(enr.shard_config(), Some(shard));
+        assert_eq!(enr.capacity(), Some(1024));
+
+        assert!(enr.intersect_shard(&ShardConfig::default()));
+        assert!(enr.intersect_shard(&ShardConfig::new(1, 2).unwrap()));
+        assert!(enr.intersect_shard(&ShardConfig::new(5, 8).unwrap()));
+        assert!(!enr.intersect_shard(&ShardConfig::new(0, 2).unwrap()));
+        assert!(!enr.intersect_shard(&ShardConfig::new(2, 4).unwrap()));
+
+        // nodes without shard config store all the data
+        let enr = EnrBuilder::new("v4")
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        assert_eq!(enr.shard_config(), None);
+        assert_eq!(enr.capacity(), None);
+        assert_eq!(enr.rpc_port(), None);
+        assert_eq!(enr.protocol_version(), None);
+        assert!(enr.intersect_shard(&ShardConfig::new(2, 4).unwrap()));
+    }
+
+    #[test]
+    fn test_enr_update() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut enr = build_enr(&key, &NetworkConfig::default()).unwrap();
+        assert_eq!(enr.protocol_version(), Some(PROTOCOL_VERSION));
+        assert_eq!(enr.rpc_port(), None);
+
+        let shard = ShardConfig::new(1, 4).unwrap();
+        let update = EnrUpdate {
+            shard_config: Some(shard),
+            rpc_port: Some(5678),
+            ..Default::default()
+        };
+        let seq = enr.seq();
+        for (key_name, value) in update.fields().unwrap() {
+            enr.insert(key_name, &value, &key).unwrap();
+        }
+        assert!(enr.seq() > seq);
+        assert_eq!(enr.shard_config(), Some(shard));
+        assert_eq!(enr.rpc_port(), Some(5678));
+        assert_eq!(enr.capacity(), None);
+        assert_eq!(enr.protocol_version(), Some(PROTOCOL_VERSION));
+
+        // the RPC service is no longer served
+        let update = EnrUpdate {
+            rpc_port: Some(0),
+            ..Default::default()
+        };
+        for (key_name, value) in update.fields().unwrap() {
+            enr.insert(key_name, &value, &key).unwrap();
+        }
+        assert_eq!(enr.rpc_port(), None);
+
+        let update = EnrUpdate {
+            shard_config: Some(ShardConfig {
+                shard_id: 4,
+                num_shard: 4,
+            }),
+            ..Default::default()
+        };
+        assert!(update.fields().is_err());
+    }
+}
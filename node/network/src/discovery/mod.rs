@@ -5,12 +5,14 @@
 
 pub(crate) mod enr;
 pub mod enr_ext;
+mod persisted_peers;
 
 use crate::metrics;
 use crate::{error, Enr, NetworkConfig, NetworkGlobals};
 use discv5::{enr::NodeId, Discv5, Discv5Event};
 pub use enr::{
     build_enr, create_enr_builder_from_config, load_enr_from_disk, use_or_load_enr, CombinedKey,
+    EnrUpdate, StorageEnr,
 };
 pub use enr_ext::{peer_id_to_node_id, CombinedKeyExt, EnrExt};
 pub use libp2p::core::identity::{Keypair, PublicKey};
@@ -25,6 +27,8 @@ pub use libp2p::{
     },
 };
 use lru::LruCache;
+use persisted_peers::PersistedPeer;
+use shared_types::ShardConfig;
 use std::{
     collections::HashMap,
     net::{IpAddr, SocketAddr},
@@ -56,7 +60,8 @@ pub enum DiscoveryEvent {
 
 #[derive(Debug, Clone, PartialEq)]
 enum QueryType {
-    /// We are searching for more peers without ENR or time constraints.
+    /// We are searching for more peers that store data of the local shard, without time
+    /// constraints.
     FindPeers,
 }
 
@@ -102,6 +107,9 @@ pub struct Discovery {
     /// A collection of network constants that can be read from other threads.
     network_globals: Arc<NetworkGlobals>,
 
+    /// The shard of the local node, which is used to filter discovered peers.
+    shard_config: ShardConfig,
+
     /// Indicates if we are actively searching for peers. We only allow a single FindPeers query at
     /// a time, regardless of the query concurrency.
     find_peer_active: bool,
@@ -112,6 +120,9 @@ pub struct Discovery {
     /// The discv5 event stream.
     event_stream: EventStream,
 
+    /// Peers persisted by the last run to dial once discovery started.
+    persisted_peers: Option<HashMap<PeerId, Option<Instant>>>,
+
     /// Indicates if the discovery service has been started. When the service is disabled, this is
     /// always false.
     pub started: bool,
@@ -139,6 +150,10 @@ impl Discovery {
             udp = ?local_enr.udp(),
             tcp = ?local_enr.tcp(),
             udp4_socket = ?local_enr.udp_socket(),
+            shard = ?local_enr.shard_config(),
+            capacity = ?local_enr.capacity(),
+            rpc_port = ?local_enr.rpc_port(),
+            protocol_version = ?local_enr.protocol_version(),
             "ENR Initialised",
         );
 
@@ -150,6 +165,14 @@ impl Discovery {
         let mut discv5 = Discv5::new(local_enr, enr_key, config.discv5_config.clone())
             .map_err(|e| format!("Discv5 service failed. Error: {:?}", e))?;
 
+        // drop the unsolicited packets from filtered IPs, reading the latest IP lists
+        let globals = network_globals.clone();
+        let ip_filter: discv5::IpFilter =
+            Arc::new(move |ip: &IpAddr| globals.ip_filter.is_allowed(ip));
+        discv5.set_ip_filter(Some(ip_filter));
+
+        let mut cached_enrs = LruCache::new(50);
+
         // Add bootnodes to routing table
         for bootnode_enr in config.boot_nodes_enr.clone() {
             debug!(
@@ -170,6 +193,29 @@ impl Discovery {
             });
         }
 
+        // Add the peers persisted by the last run to routing table, and dial the best ones
+        let persisted_peers = if !config.disable_discovery {
+            let peers = persisted_peers::load_peers_from_disk(&config.network_dir);
+            info!(num_peers = %peers.len(), "Loaded persisted peers");
+
+            let mut to_dial = HashMap::new();
+            for peer in peers {
+                let peer_id = peer.enr.peer_id();
+                if to_dial.len() < config.target_peers {
+                    cached_enrs.put(peer_id, peer.enr.clone());
+                    to_dial.insert(peer_id, None);
+                }
+
+                if let Err(e) = discv5.add_enr(peer.enr) {
+                    debug!(%peer_id, error = %e, "Could not add persisted peer to the local routing table");
+                }
+            }
+
+            Some(to_dial)
+        } else {
+            None
+        };
+
         // Start the discv5 service and obtain an event stream
         let event_stream = if !config.disable_discovery {
             discv5
@@ -231,12 +277,14 @@ impl Discovery {
         }
 
         Ok(Self {
-            cached_enrs: LruCache::new(50),
+            cached_enrs,
             network_globals,
+            shard_config: config.shard_config,
             find_peer_active: false,
             active_queries: FuturesUnordered::new(),
             discv5,
             event_stream,
+            persisted_peers,
             started: !config.disable_discovery,
             enr_dir,
         })
@@ -352,6 +400,48 @@ impl Discovery {
         Ok(())
     }
 
+    /// Updates the remaining storage capacity advertised in the local ENR.
+    pub fn update_enr_capacity(&mut self, capacity: u64) -> Result<(), String> {
+        self.update_enr(&EnrUpdate {
+            capacity: Some(capacity),
+            ..Default::default()
+        })?;
+        Ok(())
+    }
+
+    /// Updates the storage fields of the local ENR, and returns whether any field changed.
+    ///
+    /// The sequence number is increased upon changes, so that peers fetch the new record when
+    /// they ping the local node next time.
+    pub fn update_enr(&mut self, update: &EnrUpdate) -> Result<bool, String> {
+        let local_enr = self.discv5.local_enr();
+        let fields = update.fields()?;
+
+        let mut changed = false;
+        for (key, value) in fields {
+            if local_enr.get(key) == Some(value.as_slice()) {
+                continue;
+            }
+            self.discv5
+                .enr_insert(key, &value)
+                .map_err(|e| format!("{:?}", e))?;
+            changed = true;
+        }
+
+        // peers to discover depend on the local shard
+        if let Some(shard_config) = update.shard_config {
+            self.shard_config = shard_config;
+        }
+
+        if changed {
+            // replace the global version
+            *self.network_globals.local_enr.write() = self.discv5.local_enr();
+            // persist modified enr to disk
+            enr::save_enr_to_disk(Path::new(&self.enr_dir), &self.local_enr());
+        }
+        Ok(changed)
+    }
+
     // Bans a peer and it's associated seen IP addresses.
     pub fn ban_peer(&mut self, peer_id: &PeerId, ip_addresses: Vec<IpAddr>) {
         // first try and convert the peer_id to a node_id.
@@ -390,21 +480,54 @@ impl Discovery {
         self.cached_enrs.pop(peer_id);
     }
 
+    /// Persists the known peers in good standing along with their scores, which are loaded upon
+    /// the next start.
+    fn persist_peers(&self) {
+        let peers: Vec<PersistedPeer> = self
+            .network_globals
+            .peers
+            .read()
+            .peers()
+            .filter(|(_, info)| !info.is_banned() && info.score().score() >= 0.0)
+            .filter_map(|(peer_id, info)| {
+                let enr = info
+                    .enr()
+                    .or_else(|| self.cached_enrs.peek(peer_id))?
+                    .clone();
+                Some(PersistedPeer {
+                    enr,
+                    score: info.score().score(),
+                })
+            })
+            .collect();
+
+        persisted_peers::save_peers_to_disk(Path::new(&self.enr_dir), peers);
+    }
+
     /* Internal Functions */
 
     /// Search for a specified number of new peers using the underlying discovery mechanism.
     ///
-    /// This can optionally search for peers for a given predicate. Regardless of the predicate
-    /// given, this will only search for peers on the same enr_fork_id as specified in the local
-    /// ENR.
+    /// Only peers whose shard intersects with the local shard are returned, so that sharded nodes
+    /// find the peers to sync files with. Peers without shard config store all the data.
     fn start_query(&mut self, query: QueryType, target_peers: usize) {
         // Generate a random target node id.
         let random_node = NodeId::random();
 
+        let shard_config = self.shard_config;
+        let globals = self.network_globals.clone();
+        let predicate = move |enr: &Enr| {
+            let ip_allowed =
+                |ip: Option<IpAddr>| ip.map_or(true, |ip| globals.ip_filter.is_allowed(&ip));
+            enr.intersect_shard(&shard_config)
+                && ip_allowed(enr.ip().map(IpAddr::from))
+                && ip_allowed(enr.ip6().map(IpAddr::from))
+        };
+
         // Build the future
         let query_future = self
             .discv5
-            .find_node_predicate(random_node, Box::new(|_| true), target_peers)
+            .find_node_predicate(random_node, Box::new(predicate), target_peers)
             .map(|v| QueryResult {
                 query_type: query,
                 result: v,
@@ -458,6 +581,12 @@ impl Discovery {
     }
 }
 
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.persist_peers();
+    }
+}
+
 /* NetworkBehaviour Implementation */
 
 impl NetworkBehaviour for Discovery {
@@ -526,6 +655,13 @@ impl NetworkBehaviour for Discovery {
             return Poll::Pending;
         }
 
+        // Dial the persisted peers first
+        if let Some(peers) = self.persisted_peers.take() {
+            if !peers.is_empty() {
+                return Poll::Ready(NBAction::GenerateEvent(DiscoveryEvent::QueryResult(peers)));
+            }
+        }
+
         // Drive the queries and return any results from completed queries
         if let Some(results) = self.poll_queries(cx) {
             // return the result to the peer manager
@@ -0,0 +1,103 @@
+//! Persistence of known good peers, so that a restarted node reconnects to them right away
+//! instead of waiting for peers discovered from the boot nodes.
+
+use crate::Enr;
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Persisted peers storage filename.
+pub const PEERS_FILENAME: &str = "peers.dat";
+
+/// Maximum number of peers persisted.
+pub const MAX_PERSISTED_PEERS: usize = 64;
+
+/// A known peer along with its score when persisted.
+#[derive(Debug, Clone)]
+pub struct PersistedPeer {
+    pub enr: Enr,
+    pub score: f64,
+}
+
+fn sort_by_score(peers: &mut [PersistedPeer]) {
+    peers.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+}
+
+/// Saves the peers with the highest scores to disk, one peer per line as `<score> <ENR>`.
+pub fn save_peers_to_disk(dir: &Path, mut peers: Vec<PersistedPeer>) {
+    sort_by_score(&mut peers);
+    peers.truncate(MAX_PERSISTED_PEERS);
+
+    let content: String = peers
+        .iter()
+        .map(|peer| format!("{} {}\n", peer.score, peer.enr.to_base64()))
+        .collect();
+
+    let _ = fs::create_dir_all(dir);
+    match fs::write(dir.join(PEERS_FILENAME), content) {
+        Ok(()) => debug!(num_peers = %peers.len(), "Persisted peers written to disk"),
+        Err(e) => warn!(error = %e, "Could not write persisted peers to file"),
+    }
+}
+
+/// Loads the persisted peers from disk in the descending order of scores. Malformed entries are
+/// skipped.
+pub fn load_peers_from_disk(dir: &Path) -> Vec<PersistedPeer> {
+    // the file does not exist upon the first start
+    let content = match fs::read_to_string(dir.join(PEERS_FILENAME)) {
+        Ok(content) => content,
+        Err(_) => return vec![],
+    };
+
+    let mut peers: Vec<PersistedPeer> = content
+        .lines()
+        .filter_map(|line| {
+            let (score, enr) = line.split_once(' ')?;
+            Some(PersistedPeer {
+                enr: Enr::from_str(enr).ok()?,
+                score: score.parse().ok()?,
+            })
+        })
+        .collect();
+
+    sort_by_score(&mut peers);
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::enr::{CombinedKey, EnrBuilder};
+    use std::net::Ipv4Addr;
+
+    fn build_peer(score: f64) -> PersistedPeer {
+        let enr = EnrBuilder::new("v4")
+            .ip(Ipv4Addr::LOCALHOST.into())
+            .tcp(10000)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        PersistedPeer { enr, score }
+    }
+
+    #[test]
+    fn test_save_and_load_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_peers_from_disk(dir.path()).is_empty());
+
+        let mut peers: Vec<PersistedPeer> = (0..MAX_PERSISTED_PEERS + 1)
+            .map(|i| build_peer(i as f64))
+            .collect();
+        peers[0].score = -1.5;
+        save_peers_to_disk(dir.path(), peers.clone());
+
+        let loaded = load_peers_from_disk(dir.path());
+        assert_eq!(loaded.len(), MAX_PERSISTED_PEERS);
+
+        // the peer with the lowest score is dropped
+        let last = peers.last().unwrap();
+        assert_eq!(loaded[0].enr, last.enr);
+        assert_eq!(loaded[0].score, last.score);
+        assert!(loaded.iter().all(|peer| peer.enr != peers[0].enr));
+    }
+}
@@ -0,0 +1,120 @@
+//! Allow and deny lists of IP ranges, which let operators block abusive ranges without restarting
+//! the node. The lists are applied when libp2p connections are established, and to the unsolicited
+//! discv5 packets.
+
+use ipnet::IpNet;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use parking_lot::RwLock;
+use serde_derive::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// IP ranges in CIDR notation, e.g. `10.0.0.0/8`. An IP is allowed if it is in none of the denied
+/// ranges, and the allow list is either empty or has a range containing the IP.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpFilterConfig {
+    pub allow: Vec<IpNet>,
+    pub deny: Vec<IpNet>,
+}
+
+impl IpFilterConfig {
+    pub fn parse(allow: &[String], deny: &[String]) -> Result<Self, String> {
+        let parse_nets = |nets: &[String]| -> Result<Vec<IpNet>, String> {
+            nets.iter()
+                .map(|net| {
+                    net.parse::<IpNet>()
+                        .map_err(|e| format!("Invalid IP range {}: {}", net, e))
+                })
+                .collect()
+        };
+
+        Ok(IpFilterConfig {
+            allow: parse_nets(allow)?,
+            deny: parse_nets(deny)?,
+        })
+    }
+
+    pub fn is_allowed(&self, ip: &IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// IP filter shared by the network components, which could be reloaded at runtime.
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    config: RwLock<IpFilterConfig>,
+}
+
+impl IpFilter {
+    pub fn config(&self) -> IpFilterConfig {
+        self.config.read().clone()
+    }
+
+    /// Replaces the IP lists, which apply to new connections only.
+    pub fn set_config(&self, config: IpFilterConfig) {
+        *self.config.write() = config;
+    }
+
+    pub fn is_allowed(&self, ip: &IpAddr) -> bool {
+        self.config.read().is_allowed(ip)
+    }
+
+    /// Returns true if the IP of the address is allowed, or the address has no IP, e.g. a DNS
+    /// address.
+    pub fn is_multiaddr_allowed(&self, addr: &Multiaddr) -> bool {
+        let ip = addr.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(IpAddr::from(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::from(ip)),
+            _ => None,
+        });
+
+        ip.map_or(true, |ip| self.is_allowed(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_config(allow: &[&str], deny: &[&str]) -> IpFilterConfig {
+        IpFilterConfig {
+            allow: allow.iter().map(|net| net.parse().unwrap()).collect(),
+            deny: deny.iter().map(|net| net.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        let config = IpFilterConfig::default();
+        assert!(config.is_allowed(&ip("1.2.3.4")));
+
+        let config = build_config(&[], &["10.0.0.0/8"]);
+        assert!(!config.is_allowed(&ip("10.1.2.3")));
+        assert!(config.is_allowed(&ip("11.1.2.3")));
+
+        // the deny list takes precedence
+        let config = build_config(&["10.0.0.0/8", "fd00::/8"], &["10.1.0.0/16"]);
+        assert!(config.is_allowed(&ip("10.2.0.1")));
+        assert!(config.is_allowed(&ip("fd00::1")));
+        assert!(!config.is_allowed(&ip("10.1.0.1")));
+        assert!(!config.is_allowed(&ip("192.168.0.1")));
+    }
+
+    #[test]
+    fn test_reload() {
+        let filter = IpFilter::default();
+        let addr: Multiaddr = "/ip4/10.1.2.3/tcp/1234".parse().unwrap();
+        assert!(filter.is_multiaddr_allowed(&addr));
+
+        filter.set_config(build_config(&[], &["10.0.0.0/8"]));
+        assert!(!filter.is_multiaddr_allowed(&addr));
+        assert!(filter.is_multiaddr_allowed(&"/dns4/localhost/tcp/1234".parse().unwrap()));
+    }
+}
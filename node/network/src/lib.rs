@@ -8,8 +8,10 @@ extern crate lazy_static;
 #[macro_use]
 extern crate tracing;
 
+pub mod bandwidth;
 pub mod behaviour;
 mod config;
+pub mod ip_filter;
 
 #[allow(clippy::mutable_key_type)]
 // PeerId in hashmaps are no longer permitted by clippy
@@ -35,6 +37,12 @@ impl From<PeerIdSerialized> for PeerId {
     }
 }
 
+impl From<PeerId> for PeerIdSerialized {
+    fn from(peer_id: PeerId) -> Self {
+        PeerIdSerialized(peer_id)
+    }
+}
+
 impl FromStr for PeerIdSerialized {
     type Err = String;
 
@@ -70,10 +78,12 @@ pub use crate::types::{error, Enr, GossipTopic, NetworkGlobals, PubsubMessage};
 
 pub use prometheus_client;
 
+pub use bandwidth::{BandwidthConfig, BandwidthLimiter};
 pub use behaviour::{BehaviourEvent, Gossipsub, PeerRequestId, Request, Response};
 pub use config::Config as NetworkConfig;
-pub use discovery::{CombinedKeyExt, EnrExt};
+pub use discovery::{CombinedKeyExt, EnrExt, EnrUpdate, StorageEnr};
 pub use discv5;
+pub use ip_filter::{IpFilter, IpFilterConfig};
 pub use libp2p;
 pub use libp2p::bandwidth::BandwidthSinks;
 pub use libp2p::core::identity::{error::SigningError, Keypair, PublicKey};
@@ -84,6 +94,7 @@ pub use metrics::scrape_discovery_metrics;
 pub use peer_manager::{
     peerdb::client::Client,
     peerdb::score::{PeerAction, ReportSource},
+    peerdb::stats::PeerStats,
     peerdb::PeerDB,
     ConnectionDirection, PeerConnectionStatus, PeerInfo, PeerManager, SyncInfo, SyncStatus,
 };
@@ -99,6 +110,10 @@ pub enum RequestId {
 #[derive(Debug, Clone, Copy)]
 pub enum SyncId {
     SerialSync { tx_seq: u64 },
+    Audit { tx_seq: u64 },
+    Seed { tx_seq: u64, attempts: u32 },
+    Recovery { chunk_index: u64 },
+    Provisional { tx_seq: u64 },
 }
 
 /// Types of messages that the network service can receive.
@@ -142,4 +157,8 @@ pub enum NetworkMessage {
     DialPeer { address: Multiaddr, peer_id: PeerId },
     /// Notify that new file stored in db.
     AnnounceLocalFile { tx_seq: u64 },
+    /// Say goodbye to all the connected peers, since the node is shutting down.
+    DisconnectAll,
+    /// Update the storage fields of the local ENR, which are republished through discovery.
+    UpdateEnr { update: EnrUpdate },
 }
@@ -22,6 +22,10 @@ lazy_static! {
         "libp2p_peer_disconnect_event_total",
         "Count of libp2p peer disconnect events"
     );
+    pub static ref PEER_UNRESPONSIVE_COUNT: Result<IntCounter> = try_create_int_counter(
+        "libp2p_peer_unresponsive_total",
+        "Count of peers disconnected for not responding to ping in time"
+    );
     pub static ref DISCOVERY_SENT_BYTES: Result<IntGauge> = try_create_int_gauge(
         "discovery_sent_bytes",
         "The number of bytes sent in discovery"
@@ -69,6 +73,16 @@ lazy_static! {
         "RPC requests total",
         &["type"]
     );
+    pub static ref TOTAL_RPC_BYTES_PER_CLIENT: Result<IntCounterVec> = try_create_int_counter_vec(
+        "libp2p_rpc_bytes_per_client",
+        "Bytes of RPC messages sent and received per client, before compression",
+        &["client", "direction"]
+    );
+    pub static ref RPC_RESPONSE_LATENCY_PER_CLIENT: Result<HistogramVec> = try_create_histogram_vec(
+        "libp2p_rpc_response_latency_per_client",
+        "Time to receive the first response of an RPC request per client",
+        &["client"]
+    );
     pub static ref PEER_ACTION_EVENTS_PER_CLIENT: Result<IntCounterVec> =
         try_create_int_counter_vec(
             "libp2p_peer_actions_per_client",
@@ -1,3 +1,6 @@
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+
 /// The time in seconds between re-status's peers.
 pub const DEFAULT_STATUS_INTERVAL: u64 = 300;
 
@@ -7,6 +10,9 @@ pub const DEFAULT_PING_INTERVAL_OUTBOUND: u64 = 15;
 /// Default interval for inbound connections.
 pub const DEFAULT_PING_INTERVAL_INBOUND: u64 = 20;
 
+/// Default time in seconds to wait for a PONG before the peer is regarded as unresponsive.
+pub const DEFAULT_PING_TIMEOUT: u64 = 10;
+
 /// Default number of peers to connect to.
 pub const DEFAULT_TARGET_PEERS: usize = 50;
 
@@ -20,6 +26,13 @@ pub struct Config {
     pub metrics_enabled: bool,
     /// Target number of peers to connect to.
     pub target_peer_count: usize,
+    /// Maximum number of connected peers, derived from `target_peer_count` if not specified.
+    pub max_peer_count: Option<usize>,
+    /// Maximum number of inbound connected peers, derived from the maximum number of peers if not
+    /// specified.
+    pub max_inbound_peer_count: Option<usize>,
+    /// Addresses of the trusted peers, which are dialed whenever disconnected.
+    pub trusted_peers: HashMap<PeerId, Vec<Multiaddr>>,
 
     /* RPC related configurations */
     /// Time in seconds between status requests sent to peers.
@@ -30,6 +43,9 @@ pub struct Config {
     pub ping_interval_inbound: u64,
     /// Interval between PING events for peers dialed by us.
     pub ping_interval_outbound: u64,
+    /// Time in seconds to wait for a PONG, after which the peer is disconnected instead of
+    /// waiting for the TCP timeout, e.g. the connection is half open.
+    pub ping_timeout: u64,
 }
 
 impl Default for Config {
@@ -38,9 +54,13 @@ impl Default for Config {
             discovery_enabled: true,
             metrics_enabled: false,
             target_peer_count: DEFAULT_TARGET_PEERS,
+            max_peer_count: None,
+            max_inbound_peer_count: None,
+            trusted_peers: HashMap::new(),
             status_interval: DEFAULT_STATUS_INTERVAL,
             ping_interval_inbound: DEFAULT_PING_INTERVAL_INBOUND,
             ping_interval_outbound: DEFAULT_PING_INTERVAL_OUTBOUND,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
         }
     }
 }
@@ -1,12 +1,14 @@
 //! Implementation of Lighthouse's peer management system.
 
+use crate::discovery::enr::StorageEnr;
 use crate::rpc::{GoodbyeReason, Protocol, RPCError, RPCResponseErrorCode};
 use crate::{error, metrics, Gossipsub};
 use crate::{NetworkGlobals, PeerId};
 use discv5::Enr;
-use hashset_delay::HashSetDelay;
+use hashset_delay::{HashMapDelay, HashSetDelay};
 use libp2p::identify::IdentifyInfo;
 use peerdb::{client::ClientKind, BanOperation, BanResult, ScoreUpdateResult};
+use shared_types::ShardConfig;
 use smallvec::SmallVec;
 use std::{
     sync::Arc,
@@ -53,6 +55,18 @@ pub const MIN_OUTBOUND_ONLY_FACTOR: f32 = 0.2;
 /// dialing priority peers we need for validator duties.
 pub const PRIORITY_PEER_EXCESS: f32 = 0.2;
 
+/// The default maximum number of peers we allow to connect to us. This is `target_peers` * (1 +
+/// PEER_EXCESS_FACTOR)
+pub fn default_max_peers(target_peers: usize) -> usize {
+    (target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as usize
+}
+
+/// The default maximum number of inbound peers, which reserves `target_peers` *
+/// MIN_OUTBOUND_ONLY_FACTOR of `max_peers` for outbound peers.
+pub fn default_max_inbound_peers(target_peers: usize, max_peers: usize) -> usize {
+    max_peers.saturating_sub((target_peers as f32 * MIN_OUTBOUND_ONLY_FACTOR).ceil() as usize)
+}
+
 /// The main struct that handles peer's reputation and connection status.
 pub struct PeerManager {
     /// Storage of network globals to access the `PeerDB`.
@@ -65,10 +79,19 @@ pub struct PeerManager {
     outbound_ping_peers: HashSetDelay<PeerId>,
     /// A collection of peers awaiting to be Status'd.
     status_peers: HashSetDelay<PeerId>,
+    /// Peers Ping'd but not responded yet, along with the time the Ping was sent. The peers are
+    /// disconnected if not responded in time.
+    awaiting_pong: HashMapDelay<PeerId, Instant>,
     /// The target number of peers we would like to connect to.
     target_peers: usize,
+    /// The maximum number of peers we allow to connect to us.
+    max_peers: usize,
+    /// The maximum number of inbound peers we allow to connect to us.
+    max_inbound_peers: usize,
     /// The heartbeat interval to perform routine maintenance.
     heartbeat: tokio::time::Interval,
+    /// Addresses of the trusted peers, which are dialed whenever disconnected.
+    trusted_peers: HashMap<PeerId, Vec<Multiaddr>>,
     /// Keeps track of whether the discovery service is enabled or not.
     discovery_enabled: bool,
     /// Keeps track if the current instance is reporting metrics or not.
@@ -96,6 +119,8 @@ pub enum PeerManagerEvent {
     UnBanned(PeerId, Vec<IpAddr>),
     /// Request the behaviour to discover more peers and the amount of peers to discover.
     DiscoverPeers(usize),
+    /// Request the behaviour to dial a disconnected trusted peer.
+    DialPeer(PeerId),
 }
 
 impl PeerManager {
@@ -108,11 +133,22 @@ impl PeerManager {
             discovery_enabled,
             metrics_enabled,
             target_peer_count,
+            max_peer_count,
+            max_inbound_peer_count,
+            trusted_peers,
             status_interval,
             ping_interval_inbound,
             ping_interval_outbound,
+            ping_timeout,
         } = cfg;
 
+        let max_peers = max_peer_count
+            .unwrap_or_else(|| default_max_peers(target_peer_count))
+            .max(target_peer_count);
+        let max_inbound_peers = max_inbound_peer_count
+            .unwrap_or_else(|| default_max_inbound_peers(target_peer_count, max_peers))
+            .min(max_peers);
+
         // Set up the peer manager heartbeat interval
         let heartbeat = tokio::time::interval(tokio::time::Duration::from_secs(HEARTBEAT_INTERVAL));
 
@@ -122,8 +158,12 @@ impl PeerManager {
             inbound_ping_peers: HashSetDelay::new(Duration::from_secs(ping_interval_inbound)),
             outbound_ping_peers: HashSetDelay::new(Duration::from_secs(ping_interval_outbound)),
             status_peers: HashSetDelay::new(Duration::from_secs(status_interval)),
+            awaiting_pong: HashMapDelay::new(Duration::from_secs(ping_timeout)),
             target_peers: target_peer_count,
+            max_peers,
+            max_inbound_peers,
             heartbeat,
+            trusted_peers,
             discovery_enabled,
             metrics_enabled,
         })
@@ -291,18 +331,15 @@ impl PeerManager {
         self.status_peers.insert(*peer_id);
     }
 
-    /// The maximum number of peers we allow to connect to us. This is `target_peers` * (1 +
-    /// PEER_EXCESS_FACTOR)
+    /// The maximum number of peers we allow to connect to us.
     fn max_peers(&self) -> usize {
-        (self.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as usize
+        self.max_peers
     }
 
-    /// The maximum number of peers we allow when dialing a priority peer (i.e a peer that is
-    /// subscribed to subnets that our validator requires. This is `target_peers` * (1 +
-    /// PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS)
+    /// The maximum number of peers we allow when dialing a priority peer. This is `max_peers` +
+    /// `target_peers` * PRIORITY_PEER_EXCESS
     fn max_priority_peers(&self) -> usize {
-        (self.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS)).ceil()
-            as usize
+        self.max_peers + (self.target_peers as f32 * PRIORITY_PEER_EXCESS).ceil() as usize
     }
 
     /// The minimum number of outbound peers that we reach before we start another discovery query.
@@ -318,8 +355,22 @@ impl PeerManager {
     /// The maximum number of peers that are connected or dialing before we refuse to do another
     /// discovery search for more outbound peers. We can use up to half the priority peer excess allocation.
     fn max_outbound_dialing_peers(&self) -> usize {
-        (self.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS / 2.0)).ceil()
-            as usize
+        self.max_peers + (self.target_peers as f32 * PRIORITY_PEER_EXCESS / 2.0).ceil() as usize
+    }
+
+    /// The number of connected peers that dialed us.
+    fn connected_inbound_peers(&self) -> usize {
+        self.network_globals
+            .peers
+            .read()
+            .connected_peers()
+            .filter(|(_, info)| {
+                matches!(
+                    info.connection_direction(),
+                    Some(ConnectionDirection::Incoming)
+                )
+            })
+            .count()
     }
 
     /* Notifications from the Swarm */
@@ -344,8 +395,10 @@ impl PeerManager {
     /// connections.
     pub fn peer_limit_reached(&self, count_dialing: bool) -> bool {
         if count_dialing {
-            // This is an incoming connection so limit by the standard max peers
+            // This is an incoming connection so limit by the standard max peers and the max
+            // inbound peers
             self.network_globals.connected_or_dialing_peers() >= self.max_peers()
+                || self.connected_inbound_peers() >= self.max_inbound_peers
         } else {
             // We dialed this peer, allow up to max_outbound_dialing_peers
             self.network_globals.connected_peers() >= self.max_outbound_dialing_peers()
@@ -391,6 +444,44 @@ impl PeerManager {
         }
     }
 
+    /// Records the bytes of an RPC message sent to the peer.
+    pub fn on_rpc_sent(&mut self, peer_id: &PeerId, bytes: usize) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            peer_info.stats_mut().on_sent(bytes);
+            metrics::inc_counter_vec_by(
+                &metrics::TOTAL_RPC_BYTES_PER_CLIENT,
+                &[peer_info.client().kind.as_ref(), "sent"],
+                bytes as u64,
+            );
+        }
+    }
+
+    /// Records the bytes of an RPC message received from the peer, along with the latency if the
+    /// message is the first chunk of a response.
+    pub fn on_rpc_received(&mut self, peer_id: &PeerId, bytes: usize, latency: Option<Duration>) {
+        if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+            let client = peer_info.client().kind.as_ref();
+            metrics::inc_counter_vec_by(
+                &metrics::TOTAL_RPC_BYTES_PER_CLIENT,
+                &[client, "received"],
+                bytes as u64,
+            );
+            if let Some(latency) = latency {
+                metrics::observe_timer_vec(
+                    &metrics::RPC_RESPONSE_LATENCY_PER_CLIENT,
+                    &[client],
+                    latency,
+                );
+            }
+
+            let stats = peer_info.stats_mut();
+            stats.on_received(bytes);
+            if let Some(latency) = latency {
+                stats.on_response(latency);
+            }
+        }
+    }
+
     /// An error has occurred in the RPC.
     ///
     /// This adjusts a peer's score based on the error.
@@ -414,6 +505,12 @@ impl PeerManager {
             ],
         );
 
+        if let ConnectionDirection::Outgoing = direction {
+            if let Some(peer_info) = self.network_globals.peers.write().peer_info_mut(peer_id) {
+                peer_info.stats_mut().on_request_failed();
+            }
+        }
+
         // Map this error to a `PeerAction` (if any)
         let peer_action = match err {
             RPCError::IncompleteStream => {
@@ -461,6 +558,15 @@ impl PeerManager {
                     Protocol::Status => PeerAction::LowToleranceError,
                     Protocol::DataByHash => PeerAction::MidToleranceError,
                     Protocol::GetChunks => PeerAction::MidToleranceError,
+                    Protocol::OfferFile => PeerAction::MidToleranceError,
+                    Protocol::GetErasureShard => PeerAction::MidToleranceError,
+                    Protocol::GetPendingChunks => PeerAction::MidToleranceError,
+                },
+                RPCResponseErrorCode::Busy => match direction {
+                    // The peer requested more data than the bandwidth quota allows
+                    ConnectionDirection::Incoming => PeerAction::HighToleranceError,
+                    // The peer is serving other requests, which is not a fault
+                    ConnectionDirection::Outgoing => return,
                 },
             },
             RPCError::SSZDecodeError(_) => PeerAction::Fatal,
@@ -475,6 +581,9 @@ impl PeerManager {
                     Protocol::Status => PeerAction::LowToleranceError,
                     Protocol::DataByHash => return,
                     Protocol::GetChunks => return,
+                    Protocol::OfferFile => return,
+                    Protocol::GetErasureShard => return,
+                    Protocol::GetPendingChunks => return,
                 }
             }
             RPCError::StreamTimeout => match direction {
@@ -489,6 +598,9 @@ impl PeerManager {
                     Protocol::Status => return,
                     Protocol::DataByHash => PeerAction::MidToleranceError,
                     Protocol::GetChunks => PeerAction::MidToleranceError,
+                    Protocol::OfferFile => return,
+                    Protocol::GetErasureShard => PeerAction::MidToleranceError,
+                    Protocol::GetPendingChunks => PeerAction::MidToleranceError,
                 },
             },
             RPCError::NegotiationTimeout => PeerAction::LowToleranceError,
@@ -531,6 +643,9 @@ impl PeerManager {
     pub fn pong_response(&mut self, peer_id: &PeerId, _seq: u64) {
         if let Some(_peer_info) = self.network_globals.peers.read().peer_info(peer_id) {
             // received a pong
+            if let Some(sent_at) = self.awaiting_pong.remove(peer_id) {
+                trace!(%peer_id, rtt = ?sent_at.elapsed(), "Received a pong");
+            }
         } else {
             error!(%peer_id, "Received a PONG from an unknown peer");
         }
@@ -636,6 +751,7 @@ impl PeerManager {
         self.inbound_ping_peers.remove(peer_id);
         self.outbound_ping_peers.remove(peer_id);
         self.status_peers.remove(peer_id);
+        self.awaiting_pong.remove(peer_id);
         self.events.extend(
             purged_peers
                 .into_iter()
@@ -692,6 +808,29 @@ impl PeerManager {
         true
     }
 
+    /// Sends a PING to the peer, which is expected to respond in time.
+    fn ping_peer(&mut self, peer_id: PeerId) {
+        // keep the timeout of the previous PING not responded yet
+        if !self.awaiting_pong.contains_key(&peer_id) {
+            self.awaiting_pong.insert(peer_id, Instant::now());
+        }
+
+        self.events.push(PeerManagerEvent::Ping(peer_id));
+    }
+
+    /// The peer did not respond to the PING in time, e.g. it stopped responding mid-transfer, so
+    /// it is disconnected without waiting for the TCP timeout. The application layer reschedules
+    /// the requests in flight to the peer upon the disconnection.
+    fn on_ping_timeout(&mut self, peer_id: PeerId, sent_at: Instant) {
+        if !self.network_globals.peers.read().is_connected(&peer_id) {
+            return;
+        }
+
+        warn!(%peer_id, elapsed = ?sent_at.elapsed(), "Disconnecting unresponsive peer");
+        metrics::inc_counter(&metrics::PEER_UNRESPONSIVE_COUNT);
+        self.disconnect_peer(peer_id, GoodbyeReason::Fault);
+    }
+
     // Gracefully disconnects a peer without banning them.
     fn disconnect_peer(&mut self, peer_id: PeerId, reason: GoodbyeReason) {
         self.events
@@ -745,36 +884,32 @@ impl PeerManager {
         }
     }
 
+    /// Dials the trusted peers that are neither connected nor being dialed.
+    fn dial_trusted_peers(&mut self) {
+        let peers = self.network_globals.peers.read();
+        for peer_id in self.trusted_peers.keys() {
+            if !peers.is_connected_or_dialing(peer_id) {
+                self.events.push(PeerManagerEvent::DialPeer(*peer_id));
+            }
+        }
+    }
+
     /// Remove excess peers back down to our target values.
     /// This prioritises peers with a good score and uniform distribution of peers across
-    /// subnets.
+    /// shards.
     ///
     /// The logic for the peer pruning is as follows:
     ///
     /// Global rules:
     /// - Always maintain peers we need for a validator duty.
+    /// - Never prune trusted peers.
     /// - Do not prune outbound peers to exceed our outbound target.
     /// - Do not prune more peers than our target peer count.
-    /// - If we have an option to remove a number of peers, remove ones that have the least
-    ///     long-lived subnets.
-    /// - When pruning peers based on subnet count. If multiple peers can be chosen, choose a peer
-    ///     that is not subscribed to a long-lived sync committee subnet.
-    /// - When pruning peers based on subnet count, do not prune a peer that would lower us below the
-    ///     MIN_SYNC_COMMITTEE_PEERS peer count. To keep it simple, we favour a minimum number of sync-committee-peers over
-    ///     uniformity subnet peers. NOTE: We could apply more sophisticated logic, but the code is
-    ///     simpler and easier to maintain if we take this approach. If we are pruning subnet peers
-    ///     below the MIN_SYNC_COMMITTEE_PEERS and maintaining the sync committee peers, this should be
-    ///     fine as subnet peers are more likely to be found than sync-committee-peers. Also, we're
-    ///     in a bit of trouble anyway if we have so few peers on subnets. The
-    ///     MIN_SYNC_COMMITTEE_PEERS
-    ///     number should be set low as an absolute lower bound to maintain peers on the sync
-    ///     committees.
     ///
     /// Prune peers in the following order:
     /// 1. Remove worst scoring peers
-    /// 2. Remove peers that are not subscribed to a subnet (they have less value)
-    /// 3. Remove peers that we have many on any particular subnet
-    /// 4. Randomly remove peers if all the above are satisfied
+    /// 2. Remove peers that we have many on any particular shard, beginning with the worst scores.
+    ///    Peers that do not advertise a shard in ENR are regarded as storing all the data.
     ///
     fn prune_excess_peers(&mut self) {
         // The current number of connected peers.
@@ -799,7 +934,9 @@ impl PeerManager {
                     .read()
                     .worst_connected_peers()
                     .iter()
-                    .filter(|(_, info)| !info.has_future_duty() && $filter(*info))
+                    .filter(|(_, info)| {
+                        !info.has_future_duty() && !info.is_trusted() && $filter(*info)
+                    })
                 {
                     if peers_to_prune.len()
                         >= connected_peer_count.saturating_sub(self.target_peers)
@@ -828,152 +965,54 @@ impl PeerManager {
         // 1. Look through peers that have the worst score (ignoring non-penalized scored peers).
         prune_peers!(|info: &PeerInfo| { info.score().score() < 0.0 });
 
-        // 2. Attempt to remove peers that are not subscribed to a subnet, if we still need to
-        //    prune more.
+        // 2. Remove peers that are too grouped on any given shard. If all peers are on the same
+        //    shard, remove the worst scoring peers.
         if peers_to_prune.len() < connected_peer_count.saturating_sub(self.target_peers) {
-            // prune_peers!(|info: &PeerInfo| { !info.has_long_lived_subnet() });
-            prune_peers!(|_info: &PeerInfo| { true });
-        }
+            // Of our connected peers, build a map from shard -> peers ordered by score.
+            let mut shard_to_peers: HashMap<ShardConfig, Vec<(PeerId, bool)>> = HashMap::new();
+            for (peer_id, info) in self.network_globals.peers.read().worst_connected_peers() {
+                // Ignore peers we are already pruning
+                if peers_to_prune.contains(peer_id) || info.has_future_duty() || info.is_trusted() {
+                    continue;
+                }
+
+                let shard_config = info
+                    .enr()
+                    .and_then(|enr| enr.shard_config())
+                    .unwrap_or_default();
+                shard_to_peers
+                    .entry(shard_config)
+                    .or_default()
+                    .push((*peer_id, info.is_outbound_only()));
+            }
+
+            while peers_to_prune.len() < connected_peer_count.saturating_sub(self.target_peers) {
+                let peers_on_shard = match shard_to_peers
+                    .values_mut()
+                    .filter(|peers| !peers.is_empty())
+                    .max_by_key(|peers| peers.len())
+                {
+                    Some(peers) => peers,
+                    // If there are no peers left to prune exit.
+                    None => break,
+                };
+
+                let (candidate_peer, outbound_only) = peers_on_shard.remove(0);
+
+                // Ensure we don't remove too many outbound peers
+                if outbound_only {
+                    if self.target_outbound_peers() + outbound_peers_pruned
+                        < connected_outbound_peer_count
+                    {
+                        outbound_peers_pruned += 1;
+                    } else {
+                        continue;
+                    }
+                }
 
-        // 3. and 4. Remove peers that are too grouped on any given subnet. If all subnets are
-        //    uniformly distributed, remove random peers.
-        // if peers_to_prune.len() < connected_peer_count.saturating_sub(self.target_peers) {
-        //     // Of our connected peers, build a map from subnet_id -> Vec<(PeerId, PeerInfo)>
-        //     let mut subnet_to_peer: HashMap<Subnet, Vec<(PeerId, PeerInfo)>> =
-        //         HashMap::new();
-        //     // These variables are used to track if a peer is in a long-lived sync-committee as we
-        //     // may wish to retain this peer over others when pruning.
-        //     let mut sync_committee_peer_count: HashMap<SyncSubnetId, u64> = HashMap::new();
-        //     let peer_to_sync_committee: HashMap<
-        //         PeerId,
-        //         std::collections::HashSet<SyncSubnetId>,
-        //     > = HashMap::new();
-
-        //     for (peer_id, _info) in self.network_globals.peers.read().connected_peers() {
-        //         // Ignore peers we are already pruning
-        //         if peers_to_prune.contains(peer_id) {
-        //             continue;
-        //         }
-
-        //         // Count based on long-lived subnets not short-lived subnets
-        //         // NOTE: There are only 4 sync committees. These are likely to be denser than the
-        //         // subnets, so our priority here to make the subnet peer count uniform, ignoring
-        //         // the dense sync committees.
-        //         for subnet in info.long_lived_subnets() {
-        //             match subnet {
-        //                 Subnet::Attestation(_) => {
-        //                     subnet_to_peer
-        //                         .entry(subnet)
-        //                         .or_insert_with(Vec::new)
-        //                         .push((*peer_id, info.clone()));
-        //                 }
-        //                 Subnet::SyncCommittee(id) => {
-        //                     *sync_committee_peer_count.entry(id).or_default() += 1;
-        //                     peer_to_sync_committee
-        //                         .entry(*peer_id)
-        //                         .or_default()
-        //                         .insert(id);
-        //                 }
-        //             }
-        //         }
-        //     }
-
-        //     // Add to the peers to prune mapping
-        //     while peers_to_prune.len() < connected_peer_count.saturating_sub(self.target_peers) {
-        //         if let Some((_, peers_on_subnet)) = subnet_to_peer
-        //             .iter_mut()
-        //             .max_by_key(|(_, peers)| peers.len())
-        //         {
-        //             // and the subnet still contains peers
-        //             if !peers_on_subnet.is_empty() {
-        //                 // Order the peers by the number of subnets they are long-lived
-        //                 // subscribed too, shuffle equal peers.
-        //                 peers_on_subnet.shuffle(&mut rand::thread_rng());
-        //                 // peers_on_subnet.sort_by_key(|(_, info)| info.long_lived_subnet_count());
-
-        //                 // Try and find a candidate peer to remove from the subnet.
-        //                 // We ignore peers that would put us below our target outbound peers
-        //                 // and we currently ignore peers that would put us below our
-        //                 // sync-committee threshold, if we can avoid it.
-
-        //                 let mut removed_peer_index = None;
-        //                 for (index, (candidate_peer, info)) in peers_on_subnet.iter().enumerate() {
-        //                     // Ensure we don't remove too many outbound peers
-        //                     if info.is_outbound_only() {
-        //                         if self.target_outbound_peers()
-        //                             < connected_outbound_peer_count
-        //                                 .saturating_sub(outbound_peers_pruned)
-        //                         {
-        //                             outbound_peers_pruned += 1;
-        //                         } else {
-        //                             // Restart the main loop with the outbound peer removed from
-        //                             // the list. This will lower the peers per subnet count and
-        //                             // potentially a new subnet may be chosen to remove peers. This
-        //                             // can occur recursively until we have no peers left to choose
-        //                             // from.
-        //                             continue;
-        //                         }
-        //                     }
-
-        //                     // Check the sync committee
-        //                     if let Some(subnets) = peer_to_sync_committee.get(candidate_peer) {
-        //                         // The peer is subscribed to some long-lived sync-committees
-        //                         // Of all the subnets this peer is subscribed too, the minimum
-        //                         // peer count of all of them is min_subnet_count
-        //                         if let Some(min_subnet_count) = subnets
-        //                             .iter()
-        //                             .filter_map(|v| sync_committee_peer_count.get(v).copied())
-        //                             .min()
-        //                         {
-        //                             // If the minimum count is our target or lower, we
-        //                             // shouldn't remove this peer, because it drops us lower
-        //                             // than our target
-        //                             if min_subnet_count <= MIN_SYNC_COMMITTEE_PEERS {
-        //                                 // Do not drop this peer in this pruning interval
-        //                                 continue;
-        //                             }
-        //                         }
-        //                     }
-
-        //                     // This peer is suitable to be pruned
-        //                     removed_peer_index = Some(index);
-        //                     break;
-        //                 }
-
-        //                 // If we have successfully found a candidate peer to prune, prune it,
-        //                 // otherwise all peers on this subnet should not be removed due to our
-        //                 // outbound limit or min_subnet_count. In this case, we remove all
-        //                 // peers from the pruning logic and try another subnet.
-        //                 if let Some(index) = removed_peer_index {
-        //                     let (candidate_peer, _) = peers_on_subnet.remove(index);
-        //                     // Remove pruned peers from other subnet counts
-        //                     for subnet_peers in subnet_to_peer.values_mut() {
-        //                         subnet_peers.retain(|(peer_id, _)| peer_id != &candidate_peer);
-        //                     }
-        //                     // Remove pruned peers from all sync-committee counts
-        //                     if let Some(known_sync_committes) =
-        //                         peer_to_sync_committee.get(&candidate_peer)
-        //                     {
-        //                         for sync_committee in known_sync_committes {
-        //                             if let Some(sync_committee_count) =
-        //                                 sync_committee_peer_count.get_mut(sync_committee)
-        //                             {
-        //                                 *sync_committee_count =
-        //                                     sync_committee_count.saturating_sub(1);
-        //                             }
-        //                         }
-        //                     }
-        //                     peers_to_prune.insert(candidate_peer);
-        //                 } else {
-        //                     peers_on_subnet.clear();
-        //                 }
-        //                 continue;
-        //             }
-        //         }
-        //         // If there are no peers left to prune exit.
-        //         break;
-        //     }
-        // }
+                peers_to_prune.insert(candidate_peer);
+            }
+        }
 
         // Disconnect the pruned peers.
         for peer_id in peers_to_prune {
@@ -998,6 +1037,9 @@ impl PeerManager {
         // Optionally run a discovery query if we need more peers.
         self.maintain_peer_count(0);
 
+        // Reconnect to the trusted peers.
+        self.dial_trusted_peers();
+
         // Cleans up the connection state of dialing peers.
         // Libp2p dials peer-ids, but sometimes the response is from another peer-id or libp2p
         // returns dial errors without a peer-id attached. This function reverts peers that have a
@@ -1118,6 +1160,15 @@ mod tests {
         PeerManager::new(config, Arc::new(globals)).await.unwrap()
     }
 
+    fn build_enr(shard_config: ShardConfig) -> Enr {
+        let config = crate::NetworkConfig {
+            shard_config,
+            ..Default::default()
+        };
+        let key = crate::discovery::enr::CombinedKey::generate_secp256k1();
+        crate::discovery::enr::build_enr(&key, &config).unwrap()
+    }
+
     #[tokio::test]
     async fn test_peer_manager_disconnects_correctly_during_heartbeat() {
         let mut peer_manager = build_peer_manager(3).await;
@@ -1187,6 +1238,78 @@ mod tests {
         assert_eq!(peer_manager.network_globals.connected_or_dialing_peers(), 3);
     }
 
+    #[tokio::test]
+    async fn test_peer_manager_trusted_peers() {
+        use libp2p::swarm::NetworkBehaviour;
+
+        let trusted_peer = PeerId::random();
+        let disconnected_trusted_peer = PeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/1234".parse().unwrap();
+
+        let config = config::Config {
+            target_peer_count: 1,
+            discovery_enabled: false,
+            trusted_peers: HashMap::from([
+                (trusted_peer, vec![]),
+                (disconnected_trusted_peer, vec![address.clone()]),
+            ]),
+            ..Default::default()
+        };
+        let globals = NetworkGlobals::new_test_globals();
+        *globals.peers.write() = peerdb::PeerDB::new(vec![trusted_peer, disconnected_trusted_peer]);
+        let mut peer_manager = PeerManager::new(config, Arc::new(globals)).await.unwrap();
+
+        let peer = PeerId::random();
+        peer_manager.inject_connect_ingoing(&trusted_peer, "/ip4/0.0.0.0".parse().unwrap(), None);
+        peer_manager.inject_connect_ingoing(&peer, "/ip4/0.0.0.0".parse().unwrap(), None);
+
+        peer_manager.heartbeat();
+
+        // the trusted peer is never pruned
+        let peers = peer_manager.network_globals.peers.read();
+        assert!(peers.is_connected(&trusted_peer));
+        assert!(!peers.is_connected(&peer));
+        drop(peers);
+
+        // the disconnected trusted peer is dialed with the configured address
+        assert!(peer_manager.events.iter().any(|event| matches!(
+            event,
+            PeerManagerEvent::DialPeer(peer_id) if *peer_id == disconnected_trusted_peer
+        )));
+        assert_eq!(
+            peer_manager.addresses_of_peer(&disconnected_trusted_peer),
+            vec![address]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_peer_manager_disconnects_unresponsive_peers() {
+        let mut peer_manager = build_peer_manager(3).await;
+
+        let responsive = PeerId::random();
+        let unresponsive = PeerId::random();
+        for peer_id in [responsive, unresponsive] {
+            peer_manager.inject_connect_outgoing(&peer_id, "/ip4/0.0.0.0".parse().unwrap(), None);
+            peer_manager.ping_peer(peer_id);
+        }
+
+        peer_manager.pong_response(&responsive, 1);
+        assert!(!peer_manager.awaiting_pong.contains_key(&responsive));
+        assert!(peer_manager.awaiting_pong.contains_key(&unresponsive));
+
+        // the ping to the unresponsive peer times out
+        let sent_at = peer_manager.awaiting_pong.remove(&unresponsive).unwrap();
+        peer_manager.on_ping_timeout(unresponsive, sent_at);
+
+        assert!(peer_manager.events.iter().any(|event| matches!(
+            event,
+            PeerManagerEvent::DisconnectPeer(peer_id, GoodbyeReason::Fault) if *peer_id == unresponsive
+        )));
+        let peers = peer_manager.network_globals.peers.read();
+        assert!(peers.is_connected(&responsive));
+        assert!(!peers.is_connected(&unresponsive));
+    }
+
     #[tokio::test]
     async fn test_peer_manager_not_enough_outbound_peers_no_panic_during_heartbeat() {
         let mut peer_manager = build_peer_manager(20).await;
@@ -1326,4 +1449,70 @@ mod tests {
         // the number of connected peers updates and we will not remove too many peers.
         assert_eq!(peer_manager.network_globals.connected_or_dialing_peers(), 3);
     }
+
+    #[tokio::test]
+    async fn test_peer_manager_prunes_peers_on_crowded_shard() {
+        let mut peer_manager = build_peer_manager(3).await;
+
+        // Connect 4 peers on shard 0 and 1 peer on shard 1 of 2 shards.
+        // The peer on shard 1 has the lowest score.
+        let shard0_peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let shard1_peer = PeerId::random();
+
+        for peer in shard0_peers.iter() {
+            peer_manager.inject_connect_ingoing(
+                peer,
+                "/ip4/0.0.0.0".parse().unwrap(),
+                Some(build_enr(ShardConfig::new(0, 2).unwrap())),
+            );
+            peer_manager
+                .network_globals
+                .peers
+                .write()
+                .peer_info_mut(peer)
+                .unwrap()
+                .add_to_score(1.0);
+        }
+        peer_manager.inject_connect_ingoing(
+            &shard1_peer,
+            "/ip4/0.0.0.0".parse().unwrap(),
+            Some(build_enr(ShardConfig::new(1, 2).unwrap())),
+        );
+
+        peer_manager.heartbeat();
+
+        // Check that the peers are pruned from the crowded shard.
+        assert_eq!(peer_manager.network_globals.connected_or_dialing_peers(), 3);
+        assert!(peer_manager
+            .network_globals
+            .peers
+            .read()
+            .is_connected(&shard1_peer));
+    }
+
+    #[tokio::test]
+    async fn test_peer_manager_inbound_peer_limit() {
+        let config = config::Config {
+            target_peer_count: 3,
+            max_peer_count: Some(5),
+            max_inbound_peer_count: Some(2),
+            discovery_enabled: false,
+            ..Default::default()
+        };
+        let globals = NetworkGlobals::new_test_globals();
+        let mut peer_manager = PeerManager::new(config, Arc::new(globals)).await.unwrap();
+
+        for _ in 0..2 {
+            assert!(!peer_manager.peer_limit_reached(true));
+            peer_manager.inject_connect_ingoing(
+                &PeerId::random(),
+                "/ip4/0.0.0.0".parse().unwrap(),
+                None,
+            );
+        }
+
+        // Inbound peers are limited, while outbound peers are allowed until the max peers.
+        assert!(peer_manager.peer_limit_reached(true));
+        assert!(!peer_manager.peer_limit_reached(false));
+    }
 }
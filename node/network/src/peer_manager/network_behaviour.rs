@@ -50,7 +50,7 @@ impl NetworkBehaviour for PeerManager {
             match self.inbound_ping_peers.poll_next_unpin(cx) {
                 Poll::Ready(Some(Ok(peer_id))) => {
                     self.inbound_ping_peers.insert(peer_id);
-                    self.events.push(PeerManagerEvent::Ping(peer_id));
+                    self.ping_peer(peer_id);
                 }
                 Poll::Ready(Some(Err(e))) => {
                     error!(error = %e.to_string(), "Failed to check for inbound peers to ping")
@@ -63,7 +63,7 @@ impl NetworkBehaviour for PeerManager {
             match self.outbound_ping_peers.poll_next_unpin(cx) {
                 Poll::Ready(Some(Ok(peer_id))) => {
                     self.outbound_ping_peers.insert(peer_id);
-                    self.events.push(PeerManagerEvent::Ping(peer_id));
+                    self.ping_peer(peer_id);
                 }
                 Poll::Ready(Some(Err(e))) => {
                     error!(error = %e.to_string(), "Failed to check for outbound peers to ping")
@@ -85,6 +85,16 @@ impl NetworkBehaviour for PeerManager {
             }
         }
 
+        loop {
+            match self.awaiting_pong.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok((peer_id, sent_at)))) => self.on_ping_timeout(peer_id, sent_at),
+                Poll::Ready(Some(Err(e))) => {
+                    error!(error = %e.to_string(), "Failed to check for peers not responding to ping")
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+
         if !self.events.is_empty() {
             return Poll::Ready(NetworkBehaviourAction::GenerateEvent(self.events.remove(0)));
         } else {
@@ -96,6 +106,10 @@ impl NetworkBehaviour for PeerManager {
 
     /* Overwritten trait members */
 
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.trusted_peers.get(peer_id).cloned().unwrap_or_default()
+    }
+
     fn inject_connection_established(
         &mut self,
         peer_id: &PeerId,
@@ -114,6 +128,14 @@ impl NetworkBehaviour for PeerManager {
             metrics::check_nat();
         }
 
+        // Check the IP allow and deny lists
+        let address = endpoint.get_remote_address();
+        if !self.network_globals.ip_filter.is_multiaddr_allowed(address) {
+            debug!(%peer_id, %address, "Peer connected from a filtered IP, disconnecting");
+            self.disconnect_peer(*peer_id, GoodbyeReason::BannedIP);
+            return;
+        }
+
         // Check to make sure the peer is not supposed to be banned
         match self.ban_status(peer_id) {
             // TODO: directly emit the ban event?
@@ -143,7 +165,7 @@ impl NetworkBehaviour for PeerManager {
                 .peers
                 .read()
                 .peer_info(peer_id)
-                .map_or(true, |peer| !peer.has_future_duty())
+                .map_or(true, |peer| !peer.has_future_duty() && !peer.is_trusted())
         {
             // Gracefully disconnect the peer.
             self.disconnect_peer(*peer_id, GoodbyeReason::TooManyPeers);
@@ -15,6 +15,7 @@ use sync_status::SyncStatus;
 pub mod client;
 pub mod peer_info;
 pub mod score;
+pub mod stats;
 pub mod sync_status;
 
 /// Max number of disconnected nodes to remember.
@@ -139,6 +140,11 @@ impl PeerDB {
     /// This is used to determine if we should accept incoming connections or not.
     pub fn ban_status(&self, peer_id: &PeerId) -> BanResult {
         if let Some(peer) = self.peers.get(peer_id) {
+            // trusted peers are never banned, even if connecting from a banned IP
+            if peer.is_trusted() {
+                return BanResult::NotBanned;
+            }
+
             match peer.score_state() {
                 ScoreState::Banned => BanResult::BadScore,
                 _ => {
@@ -1,5 +1,6 @@
 use super::client::Client;
 use super::score::{PeerAction, Score, ScoreState};
+use super::stats::PeerStats;
 use super::sync_status::SyncStatus;
 use crate::Multiaddr;
 use discv5::Enr;
@@ -9,7 +10,7 @@ use serde::{
 };
 use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use strum::AsRefStr;
 use PeerConnectionStatus::*;
 
@@ -42,6 +43,11 @@ pub struct PeerInfo {
     connection_direction: Option<ConnectionDirection>,
     /// The enr of the peer, if known.
     enr: Option<Enr>,
+    /// The time when the current connected session with this peer was established.
+    #[serde(skip)]
+    connected_since: Option<Instant>,
+    /// RPC traffic with this peer.
+    stats: PeerStats,
 }
 
 impl Default for PeerInfo {
@@ -57,6 +63,8 @@ impl Default for PeerInfo {
             is_trusted: false,
             connection_direction: None,
             enr: None,
+            connected_since: None,
+            stats: PeerStats::default(),
         }
     }
 }
@@ -107,6 +115,19 @@ impl PeerInfo {
         self.enr.as_ref()
     }
 
+    /// Returns how long the peer has been connected, or `None` if not connected.
+    pub fn connection_duration(&self) -> Option<Duration> {
+        match self.connected_since {
+            Some(since) if self.is_connected() => Some(since.elapsed()),
+            _ => None,
+        }
+    }
+
+    /// Returns the RPC traffic statistics of the peer.
+    pub fn stats(&self) -> &PeerStats {
+        &self.stats
+    }
+
     /// Returns the seen addresses of the peer.
     pub fn seen_addresses(&self) -> impl Iterator<Item = &SocketAddr> + '_ {
         self.seen_addresses.iter()
@@ -222,6 +243,12 @@ impl PeerInfo {
         self.connection_status = connection_status
     }
 
+    /// Returns the RPC traffic statistics of the peer to update.
+    // VISIBILITY: The peer manager is able to record the traffic
+    pub(in crate::peer_manager) fn stats_mut(&mut self) -> &mut PeerStats {
+        &mut self.stats
+    }
+
     /// Sets the ENR of the peer if one is known.
     pub(super) fn set_enr(&mut self, enr: Enr) {
         self.enr = Some(enr)
@@ -285,6 +312,7 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 1, n_out: 0 };
                 self.connection_direction = Some(ConnectionDirection::Incoming);
+                self.connected_since = Some(Instant::now());
             }
         }
 
@@ -305,6 +333,7 @@ impl PeerInfo {
             | Unknown => {
                 self.connection_status = Connected { n_in: 0, n_out: 1 };
                 self.connection_direction = Some(ConnectionDirection::Outgoing);
+                self.connected_since = Some(Instant::now());
             }
         }
         if let Some(ip_addr) = seen_address {
@@ -0,0 +1,77 @@
+//! Traffic statistics of individual peers, which help operators to debug slow syncs.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// A new sample weighs `1 / LATENCY_SMOOTHING_DIVISOR` in the moving average of the request
+/// latency.
+const LATENCY_SMOOTHING_DIVISOR: u32 = 5;
+
+/// RPC traffic with a peer over all connected sessions, where the bytes are counted by the SSZ
+/// encoded size of messages before compression.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PeerStats {
+    /// Bytes of requests and responses sent to the peer.
+    pub bytes_sent: u64,
+    /// Bytes of requests and responses received from the peer.
+    pub bytes_received: u64,
+    /// Number of requests answered by the peer.
+    pub responses: u64,
+    /// Number of requests failed or timed out.
+    pub failed_requests: u64,
+    /// Exponential moving average of the time to receive the first response of a request.
+    #[serde(rename = "latency_ms", serialize_with = "serialize_millis")]
+    pub latency: Option<Duration>,
+}
+
+impl PeerStats {
+    pub fn on_sent(&mut self, bytes: usize) {
+        self.bytes_sent += bytes as u64;
+    }
+
+    pub fn on_received(&mut self, bytes: usize) {
+        self.bytes_received += bytes as u64;
+    }
+
+    pub fn on_response(&mut self, latency: Duration) {
+        self.responses += 1;
+        self.latency = Some(match self.latency {
+            Some(avg) => {
+                avg - avg / LATENCY_SMOOTHING_DIVISOR + latency / LATENCY_SMOOTHING_DIVISOR
+            }
+            None => latency,
+        });
+    }
+
+    pub fn on_request_failed(&mut self) {
+        self.failed_requests += 1;
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    latency: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match latency {
+        Some(latency) => serializer.serialize_some(&(latency.as_millis() as u64)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_average() {
+        let mut stats = PeerStats::default();
+        assert_eq!(stats.latency, None);
+
+        stats.on_response(Duration::from_millis(100));
+        assert_eq!(stats.latency, Some(Duration::from_millis(100)));
+
+        stats.on_response(Duration::from_millis(600));
+        assert_eq!(stats.latency, Some(Duration::from_millis(200)));
+        assert_eq!(stats.responses, 2);
+    }
+}
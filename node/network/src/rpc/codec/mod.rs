@@ -8,6 +8,38 @@ use crate::rpc::{InboundRequest, OutboundRequest, RPCCodedResponse};
 use libp2p::bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
 
+/// Decodes the requests and responses of `protocol` from the bytes received from peers, in the same
+/// way as the RPC handler. Only exposed to fuzz the codecs with untrusted input.
+#[cfg(feature = "fuzzing")]
+pub fn fuzz_decode(protocol: crate::rpc::Protocol, data: &[u8]) {
+    use crate::rpc::max_rpc_size;
+    use crate::rpc::protocol::{Encoding, ProtocolId, Version};
+
+    let protocol = ProtocolId::new(protocol, Version::V1, Encoding::SSZSnappy);
+
+    let mut inbound =
+        BaseInboundCodec::new(SSZSnappyInboundCodec::new(protocol.clone(), max_rpc_size()));
+    decode_all(&mut inbound, data);
+
+    let mut outbound =
+        BaseOutboundCodec::new(SSZSnappyOutboundCodec::new(protocol, max_rpc_size()));
+    decode_all(&mut outbound, data);
+}
+
+#[cfg(feature = "fuzzing")]
+fn decode_all<D: Decoder>(codec: &mut D, data: &[u8]) {
+    let mut src = BytesMut::from(data);
+
+    loop {
+        let len = src.len();
+        match codec.decode(&mut src) {
+            // stop once no more bytes consumed, e.g. waiting for more bytes
+            Ok(Some(_)) if src.len() < len => {}
+            _ => return,
+        }
+    }
+}
+
 // Known types of codecs
 pub enum InboundCodec {
     SSZSnappy(BaseInboundCodec<SSZSnappyInboundCodec>),
@@ -5,7 +5,7 @@ use crate::rpc::{
 };
 use crate::rpc::{InboundRequest, OutboundRequest, RPCCodedResponse, RPCResponse};
 use libp2p::bytes::BytesMut;
-use shared_types::ChunkArrayWithProof;
+use shared_types::{ChunkArrayWithProof, ErasureShard, ProvisionalChunks};
 use snap::read::FrameDecoder;
 use snap::write::FrameEncoder;
 use ssz::{Decode, Encode};
@@ -52,6 +52,8 @@ impl Encoder<RPCCodedResponse> for SSZSnappyInboundCodec {
                 RPCResponse::Pong(res) => res.data.as_ssz_bytes(),
                 RPCResponse::DataByHash(res) => res.as_ssz_bytes(),
                 RPCResponse::Chunks(res) => res.as_ssz_bytes(),
+                RPCResponse::ErasureShard(res) => res.as_ssz_bytes(),
+                RPCResponse::PendingChunks(res) => res.as_ssz_bytes(),
             },
             RPCCodedResponse::Error(_, err) => err.as_ssz_bytes(),
             RPCCodedResponse::StreamTermination(_) => {
@@ -160,6 +162,9 @@ impl Encoder<OutboundRequest> for SSZSnappyOutboundCodec {
             OutboundRequest::Ping(req) => req.as_ssz_bytes(),
             OutboundRequest::DataByHash(req) => req.hashes.as_ssz_bytes(),
             OutboundRequest::GetChunks(req) => req.as_ssz_bytes(),
+            OutboundRequest::OfferFile(req) => req.as_ssz_bytes(),
+            OutboundRequest::GetErasureShard(req) => req.as_ssz_bytes(),
+            OutboundRequest::GetPendingChunks(req) => req.as_ssz_bytes(),
         };
         // SSZ encoded bytes should be within `max_packet_size`
         if bytes.len() > self.max_packet_size {
@@ -349,6 +354,15 @@ fn handle_v1_request(
         Protocol::GetChunks => Ok(Some(InboundRequest::GetChunks(
             GetChunksRequest::from_ssz_bytes(decoded_buffer)?,
         ))),
+        Protocol::OfferFile => Ok(Some(InboundRequest::OfferFile(
+            OfferFileRequest::from_ssz_bytes(decoded_buffer)?,
+        ))),
+        Protocol::GetErasureShard => Ok(Some(InboundRequest::GetErasureShard(
+            GetErasureShardRequest::from_ssz_bytes(decoded_buffer)?,
+        ))),
+        Protocol::GetPendingChunks => Ok(Some(InboundRequest::GetPendingChunks(
+            GetPendingChunksRequest::from_ssz_bytes(decoded_buffer)?,
+        ))),
     }
 }
 
@@ -376,6 +390,16 @@ fn handle_v1_response(
         Protocol::GetChunks => Ok(Some(RPCResponse::Chunks(
             ChunkArrayWithProof::from_ssz_bytes(decoded_buffer)?,
         ))),
+        // This case should be unreachable as `OfferFile` has no response.
+        Protocol::OfferFile => Err(RPCError::InvalidData(
+            "OfferFile RPC message has no valid response".to_string(),
+        )),
+        Protocol::GetErasureShard => Ok(Some(RPCResponse::ErasureShard(
+            ErasureShard::from_ssz_bytes(decoded_buffer)?,
+        ))),
+        Protocol::GetPendingChunks => Ok(Some(RPCResponse::PendingChunks(
+            ProvisionalChunks::from_ssz_bytes(decoded_buffer)?,
+        ))),
     }
 }
 
@@ -385,13 +409,19 @@ mod tests {
     use super::*;
     use crate::rpc::protocol::*;
     use crate::rpc::{methods::StatusMessage, Ping};
+    use shared_types::{ChunkArray, FlowRangeProof};
 
     use snap::write::FrameEncoder;
     use ssz::Encode;
     use std::io::Write;
 
     fn status_message() -> StatusMessage {
-        StatusMessage { data: 1 }
+        StatusMessage {
+            protocol_version: 1,
+            chain_id: 1,
+            shard_config: Default::default(),
+            next_tx_seq: 1,
+        }
     }
 
     fn ping_message() -> Ping {
@@ -560,7 +590,7 @@ mod tests {
         assert_eq!(stream_identifier.len(), 10);
 
         // Status message is 84 bytes uncompressed. `max_compressed_len` is 32 + 84 + 84/6 = 130.
-        let status_message_bytes = StatusMessage { data: 1 }.as_ssz_bytes();
+        let status_message_bytes = status_message().as_ssz_bytes();
 
         let mut uvi_codec: Uvi<usize> = Uvi::default();
         let mut dst = BytesMut::with_capacity(1024);
@@ -582,4 +612,49 @@ mod tests {
             RPCError::InvalidData(_)
         ));
     }
+
+    /// Chunk arrays are compressed on the wire, and decoded back to the original response.
+    #[test]
+    fn test_encode_then_decode_chunks() {
+        let chunks = ChunkArrayWithProof {
+            chunks: ChunkArray {
+                data: vec![7u8; MAX_CHUNKS_LENGTH as usize / 16],
+                start_index: 0,
+            },
+            proof: FlowRangeProof::new_empty(),
+        };
+        let response = RPCCodedResponse::Success(RPCResponse::Chunks(chunks.clone()));
+
+        let mut encoded = encode(Protocol::GetChunks, Version::V1, response).unwrap();
+        assert!(encoded.len() < chunks.as_ssz_bytes().len() / 2);
+
+        assert_eq!(
+            decode(Protocol::GetChunks, Version::V1, &mut encoded),
+            Ok(Some(RPCResponse::Chunks(chunks)))
+        );
+    }
+
+    /// Test a small compressed chunks response whose decompressed length exceeds the limit of the
+    /// protocol, which is rejected before decompression.
+    #[test]
+    fn test_decode_chunks_decompressed_length_exceeded() {
+        let mut uvi_codec: Uvi<usize> = Uvi::default();
+        let mut dst = BytesMut::with_capacity(1024);
+
+        // Insert length-prefix of the decompressed payload
+        uvi_codec
+            .encode(*CHUNKS_RESPONSE_MAX + 1, &mut dst)
+            .unwrap();
+
+        // Insert highly compressible payload
+        let mut writer = FrameEncoder::new(Vec::new());
+        writer.write_all(&vec![0u8; 1_048_576]).unwrap();
+        writer.flush().unwrap();
+        dst.extend_from_slice(writer.get_ref());
+
+        assert!(matches!(
+            decode(Protocol::GetChunks, Version::V1, &mut dst).unwrap_err(),
+            RPCError::InvalidData(_)
+        ));
+    }
 }
@@ -160,6 +160,9 @@ struct OutboundInfo<Id> {
     remaining_chunks: Option<u64>,
     /// `Id` as given by the application that sent the request.
     req_id: Id,
+    /// The time when the request was sent, which is taken upon the first response chunk to
+    /// measure the latency of the peer.
+    request_start_time: Option<Instant>,
 }
 
 /// State of an inbound substream connection.
@@ -393,6 +396,7 @@ where
                         proto,
                         remaining_chunks: expected_responses,
                         req_id: id,
+                        request_start_time: Some(Instant::now()),
                     },
                 )
                 .is_some()
@@ -827,7 +831,14 @@ where
                             RPCCodedResponse::StreamTermination(t) => {
                                 Ok(RPCReceived::EndOfStream(id, t))
                             }
-                            RPCCodedResponse::Success(resp) => Ok(RPCReceived::Response(id, resp)),
+                            RPCCodedResponse::Success(resp) => {
+                                let latency = entry
+                                    .get_mut()
+                                    .request_start_time
+                                    .take()
+                                    .map(|since| since.elapsed());
+                                Ok(RPCReceived::Response(id, resp, latency))
+                            }
                             RPCCodedResponse::Error(ref code, ref r) => Err(HandlerErr::Outbound {
                                 id,
                                 proto,
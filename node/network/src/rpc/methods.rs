@@ -9,7 +9,7 @@ use ssz_types::{
 use std::ops::Deref;
 use strum::IntoStaticStr;
 pub type Hash256 = ethereum_types::H256;
-use shared_types::ChunkArrayWithProof;
+use shared_types::{ChunkArrayWithProof, ErasureShard, ProvisionalChunks, ShardConfig};
 
 pub use ssz_types::{typenum, typenum::Unsigned, BitList, BitVector, FixedVector};
 
@@ -28,6 +28,14 @@ pub const MAX_DATA_LEN: u64 = 256;
 // Maximum length of GetChunksResponse chunk data.
 pub const MAX_CHUNKS_LENGTH: usize = 10 * 1024 * 1024; // 10M
 
+/// Version of the network protocols, which is increased upon changes of the wire formats.
+///
+/// Version 2 adds the `Busy` response code.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The earliest protocol version of peers that the local node is compatible with.
+pub const MIN_COMPATIBLE_PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
 pub struct IonianData {
     pub hash: Hash256,
@@ -71,7 +79,37 @@ impl ToString for ErrorType {
 /// The STATUS request/response handshake message.
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
 pub struct StatusMessage {
-    pub data: u64,
+    /// Version of the network protocols supported by the node.
+    pub protocol_version: u32,
+    /// Identifier of the blockchain that hosts the log contract.
+    pub chain_id: u64,
+    /// Shard of the file data stored by the node.
+    pub shard_config: ShardConfig,
+    /// Number of transactions synced from the log contract, i.e. the latest tx_seq + 1.
+    pub next_tx_seq: u64,
+}
+
+impl StatusMessage {
+    /// Checks if the status of a remote peer is compatible with the local status, and returns
+    /// the reason if not.
+    pub fn check_compatible(&self, remote: &StatusMessage) -> Result<(), String> {
+        if remote.protocol_version < MIN_COMPATIBLE_PROTOCOL_VERSION {
+            return Err(format!(
+                "Incompatible protocol version: {}",
+                remote.protocol_version
+            ));
+        }
+
+        if remote.chain_id != self.chain_id {
+            return Err(format!("Different chain id: {}", remote.chain_id));
+        }
+
+        if !remote.shard_config.is_valid() {
+            return Err(format!("Invalid shard config: {:?}", remote.shard_config));
+        }
+
+        Ok(())
+    }
 }
 
 /// The PING request/response message.
@@ -179,10 +217,49 @@ pub struct DataByHashRequest {
 }
 
 /// Request a chunk array from a peer.
+///
+/// The peer responds with the chunks along with a range proof in the log flow, which should be
+/// validated with `ChunkArrayWithProof::validate` against a known flow root, e.g. by
+/// `validate_range_proof` of the log store, before the chunks are accepted.
 #[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
 pub struct GetChunksRequest {
     pub tx_seq: u64,
+    /// Index of the first chunk in the file.
+    pub index_start: u64,
+    /// Index of the chunk after the last requested one.
+    pub index_end: u64,
+}
+
+/// Offer a newly finalized file to a peer, which may then sync the file from the offering peer.
+///
+/// There is no response to the offer.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct OfferFileRequest {
+    pub tx_seq: u64,
+}
+
+/// Request an erasure shard of a complete PoRA chunk from a peer, to recover the chunk locally.
+///
+/// The peer responds with the shard along with the range proof of the whole chunk, which can only
+/// be validated once the chunk is reconstructed.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct GetErasureShardRequest {
+    /// Index of the PoRA chunk in the log flow.
+    pub chunk_index: u64,
+    pub shard_index: u32,
+}
+
+/// Request the chunks of a file by its data root, which may be uploaded to the peer before the
+/// transaction is confirmed on chain.
+///
+/// The peer responds with provisional chunks without any proof, which are staged locally and
+/// verified against the data root once the whole file is received.
+#[derive(Encode, Decode, Clone, Debug, PartialEq, Eq)]
+pub struct GetPendingChunksRequest {
+    pub data_root: Hash256,
+    /// Index of the first chunk in the file.
     pub index_start: u64,
+    /// Index of the chunk after the last requested one.
     pub index_end: u64,
 }
 
@@ -200,8 +277,14 @@ pub enum RPCResponse {
     /// A response to a get DATA_BY_HASH request.
     DataByHash(Box<IonianData>),
 
-    /// A response to a GET_CHUNKS request.
+    /// A response to a GET_CHUNKS request, whose chunks are proved against a flow root.
     Chunks(ChunkArrayWithProof),
+
+    /// A response to a GET_ERASURE_SHARD request.
+    ErasureShard(ErasureShard),
+
+    /// A response to a GET_PENDING_CHUNKS request, whose chunks are not proved.
+    PendingChunks(ProvisionalChunks),
 }
 
 /// Indicates which response is being terminated by a stream termination response.
@@ -229,6 +312,10 @@ pub enum RPCCodedResponse {
 #[strum(serialize_all = "snake_case")]
 pub enum RPCResponseErrorCode {
     RateLimited,
+    /// The requested data exceeds the bandwidth quota of the peer, which should retry later.
+    ///
+    /// Since protocol version 2, which peers of version 1 decode as `Unknown`.
+    Busy,
     InvalidRequest,
     ServerError,
     /// Error spec'd to indicate that a peer does not have blocks on a requested range.
@@ -258,6 +345,7 @@ impl RPCCodedResponse {
             2 => RPCResponseErrorCode::ServerError,
             3 => RPCResponseErrorCode::ResourceUnavailable,
             139 => RPCResponseErrorCode::RateLimited,
+            140 => RPCResponseErrorCode::Busy,
             _ => RPCResponseErrorCode::Unknown,
         };
         RPCCodedResponse::Error(code, err)
@@ -271,6 +359,8 @@ impl RPCCodedResponse {
                 RPCResponse::Pong(_) => false,
                 RPCResponse::DataByHash(_) => true,
                 RPCResponse::Chunks(_) => false,
+                RPCResponse::ErasureShard(_) => false,
+                RPCResponse::PendingChunks(_) => false,
             },
             RPCCodedResponse::Error(_, _) => true,
             // Stream terminations are part of responses that have chunks
@@ -292,6 +382,7 @@ impl RPCResponseErrorCode {
             RPCResponseErrorCode::ResourceUnavailable => 3,
             RPCResponseErrorCode::Unknown => 255,
             RPCResponseErrorCode::RateLimited => 139,
+            RPCResponseErrorCode::Busy => 140,
         }
     }
 }
@@ -304,6 +395,7 @@ impl std::fmt::Display for RPCResponseErrorCode {
             RPCResponseErrorCode::ServerError => "Server error occurred",
             RPCResponseErrorCode::Unknown => "Unknown error occurred",
             RPCResponseErrorCode::RateLimited => "Rate limited",
+            RPCResponseErrorCode::Busy => "Busy",
         };
         f.write_str(repr)
     }
@@ -311,7 +403,11 @@ impl std::fmt::Display for RPCResponseErrorCode {
 
 impl std::fmt::Display for StatusMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Status Message: Data: {:?}", self.data)
+        write!(
+            f,
+            "Status Message: Protocol Version: {}, Chain Id: {}, Shard Config: {:?}, Next Tx Seq: {}",
+            self.protocol_version, self.chain_id, self.shard_config, self.next_tx_seq
+        )
     }
 }
 
@@ -330,6 +426,21 @@ impl std::fmt::Display for RPCResponse {
                     data.chunks.data.len()
                 )
             }
+            RPCResponse::ErasureShard(shard) => {
+                write!(
+                    f,
+                    "ErasureShard Response, chunk index: {}, shard index: {}",
+                    shard.chunk_index, shard.shard_index
+                )
+            }
+            RPCResponse::PendingChunks(data) => {
+                write!(
+                    f,
+                    "PendingChunks Response, data root: {:?}, data length: {}",
+                    data.data_root,
+                    data.chunks.data.len()
+                )
+            }
         }
     }
 }
@@ -365,3 +476,40 @@ impl std::fmt::Display for GoodbyeReason {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(protocol_version: u32, chain_id: u64, shard_config: ShardConfig) -> StatusMessage {
+        StatusMessage {
+            protocol_version,
+            chain_id,
+            shard_config,
+            next_tx_seq: 0,
+        }
+    }
+
+    #[test]
+    fn test_status_compatible() {
+        let local = status(PROTOCOL_VERSION, 1, Default::default());
+        assert!(local.check_compatible(&local).is_ok());
+
+        // shard config is not required to be the same
+        let remote = status(PROTOCOL_VERSION, 1, ShardConfig::new(1, 2).unwrap());
+        assert!(local.check_compatible(&remote).is_ok());
+
+        let remote = status(MIN_COMPATIBLE_PROTOCOL_VERSION - 1, 1, Default::default());
+        assert!(local.check_compatible(&remote).is_err());
+
+        let remote = status(PROTOCOL_VERSION, 2, Default::default());
+        assert!(local.check_compatible(&remote).is_err());
+
+        let invalid_shard = ShardConfig {
+            shard_id: 2,
+            num_shard: 2,
+        };
+        let remote = status(PROTOCOL_VERSION, 1, invalid_shard);
+        assert!(local.check_compatible(&remote).is_err());
+    }
+}
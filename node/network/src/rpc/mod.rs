@@ -13,6 +13,7 @@ use libp2p::swarm::{
 };
 use libp2p::PeerId;
 use rate_limiter::{RPCRateLimiter as RateLimiter, RPCRateLimiterBuilder, RateLimitedErr};
+use std::collections::HashSet;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -22,12 +23,16 @@ pub(crate) use protocol::{InboundRequest, RPCProtocol};
 
 pub use handler::SubstreamId;
 pub use methods::{
-    DataByHashRequest, GetChunksRequest, GoodbyeReason, IonianData, MaxRequestBlocks,
-    RPCResponseErrorCode, ResponseTermination, StatusMessage, MAX_REQUEST_BLOCKS,
+    DataByHashRequest, GetChunksRequest, GetErasureShardRequest, GetPendingChunksRequest,
+    GoodbyeReason, IonianData, MaxRequestBlocks, OfferFileRequest, RPCResponseErrorCode,
+    ResponseTermination, StatusMessage, MAX_REQUEST_BLOCKS, PROTOCOL_VERSION,
 };
 pub(crate) use outbound::OutboundRequest;
 pub use protocol::{max_rpc_size, Protocol, RPCError};
 
+#[cfg(feature = "fuzzing")]
+pub use codec::fuzz_decode;
+
 pub(crate) mod codec;
 mod handler;
 pub mod methods;
@@ -69,8 +74,9 @@ pub enum RPCReceived<Id> {
     ///
     /// The `Id` corresponds to the application given ID of the original request sent to the
     /// peer. The second parameter is a single chunk of a response. These go over *outbound*
-    /// connections.
-    Response(Id, RPCResponse),
+    /// connections. The third parameter is the time elapsed since the request was sent, which is
+    /// only given for the first chunk of a response.
+    Response(Id, RPCResponse, Option<Duration>),
     /// Marks a request as completed
     EndOfStream(Id, ResponseTermination),
 }
@@ -106,23 +112,29 @@ pub struct RPCMessage<Id> {
 pub struct RPC<Id: ReqId> {
     /// Rate limiter
     limiter: RateLimiter,
+    /// Peers exempt from the rate limits.
+    trusted_peers: HashSet<PeerId>,
     /// Queue of events to be processed.
     events: Vec<NetworkBehaviourAction<RPCMessage<Id>, RPCHandler<Id>>>,
 }
 
 impl<Id: ReqId> RPC<Id> {
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
+    pub fn new(trusted_peers: HashSet<PeerId>, chunk_bytes_per_min: u64) -> Self {
         let limiter = RPCRateLimiterBuilder::new()
             .n_every(Protocol::Ping, 2, Duration::from_secs(10))
             .n_every(Protocol::Status, 5, Duration::from_secs(15))
             .one_every(Protocol::Goodbye, Duration::from_secs(10))
             .n_every(Protocol::DataByHash, 128, Duration::from_secs(10))
             .n_every(Protocol::GetChunks, 128, Duration::from_secs(10))
+            .chunk_bytes_every(chunk_bytes_per_min, Duration::from_secs(60))
+            .n_every(Protocol::OfferFile, 32, Duration::from_secs(10))
+            .n_every(Protocol::GetErasureShard, 64, Duration::from_secs(10))
+            .n_every(Protocol::GetPendingChunks, 128, Duration::from_secs(10))
             .build()
             .expect("Configuration parameters are valid");
         RPC {
             limiter,
+            trusted_peers,
             events: Vec::new(),
         }
     }
@@ -189,7 +201,13 @@ where
     ) {
         if let Ok(RPCReceived::Request(ref id, ref req)) = event {
             // check if the request is conformant to the quota
-            match self.limiter.allows(&peer_id, req) {
+            let allowed = if self.trusted_peers.contains(&peer_id) {
+                Ok(())
+            } else {
+                self.limiter.allows(&peer_id, req)
+            };
+
+            match allowed {
                 Ok(()) => {
                     // send the event to the user
                     self.events
@@ -234,6 +252,23 @@ where
                         ),
                     );
                 }
+                Err(RateLimitedErr::Busy(wait_time)) => {
+                    debug!(
+                        request = %req,
+                        %peer_id,
+                        wait_time_ms = %wait_time.as_millis(),
+                        "Request exceeds the bandwidth quota",
+                    );
+
+                    self.send_response(
+                        peer_id,
+                        (conn_id, *id),
+                        RPCCodedResponse::Error(
+                            RPCResponseErrorCode::Busy,
+                            format!("Wait {:?}", wait_time).into(),
+                        ),
+                    );
+                }
             }
         } else {
             self.events
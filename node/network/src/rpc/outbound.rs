@@ -35,6 +35,9 @@ pub enum OutboundRequest {
     Ping(Ping),
     DataByHash(DataByHashRequest),
     GetChunks(GetChunksRequest),
+    OfferFile(OfferFileRequest),
+    GetErasureShard(GetErasureShardRequest),
+    GetPendingChunks(GetPendingChunksRequest),
 }
 
 impl UpgradeInfo for OutboundRequestContainer {
@@ -77,6 +80,21 @@ impl OutboundRequest {
                 Version::V1,
                 Encoding::SSZSnappy,
             )],
+            OutboundRequest::OfferFile(_) => vec![ProtocolId::new(
+                Protocol::OfferFile,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
+            OutboundRequest::GetErasureShard(_) => vec![ProtocolId::new(
+                Protocol::GetErasureShard,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
+            OutboundRequest::GetPendingChunks(_) => vec![ProtocolId::new(
+                Protocol::GetPendingChunks,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
         }
     }
 
@@ -90,6 +108,9 @@ impl OutboundRequest {
             OutboundRequest::Ping(_) => 1,
             OutboundRequest::DataByHash(req) => req.hashes.len() as u64,
             OutboundRequest::GetChunks(_) => 1,
+            OutboundRequest::OfferFile(_) => 0,
+            OutboundRequest::GetErasureShard(_) => 1,
+            OutboundRequest::GetPendingChunks(_) => 1,
         }
     }
 
@@ -101,6 +122,9 @@ impl OutboundRequest {
             OutboundRequest::Ping(_) => Protocol::Ping,
             OutboundRequest::DataByHash(_) => Protocol::DataByHash,
             OutboundRequest::GetChunks(_) => Protocol::GetChunks,
+            OutboundRequest::OfferFile(_) => Protocol::OfferFile,
+            OutboundRequest::GetErasureShard(_) => Protocol::GetErasureShard,
+            OutboundRequest::GetPendingChunks(_) => Protocol::GetPendingChunks,
         }
     }
 
@@ -115,6 +139,9 @@ impl OutboundRequest {
             OutboundRequest::Goodbye(_) => unreachable!(),
             OutboundRequest::Ping(_) => unreachable!(),
             OutboundRequest::GetChunks(_) => unreachable!(),
+            OutboundRequest::OfferFile(_) => unreachable!(),
+            OutboundRequest::GetErasureShard(_) => unreachable!(),
+            OutboundRequest::GetPendingChunks(_) => unreachable!(),
         }
     }
 }
@@ -173,6 +200,15 @@ impl std::fmt::Display for OutboundRequest {
             OutboundRequest::GetChunks(req) => {
                 write!(f, "GetChunks: {:?}", req)
             }
+            OutboundRequest::OfferFile(req) => {
+                write!(f, "OfferFile: {:?}", req)
+            }
+            OutboundRequest::GetErasureShard(req) => {
+                write!(f, "GetErasureShard: {:?}", req)
+            }
+            OutboundRequest::GetPendingChunks(req) => {
+                write!(f, "GetPendingChunks: {:?}", req)
+            }
         }
     }
 }
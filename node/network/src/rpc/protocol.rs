@@ -8,7 +8,9 @@ use futures::future::BoxFuture;
 use futures::prelude::{AsyncRead, AsyncWrite};
 use futures::{FutureExt, StreamExt};
 use libp2p::core::{InboundUpgrade, ProtocolName, UpgradeInfo};
-use shared_types::{ChunkArray, ChunkArrayWithProof, FlowRangeProof};
+use shared_types::{
+    ChunkArray, ChunkArrayWithProof, DataRoot, ErasureShard, FlowRangeProof, ProvisionalChunks,
+};
 use ssz::Encode;
 use ssz_types::VariableList;
 use std::io;
@@ -60,6 +62,40 @@ lazy_static! {
     }
     .as_ssz_bytes()
     .len();
+    pub static ref ERASURE_SHARD_RESPONSE_MIN: usize = ErasureShard {
+        chunk_index: 0,
+        shard_index: 0,
+        data: vec![],
+        proof: FlowRangeProof::new_empty(),
+    }
+    .as_ssz_bytes()
+    .len();
+    pub static ref ERASURE_SHARD_RESPONSE_MAX: usize = ErasureShard {
+        chunk_index: 0,
+        shard_index: 0,
+        data: vec![0u8; MAX_CHUNKS_LENGTH as usize],
+        proof: FlowRangeProof::new_empty(),
+    }
+    .as_ssz_bytes()
+    .len();
+    pub static ref PENDING_CHUNKS_RESPONSE_MIN: usize = ProvisionalChunks {
+        data_root: DataRoot::zero(),
+        chunks: ChunkArray {
+            data: vec![],
+            start_index: 0,
+        },
+    }
+    .as_ssz_bytes()
+    .len();
+    pub static ref PENDING_CHUNKS_RESPONSE_MAX: usize = ProvisionalChunks {
+        data_root: DataRoot::zero(),
+        chunks: ChunkArray {
+            data: vec![0u8; MAX_CHUNKS_LENGTH as usize],
+            start_index: 0,
+        },
+    }
+    .as_ssz_bytes()
+    .len();
 }
 
 // /// The maximum bytes that can be sent across the RPC pre-merge.
@@ -93,6 +129,15 @@ pub enum Protocol {
 
     /// The Chunk sync protocol.
     GetChunks,
+
+    /// The protocol to offer newly finalized files to peers.
+    OfferFile,
+
+    /// The protocol to fetch erasure shards of PoRA chunks for recovery.
+    GetErasureShard,
+
+    /// The protocol to fetch provisional chunks of files not confirmed on chain yet.
+    GetPendingChunks,
 }
 
 /// RPC Versions
@@ -116,6 +161,9 @@ impl std::fmt::Display for Protocol {
             Protocol::Ping => "ping",
             Protocol::DataByHash => "data_by_hash",
             Protocol::GetChunks => "get_chunks",
+            Protocol::OfferFile => "offer_file",
+            Protocol::GetErasureShard => "get_erasure_shard",
+            Protocol::GetPendingChunks => "get_pending_chunks",
         };
         f.write_str(repr)
     }
@@ -156,6 +204,9 @@ impl UpgradeInfo for RPCProtocol {
             ProtocolId::new(Protocol::Ping, Version::V1, Encoding::SSZSnappy),
             ProtocolId::new(Protocol::DataByHash, Version::V1, Encoding::SSZSnappy),
             ProtocolId::new(Protocol::GetChunks, Version::V1, Encoding::SSZSnappy),
+            ProtocolId::new(Protocol::OfferFile, Version::V1, Encoding::SSZSnappy),
+            ProtocolId::new(Protocol::GetErasureShard, Version::V1, Encoding::SSZSnappy),
+            ProtocolId::new(Protocol::GetPendingChunks, Version::V1, Encoding::SSZSnappy),
         ]
     }
 }
@@ -220,6 +271,18 @@ impl ProtocolId {
                 <GetChunksRequest as Encode>::ssz_fixed_len(),
                 <GetChunksRequest as Encode>::ssz_fixed_len(),
             ),
+            Protocol::OfferFile => RpcLimits::new(
+                <OfferFileRequest as Encode>::ssz_fixed_len(),
+                <OfferFileRequest as Encode>::ssz_fixed_len(),
+            ),
+            Protocol::GetErasureShard => RpcLimits::new(
+                <GetErasureShardRequest as Encode>::ssz_fixed_len(),
+                <GetErasureShardRequest as Encode>::ssz_fixed_len(),
+            ),
+            Protocol::GetPendingChunks => RpcLimits::new(
+                <GetPendingChunksRequest as Encode>::ssz_fixed_len(),
+                <GetPendingChunksRequest as Encode>::ssz_fixed_len(),
+            ),
         }
     }
 
@@ -244,6 +307,16 @@ impl ProtocolId {
             ),
 
             Protocol::GetChunks => RpcLimits::new(*CHUNKS_RESPONSE_MIN, *CHUNKS_RESPONSE_MAX),
+
+            Protocol::OfferFile => RpcLimits::new(0, 0), // OfferFile request has no response
+
+            Protocol::GetErasureShard => {
+                RpcLimits::new(*ERASURE_SHARD_RESPONSE_MIN, *ERASURE_SHARD_RESPONSE_MAX)
+            }
+
+            Protocol::GetPendingChunks => {
+                RpcLimits::new(*PENDING_CHUNKS_RESPONSE_MIN, *PENDING_CHUNKS_RESPONSE_MAX)
+            }
         }
     }
 }
@@ -326,6 +399,9 @@ pub enum InboundRequest {
     Ping(Ping),
     DataByHash(DataByHashRequest),
     GetChunks(GetChunksRequest),
+    OfferFile(OfferFileRequest),
+    GetErasureShard(GetErasureShardRequest),
+    GetPendingChunks(GetPendingChunksRequest),
 }
 
 impl UpgradeInfo for InboundRequest {
@@ -368,6 +444,21 @@ impl InboundRequest {
                 Version::V1,
                 Encoding::SSZSnappy,
             )],
+            InboundRequest::OfferFile(_) => vec![ProtocolId::new(
+                Protocol::OfferFile,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
+            InboundRequest::GetErasureShard(_) => vec![ProtocolId::new(
+                Protocol::GetErasureShard,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
+            InboundRequest::GetPendingChunks(_) => vec![ProtocolId::new(
+                Protocol::GetPendingChunks,
+                Version::V1,
+                Encoding::SSZSnappy,
+            )],
         }
     }
 
@@ -381,6 +472,9 @@ impl InboundRequest {
             InboundRequest::DataByHash(req) => req.hashes.len() as u64,
             InboundRequest::Ping(_) => 1,
             InboundRequest::GetChunks(_) => 1,
+            InboundRequest::OfferFile(_) => 0,
+            InboundRequest::GetErasureShard(_) => 1,
+            InboundRequest::GetPendingChunks(_) => 1,
         }
     }
 
@@ -392,6 +486,9 @@ impl InboundRequest {
             InboundRequest::Ping(_) => Protocol::Ping,
             InboundRequest::DataByHash(_) => Protocol::DataByHash,
             InboundRequest::GetChunks(_) => Protocol::GetChunks,
+            InboundRequest::OfferFile(_) => Protocol::OfferFile,
+            InboundRequest::GetErasureShard(_) => Protocol::GetErasureShard,
+            InboundRequest::GetPendingChunks(_) => Protocol::GetPendingChunks,
         }
     }
 
@@ -406,6 +503,9 @@ impl InboundRequest {
             InboundRequest::Goodbye(_) => unreachable!(),
             InboundRequest::Ping(_) => unreachable!(),
             InboundRequest::GetChunks(_) => unreachable!(),
+            InboundRequest::OfferFile(_) => unreachable!(),
+            InboundRequest::GetErasureShard(_) => unreachable!(),
+            InboundRequest::GetPendingChunks(_) => unreachable!(),
         }
     }
 }
@@ -526,6 +626,15 @@ impl std::fmt::Display for InboundRequest {
             InboundRequest::GetChunks(req) => {
                 write!(f, "Get Chunks: {:?}", req)
             }
+            InboundRequest::OfferFile(req) => {
+                write!(f, "Offer File: {:?}", req)
+            }
+            InboundRequest::GetErasureShard(req) => {
+                write!(f, "Get Erasure Shard: {:?}", req)
+            }
+            InboundRequest::GetPendingChunks(req) => {
+                write!(f, "Get Pending Chunks: {:?}", req)
+            }
         }
     }
 }
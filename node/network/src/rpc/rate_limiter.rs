@@ -1,6 +1,7 @@
 use crate::rpc::{InboundRequest, Protocol};
 use fnv::FnvHashMap;
 use libp2p::PeerId;
+use shared_types::CHUNK_SIZE;
 use std::convert::TryInto;
 use std::future::Future;
 use std::hash::Hash;
@@ -9,6 +10,9 @@ use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 use tokio::time::Interval;
 
+/// Number of chunks in a PoRA chunk, which bounds the size of an erasure shard.
+const PORA_CHUNK_CHUNKS: u64 = 1024;
+
 /// Nanoseconds since a given time.
 // Maintained as u64 to reduce footprint
 // NOTE: this also implies that the rate limiter will manage checking if a batch is allowed for at
@@ -70,6 +74,15 @@ pub struct RPCRateLimiter {
     data_by_hash_rl: Limiter<PeerId>,
     /// GetChunks rate limiter.
     get_chunks_rl: Limiter<PeerId>,
+    /// Rate limiter of the chunk bytes served by GetChunks, GetPendingChunks and GetErasureShard,
+    /// where a token is a chunk.
+    chunk_bytes_rl: Limiter<PeerId>,
+    /// OfferFile rate limiter.
+    offer_file_rl: Limiter<PeerId>,
+    /// GetErasureShard rate limiter.
+    get_erasure_shard_rl: Limiter<PeerId>,
+    /// GetPendingChunks rate limiter.
+    get_pending_chunks_rl: Limiter<PeerId>,
 }
 
 /// Error type for non conformant requests
@@ -78,6 +91,9 @@ pub enum RateLimitedErr {
     TooLarge,
     /// Request does not fit in the quota. Gives the earliest time the request could be accepted.
     TooSoon(Duration),
+    /// Bytes to serve do not fit in the bandwidth quota. Gives the earliest time the request could
+    /// be accepted.
+    Busy(Duration),
 }
 
 /// User-friendly builder of a `RPCRateLimiter`
@@ -93,6 +109,15 @@ pub struct RPCRateLimiterBuilder {
     data_by_hash_quota: Option<Quota>,
     /// Quota for the GetChunks protocol.
     get_chunks_quota: Option<Quota>,
+    /// Quota for the chunks served by the GetChunks, GetPendingChunks and GetErasureShard
+    /// protocols.
+    chunk_bytes_quota: Option<Quota>,
+    /// Quota for the OfferFile protocol.
+    offer_file_quota: Option<Quota>,
+    /// Quota for the GetErasureShard protocol.
+    get_erasure_shard_quota: Option<Quota>,
+    /// Quota for the GetPendingChunks protocol.
+    get_pending_chunks_quota: Option<Quota>,
 }
 
 impl RPCRateLimiterBuilder {
@@ -110,6 +135,9 @@ impl RPCRateLimiterBuilder {
             Protocol::Goodbye => self.goodbye_quota = q,
             Protocol::DataByHash => self.data_by_hash_quota = q,
             Protocol::GetChunks => self.get_chunks_quota = q,
+            Protocol::OfferFile => self.offer_file_quota = q,
+            Protocol::GetErasureShard => self.get_erasure_shard_quota = q,
+            Protocol::GetPendingChunks => self.get_pending_chunks_quota = q,
         }
         self
     }
@@ -137,6 +165,16 @@ impl RPCRateLimiterBuilder {
         )
     }
 
+    /// Allow chunks of `bytes` to be served every `time_period` by the protocols serving chunk
+    /// data.
+    pub fn chunk_bytes_every(mut self, bytes: u64, time_period: Duration) -> Self {
+        self.chunk_bytes_quota = Some(Quota {
+            max_tokens: bytes / CHUNK_SIZE as u64,
+            replenish_all_every: time_period,
+        });
+        self
+    }
+
     pub fn build(self) -> Result<RPCRateLimiter, &'static str> {
         // get our quotas
         let ping_quota = self.ping_quota.ok_or("Ping quota not specified")?;
@@ -148,6 +186,18 @@ impl RPCRateLimiterBuilder {
         let get_chunks_quota = self
             .get_chunks_quota
             .ok_or("GetChunks quota not specified")?;
+        let chunk_bytes_quota = self
+            .chunk_bytes_quota
+            .ok_or("Chunk bytes quota not specified")?;
+        let offer_file_quota = self
+            .offer_file_quota
+            .ok_or("OfferFile quota not specified")?;
+        let get_erasure_shard_quota = self
+            .get_erasure_shard_quota
+            .ok_or("GetErasureShard quota not specified")?;
+        let get_pending_chunks_quota = self
+            .get_pending_chunks_quota
+            .ok_or("GetPendingChunks quota not specified")?;
 
         // create the rate limiters
         let ping_rl = Limiter::from_quota(ping_quota)?;
@@ -155,6 +205,10 @@ impl RPCRateLimiterBuilder {
         let goodbye_rl = Limiter::from_quota(goodbye_quota)?;
         let data_by_hash_rl = Limiter::from_quota(data_by_hash_quota)?;
         let get_chunks_rl = Limiter::from_quota(get_chunks_quota)?;
+        let chunk_bytes_rl = Limiter::from_quota(chunk_bytes_quota)?;
+        let offer_file_rl = Limiter::from_quota(offer_file_quota)?;
+        let get_erasure_shard_rl = Limiter::from_quota(get_erasure_shard_quota)?;
+        let get_pending_chunks_rl = Limiter::from_quota(get_pending_chunks_quota)?;
 
         // check for peers to prune every 30 seconds, starting in 30 seconds
         let prune_every = tokio::time::Duration::from_secs(30);
@@ -167,6 +221,10 @@ impl RPCRateLimiterBuilder {
             goodbye_rl,
             data_by_hash_rl,
             get_chunks_rl,
+            chunk_bytes_rl,
+            offer_file_rl,
+            get_erasure_shard_rl,
+            get_pending_chunks_rl,
             init_time: Instant::now(),
         })
     }
@@ -203,16 +261,35 @@ impl RPCRateLimiter {
         //     tokens *= penalty_factor;
         // }
 
-        let check =
-            |limiter: &mut Limiter<PeerId>| limiter.allows(time_since_start, peer_id, tokens);
         let limiter = match request.protocol() {
             Protocol::Ping => &mut self.ping_rl,
             Protocol::Status => &mut self.status_rl,
             Protocol::Goodbye => &mut self.goodbye_rl,
             Protocol::DataByHash => &mut self.data_by_hash_rl,
             Protocol::GetChunks => &mut self.get_chunks_rl,
+            Protocol::OfferFile => &mut self.offer_file_rl,
+            Protocol::GetErasureShard => &mut self.get_erasure_shard_rl,
+            Protocol::GetPendingChunks => &mut self.get_pending_chunks_rl,
         };
-        check(limiter)
+        // The request token is only spent if the chunk bytes are allowed as well.
+        limiter.check(time_since_start, peer_id, tokens)?;
+
+        // Serving chunks is bounded by the disk bandwidth, so that the bytes served to a peer are
+        // limited as well, which the peer should retry later.
+        if let Some(num_chunks) = served_chunks(request) {
+            match self
+                .chunk_bytes_rl
+                .allows(time_since_start, peer_id, num_chunks)
+            {
+                Ok(()) => {}
+                Err(RateLimitedErr::TooSoon(wait_time)) => {
+                    return Err(RateLimitedErr::Busy(wait_time))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        limiter.allows(time_since_start, peer_id, tokens)
     }
 
     pub fn prune(&mut self) {
@@ -222,9 +299,27 @@ impl RPCRateLimiter {
         self.goodbye_rl.prune(time_since_start);
         self.data_by_hash_rl.prune(time_since_start);
         self.get_chunks_rl.prune(time_since_start);
+        self.chunk_bytes_rl.prune(time_since_start);
+        self.offer_file_rl.prune(time_since_start);
+        self.get_erasure_shard_rl.prune(time_since_start);
+        self.get_pending_chunks_rl.prune(time_since_start);
     }
 }
 
+/// Returns the number of chunks that may be served for the request, which are charged to the
+/// chunk bytes quota, or `None` if no chunk data is served.
+fn served_chunks(request: &InboundRequest) -> Option<u64> {
+    let num_chunks = match request {
+        InboundRequest::GetChunks(req) => req.index_end.saturating_sub(req.index_start),
+        InboundRequest::GetPendingChunks(req) => req.index_end.saturating_sub(req.index_start),
+        // The erasure coding config is unknown here, so a shard is charged as the PoRA chunk it
+        // is encoded from, which is never smaller than the shard.
+        InboundRequest::GetErasureShard(_) => PORA_CHUNK_CHUNKS,
+        _ => return None,
+    };
+    Some(num_chunks.max(1))
+}
+
 impl Future for RPCRateLimiter {
     type Output = ();
 
@@ -275,6 +370,26 @@ impl<Key: Hash + Eq + Clone> Limiter<Key> {
         time_since_start: Duration,
         key: &Key,
         tokens: u64,
+    ) -> Result<(), RateLimitedErr> {
+        self.check(time_since_start, key, tokens)?;
+        let time_since_start = time_since_start.as_nanos() as u64;
+        // If the key is new, we consider their bucket full (which means, their request will be
+        // allowed)
+        let tat = self
+            .tat_per_key
+            .entry(key.clone())
+            .or_insert(time_since_start);
+        // calculate the new TAT
+        *tat = time_since_start.max(*tat) + self.t * tokens;
+        Ok(())
+    }
+
+    /// Checks if the tokens are allowed for the key, without spending them.
+    pub fn check(
+        &self,
+        time_since_start: Duration,
+        key: &Key,
+        tokens: u64,
     ) -> Result<(), RateLimitedErr> {
         let time_since_start = time_since_start.as_nanos() as u64;
         let tau = self.tau;
@@ -286,14 +401,14 @@ impl<Key: Hash + Eq + Clone> Limiter<Key> {
             // makes the bucket full. So, this batch can _never_ be processed
             return Err(RateLimitedErr::TooLarge);
         }
-        // If the key is new, we consider their bucket full (which means, their request will be
-        // allowed)
+        // If the key is new, their bucket is full
         let tat = self
             .tat_per_key
-            .entry(key.clone())
-            .or_insert(time_since_start);
+            .get(key)
+            .copied()
+            .unwrap_or(time_since_start);
         // check how soon could the request be made
-        let earliest_time = (*tat + additional_time).saturating_sub(tau);
+        let earliest_time = (tat + additional_time).saturating_sub(tau);
         // earliest_time is in the future
         if time_since_start < earliest_time {
             Err(RateLimitedErr::TooSoon(Duration::from_nanos(
@@ -301,8 +416,6 @@ impl<Key: Hash + Eq + Clone> Limiter<Key> {
                 earliest_time - time_since_start,
             )))
         } else {
-            // calculate the new TAT
-            *tat = time_since_start.max(*tat) + additional_time;
             Ok(())
         }
     }
@@ -317,7 +430,11 @@ impl<Key: Hash + Eq + Clone> Limiter<Key> {
 
 #[cfg(test)]
 mod tests {
-    use crate::rpc::rate_limiter::{Limiter, Quota};
+    use crate::rpc::methods::{GetChunksRequest, GetErasureShardRequest, GetPendingChunksRequest};
+    use crate::rpc::rate_limiter::{Limiter, Quota, RPCRateLimiterBuilder, RateLimitedErr};
+    use crate::rpc::{InboundRequest, Protocol};
+    use libp2p::PeerId;
+    use shared_types::CHUNK_SIZE;
     use std::time::Duration;
 
     #[test]
@@ -385,4 +502,116 @@ mod tests {
             .allows(Duration::from_secs_f32(0.4), &key, 1)
             .is_err());
     }
+
+    #[tokio::test]
+    async fn test_chunk_bytes_quota() {
+        let mut limiter = RPCRateLimiterBuilder::new()
+            .n_every(Protocol::Ping, 2, Duration::from_secs(10))
+            .n_every(Protocol::Status, 5, Duration::from_secs(15))
+            .one_every(Protocol::Goodbye, Duration::from_secs(10))
+            .n_every(Protocol::DataByHash, 128, Duration::from_secs(10))
+            .n_every(Protocol::GetChunks, 128, Duration::from_secs(10))
+            .n_every(Protocol::OfferFile, 32, Duration::from_secs(10))
+            .n_every(Protocol::GetErasureShard, 64, Duration::from_secs(10))
+            .n_every(Protocol::GetPendingChunks, 128, Duration::from_secs(10))
+            .chunk_bytes_every(100 * CHUNK_SIZE as u64, Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let request = |index_end: u64| {
+            InboundRequest::GetChunks(GetChunksRequest {
+                tx_seq: 0,
+                index_start: 0,
+                index_end,
+            })
+        };
+
+        let peer_id = PeerId::random();
+        assert!(limiter.allows(&peer_id, &request(60)).is_ok());
+        assert!(matches!(
+            limiter.allows(&peer_id, &request(60)),
+            Err(RateLimitedErr::Busy(_))
+        ));
+        assert!(matches!(
+            limiter.allows(&peer_id, &request(101)),
+            Err(RateLimitedErr::TooLarge)
+        ));
+
+        // the quota is per peer
+        assert!(limiter.allows(&PeerId::random(), &request(60)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_busy_request_not_charged() {
+        let mut limiter = RPCRateLimiterBuilder::new()
+            .n_every(Protocol::Ping, 2, Duration::from_secs(10))
+            .n_every(Protocol::Status, 5, Duration::from_secs(15))
+            .one_every(Protocol::Goodbye, Duration::from_secs(10))
+            .n_every(Protocol::DataByHash, 128, Duration::from_secs(10))
+            .n_every(Protocol::GetChunks, 2, Duration::from_secs(10))
+            .n_every(Protocol::OfferFile, 32, Duration::from_secs(10))
+            .n_every(Protocol::GetErasureShard, 64, Duration::from_secs(10))
+            .n_every(Protocol::GetPendingChunks, 128, Duration::from_secs(10))
+            .chunk_bytes_every(100 * CHUNK_SIZE as u64, Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let request = |index_end: u64| {
+            InboundRequest::GetChunks(GetChunksRequest {
+                tx_seq: 0,
+                index_start: 0,
+                index_end,
+            })
+        };
+
+        // the busy requests do not spend the request quota, which would be exhausted otherwise
+        let peer_id = PeerId::random();
+        assert!(limiter.allows(&peer_id, &request(100)).is_ok());
+        for _ in 0..3 {
+            assert!(matches!(
+                limiter.allows(&peer_id, &request(100)),
+                Err(RateLimitedErr::Busy(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunk_bytes_quota_shared() {
+        let mut limiter = RPCRateLimiterBuilder::new()
+            .n_every(Protocol::Ping, 2, Duration::from_secs(10))
+            .n_every(Protocol::Status, 5, Duration::from_secs(15))
+            .one_every(Protocol::Goodbye, Duration::from_secs(10))
+            .n_every(Protocol::DataByHash, 128, Duration::from_secs(10))
+            .n_every(Protocol::GetChunks, 128, Duration::from_secs(10))
+            .n_every(Protocol::OfferFile, 32, Duration::from_secs(10))
+            .n_every(Protocol::GetErasureShard, 64, Duration::from_secs(10))
+            .n_every(Protocol::GetPendingChunks, 128, Duration::from_secs(10))
+            .chunk_bytes_every(2048 * CHUNK_SIZE as u64, Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let pending_chunks = InboundRequest::GetPendingChunks(GetPendingChunksRequest {
+            data_root: Default::default(),
+            index_start: 0,
+            index_end: 1024,
+        });
+        let erasure_shard = InboundRequest::GetErasureShard(GetErasureShardRequest {
+            chunk_index: 1,
+            shard_index: 0,
+        });
+        let chunks = InboundRequest::GetChunks(GetChunksRequest {
+            tx_seq: 0,
+            index_start: 0,
+            index_end: 1,
+        });
+
+        // all protocols serving chunk data are charged to the same quota
+        let peer_id = PeerId::random();
+        assert!(limiter.allows(&peer_id, &pending_chunks).is_ok());
+        assert!(limiter.allows(&peer_id, &erasure_shard).is_ok());
+        assert!(matches!(
+            limiter.allows(&peer_id, &chunks),
+            Err(RateLimitedErr::Busy(_))
+        ));
+    }
 }
@@ -22,7 +22,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::peer_manager::{MIN_OUTBOUND_ONLY_FACTOR, PEER_EXCESS_FACTOR, PRIORITY_PEER_EXCESS};
+use crate::peer_manager::PRIORITY_PEER_EXCESS;
 
 pub const NETWORK_KEY_FILENAME: &str = "key";
 /// The maximum simultaneous libp2p connections per peer.
@@ -53,6 +53,9 @@ pub struct Service<AppReqId: ReqId> {
 
 pub struct Context<'a> {
     pub config: &'a NetworkConfig,
+    /// The identity of the node, which is loaded from or generated in `network_dir` if not
+    /// provided.
+    pub keypair: Option<Keypair>,
 }
 
 impl<AppReqId: ReqId> Service<AppReqId> {
@@ -64,7 +67,10 @@ impl<AppReqId: ReqId> Service<AppReqId> {
 
         let config = ctx.config;
         // initialise the node's ID
-        let local_keypair = load_private_key(config);
+        let local_keypair = match ctx.keypair {
+            Some(keypair) => keypair,
+            None => load_private_key(config),
+        };
 
         // Create an ENR or load from disk if appropriate
         let enr = enr::build_or_load_enr(local_keypair.clone(), config)?;
@@ -82,6 +88,12 @@ impl<AppReqId: ReqId> Service<AppReqId> {
                 .map(|x| PeerId::from(x.clone()))
                 .collect(),
         ));
+        network_globals
+            .sync_bandwidth
+            .set_config(config.sync_bandwidth.clone());
+        network_globals
+            .ip_filter
+            .set_config(config.ip_filter.clone());
 
         info!(
             peer_id = %enr.peer_id(),
@@ -123,17 +135,12 @@ impl<AppReqId: ReqId> Service<AppReqId> {
             let limits = ConnectionLimits::default()
                 .with_max_pending_incoming(Some(5))
                 .with_max_pending_outgoing(Some(16))
-                .with_max_established_incoming(Some(
-                    (config.target_peers as f32
-                        * (1.0 + PEER_EXCESS_FACTOR - MIN_OUTBOUND_ONLY_FACTOR))
-                        .ceil() as u32,
-                ))
-                .with_max_established_outgoing(Some(
-                    (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR)).ceil() as u32,
-                ))
+                .with_max_established_incoming(Some(config.max_inbound_peers() as u32))
+                .with_max_established_outgoing(Some(config.max_peers() as u32))
                 .with_max_established(Some(
-                    (config.target_peers as f32 * (1.0 + PEER_EXCESS_FACTOR + PRIORITY_PEER_EXCESS))
-                        .ceil() as u32,
+                    (config.max_peers()
+                        + (config.target_peers as f32 * PRIORITY_PEER_EXCESS).ceil() as usize)
+                        as u32,
                 ))
                 .with_max_established_per_peer(Some(MAX_CONNECTIONS_PER_PEER));
 
@@ -1,4 +1,6 @@
 //! A collection of variables that are accessible outside of the network thread itself.
+use crate::bandwidth::BandwidthLimiter;
+use crate::ip_filter::IpFilter;
 use crate::peer_manager::peerdb::PeerDB;
 use crate::Client;
 use crate::EnrExt;
@@ -22,6 +24,10 @@ pub struct NetworkGlobals {
     pub peers: RwLock<PeerDB>,
     /// The current gossipsub topic subscriptions.
     pub gossipsub_subscriptions: RwLock<HashSet<GossipTopic>>,
+    /// Bandwidth limiter of the sync protocol, which could be adjusted at runtime.
+    pub sync_bandwidth: BandwidthLimiter,
+    /// Allow and deny lists of IP ranges, which could be reloaded at runtime.
+    pub ip_filter: IpFilter,
 }
 
 impl NetworkGlobals {
@@ -34,6 +40,8 @@ impl NetworkGlobals {
             listen_port_udp: AtomicU16::new(udp_port),
             peers: RwLock::new(PeerDB::new(trusted_peers)),
             gossipsub_subscriptions: RwLock::new(HashSet::new()),
+            sync_bandwidth: Default::default(),
+            ip_filter: Default::default(),
         }
     }
 
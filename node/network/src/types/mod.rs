@@ -6,5 +6,8 @@ mod topics;
 pub type Enr = discv5::enr::Enr<discv5::enr::CombinedKey>;
 
 pub use globals::NetworkGlobals;
-pub use pubsub::{AnnounceFile, FindFile, PubsubMessage, SignedAnnounceFile, SnappyTransform};
+pub use pubsub::{
+    AnnounceFile, AnnounceStorage, ChunkRange, FindFile, NewTx, PubsubMessage, SignedAnnounceFile,
+    SignedAnnounceStorage, SnappyTransform,
+};
 pub use topics::{GossipEncoding, GossipKind, GossipTopic, CORE_TOPICS};
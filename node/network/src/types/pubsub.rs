@@ -6,6 +6,7 @@ use libp2p::{
     gossipsub::{DataTransform, GossipsubMessage, RawGossipsubMessage},
     Multiaddr, PeerId,
 };
+use shared_types::DataRoot;
 use snap::raw::{decompress_len, Decoder, Encoder};
 use ssz::{Decode, Encode};
 use ssz_derive::{Decode, Encode};
@@ -119,15 +120,34 @@ pub struct FindFile {
     pub timestamp: u32,
 }
 
+/// A range of chunks in a file, where `end` is excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct ChunkRange {
+    pub start: u64,
+    pub end: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
 pub struct AnnounceFile {
     pub tx_seq: u64,
     pub peer_id: WrappedPeerId,
     pub at: WrappedMultiaddr,
     pub timestamp: u32,
+    /// Chunk ranges available on the announced peer, which is empty if the whole file is
+    /// available.
+    pub ranges: Vec<ChunkRange>,
 }
 
 impl AnnounceFile {
+    /// Returns the available chunk ranges, or `None` if the whole file is available.
+    pub fn available_ranges(&self) -> Option<Vec<(u64, u64)>> {
+        if self.ranges.is_empty() {
+            return None;
+        }
+
+        Some(self.ranges.iter().map(|r| (r.start, r.end)).collect())
+    }
+
     pub fn into_signed(self, keypair: &Keypair) -> Result<SignedAnnounceFile, SigningError> {
         let raw = self.as_ssz_bytes();
         let signature = keypair.sign(&raw)?;
@@ -162,11 +182,66 @@ impl Deref for SignedAnnounceFile {
     }
 }
 
+/// Announces the data roots of files stored by a node, so that other nodes could find the
+/// providers of files without broadcasting `FindFile` queries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct AnnounceStorage {
+    pub data_roots: Vec<DataRoot>,
+    pub peer_id: WrappedPeerId,
+    pub at: WrappedMultiaddr,
+    pub timestamp: u32,
+}
+
+impl AnnounceStorage {
+    pub fn into_signed(self, keypair: &Keypair) -> Result<SignedAnnounceStorage, SigningError> {
+        let raw = self.as_ssz_bytes();
+        let signature = keypair.sign(&raw)?;
+
+        Ok(SignedAnnounceStorage {
+            inner: self,
+            signature,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct SignedAnnounceStorage {
+    pub inner: AnnounceStorage,
+    pub signature: Vec<u8>,
+}
+
+impl SignedAnnounceStorage {
+    pub fn verify_signature(&self, public_key: &PublicKey) -> bool {
+        let raw = self.inner.as_ssz_bytes();
+        public_key.verify(&raw, &self.signature)
+    }
+}
+
+impl Deref for SignedAnnounceStorage {
+    type Target = AnnounceStorage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// Announces a transaction newly synced from the log contract, so that peers whose log sync lags
+/// behind could locate the file earlier, and peers could cross-verify their views of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+pub struct NewTx {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub size: u64,
+    pub timestamp: u32,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PubsubMessage {
     ExampleMessage(u64),
     FindFile(FindFile),
     AnnounceFile(SignedAnnounceFile),
+    AnnounceStorage(SignedAnnounceStorage),
+    NewTx(NewTx),
 }
 
 // Implements the `DataTransform` trait of gossipsub to employ snappy compression
@@ -242,6 +317,8 @@ impl PubsubMessage {
             PubsubMessage::ExampleMessage(_) => GossipKind::Example,
             PubsubMessage::FindFile(_) => GossipKind::FindFile,
             PubsubMessage::AnnounceFile(_) => GossipKind::AnnounceFile,
+            PubsubMessage::AnnounceStorage(_) => GossipKind::AnnounceStorage,
+            PubsubMessage::NewTx(_) => GossipKind::NewTx,
         }
     }
 
@@ -269,6 +346,13 @@ impl PubsubMessage {
                     GossipKind::AnnounceFile => Ok(PubsubMessage::AnnounceFile(
                         SignedAnnounceFile::from_ssz_bytes(data).map_err(|e| format!("{:?}", e))?,
                     )),
+                    GossipKind::AnnounceStorage => Ok(PubsubMessage::AnnounceStorage(
+                        SignedAnnounceStorage::from_ssz_bytes(data)
+                            .map_err(|e| format!("{:?}", e))?,
+                    )),
+                    GossipKind::NewTx => Ok(PubsubMessage::NewTx(
+                        NewTx::from_ssz_bytes(data).map_err(|e| format!("{:?}", e))?,
+                    )),
                 }
             }
         }
@@ -285,6 +369,8 @@ impl PubsubMessage {
             PubsubMessage::ExampleMessage(data) => data.as_ssz_bytes(),
             PubsubMessage::FindFile(data) => data.as_ssz_bytes(),
             PubsubMessage::AnnounceFile(data) => data.as_ssz_bytes(),
+            PubsubMessage::AnnounceStorage(data) => data.as_ssz_bytes(),
+            PubsubMessage::NewTx(data) => data.as_ssz_bytes(),
         }
     }
 }
@@ -301,6 +387,12 @@ impl std::fmt::Display for PubsubMessage {
             PubsubMessage::AnnounceFile(msg) => {
                 write!(f, "AnnounceFile message: {:?}", msg)
             }
+            PubsubMessage::AnnounceStorage(msg) => {
+                write!(f, "AnnounceStorage message: {:?}", msg)
+            }
+            PubsubMessage::NewTx(msg) => {
+                write!(f, "NewTx message: {:?}", msg)
+            }
         }
     }
 }
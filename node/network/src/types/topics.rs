@@ -10,8 +10,15 @@ pub const SSZ_SNAPPY_ENCODING_POSTFIX: &str = "ssz_snappy";
 pub const EXAMPLE_TOPIC: &str = "example";
 pub const FIND_FILE_TOPIC: &str = "find_file";
 pub const ANNOUNCE_FILE_TOPIC: &str = "announce_file";
+pub const ANNOUNCE_STORAGE_TOPIC: &str = "announce_storage";
+pub const NEW_TX_TOPIC: &str = "new_tx";
 
-pub const CORE_TOPICS: [GossipKind; 2] = [GossipKind::FindFile, GossipKind::AnnounceFile];
+pub const CORE_TOPICS: [GossipKind; 4] = [
+    GossipKind::FindFile,
+    GossipKind::AnnounceFile,
+    GossipKind::AnnounceStorage,
+    GossipKind::NewTx,
+];
 
 /// A gossipsub topic which encapsulates the type of messages that should be sent and received over
 /// the pubsub protocol and the way the messages should be encoded.
@@ -31,6 +38,8 @@ pub enum GossipKind {
     Example,
     FindFile,
     AnnounceFile,
+    AnnounceStorage,
+    NewTx,
 }
 
 /// The known encoding types for gossipsub messages.
@@ -73,6 +82,8 @@ impl GossipTopic {
                 EXAMPLE_TOPIC => GossipKind::Example,
                 FIND_FILE_TOPIC => GossipKind::FindFile,
                 ANNOUNCE_FILE_TOPIC => GossipKind::AnnounceFile,
+                ANNOUNCE_STORAGE_TOPIC => GossipKind::AnnounceStorage,
+                NEW_TX_TOPIC => GossipKind::NewTx,
                 _ => return Err(format!("Unknown topic: {}", topic)),
             };
 
@@ -99,6 +110,8 @@ impl From<GossipTopic> for String {
             GossipKind::Example => EXAMPLE_TOPIC,
             GossipKind::FindFile => FIND_FILE_TOPIC,
             GossipKind::AnnounceFile => ANNOUNCE_FILE_TOPIC,
+            GossipKind::AnnounceStorage => ANNOUNCE_STORAGE_TOPIC,
+            GossipKind::NewTx => NEW_TX_TOPIC,
         };
 
         format!("/{}/{}/{}", TOPIC_PREFIX, kind, encoding)
@@ -115,6 +128,8 @@ impl std::fmt::Display for GossipTopic {
             GossipKind::Example => EXAMPLE_TOPIC,
             GossipKind::FindFile => FIND_FILE_TOPIC,
             GossipKind::AnnounceFile => ANNOUNCE_FILE_TOPIC,
+            GossipKind::AnnounceStorage => ANNOUNCE_STORAGE_TOPIC,
+            GossipKind::NewTx => NEW_TX_TOPIC,
         };
 
         write!(f, "/{}/{}/{}", TOPIC_PREFIX, kind, encoding)
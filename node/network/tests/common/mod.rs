@@ -69,7 +69,10 @@ pub async fn build_libp2p_instance(rt: Weak<Runtime>, boot_nodes: Vec<Enr>) -> L
     let (signal, exit) = exit_future::signal();
     let (shutdown_tx, _) = futures::channel::mpsc::channel(1);
     let executor = task_executor::TaskExecutor::new(rt, exit, shutdown_tx);
-    let libp2p_context = network::Context { config: &config };
+    let libp2p_context = network::Context {
+        config: &config,
+        keypair: None,
+    };
     Libp2pInstance(
         LibP2PService::new(executor, libp2p_context)
             .await
@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate tracing;
 
+mod nat;
 mod service;
+mod validation;
 
 pub use crate::service::RouterService;
@@ -0,0 +1,330 @@
+//! Maps the libp2p TCP port and the discovery UDP port on the gateway with UPnP, or NAT-PMP if
+//! UPnP is not available, so that nodes behind a home router are reachable without manual router
+//! setup.
+//!
+//! The mappings are requested with a lease and renewed periodically, since NAT-PMP gateways and
+//! some UPnP gateways do not support permanent mappings. All the network IO is blocking, and thus
+//! runs in blocking tasks.
+
+use igd::{AddPortError, PortMappingProtocol, SearchOptions};
+use natpmp::{Natpmp, Protocol, Response};
+use network::NetworkConfig;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::thread;
+use std::time::Duration;
+use task_executor::TaskExecutor;
+use tokio::sync::mpsc;
+
+/// Lease of the port mappings in seconds.
+const MAPPING_LEASE_SECS: u32 = 3600;
+
+/// Interval to renew the port mappings, which is less than the lease.
+pub const MAPPING_RENEW_INTERVAL: Duration = Duration::from_secs(1800);
+
+/// Description of the UPnP port mappings.
+const MAPPING_DESCRIPTION: &str = "ionian";
+
+/// Interval to poll the response of the NAT-PMP gateway.
+const NATPMP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Ports to map on the gateway.
+#[derive(Debug, Clone, Copy)]
+pub struct NatConfig {
+    pub tcp_port: u16,
+
+    /// The discovery port, or `None` if discovery is disabled.
+    pub udp_port: Option<u16>,
+}
+
+impl NatConfig {
+    /// Returns `None` if port mapping is disabled.
+    pub fn from_config(config: &NetworkConfig) -> Option<Self> {
+        if !config.upnp_enabled {
+            return None;
+        }
+
+        Some(NatConfig {
+            tcp_port: config.libp2p_port,
+            udp_port: (!config.disable_discovery).then(|| config.discovery_port),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatMethod {
+    Upnp,
+    NatPmp,
+}
+
+/// Port mappings established on the gateway, along with the external sockets.
+#[derive(Debug, Clone)]
+pub struct PortMappings {
+    pub method: NatMethod,
+    pub tcp_socket: Option<SocketAddr>,
+    pub udp_socket: Option<SocketAddr>,
+}
+
+/// Constructs and renews the port mappings in blocking tasks, and removes the mappings on drop.
+pub struct PortMapper {
+    config: Option<NatConfig>,
+    executor: TaskExecutor,
+
+    /// The established port mappings.
+    mappings: Option<PortMappings>,
+
+    mappings_send: mpsc::UnboundedSender<PortMappings>,
+    mappings_recv: mpsc::UnboundedReceiver<PortMappings>,
+}
+
+impl PortMapper {
+    pub fn new(executor: TaskExecutor, config: &NetworkConfig) -> Self {
+        let (mappings_send, mappings_recv) = mpsc::unbounded_channel();
+
+        PortMapper {
+            config: NatConfig::from_config(config),
+            executor,
+            mappings: None,
+            mappings_send,
+            mappings_recv,
+        }
+    }
+
+    /// Constructs or renews the port mappings in a blocking task, whose result is returned by
+    /// `next_mappings`.
+    pub fn renew(&self) {
+        let config = match self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        let mappings_send = self.mappings_send.clone();
+
+        self.executor.spawn_blocking(
+            move || {
+                if let Some(mappings) = construct_mappings(config) {
+                    let _ = mappings_send.send(mappings);
+                }
+            },
+            "port_mapping",
+        );
+    }
+
+    /// Waits for the port mappings to be established, which never completes if port mapping is
+    /// disabled.
+    pub async fn next_mappings(&mut self) -> Option<PortMappings> {
+        self.mappings_recv.recv().await
+    }
+
+    pub fn on_mappings_established(&mut self, mappings: PortMappings) {
+        self.mappings = Some(mappings);
+    }
+}
+
+impl Drop for PortMapper {
+    fn drop(&mut self) {
+        if let (Some(config), Some(mappings)) = (self.config, self.mappings.take()) {
+            remove_mappings(config, &mappings);
+        }
+    }
+}
+
+/// Maps the ports with UPnP, or NAT-PMP if UPnP is not available.
+pub fn construct_mappings(config: NatConfig) -> Option<PortMappings> {
+    match construct_upnp_mappings(config) {
+        Ok(mappings) => return Some(mappings),
+        Err(err) => debug!(%err, "UPnP port mapping failed"),
+    }
+
+    match construct_natpmp_mappings(config) {
+        Ok(mappings) => Some(mappings),
+        Err(err) => {
+            info!(%err, "Neither UPnP nor NAT-PMP is available, no ports mapped");
+            None
+        }
+    }
+}
+
+fn construct_upnp_mappings(config: NatConfig) -> Result<PortMappings, String> {
+    let gateway = igd::search_gateway(SearchOptions::default())
+        .map_err(|e| format!("UPnP gateway not found: {}", e))?;
+
+    let local_ip = local_ipv4(gateway.addr)?;
+    let external_ip = gateway
+        .get_external_ip()
+        .map_err(|e| format!("Failed to get external IP: {}", e))?;
+
+    let map_port = |protocol: PortMappingProtocol, port: u16| -> Option<SocketAddr> {
+        let local_addr = SocketAddrV4::new(local_ip, port);
+        let result = match gateway.add_port(
+            protocol,
+            port,
+            local_addr,
+            MAPPING_LEASE_SECS,
+            MAPPING_DESCRIPTION,
+        ) {
+            // fallback to a permanent mapping, which is removed on shutdown
+            Err(AddPortError::OnlyPermanentLeasesSupported) => {
+                gateway.add_port(protocol, port, local_addr, 0, MAPPING_DESCRIPTION)
+            }
+            result => result,
+        };
+
+        match result {
+            Ok(()) => {
+                info!(%protocol, %external_ip, %port, "UPnP route established");
+                Some(SocketAddr::new(external_ip.into(), port))
+            }
+            Err(err) => {
+                warn!(%protocol, %port, %err, "UPnP failed to map port");
+                None
+            }
+        }
+    };
+
+    let tcp_socket = map_port(PortMappingProtocol::TCP, config.tcp_port);
+    let udp_socket = config
+        .udp_port
+        .and_then(|port| map_port(PortMappingProtocol::UDP, port));
+
+    if tcp_socket.is_none() && udp_socket.is_none() {
+        return Err("UPnP failed to map any port".into());
+    }
+
+    Ok(PortMappings {
+        method: NatMethod::Upnp,
+        tcp_socket,
+        udp_socket,
+    })
+}
+
+/// Returns the local IPv4 address routed to the gateway, without sending any packet.
+fn local_ipv4(gateway: SocketAddrV4) -> Result<Ipv4Addr, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|e| e.to_string())?;
+    socket.connect(gateway).map_err(|e| e.to_string())?;
+
+    match socket.local_addr().map_err(|e| e.to_string())? {
+        SocketAddr::V4(addr) => Ok(*addr.ip()),
+        SocketAddr::V6(_) => Err("IPv6 not supported".into()),
+    }
+}
+
+fn construct_natpmp_mappings(config: NatConfig) -> Result<PortMappings, String> {
+    let mut natpmp = Natpmp::new().map_err(|e| format!("NAT-PMP gateway not found: {:?}", e))?;
+
+    natpmp
+        .send_public_address_request()
+        .map_err(|e| format!("{:?}", e))?;
+    let external_ip = match read_natpmp_response(&mut natpmp)? {
+        Response::Gateway(response) => *response.public_address(),
+        response => return Err(format!("Unexpected NAT-PMP response: {:?}", response)),
+    };
+
+    let mut map_port = |protocol: Protocol, port: u16| -> Option<SocketAddr> {
+        let result = natpmp
+            .send_port_mapping_request(protocol, port, port, MAPPING_LEASE_SECS)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|_| read_natpmp_response(&mut natpmp));
+
+        match result {
+            Ok(Response::TCP(response)) | Ok(Response::UDP(response)) => {
+                let public_port = response.public_port();
+                info!(?protocol, %external_ip, %public_port, "NAT-PMP route established");
+                Some(SocketAddr::new(external_ip.into(), public_port))
+            }
+            Ok(response) => {
+                warn!(?protocol, %port, ?response, "Unexpected NAT-PMP response");
+                None
+            }
+            Err(err) => {
+                warn!(?protocol, %port, %err, "NAT-PMP failed to map port");
+                None
+            }
+        }
+    };
+
+    let tcp_socket = map_port(Protocol::TCP, config.tcp_port);
+    let udp_socket = config
+        .udp_port
+        .and_then(|port| map_port(Protocol::UDP, port));
+
+    if tcp_socket.is_none() && udp_socket.is_none() {
+        return Err("NAT-PMP failed to map any port".into());
+    }
+
+    Ok(PortMappings {
+        method: NatMethod::NatPmp,
+        tcp_socket,
+        udp_socket,
+    })
+}
+
+/// Waits for the response of the NAT-PMP gateway. Requests are resent by the client until the
+/// retries are exhausted.
+fn read_natpmp_response(natpmp: &mut Natpmp) -> Result<Response, String> {
+    loop {
+        match natpmp.read_response_or_retry() {
+            Err(natpmp::Error::NATPMP_TRYAGAIN) => thread::sleep(NATPMP_POLL_INTERVAL),
+            result => return result.map_err(|e| format!("{:?}", e)),
+        }
+    }
+}
+
+/// Removes the port mappings of the local ports from the gateway.
+pub fn remove_mappings(config: NatConfig, mappings: &PortMappings) {
+    let tcp_port = mappings.tcp_socket.map(|_| config.tcp_port);
+    let udp_port = mappings.udp_socket.and(config.udp_port);
+
+    match mappings.method {
+        NatMethod::Upnp => {
+            let gateway = match igd::search_gateway(SearchOptions::default()) {
+                Ok(gateway) => gateway,
+                Err(err) => {
+                    debug!(%err, "UPnP gateway not found to remove port mappings");
+                    return;
+                }
+            };
+
+            let ports = [
+                (PortMappingProtocol::TCP, tcp_port),
+                (PortMappingProtocol::UDP, udp_port),
+            ];
+            for (protocol, port) in ports {
+                if let Some(port) = port {
+                    match gateway.remove_port(protocol, port) {
+                        Ok(()) => debug!(%protocol, %port, "UPnP removed port mapping"),
+                        Err(err) => {
+                            debug!(%protocol, %port, %err, "UPnP failed to remove port mapping")
+                        }
+                    }
+                }
+            }
+        }
+
+        NatMethod::NatPmp => {
+            let mut natpmp = match Natpmp::new() {
+                Ok(natpmp) => natpmp,
+                Err(err) => {
+                    debug!(?err, "NAT-PMP gateway not found to remove port mappings");
+                    return;
+                }
+            };
+
+            // a mapping is removed by requesting zero lifetime
+            for (protocol, port) in [(Protocol::TCP, tcp_port), (Protocol::UDP, udp_port)] {
+                if let Some(port) = port {
+                    let result = natpmp
+                        .send_port_mapping_request(protocol, port, 0, 0)
+                        .map_err(|e| format!("{:?}", e))
+                        .and_then(|_| read_natpmp_response(&mut natpmp));
+
+                    match result {
+                        Ok(_) => debug!(?protocol, %port, "NAT-PMP removed port mapping"),
+                        Err(err) => {
+                            debug!(?protocol, %port, %err, "NAT-PMP failed to remove port mapping")
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
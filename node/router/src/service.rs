@@ -1,46 +1,34 @@
+use crate::nat::{PortMapper, PortMappings, MAPPING_RENEW_INTERVAL};
+use crate::validation::{GossipValidator, Validation, MAX_ANNOUNCED_DATA_ROOTS};
 use file_location_cache::FileLocationCache;
-use futures::{channel::mpsc::Sender, prelude::*};
+use futures::{channel::mpsc::Sender, future::BoxFuture, prelude::*, stream::FuturesUnordered};
 use miner::MinerMessage;
 use network::{
-    rpc::StatusMessage,
-    types::{AnnounceFile, FindFile, SignedAnnounceFile},
-    BehaviourEvent, Keypair, Libp2pEvent, MessageAcceptance, MessageId, NetworkGlobals,
-    NetworkMessage, PeerId, PeerRequestId, PublicKey, PubsubMessage, Request, RequestId, Response,
-    Service as LibP2PService, Swarm,
+    rpc::{GoodbyeReason, OfferFileRequest, RPCError, StatusMessage, PROTOCOL_VERSION},
+    types::{
+        AnnounceFile, AnnounceStorage, ChunkRange, FindFile, NewTx, SignedAnnounceFile,
+        SignedAnnounceStorage,
+    },
+    BehaviourEvent, Keypair, Libp2pEvent, MessageAcceptance, MessageId, Multiaddr, NetworkConfig,
+    NetworkGlobals, NetworkMessage, PeerAction, PeerId, PeerRequestId, PubsubMessage, ReportSource,
+    Request, RequestId, Response, Service as LibP2PService, Swarm,
 };
-use shared_types::timestamp_now;
-use std::{ops::Neg, sync::Arc};
+use shared_types::{timestamp_now, DataRoot, ShardConfig, CHUNK_SIZE};
+use std::{sync::Arc, time::Duration};
 use storage::log_store::Store as LogStore;
 use storage_async::Store;
 use sync::{SyncMessage, SyncSender};
 use task_executor::ShutdownReason;
 use tokio::sync::{mpsc, RwLock};
 
-pub fn peer_id_to_public_key(peer_id: &PeerId) -> Result<PublicKey, String> {
-    // A libp2p peer id byte representation should be 2 length bytes + 4 protobuf bytes + compressed pk bytes
-    // if generated from a PublicKey with Identity multihash.
-    let pk_bytes = &peer_id.to_bytes()[2..];
-
-    PublicKey::from_protobuf_encoding(pk_bytes).map_err(|e| {
-        format!(
-            " Cannot parse libp2p public key public key from peer id: {}",
-            e
-        )
-    })
-}
+/// Interval to announce the data roots of newly stored files in batch.
+const ANNOUNCE_STORAGE_INTERVAL: Duration = Duration::from_secs(30);
 
-fn duration_since(timestamp: u32) -> chrono::Duration {
-    let timestamp = i64::try_from(timestamp).expect("Should fit");
-    let timestamp = chrono::NaiveDateTime::from_timestamp(timestamp, 0);
-    let now = chrono::Utc::now().naive_utc();
-    now.signed_duration_since(timestamp)
-}
+/// Interval to report the under-replicated files.
+const REPLICATION_CHECK_INTERVAL: Duration = Duration::from_secs(300);
 
-lazy_static::lazy_static! {
-    pub static ref FIND_FILE_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
-    pub static ref ANNOUNCE_FILE_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
-    pub static ref TOLERABLE_DRIFT: chrono::Duration = chrono::Duration::seconds(5);
-}
+/// Maximum number of under-replicated files logged in each report.
+const MAX_LOGGED_UNDER_REPLICATED: usize = 16;
 
 /// Service that handles communication between internal services and the libp2p service.
 pub struct RouterService {
@@ -71,6 +59,24 @@ pub struct RouterService {
 
     /// Node keypair for signing messages.
     local_keypair: Keypair,
+
+    /// Data roots of newly stored files to announce in the next `AnnounceStorage` message.
+    data_roots_to_announce: Vec<DataRoot>,
+
+    /// Sync messages delayed to conform to the bandwidth caps.
+    throttled: FuturesUnordered<BoxFuture<'static, NetworkMessage>>,
+
+    /// Maps the listening ports on the gateway with UPnP or NAT-PMP.
+    port_mapper: PortMapper,
+
+    /// Identifier of the blockchain exchanged in the status handshake.
+    chain_id: u64,
+
+    /// Shard of the file data stored by the local node.
+    shard_config: ShardConfig,
+
+    /// Validates the gossip messages and suppresses the duplicate ones.
+    gossip_validator: GossipValidator,
 }
 
 impl RouterService {
@@ -86,8 +92,10 @@ impl RouterService {
         store: Arc<RwLock<dyn LogStore>>,
         file_location_cache: Arc<FileLocationCache>,
         local_keypair: Keypair,
+        config: &NetworkConfig,
     ) {
         let store = Store::new(store, executor.clone());
+        let port_mapper = PortMapper::new(executor.clone(), config);
 
         // create the network service and spawn the task
         let router = RouterService {
@@ -100,6 +108,12 @@ impl RouterService {
             store,
             file_location_cache,
             local_keypair,
+            data_roots_to_announce: vec![],
+            throttled: Default::default(),
+            port_mapper,
+            chain_id: config.chain_id,
+            shard_config: config.shard_config,
+            gossip_validator: Default::default(),
         };
 
         // spawn service
@@ -112,6 +126,10 @@ impl RouterService {
     }
 
     async fn main(mut self, mut shutdown_sender: Sender<ShutdownReason>) {
+        let mut announce_storage_interval = tokio::time::interval(ANNOUNCE_STORAGE_INTERVAL);
+        let mut port_mapping_interval = tokio::time::interval(MAPPING_RENEW_INTERVAL);
+        let mut replication_check_interval = tokio::time::interval(REPLICATION_CHECK_INTERVAL);
+
         loop {
             tokio::select! {
                 // handle a message sent to the network
@@ -119,8 +137,48 @@ impl RouterService {
 
                 // handle event coming from the network
                 event = self.libp2p.next_event() => self.on_libp2p_event(event, &mut shutdown_sender).await,
+
+                // announce newly stored files in batch
+                _ = announce_storage_interval.tick() => self.announce_storage(),
+
+                // report the under-replicated files
+                _ = replication_check_interval.tick() => self.check_replication(),
+
+                // send sync messages delayed by the bandwidth caps
+                Some(msg) = self.throttled.next() => self.send_rpc_msg(msg),
+
+                // construct or renew the port mappings on the gateway
+                _ = port_mapping_interval.tick() => self.port_mapper.renew(),
+
+                // report the external address in ENR once ports mapped
+                Some(mappings) = self.port_mapper.next_mappings() => self.on_port_mappings_established(mappings),
+            }
+        }
+    }
+
+    fn on_port_mappings_established(&mut self, mappings: PortMappings) {
+        let discovery = self.libp2p.swarm.behaviour_mut().discovery_mut();
+
+        if let Some(socket) = mappings.tcp_socket {
+            if let Err(e) = discovery.update_enr_tcp_port(socket.port()) {
+                warn!(error = %e, "Failed to update ENR TCP port");
+            }
+        }
+
+        // the external IP is updated along with the UDP socket
+        if let Some(socket) = mappings.udp_socket {
+            if let Err(e) = discovery.update_enr_udp_socket(socket) {
+                warn!(error = %e, "Failed to update ENR UDP socket");
             }
         }
+
+        info!(
+            method = ?mappings.method,
+            tcp = ?mappings.tcp_socket,
+            udp = ?mappings.udp_socket,
+            "Port mappings established",
+        );
+        self.port_mapper.on_mappings_established(mappings);
     }
 
     fn send_to_sync(&mut self, message: SyncMessage) {
@@ -152,7 +210,7 @@ impl RouterService {
         match ev {
             Libp2pEvent::Behaviour(event) => match event {
                 BehaviourEvent::PeerConnectedOutgoing(peer_id) => {
-                    self.on_peer_connected(peer_id);
+                    self.on_peer_connected(peer_id).await;
                 }
                 BehaviourEvent::PeerConnectedIncoming(_)
                 | BehaviourEvent::PeerBanned(_)
@@ -167,20 +225,20 @@ impl RouterService {
                     id,
                     request,
                 } => {
-                    self.on_rpc_request(peer_id, id, request);
+                    self.on_rpc_request(peer_id, id, request).await;
                 }
                 BehaviourEvent::ResponseReceived {
                     peer_id,
                     id,
                     response,
                 } => {
-                    self.on_rpc_response(peer_id, id, response);
+                    self.on_rpc_response(peer_id, id, response).await;
                 }
-                BehaviourEvent::RPCFailed { id, peer_id } => {
-                    self.on_rpc_error(peer_id, id);
+                BehaviourEvent::RPCFailed { id, peer_id, error } => {
+                    self.on_rpc_error(peer_id, id, error);
                 }
                 BehaviourEvent::StatusPeer(peer_id) => {
-                    self.send_status(peer_id);
+                    self.send_status(peer_id).await;
                 }
                 BehaviourEvent::PubsubMessage {
                     id,
@@ -224,19 +282,17 @@ impl RouterService {
         debug!(?msg, "Received new message");
 
         match msg {
-            NetworkMessage::SendRequest {
-                peer_id,
-                request,
-                request_id,
-            } => {
-                self.libp2p.send_request(peer_id, request_id, request);
-            }
-            NetworkMessage::SendResponse {
-                peer_id,
-                response,
-                id,
-            } => {
-                self.libp2p.send_response(peer_id, id, response);
+            NetworkMessage::SendRequest { .. } | NetworkMessage::SendResponse { .. } => {
+                let delay = self.sync_bandwidth_delay(&msg);
+                if delay.is_zero() {
+                    self.send_rpc_msg(msg);
+                } else {
+                    debug!(?delay, "Sync message throttled");
+                    self.throttled.push(Box::pin(async move {
+                        tokio::time::sleep(delay).await;
+                        msg
+                    }));
+                }
             }
             NetworkMessage::SendErrorResponse {
                 peer_id,
@@ -285,15 +341,109 @@ impl RouterService {
                 }
             }
             NetworkMessage::AnnounceLocalFile { tx_seq } => {
-                if let Some(msg) = self.construct_announce_file_message(tx_seq) {
+                if let Some(msg) = self.construct_announce_file_message(tx_seq, vec![]) {
                     self.publish(msg);
                 }
+
+                match self.store.get_tx_by_seq_number(tx_seq).await {
+                    Ok(Some(tx)) => self.data_roots_to_announce.push(tx.data_merkle_root),
+                    Ok(None) => warn!(%tx_seq, "Announced local file not found"),
+                    Err(e) => error!(%tx_seq, %e, "Failed to get announced local file"),
+                }
+            }
+            NetworkMessage::DisconnectAll => {
+                let peers: Vec<_> = self
+                    .network_globals
+                    .peers
+                    .read()
+                    .connected_peer_ids()
+                    .cloned()
+                    .collect();
+                info!(num_peers = %peers.len(), "Disconnecting all peers");
+                for peer_id in peers {
+                    self.libp2p.goodbye_peer(
+                        &peer_id,
+                        GoodbyeReason::ClientShutdown,
+                        ReportSource::Processor,
+                    );
+                }
+            }
+            NetworkMessage::UpdateEnr { update } => {
+                let discovery = self.libp2p.swarm.behaviour_mut().discovery_mut();
+                match discovery.update_enr(&update) {
+                    Ok(true) => {
+                        info!(?update, seq = %discovery.local_enr().seq(), "Local ENR updated")
+                    }
+                    Ok(false) => debug!(?update, "Local ENR unchanged"),
+                    Err(e) => {
+                        warn!(?update, error = %e, "Failed to update local ENR");
+                        return;
+                    }
+                }
+
+                // the shard config is also exchanged in status with new peers
+                if let Some(shard_config) = update.shard_config {
+                    self.shard_config = shard_config;
+                }
+            }
+        }
+    }
+
+    /// Returns how long to delay the message to conform to the bandwidth caps of sync protocol,
+    /// where the bandwidth of chunks to download is reserved when requested.
+    fn sync_bandwidth_delay(&self, msg: &NetworkMessage) -> Duration {
+        let bandwidth = &self.network_globals.sync_bandwidth;
+
+        match msg {
+            NetworkMessage::SendRequest {
+                peer_id,
+                request: Request::GetChunks(request),
+                ..
+            } => {
+                let num_chunks = request.index_end.saturating_sub(request.index_start);
+                bandwidth.reserve_download(*peer_id, num_chunks * CHUNK_SIZE as u64)
+            }
+            NetworkMessage::SendResponse {
+                peer_id,
+                response: Response::Chunks(response),
+                ..
+            } => bandwidth.reserve_upload(*peer_id, response.chunks.data.len() as u64),
+            NetworkMessage::SendResponse {
+                peer_id,
+                response: Response::ErasureShard(shard),
+                ..
+            } => bandwidth.reserve_upload(*peer_id, shard.data.len() as u64),
+            NetworkMessage::SendResponse {
+                peer_id,
+                response: Response::PendingChunks(response),
+                ..
+            } => bandwidth.reserve_upload(*peer_id, response.chunks.data.len() as u64),
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn send_rpc_msg(&mut self, msg: NetworkMessage) {
+        match msg {
+            NetworkMessage::SendRequest {
+                peer_id,
+                request,
+                request_id,
+            } => {
+                self.libp2p.send_request(peer_id, request_id, request);
+            }
+            NetworkMessage::SendResponse {
+                peer_id,
+                response,
+                id,
+            } => {
+                self.libp2p.send_response(peer_id, id, response);
             }
+            _ => error!(?msg, "Unexpected RPC message to send"),
         }
     }
 
-    fn on_peer_connected(&mut self, peer_id: PeerId) {
-        self.send_status(peer_id);
+    async fn on_peer_connected(&mut self, peer_id: PeerId) {
+        self.send_status(peer_id).await;
         self.send_to_sync(SyncMessage::PeerConnected { peer_id });
     }
 
@@ -301,7 +451,12 @@ impl RouterService {
         self.send_to_sync(SyncMessage::PeerDisconnected { peer_id });
     }
 
-    fn on_rpc_request(&mut self, peer_id: PeerId, request_id: PeerRequestId, request: Request) {
+    async fn on_rpc_request(
+        &mut self,
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: Request,
+    ) {
         if !self.network_globals.peers.read().is_connected(&peer_id) {
             debug!(%peer_id, ?request, "Dropping request of disconnected peer");
             return;
@@ -309,7 +464,7 @@ impl RouterService {
 
         match request {
             Request::Status(status) => {
-                self.on_status_request(peer_id, request_id, status);
+                self.on_status_request(peer_id, request_id, status).await;
             }
             Request::GetChunks(request) => {
                 self.send_to_sync(SyncMessage::RequestChunks {
@@ -318,16 +473,61 @@ impl RouterService {
                     request,
                 });
             }
+            Request::OfferFile(request) => {
+                self.on_offer_file_request(peer_id, request);
+            }
+            Request::GetErasureShard(request) => {
+                self.send_to_sync(SyncMessage::RequestErasureShard {
+                    peer_id,
+                    request_id,
+                    request,
+                });
+            }
+            Request::GetPendingChunks(request) => {
+                self.send_to_sync(SyncMessage::RequestPendingChunks {
+                    peer_id,
+                    request_id,
+                    request,
+                });
+            }
             Request::DataByHash(_) => {
                 // ignore
             }
         }
     }
 
-    fn on_rpc_response(&mut self, peer_id: PeerId, request_id: RequestId, response: Response) {
+    fn on_offer_file_request(&mut self, peer_id: PeerId, request: OfferFileRequest) {
+        // the offering peer will be dialed by sync if not connected any more
+        let addr = match self
+            .network_globals
+            .peers
+            .read()
+            .peer_info(&peer_id)
+            .and_then(|info| info.listening_addresses().first().cloned())
+        {
+            Some(addr) => addr,
+            None => {
+                debug!(%peer_id, ?request, "Dropping file offer of peer without listening address");
+                return;
+            }
+        };
+
+        self.send_to_sync(SyncMessage::OfferFile {
+            tx_seq: request.tx_seq,
+            peer_id,
+            addr,
+        });
+    }
+
+    async fn on_rpc_response(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: Response,
+    ) {
         match response {
             Response::Status(status_message) => {
-                self.on_status_response(peer_id, status_message);
+                self.on_status_response(peer_id, status_message).await;
             }
             Response::Chunks(response) => {
                 let request_id = match request_id {
@@ -341,24 +541,89 @@ impl RouterService {
                     response,
                 });
             }
+            Response::ErasureShard(response) => {
+                let request_id = match request_id {
+                    RequestId::Sync(sync_id) => sync_id,
+                    _ => unreachable!("All ErasureShard responses belong to sync"),
+                };
+
+                self.send_to_sync(SyncMessage::ErasureShardResponse {
+                    peer_id,
+                    request_id,
+                    response,
+                });
+            }
+            Response::PendingChunks(response) => {
+                let request_id = match request_id {
+                    RequestId::Sync(sync_id) => sync_id,
+                    _ => unreachable!("All PendingChunks responses belong to sync"),
+                };
+
+                self.send_to_sync(SyncMessage::PendingChunksResponse {
+                    peer_id,
+                    request_id,
+                    response,
+                });
+            }
             Response::DataByHash(_) => {
                 // ignore
             }
         }
     }
 
-    fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId) {
+    fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId, error: RPCError) {
         // Check if the failed RPC belongs to sync
         if let RequestId::Sync(request_id) = request_id {
             self.send_to_sync(SyncMessage::RpcError {
                 peer_id,
                 request_id,
+                error,
             });
         }
     }
 
-    fn send_status(&mut self, peer_id: PeerId) {
-        let status_message = StatusMessage { data: 123 }; // dummy status message
+    /// Returns the status of the local node for the handshake with peers.
+    async fn local_status(&self) -> StatusMessage {
+        let next_tx_seq = match self.store.next_tx_seq().await {
+            Ok(next_tx_seq) => next_tx_seq,
+            Err(e) => {
+                error!(%e, "Failed to get next tx seq");
+                0
+            }
+        };
+
+        StatusMessage {
+            protocol_version: PROTOCOL_VERSION,
+            chain_id: self.chain_id,
+            shard_config: self.shard_config,
+            next_tx_seq,
+        }
+    }
+
+    /// Disconnects the peer if its status is incompatible with the local node, and returns
+    /// whether the peer is compatible.
+    fn check_peer_status(
+        &mut self,
+        peer_id: PeerId,
+        local: &StatusMessage,
+        status: &StatusMessage,
+    ) -> bool {
+        match local.check_compatible(status) {
+            Ok(()) => true,
+            Err(reason) => {
+                info!(%peer_id, %reason, "Disconnecting incompatible peer");
+                self.libp2p.goodbye_peer(
+                    &peer_id,
+                    GoodbyeReason::IrrelevantNetwork,
+                    ReportSource::Processor,
+                );
+                false
+            }
+        }
+    }
+
+    async fn send_status(&mut self, peer_id: PeerId) {
+        let status_message = self.local_status().await;
         debug!(%peer_id, ?status_message, "Sending Status request");
 
         self.send_to_network(NetworkMessage::SendRequest {
@@ -368,7 +633,7 @@ impl RouterService {
         })
     }
 
-    fn on_status_request(
+    async fn on_status_request(
         &mut self,
         peer_id: PeerId,
         request_id: PeerRequestId,
@@ -376,7 +641,11 @@ impl RouterService {
     ) {
         debug!(%peer_id, ?status, "Received Status request");
 
-        let status_message = StatusMessage { data: 456 }; // dummy status message
+        let status_message = self.local_status().await;
+        if !self.check_peer_status(peer_id, &status_message, &status) {
+            return;
+        }
+
         debug!(%peer_id, ?status_message, "Sending Status response");
 
         self.send_to_network(NetworkMessage::SendResponse {
@@ -386,8 +655,11 @@ impl RouterService {
         });
     }
 
-    pub fn on_status_response(&mut self, peer_id: PeerId, status: StatusMessage) {
+    pub async fn on_status_response(&mut self, peer_id: PeerId, status: StatusMessage) {
         debug!(%peer_id, ?status, "Received Status response");
+
+        let local = self.local_status().await;
+        self.check_peer_status(peer_id, &local, &status);
     }
 
     async fn on_pubsub_message(
@@ -399,10 +671,32 @@ impl RouterService {
     ) {
         info!(?message, %propagation_source, %source, %id, "Received pubsub message");
 
-        let result = match message {
-            PubsubMessage::ExampleMessage(_) => MessageAcceptance::Ignore,
-            PubsubMessage::FindFile(msg) => self.on_find_file(msg).await,
-            PubsubMessage::AnnounceFile(msg) => self.on_announce_file(propagation_source, msg),
+        let result = match self.gossip_validator.validate(&message) {
+            Validation::Valid => match message {
+                PubsubMessage::ExampleMessage(_) => MessageAcceptance::Ignore,
+                PubsubMessage::FindFile(msg) => self.on_find_file(msg).await,
+                PubsubMessage::AnnounceFile(msg) => self.on_announce_file(msg),
+                PubsubMessage::AnnounceStorage(msg) => self.on_announce_storage(msg),
+                PubsubMessage::NewTx(msg) => self.on_new_tx(propagation_source, msg).await,
+            },
+            Validation::Duplicate => {
+                debug!(%id, "Ignoring duplicate pubsub message");
+                MessageAcceptance::Ignore
+            }
+            Validation::Ignore(reason) => {
+                debug!(%id, %reason, "Ignoring pubsub message");
+                MessageAcceptance::Ignore
+            }
+            Validation::Reject(reason) => {
+                warn!(%propagation_source, %id, %reason, "Rejecting invalid pubsub message");
+                self.libp2p.report_peer(
+                    &propagation_source,
+                    PeerAction::LowToleranceError,
+                    ReportSource::Gossipsub,
+                    reason,
+                );
+                MessageAcceptance::Reject
+            }
         };
 
         self.libp2p
@@ -411,7 +705,13 @@ impl RouterService {
             .report_message_validation_result(&propagation_source, id, result);
     }
 
-    fn construct_announce_file_message(&self, tx_seq: u64) -> Option<PubsubMessage> {
+    /// Constructs the `AnnounceFile` message of the local node, where empty `ranges` means the
+    /// whole file is available.
+    fn construct_announce_file_message(
+        &self,
+        tx_seq: u64,
+        ranges: Vec<ChunkRange>,
+    ) -> Option<PubsubMessage> {
         let peer_id = *self.network_globals.peer_id.read();
 
         let addr = match self.network_globals.listen_multiaddrs.read().first() {
@@ -429,6 +729,7 @@ impl RouterService {
             peer_id: peer_id.into(),
             at: addr.into(),
             timestamp,
+            ranges,
         };
 
         let mut signed = match msg.into_signed(&self.local_keypair) {
@@ -444,21 +745,71 @@ impl RouterService {
         Some(PubsubMessage::AnnounceFile(signed))
     }
 
-    async fn on_find_file(&mut self, msg: FindFile) -> MessageAcceptance {
-        let FindFile { tx_seq, timestamp } = msg;
+    /// Publishes the data roots of newly stored files, if any.
+    fn announce_storage(&mut self) {
+        if self.data_roots_to_announce.is_empty() {
+            return;
+        }
 
-        // verify timestamp
-        let d = duration_since(timestamp);
-        if d < TOLERABLE_DRIFT.neg() || d > *FIND_FILE_TIMEOUT {
-            debug!(%timestamp, "Invalid timestamp, ignoring FindFile message");
-            return MessageAcceptance::Ignore;
+        let peer_id = *self.network_globals.peer_id.read();
+
+        let addr = match self.network_globals.listen_multiaddrs.read().first() {
+            Some(addr) => addr.clone(),
+            None => {
+                error!("No listen address available");
+                return;
+            }
+        };
+
+        let data_roots = std::mem::take(&mut self.data_roots_to_announce);
+        let timestamp = timestamp_now();
+
+        // the local node is also a replica, which never receives its own announcements
+        self.file_location_cache
+            .insert_replicas(peer_id, &data_roots, timestamp);
+
+        for data_roots in data_roots.chunks(MAX_ANNOUNCED_DATA_ROOTS) {
+            let msg = AnnounceStorage {
+                data_roots: data_roots.to_vec(),
+                peer_id: peer_id.into(),
+                at: addr.clone().into(),
+                timestamp,
+            };
+
+            match msg.into_signed(&self.local_keypair) {
+                Ok(signed) => self.publish(PubsubMessage::AnnounceStorage(signed)),
+                Err(e) => error!(%e, "Failed to sign AnnounceStorage message"),
+            }
+        }
+    }
+
+    /// Warns of the files announced by fewer nodes than the minimum replicas, and updates the
+    /// replication metrics.
+    fn check_replication(&self) {
+        let under_replicated = self.file_location_cache.under_replicated();
+        if under_replicated.is_empty() {
+            return;
+        }
+
+        warn!(
+            num_files = %under_replicated.len(),
+            min_replicas = %self.file_location_cache.min_replicas(),
+            "Found under-replicated files",
+        );
+
+        for (data_root, replicas) in under_replicated.iter().take(MAX_LOGGED_UNDER_REPLICATED) {
+            debug!(?data_root, %replicas, "Under-replicated file");
         }
+    }
+
+    async fn on_find_file(&mut self, msg: FindFile) -> MessageAcceptance {
+        let FindFile { tx_seq, .. } = msg;
 
         // check if we have it
         if matches!(self.store.check_tx_completed(tx_seq).await, Ok(true)) {
             debug!(%tx_seq, "Found file locally, responding to FindFile query");
 
-            return match self.construct_announce_file_message(tx_seq) {
+            return match self.construct_announce_file_message(tx_seq, vec![]) {
                 Some(msg) => {
                     self.publish(msg);
                     MessageAcceptance::Ignore
@@ -468,6 +819,27 @@ impl RouterService {
             };
         }
 
+        // announce the partially available file, and propagate the query to find other providers
+        if let Ok(Some(ranges)) = self.store.get_available_ranges(tx_seq).await {
+            if !ranges.is_empty() {
+                debug!(%tx_seq, ?ranges, "Found partial file locally, responding to FindFile query");
+
+                let ranges = ranges
+                    .into_iter()
+                    .map(|(start, end)| ChunkRange {
+                        start: start as u64,
+                        end: end as u64,
+                    })
+                    .collect();
+
+                if let Some(msg) = self.construct_announce_file_message(tx_seq, ranges) {
+                    self.publish(msg);
+                }
+
+                return MessageAcceptance::Accept;
+            }
+        }
+
         // try from cache
         if let Some(mut msg) = self.file_location_cache.get_one(tx_seq) {
             debug!(%tx_seq, "Found file in cache, responding to FindFile query");
@@ -482,43 +854,13 @@ impl RouterService {
         MessageAcceptance::Accept
     }
 
-    fn on_announce_file(
-        &mut self,
-        propagation_source: PeerId,
-        msg: SignedAnnounceFile,
-    ) -> MessageAcceptance {
-        // verify message signature
-        let pk = match peer_id_to_public_key(&msg.peer_id) {
-            Ok(pk) => pk,
-            Err(e) => {
-                error!(
-                    "Failed to convert peer id {:?} to public key: {:?}",
-                    msg.peer_id, e
-                );
-                return MessageAcceptance::Reject;
-            }
-        };
-
-        if !msg.verify_signature(&pk) {
-            warn!(
-                "Received message with invalid signature from peer {:?}",
-                propagation_source
-            );
-            return MessageAcceptance::Reject;
-        }
-
-        // propagate gossip to peers
-        let d = duration_since(msg.resend_timestamp);
-        if d < TOLERABLE_DRIFT.neg() || d > *ANNOUNCE_FILE_TIMEOUT {
-            debug!(%msg.resend_timestamp, "Invalid resend timestamp, ignoring AnnounceFile message");
-            return MessageAcceptance::Ignore;
-        }
-
+    fn on_announce_file(&mut self, msg: SignedAnnounceFile) -> MessageAcceptance {
         // notify sync layer
         self.send_to_sync(SyncMessage::AnnounceFileGossip {
             tx_seq: msg.tx_seq,
             peer_id: msg.peer_id.clone().into(),
             addr: msg.at.clone().into(),
+            ranges: msg.available_ranges(),
         });
 
         // insert message to cache
@@ -526,6 +868,68 @@ impl RouterService {
 
         MessageAcceptance::Accept
     }
+
+    fn on_announce_storage(&mut self, msg: SignedAnnounceStorage) -> MessageAcceptance {
+        let peer_id: PeerId = msg.peer_id.clone().into();
+        let addr: Multiaddr = msg.at.clone().into();
+
+        // index the providers
+        self.file_location_cache.insert_data_roots(
+            peer_id,
+            addr.clone(),
+            &msg.data_roots,
+            msg.timestamp,
+        );
+
+        // notify sync layer
+        self.send_to_sync(SyncMessage::AnnounceStorageGossip {
+            data_roots: msg.data_roots.clone(),
+            peer_id,
+            addr,
+        });
+
+        MessageAcceptance::Accept
+    }
+
+    /// Cross-verifies the new transaction with the local log, or notifies the sync layer if the
+    /// local log sync lags behind.
+    async fn on_new_tx(&mut self, propagation_source: PeerId, msg: NewTx) -> MessageAcceptance {
+        let NewTx {
+            tx_seq,
+            data_root,
+            size,
+            ..
+        } = msg;
+
+        match self.store.get_tx_by_seq_number(tx_seq).await {
+            Ok(Some(tx)) => {
+                // Either the peer or the local node may have an outdated view of the chain, e.g.
+                // during a chain reorg, so the message is not propagated but the peer is not penalized.
+                if tx.data_merkle_root != data_root || tx.size != size {
+                    warn!(
+                        %propagation_source, %tx_seq, ?data_root, %size,
+                        local_data_root = ?tx.data_merkle_root, local_size = %tx.size,
+                        "NewTx message mismatches the local log"
+                    );
+                    return MessageAcceptance::Ignore;
+                }
+            }
+            Ok(None) => {
+                // notify sync layer
+                self.send_to_sync(SyncMessage::NewTxGossip {
+                    tx_seq,
+                    data_root,
+                    size,
+                });
+            }
+            Err(e) => {
+                error!(%tx_seq, %e, "Failed to get transaction from store");
+                return MessageAcceptance::Ignore;
+            }
+        }
+
+        MessageAcceptance::Accept
+    }
 }
 
 impl Drop for RouterService {
@@ -0,0 +1,174 @@
+//! Validation of incoming gossip messages before any state is touched. The shape, timestamps and
+//! signatures of messages are checked, and the duplicate ones are suppressed by a seen-cache.
+
+use futures::FutureExt;
+use futures::StreamExt;
+use hashset_delay::HashMapDelay;
+use network::types::{AnnounceFile, AnnounceStorage, SignedAnnounceFile};
+use network::{PeerId, PublicKey, PubsubMessage};
+use shared_types::DataRoot;
+use std::ops::Neg;
+use std::time::Duration;
+
+lazy_static::lazy_static! {
+    pub static ref FIND_FILE_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
+    pub static ref ANNOUNCE_FILE_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
+    pub static ref ANNOUNCE_STORAGE_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
+    pub static ref NEW_TX_TIMEOUT: chrono::Duration = chrono::Duration::minutes(2);
+    pub static ref TOLERABLE_DRIFT: chrono::Duration = chrono::Duration::seconds(5);
+}
+
+/// Maximum number of data roots in a single `AnnounceStorage` message.
+pub const MAX_ANNOUNCED_DATA_ROOTS: usize = 1024;
+
+/// How long the validated messages are remembered. Messages are regarded as outdated by their
+/// timestamps afterwards, so that replays are still ignored.
+const SEEN_CACHE_TIMEOUT: Duration = Duration::from_secs(125);
+
+pub fn peer_id_to_public_key(peer_id: &PeerId) -> Result<PublicKey, String> {
+    // A libp2p peer id byte representation should be 2 length bytes + 4 protobuf bytes + compressed pk bytes
+    // if generated from a PublicKey with Identity multihash.
+    let pk_bytes = &peer_id.to_bytes()[2..];
+
+    PublicKey::from_protobuf_encoding(pk_bytes).map_err(|e| {
+        format!(
+            " Cannot parse libp2p public key public key from peer id: {}",
+            e
+        )
+    })
+}
+
+fn duration_since(timestamp: u32) -> chrono::Duration {
+    let timestamp = i64::try_from(timestamp).expect("Should fit");
+    let timestamp = chrono::NaiveDateTime::from_timestamp(timestamp, 0);
+    let now = chrono::Utc::now().naive_utc();
+    now.signed_duration_since(timestamp)
+}
+
+/// Whether the timestamp is neither too old nor too far in the future.
+fn is_fresh(timestamp: u32, timeout: chrono::Duration) -> bool {
+    let d = duration_since(timestamp);
+    d >= TOLERABLE_DRIFT.neg() && d <= timeout
+}
+
+/// Outcome of validating a gossip message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// The message is valid and seen for the first time, so it should be processed.
+    Valid,
+    /// The message is valid but seen recently, so it has been processed already.
+    Duplicate,
+    /// The message is useless, e.g. outdated, but not necessarily malicious.
+    Ignore(&'static str),
+    /// The message is invalid, and the propagation source should be penalized.
+    Reject(&'static str),
+}
+
+/// Identifies the messages of the same content. Announcements resent with different timestamps
+/// are regarded as duplicates, while forged ones with different signatures are not.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum SeenKey {
+    AnnounceFile(AnnounceFile, Vec<u8>),
+    AnnounceStorage(AnnounceStorage, Vec<u8>),
+    NewTx(u64, DataRoot, u64),
+}
+
+/// Validates the gossip messages, and remembers the valid and rejected ones for a while to
+/// suppress the duplicates.
+pub struct GossipValidator {
+    seen: HashMapDelay<SeenKey, Validation>,
+}
+
+impl Default for GossipValidator {
+    fn default() -> Self {
+        GossipValidator {
+            seen: HashMapDelay::new(SEEN_CACHE_TIMEOUT),
+        }
+    }
+}
+
+impl GossipValidator {
+    pub fn validate(&mut self, message: &PubsubMessage) -> Validation {
+        self.prune();
+
+        let key = match message {
+            PubsubMessage::ExampleMessage(_) => return Validation::Ignore("Example message"),
+            PubsubMessage::FindFile(msg) => {
+                // queries are distinct by timestamps, and deduplicated by gossipsub already
+                if !is_fresh(msg.timestamp, *FIND_FILE_TIMEOUT) {
+                    return Validation::Ignore("Invalid FindFile timestamp");
+                }
+                return Validation::Valid;
+            }
+            PubsubMessage::AnnounceFile(msg) => {
+                if !is_fresh(msg.resend_timestamp, *ANNOUNCE_FILE_TIMEOUT) {
+                    return Validation::Ignore("Invalid AnnounceFile resend timestamp");
+                }
+                SeenKey::AnnounceFile(msg.inner.clone(), msg.signature.clone())
+            }
+            PubsubMessage::AnnounceStorage(msg) => {
+                if !is_fresh(msg.timestamp, *ANNOUNCE_STORAGE_TIMEOUT) {
+                    return Validation::Ignore("Invalid AnnounceStorage timestamp");
+                }
+                SeenKey::AnnounceStorage(msg.inner.clone(), msg.signature.clone())
+            }
+            PubsubMessage::NewTx(msg) => {
+                if !is_fresh(msg.timestamp, *NEW_TX_TIMEOUT) {
+                    return Validation::Ignore("Invalid NewTx timestamp");
+                }
+                SeenKey::NewTx(msg.tx_seq, msg.data_root, msg.size)
+            }
+        };
+
+        match self.seen.get(&key) {
+            Some(Validation::Valid) => return Validation::Duplicate,
+            Some(validation) => return *validation,
+            None => {}
+        }
+
+        let validation = match message {
+            PubsubMessage::AnnounceFile(msg) => validate_announce_file(msg),
+            PubsubMessage::AnnounceStorage(msg) => {
+                if msg.data_roots.is_empty() || msg.data_roots.len() > MAX_ANNOUNCED_DATA_ROOTS {
+                    Validation::Reject("Invalid number of data roots in AnnounceStorage message")
+                } else {
+                    verify_signature(&msg.peer_id.clone().into(), |pk| msg.verify_signature(pk))
+                }
+            }
+            _ => Validation::Valid,
+        };
+
+        self.seen.insert(key, validation);
+        validation
+    }
+
+    /// Removes the expired messages from the seen-cache.
+    fn prune(&mut self) {
+        while let Some(Some(result)) = self.seen.next().now_or_never() {
+            if let Err(e) = result {
+                warn!(%e, "Failed to remove expired gossip message");
+            }
+        }
+    }
+}
+
+fn validate_announce_file(msg: &SignedAnnounceFile) -> Validation {
+    let ranges_valid = msg.ranges.iter().all(|r| r.start < r.end)
+        && msg.ranges.windows(2).all(|w| w[0].end <= w[1].start);
+    if !ranges_valid {
+        return Validation::Reject("Invalid chunk ranges in AnnounceFile message");
+    }
+
+    verify_signature(&msg.peer_id.clone().into(), |pk| msg.verify_signature(pk))
+}
+
+fn verify_signature(peer_id: &PeerId, verify: impl FnOnce(&PublicKey) -> bool) -> Validation {
+    match peer_id_to_public_key(peer_id) {
+        Ok(pk) if verify(&pk) => Validation::Valid,
+        Ok(_) => Validation::Reject("Invalid signature of pubsub message"),
+        Err(e) => {
+            debug!(%peer_id, %e, "Failed to convert peer id to public key");
+            Validation::Reject("Invalid peer id of pubsub message")
+        }
+    }
+}
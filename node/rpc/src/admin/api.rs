@@ -1,5 +1,7 @@
-use crate::types::RpcResult;
+use crate::types::{FileInfo, PeerInfo, RpcResult, StoreIoStats, TxListFilter};
 use jsonrpsee::proc_macros::rpc;
+use network::{BandwidthConfig, EnrUpdate, IpFilterConfig};
+use shared_types::DataRoot;
 
 #[rpc(server, client, namespace = "admin")]
 pub trait Rpc {
@@ -11,4 +13,69 @@ pub trait Rpc {
 
     #[method(name = "getSyncStatus")]
     async fn get_sync_status(&self, tx_seq: u64) -> RpcResult<String>;
+
+    /// Returns the connected peers along with the RPC traffic, sorted by the bytes received.
+    #[method(name = "peers")]
+    async fn peers(&self) -> RpcResult<Vec<PeerInfo>>;
+
+    /// Returns the I/O of the store by the subsystems that issue it.
+    #[method(name = "getStoreStats")]
+    async fn get_store_stats(&self) -> RpcResult<Vec<StoreIoStats>>;
+
+    #[method(name = "getSyncBandwidth")]
+    async fn get_sync_bandwidth(&self) -> RpcResult<BandwidthConfig>;
+
+    /// Updates the bandwidth caps of the sync protocol, which take effect immediately.
+    #[method(name = "setSyncBandwidth")]
+    async fn set_sync_bandwidth(&self, config: BandwidthConfig) -> RpcResult<()>;
+
+    #[method(name = "getIpFilter")]
+    async fn get_ip_filter(&self) -> RpcResult<IpFilterConfig>;
+
+    /// Replaces the allow and deny lists of IP ranges, which apply to new connections.
+    #[method(name = "setIpFilter")]
+    async fn set_ip_filter(&self, config: IpFilterConfig) -> RpcResult<()>;
+
+    /// Updates the storage fields of the local ENR, e.g. the RPC port once the RPC service is
+    /// public, which are republished through discovery without restarting the node.
+    #[method(name = "updateEnr")]
+    async fn update_enr(&self, update: EnrUpdate) -> RpcResult<()>;
+
+    #[method(name = "getLogFilter")]
+    async fn get_log_filter(&self) -> RpcResult<String>;
+
+    /// Replaces the tracing filter directives, e.g. `info,sync=debug`, until the log config file
+    /// changes or is reloaded upon SIGHUP.
+    #[method(name = "setLogFilter")]
+    async fn set_log_filter(&self, directives: String) -> RpcResult<()>;
+
+    /// Writes the finalized file of `data_root` to `path` on the node's filesystem.
+    #[method(name = "exportFile")]
+    async fn export_file(&self, data_root: DataRoot, path: String) -> RpcResult<()>;
+
+    /// Writes the file in `path` on the node's filesystem to the store as the data of `tx_seq`,
+    /// which is verified against the data root of the transaction, and then announces the file.
+    #[method(name = "importFile")]
+    async fn import_file(&self, tx_seq: u64, path: String) -> RpcResult<()>;
+
+    /// Pages through the transactions from `start_seq`, and returns at most `limit` ones that
+    /// match the filter. The next page starts from the sequence number of the last one plus 1.
+    #[method(name = "getTxList")]
+    async fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: Option<TxListFilter>,
+    ) -> RpcResult<Vec<FileInfo>>;
+
+    #[method(name = "startMining")]
+    async fn start_mining(&self) -> RpcResult<()>;
+
+    #[method(name = "stopMining")]
+    async fn stop_mining(&self) -> RpcResult<()>;
+
+    /// Replaces the key to sign the mine transactions with the one in `key_file`, which is an
+    /// encrypted keystore if `password` is provided.
+    #[method(name = "rotateMinerKey")]
+    async fn rotate_miner_key(&self, key_file: String, password: Option<String>) -> RpcResult<()>;
 }
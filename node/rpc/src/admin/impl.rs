@@ -1,10 +1,14 @@
 use super::api::RpcServer;
-use crate::types::RpcResult;
-use crate::{error, Context};
+use crate::types::{FileInfo, PeerInfo, RpcResult, StoreIoStats, TxListFilter};
+use crate::{error, Context, LogFilterControl};
 use futures::prelude::*;
 use jsonrpsee::core::async_trait;
+use miner::{MinerKey, MinerMessage};
+use network::{BandwidthConfig, EnrUpdate, IpFilterConfig, NetworkGlobals, NetworkMessage};
+use shared_types::DataRoot;
 use sync::{SyncRequest, SyncResponse, SyncSender};
 use task_executor::ShutdownReason;
+use tokio::sync::oneshot;
 
 pub struct RpcServerImpl {
     pub ctx: Context,
@@ -28,22 +32,7 @@ impl RpcServer for RpcServerImpl {
     async fn start_sync_file(&self, tx_seq: u64) -> RpcResult<()> {
         info!("admin_startSyncFile({tx_seq})");
 
-        let response = self
-            .sync_send()?
-            .request(SyncRequest::SyncFile { tx_seq })
-            .await
-            .map_err(|e| error::internal_error(format!("Failed to send sync command: {:?}", e)))?;
-
-        match response {
-            SyncResponse::SyncFile { err } => {
-                if err.is_empty() {
-                    Ok(())
-                } else {
-                    Err(error::internal_error(err))
-                }
-            }
-            _ => Err(error::internal_error("unexpected response type")),
-        }
+        start_sync_file(self.sync_send()?, tx_seq).await
     }
 
     #[tracing::instrument(skip(self), err)]
@@ -61,13 +50,327 @@ impl RpcServer for RpcServerImpl {
             _ => Err(error::internal_error("unexpected response type")),
         }
     }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn peers(&self) -> RpcResult<Vec<PeerInfo>> {
+        info!("admin_peers()");
+
+        let peers = self.network_globals()?.peers.read();
+        let mut result: Vec<PeerInfo> = peers
+            .connected_peers()
+            .map(|(peer_id, info)| {
+                let client = info.client();
+                let stats = info.stats();
+
+                PeerInfo {
+                    peer_id: peer_id.to_string(),
+                    client: client.to_string(),
+                    protocol_version: client.protocol_version.clone(),
+                    agent_version: client.agent_string.clone(),
+                    direction: info
+                        .connection_direction()
+                        .map(|direction| AsRef::<str>::as_ref(direction).to_string()),
+                    connected_secs: info
+                        .connection_duration()
+                        .map_or(0, |duration| duration.as_secs()),
+                    score: info.score().score(),
+                    bytes_sent: stats.bytes_sent,
+                    bytes_received: stats.bytes_received,
+                    responses: stats.responses,
+                    failed_requests: stats.failed_requests,
+                    latency_ms: stats.latency.map(|latency| latency.as_millis() as u64),
+                }
+            })
+            .collect();
+
+        result.sort_by(|a, b| b.bytes_received.cmp(&a.bytes_received));
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_store_stats(&self) -> RpcResult<Vec<StoreIoStats>> {
+        info!("admin_getStoreStats()");
+
+        Ok(self
+            .ctx
+            .log_store
+            .io_stats()
+            .into_iter()
+            .map(|(origin, stats)| StoreIoStats {
+                origin: origin.as_str().to_string(),
+                read_ops: stats.read_ops,
+                read_bytes: stats.read_bytes,
+                write_ops: stats.write_ops,
+                write_bytes: stats.write_bytes,
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_sync_bandwidth(&self) -> RpcResult<BandwidthConfig> {
+        info!("admin_getSyncBandwidth()");
+
+        Ok(self.network_globals()?.sync_bandwidth.config())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn set_sync_bandwidth(&self, config: BandwidthConfig) -> RpcResult<()> {
+        info!(?config, "admin_setSyncBandwidth()");
+
+        let rates = [
+            config.upload_bytes_per_sec,
+            config.download_bytes_per_sec,
+            config.peer_upload_bytes_per_sec,
+            config.peer_download_bytes_per_sec,
+        ];
+        if rates.contains(&Some(0)) {
+            return Err(error::invalid_params(
+                "config",
+                "bandwidth cap should be positive",
+            ));
+        }
+
+        self.network_globals()?.sync_bandwidth.set_config(config);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_ip_filter(&self) -> RpcResult<IpFilterConfig> {
+        info!("admin_getIpFilter()");
+
+        Ok(self.network_globals()?.ip_filter.config())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn set_ip_filter(&self, config: IpFilterConfig) -> RpcResult<()> {
+        info!(?config, "admin_setIpFilter()");
+
+        self.network_globals()?.ip_filter.set_config(config);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn update_enr(&self, update: EnrUpdate) -> RpcResult<()> {
+        info!(?update, "admin_updateEnr()");
+
+        if let Err(e) = update.fields() {
+            return Err(error::invalid_params("update", e));
+        }
+
+        match &self.ctx.network_send {
+            Some(network_send) => network_send
+                .send(NetworkMessage::UpdateEnr { update })
+                .map_err(|e| error::internal_error(format!("Failed to update ENR: {:?}", e))),
+            None => Err(error::internal_error("Network send is not initialized.")),
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_log_filter(&self) -> RpcResult<String> {
+        info!("admin_getLogFilter()");
+
+        Ok(self.log_filter()?.directives())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn set_log_filter(&self, directives: String) -> RpcResult<()> {
+        info!(%directives, "admin_setLogFilter()");
+
+        self.log_filter()?
+            .set_directives(&directives)
+            .map_err(|e| error::invalid_params("directives", e))
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn export_file(&self, data_root: DataRoot, path: String) -> RpcResult<()> {
+        info!(?data_root, %path, "admin_exportFile()");
+
+        let tx_seq = self
+            .ctx
+            .log_store
+            .get_tx_seq_by_data_root(&data_root)
+            .await?
+            .ok_or_else(|| error::invalid_params("data_root", "file not found"))?;
+
+        self.ctx
+            .log_store
+            .export_file(tx_seq, path.into())
+            .await
+            .map_err(|e| error::internal_error(format!("Failed to export file: {:?}", e)))
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn import_file(&self, tx_seq: u64, path: String) -> RpcResult<()> {
+        info!(%tx_seq, %path, "admin_importFile()");
+
+        if self.ctx.log_store.check_tx_completed(tx_seq).await? {
+            return Err(error::invalid_params("tx_seq", "file already finalized"));
+        }
+
+        if self.ctx.log_store.is_write_protected() {
+            return Err(error::storage_full());
+        }
+
+        self.ctx
+            .log_store
+            .import_file(tx_seq, path.into())
+            .await
+            .map_err(|e| error::internal_error(format!("Failed to import file: {:?}", e)))?;
+
+        if let Some(network_send) = &self.ctx.network_send {
+            if let Err(e) = network_send.send(NetworkMessage::AnnounceLocalFile { tx_seq }) {
+                warn!(%tx_seq, "Failed to announce imported file: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: Option<TxListFilter>,
+    ) -> RpcResult<Vec<FileInfo>> {
+        info!(%start_seq, %limit, ?filter, "admin_getTxList()");
+
+        if limit > self.ctx.config.max_batch_size {
+            return Err(error::invalid_params(
+                "limit",
+                format!("exceeds maximum {}", self.ctx.config.max_batch_size),
+            ));
+        }
+
+        let txs = self
+            .ctx
+            .log_store
+            .get_tx_list(start_seq, limit, filter.unwrap_or_default().into())
+            .await?;
+
+        let mut result = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let finalized = self.ctx.log_store.check_tx_completed(tx.seq).await?;
+            let metadata = self.ctx.log_store.get_file_metadata(tx.seq).await?;
+            result.push(FileInfo {
+                tx,
+                finalized,
+                metadata,
+            });
+        }
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn start_mining(&self) -> RpcResult<()> {
+        info!("admin_startMining()");
+
+        request_miner(&self.ctx, |result| MinerMessage::SetMiningEnabled {
+            enabled: true,
+            result,
+        })
+        .await?
+        .map_err(error::internal_error)
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn stop_mining(&self) -> RpcResult<()> {
+        info!("admin_stopMining()");
+
+        request_miner(&self.ctx, |result| MinerMessage::SetMiningEnabled {
+            enabled: false,
+            result,
+        })
+        .await?
+        .map_err(error::internal_error)
+    }
+
+    #[tracing::instrument(skip(self, password), err)]
+    async fn rotate_miner_key(&self, key_file: String, password: Option<String>) -> RpcResult<()> {
+        info!(%key_file, "admin_rotateMinerKey()");
+
+        let key = match password {
+            Some(password) => MinerKey::Keystore {
+                path: key_file.into(),
+                password,
+            },
+            None => MinerKey::File(key_file.into()),
+        };
+
+        request_miner(&self.ctx, |result| MinerMessage::RotateKey { key, result })
+            .await?
+            .map_err(error::internal_error)
+    }
 }
 
 impl RpcServerImpl {
     fn sync_send(&self) -> Result<&SyncSender, jsonrpsee::core::Error> {
-        match &self.ctx.sync_send {
-            Some(sync_send) => Ok(sync_send),
-            None => Err(error::internal_error("Sync send is not initialized.")),
+        sync_send(&self.ctx)
+    }
+
+    fn network_globals(&self) -> Result<&NetworkGlobals, jsonrpsee::core::Error> {
+        match &self.ctx.network_globals {
+            Some(network_globals) => Ok(network_globals),
+            None => Err(error::internal_error(
+                "Network globals are not initialized.",
+            )),
         }
     }
+
+    fn log_filter(&self) -> Result<&dyn LogFilterControl, jsonrpsee::core::Error> {
+        match &self.ctx.log_filter {
+            Some(log_filter) => Ok(log_filter.as_ref()),
+            None => Err(error::internal_error("Log filter is not initialized.")),
+        }
+    }
+}
+
+pub(crate) fn sync_send(ctx: &Context) -> Result<&SyncSender, jsonrpsee::core::Error> {
+    match &ctx.sync_send {
+        Some(sync_send) => Ok(sync_send),
+        None => Err(error::internal_error("Sync send is not initialized.")),
+    }
+}
+
+/// Requests the sync service to find peers that announced the file and download it.
+pub(crate) async fn start_sync_file(sync_send: &SyncSender, tx_seq: u64) -> RpcResult<()> {
+    let response = sync_send
+        .request(SyncRequest::SyncFile { tx_seq })
+        .await
+        .map_err(|e| error::internal_error(format!("Failed to send sync command: {:?}", e)))?;
+
+    match response {
+        SyncResponse::SyncFile { err } => {
+            if err.is_empty() {
+                Ok(())
+            } else {
+                Err(error::internal_error(err))
+            }
+        }
+        _ => Err(error::internal_error("unexpected response type")),
+    }
+}
+
+/// Sends a message to the miner service and waits for the response.
+pub(crate) async fn request_miner<T>(
+    ctx: &Context,
+    msg: impl FnOnce(oneshot::Sender<T>) -> MinerMessage,
+) -> RpcResult<T> {
+    let miner_send = ctx
+        .miner_send
+        .as_ref()
+        .ok_or_else(|| error::internal_error("Miner send is not initialized."))?;
+
+    let (response_send, response_recv) = oneshot::channel();
+    miner_send
+        .send(msg(response_send))
+        .map_err(|e| error::internal_error(format!("Failed to send miner command: {:?}", e)))?;
+
+    response_recv
+        .await
+        .map_err(|e| error::internal_error(format!("Failed to receive miner response: {:?}", e)))
 }
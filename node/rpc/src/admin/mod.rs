@@ -3,3 +3,4 @@ mod r#impl;
 
 pub use api::RpcServer;
 pub use r#impl::RpcServerImpl;
+pub(crate) use r#impl::{request_miner, start_sync_file, sync_send};
@@ -0,0 +1,219 @@
+//! Authentication of protected (write and admin) RPC methods.
+//!
+//! Clients authenticate with an `Authorization: Bearer <token>` header, where the token is either
+//! one of the configured static API keys, or a JWT signed with the shared secret using HS256, the
+//! same as the Engine API of Ethereum clients.
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::{Choice, ConstantTimeEq};
+
+/// Maximum allowed difference between the JWT `iat` claim and the local time.
+const JWT_IAT_TOLERANCE_SECS: u64 = 60;
+
+/// Methods that modify the node state, besides all methods in the `admin` namespace.
+const PROTECTED_METHODS: &[&str] = &[
+    "ionian_beginUpload",
+    "ionian_pinFile",
+    "ionian_requestFileSync",
+    "ionian_unpinFile",
+    "ionian_uploadSegment",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct AuthConfig {
+    /// File of the hex encoded JWT secret.
+    pub jwt_secret_file: Option<PathBuf>,
+    /// Static API keys accepted as bearer tokens.
+    pub api_keys: Vec<String>,
+}
+
+/// Returns whether calling `method` requires authentication.
+pub fn is_protected(method: &str) -> bool {
+    method.starts_with("admin_") || PROTECTED_METHODS.contains(&method)
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    iat: u64,
+}
+
+pub struct Authenticator {
+    jwt_secret: Option<Vec<u8>>,
+    /// SHA-256 digests of the API keys, which are compared in constant time regardless of the
+    /// token length.
+    api_key_digests: Vec<[u8; 32]>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self, String> {
+        let jwt_secret = match &config.jwt_secret_file {
+            Some(file) => {
+                let secret = std::fs::read_to_string(file)
+                    .map_err(|e| format!("Failed to read JWT secret file: {:?}", e))?;
+                let secret = secret.trim();
+                let secret = hex::decode(secret.strip_prefix("0x").unwrap_or(secret))
+                    .map_err(|e| format!("Invalid JWT secret: {:?}", e))?;
+                if secret.len() != 32 {
+                    return Err(format!(
+                        "Invalid JWT secret length: expected 32, got {}",
+                        secret.len()
+                    ));
+                }
+                Some(secret)
+            }
+            None => None,
+        };
+
+        if jwt_secret.is_none() && config.api_keys.is_empty() {
+            return Err("Neither JWT secret nor API keys configured".into());
+        }
+
+        Ok(Authenticator {
+            jwt_secret,
+            api_key_digests: config
+                .api_keys
+                .iter()
+                .map(|key| Sha256::digest(key.as_bytes()).into())
+                .collect(),
+        })
+    }
+
+    /// Authorizes a request with the value of its `Authorization` header.
+    pub fn authorize(&self, authorization: Option<&str>) -> Result<(), String> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or("missing bearer token")?
+            .trim();
+
+        if self.is_api_key(token) {
+            return Ok(());
+        }
+
+        match &self.jwt_secret {
+            Some(secret) => verify_jwt(secret, token, unix_now()),
+            None => Err("invalid API key".into()),
+        }
+    }
+
+    /// Returns whether the token is one of the API keys, which checks all the keys without
+    /// short-circuiting.
+    fn is_api_key(&self, token: &str) -> bool {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        self.api_key_digests
+            .iter()
+            .fold(Choice::from(0), |matched, key| {
+                matched | key[..].ct_eq(&digest[..])
+            })
+            .into()
+    }
+}
+
+fn verify_jwt(secret: &[u8], token: &str, now: u64) -> Result<(), String> {
+    let (message, signature) = token.rsplit_once('.').ok_or("malformed JWT")?;
+    let (header, claims) = message.split_once('.').ok_or("malformed JWT")?;
+
+    let header: JwtHeader = decode_segment(header)?;
+    if header.alg != "HS256" {
+        return Err(format!("unsupported JWT algorithm {}", header.alg));
+    }
+
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| format!("invalid JWT signature encoding: {:?}", e))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).map_err(|e| e.to_string())?;
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| "invalid JWT signature".to_string())?;
+
+    let claims: JwtClaims = decode_segment(claims)?;
+    if claims.iat.abs_diff(now) > JWT_IAT_TOLERANCE_SECS {
+        return Err(format!("JWT expired: iat={} now={}", claims.iat, now));
+    }
+
+    Ok(())
+}
+
+fn decode_segment<T: for<'de> Deserialize<'de>>(segment: &str) -> Result<T, String> {
+    let raw = base64::decode_config(segment, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| format!("invalid JWT encoding: {:?}", e))?;
+    serde_json::from_slice(&raw).map_err(|e| format!("invalid JWT json: {:?}", e))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time after unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_protected, verify_jwt, AuthConfig, Authenticator};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    fn sign(secret: &[u8], header: &str, claims: &str) -> String {
+        let message = format!(
+            "{}.{}",
+            base64::encode_config(header, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(claims, base64::URL_SAFE_NO_PAD)
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(message.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        format!(
+            "{}.{}",
+            message,
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn test_verify_jwt() {
+        let secret = [7u8; 32];
+        let header = r#"{"alg":"HS256","typ":"JWT"}"#;
+        let token = sign(&secret, header, r#"{"iat":1000}"#);
+
+        assert!(verify_jwt(&secret, &token, 1000).is_ok());
+        assert!(verify_jwt(&secret, &token, 1059).is_ok());
+        assert!(verify_jwt(&secret, &token, 1061).is_err());
+        assert!(verify_jwt(&[8u8; 32], &token, 1000).is_err());
+
+        let none_alg = sign(&secret, r#"{"alg":"none"}"#, r#"{"iat":1000}"#);
+        assert!(verify_jwt(&secret, &none_alg, 1000).is_err());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let authenticator = Authenticator::new(&AuthConfig {
+            jwt_secret_file: None,
+            api_keys: vec!["key1".into(), "key2".into()],
+        })
+        .unwrap();
+
+        assert!(authenticator.authorize(Some("Bearer key1")).is_ok());
+        assert!(authenticator.authorize(Some("Bearer key2")).is_ok());
+        assert!(authenticator.authorize(Some("Bearer key")).is_err());
+        assert!(authenticator.authorize(Some("key1")).is_err());
+        assert!(authenticator.authorize(None).is_err());
+    }
+
+    #[test]
+    fn test_protected_methods() {
+        assert!(is_protected("admin_shutdown"));
+        assert!(is_protected("ionian_beginUpload"));
+        assert!(is_protected("ionian_uploadSegment"));
+        assert!(is_protected("ionian_requestFileSync"));
+        assert!(is_protected("ionian_pinFile"));
+        assert!(is_protected("ionian_unpinFile"));
+        assert!(!is_protected("ionian_getFileInfo"));
+    }
+}
@@ -1,8 +1,29 @@
+use crate::auth::AuthConfig;
+use crate::rate_limit::RateLimitConfig;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Config {
     pub enabled: bool,
     pub listen_address: SocketAddr,
+    /// WebSocket listen address, which is required for subscriptions.
+    pub ws_listen_address: Option<SocketAddr>,
+    /// Origins allowed to access the HTTP and WebSocket servers, which allows any origin if
+    /// `None`.
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// Maximum size in bytes of a request body.
+    pub max_request_body_size: u32,
     pub chunks_per_segment: usize,
+    /// Maximum number of chunks returned in a single paged response, which is in
+    /// `[1, u32::MAX]`.
+    pub max_response_chunks: usize,
+    /// Maximum number of items queried in a single batch request.
+    pub max_batch_size: usize,
+    /// Upload sessions expire if not accessed within this timeout.
+    pub upload_session_timeout: Duration,
+    /// Per-IP rate limiting of the HTTP and WebSocket servers, which is disabled if `None`.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Authentication of protected methods, which are public if `None`.
+    pub auth: Option<AuthConfig>,
 }
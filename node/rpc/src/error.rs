@@ -3,6 +3,15 @@
 use jsonrpsee::core::Error;
 use jsonrpsee::types::error::{CallError, ErrorCode, ErrorObject};
 
+/// Error code returned when a client exceeds its rate limit quota.
+pub const RATE_LIMITED_ERROR_CODE: i32 = -32029;
+
+/// Error code returned when a protected method is called without valid credentials.
+pub const UNAUTHORIZED_ERROR_CODE: i32 = -32030;
+
+/// Error code returned when the disk space of the node is low and uploads are rejected.
+pub const STORAGE_FULL_ERROR_CODE: i32 = -32031;
+
 pub fn not_supported() -> Error {
     Error::Call(CallError::Custom(ErrorObject::borrowed(
         ErrorCode::MethodNotFound.code(),
@@ -11,6 +20,22 @@ pub fn not_supported() -> Error {
     )))
 }
 
+pub fn unauthorized() -> Error {
+    Error::Call(CallError::Custom(ErrorObject::borrowed(
+        UNAUTHORIZED_ERROR_CODE,
+        &"Unauthorized: method requires authentication",
+        None,
+    )))
+}
+
+pub fn storage_full() -> Error {
+    Error::Call(CallError::Custom(ErrorObject::borrowed(
+        STORAGE_FULL_ERROR_CODE,
+        &"Storage full: the node is low on disk space",
+        None,
+    )))
+}
+
 pub fn internal_error(msg: impl std::convert::AsRef<str>) -> Error {
     Error::Call(CallError::Custom(ErrorObject::owned(
         ErrorCode::InternalError.code(),
@@ -1,12 +1,28 @@
-use crate::types::{FileInfo, RpcResult, Segment, SegmentWithProof, Status};
+use crate::types::{
+    ChunkRange, FileId, FileInfo, FileQuery, FlowRoot, ProofVerification, RpcResult, Segment,
+    SegmentPage, SegmentWithProof, Status, UploadSession,
+};
+use ethereum_types::Address;
 use jsonrpsee::proc_macros::rpc;
-use shared_types::DataRoot;
+use shared_types::{DataRoot, FileMetadata, FlowRangeProof};
 
 #[rpc(server, client, namespace = "ionian")]
 pub trait Rpc {
     #[method(name = "getStatus")]
     async fn get_status(&self) -> RpcResult<Status>;
 
+    /// Begins the upload of a file, or resumes it if the file has been partially uploaded before.
+    /// Returns the segments that remain to be uploaded with `uploadSegment`. The optional
+    /// `metadata` is returned in the file info, and replaces the metadata set before. The file
+    /// uploaded before its transaction is synced is staged, and finalized once synced.
+    #[method(name = "beginUpload")]
+    async fn begin_upload(
+        &self,
+        data_root: DataRoot,
+        size: u64,
+        metadata: Option<FileMetadata>,
+    ) -> RpcResult<UploadSession>;
+
     #[method(name = "uploadSegment")]
     async fn upload_segment(&self, segment: SegmentWithProof) -> RpcResult<()>;
 
@@ -18,6 +34,88 @@ pub trait Rpc {
         end_index: u32,
     ) -> RpcResult<Option<Segment>>;
 
+    /// Downloads chunks in `[start_index, end_index)` page by page. Each response holds at most
+    /// `max_response_chunks` chunks, and `next_index` should be used as the `start_index` of the
+    /// following request until it is `None`.
+    #[method(name = "downloadRange")]
+    async fn download_range(
+        &self,
+        data_root: DataRoot,
+        start_index: u32,
+        end_index: u32,
+    ) -> RpcResult<Option<SegmentPage>>;
+
+    /// Streams all pages of chunks in `[start_index, end_index)` over WebSocket.
+    #[subscription(name = "subscribeRange", unsubscribe = "unsubscribeRange", item = SegmentPage)]
+    fn subscribe_range(&self, data_root: DataRoot, start_index: u32, end_index: u32);
+
+    /// Verifies the range proof of file chunks against the flow roots of the node. Returns `None`
+    /// if the file of `root` is not found.
+    #[method(name = "verifyProof")]
+    async fn verify_proof(
+        &self,
+        root: DataRoot,
+        proof: FlowRangeProof,
+        data: ChunkRange,
+    ) -> RpcResult<Option<ProofVerification>>;
+
+    /// Requests the node to find peers that announced the file and download it from them.
+    /// Returns the transaction sequence number of the file to query the sync status.
+    #[method(name = "requestFileSync")]
+    async fn request_file_sync(&self, file: FileId) -> RpcResult<u64>;
+
+    /// Returns the current flow root and length, along with the flow root right after `tx_seq` if
+    /// given. The root after a transaction is only available for the transactions appended since
+    /// the node starts.
+    #[method(name = "getFlowRoot")]
+    async fn get_flow_root(&self, tx_seq: Option<u64>) -> RpcResult<FlowRoot>;
+
     #[method(name = "getFileInfo")]
     async fn get_file_info(&self, data_root: DataRoot) -> RpcResult<Option<FileInfo>>;
+
+    /// Returns the file info of each data root in order, or `None` if not found.
+    #[method(name = "getFileInfoBatch")]
+    async fn get_file_info_batch(
+        &self,
+        data_roots: Vec<DataRoot>,
+    ) -> RpcResult<Vec<Option<FileInfo>>>;
+
+    /// Returns whether each transaction has been finalized in order.
+    #[method(name = "checkTxCompletedBatch")]
+    async fn check_tx_completed_batch(&self, tx_seqs: Vec<u64>) -> RpcResult<Vec<bool>>;
+
+    /// Returns the files submitted by `sender` in ascending order of transaction sequence numbers,
+    /// where `page` starts from 0 and each page holds at most 100 files. Files synced before the
+    /// senders are recorded are not listed.
+    #[method(name = "getTxsBySender")]
+    async fn get_txs_by_sender(&self, sender: Address, page: usize) -> RpcResult<Vec<FileInfo>>;
+
+    /// Searches the files from `start_seq` by tags, sender, size and upload time, and returns at
+    /// most `limit` ones in ascending order of transaction sequence numbers. The next page starts
+    /// from the sequence number of the last one plus 1. The upload time is when the node synced
+    /// the transaction, which is the time of the upgrade for the files synced before the search
+    /// is supported.
+    #[method(name = "searchFiles")]
+    async fn search_files(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        query: Option<FileQuery>,
+    ) -> RpcResult<Vec<FileInfo>>;
+
+    /// Returns the number of distinct nodes, including the local one, that announced to store
+    /// the file of `data_root` recently. Replicas are no longer counted once their announcements
+    /// expire.
+    #[method(name = "getReplicationCount")]
+    async fn get_replication_count(&self, data_root: DataRoot) -> RpcResult<usize>;
+
+    /// Pins the file of `data_root` on the node, so that it is never pruned regardless of the
+    /// retention policy. The file could be pinned before it is stored. Returns `false` if already
+    /// pinned.
+    #[method(name = "pinFile")]
+    async fn pin_file(&self, data_root: DataRoot) -> RpcResult<bool>;
+
+    /// Unpins the file of `data_root`. Returns `false` if not pinned.
+    #[method(name = "unpinFile")]
+    async fn unpin_file(&self, data_root: DataRoot) -> RpcResult<bool>;
 }
@@ -1,17 +1,36 @@
 use super::api::RpcServer;
+use crate::admin;
 use crate::error;
-use crate::types::{FileInfo, RpcResult, Segment, SegmentWithProof, Status};
+use crate::types::{
+    ChunkRange, FileId, FileInfo, FileQuery, FlowRoot, ProofVerification, RpcResult, Segment,
+    SegmentPage, SegmentWithProof, Status, UploadSession,
+};
+use crate::upload_session::{self, UploadSessions};
 use crate::Context;
-use jsonrpsee::core::async_trait;
+use ethereum_types::Address;
+use jsonrpsee::core::{async_trait, Error};
+use jsonrpsee::types::error::{ErrorCode, ErrorObject};
+use jsonrpsee::PendingSubscription;
 use network::NetworkGlobals;
 use network::NetworkMessage;
-use shared_types::DataRoot;
+use shared_types::{
+    bytes_to_chunks, ChunkArray, ChunkArrayWithProof, DataRoot, FileMetadata, FlowRangeProof,
+    CHUNK_SIZE, MAX_FILE_TAGS,
+};
+use std::cmp;
 use std::sync::Arc;
+use storage::log_store::log_manager::ENTRY_SIZE;
 use storage::try_option;
 use tokio::sync::mpsc::UnboundedSender;
 
+/// Number of transactions in each page of `ionian_getTxsBySender`.
+pub const TXS_PER_PAGE: usize = 100;
+
 pub struct RpcServerImpl {
     pub ctx: Context,
+    pub upload_sessions: Arc<UploadSessions>,
+    /// Whether protected methods are rejected, e.g. on a transport without authentication.
+    pub restricted: bool,
 }
 
 #[async_trait]
@@ -20,15 +39,151 @@ impl RpcServer for RpcServerImpl {
     async fn get_status(&self) -> RpcResult<Status> {
         info!("ionian_getStatus()");
 
+        let log_sync_height = self
+            .ctx
+            .log_store
+            .get_sync_progress()
+            .await?
+            .map(|(block_number, _)| block_number);
+        let chain_head = self
+            .ctx
+            .chain_head
+            .as_ref()
+            .and_then(|chain_head| *chain_head.borrow());
+        let flow_root_consistent = self
+            .ctx
+            .flow_root_status
+            .as_ref()
+            .and_then(|status| *status.borrow());
+
         Ok(Status {
+            version: ionian_version::VERSION.to_string(),
             connected_peers: self.network_globals()?.connected_peers(),
+            log_sync_height,
+            chain_head,
+            next_tx_seq: self.ctx.log_store.next_tx_seq().await?,
+            log_size: self.ctx.log_store.flow_length().await? * ENTRY_SIZE as u64,
+            flow_root_consistent,
         })
     }
 
+    async fn begin_upload(
+        &self,
+        data_root: DataRoot,
+        size: u64,
+        metadata: Option<FileMetadata>,
+    ) -> RpcResult<UploadSession> {
+        debug!("ionian_beginUpload()");
+
+        self.check_unrestricted()?;
+        self.check_writable()?;
+
+        if size == 0 {
+            return Err(error::invalid_params("size", "file is empty"));
+        }
+
+        if let Some(metadata) = &metadata {
+            metadata
+                .validate()
+                .map_err(|e| error::invalid_params("metadata", e.to_string()))?;
+        }
+
+        if let Some(session) = self.upload_sessions.get(&data_root).await {
+            if session.size != size {
+                return Err(error::invalid_params(
+                    "size",
+                    "mismatch with upload session",
+                ));
+            }
+
+            if let Some(metadata) = metadata {
+                self.attach_metadata(data_root, metadata).await?;
+            }
+
+            return Ok(session);
+        }
+
+        let chunks_per_segment = self.ctx.config.chunks_per_segment;
+        let mut chunk_ranges = vec![];
+
+        if let Some(pending) = self.ctx.log_store.get_pending_tx(&data_root).await? {
+            // The file is staged until the transaction is retrieved from blockchain. The size
+            // announced by peers is not trusted, and replaced by the uploaded one.
+            if pending.size != size && !pending.announced {
+                return Err(error::invalid_params(
+                    "size",
+                    "mismatch with pending upload",
+                ));
+            }
+            if pending.announced {
+                self.ctx
+                    .log_store
+                    .put_tx_pending(&data_root, size, false)
+                    .await?;
+            }
+
+            if pending.size == size {
+                chunk_ranges = pending
+                    .staged
+                    .iter()
+                    .map(|&(start, end)| (start as usize, end as usize))
+                    .collect();
+            }
+        } else if let Some(tx) = self.ctx.log_store.get_tx_by_data_root(&data_root).await? {
+            let tx_seq = tx.seq;
+            if tx.size != size {
+                return Err(error::invalid_params("size", "mismatch with transaction"));
+            }
+
+            if self.ctx.log_store.check_tx_completed(tx_seq).await? {
+                chunk_ranges.push((0, bytes_to_chunks(size as usize)));
+            } else {
+                chunk_ranges = self
+                    .ctx
+                    .log_store
+                    .get_chunk_ranges(tx_seq)
+                    .await?
+                    .unwrap_or_default();
+
+                // Segments are written in sequence, so resume from the first segment that is
+                // not completely stored.
+                if let Some(&(0, end)) = chunk_ranges.first() {
+                    let next_index = end / chunks_per_segment * chunks_per_segment;
+                    self.ctx.chunk_pool.resume_file(&tx, next_index).await?;
+                }
+            }
+        } else {
+            // Stage the file before the transaction is retrieved from blockchain, which is
+            // promoted once the transaction is synced.
+            self.ctx
+                .log_store
+                .put_tx_pending(&data_root, size, false)
+                .await?;
+        }
+
+        let session =
+            upload_session::new_session(data_root, size, chunks_per_segment, &chunk_ranges);
+        self.upload_sessions.insert(session.clone()).await;
+
+        if let Some(metadata) = metadata {
+            self.attach_metadata(data_root, metadata).await?;
+        }
+
+        Ok(session)
+    }
+
     async fn upload_segment(&self, segment: SegmentWithProof) -> RpcResult<()> {
         debug!("ionian_uploadSegment()");
 
-        // TODO(qhz): allow to cache small files before log entry retrieved from blockchain.
+        self.check_unrestricted()?;
+        self.check_writable()?;
+
+        // The staged file is uploaded to the staging area until completed, even if the
+        // transaction is synced in the meantime.
+        if let Some(pending) = self.ctx.log_store.get_pending_tx(&segment.root).await? {
+            return self.upload_pending_segment(segment, pending.size).await;
+        }
+
         let tx_seq = match self
             .ctx
             .log_store
@@ -39,6 +194,15 @@ impl RpcServer for RpcServerImpl {
             None => return Err(error::invalid_params("root", "data root not found")),
         };
 
+        // The metadata set before the transaction is synced.
+        if let Some(metadata) = self
+            .upload_sessions
+            .take_pending_metadata(&segment.root)
+            .await
+        {
+            self.attach_metadata(segment.root, metadata).await?;
+        }
+
         // Transaction already finalized for the specified file data root.
         if self.ctx.log_store.check_tx_completed(tx_seq).await? {
             return Err(error::invalid_params(
@@ -52,7 +216,9 @@ impl RpcServer for RpcServerImpl {
             None => return Err(error::invalid_params("root", "data root not found")),
         };
 
-        segment.validate(tx.size as usize, self.ctx.config.chunks_per_segment)?;
+        segment
+            .validate(tx.size as usize, self.ctx.config.chunks_per_segment)
+            .map_err(|e| error::invalid_params("segment", e.to_string()))?;
 
         // Chunk pool will validate the data size.
         let chunk_index = segment.chunk_index(self.ctx.config.chunks_per_segment);
@@ -61,6 +227,10 @@ impl RpcServer for RpcServerImpl {
             .add_chunks(segment.root, segment.data, chunk_index)
             .await?;
 
+        self.upload_sessions
+            .on_segment_uploaded(&segment.root, segment.index)
+            .await;
+
         Ok(())
     }
 
@@ -99,11 +269,27 @@ impl RpcServer for RpcServerImpl {
                 .await?
         );
 
+        // the next segment is likely to be downloaded next
+        self.ctx.log_store.prefetch_chunks(
+            tx_seq,
+            end_index as usize,
+            (end_index as usize).saturating_mul(2) - start_index as usize,
+        );
+
         Ok(Some(Segment(segment.data)))
     }
 
-    async fn get_file_info(&self, data_root: DataRoot) -> RpcResult<Option<FileInfo>> {
-        debug!("get_file_info()");
+    async fn download_range(
+        &self,
+        data_root: DataRoot,
+        start_index: u32,
+        end_index: u32,
+    ) -> RpcResult<Option<SegmentPage>> {
+        debug!("ionian_downloadRange()");
+
+        if start_index >= end_index {
+            return Err(error::invalid_params("end_index", "invalid chunk index"));
+        }
 
         let tx_seq = try_option!(
             self.ctx
@@ -111,16 +297,469 @@ impl RpcServer for RpcServerImpl {
                 .get_tx_seq_by_data_root(&data_root)
                 .await?
         );
-        let tx = try_option!(self.ctx.log_store.get_tx_by_seq_number(tx_seq).await?);
 
-        Ok(Some(FileInfo {
-            tx,
-            finalized: self.ctx.log_store.check_tx_completed(tx_seq).await?,
+        read_page(&self.ctx, tx_seq, start_index, end_index).await
+    }
+
+    fn subscribe_range(
+        &self,
+        pending: PendingSubscription,
+        data_root: DataRoot,
+        start_index: u32,
+        end_index: u32,
+    ) {
+        debug!("ionian_subscribeRange()");
+
+        if start_index >= end_index {
+            let err = error::invalid_params("end_index", "empty range");
+            pending.reject(subscription_error(err));
+            return;
+        }
+
+        let ctx = self.ctx.clone();
+        self.ctx.executor.spawn(
+            async move {
+                // The file and the first page are checked before accepting, so that the
+                // subscription is rejected if not available.
+                let tx_seq = match ctx.log_store.get_tx_seq_by_data_root(&data_root).await {
+                    Ok(Some(seq)) => seq,
+                    Ok(None) => {
+                        let err = error::invalid_params("data_root", "file not found");
+                        pending.reject(subscription_error(err));
+                        return;
+                    }
+                    Err(e) => {
+                        warn!(%e, ?data_root, "Failed to get tx seq for range subscription");
+                        pending.reject(subscription_error(e.into()));
+                        return;
+                    }
+                };
+
+                let mut page = match read_page(&ctx, tx_seq, start_index, end_index).await {
+                    Ok(Some(page)) => page,
+                    Ok(None) => {
+                        let err = error::invalid_params("start_index", "chunks not available");
+                        pending.reject(subscription_error(err));
+                        return;
+                    }
+                    Err(e) => {
+                        warn!(%e, %tx_seq, %start_index, "Failed to read range subscription page");
+                        pending.reject(subscription_error(e));
+                        return;
+                    }
+                };
+
+                let mut sink = match pending.accept() {
+                    Some(sink) => sink,
+                    None => return,
+                };
+
+                loop {
+                    match sink.send(&page) {
+                        Ok(true) => {}
+                        // Subscription closed by client.
+                        Ok(false) => return,
+                        Err(e) => {
+                            warn!(%e, "Failed to send range subscription page");
+                            return;
+                        }
+                    }
+
+                    let start_index = match page.next_index {
+                        Some(index) => index,
+                        None => return,
+                    };
+                    page = match read_page(&ctx, tx_seq, start_index, end_index).await {
+                        Ok(Some(page)) => page,
+                        // The chunks are removed during the subscription, e.g. pruned.
+                        Ok(None) => {
+                            let err = error::internal_error("chunks not available");
+                            sink.close(subscription_error(err));
+                            return;
+                        }
+                        Err(e) => {
+                            warn!(%e, %tx_seq, %start_index, "Failed to read range subscription page");
+                            sink.close(subscription_error(e));
+                            return;
+                        }
+                    };
+                }
+            },
+            "rpc_subscribe_range",
+        );
+    }
+
+    async fn verify_proof(
+        &self,
+        root: DataRoot,
+        proof: FlowRangeProof,
+        data: ChunkRange,
+    ) -> RpcResult<Option<ProofVerification>> {
+        debug!("ionian_verifyProof()");
+
+        let chunks = ChunkArray::try_new(data.data, data.start_index as u64)
+            .map_err(|e| error::invalid_params("data", e.to_string()))?;
+
+        let num_chunks = chunks.data.len() / CHUNK_SIZE;
+        if num_chunks > self.ctx.config.max_response_chunks {
+            return Err(error::invalid_params(
+                "data",
+                format!(
+                    "exceeds maximum chunks {}",
+                    self.ctx.config.max_response_chunks
+                ),
+            ));
+        }
+
+        let tx = try_option!(self.ctx.log_store.get_tx_by_data_root(&root).await?);
+        let tx_seq = tx.seq;
+
+        if chunks.start_index as usize + num_chunks > bytes_to_chunks(tx.size as usize) {
+            return Err(error::invalid_params("data", "index out of bound"));
+        }
+
+        let flow_start_index = tx.start_entry_index + chunks.start_index;
+        let flow_root = proof.root();
+        let chunks = ChunkArrayWithProof { chunks, proof };
+
+        // Invalid proofs are reported as errors, and unknown flow roots as `false`.
+        let (valid, error) = match self
+            .ctx
+            .log_store
+            .validate_range_proof(tx_seq, chunks)
+            .await
+        {
+            Ok(true) => (true, None),
+            Ok(false) => (false, Some("unknown flow root".to_string())),
+            Err(e) => (false, Some(e.to_string())),
+        };
+
+        Ok(Some(ProofVerification {
+            valid,
+            error,
+            flow_start_index,
+            flow_end_index: flow_start_index + num_chunks as u64,
+            flow_root,
         }))
     }
+
+    async fn request_file_sync(&self, file: FileId) -> RpcResult<u64> {
+        info!("ionian_requestFileSync({:?})", file);
+
+        self.check_unrestricted()?;
+
+        let tx_seq = match file {
+            FileId::TxSeq(tx_seq) => tx_seq,
+            FileId::DataRoot(data_root) => {
+                match self
+                    .ctx
+                    .log_store
+                    .get_tx_seq_by_data_root(&data_root)
+                    .await?
+                {
+                    Some(tx_seq) => tx_seq,
+                    None => return Err(error::invalid_params("file", "data root not found")),
+                }
+            }
+        };
+
+        admin::start_sync_file(admin::sync_send(&self.ctx)?, tx_seq).await?;
+
+        Ok(tx_seq)
+    }
+
+    async fn get_flow_root(&self, tx_seq: Option<u64>) -> RpcResult<FlowRoot> {
+        debug!(?tx_seq, "ionian_getFlowRoot()");
+
+        let (root, length) = self.ctx.log_store.get_context().await?;
+        let tx_seq_root = match tx_seq {
+            Some(tx_seq) => self.ctx.log_store.get_flow_root_by_tx_seq(tx_seq).await?,
+            None => None,
+        };
+
+        Ok(FlowRoot {
+            root,
+            length,
+            tx_seq_root,
+        })
+    }
+
+    async fn get_file_info(&self, data_root: DataRoot) -> RpcResult<Option<FileInfo>> {
+        debug!("get_file_info()");
+
+        self.file_info(data_root).await
+    }
+
+    async fn get_file_info_batch(
+        &self,
+        data_roots: Vec<DataRoot>,
+    ) -> RpcResult<Vec<Option<FileInfo>>> {
+        debug!("ionian_getFileInfoBatch({})", data_roots.len());
+
+        self.check_batch_size("data_roots", data_roots.len())?;
+
+        let mut infos = Vec::with_capacity(data_roots.len());
+        for data_root in data_roots {
+            infos.push(self.file_info(data_root).await?);
+        }
+
+        Ok(infos)
+    }
+
+    async fn check_tx_completed_batch(&self, tx_seqs: Vec<u64>) -> RpcResult<Vec<bool>> {
+        debug!("ionian_checkTxCompletedBatch({})", tx_seqs.len());
+
+        self.check_batch_size("tx_seqs", tx_seqs.len())?;
+
+        let mut completed = Vec::with_capacity(tx_seqs.len());
+        for tx_seq in tx_seqs {
+            completed.push(self.ctx.log_store.check_tx_completed(tx_seq).await?);
+        }
+
+        Ok(completed)
+    }
+
+    async fn get_txs_by_sender(&self, sender: Address, page: usize) -> RpcResult<Vec<FileInfo>> {
+        debug!("ionian_getTxsBySender({:?}, {})", sender, page);
+
+        let txs = self
+            .ctx
+            .log_store
+            .get_txs_by_sender(sender, page.saturating_mul(TXS_PER_PAGE), TXS_PER_PAGE)
+            .await?;
+
+        let mut result = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let finalized = self.ctx.log_store.check_tx_completed(tx.seq).await?;
+            let metadata = self.ctx.log_store.get_file_metadata(tx.seq).await?;
+            result.push(FileInfo {
+                tx,
+                finalized,
+                metadata,
+            });
+        }
+
+        Ok(result)
+    }
+
+    async fn search_files(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        query: Option<FileQuery>,
+    ) -> RpcResult<Vec<FileInfo>> {
+        debug!("ionian_searchFiles({}, {}, {:?})", start_seq, limit, query);
+
+        self.check_batch_size("limit", limit)?;
+        let query = query.unwrap_or_default();
+        if query.tags.len() > MAX_FILE_TAGS {
+            return Err(error::invalid_params(
+                "query",
+                format!("exceeds maximum tags {}", MAX_FILE_TAGS),
+            ));
+        }
+
+        let txs = self
+            .ctx
+            .log_store
+            .search_files(query.into(), start_seq, limit)
+            .await?;
+
+        let mut result = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let finalized = self.ctx.log_store.check_tx_completed(tx.seq).await?;
+            let metadata = self.ctx.log_store.get_file_metadata(tx.seq).await?;
+            result.push(FileInfo {
+                tx,
+                finalized,
+                metadata,
+            });
+        }
+
+        Ok(result)
+    }
+
+    async fn get_replication_count(&self, data_root: DataRoot) -> RpcResult<usize> {
+        debug!("ionian_getReplicationCount({:?})", data_root);
+
+        match &self.ctx.file_location_cache {
+            Some(cache) => Ok(cache.replication_count(&data_root)),
+            None => Err(error::internal_error(
+                "File location cache is not initialized.",
+            )),
+        }
+    }
+
+    async fn pin_file(&self, data_root: DataRoot) -> RpcResult<bool> {
+        info!("ionian_pinFile({:?})", data_root);
+
+        self.check_unrestricted()?;
+
+        Ok(self.ctx.log_store.pin_file(&data_root).await?)
+    }
+
+    async fn unpin_file(&self, data_root: DataRoot) -> RpcResult<bool> {
+        info!("ionian_unpinFile({:?})", data_root);
+
+        self.check_unrestricted()?;
+
+        Ok(self.ctx.log_store.unpin_file(&data_root).await?)
+    }
+}
+
+/// Reads at most `max_response_chunks` chunks starting from `start_index`, and returns the
+/// continuation index if the range `[start_index, end_index)` is not exhausted.
+async fn read_page(
+    ctx: &Context,
+    tx_seq: u64,
+    start_index: u32,
+    end_index: u32,
+) -> RpcResult<Option<SegmentPage>> {
+    let max_chunks = u32::try_from(ctx.config.max_response_chunks)
+        .map_err(|_| error::internal_error("max_response_chunks out of range"))?;
+    let (page_end, next_index) = page_bounds(start_index, end_index, max_chunks);
+
+    let chunks = try_option!(
+        ctx.log_store
+            .get_chunks_by_tx_and_index_range(tx_seq, start_index as usize, page_end as usize)
+            .await?
+    );
+
+    if let Some(next_index) = next_index {
+        let (next_end, _) = page_bounds(next_index, end_index, max_chunks);
+        ctx.log_store
+            .prefetch_chunks(tx_seq, next_index as usize, next_end as usize);
+    }
+
+    Ok(Some(SegmentPage {
+        data: chunks.data,
+        start_index,
+        next_index,
+    }))
+}
+
+/// Returns the end index (excluded) of the page starting from `start_index`, and the start index
+/// of the next page if the range `[start_index, end_index)` is not exhausted. Each page has at
+/// least one chunk, so that the pagination always makes progress.
+fn page_bounds(start_index: u32, end_index: u32, max_chunks: u32) -> (u32, Option<u32>) {
+    let page_end = cmp::min(
+        end_index,
+        start_index.saturating_add(cmp::max(max_chunks, 1)),
+    );
+    let next_index = if page_end < end_index {
+        Some(page_end)
+    } else {
+        None
+    };
+    (page_end, next_index)
+}
+
+/// Converts the RPC error to reject or close a subscription with.
+fn subscription_error(err: Error) -> ErrorObject<'static> {
+    match err {
+        Error::Call(err) => err.into(),
+        err => ErrorObject::owned(ErrorCode::InternalError.code(), err.to_string(), None::<()>),
+    }
 }
 
 impl RpcServerImpl {
+    async fn file_info(&self, data_root: DataRoot) -> RpcResult<Option<FileInfo>> {
+        // The same data may be submitted more than once, so prefer a finalized one.
+        let txs = self.ctx.log_store.get_txs_by_data_root(&data_root).await?;
+        let mut file = None;
+        for tx in txs {
+            if self.ctx.log_store.check_tx_completed(tx.seq).await? {
+                file = Some((tx, true));
+                break;
+            }
+            if file.is_none() {
+                file = Some((tx, false));
+            }
+        }
+
+        let (tx, finalized) = try_option!(file);
+        let metadata = self.ctx.log_store.get_file_metadata(tx.seq).await?;
+
+        Ok(Some(FileInfo {
+            tx,
+            finalized,
+            metadata,
+        }))
+    }
+
+    /// Stages the segment of a file whose transaction is not synced yet.
+    async fn upload_pending_segment(&self, segment: SegmentWithProof, size: u64) -> RpcResult<()> {
+        segment
+            .validate(size as usize, self.ctx.config.chunks_per_segment)
+            .map_err(|e| error::invalid_params("segment", e.to_string()))?;
+
+        let chunks = ChunkArray {
+            start_index: segment.chunk_index(self.ctx.config.chunks_per_segment) as u64,
+            data: segment.data,
+        };
+        self.ctx
+            .log_store
+            .put_pending_chunks(&segment.root, chunks)
+            .await?;
+
+        self.upload_sessions
+            .on_segment_uploaded(&segment.root, segment.index)
+            .await;
+
+        Ok(())
+    }
+
+    /// Stores the metadata for the transactions of the file, or keeps it in the upload session if
+    /// the transaction is not synced yet.
+    async fn attach_metadata(&self, data_root: DataRoot, metadata: FileMetadata) -> RpcResult<()> {
+        let txs = self.ctx.log_store.get_txs_by_data_root(&data_root).await?;
+        if txs.is_empty() {
+            self.upload_sessions
+                .set_pending_metadata(&data_root, metadata)
+                .await;
+            return Ok(());
+        }
+
+        for tx in txs {
+            self.ctx
+                .log_store
+                .put_file_metadata(tx.seq, metadata.clone())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn check_unrestricted(&self) -> RpcResult<()> {
+        if self.restricted {
+            return Err(error::unauthorized());
+        }
+
+        Ok(())
+    }
+
+    fn check_writable(&self) -> RpcResult<()> {
+        if self.ctx.log_store.is_write_protected() {
+            return Err(error::storage_full());
+        }
+
+        Ok(())
+    }
+
+    fn check_batch_size(&self, param: &str, size: usize) -> RpcResult<()> {
+        if size > self.ctx.config.max_batch_size {
+            return Err(error::invalid_params(
+                param,
+                format!(
+                    "exceeds maximum batch size {}",
+                    self.ctx.config.max_batch_size
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
     fn network_globals(&self) -> Result<&Arc<NetworkGlobals>, jsonrpsee::core::Error> {
         match &self.ctx.network_globals {
             Some(globals) => Ok(globals),
@@ -138,3 +777,26 @@ impl RpcServerImpl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::page_bounds;
+
+    #[test]
+    fn test_page_bounds() {
+        // a single page
+        assert_eq!(page_bounds(0, 10, 10), (10, None));
+        assert_eq!(page_bounds(5, 10, 100), (10, None));
+
+        // continued from the end of each page
+        assert_eq!(page_bounds(0, 25, 10), (10, Some(10)));
+        assert_eq!(page_bounds(10, 25, 10), (20, Some(20)));
+        assert_eq!(page_bounds(20, 25, 10), (25, None));
+
+        // no overflow near the end of the index range
+        assert_eq!(page_bounds(u32::MAX - 1, u32::MAX, 10), (u32::MAX, None));
+
+        // always makes progress
+        assert_eq!(page_bounds(0, 2, 0), (1, Some(1)));
+    }
+}
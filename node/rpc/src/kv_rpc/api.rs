@@ -0,0 +1,22 @@
+use crate::types::{KvKey, KvValue, RpcResult};
+use ethereum_types::U256;
+use jsonrpsee::proc_macros::rpc;
+use kv::KvTxResult;
+
+#[rpc(server, client, namespace = "kv")]
+pub trait Rpc {
+    /// Returns the latest value of `key` in the stream, or the value as of the transaction
+    /// `version` if provided. Returns `None` if the key does not exist or is deleted.
+    #[method(name = "getValue")]
+    async fn get_value(
+        &self,
+        stream_id: U256,
+        key: KvKey,
+        version: Option<u64>,
+    ) -> RpcResult<Option<KvValue>>;
+
+    /// Returns whether the updates of the transaction are applied, or `None` if the transaction
+    /// is not processed yet or not in the subscribed streams.
+    #[method(name = "getTransactionResult")]
+    async fn get_transaction_result(&self, tx_seq: u64) -> RpcResult<Option<KvTxResult>>;
+}
@@ -0,0 +1,46 @@
+use super::api::RpcServer;
+use crate::types::{KvKey, KvValue, RpcResult};
+use crate::{error, Context};
+use ethereum_types::U256;
+use jsonrpsee::core::async_trait;
+use kv::{KvStore, KvTxResult};
+
+pub struct RpcServerImpl {
+    pub ctx: Context,
+}
+
+#[async_trait]
+impl RpcServer for RpcServerImpl {
+    #[tracing::instrument(skip(self), err)]
+    async fn get_value(
+        &self,
+        stream_id: U256,
+        key: KvKey,
+        version: Option<u64>,
+    ) -> RpcResult<Option<KvValue>> {
+        debug!("kv_getValue()");
+
+        let value = self.kv_store()?.get_value(stream_id, &key.0, version)?;
+
+        Ok(value.map(|value| KvValue {
+            version: value.version,
+            data: value.data,
+        }))
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn get_transaction_result(&self, tx_seq: u64) -> RpcResult<Option<KvTxResult>> {
+        debug!("kv_getTransactionResult({tx_seq})");
+
+        Ok(self.kv_store()?.get_tx_result(tx_seq)?)
+    }
+}
+
+impl RpcServerImpl {
+    fn kv_store(&self) -> Result<&KvStore, jsonrpsee::core::Error> {
+        match &self.ctx.kv_store {
+            Some(kv_store) => Ok(kv_store),
+            None => Err(error::internal_error("KV store is not enabled.")),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod api;
+mod r#impl;
+
+pub use api::RpcServer;
+pub use r#impl::RpcServerImpl;
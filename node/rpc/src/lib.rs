@@ -1,28 +1,61 @@
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate tracing;
 
 mod admin;
+mod auth;
 mod config;
 mod error;
 mod ionian;
-mod types;
+mod kv_rpc;
+mod metrics;
+mod mine;
+mod proxy;
+mod rate_limit;
+pub mod types;
+mod upload_session;
 
+use auth::Authenticator;
 use chunk_pool::MemoryChunkPool;
+use file_location_cache::FileLocationCache;
 use futures::channel::mpsc::Sender;
-use jsonrpsee::http_server::{HttpServerBuilder, HttpServerHandle};
+use jsonrpsee::core::server::rpc_module::Methods;
+use jsonrpsee::http_server::{AccessControlBuilder, HttpServerBuilder, HttpServerHandle};
+use jsonrpsee::ws_server::{WsServerBuilder, WsServerHandle};
+use kv::KvStore;
+use metrics::RpcMetrics;
+use miner::MinerMessage;
 use network::NetworkGlobals;
 use network::NetworkMessage;
+use proxy::Gate;
+use rate_limit::RateLimiter;
 use std::error::Error;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use storage_async::Store;
 use sync::SyncSender;
-use task_executor::ShutdownReason;
+use task_executor::{ShutdownReason, TaskExecutor};
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use upload_session::UploadSessions;
 
 use admin::RpcServer as AdminRpcServer;
 use ionian::RpcServer as IonianRpcServer;
+use kv_rpc::RpcServer as KvRpcServer;
+use mine::RpcServer as MinerRpcServer;
 
+pub use auth::AuthConfig;
 pub use config::Config as RPCConfig;
+pub use rate_limit::RateLimitConfig;
+
+/// Changes the tracing filter of the node at runtime.
+pub trait LogFilterControl: Send + Sync {
+    /// The filter directives in effect, e.g. `info,sync=debug`.
+    fn directives(&self) -> String;
+
+    fn set_directives(&self, directives: &str) -> Result<(), String>;
+}
 
 /// A wrapper around all the items required to spawn the HTTP server.
 ///
@@ -33,23 +66,143 @@ pub struct Context {
     pub network_globals: Option<Arc<NetworkGlobals>>,
     pub network_send: Option<UnboundedSender<NetworkMessage>>,
     pub sync_send: Option<SyncSender>,
+    pub miner_send: Option<UnboundedSender<MinerMessage>>,
+    /// Latest block number of the chain observed by the log sync.
+    pub chain_head: Option<watch::Receiver<Option<u64>>>,
+    /// Whether the local flow root matches the root committed on-chain, if verified.
+    pub flow_root_status: Option<watch::Receiver<Option<bool>>>,
     pub chunk_pool: Arc<MemoryChunkPool>,
     pub log_store: Store,
+    /// Key-value index of the subscribed streams, if enabled.
+    pub kv_store: Option<Arc<KvStore>>,
+    /// Files announced by peers, to count the replicas in the network.
+    pub file_location_cache: Option<Arc<FileLocationCache>>,
+    pub log_filter: Option<Arc<dyn LogFilterControl>>,
     pub shutdown_sender: Sender<ShutdownReason>,
+    pub executor: TaskExecutor,
 }
 
-pub async fn run_server(ctx: Context) -> Result<HttpServerHandle, Box<dyn Error>> {
+pub async fn run_server(
+    ctx: Context,
+) -> Result<(HttpServerHandle, Option<WsServerHandle>), Box<dyn Error>> {
+    let ws_listen_address = ctx.config.ws_listen_address;
+    let listen_address = ctx.config.listen_address;
+    let max_request_body_size = ctx.config.max_request_body_size;
+    let executor = ctx.executor.clone();
+    // Shared by both transports, so that an upload could be resumed on either of them.
+    let upload_sessions = Arc::new(UploadSessions::new(ctx.config.upload_session_timeout));
+
+    let gate = Arc::new(Gate {
+        rate_limiter: match &ctx.config.rate_limit {
+            Some(config) => Some(RateLimiter::new(config)?),
+            None => None,
+        },
+        authenticator: match &ctx.config.auth {
+            Some(config) => Some(Authenticator::new(config)?),
+            None => None,
+        },
+        max_request_body_size,
+        upstream_host: format!("{:032x}.proxy.invalid", rand::random::<u128>()),
+    });
+    let gated = gate.rate_limiter.is_some() || gate.authenticator.is_some();
+    let restricted = gate.authenticator.is_some();
+    let ws_gated = gate.rate_limiter.is_some();
+
+    // With the gate enabled, the server is only reachable via the proxy. It serves the protected
+    // methods as well, so the requests bypassing the proxy on the loopback interface are rejected
+    // by the host only known to the proxy.
+    let http_listen_address = if gated {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    } else {
+        listen_address
+    };
+    let mut access_control = AccessControlBuilder::new();
+    if let Some(origins) = &ctx.config.cors_allowed_origins {
+        access_control = access_control.set_allowed_origins(origins)?;
+    }
+    if gated {
+        access_control = access_control.set_allowed_hosts([gate.upstream_host.clone()])?;
+    }
     let server = HttpServerBuilder::default()
-        .build(ctx.config.listen_address)
+        .set_access_control(access_control.build())
+        .max_request_body_size(max_request_body_size)
+        .set_middleware(RpcMetrics)
+        .build(http_listen_address)
         .await?;
 
-    let mut ionian = (ionian::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
-    let admin = (admin::RpcServerImpl { ctx }).into_rpc();
-    ionian.merge(admin)?;
-
     let addr = server.local_addr()?;
-    let handle = server.start(ionian)?;
-    info!("Server started http://{}", addr);
+    let handle = server.start(rpc_module(ctx.clone(), upload_sessions.clone(), false)?)?;
+
+    if gated {
+        let proxy = proxy::serve(listen_address, addr, gate.clone())?;
+        executor.spawn(proxy, "rpc_proxy");
+        info!(%restricted, "Server started http://{} via proxy", listen_address);
+    } else {
+        info!("Server started http://{}", addr);
+    }
+
+    // Subscriptions are only available over WebSocket. Since the WebSocket server cannot
+    // authenticate requests, protected methods are unavailable on it if authentication is enabled.
+    // With rate limiting enabled, the server is only reachable via the proxy, which limits the
+    // connections and throttles the traffic of subscriptions per client.
+    let ws_handle = match ws_listen_address {
+        Some(ws_address) => {
+            let ws_listen_address = if ws_gated {
+                SocketAddr::from(([127, 0, 0, 1], 0))
+            } else {
+                ws_address
+            };
+            let mut ws_builder =
+                WsServerBuilder::default().max_request_body_size(max_request_body_size);
+            if let Some(origins) = &ctx.config.cors_allowed_origins {
+                ws_builder = ws_builder.set_allowed_origins(origins)?;
+            }
+            let ws_server = ws_builder
+                .set_middleware(RpcMetrics)
+                .build(ws_listen_address)
+                .await?;
+            let addr = ws_server.local_addr()?;
+            let handle = ws_server.start(rpc_module(ctx, upload_sessions, restricted)?)?;
+            if ws_gated {
+                let proxy = proxy::serve_ws(ws_address, addr, gate).await?;
+                executor.spawn(proxy, "rpc_ws_proxy");
+                info!("Server started ws://{} via proxy", ws_address);
+            } else {
+                info!("Server started ws://{}", addr);
+            }
+            Some(handle)
+        }
+        None => None,
+    };
+
+    Ok((handle, ws_handle))
+}
+
+/// Builds the RPC methods. Protected methods are excluded or rejected if `restricted`.
+fn rpc_module(
+    ctx: Context,
+    upload_sessions: Arc<UploadSessions>,
+    restricted: bool,
+) -> Result<Methods, Box<dyn Error>> {
+    let mut ionian = (ionian::RpcServerImpl {
+        ctx: ctx.clone(),
+        upload_sessions,
+        restricted,
+    })
+    .into_rpc();
+
+    if ctx.kv_store.is_some() {
+        let kv = (kv_rpc::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+        ionian.merge(kv)?;
+    }
+
+    if !restricted {
+        let miner = (mine::RpcServerImpl { ctx: ctx.clone() }).into_rpc();
+        ionian.merge(miner)?;
+
+        let admin = (admin::RpcServerImpl { ctx }).into_rpc();
+        ionian.merge(admin)?;
+    }
 
-    Ok(handle)
+    Ok(ionian.into())
 }
@@ -0,0 +1,44 @@
+use jsonrpsee::core::middleware::Middleware;
+pub use lighthouse_metrics::*;
+use std::time::Instant;
+
+lazy_static! {
+    pub static ref RPC_REQUESTS: Result<IntCounterVec> = try_create_int_counter_vec(
+        "rpc_requests_total",
+        "Count of RPC method calls",
+        &["method", "result"]
+    );
+    pub static ref RPC_REQUEST_TIMES: Result<HistogramVec> = try_create_histogram_vec(
+        "rpc_request_seconds",
+        "Time taken to handle an RPC method call",
+        &["method"]
+    );
+    pub static ref RPC_CONNECTIONS: Result<IntGauge> =
+        try_create_int_gauge("rpc_connections", "Number of open RPC connections");
+}
+
+/// Server middleware that records the call count and duration of each RPC method.
+#[derive(Clone, Default)]
+pub struct RpcMetrics;
+
+impl Middleware for RpcMetrics {
+    type Instant = Instant;
+
+    fn on_connect(&self) {
+        inc_gauge(&RPC_CONNECTIONS);
+    }
+
+    fn on_request(&self) -> Self::Instant {
+        Instant::now()
+    }
+
+    fn on_result(&self, name: &str, success: bool, started_at: Self::Instant) {
+        let result = if success { "success" } else { "error" };
+        inc_counter_vec(&RPC_REQUESTS, &[name, result]);
+        observe_timer_vec(&RPC_REQUEST_TIMES, &[name], started_at.elapsed());
+    }
+
+    fn on_disconnect(&self) {
+        dec_gauge(&RPC_CONNECTIONS);
+    }
+}
@@ -0,0 +1,10 @@
+use crate::types::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use miner::MinerStats;
+
+#[rpc(server, client, namespace = "miner")]
+pub trait Rpc {
+    /// Returns the mining statistics since the node started.
+    #[method(name = "getStats")]
+    async fn get_stats(&self) -> RpcResult<MinerStats>;
+}
@@ -0,0 +1,20 @@
+use super::api::RpcServer;
+use crate::admin::request_miner;
+use crate::types::RpcResult;
+use crate::Context;
+use jsonrpsee::core::async_trait;
+use miner::{MinerMessage, MinerStats};
+
+pub struct RpcServerImpl {
+    pub ctx: Context,
+}
+
+#[async_trait]
+impl RpcServer for RpcServerImpl {
+    #[tracing::instrument(skip(self), err)]
+    async fn get_stats(&self) -> RpcResult<MinerStats> {
+        debug!("miner_getStats()");
+
+        request_miner(&self.ctx, |result| MinerMessage::GetStats { result }).await
+    }
+}
@@ -0,0 +1,355 @@
+//! HTTP proxy in front of the RPC server to enforce per-IP rate limits and authentication.
+//!
+//! jsonrpsee does not allow rejecting a request based on its remote address or HTTP headers. So
+//! when either is enabled, the RPC server listens on a local ephemeral port, and this proxy bound
+//! to the configured listen address checks requests before forwarding them to it.
+//!
+//! Likewise, the WebSocket server is put behind a TCP proxy if rate limiting is enabled, which
+//! counts every connection as a request, and throttles the responses and subscription
+//! notifications by the bytes quota of the client.
+
+use crate::auth::{self, Authenticator};
+use crate::error::{RATE_LIMITED_ERROR_CODE, UNAUTHORIZED_ERROR_CODE};
+use crate::rate_limit::RateLimiter;
+use futures::TryStreamExt;
+use hyper::body::{Bytes, HttpBody};
+use hyper::client::HttpConnector;
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Client, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Interval to prune clients whose buckets have been fully replenished.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Size of the buffer to forward WebSocket traffic from the server to a client.
+const WS_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Checks applied to every request before it is forwarded.
+pub struct Gate {
+    pub rate_limiter: Option<RateLimiter>,
+    pub authenticator: Option<Authenticator>,
+    /// Requests with a larger body are rejected, without reading the body if the size is declared.
+    pub max_request_body_size: u32,
+    /// Host of the forwarded requests, which is the only host allowed by the RPC server, so that
+    /// the requests not via the proxy are rejected.
+    pub upstream_host: String,
+}
+
+/// Starts the proxy on `listen_address`, which forwards allowed requests to the RPC server on
+/// `upstream`. Returns the future to drive the proxy.
+pub fn serve(
+    listen_address: SocketAddr,
+    upstream: SocketAddr,
+    gate: Arc<Gate>,
+) -> Result<impl Future<Output = ()>, hyper::Error> {
+    let client = Client::new();
+
+    let prune_gate = gate.clone();
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote_ip = conn.remote_addr().ip();
+        let client = client.clone();
+        let gate = gate.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                forward(req, remote_ip, upstream, client.clone(), gate.clone())
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&listen_address)?.serve(make_service);
+
+    Ok(async move {
+        let mut prune_interval = tokio::time::interval(PRUNE_INTERVAL);
+        let prune = async move {
+            loop {
+                prune_interval.tick().await;
+                if let Some(rate_limiter) = &prune_gate.rate_limiter {
+                    rate_limiter.prune();
+                }
+            }
+        };
+
+        tokio::select! {
+            res = server => {
+                if let Err(e) = res {
+                    error!(error = %e, "RPC proxy failed");
+                }
+            }
+            _ = prune => {}
+        }
+    })
+}
+
+/// Starts the WebSocket proxy on `listen_address`, which forwards the connections allowed by the
+/// rate limiter to the WebSocket server on `upstream`. Returns the future to drive the proxy.
+pub async fn serve_ws(
+    listen_address: SocketAddr,
+    upstream: SocketAddr,
+    gate: Arc<Gate>,
+) -> io::Result<impl Future<Output = ()>> {
+    let listener = TcpListener::bind(listen_address).await?;
+
+    Ok(async move {
+        loop {
+            let (stream, remote_addr) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept WebSocket connection");
+                    continue;
+                }
+            };
+
+            let remote_ip = remote_addr.ip();
+            if let Some(rate_limiter) = &gate.rate_limiter {
+                if let Err(wait) = rate_limiter.allows(&remote_ip) {
+                    debug!(%remote_ip, ?wait, "WebSocket connection rate limited");
+                    continue;
+                }
+            }
+
+            let gate = gate.clone();
+            tokio::spawn(async move {
+                if let Err(e) = forward_ws(stream, remote_ip, upstream, gate).await {
+                    debug!(%remote_ip, error = %e, "WebSocket connection closed");
+                }
+            });
+        }
+    })
+}
+
+/// Forwards the WebSocket traffic between the client and the server until either side closes,
+/// where the traffic to the client waits for the bytes quota of the client to replenish.
+async fn forward_ws(
+    client: TcpStream,
+    remote_ip: IpAddr,
+    upstream: SocketAddr,
+    gate: Arc<Gate>,
+) -> io::Result<()> {
+    let server = TcpStream::connect(upstream).await?;
+    let (mut client_read, mut client_write) = client.into_split();
+    let (mut server_read, mut server_write) = server.into_split();
+
+    let requests = io::copy(&mut client_read, &mut server_write);
+    let responses = async {
+        let mut buf = vec![0; WS_BUFFER_SIZE];
+        loop {
+            let len = server_read.read(&mut buf).await?;
+            if len == 0 {
+                return client_write.shutdown().await;
+            }
+            if let Some(rate_limiter) = &gate.rate_limiter {
+                while let Err(wait) = rate_limiter.allows_bytes(&remote_ip) {
+                    tokio::time::sleep(wait).await;
+                }
+                rate_limiter.on_served(&remote_ip, len);
+            }
+            client_write.write_all(&buf[..len]).await?;
+        }
+    };
+
+    tokio::select! {
+        res = requests => res.map(|_| ()),
+        res = responses => res,
+    }
+}
+
+async fn forward(
+    req: Request<Body>,
+    remote_ip: IpAddr,
+    upstream: SocketAddr,
+    client: Client<HttpConnector>,
+    gate: Arc<Gate>,
+) -> Result<Response<Body>, Infallible> {
+    if let Some(rate_limiter) = &gate.rate_limiter {
+        if let Err(wait) = rate_limiter.allows(&remote_ip) {
+            debug!(%remote_ip, ?wait, "RPC request rate limited");
+            return Ok(rate_limited_response(wait));
+        }
+    }
+
+    let (mut parts, body) = req.into_parts();
+    let content_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.map_or(false, |len| len > gate.max_request_body_size as u64) {
+        return Ok(error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Request body too large".into(),
+        ));
+    }
+
+    let body = match read_body(body, gate.max_request_body_size as usize).await {
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            return Ok(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Request body too large".into(),
+            ))
+        }
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    if let Some(authenticator) = &gate.authenticator {
+        if calls_protected_method(&body) {
+            let authorization = parts
+                .headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok());
+            if let Err(e) = authenticator.authorize(authorization) {
+                debug!(%remote_ip, %e, "RPC request unauthorized");
+                return Ok(unauthorized_response(&e));
+            }
+        }
+    }
+
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    parts.uri = match format!("http://{}{}", upstream, path).parse() {
+        Ok(uri) => uri,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    let host = match header::HeaderValue::from_str(&gate.upstream_host) {
+        Ok(host) => host,
+        Err(e) => return Ok(error_response(StatusCode::BAD_GATEWAY, e.to_string())),
+    };
+    parts.headers.insert(header::HOST, host);
+
+    let (parts, body) = match client
+        .request(Request::from_parts(parts, body.into()))
+        .await
+    {
+        Ok(res) => res.into_parts(),
+        Err(e) => return Ok(error_response(StatusCode::BAD_GATEWAY, e.to_string())),
+    };
+
+    // The response is streamed to the client, and charged to the bytes quota as it is served.
+    let body = body.map_ok(move |chunk| {
+        if let Some(rate_limiter) = &gate.rate_limiter {
+            rate_limiter.on_served(&remote_ip, chunk.len());
+        }
+        chunk
+    });
+
+    Ok(Response::from_parts(parts, Body::wrap_stream(body)))
+}
+
+/// Reads the body, or returns `None` once it exceeds `max_size`, which is checked as the body is
+/// streamed since the declared size is absent for chunked bodies.
+async fn read_body(mut body: Body, max_size: usize) -> Result<Option<Bytes>, hyper::Error> {
+    let mut buf = Vec::new();
+    while let Some(data) = body.data().await {
+        let data = data?;
+        if buf.len() + data.len() > max_size {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&data);
+    }
+    Ok(Some(buf.into()))
+}
+
+/// Returns whether the JSON-RPC request or any call of the batch request invokes a protected
+/// method. Malformed requests are forwarded as is and rejected by the RPC server.
+fn calls_protected_method(body: &[u8]) -> bool {
+    let is_protected_call = |call: &serde_json::Value| {
+        call.get("method")
+            .and_then(|method| method.as_str())
+            .map_or(false, auth::is_protected)
+    };
+
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(calls)) => calls.iter().any(is_protected_call),
+        Ok(call) => is_protected_call(&call),
+        Err(_) => false,
+    }
+}
+
+fn json_rpc_error_response(status: StatusCode, code: i32, message: &str) -> Response<Body> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": null,
+    });
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid response")
+}
+
+fn rate_limited_response(wait: Duration) -> Response<Body> {
+    let mut response = json_rpc_error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        RATE_LIMITED_ERROR_CODE,
+        "Too many requests",
+    );
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from(wait.as_secs().max(1)),
+    );
+    response
+}
+
+fn unauthorized_response(reason: &str) -> Response<Body> {
+    json_rpc_error_response(
+        StatusCode::UNAUTHORIZED,
+        UNAUTHORIZED_ERROR_CODE,
+        &format!("Unauthorized: {}", reason),
+    )
+}
+
+fn error_response(status: StatusCode, msg: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(msg))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calls_protected_method, read_body};
+    use futures::executor::block_on;
+    use hyper::Body;
+
+    #[test]
+    fn test_calls_protected_method() {
+        let call = |method: &str| {
+            format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"{}","params":[]}}"#,
+                method
+            )
+        };
+
+        assert!(!calls_protected_method(call("ionian_getStatus").as_bytes()));
+        assert!(calls_protected_method(call("admin_shutdown").as_bytes()));
+
+        let batch = format!("[{},{}]", call("ionian_getStatus"), call("admin_shutdown"));
+        assert!(calls_protected_method(batch.as_bytes()));
+
+        assert!(!calls_protected_method(b"not json"));
+    }
+
+    #[test]
+    fn test_read_body_limit() {
+        let body = || Body::from(vec![1u8; 100]);
+
+        assert_eq!(
+            block_on(read_body(body(), 100)).unwrap().unwrap().len(),
+            100
+        );
+        assert!(block_on(read_body(body(), 99)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,232 @@
+//! Per-IP rate limiting of the RPC servers, which is enforced by the gate proxies.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Served bytes are metered in KiB to keep the token replenish period representable.
+const BYTES_PER_TOKEN: u64 = 1024;
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    /// Sustained number of requests allowed per second for each client IP.
+    pub requests_per_sec: Option<u64>,
+    /// Maximum number of requests that can be served instantaneously for each client IP.
+    pub burst: u64,
+    /// Number of response bytes allowed per minute for each client IP.
+    pub bytes_per_min: Option<u64>,
+}
+
+/// Nanoseconds since the creation of the rate limiter.
+type Nanosecs = u64;
+
+/// Per key token bucket with the GCRA implementation, the same as the p2p RPC rate limiter.
+struct Limiter {
+    /// After how long is the bucket considered full via replenishing 1T every `t`.
+    tau: Nanosecs,
+    /// How often is 1T replenished.
+    t: Nanosecs,
+    /// Time when the bucket will be full for each client (TAT from GCRA).
+    tat_per_key: HashMap<IpAddr, Nanosecs>,
+}
+
+impl Limiter {
+    fn new(max_tokens: u64, replenish_all_every: Duration) -> Result<Self, &'static str> {
+        if max_tokens == 0 {
+            return Err("Max number of tokens should be positive");
+        }
+        let tau = replenish_all_every.as_nanos();
+        let t = (tau / max_tokens as u128)
+            .try_into()
+            .map_err(|_| "total replenish time is too long")?;
+        if t == 0 {
+            return Err("Too many tokens for the replenish time");
+        }
+        let tau = tau
+            .try_into()
+            .map_err(|_| "total replenish time is too long")?;
+        Ok(Limiter {
+            tau,
+            t,
+            tat_per_key: HashMap::default(),
+        })
+    }
+
+    /// Checks whether `tokens` can be consumed now, and consumes them if so. Otherwise, returns
+    /// how long the client has to wait.
+    fn allows(&mut self, now: Nanosecs, key: &IpAddr, tokens: u64) -> Result<(), Duration> {
+        let additional_time = self.t.saturating_mul(tokens);
+        let tat = self.tat_per_key.entry(*key).or_insert(now);
+        let earliest_time = (*tat + additional_time).saturating_sub(self.tau);
+        if now < earliest_time {
+            Err(Duration::from_nanos(earliest_time - now))
+        } else {
+            *tat = now.max(*tat) + additional_time;
+            Ok(())
+        }
+    }
+
+    /// Consumes `tokens` unconditionally, which is used when the cost is only known afterwards.
+    fn charge(&mut self, now: Nanosecs, key: &IpAddr, tokens: u64) {
+        let tat = self.tat_per_key.entry(*key).or_insert(now);
+        *tat = now.max(*tat).saturating_add(self.t.saturating_mul(tokens));
+    }
+
+    fn prune(&mut self, now: Nanosecs) {
+        self.tat_per_key.retain(|_k, tat| *tat >= now)
+    }
+}
+
+/// Rate limiter of requests and served bytes per client IP.
+pub struct RateLimiter {
+    init_time: Instant,
+    requests: Option<Mutex<Limiter>>,
+    bytes: Option<Mutex<Limiter>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Result<Self, &'static str> {
+        let requests = match config.requests_per_sec {
+            Some(rate) => {
+                if rate == 0 {
+                    return Err("Requests per second should be positive");
+                }
+                if config.burst == 0 {
+                    return Err("Request burst should be positive");
+                }
+                let rate = u32::try_from(rate).map_err(|_| "Too many requests per second")?;
+                // Allow `burst` requests instantaneously, which are replenished at `rate`.
+                let replenish = Duration::from_secs(config.burst) / rate;
+                Some(Mutex::new(Limiter::new(config.burst, replenish)?))
+            }
+            None => None,
+        };
+
+        let bytes = match config.bytes_per_min {
+            Some(bytes) => Some(Mutex::new(Limiter::new(
+                (bytes / BYTES_PER_TOKEN).max(1),
+                Duration::from_secs(60),
+            )?)),
+            None => None,
+        };
+
+        Ok(RateLimiter {
+            init_time: Instant::now(),
+            requests,
+            bytes,
+        })
+    }
+
+    fn now(&self) -> Nanosecs {
+        self.init_time.elapsed().as_nanos() as u64
+    }
+
+    /// Checks if a new request from `ip` is allowed. Returns the time to wait if not.
+    pub fn allows(&self, ip: &IpAddr) -> Result<(), Duration> {
+        // Reject if the bytes quota is already used up, and do not consume any request token.
+        self.allows_bytes(ip)?;
+
+        if let Some(requests) = &self.requests {
+            requests
+                .lock()
+                .expect("not poisoned")
+                .allows(self.now(), ip, 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if more bytes could be served to `ip`. Returns the time to wait if the bytes quota
+    /// is used up.
+    pub fn allows_bytes(&self, ip: &IpAddr) -> Result<(), Duration> {
+        match &self.bytes {
+            Some(bytes) => bytes
+                .lock()
+                .expect("not poisoned")
+                .allows(self.now(), ip, 0),
+            None => Ok(()),
+        }
+    }
+
+    /// Records `size` bytes served to `ip`.
+    pub fn on_served(&self, ip: &IpAddr, size: usize) {
+        if let Some(bytes) = &self.bytes {
+            let tokens = (size as u64 + BYTES_PER_TOKEN - 1) / BYTES_PER_TOKEN;
+            bytes
+                .lock()
+                .expect("not poisoned")
+                .charge(self.now(), ip, tokens);
+        }
+    }
+
+    pub fn prune(&self) {
+        let now = self.now();
+        if let Some(requests) = &self.requests {
+            requests.lock().expect("not poisoned").prune(now);
+        }
+        if let Some(bytes) = &self.bytes {
+            bytes.lock().expect("not poisoned").prune(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimitConfig, RateLimiter};
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_requests_burst() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            requests_per_sec: Some(1),
+            burst: 3,
+            bytes_per_min: None,
+        })
+        .unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(limiter.allows(&ip).is_ok());
+        }
+        assert!(limiter.allows(&ip).is_err());
+
+        // Quota is tracked per IP.
+        assert!(limiter.allows(&other).is_ok());
+    }
+
+    #[test]
+    fn test_bytes_quota() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            requests_per_sec: None,
+            burst: 0,
+            bytes_per_min: Some(10 * 1024),
+        })
+        .unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert!(limiter.allows(&ip).is_ok());
+        limiter.on_served(&ip, 8 * 1024);
+        assert!(limiter.allows(&ip).is_ok());
+        limiter.on_served(&ip, 8 * 1024);
+        assert!(limiter.allows(&ip).is_err());
+        assert!(limiter.allows_bytes(&ip).is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = |requests_per_sec, burst| RateLimitConfig {
+            requests_per_sec: Some(requests_per_sec),
+            burst,
+            bytes_per_min: None,
+        };
+
+        assert!(RateLimiter::new(&config(0, 100)).is_err());
+        assert!(RateLimiter::new(&config(10, 0)).is_err());
+        assert!(RateLimiter::new(&config(u64::MAX, 100)).is_err());
+        assert!(RateLimiter::new(&config(1, u64::MAX)).is_err());
+        // Fine as long as the replenish time fits in nanoseconds.
+        assert!(RateLimiter::new(&config(1000, 1_000_000_000_000)).is_ok());
+    }
+}
@@ -1,159 +1,101 @@
-use crate::error;
+use ethereum_types::Address;
 use jsonrpsee::core::Error as RpcError;
-use merkle_light::hash::Algorithm;
-use merkle_light::merkle::MerkleTree;
-use merkle_tree::{RawLeafSha3Algorithm, LEAF};
 use serde::{Deserialize, Serialize};
-use shared_types::{DataRoot, FileProof, Transaction, CHUNK_SIZE};
-use std::hash::Hasher;
+use shared_types::rpc::base64;
+pub use shared_types::rpc::{
+    ChunkRange, FileId, FileInfo, FileQuery, ProofVerification, SegmentPage, SegmentWithProof,
+    Status, UploadSession,
+};
+use shared_types::DataRoot;
 
 pub(crate) type RpcResult<T> = Result<T, RpcError>;
 
-#[derive(Serialize, Deserialize)]
+/// The flow root of the node, to be cross-checked against the log contract.
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Status {
-    pub connected_peers: usize,
+pub struct FlowRoot {
+    /// The current flow root.
+    pub root: DataRoot,
+    /// Number of entries in the flow, including the padding between files.
+    pub length: u64,
+    /// The flow root right after the requested transaction is appended, if available.
+    pub tx_seq_root: Option<DataRoot>,
 }
 
+/// A connected peer along with the RPC traffic, where the bytes are counted before compression.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct FileInfo {
-    pub tx: Transaction,
-    pub finalized: bool,
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub client: String,
+    /// The libp2p protocol version reported by identify.
+    pub protocol_version: String,
+    pub agent_version: Option<String>,
+    /// Direction of the first connection with the peer, `incoming` or `outgoing`.
+    pub direction: Option<String>,
+    pub connected_secs: u64,
+    pub score: f64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub responses: u64,
+    pub failed_requests: u64,
+    /// Moving average of the time to receive the first response of a request.
+    pub latency_ms: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Segment(#[serde(with = "base64")] pub Vec<u8>);
-
+/// I/O of the store issued by a subsystem since the node started, where the bytes only count the
+/// chunk data.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct SegmentWithProof {
-    /// File merkle root.
-    pub root: DataRoot,
-    #[serde(with = "base64")]
-    /// With fixed data size except the last segment.
-    pub data: Vec<u8>,
-    /// Segment index.
-    pub index: u32,
-    /// File merkle proof whose leaf node is segment root.
-    pub proof: FileProof,
+pub struct StoreIoStats {
+    /// The subsystem, `rpc`, `sync`, `miner`, `scrub` or `other`.
+    pub origin: String,
+    pub read_ops: u64,
+    pub read_bytes: u64,
+    pub write_ops: u64,
+    pub write_bytes: u64,
 }
 
-impl SegmentWithProof {
-    /// Splits file into segments and returns the total number of segments and the last segment size.
-    fn split_file_into_segments(
-        &self,
-        file_size: usize,
-        chunks_per_segment: usize,
-    ) -> RpcResult<(u32, usize)> {
-        if file_size == 0 {
-            return Err(error::invalid_params("file_size", "file is empty"));
-        }
-
-        let segment_size = chunks_per_segment * CHUNK_SIZE;
-        let remaining_size = file_size % segment_size;
-        let mut num_segments = file_size / segment_size;
-
-        if remaining_size == 0 {
-            return Ok((num_segments as u32, segment_size));
-        }
-
-        // Otherwise, the last segment is not full.
-        num_segments += 1;
-
-        let last_chunk_size = remaining_size % CHUNK_SIZE;
-        if last_chunk_size == 0 {
-            Ok((num_segments as u32, remaining_size))
-        } else {
-            // Padding last chunk with zeros.
-            let last_segment_size = remaining_size - last_chunk_size + CHUNK_SIZE;
-            Ok((num_segments as u32, last_segment_size))
-        }
-    }
-
-    fn validate_data_size_and_index(
-        &self,
-        file_size: usize,
-        chunks_per_segment: usize,
-    ) -> RpcResult<u32> {
-        let (num_segments, last_segment_size) =
-            self.split_file_into_segments(file_size, chunks_per_segment)?;
-
-        if self.index >= num_segments {
-            return Err(error::invalid_params("index", "index out of bound"));
-        }
-
-        let data_size = if self.index == num_segments - 1 {
-            last_segment_size
-        } else {
-            chunks_per_segment * CHUNK_SIZE
-        };
-
-        if self.data.len() != data_size {
-            return Err(error::invalid_params("data", "invalid data length"));
-        }
-
-        Ok(num_segments)
-    }
-
-    fn calculate_segment_merkle_root(&self) -> [u8; 32] {
-        let mut a = RawLeafSha3Algorithm::default();
-        let hashes = self.data.chunks_exact(CHUNK_SIZE).map(|x| {
-            a.reset();
-            a.write(&[LEAF]);
-            a.write(x);
-            a.hash()
-        });
-        MerkleTree::<_, RawLeafSha3Algorithm>::new(hashes).root()
-    }
-
-    fn validate_proof(&self, num_segments: usize) -> RpcResult<()> {
-        // Validate proof data format at first.
-        if self.proof.path.is_empty() {
-            if self.proof.lemma.len() != 1 {
-                return Err(error::invalid_params("proof", "invalid proof"));
-            }
-        } else if self.proof.lemma.len() != self.proof.path.len() + 2 {
-            return Err(error::invalid_params("proof", "invalid proof"));
-        }
+/// Filters of `admin_getTxList`, which all apply if set.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TxListFilter {
+    /// Only lists the finalized transactions.
+    pub finalized_only: bool,
+    /// Minimum file size in bytes (included).
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes (included).
+    pub max_size: Option<u64>,
+    /// Only lists the transactions submitted by the account.
+    pub sender: Option<Address>,
+}
 
-        // Calculate segment merkle root to verify proof.
-        let segment_root = self.calculate_segment_merkle_root();
-        if !self
-            .proof
-            .validate(&segment_root, &self.root, self.index as usize, num_segments)?
-        {
-            return Err(error::invalid_params("proof", "validation failed"));
+impl From<TxListFilter> for storage::log_store::TxListFilter {
+    fn from(filter: TxListFilter) -> Self {
+        Self {
+            finalized_only: filter.finalized_only,
+            min_size: filter.min_size,
+            max_size: filter.max_size,
+            sender: filter.sender,
         }
-
-        Ok(())
-    }
-
-    /// Validates the segment data size and proof.
-    pub fn validate(&self, file_size: usize, chunks_per_segment: usize) -> RpcResult<()> {
-        let num_segments = self.validate_data_size_and_index(file_size, chunks_per_segment)?;
-        self.validate_proof(num_segments as usize)?;
-        Ok(())
-    }
-
-    /// Returns the index of first chunk in the segment.
-    pub fn chunk_index(&self, chunks_per_segment: usize) -> usize {
-        self.index as usize * chunks_per_segment
     }
 }
 
-mod base64 {
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Segment(#[serde(with = "base64")] pub Vec<u8>);
 
-    pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
-        let base64 = base64::encode(v);
-        String::serialize(&base64, s)
-    }
+/// A key of the KV store.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KvKey(#[serde(with = "base64")] pub Vec<u8>);
 
-    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
-        let base64 = String::deserialize(d)?;
-        base64::decode(base64.as_bytes()).map_err(serde::de::Error::custom)
-    }
+/// A version of a value in the KV store.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KvValue {
+    /// Sequence number of the transaction that writes the value.
+    pub version: u64,
+    #[serde(with = "base64")]
+    pub data: Vec<u8>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,176 @@
+//! Upload sessions that track the segments remaining to upload for each file, so that interrupted
+//! uploads could be resumed without uploading the whole file again.
+
+use crate::types::UploadSession;
+use hashlink::LinkedHashMap;
+use shared_types::{bytes_to_chunks, DataRoot, FileMetadata};
+use std::ops::Add;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+struct Entry {
+    session: UploadSession,
+    /// File metadata to store once the transaction of the file is synced.
+    pending_metadata: Option<FileMetadata>,
+    /// Used for garbage collection.
+    expired_at: Instant,
+}
+
+/// Upload sessions ordered by expiration time, which expire if not accessed in time.
+pub struct UploadSessions {
+    timeout: Duration,
+    sessions: Mutex<LinkedHashMap<DataRoot, Entry>>,
+}
+
+impl UploadSessions {
+    pub fn new(timeout: Duration) -> Self {
+        UploadSessions {
+            timeout,
+            sessions: Default::default(),
+        }
+    }
+
+    /// Returns the session of the specified file, and extends its expiration time.
+    pub async fn get(&self, root: &DataRoot) -> Option<UploadSession> {
+        let mut sessions = self.sessions.lock().await;
+        garbage_collect(&mut sessions);
+
+        let entry = sessions.to_back(root)?;
+        entry.expired_at = Instant::now().add(self.timeout);
+        Some(entry.session.clone())
+    }
+
+    pub async fn insert(&self, session: UploadSession) {
+        let mut sessions = self.sessions.lock().await;
+        garbage_collect(&mut sessions);
+
+        let entry = Entry {
+            session,
+            pending_metadata: None,
+            expired_at: Instant::now().add(self.timeout),
+        };
+        sessions.replace(entry.session.data_root, entry);
+    }
+
+    /// Keeps the metadata of the file in its session, until the transaction of the file is synced.
+    pub async fn set_pending_metadata(&self, root: &DataRoot, metadata: FileMetadata) {
+        let mut sessions = self.sessions.lock().await;
+
+        if let Some(entry) = sessions.get_mut(root) {
+            entry.pending_metadata = Some(metadata);
+        }
+    }
+
+    pub async fn take_pending_metadata(&self, root: &DataRoot) -> Option<FileMetadata> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.get_mut(root)?.pending_metadata.take()
+    }
+
+    /// Removes the uploaded segment from the missing segments of the file session if any.
+    pub async fn on_segment_uploaded(&self, root: &DataRoot, index: u32) {
+        let mut sessions = self.sessions.lock().await;
+
+        if let Some(entry) = sessions.to_back(root) {
+            remove_segment(&mut entry.session.missing_segments, index);
+            entry.expired_at = Instant::now().add(self.timeout);
+        }
+    }
+}
+
+fn garbage_collect(sessions: &mut LinkedHashMap<DataRoot, Entry>) {
+    while let Some((_, entry)) = sessions.front() {
+        if entry.expired_at > Instant::now() {
+            return;
+        }
+
+        if let Some((root, _)) = sessions.pop_front() {
+            debug!("Upload session expired for file {}", root);
+        }
+    }
+}
+
+/// Creates a session of the file, of which the chunks in `chunk_ranges` have been stored.
+pub fn new_session(
+    data_root: DataRoot,
+    size: u64,
+    chunks_per_segment: usize,
+    chunk_ranges: &[(usize, usize)],
+) -> UploadSession {
+    let num_chunks = bytes_to_chunks(size as usize);
+    let num_segments = ((num_chunks + chunks_per_segment - 1) / chunks_per_segment) as u32;
+
+    // A segment is uploaded only if all its chunks are stored.
+    let is_stored = |index: u32| {
+        let start = index as usize * chunks_per_segment;
+        let end = std::cmp::min(start + chunks_per_segment, num_chunks);
+        chunk_ranges.iter().any(|&(s, e)| s <= start && end <= e)
+    };
+
+    let mut missing_segments: Vec<(u32, u32)> = Vec::new();
+    for index in (0..num_segments).filter(|&index| !is_stored(index)) {
+        match missing_segments.last_mut() {
+            Some(last) if last.1 == index => last.1 = index + 1,
+            _ => missing_segments.push((index, index + 1)),
+        }
+    }
+
+    UploadSession {
+        data_root,
+        size,
+        chunks_per_segment,
+        num_segments,
+        missing_segments,
+    }
+}
+
+fn remove_segment(ranges: &mut Vec<(u32, u32)>, index: u32) {
+    let pos = match ranges.iter().position(|&(s, e)| s <= index && index < e) {
+        Some(pos) => pos,
+        None => return,
+    };
+
+    let (start, end) = ranges[pos];
+    match (start == index, index + 1 == end) {
+        (true, true) => {
+            ranges.remove(pos);
+        }
+        (true, false) => ranges[pos].0 = index + 1,
+        (false, true) => ranges[pos].1 = index,
+        (false, false) => {
+            ranges[pos].1 = index;
+            ranges.insert(pos + 1, (index + 1, end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_session, remove_segment};
+    use shared_types::{DataRoot, CHUNK_SIZE};
+
+    #[test]
+    fn test_new_session() {
+        let size = (CHUNK_SIZE * 10 + 1) as u64;
+        let session = new_session(DataRoot::zero(), size, 2, &[(0, 4), (6, 9), (10, 11)]);
+
+        assert_eq!(session.num_segments, 6);
+        assert_eq!(session.missing_segments, vec![(2, 3), (4, 5)]);
+
+        let session = new_session(DataRoot::zero(), size, 2, &[]);
+        assert_eq!(session.missing_segments, vec![(0, 6)]);
+    }
+
+    #[test]
+    fn test_remove_segment() {
+        let mut ranges = vec![(0, 5), (7, 8)];
+
+        remove_segment(&mut ranges, 2);
+        assert_eq!(ranges, vec![(0, 2), (3, 5), (7, 8)]);
+
+        remove_segment(&mut ranges, 0);
+        remove_segment(&mut ranges, 4);
+        remove_segment(&mut ranges, 7);
+        remove_segment(&mut ranges, 6);
+        assert_eq!(ranges, vec![(1, 2), (3, 4)]);
+    }
+}
@@ -1,10 +1,14 @@
-use anyhow::bail;
-use append_merkle::{Proof as RawProof, RangeProof as RawRangeProof};
-use ethereum_types::{H256, U256};
-use merkle_light::merkle::next_pow2;
+pub mod rpc;
+
+use anyhow::{bail, ensure};
+use append_merkle::{Algorithm, Proof as RawProof, RangeProof as RawRangeProof, Sha3Algorithm};
+use ethereum_types::{Address, H256, U256};
+use merkle_light::merkle::{next_pow2, MerkleTree};
 use merkle_light::proof::Proof as RawFileProof;
 use merkle_tree::RawLeafSha3Algorithm;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use ssz::{Decode, DecodeError, Encode};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use tracing::debug;
 
@@ -22,6 +26,9 @@ pub type FlowRangeProof = RawRangeProof<H256>;
 // Each chunk is 32 bytes.
 pub const CHUNK_SIZE: usize = 256;
 
+/// Maximum depth of a subtree in the merkle nodes of a transaction, whose entries fit in `u64`.
+pub const MAX_SUBTREE_DEPTH: usize = 64;
+
 pub fn bytes_to_chunks(size_bytes: usize) -> usize {
     if size_bytes % CHUNK_SIZE == 0 {
         size_bytes / CHUNK_SIZE
@@ -30,9 +37,52 @@ pub fn bytes_to_chunks(size_bytes: usize) -> usize {
     }
 }
 
+// The types sent over the network or as RPC byte payloads are encoded in SSZ, where a struct is a
+// container of its fields in the order of declaration. The fields must not be reordered or
+// changed, and a new field requires a new version of the protocols that carry the type.
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Chunk(pub [u8; CHUNK_SIZE]);
 
+/// Encoded as the fixed length bytes of the chunk.
+impl Encode for Chunk {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        CHUNK_SIZE
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        CHUNK_SIZE
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.0);
+    }
+}
+
+impl Decode for Chunk {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        CHUNK_SIZE
+    }
+
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let chunk = bytes
+            .try_into()
+            .map_err(|_| DecodeError::InvalidByteLength {
+                len: bytes.len(),
+                expected: CHUNK_SIZE,
+            })?;
+        Ok(Chunk(chunk))
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, DeriveDecode, DeriveEncode, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Transaction {
@@ -46,6 +96,10 @@ pub struct Transaction {
     pub start_entry_index: u64,
     pub size: u64,
     pub seq: u64,
+    /// The account that submitted the transaction to the flow contract.
+    pub sender: Address,
+    /// Timestamp in seconds of the block that includes the transaction, or 0 if unknown.
+    pub block_timestamp: u64,
 }
 
 impl Transaction {
@@ -54,8 +108,66 @@ impl Transaction {
             .iter()
             .fold(0, |size, &(depth, _)| size + (1 << (depth - 1)))
     }
+
+    /// Checks the shape of a transaction from an untrusted source before it is appended to the
+    /// log flow: the merkle nodes are the subtrees of the file in descending sizes, the first of
+    /// which is aligned in the flow, and they cover the file data without overflowing the flow.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.size > 0, "empty file: seq={}", self.seq);
+        ensure!(
+            !self.merkle_nodes.is_empty(),
+            "no merkle nodes: seq={}",
+            self.seq
+        );
+
+        let mut num_entries = 0u64;
+        let mut last_depth = MAX_SUBTREE_DEPTH + 1;
+        for &(depth, _) in &self.merkle_nodes {
+            ensure!(
+                depth >= 1 && depth < last_depth,
+                "invalid subtree depths: seq={} depths={:?}",
+                self.seq,
+                self.merkle_nodes
+                    .iter()
+                    .map(|(depth, _)| depth)
+                    .collect::<Vec<_>>()
+            );
+            last_depth = depth;
+            // no overflow, since the sizes are distinct powers of 2 below 2^64
+            num_entries += 1 << (depth - 1);
+        }
+
+        let first_subtree_size = 1u64 << (self.merkle_nodes[0].0 - 1);
+        ensure!(
+            self.start_entry_index % first_subtree_size == 0,
+            "unaligned start entry index: seq={} start_entry_index={} first_subtree_size={}",
+            self.seq,
+            self.start_entry_index,
+            first_subtree_size
+        );
+        ensure!(
+            self.start_entry_index.checked_add(num_entries).is_some(),
+            "entry index overflow: seq={} start_entry_index={} num_entries={}",
+            self.seq,
+            self.start_entry_index,
+            num_entries
+        );
+
+        let num_chunks =
+            self.size / CHUNK_SIZE as u64 + (self.size % CHUNK_SIZE as u64 != 0) as u64;
+        ensure!(
+            num_entries >= num_chunks,
+            "merkle nodes do not cover the file: seq={} num_entries={} size={}",
+            self.seq,
+            num_entries,
+            self.size
+        );
+
+        Ok(())
+    }
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, DeriveEncode, DeriveDecode)]
 pub struct ChunkWithProof {
     pub chunk: Chunk,
     pub proof: FlowProof,
@@ -68,6 +180,67 @@ pub struct ChunkArrayWithProof {
     pub proof: FlowRangeProof,
 }
 
+impl ChunkArrayWithProof {
+    /// Validates the chunks against the proof, where the chunks belong to the file that starts at
+    /// `tx_start_index` in the log flow. Returns the root of the proof, which should then be
+    /// checked against a trusted flow root, e.g. with [`ChunkArrayWithProof::verify`].
+    pub fn validate(&self, tx_start_index: u64) -> anyhow::Result<DataRoot> {
+        let start_index = self
+            .chunks
+            .start_index
+            .checked_add(tx_start_index)
+            .ok_or_else(|| anyhow::anyhow!("chunk index overflow"))?;
+        self.proof
+            .validate::<Sha3Algorithm>(&chunk_leaves(&self.chunks.data)?, start_index as usize)?;
+        Ok(self.proof.root())
+    }
+
+    /// Verifies the chunks against the proof and the expected `flow_root`, which does not need a
+    /// store, e.g. to verify the chunks received from a node.
+    pub fn verify(&self, tx_start_index: u64, flow_root: &DataRoot) -> anyhow::Result<()> {
+        let root = self.validate(tx_start_index)?;
+        ensure!(
+            root == *flow_root,
+            "flow root mismatch: proof_root={:?} expected={:?}",
+            root,
+            flow_root
+        );
+        Ok(())
+    }
+}
+
+/// Hashes each chunk of `data` as a merkle leaf of the log flow.
+fn chunk_leaves(data: &[u8]) -> anyhow::Result<Vec<H256>> {
+    if data.is_empty() || data.len() % CHUNK_SIZE != 0 {
+        bail!("invalid data length: {}", data.len());
+    }
+    Ok(data
+        .chunks_exact(CHUNK_SIZE)
+        .map(<Sha3Algorithm as Algorithm<H256>>::leaf)
+        .collect())
+}
+
+/// A data or parity shard of an erasure coded PoRA chunk. The shard itself cannot be proved, so
+/// `proof` is the range proof of the whole PoRA chunk, which is validated once the chunk is
+/// reconstructed from enough shards.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct ErasureShard {
+    /// Index of the PoRA chunk in the log flow.
+    pub chunk_index: u64,
+    pub shard_index: u32,
+    pub data: Vec<u8>,
+    pub proof: FlowRangeProof,
+}
+
+/// Chunks of a file that may not be confirmed on chain yet, e.g. uploaded to a peer before the
+/// transaction is synced. The chunks cannot be proved against a flow root, so they are provisional
+/// until the whole file is staged and verified against `data_root`.
+#[derive(Debug, Clone, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct ProvisionalChunks {
+    pub data_root: DataRoot,
+    pub chunks: ChunkArray,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, DeriveEncode, DeriveDecode)]
 pub struct ChunkArray {
     // The length is exactly a multiple of `CHUNK_SIZE`
@@ -76,20 +249,37 @@ pub struct ChunkArray {
 }
 
 impl ChunkArray {
+    /// Creates a chunk array from untrusted data, which should be non-empty and aligned with
+    /// chunks, and whose end index should not overflow.
+    pub fn try_new(data: Vec<u8>, start_index: u64) -> anyhow::Result<Self> {
+        if data.is_empty() || data.len() % CHUNK_SIZE != 0 {
+            bail!("invalid data length: {}", data.len());
+        }
+        let chunks = ChunkArray { data, start_index };
+        if chunks.end_index().is_none() {
+            bail!("chunk index overflow: start_index={}", start_index);
+        }
+        Ok(chunks)
+    }
+
     pub fn first_chunk(&self) -> Option<Chunk> {
         self.chunk_at(self.start_index as usize)
     }
 
     pub fn last_chunk(&self) -> Option<Chunk> {
-        let last_index =
-            (self.start_index as usize + self.data.len() / CHUNK_SIZE).checked_sub(1)?;
+        let last_index = (self.end_index()? as usize).checked_sub(1)?;
         self.chunk_at(last_index)
     }
 
+    /// Returns the index after the last chunk, or `None` if overflows, e.g. the `start_index`
+    /// received from peers is invalid.
+    pub fn end_index(&self) -> Option<u64> {
+        self.start_index
+            .checked_add((self.data.len() / CHUNK_SIZE) as u64)
+    }
+
     pub fn chunk_at(&self, index: usize) -> Option<Chunk> {
-        if index >= self.data.len() / CHUNK_SIZE + self.start_index as usize
-            || index < self.start_index as usize
-        {
+        if index as u64 >= self.end_index()? || (index as u64) < self.start_index {
             return None;
         }
         let offset = (index - self.start_index as usize) * CHUNK_SIZE;
@@ -101,9 +291,10 @@ impl ChunkArray {
     }
 
     pub fn sub_array(&self, start: u64, end: u64) -> Option<ChunkArray> {
-        if start >= (self.data.len() / CHUNK_SIZE) as u64 + self.start_index
+        let end_index = self.end_index()?;
+        if start >= end_index
             || start < self.start_index
-            || end > (self.data.len() / CHUNK_SIZE) as u64 + self.start_index
+            || end > end_index
             || end <= self.start_index
             || end <= start
         {
@@ -118,6 +309,44 @@ impl ChunkArray {
     }
 }
 
+/// Range proof of file chunks against the merkle root of the log flow, which could be verified by
+/// clients without trusting the node:
+///
+/// 1. Hash each `CHUNK_SIZE` bytes chunk of the data as a merkle leaf with `Sha3Algorithm::leaf`.
+/// 2. Validate `proof` with the leaves at `flow_start_index`.
+/// 3. Check that `flow_root` equals the root of `proof`.
+/// 4. Check that `flow_root` matches a flow root committed to the log contract on chain.
+///
+/// [`FileRangeProof::verify`] performs steps 1 to 3, which only guarantees that the data is in the
+/// flow of `flow_root` at `flow_start_index`. Step 4 is left to the caller, since `flow_root` is
+/// given by the node and not trusted until checked on chain.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRangeProof {
+    /// Index of the first chunk in the log flow, i.e. `tx.start_entry_index` plus the index of the
+    /// chunk in the file.
+    pub flow_start_index: u64,
+    pub flow_root: H256,
+    pub proof: FlowRangeProof,
+}
+
+impl FileRangeProof {
+    /// Verifies that the chunks in `data` are in the flow of `flow_root` at `flow_start_index`,
+    /// without checking `flow_root` on chain.
+    pub fn verify(&self, data: &[u8]) -> anyhow::Result<()> {
+        self.proof
+            .validate::<Sha3Algorithm>(&chunk_leaves(data)?, self.flow_start_index as usize)?;
+        ensure!(
+            self.proof.root() == self.flow_root,
+            "flow root mismatch: proof_root={:?} provided={:?}",
+            self.proof.root(),
+            self.flow_root
+        );
+
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for ChunkArray {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -209,6 +438,29 @@ impl FileProof {
     }
 }
 
+/// This represents the subtree of a chunk or the whole data merkle tree.
+pub type FileMerkleTree = MerkleTree<[u8; 32], RawLeafSha3Algorithm>;
+
+/// This should be called with input checked.
+pub fn sub_merkle_tree(leaf_data: &[u8]) -> anyhow::Result<FileMerkleTree> {
+    Ok(FileMerkleTree::new(
+        data_to_merkle_leaves(leaf_data)?
+            .into_iter()
+            .map(|h| h.0)
+            .collect::<Vec<[u8; 32]>>(),
+    ))
+}
+
+pub fn data_to_merkle_leaves(leaf_data: &[u8]) -> anyhow::Result<Vec<H256>> {
+    if leaf_data.len() % CHUNK_SIZE != 0 {
+        bail!("merkle_tree: unmatch data size");
+    }
+    Ok(leaf_data
+        .par_chunks_exact(CHUNK_SIZE)
+        .map(<Sha3Algorithm as Algorithm<H256>>::leaf)
+        .collect())
+}
+
 impl TryFrom<&FileProof> for RawFileProof<[u8; 32]> {
     type Error = anyhow::Error;
 
@@ -230,3 +482,203 @@ pub fn timestamp_now() -> u32 {
     let timestamp = chrono::Utc::now().timestamp();
     u32::try_from(timestamp).expect("The year is between 1970 and 2106")
 }
+
+/// Sharding of the file data, where a node only stores the data of segments whose index modulo
+/// `num_shard` equals `shard_id`. The default config stores all the data.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, DeriveEncode, DeriveDecode, Deserialize, Serialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ShardConfig {
+    pub shard_id: u64,
+    pub num_shard: u64,
+}
+
+impl Default for ShardConfig {
+    fn default() -> Self {
+        ShardConfig {
+            shard_id: 0,
+            num_shard: 1,
+        }
+    }
+}
+
+impl ShardConfig {
+    pub fn new(shard_id: u64, num_shard: u64) -> anyhow::Result<Self> {
+        let config = ShardConfig {
+            shard_id,
+            num_shard,
+        };
+        ensure!(
+            config.is_valid(),
+            "Invalid shard config: shard_id={} num_shard={}",
+            shard_id,
+            num_shard
+        );
+        Ok(config)
+    }
+
+    /// `num_shard` should be a power of 2, so that shards with different `num_shard` are aligned.
+    pub fn is_valid(&self) -> bool {
+        self.num_shard.is_power_of_two() && self.shard_id < self.num_shard
+    }
+
+    /// Returns whether the two shards store any data in common.
+    pub fn intersect(&self, other: &ShardConfig) -> bool {
+        let num_shard = self.num_shard.min(other.num_shard);
+        self.shard_id % num_shard == other.shard_id % num_shard
+    }
+}
+
+/// The streams of the transactions that a node stores and syncs. All transactions are stored if
+/// no stream is subscribed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamFilter {
+    streams: Vec<U256>,
+}
+
+impl StreamFilter {
+    pub fn new(streams: Vec<U256>) -> Self {
+        StreamFilter { streams }
+    }
+
+    pub fn streams(&self) -> &[U256] {
+        &self.streams
+    }
+
+    /// Returns whether only the transactions of the subscribed streams are stored.
+    pub fn is_enabled(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    pub fn is_subscribed(&self, stream_id: &U256) -> bool {
+        !self.is_enabled() || self.streams.contains(stream_id)
+    }
+
+    /// Returns whether the transaction belongs to any subscribed stream.
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        !self.is_enabled() || tx.stream_ids.iter().any(|id| self.streams.contains(id))
+    }
+}
+
+/// Maximum length in bytes of the file name in [`FileMetadata`].
+pub const MAX_FILE_NAME_LEN: usize = 256;
+/// Maximum length in bytes of the MIME type in [`FileMetadata`].
+pub const MAX_MIME_TYPE_LEN: usize = 128;
+/// Maximum number of tags in [`FileMetadata`].
+pub const MAX_FILE_TAGS: usize = 16;
+/// Maximum length in bytes of each tag in [`FileMetadata`].
+pub const MAX_TAG_LEN: usize = 64;
+
+/// Optional user metadata of an uploaded file, e.g. for gateways to serve the file with the right
+/// content type. It is kept by the node that the file is uploaded to, and not verified on chain.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FileMetadata {
+    pub name: Option<String>,
+    pub mime_type: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl FileMetadata {
+    /// Checks the metadata from an untrusted source against the length limits.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(name) = &self.name {
+            ensure!(
+                !name.is_empty() && name.len() <= MAX_FILE_NAME_LEN,
+                "invalid file name length {}",
+                name.len()
+            );
+        }
+        if let Some(mime_type) = &self.mime_type {
+            ensure!(
+                mime_type.len() <= MAX_MIME_TYPE_LEN,
+                "invalid MIME type length {}",
+                mime_type.len()
+            );
+            match mime_type.split_once('/') {
+                Some((ty, subtype)) if !ty.is_empty() && !subtype.is_empty() => {}
+                _ => bail!("invalid MIME type {:?}", mime_type),
+            }
+        }
+        ensure!(
+            self.tags.len() <= MAX_FILE_TAGS,
+            "too many tags: {}",
+            self.tags.len()
+        );
+        for (i, tag) in self.tags.iter().enumerate() {
+            ensure!(
+                !tag.is_empty() && tag.len() <= MAX_TAG_LEN,
+                "invalid tag length {}",
+                tag.len()
+            );
+            ensure!(!self.tags[..i].contains(tag), "duplicate tag {:?}", tag);
+        }
+
+        Ok(())
+    }
+
+    fn to_ssz(&self) -> FileMetadataSsz {
+        let optional_bytes =
+            |s: &Option<String>| s.as_deref().unwrap_or_default().as_bytes().to_vec();
+        FileMetadataSsz {
+            name: optional_bytes(&self.name),
+            mime_type: optional_bytes(&self.mime_type),
+            tags: self
+                .tags
+                .iter()
+                .map(|tag| tag.as_bytes().to_vec())
+                .collect(),
+        }
+    }
+}
+
+/// The SSZ container of [`FileMetadata`], where an absent string is empty.
+#[derive(DeriveEncode, DeriveDecode)]
+struct FileMetadataSsz {
+    name: Vec<u8>,
+    mime_type: Vec<u8>,
+    tags: Vec<Vec<u8>>,
+}
+
+impl Encode for FileMetadata {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        self.to_ssz().ssz_bytes_len()
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        self.to_ssz().ssz_append(buf)
+    }
+}
+
+impl Decode for FileMetadata {
+    fn is_ssz_fixed_len() -> bool {
+        false
+    }
+
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let ssz = FileMetadataSsz::from_ssz_bytes(bytes)?;
+        let decode_string = |bytes: Vec<u8>| {
+            String::from_utf8(bytes)
+                .map_err(|e| DecodeError::BytesInvalid(format!("invalid UTF-8: {}", e)))
+        };
+        let decode_optional = |bytes: Vec<u8>| match bytes.is_empty() {
+            true => Ok(None),
+            false => decode_string(bytes).map(Some),
+        };
+
+        Ok(FileMetadata {
+            name: decode_optional(ssz.name)?,
+            mime_type: decode_optional(ssz.mime_type)?,
+            tags: ssz
+                .tags
+                .into_iter()
+                .map(decode_string)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
@@ -0,0 +1,263 @@
+//! Types of the RPC interface, which are shared by the node and its clients.
+
+use crate::{DataRoot, FileMetadata, FileProof, Transaction, CHUNK_SIZE};
+use anyhow::{bail, Result};
+use ethereum_types::{Address, H256};
+use merkle_light::hash::Algorithm;
+use merkle_light::merkle::MerkleTree;
+use merkle_tree::{RawLeafSha3Algorithm, LEAF};
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub version: String,
+    pub connected_peers: usize,
+    /// Block number up to which the log entries have been synced, if any.
+    pub log_sync_height: Option<u64>,
+    /// Latest block number of the chain, if retrieved by the log sync.
+    pub chain_head: Option<u64>,
+    pub next_tx_seq: u64,
+    /// Size in bytes of the log flow, including the padding between files.
+    pub log_size: u64,
+    /// Whether the local flow root matches the root committed on-chain, or `None` if not
+    /// verified yet. A divergence means the local data could not be trusted.
+    pub flow_root_consistent: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    pub tx: Transaction,
+    pub finalized: bool,
+    /// User metadata set at upload time, e.g. to serve the file with its MIME type.
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Filters of `ionian_searchFiles`, which all apply if set.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FileQuery {
+    /// Only the files with all the tags, which are case-insensitive.
+    pub tags: Vec<String>,
+    /// Only the files submitted by the account.
+    pub sender: Option<Address>,
+    /// Minimum file size in bytes (included).
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes (included).
+    pub max_size: Option<u64>,
+    /// Minimum upload time in seconds since the Unix epoch (included).
+    pub start_time: Option<u32>,
+    /// Maximum upload time in seconds since the Unix epoch (included).
+    pub end_time: Option<u32>,
+}
+
+/// Identifies a file by either its data root or transaction sequence number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FileId {
+    DataRoot(DataRoot),
+    TxSeq(u64),
+}
+
+/// Upload progress of a file, which could be used to resume an interrupted upload.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    pub data_root: DataRoot,
+    pub size: u64,
+    pub chunks_per_segment: usize,
+    pub num_segments: u32,
+    /// Segment index ranges (end excluded) that have not been uploaded yet.
+    pub missing_segments: Vec<(u32, u32)>,
+}
+
+/// A page of file chunks for a requested index range.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentPage {
+    #[serde(with = "base64")]
+    pub data: Vec<u8>,
+    /// Index of the first chunk in this page.
+    pub start_index: u32,
+    /// Continuation token, i.e. the start index of the next page. `None` if the
+    /// requested range has been fully served.
+    pub next_index: Option<u32>,
+}
+
+/// Consecutive chunks of a file to verify.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkRange {
+    #[serde(with = "base64")]
+    pub data: Vec<u8>,
+    /// Index of the first chunk in the file.
+    pub start_index: u32,
+}
+
+/// Result of the server side proof verification, along with the flow range of the chunks derived
+/// from the transaction of the file, so that clients could also verify the proof by themselves as
+/// a [`crate::FileRangeProof`] at `flow_start_index`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofVerification {
+    /// Whether the proof is valid and its root is a flow root of the node.
+    pub valid: bool,
+    /// Reason of the failed verification.
+    pub error: Option<String>,
+    /// Index of the first chunk in the log flow.
+    pub flow_start_index: u64,
+    /// Index of the chunk after the last one in the log flow.
+    pub flow_end_index: u64,
+    /// Root of the proof, which should match a flow root committed on chain if `valid`.
+    pub flow_root: H256,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentWithProof {
+    /// File merkle root.
+    pub root: DataRoot,
+    #[serde(with = "base64")]
+    /// With fixed data size except the last segment.
+    pub data: Vec<u8>,
+    /// Segment index.
+    pub index: u32,
+    /// File merkle proof whose leaf node is segment root.
+    pub proof: FileProof,
+}
+
+impl SegmentWithProof {
+    /// Splits file into segments and returns the total number of segments and the last segment size.
+    fn split_file_into_segments(
+        &self,
+        file_size: usize,
+        chunks_per_segment: usize,
+    ) -> Result<(u32, usize)> {
+        if file_size == 0 {
+            bail!("file is empty");
+        }
+
+        let segment_size = chunks_per_segment * CHUNK_SIZE;
+        let remaining_size = file_size % segment_size;
+        let mut num_segments = file_size / segment_size;
+
+        if remaining_size == 0 {
+            return Ok((num_segments as u32, segment_size));
+        }
+
+        // Otherwise, the last segment is not full.
+        num_segments += 1;
+
+        let last_chunk_size = remaining_size % CHUNK_SIZE;
+        if last_chunk_size == 0 {
+            Ok((num_segments as u32, remaining_size))
+        } else {
+            // Padding last chunk with zeros.
+            let last_segment_size = remaining_size - last_chunk_size + CHUNK_SIZE;
+            Ok((num_segments as u32, last_segment_size))
+        }
+    }
+
+    fn validate_data_size_and_index(
+        &self,
+        file_size: usize,
+        chunks_per_segment: usize,
+    ) -> Result<u32> {
+        let (num_segments, last_segment_size) =
+            self.split_file_into_segments(file_size, chunks_per_segment)?;
+
+        if self.index >= num_segments {
+            bail!("index out of bound");
+        }
+
+        let data_size = if self.index == num_segments - 1 {
+            last_segment_size
+        } else {
+            chunks_per_segment * CHUNK_SIZE
+        };
+
+        if self.data.len() != data_size {
+            bail!("invalid data length");
+        }
+
+        Ok(num_segments)
+    }
+
+    fn calculate_segment_merkle_root(&self) -> [u8; 32] {
+        let mut a = RawLeafSha3Algorithm::default();
+        let hashes = self.data.chunks_exact(CHUNK_SIZE).map(|x| {
+            a.reset();
+            a.write(&[LEAF]);
+            a.write(x);
+            a.hash()
+        });
+        MerkleTree::<_, RawLeafSha3Algorithm>::new(hashes).root()
+    }
+
+    fn validate_proof(&self, num_segments: usize) -> Result<()> {
+        // Validate proof data format at first.
+        if self.proof.path.is_empty() {
+            if self.proof.lemma.len() != 1 {
+                bail!("invalid proof");
+            }
+        } else if self.proof.lemma.len() != self.proof.path.len() + 2 {
+            bail!("invalid proof");
+        }
+
+        // Calculate segment merkle root to verify proof.
+        let segment_root = self.calculate_segment_merkle_root();
+        if !self
+            .proof
+            .validate(&segment_root, &self.root, self.index as usize, num_segments)?
+        {
+            bail!("proof validation failed");
+        }
+
+        Ok(())
+    }
+
+    /// Validates the segment data size and proof.
+    pub fn validate(&self, file_size: usize, chunks_per_segment: usize) -> Result<()> {
+        let num_segments = self.validate_data_size_and_index(file_size, chunks_per_segment)?;
+        self.validate_proof(num_segments as usize)?;
+        Ok(())
+    }
+
+    /// Returns the index of first chunk in the segment.
+    pub fn chunk_index(&self, chunks_per_segment: usize) -> usize {
+        self.index as usize * chunks_per_segment
+    }
+}
+
+/// Serializes the bytes as a base64 string.
+pub mod base64 {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error> {
+        let base64 = base64::encode(v);
+        String::serialize(&base64, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let base64 = String::deserialize(d)?;
+        base64::decode(base64.as_bytes()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileId;
+    use crate::DataRoot;
+
+    #[test]
+    fn test_file_id_serde() {
+        let id: FileId = serde_json::from_str("7").unwrap();
+        assert_eq!(id, FileId::TxSeq(7));
+
+        let root = DataRoot::repeat_byte(1);
+        let id: FileId = serde_json::from_str(&serde_json::to_string(&root).unwrap()).unwrap();
+        assert_eq!(id, FileId::DataRoot(root));
+    }
+}
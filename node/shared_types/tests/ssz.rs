@@ -0,0 +1,162 @@
+//! The SSZ encodings of the wire types, which are pinned so that an incompatible change of the
+//! types is caught before it breaks the network protocols.
+
+use append_merkle::{Algorithm, Proof, RangeProof, Sha3Algorithm};
+use ethereum_types::{Address, H256, U256};
+use shared_types::{
+    Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof, FileMetadata, FileProof,
+    FileRangeProof, Transaction, CHUNK_SIZE,
+};
+use ssz::{Decode, Encode};
+use std::fmt::Debug;
+
+fn h256(byte: u8) -> H256 {
+    H256::repeat_byte(byte)
+}
+
+fn proof() -> Proof<H256> {
+    Proof::new(vec![h256(1), h256(2), h256(3)], vec![true])
+}
+
+fn range_proof() -> RangeProof<H256> {
+    RangeProof {
+        left_proof: proof(),
+        right_proof: proof(),
+    }
+}
+
+fn transaction() -> Transaction {
+    Transaction {
+        stream_ids: vec![U256::from(7)],
+        data: vec![0xaa, 0xbb],
+        data_merkle_root: h256(4),
+        merkle_nodes: vec![(2, h256(5))],
+        start_entry_index: 16,
+        size: 512,
+        seq: 3,
+        sender: Address::repeat_byte(6),
+        block_timestamp: 9,
+    }
+}
+
+fn chunk_array() -> ChunkArray {
+    ChunkArray {
+        data: vec![9; 2 * CHUNK_SIZE],
+        start_index: 4,
+    }
+}
+
+fn assert_round_trip<T: Encode + Decode + PartialEq + Debug>(value: &T) -> Vec<u8> {
+    let bytes = value.as_ssz_bytes();
+    assert_eq!(bytes.len(), value.ssz_bytes_len());
+    assert_eq!(&T::from_ssz_bytes(&bytes).unwrap(), value);
+    bytes
+}
+
+/// Digest of the long encodings to pin.
+fn sha3(bytes: &[u8]) -> String {
+    hex::encode(<Sha3Algorithm as Algorithm<H256>>::leaf(bytes))
+}
+
+#[test]
+fn test_chunk() {
+    let chunk = Chunk([8; CHUNK_SIZE]);
+    let bytes = assert_round_trip(&chunk);
+    assert_eq!(bytes, vec![8; CHUNK_SIZE]);
+
+    assert!(Chunk::from_ssz_bytes(&[8; CHUNK_SIZE - 1]).is_err());
+}
+
+#[test]
+fn test_transaction() {
+    let bytes = assert_round_trip(&transaction());
+    assert_eq!(
+        hex::encode(&bytes),
+        "60000000800000000404040404040404040404040404040404040404040404040404040404040404\
+         82000000100000000000000000020000000000000300000000000000060606060606060606060606\
+         06060606060606060900000000000000070000000000000000000000000000000000000000000000\
+         0000000000000000aabb020000000000000005050505050505050505050505050505050505050505\
+         05050505050505050505"
+    );
+}
+
+#[test]
+fn test_chunk_array() {
+    let bytes = assert_round_trip(&chunk_array());
+    // offset of the data, start_index, and then the data
+    assert_eq!(&bytes[..12], &[12, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(&bytes[12..], &[9; 2 * CHUNK_SIZE]);
+}
+
+#[test]
+fn test_proofs() {
+    let bytes = assert_round_trip(&proof());
+    // offsets of the lemma and path, the lemma, and then the path
+    let expected = "08000000680000000101010101010101010101010101010101010101010101010101010101010101\
+                    02020202020202020202020202020202020202020202020202020202020202020303030303030303\
+                    03030303030303030303030303030303030303030303030301";
+    assert_eq!(hex::encode(&bytes), expected);
+
+    let bytes = assert_round_trip(&FileProof {
+        lemma: vec![h256(1), h256(2), h256(3)],
+        path: vec![true],
+    });
+    // the same layout as the flow proof
+    assert_eq!(hex::encode(&bytes), expected);
+
+    let bytes = assert_round_trip(&FileRangeProof {
+        flow_start_index: 16,
+        flow_root: h256(3),
+        proof: range_proof(),
+    });
+    assert_eq!(
+        sha3(&bytes),
+        "8fff81070dd332b1e278074d00149f01978c767a7c3247b4aed0f8261168d0bf"
+    );
+}
+
+#[test]
+fn test_chunks_with_proof() {
+    let bytes = assert_round_trip(&ChunkWithProof {
+        chunk: Chunk([8; CHUNK_SIZE]),
+        proof: proof(),
+    });
+    assert_eq!(
+        sha3(&bytes),
+        "58130f8e02707f363a44eab87f42f7f004134c6fa686737cf89e98dac7bbfaca"
+    );
+
+    let bytes = assert_round_trip(&ChunkArrayWithProof {
+        chunks: chunk_array(),
+        proof: range_proof(),
+    });
+    assert_eq!(
+        sha3(&bytes),
+        "d4a6be270dea663b6c417b184dfef35e8affce533dd445a724807a5eaef3ea2d"
+    );
+}
+
+#[test]
+fn test_file_metadata() {
+    let metadata = FileMetadata {
+        name: None,
+        mime_type: Some("a/b".into()),
+        tags: vec!["x".into(), "yz".into()],
+    };
+    let bytes = assert_round_trip(&metadata);
+    // offsets of the fields, "a/b", and then the offsets and bytes of the tags
+    assert_eq!(
+        hex::encode(&bytes),
+        "0c0000000c0000000f000000612f62080000000900000078797a"
+    );
+
+    assert_round_trip(&FileMetadata::default());
+    // invalid UTF-8 name
+    let mut bytes = FileMetadata {
+        name: Some("n".into()),
+        ..Default::default()
+    }
+    .as_ssz_bytes();
+    bytes[12] = 0xff;
+    assert!(FileMetadata::from_ssz_bytes(&bytes).is_err());
+}
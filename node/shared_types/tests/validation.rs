@@ -0,0 +1,81 @@
+use ethereum_types::H256;
+use shared_types::{
+    ChunkArray, FileMetadata, Transaction, CHUNK_SIZE, MAX_FILE_NAME_LEN, MAX_FILE_TAGS,
+};
+
+fn tx(start_entry_index: u64, depths: &[usize], size: u64) -> Transaction {
+    Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: H256::zero(),
+        merkle_nodes: depths.iter().map(|depth| (*depth, H256::zero())).collect(),
+        start_entry_index,
+        size,
+        seq: 0,
+        sender: Default::default(),
+        block_timestamp: 0,
+    }
+}
+
+#[test]
+fn test_validate_transaction() {
+    // 7 chunks in subtrees of 4, 2 and 1 chunks
+    let size = 7 * CHUNK_SIZE as u64;
+    assert!(tx(8, &[3, 2, 1], size).validate().is_ok());
+    assert!(tx(8, &[3, 2, 1], size - 1).validate().is_ok());
+
+    assert!(tx(8, &[], size).validate().is_err());
+    assert!(tx(8, &[3, 2, 1], 0).validate().is_err());
+    // not covered
+    assert!(tx(8, &[3, 2, 1], size + 1).validate().is_err());
+    // invalid depths
+    assert!(tx(8, &[3, 2, 0], size).validate().is_err());
+    assert!(tx(8, &[2, 3, 1], size).validate().is_err());
+    assert!(tx(8, &[3, 3], size).validate().is_err());
+    assert!(tx(0, &[65], size).validate().is_err());
+    // unaligned
+    assert!(tx(6, &[3, 2, 1], size).validate().is_err());
+    // overflow
+    assert!(tx(1 << 63, &[64], size).validate().is_err());
+    assert!(tx(0, &[64], size).validate().is_ok());
+}
+
+#[test]
+fn test_chunk_array_try_new() {
+    assert!(ChunkArray::try_new(vec![0; 2 * CHUNK_SIZE], 4).is_ok());
+
+    assert!(ChunkArray::try_new(vec![], 4).is_err());
+    assert!(ChunkArray::try_new(vec![0; CHUNK_SIZE + 1], 4).is_err());
+    assert!(ChunkArray::try_new(vec![0; 2 * CHUNK_SIZE], u64::MAX).is_err());
+}
+
+#[test]
+fn test_validate_file_metadata() {
+    let metadata = |name: &str, mime_type: &str, tags: &[&str]| FileMetadata {
+        name: Some(name.into()),
+        mime_type: Some(mime_type.into()),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+    };
+    assert!(FileMetadata::default().validate().is_ok());
+    assert!(metadata("a.txt", "text/plain", &["a", "b"])
+        .validate()
+        .is_ok());
+
+    assert!(metadata("", "text/plain", &[]).validate().is_err());
+    assert!(
+        metadata(&"a".repeat(MAX_FILE_NAME_LEN + 1), "text/plain", &[])
+            .validate()
+            .is_err()
+    );
+    assert!(metadata("a.txt", "text", &[]).validate().is_err());
+    assert!(metadata("a.txt", "text/", &[]).validate().is_err());
+    assert!(metadata("a.txt", "text/plain", &["a", ""])
+        .validate()
+        .is_err());
+    assert!(metadata("a.txt", "text/plain", &["a", "a"])
+        .validate()
+        .is_err());
+    let tags: Vec<String> = (0..=MAX_FILE_TAGS).map(|i| i.to_string()).collect();
+    let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+    assert!(metadata("a.txt", "text/plain", &tags).validate().is_err());
+}
@@ -0,0 +1,215 @@
+//! Offline commands on the store, which open the database directly without starting the node.
+//! Except for compaction and import, the commands only read the store.
+
+use crate::IonianConfig;
+use clap::ArgMatches;
+use ethereum_types::H256;
+use std::cmp;
+use std::path::Path;
+use storage::log_store::log_manager::{
+    bytes_to_entries, data_to_merkle_leaves, FileMerkleTree, LogConfig,
+};
+use storage::log_store::{
+    local_file, FlowConfig, LogStoreChunkRead, LogStoreRead, LogStoreWrite, PendingConfig,
+};
+use storage::LogManager;
+
+/// Number of entries to read at a time when verifying a file, which is 1MB.
+const VERIFY_BATCH_ENTRIES: usize = 4096;
+
+pub fn run(matches: &ArgMatches, config: &IonianConfig) -> Result<(), String> {
+    let storage_config = config.storage_config()?;
+
+    if let Some(("compact", _)) = matches.subcommand() {
+        LogManager::compact_rocksdb(&storage_config.db_dir)
+            .map_err(|e| format!("Unable to compact database: {:?}", e))?;
+        println!("Database compacted");
+        return Ok(());
+    }
+
+    let log_config = LogConfig {
+        flow: FlowConfig {
+            batch_size: storage_config.entry_batch_size,
+            seal_chunks: storage_config.seal_chunks,
+            sealed_file: storage_config.sealed_file,
+            verify_checksum: storage_config.verify_checksum,
+            allow_overwrite: storage_config.allow_overwrite,
+            erasure: storage_config.erasure,
+            ..Default::default()
+        },
+        proof_threads: storage_config.proof_threads,
+        pending: PendingConfig {
+            max_staged_bytes: storage_config.max_staged_bytes,
+            expiration_secs: storage_config.staged_expiration_secs,
+        },
+    };
+    let mut store = LogManager::rocksdb(log_config, &storage_config.db_dir)
+        .map_err(|e| format!("Unable to open store, is the node running? {:?}", e))?;
+
+    match matches.subcommand() {
+        Some(("inspect-tx", matches)) => inspect_tx(&store, parse_arg(matches, "TX_SEQ")?),
+        Some(("list-txs", matches)) => list_txs(
+            &store,
+            parse_arg(matches, "start")?,
+            parse_arg(matches, "limit")?,
+        ),
+        Some(("verify", matches)) => verify(&store, parse_arg(matches, "TX_SEQ")?),
+        Some(("export", matches)) => export(
+            &store,
+            parse_data_root(matches)?,
+            matches.value_of("PATH").unwrap(),
+        ),
+        Some(("import", matches)) => import(
+            &mut store,
+            parse_arg(matches, "TX_SEQ")?,
+            matches.value_of("PATH").unwrap(),
+        ),
+        Some(("stats", _)) => stats(&store, &storage_config.db_dir),
+        _ => Err(anyhow::anyhow!("Unknown db command")),
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn parse_arg(matches: &ArgMatches, name: &str) -> Result<u64, String> {
+    matches
+        .value_of(name)
+        .ok_or_else(|| format!("Missing {}", name))?
+        .parse()
+        .map_err(|e| format!("Invalid {}: {:?}", name, e))
+}
+
+fn parse_data_root(matches: &ArgMatches) -> Result<H256, String> {
+    let data_root = matches.value_of("DATA_ROOT").ok_or("Missing DATA_ROOT")?;
+    data_root
+        .trim_start_matches("0x")
+        .parse()
+        .map_err(|e| format!("Invalid DATA_ROOT: {:?}", e))
+}
+
+fn inspect_tx(store: &dyn LogStoreRead, tx_seq: u64) -> anyhow::Result<()> {
+    let tx = store
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow::anyhow!("Transaction {} not found", tx_seq))?;
+
+    println!("{}", serde_json::to_string_pretty(&tx)?);
+    println!("entries: {}", bytes_to_entries(tx.size));
+    println!("finalized: {}", store.check_tx_completed(tx_seq)?);
+    println!(
+        "available ranges: {:?}",
+        store.get_available_ranges(tx_seq)?
+    );
+
+    Ok(())
+}
+
+fn list_txs(store: &dyn LogStoreRead, start: u64, limit: u64) -> anyhow::Result<()> {
+    let end = cmp::min(store.next_tx_seq()?, start.saturating_add(limit));
+
+    for tx_seq in start..end {
+        match store.get_tx_by_seq_number(tx_seq)? {
+            Some(tx) => println!(
+                "{}\t{:?}\tsize={}\tstart_entry_index={}\tfinalized={}",
+                tx_seq,
+                tx.data_merkle_root,
+                tx.size,
+                tx.start_entry_index,
+                store.check_tx_completed(tx_seq)?
+            ),
+            None => println!("{}\tmissing", tx_seq),
+        }
+    }
+
+    Ok(())
+}
+
+/// Recomputes the data root from the entries in the store, in the same way as the client
+/// verifies the downloaded files.
+fn verify(store: &dyn LogStoreRead, tx_seq: u64) -> anyhow::Result<()> {
+    let tx = store
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow::anyhow!("Transaction {} not found", tx_seq))?;
+    let num_entries = bytes_to_entries(tx.size) as usize;
+
+    let mut leaves = Vec::with_capacity(num_entries);
+    for start in (0..num_entries).step_by(VERIFY_BATCH_ENTRIES) {
+        let end = cmp::min(start + VERIFY_BATCH_ENTRIES, num_entries);
+        let chunks = store
+            .get_chunks_by_tx_and_index_range(tx_seq, start, end)?
+            .ok_or_else(|| anyhow::anyhow!("Entries [{}, {}) missing", start, end))?;
+        leaves.extend(
+            data_to_merkle_leaves(&chunks.data)?
+                .into_iter()
+                .map(|h| h.0),
+        );
+    }
+
+    let root = FileMerkleTree::new(leaves).root();
+    if root != tx.data_merkle_root.0 {
+        anyhow::bail!(
+            "Data root mismatch, expected {:?}, computed {:?}",
+            tx.data_merkle_root,
+            ethereum_types::H256(root)
+        );
+    }
+
+    println!("Transaction {} verified, {} entries", tx_seq, num_entries);
+    Ok(())
+}
+
+fn export(store: &dyn LogStoreRead, data_root: H256, path: &str) -> anyhow::Result<()> {
+    let tx_seq = store
+        .get_tx_seq_by_data_root(&data_root)?
+        .ok_or_else(|| anyhow::anyhow!("File {:?} not found", data_root))?;
+
+    local_file::export_file(store, tx_seq, Path::new(path))?;
+
+    println!("Transaction {} exported to {}", tx_seq, path);
+    Ok(())
+}
+
+fn import(store: &mut LogManager, tx_seq: u64, path: &str) -> anyhow::Result<()> {
+    if store.check_tx_completed(tx_seq)? {
+        anyhow::bail!("Transaction {} already finalized", tx_seq);
+    }
+
+    local_file::import_file(store, tx_seq, Path::new(path))?;
+    store.finalize_tx(tx_seq)?;
+
+    println!("Transaction {} imported from {}", tx_seq, path);
+    Ok(())
+}
+
+fn stats(store: &dyn LogStoreRead, db_dir: &Path) -> anyhow::Result<()> {
+    let next_tx_seq = store.next_tx_seq()?;
+    let mut finalized = 0;
+    for tx_seq in 0..next_tx_seq {
+        if store.check_tx_completed(tx_seq)? {
+            finalized += 1;
+        }
+    }
+    let (root, flow_length) = store.get_context()?;
+
+    println!("transactions: {}", next_tx_seq);
+    println!("finalized transactions: {}", finalized);
+    println!(
+        "files in sync: {}",
+        store.get_all_file_sync_progress()?.len()
+    );
+    println!("flow length: {}", flow_length);
+    println!("flow root: {:?}", root);
+    println!("log sync progress: {:?}", store.get_sync_progress()?);
+    println!("database size: {} bytes", dir_size(db_dir)?);
+
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
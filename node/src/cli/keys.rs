@@ -0,0 +1,77 @@
+//! Commands to manage the keys in the keystore directory, which is configured by `keystore_dir`
+//! and `keystore_password`.
+
+use crate::keystore::{KeyKind, Keystore};
+use crate::IonianConfig;
+use clap::ArgMatches;
+use ethereum_types::H256;
+use network::PeerId;
+
+pub fn run(matches: &ArgMatches, config: &IonianConfig) -> Result<(), String> {
+    let keystore = config
+        .keystore()?
+        .ok_or("keystore_dir and keystore_password are required")?;
+
+    match matches.subcommand() {
+        Some(("create", matches)) => {
+            let kind = parse_kind(matches)?;
+            keystore.create(kind, matches.is_present("force"))?;
+            println!("Key {} created in {:?}", kind, keystore.path(kind));
+        }
+        Some(("import", matches)) => {
+            let kind = parse_kind(matches)?;
+            let secret = read_secret(matches.value_of("FILE").unwrap())?;
+            keystore.import(kind, secret.as_bytes(), matches.is_present("force"))?;
+            println!("Key {} imported to {:?}", kind, keystore.path(kind));
+        }
+        Some(("export", matches)) => {
+            println!("{:?}", keystore.export(parse_kind(matches)?)?);
+        }
+        Some(("list", _)) => list(&keystore)?,
+        _ => return Err("Unknown keys command".into()),
+    }
+
+    Ok(())
+}
+
+fn parse_kind(matches: &ArgMatches) -> Result<KeyKind, String> {
+    matches.value_of("KIND").ok_or("Missing KIND")?.parse()
+}
+
+/// Reads the hex encoded private key, or the raw bytes such as the legacy network key file.
+fn read_secret(path: &str) -> Result<H256, String> {
+    let content =
+        std::fs::read(path).map_err(|e| format!("Unable to read key file {}: {:?}", path, e))?;
+
+    if let Ok(secret) = std::str::from_utf8(&content)
+        .map_err(|_| ())
+        .and_then(|s| s.trim().trim_start_matches("0x").parse().map_err(|_| ()))
+    {
+        return Ok(secret);
+    }
+
+    if content.len() == H256::len_bytes() {
+        return Ok(H256::from_slice(&content));
+    }
+
+    Err(format!("Unable to parse private key in {}", path))
+}
+
+fn list(keystore: &Keystore) -> Result<(), String> {
+    for kind in KeyKind::ALL {
+        if !keystore.contains(kind) {
+            println!("{}\tmissing", kind);
+            continue;
+        }
+
+        match kind {
+            KeyKind::Network => {
+                let peer_id = PeerId::from(keystore.network_keypair()?.public());
+                println!("{}\t{:?}\tpeer_id={}", kind, keystore.path(kind), peer_id);
+            }
+            KeyKind::Miner => println!("{}\t{:?}", kind, keystore.path(kind)),
+        }
+    }
+
+    Ok(())
+}
@@ -1,7 +1,89 @@
+mod db;
+mod keys;
+
 use clap::{arg, command, Command};
 
+pub use db::run as run_db_command;
+pub use keys::run as run_keys_command;
+
 pub fn cli_app<'a>() -> Command<'a> {
     command!()
-        .arg(arg!(-c --config <FILE> "Sets a custom config file"))
+        .arg(
+            arg!(-c --config <FILE> "Sets a custom config file, later files override earlier ones")
+                .multiple_occurrences(true),
+        )
         .allow_external_subcommands(true)
+        .subcommand(
+            Command::new("config")
+                .about("Manages the node configuration")
+                .subcommand_required(true)
+                .subcommand(Command::new("validate").about(
+                    "Validates the configuration and prints the resolved effective configuration",
+                )),
+        )
+        .subcommand(
+            Command::new("db")
+                .about("Inspects or maintains the store offline, which requires the node to be stopped")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("inspect-tx")
+                        .about("Prints a transaction and the status of its data")
+                        .arg(arg!(<TX_SEQ> "Sequence number of the transaction")),
+                )
+                .subcommand(
+                    Command::new("list-txs")
+                        .about("Lists the transactions in the order of sequence numbers")
+                        .arg(
+                            arg!(--start <TX_SEQ> "The first transaction to list")
+                                .default_value("0"),
+                        )
+                        .arg(
+                            arg!(--limit <NUM> "Maximum number of transactions to list")
+                                .default_value("100"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("verify")
+                        .about("Verifies the data of a transaction against its data root")
+                        .arg(arg!(<TX_SEQ> "Sequence number of the transaction")),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Writes a finalized file to a local path")
+                        .arg(arg!(<DATA_ROOT> "Data root of the file"))
+                        .arg(arg!(<PATH> "Path to write the file to")),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Writes a local file to the store as the data of a known transaction, once verified against its data root")
+                        .arg(arg!(<TX_SEQ> "Sequence number of the transaction"))
+                        .arg(arg!(<PATH> "Path of the file")),
+                )
+                .subcommand(Command::new("stats").about("Prints the statistics of the store"))
+                .subcommand(Command::new("compact").about("Compacts the database")),
+        )
+        .subcommand(
+            Command::new("keys")
+                .about("Manages the encrypted keys in the keystore directory")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Generates a new key")
+                        .arg(arg!(<KIND> "The key to generate, network or miner"))
+                        .arg(arg!(--force "Overwrites the existing key")),
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Encrypts a private key into the keystore")
+                        .arg(arg!(<KIND> "The key to import, network or miner"))
+                        .arg(arg!(<FILE> "File of the hex encoded or raw private key"))
+                        .arg(arg!(--force "Overwrites the existing key")),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("Prints the decrypted private key in hex")
+                        .arg(arg!(<KIND> "The key to export, network or miner")),
+                )
+                .subcommand(Command::new("list").about("Lists the keys in the keystore")),
+        )
 }
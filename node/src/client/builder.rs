@@ -1,20 +1,26 @@
-use super::{Client, RuntimeContext};
+use super::{Client, RuntimeContext, ShutdownCoordinator, ShutdownPhase};
 use chunk_pool::Config as ChunkPoolConfig;
-use file_location_cache::FileLocationCache;
+use file_location_cache::{Config as FileLocationCacheConfig, FileLocationCache};
+use kv::{KvConfig, KvService, KvStore};
 use log_entry_sync::{LogSyncConfig, LogSyncManager};
-use miner::{MinerMessage, MinerService};
+use miner::{Attester, AttesterConfig, MinerConfig, MinerMessage, MinerService};
 use network::{
     self, Keypair, NetworkConfig, NetworkGlobals, NetworkMessage, RequestId,
     Service as LibP2PService,
 };
 use router::RouterService;
-use rpc::RPCConfig;
+use rpc::{LogFilterControl, RPCConfig};
 use std::sync::Arc;
+use std::time::Duration;
 use storage::log_store::log_manager::LogConfig;
-use storage::log_store::Store;
+use storage::log_store::{FlowConfig, PendingConfig, Store};
 use storage::{LogManager, StorageConfig};
-use sync::{SyncSender, SyncService};
-use tokio::sync::{mpsc, RwLock};
+use storage_async::{DiskWatchdog, DiskWatchdogConfig, Finalizer, IoOrigin};
+use sync::{Config as SyncConfig, SyncSender, SyncService};
+use tokio::sync::{mpsc, watch, RwLock};
+
+/// Maximum time to wait for the peers to disconnect upon shutdown.
+const DISCONNECT_PEERS_TIMEOUT: Duration = Duration::from_secs(3);
 
 macro_rules! require {
     ($component:expr, $self:ident, $e:ident) => {
@@ -45,6 +51,11 @@ struct MinerComponents {
     send: mpsc::UnboundedSender<MinerMessage>,
 }
 
+struct LogSyncComponents {
+    chain_head: watch::Receiver<Option<u64>>,
+    flow_root_status: watch::Receiver<Option<bool>>,
+}
+
 /// Builds a `Client` instance.
 ///
 /// ## Notes
@@ -59,6 +70,10 @@ pub struct ClientBuilder {
     network: Option<NetworkComponents>,
     sync: Option<SyncComponents>,
     miner: Option<MinerComponents>,
+    log_sync: Option<LogSyncComponents>,
+    kv_store: Option<Arc<KvStore>>,
+    shutdown: ShutdownCoordinator,
+    log_filter: Option<Arc<dyn LogFilterControl>>,
 }
 
 impl ClientBuilder {
@@ -72,6 +87,10 @@ impl ClientBuilder {
             network: None,
             sync: None,
             miner: None,
+            log_sync: None,
+            kv_store: None,
+            shutdown: Default::default(),
+            log_filter: None,
         }
     }
 
@@ -81,6 +100,12 @@ impl ClientBuilder {
         self
     }
 
+    /// Allows the admin RPC to change the log filter at runtime.
+    pub fn with_log_filter(mut self, log_filter: Arc<dyn LogFilterControl>) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
     /// Initializes in-memory storage.
     pub fn with_memory_store(mut self) -> Result<Self, String> {
         // TODO(zz): Set config.
@@ -100,8 +125,24 @@ impl ClientBuilder {
 
     /// Initializes RocksDB storage.
     pub fn with_rocksdb_store(mut self, config: &StorageConfig) -> Result<Self, String> {
+        let log_config = LogConfig {
+            flow: FlowConfig {
+                batch_size: config.entry_batch_size,
+                seal_chunks: config.seal_chunks,
+                sealed_file: config.sealed_file.clone(),
+                verify_checksum: config.verify_checksum,
+                allow_overwrite: config.allow_overwrite,
+                erasure: config.erasure,
+                ..Default::default()
+            },
+            proof_threads: config.proof_threads,
+            pending: PendingConfig {
+                max_staged_bytes: config.max_staged_bytes,
+                expiration_secs: config.staged_expiration_secs,
+            },
+        };
         let store = Arc::new(RwLock::new(
-            LogManager::rocksdb(LogConfig::default(), &config.db_dir)
+            LogManager::rocksdb(log_config, &config.db_dir)
                 .map_err(|e| format!("Unable to start RocksDB store: {:?}", e))?,
         ));
 
@@ -114,18 +155,48 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    pub fn with_file_location_cache(mut self) -> Self {
-        let file_location_cache = Default::default();
+    /// Protects the store from writes when the disk space is low, if configured.
+    pub fn with_disk_watchdog(self, config: Option<DiskWatchdogConfig>) -> Result<Self, String> {
+        let executor = require!("disk_watchdog", self, runtime_context)
+            .clone()
+            .executor;
+        let async_store = require!("disk_watchdog", self, async_store).clone();
+
+        if let Some(config) = config {
+            DiskWatchdog::spawn(executor, config, async_store);
+        }
+
+        Ok(self)
+    }
+
+    /// Finalizes the files in background once all their chunks are written.
+    pub fn with_finalizer(self) -> Result<Self, String> {
+        let executor = require!("finalizer", self, runtime_context)
+            .clone()
+            .executor;
+        let async_store = require!("finalizer", self, async_store).clone();
+
+        Finalizer::spawn(executor, async_store);
+
+        Ok(self)
+    }
+
+    pub fn with_file_location_cache(mut self, config: FileLocationCacheConfig) -> Self {
+        let file_location_cache = FileLocationCache::new(config);
         self.file_location_cache = Some(Arc::new(file_location_cache));
         self
     }
 
     /// Starts the networking stack.
-    pub async fn with_network(mut self, config: &NetworkConfig) -> Result<Self, String> {
+    pub async fn with_network(
+        mut self,
+        config: &NetworkConfig,
+        keypair: Option<Keypair>,
+    ) -> Result<Self, String> {
         let executor = require!("network", self, runtime_context).clone().executor;
 
         // construct the libp2p service context
-        let service_context = network::Context { config };
+        let service_context = network::Context { config, keypair };
 
         // launch libp2p service
         let (globals, keypair, libp2p) = LibP2PService::new(executor, service_context)
@@ -145,30 +216,60 @@ impl ClientBuilder {
         Ok(self)
     }
 
-    pub fn with_sync(mut self) -> Result<Self, String> {
+    pub fn with_sync(mut self, config: SyncConfig) -> Result<Self, String> {
         let executor = require!("sync", self, runtime_context).clone().executor;
-        let store = require!("sync", self, store).clone();
+        let async_store = require!("sync", self, async_store).with_origin(IoOrigin::Sync);
         let file_location_cache = require!("sync", self, file_location_cache).clone();
         let network_send = require!("sync", self, network).send.clone();
 
-        let send = SyncService::spawn(executor, network_send, store, file_location_cache);
+        let send = SyncService::spawn_with_config(
+            config,
+            executor,
+            network_send,
+            async_store,
+            file_location_cache,
+        );
         self.sync = Some(SyncComponents { send });
 
         Ok(self)
     }
 
-    pub fn with_miner(mut self) -> Result<Self, String> {
+    pub async fn with_miner(mut self, config: Option<MinerConfig>) -> Result<Self, String> {
         let executor = require!("miner", self, runtime_context).clone().executor;
         let network_send = require!("miner", self, network).send.clone();
+        let async_store = require!("miner", self, async_store).with_origin(IoOrigin::Miner);
 
-        let send = MinerService::spawn(executor, network_send);
+        let send = MinerService::spawn(executor, network_send, config, async_store).await?;
         self.miner = Some(MinerComponents { send });
 
         Ok(self)
     }
 
+    pub async fn with_attester(self, config: Option<AttesterConfig>) -> Result<Self, String> {
+        let executor = require!("attester", self, runtime_context).clone().executor;
+        let async_store = require!("attester", self, async_store).with_origin(IoOrigin::Miner);
+
+        if let Some(config) = config {
+            Attester::spawn(executor, config, async_store).await?;
+        }
+
+        Ok(self)
+    }
+
+    /// Starts to apply the transactions of the KV streams, if configured.
+    pub fn with_kv(mut self, config: Option<KvConfig>) -> Result<Self, String> {
+        let executor = require!("kv", self, runtime_context).clone().executor;
+        let async_store = require!("kv", self, async_store).clone();
+
+        if let Some(config) = config {
+            self.kv_store = Some(KvService::spawn(executor, config, async_store)?);
+        }
+
+        Ok(self)
+    }
+
     /// Starts the networking stack.
-    pub fn with_router(mut self) -> Result<Self, String> {
+    pub fn with_router(mut self, config: &NetworkConfig) -> Result<Self, String> {
         let executor = require!("router", self, runtime_context).clone().executor;
         let sync_send = require!("router", self, sync).send.clone(); // note: we can make this optional in the future
         let miner_send = require!("router", self, miner).send.clone(); // note: we can make this optional in the future
@@ -193,13 +294,14 @@ impl ClientBuilder {
             store,
             file_location_cache,
             network.keypair.clone(),
+            config,
         );
 
         Ok(self)
     }
 
     pub async fn with_rpc(
-        self,
+        mut self,
         rpc_config: RPCConfig,
         chunk_pool_config: ChunkPoolConfig,
     ) -> Result<Self, String> {
@@ -208,7 +310,7 @@ impl ClientBuilder {
         }
 
         let executor = require!("rpc", self, runtime_context).clone().executor;
-        let async_store = require!("rpc", self, async_store).clone();
+        let async_store = require!("rpc", self, async_store).with_origin(IoOrigin::Rpc);
         let network_send = require!("rpc", self, network).send.clone();
 
         let (chunk_pool, chunk_pool_handler) =
@@ -219,37 +321,124 @@ impl ClientBuilder {
             network_globals: self.network.as_ref().map(|network| network.globals.clone()),
             network_send: self.network.as_ref().map(|network| network.send.clone()),
             sync_send: self.sync.as_ref().map(|sync| sync.send.clone()),
+            miner_send: self.miner.as_ref().map(|miner| miner.send.clone()),
+            chain_head: self
+                .log_sync
+                .as_ref()
+                .map(|log_sync| log_sync.chain_head.clone()),
+            flow_root_status: self
+                .log_sync
+                .as_ref()
+                .map(|log_sync| log_sync.flow_root_status.clone()),
             log_store: async_store,
+            kv_store: self.kv_store.clone(),
+            file_location_cache: self.file_location_cache.clone(),
+            log_filter: self.log_filter.clone(),
             chunk_pool,
             shutdown_sender: executor.shutdown_sender(),
+            executor: executor.clone(),
         };
 
-        let rpc_handle = rpc::run_server(ctx)
+        let (rpc_handle, ws_handle) = rpc::run_server(ctx)
             .await
             .map_err(|e| format!("Unable to start HTTP RPC server: {:?}", e))?;
 
-        executor.spawn(rpc_handle, "rpc");
         executor.spawn(chunk_pool_handler.run(), "chunk_pool_handler");
 
+        // the servers run until stopped by the handles
+        self.shutdown
+            .register(ShutdownPhase::StopRpc, "rpc", move || {
+                Box::pin(async move {
+                    if let Err(e) = rpc_handle.stop() {
+                        warn!(?e, "Failed to stop HTTP RPC server");
+                    }
+                    if let Some(ws_handle) = ws_handle {
+                        if let Err(e) = ws_handle.stop() {
+                            warn!(?e, "Failed to stop WebSocket RPC server");
+                        }
+                    }
+                })
+            });
+
+        Ok(self)
+    }
+
+    /// Starts the HTTP server of Prometheus metrics.
+    pub fn with_metrics(self, config: http_metrics::Config) -> Result<Self, String> {
+        if !config.enabled {
+            return Ok(self);
+        }
+
+        let executor = require!("metrics", self, runtime_context).clone().executor;
+
+        let ctx = http_metrics::Context {
+            config,
+            log_store: self.async_store.clone(),
+        };
+
+        let (addr, server) = http_metrics::serve(ctx)
+            .map_err(|e| format!("Unable to start HTTP metrics server: {:?}", e))?;
+        info!("Metrics server started http://{}/metrics", addr);
+
+        executor.spawn(server, "http_metrics");
+
         Ok(self)
     }
 
-    pub async fn with_log_sync(self, config: LogSyncConfig) -> Result<Self, String> {
+    pub async fn with_log_sync(mut self, config: LogSyncConfig) -> Result<Self, String> {
         let executor = require!("log_sync", self, runtime_context).clone().executor;
-        let store = require!("log_sync", self, store).clone();
-        LogSyncManager::spawn(config, executor, store)
-            .await
-            .map_err(|e| e.to_string())?;
+        let store = require!("log_sync", self, async_store).clone();
+        let network_send = self.network.as_ref().map(|network| network.send.clone());
+        let (chain_head, flow_root_status) =
+            LogSyncManager::spawn(config, executor, store, network_send)
+                .await
+                .map_err(|e| e.to_string())?;
+        self.log_sync = Some(LogSyncComponents {
+            chain_head,
+            flow_root_status,
+        });
         Ok(self)
     }
 
     /// Consumes the builder, returning a `Client` if all necessary components have been
     /// specified.
-    pub fn build(self) -> Result<Client, String> {
+    pub fn build(mut self) -> Result<Client, String> {
         require!("client", self, runtime_context);
 
+        if let Some(async_store) = self.async_store.clone() {
+            self.shutdown
+                .register(ShutdownPhase::DrainStore, "store", move || {
+                    Box::pin(async move { async_store.shutdown().await })
+                });
+        }
+
+        if let Some(network) = self.network.as_ref() {
+            let send = network.send.clone();
+            let globals = network.globals.clone();
+            self.shutdown
+                .register(ShutdownPhase::DisconnectPeers, "network", move || {
+                    Box::pin(async move {
+                        if send.send(network::NetworkMessage::DisconnectAll).is_err() {
+                            return;
+                        }
+                        let disconnected = async {
+                            while globals.connected_peers() > 0 {
+                                tokio::time::sleep(Duration::from_millis(100)).await;
+                            }
+                        };
+                        if tokio::time::timeout(DISCONNECT_PEERS_TIMEOUT, disconnected)
+                            .await
+                            .is_err()
+                        {
+                            debug!("Some peers are not disconnected before shutdown");
+                        }
+                    })
+                });
+        }
+
         Ok(Client {
             network_globals: self.network.as_ref().map(|network| network.globals.clone()),
+            shutdown: self.shutdown,
         })
     }
 }
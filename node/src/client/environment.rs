@@ -141,14 +141,7 @@ impl Environment {
                 Err(e) => error!(error = %e, "Could not register SIGINT handler"),
             }
 
-            // setup for handling a SIGHUP
-            match signal(SignalKind::hangup()) {
-                Ok(hup_stream) => {
-                    let hup = SignalFuture::new(hup_stream, "Received SIGHUP");
-                    handles.push(hup);
-                }
-                Err(e) => error!(error = %e, "Could not register SIGHUP handler"),
-            }
+            // SIGHUP reloads the log config instead, see `log::configure`
 
             future::select(inner_shutdown, future::select_all(handles.into_iter())).await
         }) {
@@ -2,18 +2,21 @@
 
 mod builder;
 mod environment;
+mod shutdown;
 
 use network::{Enr, Multiaddr, NetworkGlobals};
 use std::sync::Arc;
 
 pub use builder::ClientBuilder;
 pub use environment::{Environment, EnvironmentBuilder, RuntimeContext};
+pub use shutdown::{ShutdownCoordinator, ShutdownPhase};
 
 /// The core Ionian client.
 ///
 /// Holds references to running services, cleanly shutting them down when dropped.
 pub struct Client {
     network_globals: Option<Arc<NetworkGlobals>>,
+    shutdown: ShutdownCoordinator,
 }
 
 impl Client {
@@ -31,4 +34,9 @@ impl Client {
     pub fn enr(&self) -> Option<Enr> {
         self.network_globals.as_ref().map(|n| n.local_enr())
     }
+
+    /// Stops the services gracefully, which should complete before the exit signal is fired.
+    pub async fn shutdown(self) {
+        self.shutdown.shutdown().await
+    }
 }
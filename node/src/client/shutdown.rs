@@ -0,0 +1,48 @@
+//! Stops the services in order once a shutdown is requested, before the tasks are cancelled by
+//! the exit signal, so that an abrupt exit does not leave partially written chunks in the store.
+
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+/// Maximum time to wait for each step to complete, after which the next step starts anyway.
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The phases of shutdown, which run in the order declared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownPhase {
+    /// Stop accepting RPC requests, e.g. new uploads.
+    StopRpc,
+    /// Complete the writes queued to the store, and reject the writes afterwards.
+    DrainStore,
+    /// Say goodbye to the peers, so that they do not wait for the requests in flight.
+    DisconnectPeers,
+}
+
+type ShutdownStep = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Collects the steps to stop the services while the client is built.
+#[derive(Default)]
+pub struct ShutdownCoordinator {
+    steps: Vec<(ShutdownPhase, &'static str, ShutdownStep)>,
+}
+
+impl ShutdownCoordinator {
+    pub fn register<F>(&mut self, phase: ShutdownPhase, name: &'static str, step: F)
+    where
+        F: FnOnce() -> BoxFuture<'static, ()> + Send + 'static,
+    {
+        self.steps.push((phase, name, Box::new(step)));
+    }
+
+    /// Runs the steps phase by phase, and the steps of the same phase in the order registered.
+    pub async fn shutdown(mut self) {
+        self.steps.sort_by_key(|(phase, _, _)| *phase);
+
+        for (phase, name, step) in self.steps {
+            debug!(?phase, %name, "Shutting down");
+            if tokio::time::timeout(STEP_TIMEOUT, step()).await.is_err() {
+                warn!(?phase, %name, "Shutdown step timed out");
+            }
+        }
+    }
+}
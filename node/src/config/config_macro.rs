@@ -20,6 +20,24 @@ macro_rules! if_not_vector {
 	);
 }
 
+macro_rules! parse_value {
+    (Option<$type:ty>, $value:expr) => {
+        $value.parse::<$type>().map(Some)
+    };
+    (Vec<$type:ty>, $value:expr) => {
+        // comma separated
+        $value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::parse::<$type>)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    ($type:ty, $value:expr) => {
+        $value.parse::<$type>()
+    };
+}
+
 macro_rules! underscore_to_hyphen {
     ($e:expr) => {
         str::replace($e, "_", "-")
@@ -28,7 +46,7 @@ macro_rules! underscore_to_hyphen {
 
 macro_rules! build_config{
     ($(($name:ident, ($($type:tt)+), $default:expr))*) => {
-        #[derive(Debug, PartialEq, Eq, Clone)]
+        #[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
         pub struct RawConfiguration {
             $(pub $name: $($type)+,)*
         }
@@ -42,28 +60,24 @@ macro_rules! build_config{
         }
 
         impl RawConfiguration {
-            // First parse arguments from config file, and then parse them from command line.
-            // Replace the ones from config file with the ones from commandline for duplicates.
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($name),)*];
+
+            // The options are resolved in layers, each of which replaces the duplicates of the
+            // previous ones: the defaults, the config files in the order given, the environment
+            // variables and then the command line.
             pub fn parse(matches: &clap::ArgMatches) -> Result<RawConfiguration, String> {
                 let mut config = RawConfiguration::default();
 
-                // read from config file
-                if let Some(config_file) = matches.value_of("config") {
-                    let config_value = std::fs::read_to_string(config_file)
-                        .map_err(|e| format!("failed to read configuration file: {:?}", e))?
-                        .parse::<toml::Value>()
-                        .map_err(|e| format!("failed to parse configuration file: {:?}", e))?;
-
-                    $(
-                        if let Some(value) = config_value.get(stringify!($name)) {
-                            config.$name = if_option!($($type)+,
-                                THEN { Some(value.clone().try_into().map_err(|e| format!("Invalid {}: err={:?}", stringify!($name), e).to_owned())?) }
-                                ELSE { value.clone().try_into().map_err(|e| format!("Invalid {}: err={:?}", stringify!($name), e).to_owned())? }
-                            );
-                        }
-                    )*
+                // read from config files
+                if let Some(config_files) = matches.values_of("config") {
+                    for config_file in config_files {
+                        config.load_file(config_file)?;
+                    }
                 }
 
+                // read from environment variables
+                config.load_env(|name| std::env::var(name).ok())?;
+
                 // read from command line
                 $(
                     #[allow(unused_variables)]
@@ -79,8 +93,47 @@ macro_rules! build_config{
 
                 Ok(config)
             }
+
+            fn load_file(&mut self, config_file: &str) -> Result<(), String> {
+                let config_value = std::fs::read_to_string(config_file)
+                    .map_err(|e| format!("failed to read configuration file {}: {:?}", config_file, e))?
+                    .parse::<toml::Value>()
+                    .map_err(|e| format!("failed to parse configuration file {}: {:?}", config_file, e))?;
+
+                // reject the unknown options, which are likely typos
+                if let Some(table) = config_value.as_table() {
+                    if let Some(name) = table.keys().find(|name| !Self::NAMES.contains(&name.as_str())) {
+                        return Err(format!("Unknown option {} in configuration file {}", name, config_file));
+                    }
+                }
+
+                $(
+                    if let Some(value) = config_value.get(stringify!($name)) {
+                        self.$name = if_option!($($type)+,
+                            THEN { Some(value.clone().try_into().map_err(|e| format!("Invalid {}: err={:?}", stringify!($name), e).to_owned())?) }
+                            ELSE { value.clone().try_into().map_err(|e| format!("Invalid {}: err={:?}", stringify!($name), e).to_owned())? }
+                        );
+                    }
+                )*
+
+                Ok(())
+            }
+
+            /// Reads the options from the environment variables prefixed with `IONIAN_`, e.g.
+            /// `IONIAN_DB_DIR`. Vectors are comma separated.
+            fn load_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+                $(
+                    let name = format!("IONIAN_{}", stringify!($name).to_uppercase());
+                    if let Some(value) = var(&name) {
+                        self.$name = parse_value!($($type)+, value)
+                            .map_err(|_| format!("Invalid {}", name))?;
+                    }
+                )*
+
+                Ok(())
+            }
         }
     }
 }
 
-pub(crate) use {build_config, if_not_vector, if_option, underscore_to_hyphen};
+pub(crate) use {build_config, if_not_vector, if_option, parse_value, underscore_to_hyphen};
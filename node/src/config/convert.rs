@@ -1,10 +1,22 @@
 #![allow(clippy::field_reassign_with_default)]
 
+use crate::keystore::{KeyKind, Keystore};
 use crate::IonianConfig;
+use ethereum_types::{Address, H256, U256};
+use kv::KvConfig;
 use log_entry_sync::{ContractAddress, LogSyncConfig};
-use network::NetworkConfig;
-use rpc::RPCConfig;
+use miner::{AttesterConfig, MinerConfig, MinerKey};
+use network::multiaddr::Protocol;
+use network::{
+    BandwidthConfig, Enr, EnrExt, IpFilterConfig, Keypair, Multiaddr, NetworkConfig, PeerId,
+};
+use rpc::{AuthConfig, RPCConfig, RateLimitConfig};
+use shared_types::{DataRoot, ShardConfig, StreamFilter, CHUNK_SIZE};
+use std::str::FromStr;
+use std::time::Duration;
+use storage::log_store::{ErasureConfig, FlowConfig};
 use storage::StorageConfig;
+use storage_async::DiskWatchdogConfig;
 
 impl IonianConfig {
     pub fn network_config(&self) -> Result<NetworkConfig, String> {
@@ -18,6 +30,7 @@ impl IonianConfig {
         network_config.network_dir = self.network_dir.clone().into();
         network_config.libp2p_port = self.network_libp2p_port;
         network_config.disable_discovery = self.network_disable_discovery;
+        network_config.upnp_enabled = !self.network_disable_upnp;
         network_config.discovery_port = self.network_libp2p_port;
         network_config.enr_tcp_port = Some(self.network_libp2p_port);
         network_config.enr_udp_port = Some(self.network_libp2p_port);
@@ -36,32 +49,181 @@ impl IonianConfig {
             .collect::<Result<_, _>>()
             .map_err(|e| format!("Unable to parse network_libp2p_nodes: {:?}", e))?;
 
+        for peer in self.network_trusted_peers.iter() {
+            let (peer_id, multiaddrs) = parse_trusted_peer(peer)
+                .map_err(|e| format!("Unable to parse network_trusted_peers: {}", e))?;
+            network_config.trusted_peers.push(peer_id.into());
+            network_config.trusted_peers_multiaddr.extend(multiaddrs);
+        }
+
+        network_config.ip_filter =
+            IpFilterConfig::parse(&self.network_ip_allow, &self.network_ip_deny)
+                .map_err(|e| format!("Unable to parse network IP filter: {}", e))?;
+
         network_config.discv5_config.table_filter = |_| true;
 
         // TODO
         network_config.enr_address = Some("127.0.0.1".parse::<std::net::IpAddr>().unwrap());
         network_config.target_peers = self.network_target_peers;
+        network_config.max_peers = self.network_max_peers;
+        network_config.max_inbound_peers = self.network_max_inbound_peers;
+        network_config.ping_timeout = self.network_ping_timeout_secs;
         network_config.private = self.network_private;
 
+        network_config.chain_id = self.network_chain_id;
+        network_config.shard_config =
+            ShardConfig::new(self.network_shard_id, self.network_num_shard)
+                .map_err(|e| format!("Unable to parse network shard config: {:?}", e))?;
+        network_config.enr_capacity = self.network_capacity;
+        network_config.enr_rpc_port = self.network_enr_rpc_port;
+
+        network_config.sync_bandwidth = BandwidthConfig {
+            upload_bytes_per_sec: self.sync_upload_bytes_per_sec,
+            download_bytes_per_sec: self.sync_download_bytes_per_sec,
+            peer_upload_bytes_per_sec: self.sync_peer_upload_bytes_per_sec,
+            peer_download_bytes_per_sec: self.sync_peer_download_bytes_per_sec,
+        };
+        if self.sync_peer_served_bytes_per_min < CHUNK_SIZE as u64 {
+            return Err(format!(
+                "sync_peer_served_bytes_per_min should be at least a chunk: {}",
+                self.sync_peer_served_bytes_per_min
+            ));
+        }
+        network_config.peer_chunk_bytes_per_min = self.sync_peer_served_bytes_per_min;
+
         Ok(network_config)
     }
 
+    pub fn keystore(&self) -> Result<Option<Keystore>, String> {
+        match (&self.keystore_dir, &self.keystore_password) {
+            (Some(dir), Some(password)) => Ok(Some(Keystore::new(dir, password.clone()))),
+            (Some(_), None) => Err("keystore_dir requires keystore_password".into()),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// Loads the network identity from the keystore if configured, otherwise the raw key file in
+    /// `network_dir` is used.
+    pub fn network_keypair(&self) -> Result<Option<Keypair>, String> {
+        match self.keystore()? {
+            Some(keystore) => Ok(Some(keystore.network_keypair()?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn storage_config(&self) -> Result<StorageConfig, String> {
+        let erasure = if self.db_erasure_data_shards == 0 {
+            None
+        } else {
+            Some(
+                ErasureConfig::new(self.db_erasure_data_shards, self.db_erasure_parity_shards)
+                    .map_err(|e| format!("Invalid erasure config: {:?}", e))?,
+            )
+        };
+
+        let flow = FlowConfig {
+            batch_size: self.db_entry_batch_size,
+            ..Default::default()
+        };
+        flow.validate()
+            .map_err(|e| format!("Invalid db_entry_batch_size: {:?}", e))?;
+
         Ok(StorageConfig {
             db_dir: self.db_dir.clone().into(),
+            entry_batch_size: self.db_entry_batch_size,
+            seal_chunks: self.db_seal_chunks,
+            verify_checksum: self.db_verify_checksum,
+            allow_overwrite: self.db_allow_overwrite,
+            sealed_file: if self.db_sealed_file.is_empty() {
+                None
+            } else {
+                Some(self.db_sealed_file.clone().into())
+            },
+            erasure,
+            proof_threads: self.db_proof_threads,
+            max_staged_bytes: self.db_max_staged_mb.saturating_mul(1024 * 1024),
+            staged_expiration_secs: self.db_staged_expiration_secs,
         })
     }
 
+    pub fn disk_watchdog_config(&self) -> Result<Option<DiskWatchdogConfig>, String> {
+        if self.db_min_free_space_mb == 0 {
+            return Ok(None);
+        }
+
+        if self.db_free_space_check_interval_secs == 0 {
+            return Err("db_free_space_check_interval_secs should be positive".into());
+        }
+
+        Ok(Some(DiskWatchdogConfig {
+            db_dir: self.db_dir.clone().into(),
+            min_free_space: self.db_min_free_space_mb * 1024 * 1024,
+            check_interval: Duration::from_secs(self.db_free_space_check_interval_secs),
+        }))
+    }
+
     pub fn rpc_config(&self) -> Result<RPCConfig, String> {
         let listen_address = self
             .rpc_listen_address
             .parse::<std::net::SocketAddr>()
             .map_err(|e| format!("Unable to parse rpc_listen_address: {:?}", e))?;
 
+        let ws_listen_address = self
+            .rpc_ws_listen_address
+            .as_ref()
+            .map(|addr| addr.parse::<std::net::SocketAddr>())
+            .transpose()
+            .map_err(|e| format!("Unable to parse rpc_ws_listen_address: {:?}", e))?;
+
+        let rate_limit = if self.rpc_rate_limit_requests_per_sec.is_some()
+            || self.rpc_rate_limit_bytes_per_min.is_some()
+        {
+            Some(RateLimitConfig {
+                requests_per_sec: self.rpc_rate_limit_requests_per_sec,
+                burst: self.rpc_rate_limit_burst,
+                bytes_per_min: self.rpc_rate_limit_bytes_per_min,
+            })
+        } else {
+            None
+        };
+
+        let auth = if self.rpc_jwt_secret_file.is_some() || !self.rpc_api_keys.is_empty() {
+            Some(AuthConfig {
+                jwt_secret_file: self.rpc_jwt_secret_file.clone().map(Into::into),
+                api_keys: self.rpc_api_keys.clone(),
+            })
+        } else {
+            None
+        };
+
+        // Pages are indexed by u32 chunk indices.
+        if self.rpc_max_response_chunks == 0 || u32::try_from(self.rpc_max_response_chunks).is_err()
+        {
+            return Err(format!(
+                "Invalid rpc_max_response_chunks {}, should be in [1, {}]",
+                self.rpc_max_response_chunks,
+                u32::MAX
+            ));
+        }
+
+        let cors_allowed_origins = if self.rpc_cors_allowed_origins.is_empty() {
+            None
+        } else {
+            Some(self.rpc_cors_allowed_origins.clone())
+        };
+
         Ok(RPCConfig {
             enabled: self.rpc_enabled,
             listen_address,
+            ws_listen_address,
+            cors_allowed_origins,
+            max_request_body_size: self.rpc_max_request_body_size,
             chunks_per_segment: self.rpc_chunks_per_segment,
+            max_response_chunks: self.rpc_max_response_chunks,
+            max_batch_size: self.rpc_max_batch_size,
+            upload_session_timeout: Duration::from_secs(self.rpc_upload_session_timeout_secs),
+            rate_limit,
+            auth,
         })
     }
 
@@ -70,11 +232,148 @@ impl IonianConfig {
             .log_contract_address
             .parse::<ContractAddress>()
             .map_err(|e| format!("Unable to parse log_contract_address: {:?}", e))?;
-        Ok(LogSyncConfig::new(
+        let mut config = LogSyncConfig::new(
             self.blockchain_rpc_endpoint.clone(),
             contract_address,
             self.log_sync_start_block_number,
-        ))
+        );
+        config.fallback_rpc_endpoint_urls = self.blockchain_rpc_fallback_endpoints.clone();
+        config.ws_endpoint_url = self.blockchain_ws_endpoint.clone();
+        config.contract_version = self.log_contract_version;
+        config.max_block_lag = self.log_sync_max_block_lag;
+        config.confirmation_block_count = self.log_sync_confirmation_block_count;
+        config.max_concurrent_requests = self.blockchain_rpc_max_concurrent_requests;
+        config.catch_up_concurrency = self.log_sync_catch_up_concurrency;
+        config.stream_filter = self.stream_filter()?;
+        config.root_verify_interval = match self.log_sync_root_verify_interval_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        Ok(config)
+    }
+
+    /// Loads the miner key, which also signs the attestations. The key in the keystore is used
+    /// unless `miner_key` or `miner_key_file` is set.
+    fn miner_key(&self) -> Result<Option<MinerKey>, String> {
+        let miner_key = match (&self.miner_key, &self.miner_key_file) {
+            (Some(_), Some(_)) => {
+                return Err("miner_key and miner_key_file should not be both set".into())
+            }
+            (Some(miner_key), None) => MinerKey::Raw(
+                miner_key
+                    .parse::<H256>()
+                    .map_err(|e| format!("Unable to parse miner_key: {:?}", e))?,
+            ),
+            (None, Some(path)) => match &self.miner_keystore_password {
+                Some(password) => MinerKey::Keystore {
+                    path: path.into(),
+                    password: password.clone(),
+                },
+                None => MinerKey::File(path.into()),
+            },
+            (None, None) => match self.keystore()? {
+                Some(keystore) if keystore.contains(KeyKind::Miner) => MinerKey::Keystore {
+                    path: keystore.path(KeyKind::Miner),
+                    password: keystore.password().into(),
+                },
+                _ => return Ok(None),
+            },
+        };
+
+        Ok(Some(miner_key))
+    }
+
+    pub fn miner_config(&self) -> Result<Option<MinerConfig>, String> {
+        let miner_key = match self.miner_key()? {
+            Some(miner_key) => miner_key,
+            None => return Ok(None),
+        };
+
+        let miner_id = match &self.miner_id {
+            Some(miner_id) => miner_id
+                .parse::<H256>()
+                .map_err(|e| format!("Unable to parse miner_id: {:?}", e))?,
+            None => return Ok(None),
+        };
+
+        if self.miner_probes_per_sec == Some(0) {
+            return Err("miner_probes_per_sec should be positive".into());
+        }
+
+        let beneficiary = self
+            .miner_beneficiary
+            .as_ref()
+            .map(|address| address.parse::<Address>())
+            .transpose()
+            .map_err(|e| format!("Unable to parse miner_beneficiary: {:?}", e))?;
+        let mine_address = self
+            .mine_contract_address
+            .parse::<ContractAddress>()
+            .map_err(|e| format!("Unable to parse mine_contract_address: {:?}", e))?;
+        let flow_address = self
+            .log_contract_address
+            .parse::<ContractAddress>()
+            .map_err(|e| format!("Unable to parse log_contract_address: {:?}", e))?;
+
+        Ok(Some(MinerConfig {
+            enabled: self.miner_enabled,
+            miner_id,
+            miner_key,
+            beneficiary,
+            probes_per_sec: self.miner_probes_per_sec,
+            rpc_endpoint_url: self.blockchain_rpc_endpoint.clone(),
+            mine_address,
+            flow_address,
+        }))
+    }
+
+    pub fn attester_config(&self) -> Result<Option<AttesterConfig>, String> {
+        let contract_address = match &self.attest_contract_address {
+            Some(address) => address
+                .parse::<Address>()
+                .map_err(|e| format!("Unable to parse attest_contract_address: {:?}", e))?,
+            None => return Ok(None),
+        };
+        let key = self
+            .miner_key()?
+            .ok_or("attest_contract_address requires miner_key or miner_key_file")?;
+
+        Ok(Some(AttesterConfig {
+            key,
+            rpc_endpoint_url: self.blockchain_rpc_endpoint.clone(),
+            contract_address,
+            method: self.attest_method.clone(),
+        }))
+    }
+
+    pub fn kv_config(&self) -> Result<Option<KvConfig>, String> {
+        if self.kv_streams.is_empty() {
+            return Ok(None);
+        }
+
+        let streams = parse_stream_ids(&self.kv_streams)?;
+        // the transactions of the KV streams are applied in order, which must all be synced
+        let stream_filter = self.stream_filter()?;
+        if let Some(stream) = streams.iter().find(|id| !stream_filter.is_subscribed(id)) {
+            return Err(format!("KV stream {:#x} is not in stream_ids", stream));
+        }
+
+        Ok(Some(KvConfig {
+            db_dir: self.kv_db_dir.clone().into(),
+            streams,
+        }))
+    }
+
+    pub fn metrics_config(&self) -> Result<http_metrics::Config, String> {
+        let listen_address = self
+            .metrics_listen_address
+            .parse::<std::net::SocketAddr>()
+            .map_err(|e| format!("Unable to parse metrics_listen_address: {:?}", e))?;
+
+        Ok(http_metrics::Config {
+            enabled: self.metrics_enabled,
+            listen_address,
+        })
     }
 
     pub fn chunk_pool_config(&self) -> chunk_pool::Config {
@@ -85,4 +384,90 @@ impl IonianConfig {
             expiration_time_secs: self.chunk_pool_expiration_time_secs,
         }
     }
+
+    pub fn file_location_cache_config(&self) -> file_location_cache::Config {
+        file_location_cache::Config {
+            min_replicas: self.file_location_min_replicas,
+            replica_expiration_time_secs: self.file_location_replica_expiration_secs,
+            ..Default::default()
+        }
+    }
+
+    pub fn sync_config(&self) -> Result<sync::Config, String> {
+        let priority = self.sync_priority.parse()?;
+
+        if self.sync_max_concurrent_syncs == 0 || self.sync_max_requests_per_peer == 0 {
+            return Err(
+                "sync_max_concurrent_syncs and sync_max_requests_per_peer should be positive"
+                    .into(),
+            );
+        }
+
+        let pinned_files = self
+            .sync_pinned_files
+            .iter()
+            .map(|root| root.parse::<DataRoot>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Unable to parse sync_pinned_files: {:?}", e))?;
+
+        let mut retry = sync::RetryConfig::default();
+        let policies = [
+            (&mut retry.chunks, self.sync_chunks_max_attempts),
+            (&mut retry.find_peers, self.sync_find_peers_max_attempts),
+            (&mut retry.offer_file, self.sync_offer_file_max_attempts),
+        ];
+        for (policy, max_attempts) in policies {
+            if max_attempts == 0 {
+                return Err("sync max attempts of retries should be positive".into());
+            }
+
+            policy.max_attempts = max_attempts;
+            if let Some(secs) = self.sync_retry_max_backoff_secs {
+                policy.max_backoff = Duration::from_secs(secs);
+            }
+        }
+
+        Ok(sync::Config {
+            priority,
+            pinned_files,
+            audit_interval: Duration::from_secs(self.sync_audit_interval_secs),
+            max_concurrent_syncs: self.sync_max_concurrent_syncs,
+            max_requests_per_peer: self.sync_max_requests_per_peer,
+            seed_peers: self.sync_seed_peers,
+            stream_filter: self.stream_filter()?,
+            verify_threads: self.sync_verify_threads,
+            retry,
+        })
+    }
+
+    pub fn stream_filter(&self) -> Result<StreamFilter, String> {
+        Ok(StreamFilter::new(parse_stream_ids(&self.stream_ids)?))
+    }
+}
+
+fn parse_stream_ids(stream_ids: &[String]) -> Result<Vec<U256>, String> {
+    stream_ids
+        .iter()
+        .map(|id| {
+            U256::from_str(id).map_err(|e| format!("Unable to parse stream id {}: {:?}", id, e))
+        })
+        .collect()
+}
+
+/// Parses a trusted peer on ENR format, or Multiaddr format with the `/p2p/<peer id>` suffix.
+fn parse_trusted_peer(peer: &str) -> Result<(PeerId, Vec<Multiaddr>), String> {
+    if peer.starts_with("enr:") {
+        let enr = peer.parse::<Enr>()?;
+        return Ok((enr.peer_id(), enr.multiaddr_p2p_tcp()));
+    }
+
+    let multiaddr = peer.parse::<Multiaddr>().map_err(|e| format!("{:?}", e))?;
+    match multiaddr.iter().last() {
+        Some(Protocol::P2p(hash)) => {
+            let peer_id =
+                PeerId::from_multihash(hash).map_err(|_| format!("Invalid peer id in {}", peer))?;
+            Ok((peer_id, vec![multiaddr]))
+        }
+        _ => Err(format!("Missing peer id in {}", peer)),
+    }
 }
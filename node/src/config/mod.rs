@@ -10,20 +10,91 @@ build_config! {
     (network_listen_address, (String), "0.0.0.0".to_string())
     (network_libp2p_port, (u16), 1234)
     (network_target_peers, (usize), 3)
+    (network_max_peers, (Option<usize>), None)
+    (network_max_inbound_peers, (Option<usize>), None)
+    (network_ping_timeout_secs, (u64), 10)  // disconnect peers not responding to ping in time
     (network_boot_nodes, (Vec<String>), vec![])
     (network_libp2p_nodes, (Vec<String>), vec![])
+    (network_ip_allow, (Vec<String>), vec![])  // CIDR ranges, e.g. 10.0.0.0/8, empty to allow all
+    (network_ip_deny, (Vec<String>), vec![])
+    (network_trusted_peers, (Vec<String>), vec![])  // ENRs or multiaddrs with the /p2p/<peer id> suffix
     (network_private, (bool), false)
     (network_disable_discovery, (bool), false)
+    (network_disable_upnp, (bool), false)   // port mapping with UPnP or NAT-PMP
+    (network_chain_id, (u64), 0)    // chain id of the blockchain that hosts the log contract
+    (network_shard_id, (u64), 0)
+    (network_num_shard, (u64), 1)   // power of 2
+    (network_capacity, (Option<u64>), None)    // remaining storage capacity in bytes
+    (network_enr_rpc_port, (Option<u16>), None)    // port of the public RPC service advertised in ENR
 
     // log sync
     (blockchain_rpc_endpoint, (String), "http://127.0.0.1:8545".to_string())
+    (blockchain_rpc_fallback_endpoints, (Vec<String>), vec![])  // in the order of priority
+    (blockchain_ws_endpoint, (Option<String>), None)    // to subscribe to new logs instead of polling
+    (blockchain_rpc_max_concurrent_requests, (usize), 16)  // for each endpoint
+    (log_sync_max_block_lag, (u64), 20)     // fail over if the endpoint falls behind others by more blocks
     (log_contract_address, (String), "".to_string())
+    (log_contract_version, (Option<u32>), None)     // detected from the submission events by default
     (log_sync_start_block_number, (u64), 0)
+    (log_sync_confirmation_block_count, (u64), 12)  // transactions are synced after the confirmations
+    (log_sync_catch_up_concurrency, (usize), 8)     // block ranges to fetch logs in parallel while catching up
+    (log_sync_root_verify_interval_secs, (u64), 300)    // verify the flow root against the chain, 0 to disable
+
+    // miner, which is configured only if both the miner id and key are set
+    (mine_contract_address, (String), "".to_string())
+    (miner_enabled, (bool), false)  // mining could also be started by the admin RPC
+    (miner_id, (Option<String>), None)
+    (miner_key, (Option<String>), None)     // private key to sign the mine transactions
+    (miner_key_file, (Option<String>), None)    // file of the private key, or the keystore if the password is set
+    (miner_keystore_password, (Option<String>), None)
+    (miner_beneficiary, (Option<String>), None)     // the address of the miner key by default
+    (miner_probes_per_sec, (Option<u64>), None)     // unlimited by default
+
+    // encrypted keys of the node, managed by the `keys` commands
+    (keystore_dir, (Option<String>), None)     // the network key is generated here if missing
+    (keystore_password, (Option<String>), None)
+
+    // attestation of finalized files, signed by the miner key
+    (attest_contract_address, (Option<String>), None)   // disabled by default
+    (attest_method, (String), "attest(uint256,bytes32)".to_string())  // called with the tx seq and data root
 
     // rpc
     (rpc_enabled, (bool), true)
     (rpc_listen_address, (String), "127.0.0.1:5678".to_string())
+    (rpc_ws_listen_address, (Option<String>), None)
+    (rpc_cors_allowed_origins, (Vec<String>), vec![])
+    (rpc_max_request_body_size, (u32), 10*1024*1024)    // 10M
     (rpc_chunks_per_segment, (usize), 1024)
+    (rpc_max_response_chunks, (usize), 16*1024)   // 4M
+    (rpc_max_batch_size, (usize), 1024)
+    (rpc_upload_session_timeout_secs, (u64), 600)
+    (rpc_rate_limit_requests_per_sec, (Option<u64>), None)
+    (rpc_rate_limit_burst, (u64), 100)
+    (rpc_rate_limit_bytes_per_min, (Option<u64>), None)
+    (rpc_jwt_secret_file, (Option<String>), None)
+    (rpc_api_keys, (Vec<String>), vec![])
+
+    // sync
+    (sync_priority, (String), "oldest_first".to_string())
+    (sync_pinned_files, (Vec<String>), vec![])
+    (sync_audit_interval_secs, (u64), 300)  // 0 to disable
+    (sync_max_concurrent_syncs, (usize), 16)
+    (sync_max_requests_per_peer, (usize), 4)
+    (sync_seed_peers, (usize), 0)  // 0 to disable
+    (sync_verify_threads, (usize), 4)  // threads to verify downloaded chunks concurrently, 0 to disable
+    (sync_chunks_max_attempts, (u32), 4)    // failed chunks requests before banning the peer from the file sync
+    (sync_find_peers_max_attempts, (u32), 10)   // failed provider lookups before giving up until the file is announced
+    (sync_offer_file_max_attempts, (u32), 3)
+    (sync_retry_max_backoff_secs, (Option<u64>), None)  // caps the exponential backoff of all retries
+    (sync_upload_bytes_per_sec, (Option<u64>), None)
+    (sync_download_bytes_per_sec, (Option<u64>), None)
+    (sync_peer_upload_bytes_per_sec, (Option<u64>), None)
+    (sync_peer_download_bytes_per_sec, (Option<u64>), None)
+    (sync_peer_served_bytes_per_min, (u64), 256*1024*1024)  // chunk data served to each peer, beyond which requests are answered as busy
+
+    // file location cache
+    (file_location_min_replicas, (usize), 3)   // files announced by fewer nodes are reported as under-replicated
+    (file_location_replica_expiration_secs, (u32), 86400)   // replicas not announced again in time are no longer counted
 
     // chunk pool
     (chunk_pool_max_cached_chunks_per_file, (usize), 4*1024)    // 1M
@@ -31,11 +102,37 @@ build_config! {
     (chunk_pool_max_writings, (usize), 16)
     (chunk_pool_expiration_time_secs, (u64), 300)   // 5 minutes
 
+    // metrics
+    (metrics_enabled, (bool), false)
+    (metrics_listen_address, (String), "127.0.0.1:6789".to_string())
+
     // db
     (db_dir, (String), "db".to_string())
+    (db_entry_batch_size, (usize), 1024)    // entries per database value, a power of 2 dividing the PoRA chunk size, fixed once the db is created
+    (db_seal_chunks, (bool), false)    // store PoRA chunks in the sealed layout to speed up mining
+    (db_verify_checksum, (bool), true)    // verify the checksums of stored entries on reads to avoid serving corrupted data
+    (db_allow_overwrite, (bool), false)    // allow overwriting stored entries with different data, only to repair corrupted data
+    (db_sealed_file, (String), "".to_string())    // flat file to store the sealed chunks in instead of the db, empty to disable
+    (db_erasure_data_shards, (usize), 0)    // erasure code PoRA chunks to recover missing data from peers, 0 to disable
+    (db_erasure_parity_shards, (usize), 0)
+    (db_min_free_space_mb, (u64), 1024)     // reject uploads and synced chunks below the free disk space, 0 to disable
+    (db_free_space_check_interval_secs, (u64), 10)
+    (db_proof_threads, (usize), 2)    // threads to read chunks and generate range proofs concurrently, 0 to disable
+    (db_max_staged_mb, (u64), 4096)    // max total size of files staged before their transactions are synced
+    (db_staged_expiration_secs, (u32), 86400)    // drop staged files not promoted within this time
+
+    // streams
+    (stream_ids, (Vec<String>), vec![])    // only store and sync the files of these streams, empty for all
+
+    // kv
+    (kv_streams, (Vec<String>), vec![])    // stream ids of the transactions to interpret as key-value updates, empty to disable
+    (kv_db_dir, (String), "kv_db".to_string())
 
     // misc
-    (log_config_file, (String), "log_config".to_string())
+    (log_config_file, (String), "log_config".to_string())   // reloaded upon changes or SIGHUP
+    (log_directory, (Option<String>), None)     // also write logs to files in the directory
+    (log_rotation, (String), "daily".to_string())   // minutely, hourly, daily or never
+    (log_format, (String), "text".to_string())  // text, or json for one event per line
 }
 
 #[derive(Debug)]
@@ -57,4 +154,42 @@ impl IonianConfig {
             raw_conf: RawConfiguration::parse(matches)?,
         })
     }
+
+    /// Checks that the options of all the services could be converted.
+    pub fn validate(&self) -> Result<(), String> {
+        self.network_config()?;
+        self.keystore()?;
+        self.storage_config()?;
+        self.disk_watchdog_config()?;
+        self.rpc_config()?;
+        self.log_sync_config()?;
+        self.metrics_config()?;
+        self.sync_config()?;
+        self.miner_config()?;
+        self.attester_config()?;
+        self.kv_config()?;
+        Ok(())
+    }
+
+    /// The effective configuration in TOML, with the secrets redacted.
+    pub fn to_toml(&self) -> Result<String, String> {
+        const REDACTED: &str = "<redacted>";
+
+        let mut raw_conf = self.raw_conf.clone();
+        if raw_conf.miner_key.is_some() {
+            raw_conf.miner_key = Some(REDACTED.into());
+        }
+        if raw_conf.miner_keystore_password.is_some() {
+            raw_conf.miner_keystore_password = Some(REDACTED.into());
+        }
+        if raw_conf.keystore_password.is_some() {
+            raw_conf.keystore_password = Some(REDACTED.into());
+        }
+        for api_key in raw_conf.rpc_api_keys.iter_mut() {
+            *api_key = REDACTED.into();
+        }
+
+        toml::to_string(&raw_conf)
+            .map_err(|e| format!("Unable to serialize configuration: {:?}", e))
+    }
 }
@@ -0,0 +1,140 @@
+//! Encrypted keys of the node, each of which is a JSON keystore (scrypt and AES-128-CTR, the same
+//! as EIP-2335) named after its purpose in the keystore directory, instead of raw key files.
+
+use ethereum_types::H256;
+use libp2p::core::identity::{secp256k1, Keypair};
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    /// The libp2p identity, which also signs the discv5 ENR.
+    Network,
+    /// The key to sign the mine transactions and attestations.
+    Miner,
+}
+
+impl KeyKind {
+    pub const ALL: [KeyKind; 2] = [KeyKind::Network, KeyKind::Miner];
+
+    fn name(&self) -> &'static str {
+        match self {
+            KeyKind::Network => "network",
+            KeyKind::Miner => "miner",
+        }
+    }
+}
+
+impl Display for KeyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KeyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("Unknown key {}, expected network or miner", s))
+    }
+}
+
+pub struct Keystore {
+    dir: PathBuf,
+    password: String,
+}
+
+impl Keystore {
+    pub fn new(dir: impl Into<PathBuf>, password: String) -> Self {
+        Keystore {
+            dir: dir.into(),
+            password,
+        }
+    }
+
+    pub fn path(&self, kind: KeyKind) -> PathBuf {
+        self.dir.join(format!("{}.json", kind))
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn contains(&self, kind: KeyKind) -> bool {
+        self.path(kind).exists()
+    }
+
+    /// Generates a new secp256k1 key.
+    pub fn create(&self, kind: KeyKind, overwrite: bool) -> Result<(), String> {
+        let secret = secp256k1::SecretKey::generate();
+        self.import(kind, &secret.to_bytes(), overwrite)
+    }
+
+    /// Encrypts the secp256k1 private key into the keystore.
+    pub fn import(&self, kind: KeyKind, secret: &[u8], overwrite: bool) -> Result<(), String> {
+        secp256k1::SecretKey::from_bytes(&mut secret.to_vec())
+            .map_err(|e| format!("Invalid secp256k1 private key: {:?}", e))?;
+
+        if !overwrite && self.contains(kind) {
+            return Err(format!(
+                "Key {} already exists in {:?}",
+                kind,
+                self.path(kind)
+            ));
+        }
+
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Unable to create keystore directory: {:?}", e))?;
+        let file_name = format!("{}.json", kind);
+        eth_keystore::encrypt_key(
+            &self.dir,
+            &mut rand::thread_rng(),
+            secret,
+            &self.password,
+            Some(&file_name),
+        )
+        .map_err(|e| format!("Unable to encrypt key {}: {:?}", kind, e))?;
+        restrict_permissions(&self.path(kind))
+    }
+
+    /// Decrypts the private key.
+    pub fn export(&self, kind: KeyKind) -> Result<H256, String> {
+        let secret = eth_keystore::decrypt_key(self.path(kind), &self.password)
+            .map_err(|e| format!("Unable to decrypt key {}: {:?}", kind, e))?;
+        if secret.len() != H256::len_bytes() {
+            return Err(format!("Invalid length of key {}: {}", kind, secret.len()));
+        }
+        Ok(H256::from_slice(&secret))
+    }
+
+    /// Loads the network identity, which is generated on first use.
+    pub fn network_keypair(&self) -> Result<Keypair, String> {
+        if !self.contains(KeyKind::Network) {
+            info!(path = ?self.path(KeyKind::Network), "Generating network key");
+            self.create(KeyKind::Network, false)?;
+        }
+
+        let mut secret = self.export(KeyKind::Network)?.to_fixed_bytes();
+        let secret = secp256k1::SecretKey::from_bytes(&mut secret)
+            .map_err(|e| format!("Invalid network key: {:?}", e))?;
+        Ok(Keypair::Secp256k1(secret.into()))
+    }
+}
+
+/// Only the owner could read the keystore files.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Unable to set permissions of {:?}: {:?}", path, e))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
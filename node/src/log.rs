@@ -1,38 +1,154 @@
+use rpc::LogFilterControl;
+use std::sync::{Arc, Mutex};
 use task_executor::TaskExecutor;
-use tracing::Level;
-use tracing_subscriber::EnvFilter;
+use tokio::sync::Notify;
+use tracing::{Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt};
+use tracing_subscriber::{reload, EnvFilter};
 
 const LOG_RELOAD_PERIOD_SEC: u64 = 30;
 
-pub fn configure(logfile: &str, executor: TaskExecutor) {
+/// Prefix of the log files, which are suffixed with the date and time of rotation.
+const LOG_FILE_PREFIX: &str = "ionian.log";
+
+type ReloadFn = dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync;
+
+/// The tracing filter in effect, which is loaded from the log config file and could be changed
+/// by the admin RPC.
+pub struct LogFilter {
+    reload: Box<ReloadFn>,
+    directives: Mutex<String>,
+}
+
+impl LogFilterControl for LogFilter {
+    fn directives(&self) -> String {
+        self.directives.lock().unwrap().clone()
+    }
+
+    fn set_directives(&self, directives: &str) -> Result<(), String> {
+        let directives = directives.trim();
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| format!("Invalid log filter {:?}: {}", directives, e))?;
+        (self.reload)(filter)?;
+        *self.directives.lock().unwrap() = directives.to_string();
+        Ok(())
+    }
+}
+
+/// Logs to stdout, and also to the files in `log_directory` rotated by `rotation` if provided.
+/// The events are formatted as plain text, or as JSON objects one per line, with the fields
+/// flattened, e.g. `tx_seq` and `peer_id`, for log aggregation systems to ingest.
+/// The returned guard flushes the log files when dropped, which should be held until exit.
+pub fn configure(
+    logfile: &str,
+    log_directory: Option<&str>,
+    rotation: &str,
+    format: &str,
+    executor: TaskExecutor,
+) -> Result<(Arc<LogFilter>, Option<WorkerGuard>), String> {
+    let (writer, guard) = match log_directory {
+        Some(log_directory) => {
+            let rotation = match rotation {
+                "minutely" => Rotation::MINUTELY,
+                "hourly" => Rotation::HOURLY,
+                "daily" => Rotation::DAILY,
+                "never" => Rotation::NEVER,
+                _ => return Err(format!("Invalid log_rotation: {}", rotation)),
+            };
+            let appender = RollingFileAppender::new(rotation, log_directory, LOG_FILE_PREFIX);
+            let (file_writer, guard) = tracing_appender::non_blocking(appender);
+            (
+                BoxMakeWriter::new(std::io::stdout.and(file_writer)),
+                Some(guard),
+            )
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
+
     let builder = tracing_subscriber::fmt()
         .with_max_level(Level::TRACE)
         .with_env_filter(EnvFilter::default())
+        .with_writer(writer)
         // .with_file(true)
         // .with_line_number(true)
         // .with_thread_names(true)
-        .with_filter_reloading();
+        // no color codes in the log files
+        .with_ansi(guard.is_none());
+
+    let reload = match format {
+        "text" => {
+            let builder = builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.init();
+            reload_fn(handle)
+        }
+        "json" => {
+            let builder = builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_ansi(false)
+                .with_filter_reloading();
+            let handle = builder.reload_handle();
+            builder.init();
+            reload_fn(handle)
+        }
+        _ => return Err(format!("Invalid log_format: {}", format)),
+    };
 
-    let handle = builder.reload_handle();
-    builder.init();
+    let log_filter = Arc::new(LogFilter {
+        reload,
+        directives: Default::default(),
+    });
 
     let logfile = logfile.to_string();
 
     // load config synchronously
     let mut config = std::fs::read_to_string(&logfile).unwrap_or_default();
-    let _ = handle.reload(&config);
+    if let Err(e) = log_filter.set_directives(&config) {
+        println!("{}", e);
+    }
+
+    // reload the config file immediately upon SIGHUP
+    let hangup = Arc::new(Notify::new());
+    #[cfg(target_family = "unix")]
+    {
+        let hangup = hangup.clone();
+        executor.spawn(
+            async move {
+                use tokio::signal::unix::{signal, SignalKind};
+
+                match signal(SignalKind::hangup()) {
+                    Ok(mut hangup_stream) => {
+                        while hangup_stream.recv().await.is_some() {
+                            hangup.notify_one();
+                        }
+                    }
+                    Err(e) => println!("Could not register SIGHUP handler: {:?}", e),
+                }
+            },
+            "log_reload_signal",
+        );
+    }
 
     // periodically check for config changes
+    let reloader = log_filter.clone();
     executor.spawn(
         async move {
             let mut interval =
                 tokio::time::interval(std::time::Duration::from_secs(LOG_RELOAD_PERIOD_SEC));
 
             loop {
-                interval.tick().await;
+                let forced = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = hangup.notified() => true,
+                };
 
                 let new_config = match tokio::fs::read_to_string(&logfile).await {
-                    Ok(c) if c == config => continue,
+                    Ok(c) if c == config && !forced => continue,
                     Ok(c) => c,
                     Err(e) => {
                         println!("Unable to read log file {}: {:?}", logfile, e);
@@ -42,14 +158,24 @@ pub fn configure(logfile: &str, executor: TaskExecutor) {
 
                 println!("Updating log config to {:?}", new_config);
 
-                match handle.reload(&new_config) {
+                match reloader.set_directives(&new_config) {
                     Ok(()) => config = new_config,
                     Err(e) => {
-                        println!("Failed to load new config: {:?}", e);
+                        println!("Failed to load new config: {}", e);
                     }
                 }
             }
         },
         "log_reload",
     );
+
+    Ok((log_filter, guard))
+}
+
+fn reload_fn<S: Subscriber + 'static>(handle: reload::Handle<EnvFilter, S>) -> Box<ReloadFn> {
+    Box::new(move |filter| {
+        handle
+            .reload(filter)
+            .map_err(|e| format!("Failed to reload log filter: {:?}", e))
+    })
 }
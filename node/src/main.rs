@@ -4,31 +4,54 @@ extern crate tracing;
 mod cli;
 mod client;
 mod config;
+mod keystore;
 mod log;
 
 use client::{Client, ClientBuilder, RuntimeContext};
 use config::IonianConfig;
+use log::LogFilter;
 use std::error::Error;
+use std::sync::Arc;
 
-async fn start_node(context: RuntimeContext, config: IonianConfig) -> Result<Client, String> {
+async fn start_node(
+    context: RuntimeContext,
+    config: IonianConfig,
+    log_filter: Arc<LogFilter>,
+) -> Result<Client, String> {
     let network_config = config.network_config()?;
+    let network_keypair = config.network_keypair()?;
     let storage_config = config.storage_config()?;
+    let disk_watchdog_config = config.disk_watchdog_config()?;
     let rpc_config = config.rpc_config()?;
     let log_sync_config = config.log_sync_config()?;
+    let metrics_config = config.metrics_config()?;
+    let sync_config = config.sync_config()?;
+    let miner_config = config.miner_config()?;
+    let attester_config = config.attester_config()?;
+    let kv_config = config.kv_config()?;
+    let file_location_cache_config = config.file_location_cache_config();
 
     ClientBuilder::new()
         .with_runtime_context(context)
+        .with_log_filter(log_filter)
         .with_rocksdb_store(&storage_config)?
-        .with_file_location_cache()
-        .with_network(&network_config)
+        .with_disk_watchdog(disk_watchdog_config)?
+        .with_finalizer()?
+        .with_file_location_cache(file_location_cache_config)
+        .with_network(&network_config, network_keypair)
         .await?
-        .with_sync()?
-        .with_miner()?
-        .with_router()?
-        .with_rpc(rpc_config, config.chunk_pool_config())
+        .with_sync(sync_config)?
+        .with_miner(miner_config)
+        .await?
+        .with_attester(attester_config)
         .await?
+        .with_router(&network_config)?
+        .with_kv(kv_config)?
         .with_log_sync(log_sync_config)
         .await?
+        .with_rpc(rpc_config, config.chunk_pool_config())
+        .await?
+        .with_metrics(metrics_config)?
         .build()
 }
 
@@ -36,37 +59,64 @@ fn main() -> Result<(), Box<dyn Error>> {
     // enable backtraces
     std::env::set_var("RUST_BACKTRACE", "1");
 
+    // CLI and config
+    let matches = cli::cli_app().get_matches();
+    let config = IonianConfig::parse(&matches)?;
+
+    if let Some(("config", config_matches)) = matches.subcommand() {
+        if let Some(("validate", _)) = config_matches.subcommand() {
+            config.validate()?;
+            println!("{}", config.to_toml()?);
+        }
+        return Ok(());
+    }
+
+    if let Some(("db", db_matches)) = matches.subcommand() {
+        return Ok(cli::run_db_command(db_matches, &config)?);
+    }
+
+    if let Some(("keys", keys_matches)) = matches.subcommand() {
+        return Ok(cli::run_keys_command(keys_matches, &config)?);
+    }
+
     // runtime environment
     let mut environment = client::EnvironmentBuilder::new()
         .multi_threaded_tokio_runtime()?
         .build()?;
-   
+
     println!("test");
 
     let context = environment.core_context();
     let executor = context.executor.clone();
 
-    // CLI, config, and logs
-    let matches = cli::cli_app().get_matches();
-    let config = IonianConfig::parse(&matches)?;
-    log::configure(&config.log_config_file, executor.clone());
+    // logs
+    let (log_filter, _log_guard) = log::configure(
+        &config.log_config_file,
+        config.log_directory.as_deref(),
+        &config.log_rotation,
+        &config.log_format,
+        executor.clone(),
+    )?;
 
     // start services
+    let (client_send, mut client_recv) = tokio::sync::oneshot::channel();
     executor.clone().spawn(
         async move {
             info!("Starting services...");
-            if let Err(e) = start_node(context.clone(), config).await {
-                error!(reason = %e, "Failed to start ionian node");
-                // Ignore the error since it always occurs during normal operation when
-                // shutting down.
-                let _ =
-                    executor
-                        .shutdown_sender()
-                        .try_send(task_executor::ShutdownReason::Failure(
-                            "Failed to start ionian node",
-                        ));
-            } else {
-                info!("Services started");
+            match start_node(context.clone(), config, log_filter).await {
+                Ok(client) => {
+                    info!("Services started");
+                    // kept until shutdown to stop the services gracefully
+                    let _ = client_send.send(client);
+                }
+                Err(e) => {
+                    error!(reason = %e, "Failed to start ionian node");
+                    // Ignore the error since it always occurs during normal operation when
+                    // shutting down.
+                    let _ = executor.shutdown_sender().try_send(
+                        task_executor::ShutdownReason::Failure("Failed to start ionian node"),
+                    );
+                }
             }
         },
         "ionian_node",
@@ -76,6 +126,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     let shutdown_reason = environment.block_until_shutdown_requested()?;
     info!(reason = ?shutdown_reason, "Shutting down...");
 
+    // stop the services in order before the tasks are cancelled
+    if let Ok(client) = client_recv.try_recv() {
+        environment.runtime().block_on(client.shutdown());
+    }
+
     environment.fire_signal();
 
     // Shutdown the environment once all tasks have completed.
@@ -0,0 +1,99 @@
+//! Compares the async store with the dynamically dispatched log store and with a concrete one,
+//! on the proof generation that dominates serving peers and mining.
+//!
+//! cargo bench -p storage-async --bench store
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle_light::merkle::log2_pow2;
+use shared_types::{ChunkArray, Transaction, CHUNK_SIZE};
+use std::sync::Arc;
+use storage::log_store::log_manager::{sub_merkle_tree, LogConfig, PORA_CHUNK_SIZE};
+use storage::log_store::{LogStoreChunkWrite, LogStoreWrite};
+use storage::LogManager;
+use storage_async::{GenericStore, Store};
+use task_executor::test_utils::TestRuntime;
+use tokio::sync::RwLock;
+
+/// Number of chunks of the file, which is a power of two so that the file is a single subtree.
+const FILE_CHUNKS: usize = 4 * 1024;
+
+/// Number of chunks of each proof, as requested by the sync protocol.
+const RANGE_CHUNKS: usize = 64;
+
+fn new_log_manager() -> LogManager {
+    let data: Vec<u8> = (0..FILE_CHUNKS * CHUNK_SIZE)
+        .map(|_| rand::random())
+        .collect();
+    let root = sub_merkle_tree(&data).unwrap().root();
+
+    let mut store = LogManager::memorydb(LogConfig::default()).unwrap();
+    store
+        .put_tx(Transaction {
+            stream_ids: vec![],
+            data: vec![],
+            data_merkle_root: root.into(),
+            merkle_nodes: vec![(log2_pow2(FILE_CHUNKS) + 1, root.into())],
+            start_entry_index: FILE_CHUNKS as u64,
+            size: data.len() as u64,
+            seq: 0,
+            sender: Default::default(),
+            block_timestamp: 0,
+        })
+        .unwrap();
+    for (i, batch) in data.chunks(PORA_CHUNK_SIZE * CHUNK_SIZE).enumerate() {
+        let chunks = ChunkArray {
+            data: batch.to_vec(),
+            start_index: (i * PORA_CHUNK_SIZE) as u64,
+        };
+        store.put_chunks(0, chunks).unwrap();
+    }
+    store.finalize_tx(0).unwrap();
+    store
+}
+
+fn bench_range_proof(c: &mut Criterion) {
+    let runtime = TestRuntime::default();
+    let executor = runtime.task_executor.clone();
+    let handle = executor.handle().unwrap();
+
+    let dyn_store = Store::new(Arc::new(RwLock::new(new_log_manager())), executor.clone());
+    let generic_store: GenericStore<LogManager> =
+        GenericStore::new(Arc::new(RwLock::new(new_log_manager())), executor.clone());
+
+    let mut group = c.benchmark_group("range_proof");
+
+    group.bench_function(BenchmarkId::new("dyn", RANGE_CHUNKS), |b| {
+        let mut start = 0;
+        b.iter(|| {
+            start = (start + RANGE_CHUNKS) % FILE_CHUNKS;
+            handle
+                .block_on(dyn_store.get_chunks_with_proof_by_tx_and_index_range(
+                    0,
+                    start,
+                    start + RANGE_CHUNKS,
+                ))
+                .unwrap()
+                .unwrap()
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("generic", RANGE_CHUNKS), |b| {
+        let mut start = 0;
+        b.iter(|| {
+            start = (start + RANGE_CHUNKS) % FILE_CHUNKS;
+            handle
+                .block_on(generic_store.get_chunks_with_proof_by_tx_and_index_range(
+                    0,
+                    start,
+                    start + RANGE_CHUNKS,
+                ))
+                .unwrap()
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_range_proof);
+criterion_main!(benches);
@@ -0,0 +1,153 @@
+use crate::{Store, StoreEvent};
+use shared_types::bytes_to_chunks;
+use std::collections::HashMap;
+use storage::error::Result;
+use task_executor::TaskExecutor;
+use tokio::sync::broadcast;
+
+/// Maximum number of files whose written chunks are tracked, beyond which the tracked files are
+/// dropped and reloaded from the store on their next writes.
+const MAX_TRACKED_FILES: usize = 1024;
+
+/// The written chunks of a file, as ascending disjoint ranges (`end` excluded).
+struct WrittenChunks {
+    num_chunks: usize,
+    ranges: Vec<(usize, usize)>,
+}
+
+impl WrittenChunks {
+    fn insert(&mut self, start: usize, end: usize) {
+        let (mut start, mut end) = (start, end);
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for &(s, e) in &self.ranges {
+            if e < start || s > end {
+                ranges.push((s, e));
+            } else {
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
+        let position = ranges.partition_point(|&(s, _)| s < start);
+        ranges.insert(position, (start, end));
+        self.ranges = ranges;
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ranges == [(0, self.num_chunks)]
+    }
+}
+
+/// Finalizes the files once all their chunks are written, which is driven by the store events,
+/// so that the writers, e.g. file sync and uploads, do not wait for the finalization that
+/// verifies the data root of the whole file. `StoreEvent::Finalized` is published once finalized.
+pub struct Finalizer {
+    store: Store,
+    files: HashMap<u64, WrittenChunks>,
+}
+
+impl Finalizer {
+    pub fn spawn(executor: TaskExecutor, store: Store) {
+        let events = store.subscribe_events();
+        let finalizer = Finalizer {
+            store,
+            files: Default::default(),
+        };
+        executor.spawn(async move { finalizer.start(events).await }, "finalizer");
+    }
+
+    async fn start(mut self, mut events: broadcast::Receiver<StoreEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(StoreEvent::ChunksWritten {
+                    tx_seq,
+                    start_index,
+                    end_index,
+                }) => {
+                    if let Err(e) = self.on_chunks_written(tx_seq, start_index, end_index).await {
+                        warn!(%tx_seq, ?e, "Failed to finalize file");
+                    }
+                }
+                Ok(StoreEvent::Finalized { tx_seq })
+                | Ok(StoreEvent::FinalizeFailed { tx_seq }) => {
+                    self.files.remove(&tx_seq);
+                }
+                Ok(StoreEvent::Reverted { .. }) => self.files.clear(),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    // the missed writes are reloaded from the store
+                    warn!(%n, "Finalizer lagged behind store events");
+                    self.files.clear();
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    async fn on_chunks_written(
+        &mut self,
+        tx_seq: u64,
+        start_index: usize,
+        end_index: usize,
+    ) -> Result<()> {
+        let file = match self.files.get_mut(&tx_seq) {
+            Some(file) => {
+                file.insert(start_index, end_index);
+                file
+            }
+            None => {
+                // the file is seen for the first time, so load the chunks written before
+                let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+                    Some(tx) => tx,
+                    None => return Ok(()),
+                };
+                if self.store.check_tx_completed(tx_seq).await? {
+                    return Ok(());
+                }
+                let ranges = self
+                    .store
+                    .get_chunk_ranges(tx_seq)
+                    .await?
+                    .unwrap_or_default();
+
+                if self.files.len() >= MAX_TRACKED_FILES {
+                    self.files.clear();
+                }
+                self.files.entry(tx_seq).or_insert(WrittenChunks {
+                    num_chunks: bytes_to_chunks(tx.size as usize),
+                    ranges,
+                })
+            }
+        };
+
+        if !file.is_complete() {
+            return Ok(());
+        }
+
+        self.files.remove(&tx_seq);
+        debug!(%tx_seq, "All chunks written, finalize file");
+        self.store.finalize_tx(tx_seq).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WrittenChunks;
+
+    #[test]
+    fn test_written_chunks() {
+        let mut chunks = WrittenChunks {
+            num_chunks: 10,
+            ranges: vec![],
+        };
+        chunks.insert(4, 6);
+        chunks.insert(8, 10);
+        chunks.insert(0, 2);
+        assert_eq!(chunks.ranges, vec![(0, 2), (4, 6), (8, 10)]);
+        assert!(!chunks.is_complete());
+
+        // adjacent and overlapped ranges are merged
+        chunks.insert(2, 4);
+        chunks.insert(5, 9);
+        assert_eq!(chunks.ranges, vec![(0, 10)]);
+        assert!(chunks.is_complete());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::metrics;
+use shared_types::{Chunk, ChunkArray, ChunkArrayWithProof, ErasureShard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The subsystem that issues store operations, by which the I/O is accounted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoOrigin {
+    Rpc,
+    Sync,
+    Miner,
+    /// Background integrity checks of the stored data.
+    Scrub,
+    /// Operations of the other components, e.g. log sync and finalizer.
+    Other,
+}
+
+impl IoOrigin {
+    pub const ALL: [IoOrigin; 5] = [
+        IoOrigin::Rpc,
+        IoOrigin::Sync,
+        IoOrigin::Miner,
+        IoOrigin::Scrub,
+        IoOrigin::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IoOrigin::Rpc => "rpc",
+            IoOrigin::Sync => "sync",
+            IoOrigin::Miner => "miner",
+            IoOrigin::Scrub => "scrub",
+            IoOrigin::Other => "other",
+        }
+    }
+}
+
+/// I/O of an origin since the node started. The bytes only count the chunk data, which dominates
+/// the disk usage, while the operations count all the store calls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub read_ops: u64,
+    pub read_bytes: u64,
+    pub write_ops: u64,
+    pub write_bytes: u64,
+}
+
+#[derive(Default)]
+struct IoCounters {
+    read_ops: AtomicU64,
+    read_bytes: AtomicU64,
+    write_ops: AtomicU64,
+    write_bytes: AtomicU64,
+}
+
+/// Counters of all origins, which are shared by all clones of a store and also reported as
+/// metrics.
+#[derive(Default)]
+pub(crate) struct IoAccounting {
+    counters: [IoCounters; IoOrigin::ALL.len()],
+}
+
+impl IoAccounting {
+    pub fn on_read(&self, origin: IoOrigin, bytes: usize) {
+        let counters = &self.counters[origin as usize];
+        counters.read_ops.fetch_add(1, Ordering::Relaxed);
+        counters
+            .read_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        metrics::inc_counter_vec(&metrics::STORE_READ_OPS, &[origin.as_str()]);
+        metrics::inc_counter_vec_by(&metrics::STORE_READ_BYTES, &[origin.as_str()], bytes as u64);
+    }
+
+    pub fn on_write(&self, origin: IoOrigin, bytes: usize) {
+        let counters = &self.counters[origin as usize];
+        counters.write_ops.fetch_add(1, Ordering::Relaxed);
+        counters
+            .write_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        metrics::inc_counter_vec(&metrics::STORE_WRITE_OPS, &[origin.as_str()]);
+        metrics::inc_counter_vec_by(
+            &metrics::STORE_WRITE_BYTES,
+            &[origin.as_str()],
+            bytes as u64,
+        );
+    }
+
+    pub fn stats(&self, origin: IoOrigin) -> IoStats {
+        let counters = &self.counters[origin as usize];
+        IoStats {
+            read_ops: counters.read_ops.load(Ordering::Relaxed),
+            read_bytes: counters.read_bytes.load(Ordering::Relaxed),
+            write_ops: counters.write_ops.load(Ordering::Relaxed),
+            write_bytes: counters.write_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Data read from the store, whose size is accounted.
+pub(crate) trait IoData {
+    fn data_len(&self) -> usize;
+}
+
+impl IoData for Chunk {
+    fn data_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl IoData for ChunkArray {
+    fn data_len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl IoData for ChunkArrayWithProof {
+    fn data_len(&self) -> usize {
+        self.chunks.data.len()
+    }
+}
+
+impl IoData for ErasureShard {
+    fn data_len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_accounting() {
+        let io = IoAccounting::default();
+        io.on_read(IoOrigin::Rpc, 10);
+        io.on_read(IoOrigin::Rpc, 0);
+        io.on_write(IoOrigin::Sync, 20);
+
+        assert_eq!(
+            io.stats(IoOrigin::Rpc),
+            IoStats {
+                read_ops: 2,
+                read_bytes: 10,
+                write_ops: 0,
+                write_bytes: 0,
+            }
+        );
+        assert_eq!(
+            io.stats(IoOrigin::Sync),
+            IoStats {
+                read_ops: 0,
+                read_bytes: 0,
+                write_ops: 1,
+                write_bytes: 20,
+            }
+        );
+        assert_eq!(io.stats(IoOrigin::Miner), IoStats::default());
+    }
+}
@@ -1,16 +1,44 @@
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate tracing;
 
+mod finalizer;
+mod io_stats;
+mod metrics;
+mod watchdog;
+
+pub use finalizer::Finalizer;
+pub use io_stats::{IoOrigin, IoStats};
+pub use watchdog::{DiskWatchdog, DiskWatchdogConfig};
+
 use anyhow::bail;
-use shared_types::{Chunk, ChunkArray, ChunkArrayWithProof, DataRoot, Transaction};
+use ethereum_types::{Address, H256};
+use io_stats::{IoAccounting, IoData};
+use shared_types::{
+    bytes_to_chunks, Chunk, ChunkArray, ChunkArrayWithProof, DataRoot, ErasureShard, FileMetadata,
+    Transaction,
+};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use storage::{error, error::Result, log_store::Store as LogStore};
+use storage::log_store::{
+    local_file, ErasureConfig, FileQuery, FileSyncProgress, LogSyncCheckpoint, PendingTx,
+    Store as LogStore, TxListFilter,
+};
+use storage::{error, error::Result};
 use task_executor::TaskExecutor;
-use tokio::sync::{oneshot, RwLock};
+use tokio::sync::{broadcast, oneshot, RwLock};
 
 /// The name of the worker tokio tasks.
 const WORKER_TASK_NAME: &str = "async_storage_worker";
 
+/// The name of the tokio tasks to prefetch chunks.
+const PREFETCH_TASK_NAME: &str = "async_storage_prefetch";
+
+/// Capacity of the store events channel, which is published upon every chunk write.
+const EVENTS_CAPACITY: usize = 1024;
+
 macro_rules! delegate {
     (fn $name:tt($($v:ident: $t:ty),*)) => {
         delegate!($name($($v: $t),*) -> ());
@@ -18,51 +46,514 @@ macro_rules! delegate {
 
     (fn $name:tt($($v:ident: $t:ty),*) -> $ret:ty) => {
         pub async fn $name(&self, $($v: $t),*) -> $ret {
-            self.spawn(move |store| store.$name($($v),*)).await
+            self.read(move |store| store.$name($($v),*)).await
+        }
+    };
+
+    // reads chunk data, whose size is accounted
+    (data fn $name:tt($($v:ident: $t:ty),*) -> $ret:ty) => {
+        pub async fn $name(&self, $($v: $t),*) -> $ret {
+            self.read_data(move |store| store.$name($($v),*)).await
+        }
+    };
+
+    (write fn $name:tt($($v:ident: $t:ty),*) -> $ret:ty) => {
+        pub async fn $name(&self, $($v: $t),*) -> $ret {
+            self.write(0, move |store| store.$name($($v),*)).await
         }
     };
 }
 
-#[derive(Clone)]
-pub struct Store {
+/// Events of store operations that other components may react to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StoreEvent {
+    /// Chunks of a file are written, in the range of chunk index (`end_index` excluded).
+    ChunksWritten {
+        tx_seq: u64,
+        start_index: usize,
+        end_index: usize,
+    },
+    /// A file is finalized, either uploaded or synced from peers.
+    Finalized { tx_seq: u64 },
+    /// Failed to finalize a file, e.g. some of its chunks are missing or invalid.
+    FinalizeFailed { tx_seq: u64 },
+    /// The log is reverted to `tx_seq` due to chain reorg, which invalidates the merkle proofs
+    /// generated before.
+    Reverted { tx_seq: u64 },
+}
+
+/// The store with the dynamically dispatched log store, which is used by most components.
+pub type Store = GenericStore<dyn LogStore>;
+
+/// Async wrapper of a log store, whose operations run on the worker tasks. The log store is
+/// generic, so that the hot paths could avoid the dynamic dispatch with a concrete store.
+pub struct GenericStore<S: LogStore + ?Sized> {
     /// Log and transaction storage.
-    store: Arc<RwLock<dyn LogStore>>,
+    store: Arc<RwLock<S>>,
 
     /// Tokio executor for spawning worker tasks.
     executor: TaskExecutor,
+
+    /// Sender of store events, which is shared by all clones of this store.
+    events: broadcast::Sender<StoreEvent>,
+
+    /// Set once the store is shut down, after which the operations are rejected.
+    closed: Arc<AtomicBool>,
+
+    /// Set by the disk watchdog when the disk space is low, during which the chunk writes are
+    /// rejected with `Error::StorageFull`.
+    write_protected: Arc<AtomicBool>,
+
+    /// I/O counters shared by all clones of this store.
+    io: Arc<IoAccounting>,
+
+    /// The subsystem that uses this clone, to which its I/O is accounted.
+    origin: IoOrigin,
+}
+
+// Derived `Clone` would require `S: Clone`.
+impl<S: LogStore + ?Sized> Clone for GenericStore<S> {
+    fn clone(&self) -> Self {
+        GenericStore {
+            store: self.store.clone(),
+            executor: self.executor.clone(),
+            events: self.events.clone(),
+            closed: self.closed.clone(),
+            write_protected: self.write_protected.clone(),
+            io: self.io.clone(),
+            origin: self.origin,
+        }
+    }
 }
 
-impl Store {
-    pub fn new(store: Arc<RwLock<dyn LogStore>>, executor: TaskExecutor) -> Self {
-        Store { store, executor }
+impl<S: LogStore + ?Sized> GenericStore<S> {
+    pub fn new(store: Arc<RwLock<S>>, executor: TaskExecutor) -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        GenericStore {
+            store,
+            executor,
+            events,
+            closed: Default::default(),
+            write_protected: Default::default(),
+            io: Default::default(),
+            origin: IoOrigin::Other,
+        }
+    }
+
+    /// Returns a clone of this store whose I/O is accounted to `origin`.
+    pub fn with_origin(&self, origin: IoOrigin) -> Self {
+        GenericStore {
+            origin,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the I/O of all origins since the node started.
+    pub fn io_stats(&self) -> Vec<(IoOrigin, IoStats)> {
+        IoOrigin::ALL
+            .iter()
+            .map(|origin| (*origin, self.io.stats(*origin)))
+            .collect()
+    }
+
+    /// Waits for the operations queued before to complete, and then rejects the operations
+    /// afterwards, so that no write is interrupted halfway once the node exits.
+    pub async fn shutdown(&self) {
+        // the lock is fair, so it is acquired after the queued operations
+        let _store = self.store.write().await;
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected.load(Ordering::SeqCst)
+    }
+
+    pub fn set_write_protected(&self, protected: bool) {
+        self.write_protected.store(protected, Ordering::SeqCst);
+    }
+
+    /// Subscribes to the events of operations on this store and its clones.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<StoreEvent> {
+        self.events.subscribe()
     }
 
     delegate!(fn check_tx_completed(tx_seq: u64) -> Result<bool>);
-    delegate!(fn get_chunk_by_tx_and_index(tx_seq: u64, index: usize) -> Result<Option<Chunk>>);
-    delegate!(fn get_chunks_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArray>>);
-    delegate!(fn get_chunks_with_proof_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArrayWithProof>>);
+    delegate!(data fn get_chunk_by_tx_and_index(tx_seq: u64, index: usize) -> Result<Option<Chunk>>);
+    delegate!(data fn get_chunks_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArray>>);
+    delegate!(fn get_chunk_ranges(tx_seq: u64) -> Result<Option<Vec<(usize, usize)>>>);
+    delegate!(fn get_available_ranges(tx_seq: u64) -> Result<Option<Vec<(usize, usize)>>>);
+    delegate!(data fn get_chunks_with_proof_by_tx_and_index_range(tx_seq: u64, index_start: usize, index_end: usize) -> Result<Option<ChunkArrayWithProof>>);
     delegate!(fn get_tx_by_seq_number(seq: u64) -> Result<Option<Transaction>>);
-    delegate!(fn put_chunks(tx_seq: u64, chunks: ChunkArray) -> Result<()>);
-    delegate!(fn finalize_tx(tx_seq: u64) -> Result<()>);
+    delegate!(fn next_tx_seq() -> Result<u64>);
+    delegate!(fn flow_length() -> Result<u64>);
+    delegate!(fn get_context() -> Result<(DataRoot, u64)>);
+    delegate!(fn get_flow_root_by_tx_seq(tx_seq: u64) -> Result<Option<DataRoot>>);
+    delegate!(data fn get_chunks_by_flow_index_range(index_start: u64, index_end: u64) -> Result<Option<ChunkArray>>);
+    delegate!(data fn get_sealed_chunk(chunk_index: u64) -> Result<Option<ChunkArray>>);
+    delegate!(data fn get_chunks_with_proof_by_flow_index_range(index_start: u64, index_end: u64) -> Result<Option<ChunkArrayWithProof>>);
+    delegate!(data fn get_erasure_shard(chunk_index: u64, shard_index: usize) -> Result<Option<ErasureShard>>);
+    delegate!(fn get_sync_progress() -> Result<Option<(u64, H256)>>);
+    delegate!(fn get_log_sync_checkpoints() -> Result<Vec<LogSyncCheckpoint>>);
+    delegate!(write fn put_log_sync_checkpoint(checkpoint: LogSyncCheckpoint) -> Result<()>);
+    delegate!(write fn put_tx(tx: Transaction) -> Result<()>);
+    delegate!(fn get_all_file_sync_progress() -> Result<Vec<(u64, FileSyncProgress)>>);
+    delegate!(write fn delete_file_sync_progress(tx_seq: u64) -> Result<()>);
+    delegate!(write fn invalidate_corrupted_chunks(tx_seq: u64) -> Result<Vec<(u64, u64)>>);
+    delegate!(fn get_file_metadata(tx_seq: u64) -> Result<Option<FileMetadata>>);
+
+    /// Writes the chunks of a file, unless the store is write protected due to low disk space.
+    pub async fn put_chunks(&self, tx_seq: u64, chunks: ChunkArray) -> Result<()> {
+        self.check_writable()?;
+        let start_index = chunks.start_index as usize;
+        let end_index = start_index + bytes_to_chunks(chunks.data.len());
+        self.write(chunks.data.len(), move |store| {
+            store.put_chunks(tx_seq, chunks)
+        })
+        .await?;
+
+        // no subscriber is fine
+        let _ = self.events.send(StoreEvent::ChunksWritten {
+            tx_seq,
+            start_index,
+            end_index,
+        });
+
+        Ok(())
+    }
+
+    /// Hints that the chunks will be read soon, e.g. the next pages of a streaming download, so
+    /// that they are loaded into the caches in the background. Returns without waiting for it.
+    pub fn prefetch_chunks(&self, tx_seq: u64, index_start: usize, index_end: usize) {
+        let store = self.store.clone();
+        let closed = self.closed.clone();
+
+        self.executor.spawn(
+            async move {
+                let store = store.read().await;
+                if closed.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                if let Err(e) = store.prefetch_chunks(tx_seq, index_start, index_end) {
+                    debug!(%tx_seq, %index_start, %index_end, ?e, "Failed to prefetch chunks");
+                }
+            },
+            PREFETCH_TASK_NAME,
+        );
+    }
+
+    pub async fn get_erasure_config(&self) -> Result<Option<ErasureConfig>> {
+        self.read(|store| Ok(store.get_erasure_config())).await
+    }
+
+    /// Writes the PoRA chunk reconstructed from the erasure shards, unless the store is write
+    /// protected due to low disk space.
+    pub async fn recover_chunk(&self, shards: Vec<ErasureShard>) -> Result<()> {
+        self.check_writable()?;
+        let bytes = shards.iter().map(|shard| shard.data.len()).sum();
+        self.write(bytes, move |store| store.recover_chunk(shards))
+            .await
+    }
+
+    /// Finalizes the file, and publishes `StoreEvent::Finalized` or `StoreEvent::FinalizeFailed`
+    /// accordingly. Nothing is published if the file is already finalized, e.g. by the
+    /// `Finalizer` once all chunks are written.
+    pub async fn finalize_tx(&self, tx_seq: u64) -> Result<()> {
+        let result = self
+            .write(0, move |store| {
+                if store.check_tx_completed(tx_seq)? {
+                    return Ok(false);
+                }
+                store.finalize_tx(tx_seq).map(|()| true)
+            })
+            .await;
+
+        let event = match result {
+            Ok(false) => return Ok(()),
+            Ok(true) => StoreEvent::Finalized { tx_seq },
+            Err(_) => StoreEvent::FinalizeFailed { tx_seq },
+        };
+
+        // no subscriber is fine
+        let _ = self.events.send(event);
+
+        result.map(|_| ())
+    }
+
+    /// Reverts the log to `tx_seq`, and publishes `StoreEvent::Reverted` upon success.
+    pub async fn revert_to(&self, tx_seq: u64) -> Result<()> {
+        self.write(0, move |store| store.revert_to(tx_seq)).await?;
+
+        // no subscriber is fine
+        let _ = self.events.send(StoreEvent::Reverted { tx_seq });
+
+        Ok(())
+    }
+
+    pub async fn validate_range_proof(
+        &self,
+        tx_seq: u64,
+        data: ChunkArrayWithProof,
+    ) -> Result<bool> {
+        self.read(move |store| store.validate_range_proof(tx_seq, &data))
+            .await
+    }
+
+    pub async fn put_file_sync_progress(
+        &self,
+        tx_seq: u64,
+        progress: FileSyncProgress,
+    ) -> Result<()> {
+        self.write(0, move |store| {
+            store.put_file_sync_progress(tx_seq, &progress)
+        })
+        .await
+    }
+
+    pub async fn put_file_metadata(&self, tx_seq: u64, metadata: FileMetadata) -> Result<()> {
+        self.write(0, move |store| store.put_file_metadata(tx_seq, &metadata))
+            .await
+    }
+
+    pub async fn pin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        let root = *data_root;
+        self.write(0, move |store| store.pin_file(&root)).await
+    }
+
+    pub async fn unpin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        let root = *data_root;
+        self.write(0, move |store| store.unpin_file(&root)).await
+    }
+
+    pub async fn is_pinned(&self, data_root: &DataRoot) -> Result<bool> {
+        let root = *data_root;
+        self.read(move |store| store.is_pinned(&root)).await
+    }
+
+    /// Starts to stage the file uploaded before its transaction is synced, or `announced` by
+    /// peers. Returns `false` if already staged.
+    pub async fn put_tx_pending(
+        &self,
+        data_root: &DataRoot,
+        size: u64,
+        announced: bool,
+    ) -> Result<bool> {
+        let root = *data_root;
+        self.write(0, move |store| store.put_tx_pending(&root, size, announced))
+            .await
+    }
+
+    /// Removes the staged files not promoted in time.
+    pub async fn prune_pending_txs(&self) -> Result<usize> {
+        self.write(0, move |store| store.prune_pending_txs()).await
+    }
+
+    pub async fn get_pending_tx(&self, data_root: &DataRoot) -> Result<Option<PendingTx>> {
+        let root = *data_root;
+        self.read(move |store| store.get_pending_tx(&root)).await
+    }
+
+    pub async fn get_pending_chunks(
+        &self,
+        data_root: &DataRoot,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArray>> {
+        let root = *data_root;
+        self.read_data(move |store| store.get_pending_chunks(&root, index_start, index_end))
+            .await
+    }
+
+    /// Stages the chunks of a file uploaded before its transaction is synced, unless the store is
+    /// write protected due to low disk space. Once the file is completely staged, it is promoted
+    /// right away if the transaction has been synced in the meantime.
+    pub async fn put_pending_chunks(&self, data_root: &DataRoot, chunks: ChunkArray) -> Result<()> {
+        self.check_writable()?;
+        let root = *data_root;
+        let promoted = self
+            .write(chunks.data.len(), move |store| {
+                if !store.put_pending_chunks(&root, chunks)? {
+                    return Ok(None);
+                }
+                match store.get_tx_seq_by_data_root(&root)? {
+                    Some(tx_seq) => Ok(store.promote_tx(tx_seq)?.then(|| tx_seq)),
+                    None => Ok(None),
+                }
+            })
+            .await?;
+
+        if let Some(tx_seq) = promoted {
+            // no subscriber is fine
+            let _ = self.events.send(StoreEvent::Finalized { tx_seq });
+        }
+
+        Ok(())
+    }
+
+    /// Splices the file staged before the transaction is synced into the flow, and publishes
+    /// `StoreEvent::Finalized` once promoted. Returns `false` if the file is not completely
+    /// staged.
+    pub async fn promote_tx(&self, tx_seq: u64) -> Result<bool> {
+        let promoted = self.write(0, move |store| store.promote_tx(tx_seq)).await?;
+
+        if promoted {
+            // no subscriber is fine
+            let _ = self.events.send(StoreEvent::Finalized { tx_seq });
+        }
+
+        Ok(promoted)
+    }
 
     pub async fn get_tx_seq_by_data_root(&self, data_root: &DataRoot) -> Result<Option<u64>> {
         let root = *data_root;
-        self.spawn(move |store| store.get_tx_seq_by_data_root(&root))
+        self.read(move |store| store.get_tx_seq_by_data_root(&root))
+            .await
+    }
+
+    pub async fn get_tx_by_data_root(&self, data_root: &DataRoot) -> Result<Option<Transaction>> {
+        let root = *data_root;
+        self.read(move |store| store.get_tx_by_data_root(&root))
+            .await
+    }
+
+    pub async fn get_txs_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<Transaction>> {
+        let root = *data_root;
+        self.read(move |store| store.get_txs_by_data_root(&root))
             .await
     }
 
+    pub async fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: TxListFilter,
+    ) -> Result<Vec<Transaction>> {
+        self.read(move |store| store.get_tx_list(start_seq, limit, &filter))
+            .await
+    }
+
+    pub async fn search_files(
+        &self,
+        query: FileQuery,
+        start_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<Transaction>> {
+        self.read(move |store| store.search_files(&query, start_seq, limit))
+            .await
+    }
+
+    pub async fn get_txs_by_sender(
+        &self,
+        sender: Address,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>> {
+        self.read(move |store| store.get_txs_by_sender(&sender, skip, limit))
+            .await
+    }
+
+    pub async fn get_txs_in_time_range(
+        &self,
+        start_time: u64,
+        end_time: u64,
+    ) -> Result<Vec<Transaction>> {
+        self.read(move |store| store.get_txs_in_time_range(start_time, end_time))
+            .await
+    }
+
+    /// Writes the finalized file to `path`, during which the other operations wait.
+    pub async fn export_file(&self, tx_seq: u64, path: PathBuf) -> Result<()> {
+        let size = self
+            .get_tx_by_seq_number(tx_seq)
+            .await?
+            .map_or(0, |tx| tx.size as usize);
+        self.read_bytes(size, move |store| {
+            local_file::export_file(store, tx_seq, &path)
+        })
+        .await
+    }
+
+    /// Writes the file in `path` to the store once verified against the data root, and then
+    /// finalizes the file.
+    pub async fn import_file(&self, tx_seq: u64, path: PathBuf) -> Result<()> {
+        self.check_writable()?;
+        let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len() as usize);
+        self.write(size, move |store| {
+            local_file::import_file(store, tx_seq, &path)
+        })
+        .await?;
+        self.finalize_tx(tx_seq).await
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.is_write_protected() {
+            metrics::inc_counter(&metrics::STORE_WRITES_REJECTED);
+            bail!(error::Error::StorageFull);
+        }
+
+        Ok(())
+    }
+
+    async fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.read_bytes(0, f).await
+    }
+
+    async fn read_bytes<T, F>(&self, bytes: usize, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = self.spawn(f).await;
+        self.io.on_read(self.origin, bytes);
+        result
+    }
+
+    async fn read_data<T, F>(&self, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut S) -> Result<Option<T>> + Send + 'static,
+        T: IoData + Send + 'static,
+    {
+        let result = self.spawn(f).await;
+        let bytes = match &result {
+            Ok(Some(data)) => data.data_len(),
+            _ => 0,
+        };
+        self.io.on_read(self.origin, bytes);
+        result
+    }
+
+    async fn write<T, F>(&self, bytes: usize, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut S) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = self.spawn(f).await;
+        self.io.on_write(self.origin, bytes);
+        result
+    }
+
     async fn spawn<T, F>(&self, f: F) -> Result<T>
     where
-        F: FnOnce(&mut dyn LogStore) -> Result<T> + Send + 'static,
+        F: FnOnce(&mut S) -> Result<T> + Send + 'static,
         T: Send + 'static,
     {
         let store = self.store.clone();
+        let closed = self.closed.clone();
         let (tx, rx) = oneshot::channel();
 
         self.executor.spawn(
             async move {
                 // FIXME(zz): Not all functions need `write`. Refactor store usage.
-                let res = f(&mut *store.write().await);
+                let mut store = store.write().await;
+                let res = if closed.load(Ordering::SeqCst) {
+                    Err(error::Error::Custom("Store is shut down".to_string()).into())
+                } else {
+                    f(&mut *store)
+                };
 
                 if tx.send(res).is_err() {
                     error!("Unable to complete async storage operation: the receiver dropped");
@@ -76,7 +567,7 @@ impl Store {
     }
 
     // FIXME(zz): Refactor the lock and async call here.
-    pub fn get_store(&self) -> &RwLock<dyn LogStore> {
+    pub fn get_store(&self) -> &RwLock<S> {
         self.store.as_ref()
     }
 }
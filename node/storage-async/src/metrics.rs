@@ -0,0 +1,36 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref DISK_FREE_BYTES: Result<IntGauge> = try_create_int_gauge(
+        "storage_disk_free_bytes",
+        "Free space in bytes of the file system of the database"
+    );
+    pub static ref STORE_WRITE_PROTECTED: Result<IntGauge> = try_create_int_gauge(
+        "storage_write_protected",
+        "Set to 1 when the disk space is low and the store rejects new writes"
+    );
+    pub static ref STORE_WRITES_REJECTED: Result<IntCounter> = try_create_int_counter(
+        "storage_writes_rejected_total",
+        "Count of writes rejected due to low disk space"
+    );
+    pub static ref STORE_READ_OPS: Result<IntCounterVec> = try_create_int_counter_vec(
+        "storage_read_ops_total",
+        "Count of store read operations by the origin subsystem",
+        &["origin"]
+    );
+    pub static ref STORE_READ_BYTES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "storage_read_bytes_total",
+        "Bytes of chunk data read from the store by the origin subsystem",
+        &["origin"]
+    );
+    pub static ref STORE_WRITE_OPS: Result<IntCounterVec> = try_create_int_counter_vec(
+        "storage_write_ops_total",
+        "Count of store write operations by the origin subsystem",
+        &["origin"]
+    );
+    pub static ref STORE_WRITE_BYTES: Result<IntCounterVec> = try_create_int_counter_vec(
+        "storage_write_bytes_total",
+        "Bytes of chunk data written to the store by the origin subsystem",
+        &["origin"]
+    );
+}
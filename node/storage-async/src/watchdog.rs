@@ -0,0 +1,71 @@
+use crate::{metrics, Store};
+use std::path::PathBuf;
+use std::time::Duration;
+use task_executor::TaskExecutor;
+
+#[derive(Clone, Debug)]
+pub struct DiskWatchdogConfig {
+    /// Directory of the database, whose file system is monitored.
+    pub db_dir: PathBuf,
+
+    /// The store is write protected once the free space falls below this threshold in bytes.
+    pub min_free_space: u64,
+
+    /// Interval to check the free space.
+    pub check_interval: Duration,
+}
+
+/// Monitors the free space of the database's file system, and protects the store from writes
+/// before the disk is full, rather than letting rocksdb fail and corrupt itself upon `ENOSPC`.
+pub struct DiskWatchdog {
+    config: DiskWatchdogConfig,
+    store: Store,
+}
+
+impl DiskWatchdog {
+    pub fn spawn(executor: TaskExecutor, config: DiskWatchdogConfig, store: Store) {
+        let watchdog = DiskWatchdog { config, store };
+        executor.spawn(async move { watchdog.start().await }, "disk_watchdog");
+    }
+
+    async fn start(self) {
+        let mut interval = tokio::time::interval(self.config.check_interval);
+
+        loop {
+            interval.tick().await;
+
+            match fs2::available_space(&self.config.db_dir) {
+                Ok(free_space) => self.on_free_space(free_space),
+                Err(e) => {
+                    warn!(db_dir = ?self.config.db_dir, "Failed to get free disk space: {:?}", e)
+                }
+            }
+        }
+    }
+
+    fn on_free_space(&self, free_space: u64) {
+        metrics::set_gauge(&metrics::DISK_FREE_BYTES, free_space as i64);
+
+        let protected = self.store.is_write_protected();
+
+        // resume only after some space is freed, so as not to toggle around the threshold
+        let resume_free_space = self.config.min_free_space + self.config.min_free_space / 10;
+
+        if !protected && free_space < self.config.min_free_space {
+            error!(
+                %free_space,
+                min_free_space = %self.config.min_free_space,
+                "Disk space is low, stop accepting uploads and synced chunks"
+            );
+            self.store.set_write_protected(true);
+        } else if protected && free_space >= resume_free_space {
+            info!(%free_space, "Disk space is freed, resume accepting writes");
+            self.store.set_write_protected(false);
+        }
+
+        metrics::set_gauge(
+            &metrics::STORE_WRITE_PROTECTED,
+            self.store.is_write_protected() as i64,
+        );
+    }
+}
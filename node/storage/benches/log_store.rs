@@ -0,0 +1,167 @@
+//! Measures the hot paths of the log store, i.e. ingesting and finalizing files, and generating
+//! proofs to serve peers.
+//!
+//! cargo bench -p storage --bench log_store
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use merkle_light::merkle::log2_pow2;
+use shared_types::{ChunkArray, Transaction, CHUNK_SIZE};
+use storage::log_store::log_manager::{
+    data_to_merkle_leaves, sub_merkle_tree, LogConfig, PORA_CHUNK_SIZE,
+};
+use storage::log_store::{LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use storage::LogManager;
+use tempdir::TempDir;
+
+/// Number of chunks of the file, which is a power of two so that the file is a single subtree.
+const FILE_CHUNKS: usize = 16 * 1024;
+
+fn random_data(num_chunks: usize) -> Vec<u8> {
+    (0..num_chunks * CHUNK_SIZE)
+        .map(|_| rand::random())
+        .collect()
+}
+
+/// The first file in the flow, which starts at the boundary of its subtree.
+fn new_tx(data: &[u8]) -> Transaction {
+    let num_chunks = data.len() / CHUNK_SIZE;
+    let root = sub_merkle_tree(data).unwrap().root();
+
+    Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: root.into(),
+        merkle_nodes: vec![(log2_pow2(num_chunks) + 1, root.into())],
+        start_entry_index: num_chunks as u64,
+        size: data.len() as u64,
+        seq: 0,
+        sender: Default::default(),
+        block_timestamp: 0,
+    }
+}
+
+fn put_chunks(store: &mut LogManager, data: &[u8]) {
+    for (i, batch) in data.chunks(PORA_CHUNK_SIZE * CHUNK_SIZE).enumerate() {
+        let chunks = ChunkArray {
+            data: batch.to_vec(),
+            start_index: (i * PORA_CHUNK_SIZE) as u64,
+        };
+        store.put_chunks(0, chunks).unwrap();
+    }
+}
+
+/// Stores the file with all chunks, but not finalized yet.
+fn new_store(data: &[u8]) -> LogManager {
+    let mut store = LogManager::memorydb(LogConfig::default()).unwrap();
+    store.put_tx(new_tx(data)).unwrap();
+    store
+}
+
+fn bench_put_chunks(c: &mut Criterion) {
+    let data = random_data(FILE_CHUNKS);
+
+    let mut group = c.benchmark_group("put_chunks");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    group.bench_function(BenchmarkId::from_parameter(FILE_CHUNKS), |b| {
+        b.iter_batched(
+            || new_store(&data),
+            |mut store| {
+                put_chunks(&mut store, &data);
+                store
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn bench_finalize_tx(c: &mut Criterion) {
+    let data = random_data(FILE_CHUNKS);
+
+    let mut group = c.benchmark_group("finalize_tx");
+    group.sample_size(10);
+    group.bench_function(BenchmarkId::from_parameter(FILE_CHUNKS), |b| {
+        b.iter_batched(
+            || {
+                let mut store = new_store(&data);
+                put_chunks(&mut store, &data);
+                store
+            },
+            |store| {
+                store.finalize_tx(0).unwrap();
+                store
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+/// Proofs from a freshly opened database (cold), or repeatedly from the same database whose
+/// blocks are cached (cached).
+fn bench_gen_proof(c: &mut Criterion) {
+    let data = random_data(FILE_CHUNKS);
+    let dir = TempDir::new("log_store_bench").unwrap();
+    {
+        let mut store = LogManager::rocksdb(LogConfig::default(), dir.path()).unwrap();
+        store.put_tx(new_tx(&data)).unwrap();
+        put_chunks(&mut store, &data);
+        store.finalize_tx(0).unwrap();
+    }
+
+    let mut group = c.benchmark_group("gen_proof");
+
+    group.bench_function("cold", |b| {
+        let mut index = 0;
+        b.iter_batched(
+            || LogManager::rocksdb(LogConfig::default(), dir.path()).unwrap(),
+            |store| {
+                index = (index + PORA_CHUNK_SIZE + 1) % FILE_CHUNKS;
+                store
+                    .get_chunk_with_proof_by_tx_and_index(0, index)
+                    .unwrap()
+                    .unwrap();
+                store
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    let store = LogManager::rocksdb(LogConfig::default(), dir.path()).unwrap();
+    group.bench_function("cached", |b| {
+        let mut index = 0;
+        b.iter(|| {
+            index = (index + 1) % FILE_CHUNKS;
+            store
+                .get_chunk_with_proof_by_tx_and_index(0, index)
+                .unwrap()
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_data_to_merkle_leaves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("data_to_merkle_leaves");
+
+    for num_chunks in [1024, 16 * 1024, 256 * 1024] {
+        let data = random_data(num_chunks);
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(num_chunks), &data, |b, data| {
+            b.iter(|| data_to_merkle_leaves(data).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_put_chunks,
+    bench_finalize_tx,
+    bench_gen_proof,
+    bench_data_to_merkle_leaves
+);
+criterion_main!(benches);
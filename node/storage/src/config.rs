@@ -1,6 +1,25 @@
+use crate::log_store::ErasureConfig;
 use std::path::PathBuf;
 
 #[derive(Clone)]
 pub struct Config {
     pub db_dir: PathBuf,
+    /// Number of entries stored in a single value of the database.
+    pub entry_batch_size: usize,
+    /// Whether to store the complete PoRA chunks in the sealed layout for mining.
+    pub seal_chunks: bool,
+    /// The flat file to store the sealed chunks in, or `None` to store them in the database.
+    pub sealed_file: Option<PathBuf>,
+    /// Whether to verify the checksums of the entry batches on reads.
+    pub verify_checksum: bool,
+    /// Whether to allow overwriting the stored entries, only to repair the corrupted data.
+    pub allow_overwrite: bool,
+    /// Erasure coding of the complete PoRA chunks, or `None` to disable.
+    pub erasure: Option<ErasureConfig>,
+    /// Number of threads to generate the proofs of a range concurrently, or 0 to disable.
+    pub proof_threads: usize,
+    /// Maximum total size in bytes of the files staged before their transactions are synced.
+    pub max_staged_bytes: u64,
+    /// Seconds after which the staged files are dropped if not promoted.
+    pub staged_expiration_secs: u32,
 }
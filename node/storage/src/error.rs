@@ -11,6 +11,10 @@ pub enum Error {
     Io(IoError),
     /// A partial chunk batch is written.
     InvalidBatchBoundary,
+    /// The disk space is low, so the writes are rejected until some space is freed.
+    StorageFull,
+    /// The stored data does not match its checksum, e.g. due to bit rot of the disk.
+    Corrupted(String),
     ValueDecodingError(DecodeError),
     Custom(String),
 }
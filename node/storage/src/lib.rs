@@ -1,8 +1,12 @@
+#[macro_use]
+extern crate lazy_static;
+
 use kvdb::KeyValueDB;
 
 pub mod config;
 pub mod error;
 pub mod log_store;
+pub mod metrics;
 
 pub use config::Config as StorageConfig;
 pub use log_store::log_manager::LogManager;
@@ -0,0 +1,94 @@
+use crate::log_store::log_manager::{ENTRY_SIZE, PORA_CHUNK_SIZE};
+use anyhow::{anyhow, bail, Result};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+/// Reed-Solomon coding of complete PoRA chunks, where a chunk is split into `data_shards` shards
+/// of contiguous entries, and `parity_shards` shards are computed from them. A chunk could be
+/// reconstructed from any `data_shards` of the shards, so the nodes of the network should use the
+/// same configuration to recover chunks from each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErasureConfig {
+    pub data_shards: usize,
+    pub parity_shards: usize,
+}
+
+impl ErasureConfig {
+    pub fn new(data_shards: usize, parity_shards: usize) -> Result<Self> {
+        if data_shards == 0 || parity_shards == 0 {
+            bail!("both data shards and parity shards should be positive");
+        }
+        // the limit of the GF(2^8) field
+        if data_shards + parity_shards > 256 {
+            bail!("too many shards: {}", data_shards + parity_shards);
+        }
+        if PORA_CHUNK_SIZE % data_shards != 0 {
+            bail!(
+                "data shards should divide the PoRA chunk size {}: {}",
+                PORA_CHUNK_SIZE,
+                data_shards
+            );
+        }
+        Ok(Self {
+            data_shards,
+            parity_shards,
+        })
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+
+    /// The size in bytes of each shard.
+    pub fn shard_size(&self) -> usize {
+        PORA_CHUNK_SIZE * ENTRY_SIZE / self.data_shards
+    }
+
+    /// Computes the parity shards of a complete PoRA chunk, which are concatenated in order.
+    pub fn encode(&self, chunk_data: &[u8]) -> Result<Vec<u8>> {
+        if chunk_data.len() != PORA_CHUNK_SIZE * ENTRY_SIZE {
+            bail!("encode incomplete chunk: len={}", chunk_data.len());
+        }
+        let mut shards: Vec<Vec<u8>> = chunk_data
+            .chunks_exact(self.shard_size())
+            .map(|shard| shard.to_vec())
+            .collect();
+        shards.resize(self.num_shards(), vec![0; self.shard_size()]);
+        self.codec()?
+            .encode(&mut shards)
+            .map_err(|e| anyhow!("erasure encoding failed: {:?}", e))?;
+        Ok(shards.split_off(self.data_shards).concat())
+    }
+
+    /// Reconstructs the data of a PoRA chunk from the shards indexed by position, of which at
+    /// least `data_shards` should be present.
+    pub fn reconstruct(&self, mut shards: Vec<Option<Vec<u8>>>) -> Result<Vec<u8>> {
+        if shards.len() != self.num_shards() {
+            bail!(
+                "shard count mismatch: expected={} got={}",
+                self.num_shards(),
+                shards.len()
+            );
+        }
+        if shards
+            .iter()
+            .flatten()
+            .any(|shard| shard.len() != self.shard_size())
+        {
+            bail!("shard size mismatch: expected={}", self.shard_size());
+        }
+        self.codec()?
+            .reconstruct_data(&mut shards)
+            .map_err(|e| anyhow!("erasure reconstruction failed: {:?}", e))?;
+        Ok(shards
+            .into_iter()
+            .take(self.data_shards)
+            .map(|shard| shard.expect("reconstructed"))
+            .collect::<Vec<_>>()
+            .concat())
+    }
+
+    fn codec(&self) -> Result<ReedSolomon> {
+        ReedSolomon::new(self.data_shards, self.parity_shards)
+            .map_err(|e| anyhow!("invalid erasure config: {:?}", e))
+    }
+}
@@ -0,0 +1,239 @@
+//! Inverted index of the stored files by tags, size and upload time, to search files without
+//! scanning all the transactions. The keys of an index are the indexed value followed by the tx
+//! seq, so the files of a value are adjacent and sorted by sequence numbers.
+//!
+//! The upload time is when the transaction is synced by the node, which never decreases along the
+//! tx seqs, so the time index is also sorted by sequence numbers.
+
+use crate::error::Error;
+use crate::log_store::log_manager::{COL_FILE_INDEX, COL_TX, COL_TX_BY_SENDER};
+use crate::IonianKeyValueDB;
+use anyhow::{anyhow, Result};
+use ethereum_types::Address;
+use kvdb::DBTransaction;
+use shared_types::Transaction;
+use ssz::{Decode, Encode};
+
+const TAG_PREFIX: u8 = 0;
+const SIZE_PREFIX: u8 = 1;
+const TIME_PREFIX: u8 = 2;
+/// The upload time of each tx seq, to look up and remove the time index of a transaction.
+const TX_TIME_PREFIX: u8 = 3;
+
+/// Filters of the files searched by `search_files`, which all apply if set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileQuery {
+    /// Only the files with all the tags, which are case-insensitive.
+    pub tags: Vec<String>,
+    /// Only the files submitted by the account.
+    pub sender: Option<Address>,
+    /// Minimum file size in bytes (included).
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes (included).
+    pub max_size: Option<u64>,
+    /// Minimum upload time in seconds since the Unix epoch (included).
+    pub start_time: Option<u32>,
+    /// Maximum upload time in seconds since the Unix epoch (included).
+    pub end_time: Option<u32>,
+}
+
+impl FileQuery {
+    pub(crate) fn matches_size(&self, size: u64) -> bool {
+        self.min_size.map_or(true, |min_size| size >= min_size)
+            && self.max_size.map_or(true, |max_size| size <= max_size)
+    }
+
+    pub(crate) fn matches_time(&self, time: u32) -> bool {
+        self.start_time
+            .map_or(true, |start_time| time >= start_time)
+            && self.end_time.map_or(true, |end_time| time <= end_time)
+    }
+
+    fn has_size_filter(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    pub(crate) fn has_time_filter(&self) -> bool {
+        self.start_time.is_some() || self.end_time.is_some()
+    }
+}
+
+impl From<shared_types::rpc::FileQuery> for FileQuery {
+    fn from(query: shared_types::rpc::FileQuery) -> Self {
+        Self {
+            tags: query.tags,
+            sender: query.sender,
+            min_size: query.min_size,
+            max_size: query.max_size,
+            start_time: query.start_time,
+            end_time: query.end_time,
+        }
+    }
+}
+
+/// Files are bucketed by the power of two of their sizes.
+pub(crate) fn size_bucket(size: u64) -> u8 {
+    (u64::BITS - 1 - size.max(1).leading_zeros()) as u8
+}
+
+fn normalize_tag(tag: &str) -> String {
+    tag.to_lowercase()
+}
+
+fn tag_prefix(tag: &str) -> Vec<u8> {
+    let tag = normalize_tag(tag);
+    let mut key = vec![TAG_PREFIX];
+    // the length avoids matching the tags that start with `tag`
+    key.extend_from_slice(&(tag.len() as u16).to_be_bytes());
+    key.extend_from_slice(tag.as_bytes());
+    key
+}
+
+fn index_key(mut prefix: Vec<u8>, tx_seq: u64) -> Vec<u8> {
+    prefix.extend_from_slice(&tx_seq.to_be_bytes());
+    prefix
+}
+
+fn size_prefix(bucket: u8) -> Vec<u8> {
+    vec![SIZE_PREFIX, bucket]
+}
+
+fn time_key(time: u32, tx_seq: u64) -> Vec<u8> {
+    let mut key = vec![TIME_PREFIX];
+    key.extend_from_slice(&time.to_be_bytes());
+    index_key(key, tx_seq)
+}
+
+fn tx_time_key(tx_seq: u64) -> Vec<u8> {
+    index_key(vec![TX_TIME_PREFIX], tx_seq)
+}
+
+/// The tx seq at the end of an index key.
+fn decode_index_tx_seq(key: &[u8]) -> Result<u64> {
+    let bytes = key
+        .len()
+        .checked_sub(8)
+        .map(|start| &key[start..])
+        .ok_or_else(|| anyhow!("invalid file index key {:?}", key))?;
+    Ok(u64::from_be_bytes(bytes.try_into()?))
+}
+
+/// Indexes the size and upload time of the transaction.
+pub(crate) fn put_tx(db_tx: &mut DBTransaction, tx: &Transaction, time: u32) {
+    db_tx.put(
+        COL_FILE_INDEX,
+        &index_key(size_prefix(size_bucket(tx.size)), tx.seq),
+        &[],
+    );
+    db_tx.put(COL_FILE_INDEX, &time_key(time, tx.seq), &[]);
+    db_tx.put(COL_FILE_INDEX, &tx_time_key(tx.seq), &time.as_ssz_bytes());
+}
+
+pub(crate) fn delete_tx(db_tx: &mut DBTransaction, tx: &Transaction, time: Option<u32>) {
+    db_tx.delete(
+        COL_FILE_INDEX,
+        &index_key(size_prefix(size_bucket(tx.size)), tx.seq),
+    );
+    if let Some(time) = time {
+        db_tx.delete(COL_FILE_INDEX, &time_key(time, tx.seq));
+    }
+    db_tx.delete(COL_FILE_INDEX, &tx_time_key(tx.seq));
+}
+
+pub(crate) fn put_tags(db_tx: &mut DBTransaction, tx_seq: u64, tags: &[String]) {
+    for tag in tags {
+        db_tx.put(COL_FILE_INDEX, &index_key(tag_prefix(tag), tx_seq), &[]);
+    }
+}
+
+pub(crate) fn delete_tags(db_tx: &mut DBTransaction, tx_seq: u64, tags: &[String]) {
+    for tag in tags {
+        db_tx.delete(COL_FILE_INDEX, &index_key(tag_prefix(tag), tx_seq));
+    }
+}
+
+pub(crate) fn has_tag(db: &dyn IonianKeyValueDB, tag: &str, tx_seq: u64) -> Result<bool> {
+    Ok(db.has_key(COL_FILE_INDEX, &index_key(tag_prefix(tag), tx_seq))?)
+}
+
+/// Returns the upload time of the transaction, which is not recorded for the transactions
+/// reverted by a chain reorg.
+pub(crate) fn get_upload_time(db: &dyn IonianKeyValueDB, tx_seq: u64) -> Result<Option<u32>> {
+    match db.get(COL_FILE_INDEX, &tx_time_key(tx_seq))? {
+        Some(value) => Ok(Some(u32::from_ssz_bytes(&value).map_err(Error::from)?)),
+        None => Ok(None),
+    }
+}
+
+type TxSeqs<'a> = Box<dyn Iterator<Item = Result<u64>> + 'a>;
+
+/// Visits the tx seqs of the files in the index that narrows down the query the most, in
+/// ascending order until `visit` returns `false`. The files visited may not match the other
+/// filters of the query.
+pub(crate) fn scan(
+    db: &dyn IonianKeyValueDB,
+    query: &FileQuery,
+    mut visit: impl FnMut(u64) -> Result<bool>,
+) -> Result<()> {
+    // the prefixes outlive the iterators that borrow them
+    let prefixes: Vec<Vec<u8>>;
+    let tx_seqs: TxSeqs = if let Some(tag) = query.tags.first() {
+        prefixes = vec![tag_prefix(tag)];
+        Box::new(
+            db.iter_with_prefix(COL_FILE_INDEX, &prefixes[0])
+                .map(|(key, _)| decode_index_tx_seq(&key)),
+        )
+    } else if let Some(sender) = &query.sender {
+        Box::new(
+            db.iter_with_prefix(COL_TX_BY_SENDER, sender.as_bytes())
+                .map(|(key, _)| decode_index_tx_seq(&key)),
+        )
+    } else if query.has_time_filter() {
+        let start_time = query.start_time.unwrap_or(0);
+        let end_time = query.end_time.unwrap_or(u32::MAX);
+        Box::new(
+            db.iter_with_prefix(COL_FILE_INDEX, &[TIME_PREFIX])
+                .map(|(key, _)| {
+                    let time = key
+                        .get(1..5)
+                        .ok_or_else(|| anyhow!("invalid file index key {:?}", key))?;
+                    Ok((
+                        u32::from_be_bytes(time.try_into()?),
+                        decode_index_tx_seq(&key)?,
+                    ))
+                })
+                .skip_while(move |entry| matches!(entry, Ok((time, _)) if *time < start_time))
+                .take_while(move |entry| !matches!(entry, Ok((time, _)) if *time > end_time))
+                .map(|entry| entry.map(|(_, tx_seq)| tx_seq)),
+        )
+    } else if query.has_size_filter() {
+        let min_bucket = size_bucket(query.min_size.unwrap_or(0));
+        let max_bucket = size_bucket(query.max_size.unwrap_or(u64::MAX));
+        prefixes = (min_bucket..=max_bucket).map(size_prefix).collect();
+        // merge the tx seqs of the buckets, which are sorted in each bucket
+        let buckets = prefixes.iter().map(|prefix| {
+            db.iter_with_prefix(COL_FILE_INDEX, prefix)
+                .map(|(key, _)| decode_index_tx_seq(&key))
+        });
+        Box::new(itertools::kmerge_by(
+            buckets,
+            |a: &Result<u64>, b: &Result<u64>| {
+                match (a, b) {
+                    (Ok(a), Ok(b)) => a < b,
+                    // surface the errors first
+                    (a, _) => a.is_err(),
+                }
+            },
+        ))
+    } else {
+        Box::new(db.iter(COL_TX).map(|(key, _)| decode_index_tx_seq(&key)))
+    };
+
+    for tx_seq in tx_seqs {
+        if !visit(tx_seq?)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
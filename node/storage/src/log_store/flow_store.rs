@@ -1,20 +1,33 @@
 use crate::error::Error;
+use crate::log_store::erasure::ErasureConfig;
 use crate::log_store::log_manager::{
     bytes_to_entries, data_to_merkle_leaves, sub_merkle_tree, COL_ENTRY_BATCH,
-    COL_ENTRY_BATCH_ROOT, ENTRY_SIZE, PORA_CHUNK_SIZE,
+    COL_ENTRY_BATCH_CHECKSUM, COL_ENTRY_BATCH_ROOT, COL_MISC, COL_PARITY_SHARD, COL_SEALED_CHUNK,
+    ENTRY_SIZE, PORA_CHUNK_SIZE,
 };
+use crate::log_store::sealed_file::SealedFile;
 use crate::log_store::{FlowRead, FlowWrite};
+use crate::metrics;
 use crate::{try_option, IonianKeyValueDB};
 use anyhow::{anyhow, bail, Result};
 use append_merkle::{AppendMerkleTree, Sha3Algorithm};
 use ethereum_types::H256;
+use kvdb::DBTransaction;
+use rayon::prelude::*;
 use shared_types::{bytes_to_chunks, ChunkArray, DataRoot};
 use ssz::{Decode, DecodeError, Encode};
 use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::{cmp, mem};
-use tracing::trace;
+use tracing::{info, trace};
+
+/// Key of the entry batch size that the database is created with.
+const ENTRY_BATCH_SIZE_KEY: &str = "entry_batch_size";
+
+/// Number of batch roots between the progress logs when loading them upon startup.
+const BATCH_ROOT_PROGRESS_INTERVAL: usize = 1 << 20;
 
 pub struct FlowStore {
     db: FlowDBStore,
@@ -22,28 +35,95 @@ pub struct FlowStore {
 }
 
 impl FlowStore {
-    pub fn new(db: Arc<dyn IonianKeyValueDB>, config: FlowConfig) -> Self {
-        Self {
-            db: FlowDBStore::new(db),
-            config,
+    /// Opens the flow store, which refuses a batch size different from the one that the database
+    /// is created with, since the entry batches are keyed by the batch index.
+    pub fn new(db: Arc<dyn IonianKeyValueDB>, config: FlowConfig) -> Result<Self> {
+        config.validate()?;
+        let mut db = FlowDBStore::new(db);
+        db.verify_checksum = config.verify_checksum;
+        if let Some(path) = config.sealed_file.as_ref().filter(|_| config.seal_chunks) {
+            db.sealed_file = Some(SealedFile::open(path)?);
+        }
+        match db.get_batch_size()? {
+            Some(batch_size) if batch_size != config.batch_size => bail!(
+                "the database is created with entry batch size {}, which could not be changed to {}",
+                batch_size,
+                config.batch_size
+            ),
+            Some(_) => {}
+            // The databases created before the batch size is recorded use `PORA_CHUNK_SIZE`.
+            None if db.has_entry_batch() && config.batch_size != PORA_CHUNK_SIZE => bail!(
+                "the database is created with entry batch size {}, which could not be changed to {}",
+                PORA_CHUNK_SIZE,
+                config.batch_size
+            ),
+            None => db.put_batch_size(config.batch_size)?,
         }
+        Ok(Self { db, config })
     }
 
     pub fn put_batch_root(&self, batch_index: u64, root: DataRoot, length: usize) -> Result<()> {
         self.db.put_batch_root(batch_index, root, length)
     }
+
+    pub fn erasure_config(&self) -> Option<&ErasureConfig> {
+        self.config.erasure.as_ref()
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct FlowConfig {
+    /// Number of entries stored in a single value of the database. Smaller batches rewrite less
+    /// data for partial writes, while larger ones take fewer reads to load a PoRA chunk. It should
+    /// be a power of 2 that divides `PORA_CHUNK_SIZE`, and could not be changed once the database
+    /// is created.
     pub batch_size: usize,
+    /// Whether to also store the complete PoRA chunks as raw contiguous blobs, so that mining
+    /// reads a chunk with a single lookup. This takes extra disk space of the chunk data.
+    pub seal_chunks: bool,
+    /// The flat file to store the sealed chunks in instead of the database, which is memory
+    /// mapped for reads with the `mmap` feature. Only used if `seal_chunks` is set.
+    pub sealed_file: Option<PathBuf>,
+    /// Whether to verify the checksums of the entry batches on reads, so that the corrupted data
+    /// are not served to peers, which would ban the node for the invalid proofs. The checksums
+    /// are always stored, but the batches written before they are introduced are not verified.
+    pub verify_checksum: bool,
+    /// Whether to allow overwriting the stored entries with different data, which are otherwise
+    /// write-once so that a malicious peer could not corrupt the finalized data. This should only
+    /// be enabled to repair the corrupted data.
+    pub allow_overwrite: bool,
+    /// Whether to also store the parity shards of the complete PoRA chunks, from which the chunks
+    /// missing on other nodes could be reconstructed.
+    pub erasure: Option<ErasureConfig>,
 }
 
 impl Default for FlowConfig {
     fn default() -> Self {
         Self {
             batch_size: PORA_CHUNK_SIZE,
+            seal_chunks: false,
+            sealed_file: None,
+            verify_checksum: true,
+            allow_overwrite: false,
+            erasure: None,
+        }
+    }
+}
+
+impl FlowConfig {
+    pub fn validate(&self) -> Result<()> {
+        // The first entry of the flow is never stored, so a batch holds at least one more entry.
+        if self.batch_size < 2
+            || !self.batch_size.is_power_of_two()
+            || PORA_CHUNK_SIZE % self.batch_size != 0
+        {
+            bail!(
+                "invalid entry batch size {}, which should be a power of 2 in [2, {}]",
+                self.batch_size,
+                PORA_CHUNK_SIZE
+            );
         }
+        Ok(())
     }
 }
 
@@ -84,17 +164,99 @@ impl FlowRead for FlowStore {
 
     /// Return the list of all stored chunk roots.
     fn get_chunk_root_list(&self) -> Result<Vec<(usize, DataRoot)>> {
-        let mut chunk_roots = Vec::new();
-        let mut i = 0;
-        while let Some(root) = self.db.get_batch_root(i)? {
-            let subtree = match root {
+        Ok(self
+            .db
+            .get_batch_root_list()?
+            .into_iter()
+            .map(|root| match root {
                 BatchRoot::Single(r) => (1, r),
                 BatchRoot::Multiple(t) => t,
+            })
+            .collect())
+    }
+
+    fn prefetch_entries(&self, index_start: u64, index_end: u64) -> Result<()> {
+        if index_end <= index_start {
+            return Ok(());
+        }
+        // Reading the batches warms the block cache of the database and the page cache.
+        let batch_size = self.config.batch_size as u64;
+        for batch_index in index_start / batch_size..=(index_end - 1) / batch_size {
+            self.db.prefetch_entry_batch(batch_index)?;
+        }
+        if let Some(file) = &self.db.sealed_file {
+            file.prefetch(
+                index_start / PORA_CHUNK_SIZE as u64,
+                (index_end - 1) / PORA_CHUNK_SIZE as u64 + 1,
+            );
+        }
+        Ok(())
+    }
+
+    fn get_sealed_chunk(&self, chunk_index: u64) -> Result<Option<ChunkArray>> {
+        let data = try_option!(self.db.get_sealed_chunk(chunk_index)?);
+        Ok(Some(ChunkArray {
+            data,
+            start_index: chunk_index * PORA_CHUNK_SIZE as u64,
+        }))
+    }
+
+    fn get_shard(&self, chunk_index: u64, shard_index: usize) -> Result<Option<Vec<u8>>> {
+        let erasure = match &self.config.erasure {
+            Some(erasure) => erasure,
+            None => bail!("erasure coding is disabled"),
+        };
+        if shard_index >= erasure.num_shards() {
+            bail!(
+                "shard index out of bound: shard_index={} num_shards={}",
+                shard_index,
+                erasure.num_shards()
+            );
+        }
+        let shard_size = erasure.shard_size();
+        let shard = if shard_index < erasure.data_shards {
+            let data = try_option!(self
+                .db
+                .get_complete_chunk(chunk_index, self.config.batch_size)?);
+            try_option!(data.get(shard_index * shard_size..(shard_index + 1) * shard_size)).to_vec()
+        } else {
+            let parity_index = shard_index - erasure.data_shards;
+            let parity = try_option!(self.db.get_parity_shards(chunk_index)?);
+            try_option!(parity.get(parity_index * shard_size..(parity_index + 1) * shard_size))
+                .to_vec()
+        };
+        Ok(Some(shard))
+    }
+
+    fn get_available_entry_ranges(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Vec<(u64, u64)>> {
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+        for (start_entry_index, end_entry_index) in
+            batch_iter(index_start, index_end, self.config.batch_size)
+        {
+            let chunk_index = start_entry_index / self.config.batch_size as u64;
+            let batch_start = chunk_index * self.config.batch_size as u64;
+            let batch = match self.db.get_entry_batch(chunk_index)? {
+                Some(batch) => batch,
+                None => continue,
             };
-            chunk_roots.push(subtree);
-            i += 1;
+            for (start_offset, end_offset) in batch.available_ranges() {
+                let start = cmp::max(batch_start + start_offset as u64, start_entry_index);
+                let end = cmp::min(batch_start + end_offset as u64, end_entry_index);
+                if start >= end {
+                    continue;
+                }
+                // Merge the ranges across batch boundaries.
+                match ranges.last_mut() {
+                    Some(last) if last.1 == start => last.1 = end,
+                    _ => ranges.push((start, end)),
+                }
+            }
         }
-        Ok(chunk_roots)
+        Ok(ranges)
     }
 }
 
@@ -117,103 +279,268 @@ impl FlowWrite for FlowStore {
                 .sub_array(start_entry_index, end_entry_index)
                 .expect("in range");
             let chunk_index = chunk.start_index / self.config.batch_size as u64;
-            let batch = if chunk.data.len() != self.config.batch_size * ENTRY_SIZE {
-                // We are writing partial data.
-                // TODO: Try to avoid loading from db if possible.
-                match self.db.get_entry_batch(chunk_index)? {
-                    None => {
-                        // no data in db, so just store the new data.
-                        EntryBatch::Incomplete(vec![PartialBatch {
-                            start_offset: (chunk.start_index % self.config.batch_size as u64)
-                                as usize,
-                            data: chunk.data,
-                        }])
-                    }
-                    Some(mut data_in_db) => {
-                        data_in_db.insert_data(
-                            (chunk.start_index % self.config.batch_size as u64) as usize,
-                            chunk.data,
-                        )?;
-                        data_in_db
-                    }
+            let offset = (chunk.start_index % self.config.batch_size as u64) as usize;
+            // The stored entries are checked against the new data, since they are write-once.
+            // TODO: Try to avoid loading from db if possible.
+            let batch = match self.db.get_entry_batch(chunk_index)? {
+                None if chunk.data.len() == self.config.batch_size * ENTRY_SIZE => {
+                    EntryBatch::Complete(chunk.data)
+                }
+                None => {
+                    // no data in db, so just store the new data.
+                    EntryBatch::Incomplete(vec![PartialBatch {
+                        start_offset: offset,
+                        data: chunk.data,
+                    }])
+                }
+                Some(mut data_in_db) => {
+                    data_in_db.insert_data(
+                        offset,
+                        chunk.data,
+                        self.config.batch_size,
+                        self.config.allow_overwrite,
+                    )?;
+                    data_in_db
                 }
-            } else {
-                EntryBatch::Complete(chunk.data)
             };
             batch_list.push((chunk_index, batch));
         }
-        self.db.put_entry_batch_list(batch_list)
+        self.db.put_entry_batch_list(
+            batch_list,
+            self.config.batch_size,
+            self.config.seal_chunks,
+            self.config.erasure.as_ref(),
+        )
     }
 
     fn truncate(&self, start_index: u64) -> crate::error::Result<()> {
         self.db.truncate(start_index, self.config.batch_size)
     }
+
+    fn remove_entries(&self, start_index: u64, end_index: u64) -> Result<()> {
+        if end_index <= start_index {
+            bail!(
+                "invalid entry index: start={} end={}",
+                start_index,
+                end_index
+            );
+        }
+        self.db
+            .remove_entries(start_index, end_index, self.config.batch_size)
+    }
 }
 
 pub struct FlowDBStore {
     kvdb: Arc<dyn IonianKeyValueDB>,
+    sealed_file: Option<SealedFile>,
+    verify_checksum: bool,
 }
 
 impl FlowDBStore {
     pub fn new(kvdb: Arc<dyn IonianKeyValueDB>) -> Self {
-        Self { kvdb }
+        Self {
+            kvdb,
+            sealed_file: None,
+            verify_checksum: false,
+        }
     }
 
+    /// Puts the entry batches, and returns the roots of the PoRA chunks completed by them. The
+    /// complete chunks are also sealed if `seal` is true, and their parity shards are stored if
+    /// `erasure` is set. The first chunk is never sealed or encoded since its first entry is not
+    /// stored.
     fn put_entry_batch_list(
         &self,
         batch_list: Vec<(u64, EntryBatch)>,
+        batch_size: usize,
+        seal: bool,
+        erasure: Option<&ErasureConfig>,
     ) -> Result<Vec<(u64, DataRoot)>> {
+        let batches_per_chunk = (PORA_CHUNK_SIZE / batch_size) as u64;
         let mut completed_batches = Vec::new();
+        let mut sealed_to_file = false;
         let mut tx = self.kvdb.transaction();
-        for (batch_index, data) in batch_list {
-            tx.put(
-                COL_ENTRY_BATCH,
-                &batch_index.to_be_bytes(),
-                &data.as_ssz_bytes(),
-            );
-            if batch_index == 0 {
+        let mut chunk_indices: Vec<u64> = Vec::new();
+        for (batch_index, data) in &batch_list {
+            if *batch_index == 0 && matches!(data, EntryBatch::Complete(_)) {
                 // Special case because the first entry hash is initialized as 0.
-                match data {
-                    EntryBatch::Complete(_) => {
-                        bail!("Unexpected first batch");
-                    }
-                    EntryBatch::Incomplete(p) => {
-                        trace!("put first batch: {:?}", p);
-                        if p.len() == 1
-                            && p[0].start_offset == 1
-                            && p[0].data.len() == ENTRY_SIZE * (PORA_CHUNK_SIZE - 1)
-                        {
-                            let mut leaves = vec![H256::zero()];
-                            leaves.append(&mut data_to_merkle_leaves(&p[0].data)?);
-                            let root =
-                                *AppendMerkleTree::<H256, Sha3Algorithm>::new(leaves, None).root();
-                            tx.put(
-                                COL_ENTRY_BATCH_ROOT,
-                                &batch_index.to_be_bytes(),
-                                &BatchRoot::Single(root).as_ssz_bytes(),
-                            );
-                            completed_batches.push((batch_index, root));
+                bail!("Unexpected first batch");
+            }
+            put_entry_batch(&mut tx, *batch_index, data);
+            let chunk_index = batch_index / batches_per_chunk;
+            if chunk_indices.last() != Some(&chunk_index) {
+                chunk_indices.push(chunk_index);
+            }
+        }
+
+        for chunk_index in chunk_indices {
+            let batch_range =
+                chunk_index * batches_per_chunk..(chunk_index + 1) * batches_per_chunk;
+            // Skip reading the other batches of the chunk if the written ones are incomplete.
+            if batch_list
+                .iter()
+                .filter(|(batch_index, _)| batch_range.contains(batch_index))
+                .any(|(batch_index, batch)| batch.complete_data(*batch_index, batch_size).is_none())
+            {
+                continue;
+            }
+
+            let mut raw_data = Vec::with_capacity(PORA_CHUNK_SIZE * ENTRY_SIZE);
+            let mut complete = true;
+            for batch_index in batch_range {
+                let batch_in_db;
+                let batch = match batch_list.iter().find(|(i, _)| *i == batch_index) {
+                    Some((_, batch)) => batch,
+                    None => match self.get_entry_batch(batch_index)? {
+                        Some(batch) => {
+                            batch_in_db = batch;
+                            &batch_in_db
+                        }
+                        None => {
+                            complete = false;
+                            break;
                         }
+                    },
+                };
+                match batch.complete_data(batch_index, batch_size) {
+                    Some(data) => raw_data.extend_from_slice(data),
+                    None => {
+                        complete = false;
+                        break;
                     }
                 }
-            } else if let EntryBatch::Complete(raw_data) = &data {
-                // TODO(zz): Check if we want to insert here.
+            }
+            if !complete {
+                continue;
+            }
+
+            if chunk_index == 0 {
+                trace!("put first chunk: len={}", raw_data.len());
+                let mut leaves = vec![H256::zero()];
+                leaves.append(&mut data_to_merkle_leaves(&raw_data)?);
+                let root = *AppendMerkleTree::<H256, Sha3Algorithm>::new(leaves, None).root();
+                tx.put(
+                    COL_ENTRY_BATCH_ROOT,
+                    &chunk_index.to_be_bytes(),
+                    &BatchRoot::Single(root).as_ssz_bytes(),
+                );
+                completed_batches.push((chunk_index, root));
+            } else {
                 assert_eq!(raw_data.len(), ENTRY_SIZE * PORA_CHUNK_SIZE);
                 let root: DataRoot = sub_merkle_tree(raw_data.as_slice())?.root().into();
                 tx.put(
                     COL_ENTRY_BATCH_ROOT,
-                    &batch_index.to_be_bytes(),
+                    &chunk_index.to_be_bytes(),
                     &BatchRoot::Single(root).as_ssz_bytes(),
                 );
-                completed_batches.push((batch_index, root));
+                if seal {
+                    match &self.sealed_file {
+                        Some(file) => {
+                            // An empty value marks the chunk as sealed in the file.
+                            file.write(chunk_index, &raw_data)?;
+                            tx.put(COL_SEALED_CHUNK, &chunk_index.to_be_bytes(), &[]);
+                            sealed_to_file = true;
+                        }
+                        None => tx.put(COL_SEALED_CHUNK, &chunk_index.to_be_bytes(), &raw_data),
+                    }
+                }
+                if let Some(erasure) = erasure {
+                    tx.put(
+                        COL_PARITY_SHARD,
+                        &chunk_index.to_be_bytes(),
+                        &erasure.encode(&raw_data)?,
+                    );
+                }
+                completed_batches.push((chunk_index, root));
+            }
+        }
+        // The sealed chunks are made durable before they are marked in the database.
+        if sealed_to_file {
+            if let Some(file) = &self.sealed_file {
+                file.sync()?;
             }
         }
         self.kvdb.write(tx)?;
         Ok(completed_batches)
     }
 
+    /// Returns the data of the PoRA chunk if all its entry batches are complete.
+    fn get_complete_chunk(&self, chunk_index: u64, batch_size: usize) -> Result<Option<Vec<u8>>> {
+        let batches_per_chunk = (PORA_CHUNK_SIZE / batch_size) as u64;
+        let mut data = Vec::with_capacity(PORA_CHUNK_SIZE * ENTRY_SIZE);
+        for batch_index in chunk_index * batches_per_chunk..(chunk_index + 1) * batches_per_chunk {
+            match try_option!(self.get_entry_batch(batch_index)?) {
+                EntryBatch::Complete(batch) => data.extend_from_slice(&batch),
+                EntryBatch::Incomplete(_) => return Ok(None),
+            }
+        }
+        Ok(Some(data))
+    }
+
+    fn get_batch_size(&self) -> Result<Option<usize>> {
+        let value = try_option!(self.kvdb.get(COL_MISC, ENTRY_BATCH_SIZE_KEY.as_bytes())?);
+        Ok(Some(
+            u64::from_ssz_bytes(&value).map_err(Error::from)? as usize
+        ))
+    }
+
+    fn put_batch_size(&self, batch_size: usize) -> Result<()> {
+        Ok(self.kvdb.put(
+            COL_MISC,
+            ENTRY_BATCH_SIZE_KEY.as_bytes(),
+            &(batch_size as u64).as_ssz_bytes(),
+        )?)
+    }
+
+    fn has_entry_batch(&self) -> bool {
+        self.kvdb.iter(COL_ENTRY_BATCH).next().is_some()
+    }
+
+    fn get_sealed_chunk(&self, chunk_index: u64) -> Result<Option<Vec<u8>>> {
+        let data = try_option!(self
+            .kvdb
+            .get(COL_SEALED_CHUNK, &chunk_index.to_be_bytes())?);
+        if !data.is_empty() {
+            return Ok(Some(data));
+        }
+        match &self.sealed_file {
+            Some(file) => match file.read(chunk_index)? {
+                Some(data) => Ok(Some(data)),
+                None => bail!("sealed chunk missing in file: chunk_index={}", chunk_index),
+            },
+            None => bail!(
+                "chunk {} is sealed in a file, which is not configured",
+                chunk_index
+            ),
+        }
+    }
+
+    fn get_parity_shards(&self, batch_index: u64) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .kvdb
+            .get(COL_PARITY_SHARD, &batch_index.to_be_bytes())?)
+    }
+
+    fn prefetch_entry_batch(&self, batch_index: u64) -> Result<()> {
+        self.kvdb.get(COL_ENTRY_BATCH, &batch_index.to_be_bytes())?;
+        Ok(())
+    }
+
     fn get_entry_batch(&self, batch_index: u64) -> Result<Option<EntryBatch>> {
         let raw = try_option!(self.kvdb.get(COL_ENTRY_BATCH, &batch_index.to_be_bytes())?);
+        if self.verify_checksum {
+            if let Some(checksum) = self
+                .kvdb
+                .get(COL_ENTRY_BATCH_CHECKSUM, &batch_index.to_be_bytes())?
+            {
+                if checksum != crc32fast::hash(&raw).to_be_bytes() {
+                    bail!(Error::Corrupted(format!(
+                        "entry batch checksum mismatch: batch_index={}",
+                        batch_index
+                    )));
+                }
+            }
+        }
         Ok(Some(EntryBatch::from_ssz_bytes(&raw).map_err(Error::from)?))
     }
 
@@ -230,11 +557,43 @@ impl FlowDBStore {
         )?)
     }
 
-    fn get_batch_root(&self, batch_index: u64) -> Result<Option<BatchRoot>> {
-        let raw = try_option!(self
-            .kvdb
-            .get(COL_ENTRY_BATCH_ROOT, &batch_index.to_be_bytes())?);
-        Ok(Some(BatchRoot::from_ssz_bytes(&raw).map_err(Error::from)?))
+    /// Returns the roots of the batches from 0 until the first missing one. The roots are read
+    /// in a single scan, and then decoded and verified in parallel since there are a root for
+    /// each PoRA chunk of the flow.
+    fn get_batch_root_list(&self) -> Result<Vec<BatchRoot>> {
+        let mut raw_roots = Vec::new();
+        for (key, value) in self.kvdb.iter(COL_ENTRY_BATCH_ROOT) {
+            if decode_batch_index(&key)? != raw_roots.len() as u64 {
+                break;
+            }
+            raw_roots.push(value);
+            if raw_roots.len() % BATCH_ROOT_PROGRESS_INTERVAL == 0 {
+                info!(loaded = raw_roots.len(), "Loading chunk roots");
+                metrics::set_gauge(&metrics::STARTUP_CHUNK_ROOTS, raw_roots.len() as i64);
+            }
+        }
+        metrics::set_gauge(&metrics::STARTUP_CHUNK_ROOTS, raw_roots.len() as i64);
+
+        raw_roots
+            .par_iter()
+            .enumerate()
+            .map(|(batch_index, raw)| {
+                let root = BatchRoot::from_ssz_bytes(raw).map_err(Error::from)?;
+                let (length, data_root) = match &root {
+                    BatchRoot::Single(data_root) => (1, data_root),
+                    BatchRoot::Multiple((length, data_root)) => (*length, data_root),
+                };
+                if length == 0 || data_root.is_zero() {
+                    bail!(
+                        "invalid batch root: batch_index={} length={} root={:?}",
+                        batch_index,
+                        length,
+                        data_root
+                    );
+                }
+                Ok(root)
+            })
+            .collect()
     }
 
     fn truncate(&self, start_index: u64, batch_size: usize) -> crate::error::Result<()> {
@@ -243,16 +602,19 @@ impl FlowDBStore {
         let first_batch_offset = start_index as usize % batch_size;
         if first_batch_offset != 0 {
             if let Some(mut first_batch) = self.get_entry_batch(start_batch_index)? {
-                first_batch.truncate(first_batch_offset as usize);
-                tx.put(
-                    COL_ENTRY_BATCH,
-                    &start_batch_index.to_be_bytes(),
-                    &first_batch.as_ssz_bytes(),
-                );
+                first_batch.truncate(first_batch_offset as usize, batch_size);
+                put_entry_batch(&mut tx, start_batch_index, &first_batch);
             }
-
             start_batch_index += 1;
         }
+        // The PoRA chunk that is partially truncated is no longer complete.
+        let mut start_chunk_index = start_index / PORA_CHUNK_SIZE as u64;
+        if start_index % PORA_CHUNK_SIZE as u64 != 0 {
+            tx.delete(COL_SEALED_CHUNK, &start_chunk_index.to_be_bytes());
+            tx.delete(COL_PARITY_SHARD, &start_chunk_index.to_be_bytes());
+
+            start_chunk_index += 1;
+        }
         // TODO: `kvdb` and `kvdb-rocksdb` does not support `seek_to_last` yet.
         // We'll need to fork it or use another wrapper for a better performance in this.
         let end = match self.kvdb.iter(COL_ENTRY_BATCH).last() {
@@ -264,13 +626,65 @@ impl FlowDBStore {
         };
         for batch_index in start_batch_index..=end {
             tx.delete(COL_ENTRY_BATCH, &batch_index.to_be_bytes());
-            tx.delete(COL_ENTRY_BATCH_ROOT, &batch_index.to_be_bytes());
+            tx.delete(COL_ENTRY_BATCH_CHECKSUM, &batch_index.to_be_bytes());
+        }
+        let end_chunk_index = end / (PORA_CHUNK_SIZE / batch_size) as u64;
+        for chunk_index in start_chunk_index..=end_chunk_index {
+            tx.delete(COL_ENTRY_BATCH_ROOT, &chunk_index.to_be_bytes());
+            tx.delete(COL_SEALED_CHUNK, &chunk_index.to_be_bytes());
+            tx.delete(COL_PARITY_SHARD, &chunk_index.to_be_bytes());
+        }
+        self.kvdb.write(tx)?;
+        Ok(())
+    }
+
+    fn remove_entries(&self, start_index: u64, end_index: u64, batch_size: usize) -> Result<()> {
+        let mut tx = self.kvdb.transaction();
+        for (start_entry_index, end_entry_index) in batch_iter(start_index, end_index, batch_size) {
+            let batch_index = start_entry_index / batch_size as u64;
+            let batch_start_index = batch_index * batch_size as u64;
+            let batch = match self.get_entry_batch(batch_index) {
+                Ok(batch) => batch,
+                // The whole batch is untrusted if it does not match its checksum.
+                Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::Corrupted(_))) => None,
+                Err(e) => return Err(e),
+            };
+            if let Some(mut batch) = batch {
+                batch.remove(
+                    (start_entry_index - batch_start_index) as usize,
+                    (end_entry_index - batch_start_index) as usize,
+                );
+                if !batch.available_ranges().is_empty() {
+                    put_entry_batch(&mut tx, batch_index, &batch);
+                    continue;
+                }
+            }
+            tx.delete(COL_ENTRY_BATCH, &batch_index.to_be_bytes());
+            tx.delete(COL_ENTRY_BATCH_CHECKSUM, &batch_index.to_be_bytes());
+        }
+        // The PoRA chunks with removed entries are no longer complete.
+        for chunk_index in
+            start_index / PORA_CHUNK_SIZE as u64..=(end_index - 1) / PORA_CHUNK_SIZE as u64
+        {
+            tx.delete(COL_SEALED_CHUNK, &chunk_index.to_be_bytes());
+            tx.delete(COL_PARITY_SHARD, &chunk_index.to_be_bytes());
         }
         self.kvdb.write(tx)?;
         Ok(())
     }
 }
 
+/// Puts the entry batch along with its checksum, which is verified on reads.
+fn put_entry_batch(tx: &mut DBTransaction, batch_index: u64, batch: &EntryBatch) {
+    let raw = batch.as_ssz_bytes();
+    tx.put(
+        COL_ENTRY_BATCH_CHECKSUM,
+        &batch_index.to_be_bytes(),
+        &crc32fast::hash(&raw).to_be_bytes(),
+    );
+    tx.put(COL_ENTRY_BATCH, &batch_index.to_be_bytes(), &raw);
+}
+
 enum EntryBatch {
     Complete(Vec<u8>),
     /// All `PartialBatch`s are ordered based on `start_index`.
@@ -383,6 +797,34 @@ impl PartialBatch {
 }
 
 impl EntryBatch {
+    /// Return the data of all entries in this batch if complete. The first batch of the flow is
+    /// complete without its first entry, which is never stored.
+    fn complete_data(&self, batch_index: u64, batch_size: usize) -> Option<&[u8]> {
+        match self {
+            EntryBatch::Complete(data) => Some(data),
+            EntryBatch::Incomplete(p)
+                if batch_index == 0
+                    && p.len() == 1
+                    && p[0].start_offset == 1
+                    && p[0].data.len() == ENTRY_SIZE * (batch_size - 1) =>
+            {
+                Some(&p[0].data)
+            }
+            EntryBatch::Incomplete(_) => None,
+        }
+    }
+
+    /// Return the offset ranges (`end` excluded) of the available entries in this batch.
+    fn available_ranges(&self) -> Vec<(usize, usize)> {
+        match self {
+            EntryBatch::Complete(data) => vec![(0, bytes_to_chunks(data.len()))],
+            EntryBatch::Incomplete(data_list) => data_list
+                .iter()
+                .map(|p| (p.start_offset, p.end_offset()))
+                .collect(),
+        }
+    }
+
     fn get_data(&self, offset: usize, length: usize) -> Option<Vec<u8>> {
         match self {
             EntryBatch::Complete(data) => data
@@ -407,93 +849,103 @@ impl EntryBatch {
         }
     }
 
-    /// Return `Error` if the new data overlaps with old data.
+    /// Inserts the data at the entry `offset` of this batch. The entries that are already stored
+    /// are write-once, so the new data should be the same as the old ones on the overlapped
+    /// entries, or an `Error` is returned. With `overwrite`, the old data are replaced instead,
+    /// which is only for repairing the corrupted data.
     /// Convert `Incomplete` to `Completed` if the chunk is completed after the insertion.
-    fn insert_data(&mut self, offset: usize, mut data: Vec<u8>) -> Result<()> {
+    fn insert_data(
+        &mut self,
+        offset: usize,
+        data: Vec<u8>,
+        batch_size: usize,
+        overwrite: bool,
+    ) -> Result<()> {
+        let end_offset = offset + bytes_to_chunks(data.len());
+        if end_offset > batch_size {
+            bail!(
+                "data out of the batch: end_offset={} batch_size={}",
+                end_offset,
+                batch_size
+            );
+        }
         match self {
-            EntryBatch::Complete(_) => {
-                bail!("overwriting a completed PoRA Chunk with partial data");
-            }
-            EntryBatch::Incomplete(list) => {
-                let data_entry_len = bytes_to_chunks(data.len());
-                match list.binary_search_by_key(&offset, |p| p.start_offset) {
-                    Ok(i) => {
+            EntryBatch::Complete(old) => {
+                let old = &mut old[offset * ENTRY_SIZE..end_offset * ENTRY_SIZE];
+                if old != data.as_slice() {
+                    if !overwrite {
                         bail!(
-                            "same offset with a PartialBatch at index {}: offset={}",
-                            i,
-                            offset
+                            "conflict with the stored entries: offset={} end_offset={}",
+                            offset,
+                            end_offset
                         );
                     }
-                    Err(position) => {
-                        if position != 0 && offset < list[position - 1].end_offset() {
-                            bail!(
-                                "Overlap with index {}: end_offset={} new_offset={}",
-                                position - 1,
-                                list[position - 1].end_offset(),
-                                offset
-                            );
+                    old.copy_from_slice(&data);
+                }
+            }
+            EntryBatch::Incomplete(list) => {
+                if overwrite {
+                    punch_hole(list, offset, end_offset);
+                    insert_partial(list, offset, data);
+                } else {
+                    // Only insert the entries that are not stored yet.
+                    let mut next = offset;
+                    let mut gaps = Vec::new();
+                    for p in list.iter() {
+                        let start = cmp::max(p.start_offset, offset);
+                        let end = cmp::min(p.end_offset(), end_offset);
+                        if start >= end {
+                            continue;
                         }
-                        if position != list.len()
-                            && offset + data_entry_len > list[position].start_offset
+                        if p.data[(start - p.start_offset) * ENTRY_SIZE
+                            ..(end - p.start_offset) * ENTRY_SIZE]
+                            != data[(start - offset) * ENTRY_SIZE..(end - offset) * ENTRY_SIZE]
                         {
                             bail!(
-                                "Overlap with index{}: start_offset={} new_end_offset={}",
-                                position,
-                                list[position].start_offset,
-                                offset + data_entry_len
+                                "conflict with the stored entries: offset={} end_offset={}",
+                                start,
+                                end
                             );
                         }
-                        let merge_prev = position != 0 && offset == list[position - 1].end_offset();
-                        let merge_next = position != list.len()
-                            && offset + data_entry_len == list[position].start_offset;
-                        match (merge_prev, merge_next) {
-                            (false, false) => {
-                                list.insert(
-                                    position,
-                                    PartialBatch {
-                                        start_offset: offset,
-                                        data,
-                                    },
-                                );
-                            }
-                            (true, false) => {
-                                list[position - 1].data.append(&mut data);
-                            }
-                            (false, true) => {
-                                data.append(&mut list[position].data);
-                                list[position] = PartialBatch {
-                                    start_offset: offset,
-                                    data,
-                                };
-                            }
-                            (true, true) => {
-                                // Merge the new data with the two around partial batches to
-                                // a single one.
-                                list[position - 1].data.append(&mut data);
-                                let mut next = list.remove(position);
-                                list[position - 1].data.append(&mut next.data);
-                            }
+                        if next < start {
+                            gaps.push((next, start));
                         }
-                        // TODO(zz): Use config here?
-                        if list.len() == 1
-                            && list[0].start_offset == 0
-                            && bytes_to_chunks(list[0].data.len()) == PORA_CHUNK_SIZE
-                        {
-                            // All data in this batch have been filled.
-                            *self = EntryBatch::Complete(list.remove(0).data);
-                        }
-                        Ok(())
+                        next = end;
+                    }
+                    if next < end_offset {
+                        gaps.push((next, end_offset));
                     }
+                    for (start, end) in gaps {
+                        insert_partial(
+                            list,
+                            start,
+                            data[(start - offset) * ENTRY_SIZE..(end - offset) * ENTRY_SIZE]
+                                .to_vec(),
+                        );
+                    }
+                }
+                if list.len() == 1
+                    && list[0].start_offset == 0
+                    && bytes_to_chunks(list[0].data.len()) == batch_size
+                {
+                    // All data in this batch have been filled.
+                    *self = EntryBatch::Complete(list.remove(0).data);
                 }
             }
         }
+        Ok(())
     }
 
-    fn truncate(&mut self, start_offset: usize) {
-        assert!(start_offset > 0 && start_offset < PORA_CHUNK_SIZE);
+    fn truncate(&mut self, start_offset: usize, batch_size: usize) {
+        assert!(start_offset > 0 && start_offset < batch_size);
         match self {
             EntryBatch::Complete(data) => {
-                data.truncate(start_offset);
+                let mut data = mem::take(data);
+                data.truncate(start_offset * ENTRY_SIZE);
+                *self = EntryBatch::Incomplete(vec![PartialBatch {
+                    start_offset: 0,
+                    data,
+                }]);
             }
             EntryBatch::Incomplete(batch_list) => {
                 let mut start_partial_batch_index = None;
@@ -515,6 +967,87 @@ impl EntryBatch {
             }
         }
     }
+
+    /// Removes the entries in the offset range (`end_offset` excluded), so that they can be
+    /// written again.
+    fn remove(&mut self, start_offset: usize, end_offset: usize) {
+        if let EntryBatch::Complete(data) = self {
+            let data = mem::take(data);
+            *self = EntryBatch::Incomplete(vec![PartialBatch {
+                start_offset: 0,
+                data,
+            }]);
+        }
+        if let EntryBatch::Incomplete(list) = self {
+            punch_hole(list, start_offset, end_offset);
+        }
+    }
+}
+
+/// Inserts the data into the ordered partial batches, and merges them with the adjacent ones. The
+/// data should not overlap with the existing ones.
+fn insert_partial(list: &mut Vec<PartialBatch>, offset: usize, mut data: Vec<u8>) {
+    let data_entry_len = bytes_to_chunks(data.len());
+    let position = match list.binary_search_by_key(&offset, |p| p.start_offset) {
+        Ok(i) => panic!("same offset with a PartialBatch at index {}", i),
+        Err(position) => position,
+    };
+    let merge_prev = position != 0 && offset == list[position - 1].end_offset();
+    let merge_next =
+        position != list.len() && offset + data_entry_len == list[position].start_offset;
+    match (merge_prev, merge_next) {
+        (false, false) => {
+            list.insert(
+                position,
+                PartialBatch {
+                    start_offset: offset,
+                    data,
+                },
+            );
+        }
+        (true, false) => {
+            list[position - 1].data.append(&mut data);
+        }
+        (false, true) => {
+            data.append(&mut list[position].data);
+            list[position] = PartialBatch {
+                start_offset: offset,
+                data,
+            };
+        }
+        (true, true) => {
+            // Merge the new data with the two around partial batches to
+            // a single one.
+            list[position - 1].data.append(&mut data);
+            let mut next = list.remove(position);
+            list[position - 1].data.append(&mut next.data);
+        }
+    }
+}
+
+/// Removes the entries in the offset range (`end_offset` excluded) from the partial batches.
+fn punch_hole(list: &mut Vec<PartialBatch>, start_offset: usize, end_offset: usize) {
+    let mut result = Vec::with_capacity(list.len() + 1);
+    for p in mem::take(list) {
+        let p_end = p.end_offset();
+        if p_end <= start_offset || p.start_offset >= end_offset {
+            result.push(p);
+            continue;
+        }
+        if p.start_offset < start_offset {
+            result.push(PartialBatch {
+                start_offset: p.start_offset,
+                data: p.data[..(start_offset - p.start_offset) * ENTRY_SIZE].to_vec(),
+            });
+        }
+        if p_end > end_offset {
+            result.push(PartialBatch {
+                start_offset: end_offset,
+                data: p.data[(end_offset - p.start_offset) * ENTRY_SIZE..].to_vec(),
+            });
+        }
+    }
+    *list = result;
 }
 
 /// Return the batch boundaries `(batch_start_index, batch_end_index)` given the index range.
@@ -0,0 +1,120 @@
+//! Copies the files between the store and the local filesystem, which bypasses the network for
+//! bulk seeding or recovery.
+
+use crate::log_store::log_manager::{bytes_to_entries, data_to_merkle_leaves, FileMerkleTree};
+use crate::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead};
+use anyhow::{anyhow, bail, Result};
+use ethereum_types::H256;
+use shared_types::{ChunkArray, Transaction, CHUNK_SIZE};
+use std::cmp;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Number of entries to read or write at a time, which is 1MB.
+const BATCH_ENTRIES: usize = 4096;
+
+/// Writes the data of a finalized file to `path`.
+pub fn export_file<S: LogStoreRead + ?Sized>(store: &S, tx_seq: u64, path: &Path) -> Result<()> {
+    let tx = get_tx(store, tx_seq)?;
+    if !store.check_tx_completed(tx_seq)? {
+        bail!("file not finalized: tx_seq={}", tx_seq);
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    let num_entries = bytes_to_entries(tx.size) as usize;
+    let mut remaining = tx.size as usize;
+    for start in (0..num_entries).step_by(BATCH_ENTRIES) {
+        let end = cmp::min(start + BATCH_ENTRIES, num_entries);
+        let chunks = store
+            .get_chunks_by_tx_and_index_range(tx_seq, start, end)?
+            .ok_or_else(|| anyhow!("entries [{}, {}) missing: tx_seq={}", start, end, tx_seq))?;
+        // the last entry is padded
+        let len = cmp::min(remaining, chunks.data.len());
+        writer.write_all(&chunks.data[..len])?;
+        remaining -= len;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the data in `path` to the store as the chunks of a known transaction, once the data
+/// matches the data root of the transaction. The file is not finalized here.
+pub fn import_file<S: LogStoreRead + LogStoreChunkWrite + ?Sized>(
+    store: &mut S,
+    tx_seq: u64,
+    path: &Path,
+) -> Result<()> {
+    let tx = get_tx(store, tx_seq)?;
+    let size = std::fs::metadata(path)?.len();
+    if size != tx.size {
+        bail!(
+            "file size mismatch, expected {}, actual {}: tx_seq={}",
+            tx.size,
+            size,
+            tx_seq
+        );
+    }
+
+    // verify all the data before writing any to the store
+    let mut leaves = vec![];
+    read_batches(path, |_, data| {
+        leaves.extend(data_to_merkle_leaves(data)?.into_iter().map(|h| h.0));
+        Ok(())
+    })?;
+    let root = FileMerkleTree::new(leaves).root();
+    if root != tx.data_merkle_root.0 {
+        bail!(
+            "data root mismatch, expected {:?}, computed {:?}: tx_seq={}",
+            tx.data_merkle_root,
+            H256(root),
+            tx_seq
+        );
+    }
+
+    read_batches(path, |start_index, data| {
+        store.put_chunks(
+            tx_seq,
+            ChunkArray {
+                data: data.to_vec(),
+                start_index,
+            },
+        )
+    })
+}
+
+fn get_tx<S: LogStoreRead + ?Sized>(store: &S, tx_seq: u64) -> Result<Transaction> {
+    store
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow!("transaction not found: tx_seq={}", tx_seq))
+}
+
+/// Reads the file in batches of entries, where the last entry is padded with zeros.
+fn read_batches(path: &Path, mut f: impl FnMut(u64, &[u8]) -> Result<()>) -> Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = vec![0u8; BATCH_ENTRIES * CHUNK_SIZE];
+    let mut start_index = 0;
+
+    loop {
+        let mut len = 0;
+        while len < buf.len() {
+            match reader.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            return Ok(());
+        }
+
+        let padded_len = bytes_to_entries(len as u64) as usize * CHUNK_SIZE;
+        buf[len..padded_len].fill(0);
+        f(start_index, &buf[..padded_len])?;
+        start_index += (padded_len / CHUNK_SIZE) as u64;
+
+        if len < buf.len() {
+            return Ok(());
+        }
+    }
+}
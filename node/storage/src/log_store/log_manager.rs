@@ -1,25 +1,30 @@
+use crate::error::Error;
+use crate::log_store::erasure::ErasureConfig;
 use crate::log_store::flow_store::{FlowConfig, FlowStore};
+use crate::log_store::pending_store::{PendingConfig, PendingStore, PendingTx};
+use crate::log_store::schema;
 use crate::log_store::tx_store::TransactionStore;
 use crate::log_store::{
-    FlowRead, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite,
+    FileQuery, FileSyncProgress, FlowRead, FlowWrite, LogStoreChunkRead, LogStoreChunkWrite,
+    LogStoreRead, LogStoreWrite, LogSyncCheckpoint, TxListFilter,
 };
+use crate::metrics;
 use crate::{try_option, IonianKeyValueDB};
 use anyhow::{anyhow, bail, Result};
 use append_merkle::{Algorithm, AppendMerkleTree, Sha3Algorithm};
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
 use kvdb_rocksdb::{Database, DatabaseConfig};
-use merkle_light::merkle::{log2_pow2, MerkleTree};
-use merkle_tree::RawLeafSha3Algorithm;
-use rayon::iter::ParallelIterator;
-use rayon::prelude::ParallelSlice;
+use merkle_light::merkle::log2_pow2;
 use shared_types::{
-    bytes_to_chunks, Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof, DataRoot, FlowProof,
-    FlowRangeProof, Transaction,
+    bytes_to_chunks, timestamp_now, Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof,
+    DataRoot, ErasureShard, FileMetadata, FlowProof, FlowRangeProof, Transaction,
 };
+pub use shared_types::{data_to_merkle_leaves, sub_merkle_tree, FileMerkleTree};
 use std::cmp;
 use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, instrument};
+use std::time::Instant;
+use tracing::{debug, info, instrument, warn};
 
 /// 256 Bytes
 pub const ENTRY_SIZE: usize = 256;
@@ -32,27 +37,52 @@ pub const COL_TX_DATA_ROOT_INDEX: u32 = 2;
 pub const COL_ENTRY_BATCH_ROOT: u32 = 3;
 pub const COL_TX_COMPLETED: u32 = 4;
 pub const COL_MISC: u32 = 5;
-pub const COL_NUM: u32 = 6;
+pub const COL_FILE_SYNC_PROGRESS: u32 = 6;
+pub const COL_SEALED_CHUNK: u32 = 7;
+pub const COL_LOG_SYNC_CHECKPOINT: u32 = 8;
+pub const COL_PARITY_SHARD: u32 = 9;
+pub const COL_TX_BY_DATA_ROOT: u32 = 10;
+pub const COL_TX_BY_SENDER: u32 = 11;
+pub const COL_ENTRY_BATCH_CHECKSUM: u32 = 12;
+pub const COL_FILE_METADATA: u32 = 13;
+pub const COL_FILE_INDEX: u32 = 14;
+pub const COL_PINNED_FILE: u32 = 15;
+pub const COL_PENDING_TX: u32 = 16;
+pub const COL_PENDING_DATA: u32 = 17;
+pub const COL_TX_BY_TIME: u32 = 18;
+pub const COL_NUM: u32 = 19;
 
 type Merkle = AppendMerkleTree<H256, Sha3Algorithm>;
 
 pub struct LogManager {
     tx_store: TransactionStore,
     flow_store: FlowStore,
+    /// The files uploaded before their transactions are synced.
+    pending_store: PendingStore,
     // TODO(zz): Refactor the in-memory merkle and in-disk storage together.
     pora_chunks_merkle: Merkle,
     /// The in-memory structure of the sub merkle tree of the last chunk.
     /// The size is always less than `PORA_CHUNK_SIZE`.
     last_chunk_merkle: Merkle,
+    /// The thread pool to generate the proofs of a range concurrently, or `None` to generate them
+    /// sequentially on the caller thread.
+    proof_pool: Option<rayon::ThreadPool>,
 }
 
 #[derive(Clone, Default)]
 pub struct LogConfig {
     pub flow: FlowConfig,
+    /// Number of threads to read the chunks and generate the proofs of a range concurrently, or
+    /// 0 to do it sequentially. The threads are dedicated, so that a busy global rayon pool does
+    /// not delay the responses.
+    pub proof_threads: usize,
+    /// Limits of the files staged before their transactions are synced.
+    pub pending: PendingConfig,
 }
 
 impl LogStoreChunkWrite for LogManager {
     fn put_chunks(&mut self, tx_seq: u64, chunks: ChunkArray) -> Result<()> {
+        let _timer = metrics::start_timer(&metrics::PUT_CHUNKS_TIMES);
         let tx = self
             .tx_store
             .get_tx_by_seq_number(tx_seq)?
@@ -67,22 +97,112 @@ impl LogStoreChunkWrite for LogManager {
             );
         }
         // TODO: Use another struct to avoid confusion.
+        let data_len = chunks.data.len();
         let mut flow_entry_array = chunks;
         flow_entry_array.start_index += tx.start_entry_index;
         self.append_entries(flow_entry_array)?;
+        metrics::inc_counter_by(&metrics::PUT_CHUNKS_BYTES, data_len as u64);
         Ok(())
     }
 
-    fn remove_all_chunks(&self, _tx_seq: u64) -> crate::error::Result<()> {
+    fn remove_all_chunks(&self, tx_seq: u64) -> crate::error::Result<()> {
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| anyhow!("remove chunks with missing tx: tx_seq={}", tx_seq))?;
+        if self.tx_store.is_pinned(&tx.data_merkle_root)? {
+            bail!("file is pinned: tx_seq={}", tx_seq);
+        }
         todo!()
     }
+
+    fn invalidate_corrupted_chunks(&self, tx_seq: u64) -> Result<Vec<(u64, u64)>> {
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| anyhow!("invalidate chunks with missing tx: tx_seq={}", tx_seq))?;
+        let num_entries = bytes_to_entries(tx.size);
+        let mut removed = Vec::new();
+        let mut start = 0;
+        // Each subtree is verified against its root in the tx. The subtrees that are not
+        // completely stored yet are skipped, and verified after they are downloaded.
+        for &(depth, root) in &tx.merkle_nodes {
+            let end = cmp::min(start + (1 << (depth - 1)), num_entries);
+            if start >= end {
+                break;
+            }
+            let (flow_start, flow_end) = (tx.start_entry_index + start, tx.start_entry_index + end);
+            let corrupted = match self.flow_store.get_entries(flow_start, flow_end) {
+                Ok(Some(data)) => DataRoot::from(sub_merkle_tree(&data.data)?.root()) != root,
+                Ok(None) => false,
+                Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::Corrupted(_))) => true,
+                Err(e) => return Err(e),
+            };
+            if corrupted {
+                self.flow_store.remove_entries(flow_start, flow_end)?;
+                removed.push((start, end));
+            }
+            start = end;
+        }
+        if !removed.is_empty() {
+            warn!(
+                "removed corrupted chunks: tx_seq={} ranges={:?}",
+                tx_seq, removed
+            );
+        }
+        Ok(removed)
+    }
+
+    fn recover_chunk(&mut self, shards: Vec<ErasureShard>) -> Result<()> {
+        let erasure = self
+            .flow_store
+            .erasure_config()
+            .ok_or_else(|| anyhow!("erasure coding is disabled"))?;
+        let first = shards
+            .first()
+            .ok_or_else(|| anyhow!("no shards to recover"))?;
+        let (chunk_index, proof) = (first.chunk_index, first.proof.clone());
+        // The first chunk is never encoded since its first entry is not stored.
+        if chunk_index == 0 || chunk_index >= self.last_chunk_start_index() / PORA_CHUNK_SIZE as u64
+        {
+            bail!("recover incomplete chunk: chunk_index={}", chunk_index);
+        }
+
+        let mut shard_list = vec![None; erasure.num_shards()];
+        for shard in shards {
+            if shard.chunk_index != chunk_index {
+                bail!(
+                    "recover with shards of different chunks: {} {}",
+                    chunk_index,
+                    shard.chunk_index
+                );
+            }
+            let slot = shard_list
+                .get_mut(shard.shard_index as usize)
+                .ok_or_else(|| anyhow!("shard index out of bound: {}", shard.shard_index))?;
+            *slot = Some(shard.data);
+        }
+        let data = erasure.reconstruct(shard_list)?;
+
+        // The chunk is written as a whole, so it must be validated before overwriting any data.
+        let start_index = chunk_index * PORA_CHUNK_SIZE as u64;
+        proof.validate::<Sha3Algorithm>(&data_to_merkle_leaves(&data)?, start_index as usize)?;
+        if !self.pora_chunks_merkle.check_root(&proof.root()) {
+            bail!(
+                "recovered chunk with unknown root: chunk_index={}",
+                chunk_index
+            );
+        }
+        self.append_entries(ChunkArray { data, start_index })
+    }
 }
 
 impl LogStoreWrite for LogManager {
     #[instrument(skip(self))]
     fn put_tx(&mut self, tx: Transaction) -> Result<()> {
         debug!("put_tx: tx={:?}", tx);
-        // TODO(zz): Should we validate received tx?
+        let _timer = metrics::start_timer(&metrics::PUT_TX_TIMES);
+        tx.validate()?;
         self.append_subtree_list(tx.merkle_nodes.clone())?;
         // TODO(zz): tx_store and the merkle tree are not updated atomically.
         self.commit(tx.seq)?;
@@ -97,20 +217,104 @@ impl LogStoreWrite for LogManager {
             .ok_or_else(|| anyhow!("finalize_tx with tx missing: tx_seq={}", tx_seq))?;
         let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
         // TODO: Check completeness without loading all data in memory.
-        // TODO: Should we double check the tx merkle root?
-        if self
+        let data = match self
             .flow_store
             .get_entries(tx.start_entry_index, tx_end_index)?
-            .is_some()
         {
-            self.tx_store.finalize_tx(tx_seq)
-        } else {
-            bail!("finalize tx with data missing: tx_seq={}", tx_seq)
+            Some(data) => data,
+            None => bail!("finalize tx with data missing: tx_seq={}", tx_seq),
+        };
+        // Verify the data root, so that a file is never finalized with corrupted data.
+        let root: DataRoot = sub_merkle_tree(&data.data)?.root().into();
+        if root != tx.data_merkle_root {
+            bail!(
+                "finalize tx with data root mismatch: tx_seq={} expected={:?} computed={:?}",
+                tx_seq,
+                tx.data_merkle_root,
+                root
+            );
         }
+        self.tx_store.finalize_tx(tx_seq)?;
+        metrics::inc_counter(&metrics::FINALIZED_TX_COUNT);
+        Ok(())
+    }
+
+    fn put_log_sync_checkpoint(&self, checkpoint: LogSyncCheckpoint) -> Result<()> {
+        self.tx_store.put_log_sync_checkpoint(&checkpoint)
+    }
+
+    fn put_file_sync_progress(&self, tx_seq: u64, progress: &FileSyncProgress) -> Result<()> {
+        self.tx_store.put_file_sync_progress(tx_seq, progress)
+    }
+
+    fn delete_file_sync_progress(&self, tx_seq: u64) -> Result<()> {
+        self.tx_store.delete_file_sync_progress(tx_seq)
     }
 
-    fn put_sync_progress(&self, progress: (u64, H256)) -> Result<()> {
-        self.tx_store.put_progress(progress)
+    fn put_file_metadata(&self, tx_seq: u64, metadata: &FileMetadata) -> Result<()> {
+        self.tx_store.put_file_metadata(tx_seq, metadata)
+    }
+
+    fn pin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        self.tx_store.pin_file(data_root)
+    }
+
+    fn unpin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        self.tx_store.unpin_file(data_root)
+    }
+
+    fn put_tx_pending(&self, data_root: &DataRoot, size: u64, announced: bool) -> Result<bool> {
+        self.pending_store.put_tx(data_root, size, announced)
+    }
+
+    fn prune_pending_txs(&self) -> Result<usize> {
+        self.pending_store.prune(timestamp_now())
+    }
+
+    fn put_pending_chunks(&self, data_root: &DataRoot, chunks: ChunkArray) -> Result<bool> {
+        self.pending_store.put_chunks(data_root, chunks)
+    }
+
+    fn promote_tx(&mut self, tx_seq: u64) -> Result<bool> {
+        let tx = self
+            .tx_store
+            .get_tx_by_seq_number(tx_seq)?
+            .ok_or_else(|| anyhow!("promote tx with tx missing: tx_seq={}", tx_seq))?;
+        let data_root = tx.data_merkle_root;
+        match self.pending_store.get_tx(&data_root)? {
+            Some(pending) if pending.verified => {
+                if pending.size != tx.size {
+                    // staged with a wrong size announced by peers, so synced as usual
+                    warn!(
+                        "drop pending tx with size mismatch: tx_seq={} expected={} staged={}",
+                        tx_seq, tx.size, pending.size
+                    );
+                    self.pending_store.delete_tx(&data_root)?;
+                    return Ok(false);
+                }
+            }
+            _ => return Ok(false),
+        }
+        if self.tx_store.check_tx_completed(tx_seq)? {
+            // uploaded again after the transaction is synced
+            self.pending_store.delete_tx(&data_root)?;
+            return Ok(false);
+        }
+
+        // The staged data is verified against the data root, so it is spliced into the flow
+        // directly, where the last entry is padded.
+        for mut segment in self.pending_store.get_segments(&data_root)? {
+            segment.start_index += tx.start_entry_index;
+            self.append_entries(segment)?;
+        }
+        self.finalize_tx(tx_seq)?;
+        self.pending_store.delete_tx(&data_root)?;
+        metrics::inc_counter(&metrics::PROMOTED_TX_COUNT);
+        debug!(
+            "promoted pending tx: tx_seq={} data_root={:?}",
+            tx_seq, data_root
+        );
+        Ok(true)
     }
 
     fn revert_to(&mut self, tx_seq: u64) -> Result<()> {
@@ -153,6 +357,26 @@ impl LogStoreChunkRead for LogManager {
         Ok(Some(tx_chunk))
     }
 
+    fn prefetch_chunks(
+        &self,
+        tx_seq: u64,
+        index_start: usize,
+        index_end: usize,
+    ) -> crate::error::Result<()> {
+        let tx = match self.get_tx_by_seq_number(tx_seq)? {
+            Some(tx) => tx,
+            None => return Ok(()),
+        };
+        let index_end = cmp::min(index_end, bytes_to_chunks(tx.size as usize));
+        if index_start >= index_end {
+            return Ok(());
+        }
+        self.flow_store.prefetch_entries(
+            tx.start_entry_index + index_start as u64,
+            tx.start_entry_index + index_end as u64,
+        )
+    }
+
     fn get_chunk_by_data_root_and_index(
         &self,
         _data_root: &DataRoot,
@@ -171,8 +395,112 @@ impl LogStoreChunkRead for LogManager {
         self.get_chunks_by_tx_and_index_range(tx_seq, index_start, index_end)
     }
 
-    fn get_chunk_index_list(&self, _tx_seq: u64) -> crate::error::Result<Vec<usize>> {
-        todo!()
+    fn get_chunks_by_flow_index_range(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> crate::error::Result<Option<ChunkArray>> {
+        self.flow_store.get_entries(index_start, index_end)
+    }
+
+    fn get_chunks_with_proof_by_flow_index_range(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> crate::error::Result<Option<ChunkArrayWithProof>> {
+        let (chunks, proof) = self.join(
+            || self.get_chunks_by_flow_index_range(index_start, index_end),
+            || self.gen_range_proof(index_start, index_end),
+        );
+        let chunks = try_option!(chunks?);
+        Ok(Some(ChunkArrayWithProof {
+            chunks,
+            proof: proof?,
+        }))
+    }
+
+    fn get_sealed_chunk(&self, chunk_index: u64) -> crate::error::Result<Option<ChunkArray>> {
+        self.flow_store.get_sealed_chunk(chunk_index)
+    }
+
+    fn get_erasure_config(&self) -> Option<ErasureConfig> {
+        self.flow_store.erasure_config().copied()
+    }
+
+    fn get_erasure_shard(
+        &self,
+        chunk_index: u64,
+        shard_index: usize,
+    ) -> crate::error::Result<Option<ErasureShard>> {
+        let data = try_option!(self.flow_store.get_shard(chunk_index, shard_index)?);
+        let start_index = chunk_index * PORA_CHUNK_SIZE as u64;
+        let left_proof = self.gen_proof(start_index)?;
+        let right_proof = self.gen_proof(start_index + PORA_CHUNK_SIZE as u64 - 1)?;
+        Ok(Some(ErasureShard {
+            chunk_index,
+            shard_index: shard_index as u32,
+            data,
+            proof: FlowRangeProof {
+                left_proof,
+                right_proof,
+            },
+        }))
+    }
+
+    fn get_chunk_index_list(&self, tx_seq: u64) -> crate::error::Result<Vec<usize>> {
+        Ok(self
+            .get_chunk_ranges(tx_seq)?
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(start, end)| start..end)
+            .collect())
+    }
+
+    fn get_chunk_ranges(&self, tx_seq: u64) -> crate::error::Result<Option<Vec<(usize, usize)>>> {
+        let tx = try_option!(self.tx_store.get_tx_by_seq_number(tx_seq)?);
+        let tx_end_index = tx.start_entry_index + bytes_to_entries(tx.size);
+        let ranges = self
+            .flow_store
+            .get_available_entry_ranges(tx.start_entry_index, tx_end_index)?
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    (start - tx.start_entry_index) as usize,
+                    (end - tx.start_entry_index) as usize,
+                )
+            })
+            .collect();
+        Ok(Some(ranges))
+    }
+
+    fn get_available_ranges(
+        &self,
+        tx_seq: u64,
+    ) -> crate::error::Result<Option<Vec<(usize, usize)>>> {
+        let tx = try_option!(self.tx_store.get_tx_by_seq_number(tx_seq)?);
+        let num_entries = bytes_to_entries(tx.size);
+        if self.tx_store.check_tx_completed(tx_seq)? {
+            return Ok(Some(vec![(0, num_entries as usize)]));
+        }
+
+        // Proofs could only be generated from complete PoRA chunks before finalized.
+        let batch_size = PORA_CHUNK_SIZE as u64;
+        let ranges = try_option!(self.get_chunk_ranges(tx_seq)?)
+            .into_iter()
+            .filter_map(|(start, end)| {
+                let start = tx.start_entry_index + start as u64;
+                let end = tx.start_entry_index + end as u64;
+                let start = (start + batch_size - 1) / batch_size * batch_size;
+                let end = end / batch_size * batch_size;
+                (start < end).then(|| {
+                    (
+                        (start - tx.start_entry_index) as usize,
+                        (end - tx.start_entry_index) as usize,
+                    )
+                })
+            })
+            .collect();
+        Ok(Some(ranges))
     }
 }
 
@@ -185,6 +513,56 @@ impl LogStoreRead for LogManager {
         self.tx_store.get_tx_seq_by_data_root(data_root)
     }
 
+    fn get_tx_by_data_root(
+        &self,
+        data_root: &DataRoot,
+    ) -> crate::error::Result<Option<Transaction>> {
+        Ok(self
+            .tx_store
+            .get_txs_by_data_root(data_root)?
+            .into_iter()
+            .next())
+    }
+
+    fn get_txs_by_data_root(&self, data_root: &DataRoot) -> crate::error::Result<Vec<Transaction>> {
+        self.tx_store.get_txs_by_data_root(data_root)
+    }
+
+    fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: &TxListFilter,
+    ) -> crate::error::Result<Vec<Transaction>> {
+        self.tx_store.get_tx_list(start_seq, limit, filter)
+    }
+
+    fn search_files(
+        &self,
+        query: &FileQuery,
+        start_seq: u64,
+        limit: usize,
+    ) -> crate::error::Result<Vec<Transaction>> {
+        self.tx_store.search_files(query, start_seq, limit)
+    }
+
+    fn get_txs_by_sender(
+        &self,
+        sender: &Address,
+        skip: usize,
+        limit: usize,
+    ) -> crate::error::Result<Vec<Transaction>> {
+        self.tx_store.get_txs_by_sender(sender, skip, limit)
+    }
+
+    fn get_txs_in_time_range(
+        &self,
+        start_time: u64,
+        end_time: u64,
+    ) -> crate::error::Result<Vec<Transaction>> {
+        self.tx_store.get_txs_in_time_range(start_time, end_time)
+    }
+
     fn get_chunk_with_proof_by_tx_and_index(
         &self,
         tx_seq: u64,
@@ -209,16 +587,19 @@ impl LogStoreRead for LogManager {
         index_end: usize,
     ) -> crate::error::Result<Option<ChunkArrayWithProof>> {
         let tx = try_option!(self.tx_store.get_tx_by_seq_number(tx_seq)?);
-        let chunks =
-            try_option!(self.get_chunks_by_tx_and_index_range(tx_seq, index_start, index_end)?);
-        let left_proof = self.gen_proof(tx.start_entry_index + index_start as u64)?;
-        let right_proof = self.gen_proof(tx.start_entry_index + index_end as u64 - 1)?;
+        let (chunks, proof) = self.join(
+            || self.get_chunks_by_tx_and_index_range(tx_seq, index_start, index_end),
+            || {
+                self.gen_range_proof(
+                    tx.start_entry_index + index_start as u64,
+                    tx.start_entry_index + index_end as u64,
+                )
+            },
+        );
+        let chunks = try_option!(chunks?);
         Ok(Some(ChunkArrayWithProof {
             chunks,
-            proof: FlowRangeProof {
-                left_proof,
-                right_proof,
-            },
+            proof: proof?,
         }))
     }
 
@@ -230,21 +611,67 @@ impl LogStoreRead for LogManager {
         let tx = self
             .get_tx_by_seq_number(tx_seq)?
             .ok_or_else(|| anyhow!("tx missing"))?;
-        let leaves = data_to_merkle_leaves(&data.chunks.data)?;
-        data.proof.validate::<Sha3Algorithm>(
-            &leaves,
-            (data.chunks.start_index + tx.start_entry_index) as usize,
-        )?;
-        Ok(self.pora_chunks_merkle.check_root(&data.proof.root()))
+        let root = data.validate(tx.start_entry_index)?;
+        Ok(self.check_flow_root(&root))
+    }
+
+    fn check_flow_root(&self, root: &DataRoot) -> bool {
+        self.pora_chunks_merkle.check_root(root)
+    }
+
+    fn get_context(&self) -> Result<(DataRoot, u64)> {
+        Ok((*self.pora_chunks_merkle.root(), self.flow_length()?))
     }
 
     fn get_sync_progress(&self) -> Result<Option<(u64, H256)>> {
         self.tx_store.get_progress()
     }
 
+    fn get_log_sync_checkpoints(&self) -> Result<Vec<LogSyncCheckpoint>> {
+        self.tx_store.get_log_sync_checkpoints()
+    }
+
+    fn get_all_file_sync_progress(&self) -> Result<Vec<(u64, FileSyncProgress)>> {
+        self.tx_store.get_all_file_sync_progress()
+    }
+
+    fn get_file_metadata(&self, tx_seq: u64) -> Result<Option<FileMetadata>> {
+        self.tx_store.get_file_metadata(tx_seq)
+    }
+
+    fn is_pinned(&self, data_root: &DataRoot) -> Result<bool> {
+        self.tx_store.is_pinned(data_root)
+    }
+
+    fn get_pinned_files(&self) -> Result<Vec<DataRoot>> {
+        self.tx_store.get_pinned_files()
+    }
+
+    fn get_pending_tx(&self, data_root: &DataRoot) -> Result<Option<PendingTx>> {
+        self.pending_store.get_tx(data_root)
+    }
+
+    fn get_pending_chunks(
+        &self,
+        data_root: &DataRoot,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArray>> {
+        self.pending_store
+            .get_chunks(data_root, index_start, index_end)
+    }
+
     fn next_tx_seq(&self) -> Result<u64> {
         self.tx_store.next_tx_seq()
     }
+
+    fn get_flow_root_by_tx_seq(&self, tx_seq: u64) -> Result<Option<DataRoot>> {
+        Ok(self.pora_chunks_merkle.tx_seq_root(tx_seq).copied())
+    }
+
+    fn flow_length(&self) -> Result<u64> {
+        Ok(self.last_chunk_start_index() + self.last_chunk_merkle.leaves() as u64)
+    }
 }
 
 impl LogManager {
@@ -255,15 +682,49 @@ impl LogManager {
         Self::new(db, config)
     }
 
+    /// Compacts all the columns of the RocksDB store at `path`, which should not be opened
+    /// elsewhere.
+    pub fn compact_rocksdb(path: impl AsRef<Path>) -> Result<()> {
+        let options = rocksdb::Options::default();
+        let columns = rocksdb::DB::list_cf(&options, &path)?;
+        let db = rocksdb::DB::open_cf(&options, &path, &columns)?;
+        for column in &columns {
+            if let Some(cf) = db.cf_handle(column) {
+                db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+            }
+        }
+        Ok(())
+    }
+
     pub fn memorydb(config: LogConfig) -> Result<Self> {
         let db = Arc::new(kvdb_memorydb::create(COL_NUM));
         Self::new(db, config)
     }
 
     fn new(db: Arc<dyn IonianKeyValueDB>, config: LogConfig) -> Result<Self> {
+        schema::migrate(db.as_ref())?;
         let tx_store = TransactionStore::new(db.clone());
-        let flow_store = FlowStore::new(db, config.flow);
+        let pending_store = PendingStore::new(db.clone(), config.pending);
+        let flow_store = FlowStore::new(db, config.flow)?;
+        let proof_pool = if config.proof_threads == 0 {
+            None
+        } else {
+            Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(config.proof_threads)
+                    .thread_name(|i| format!("proof-{}", i))
+                    .build()?,
+            )
+        };
+        let timer = metrics::start_timer(&metrics::STARTUP_MERKLE_TIMES);
+        let started_at = Instant::now();
+        info!("Reconstructing the flow merkle tree");
         let chunk_roots = flow_store.get_chunk_root_list()?;
+        info!(
+            num_chunks = chunk_roots.len(),
+            elapsed = ?started_at.elapsed(),
+            "Loaded the chunk roots, building the tree",
+        );
         let next_tx_seq = tx_store.next_tx_seq()?;
         let start_tx_seq = if next_tx_seq > 0 {
             Some(next_tx_seq - 1)
@@ -340,11 +801,19 @@ impl LogManager {
         if last_chunk_merkle.leaves() != 0 {
             pora_chunks_merkle.append(*last_chunk_merkle.root());
         }
+        metrics::stop_timer(timer);
+        info!(
+            num_chunks = pora_chunks_merkle.leaves(),
+            elapsed = ?started_at.elapsed(),
+            "Flow merkle tree reconstructed",
+        );
         let mut log_manager = Self {
             tx_store,
             flow_store,
+            pending_store,
             pora_chunks_merkle,
             last_chunk_merkle,
+            proof_pool,
         };
         log_manager.try_initialize();
         Ok(log_manager)
@@ -358,48 +827,106 @@ impl LogManager {
         }
     }
 
+    /// Runs the two closures concurrently in the proof pool if configured, or sequentially.
+    fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        match &self.proof_pool {
+            Some(pool) => pool.install(|| rayon::join(a, b)),
+            None => (a(), b()),
+        }
+    }
+
+    /// Generates the proofs of the first and the last entries of a range (`index_end` excluded).
+    fn gen_range_proof(&self, index_start: u64, index_end: u64) -> Result<FlowRangeProof> {
+        if index_end <= index_start {
+            bail!(
+                "invalid entry index: start={} end={}",
+                index_start,
+                index_end
+            );
+        }
+        let chunk_index = index_start / PORA_CHUNK_SIZE as u64;
+        if (index_end - 1) / PORA_CHUNK_SIZE as u64 == chunk_index {
+            // Both proofs are within the same PoRA chunk, so its sub-tree is only built once.
+            let top_proof = self.pora_chunks_merkle.gen_proof(chunk_index as usize)?;
+            let chunk_merkle = self.load_chunk_merkle(chunk_index, index_start)?;
+            let chunk_merkle = chunk_merkle.as_ref().unwrap_or(&self.last_chunk_merkle);
+            let left_proof = chunk_merkle.gen_proof(index_start as usize % PORA_CHUNK_SIZE)?;
+            let right_proof = chunk_merkle.gen_proof((index_end - 1) as usize % PORA_CHUNK_SIZE)?;
+            return Ok(FlowRangeProof {
+                left_proof: entry_proof(&top_proof, &left_proof)?,
+                right_proof: entry_proof(&top_proof, &right_proof)?,
+            });
+        }
+        let (left_proof, right_proof) = self.join(
+            || self.gen_proof(index_start),
+            || self.gen_proof(index_end - 1),
+        );
+        Ok(FlowRangeProof {
+            left_proof: left_proof?,
+            right_proof: right_proof?,
+        })
+    }
+
     fn gen_proof(&self, flow_index: u64) -> Result<FlowProof> {
         let chunk_index = flow_index / PORA_CHUNK_SIZE as u64;
         let top_proof = self.pora_chunks_merkle.gen_proof(chunk_index as usize)?;
+        let chunk_merkle = self.load_chunk_merkle(chunk_index, flow_index)?;
+        let sub_proof = chunk_merkle
+            .as_ref()
+            .unwrap_or(&self.last_chunk_merkle)
+            .gen_proof(flow_index as usize % PORA_CHUNK_SIZE)?;
+        entry_proof(&top_proof, &sub_proof)
+    }
 
+    /// Builds the sub-tree of a PoRA chunk from its data, or returns `None` if the chunk is the
+    /// last one, whose sub-tree is kept in memory as `last_chunk_merkle`.
+    fn load_chunk_merkle(&self, chunk_index: u64, flow_index: u64) -> Result<Option<Merkle>> {
         // TODO(zz): Maybe we can decide that all proofs are at the PoRA chunk level, so
         // we do not need to maintain the proof at the entry level below.
         // Condition (self.last_chunk_merkle.leaves() == 0): When last chunk size is exactly PORA_CHUNK_SIZE, proof should be generated from flow data, as last_chunk_merkle.leaves() is zero at this time
-        let sub_proof = if chunk_index as usize != self.pora_chunks_merkle.leaves() - 1
-            || self.last_chunk_merkle.leaves() == 0
+        if chunk_index as usize == self.pora_chunks_merkle.leaves() - 1
+            && self.last_chunk_merkle.leaves() != 0
         {
-            // FIXME(zz）: Even if the data is incomplete, given the intermediate merkle roots
-            // it's still possible to generate needed proofs. These merkle roots may be stored
-            // within `EntryBatch::Incomplete`.
-            let pora_chunk = self
-                .flow_store
-                .get_entries(
-                    chunk_index * PORA_CHUNK_SIZE as u64,
-                    (chunk_index + 1) * PORA_CHUNK_SIZE as u64,
-                )?
-                .ok_or_else(|| {
-                    anyhow!(
-                        "data incomplete for generating proof of index {}",
-                        flow_index
-                    )
-                })?;
+            return Ok(None);
+        }
+        // FIXME(zz）: Even if the data is incomplete, given the intermediate merkle roots
+        // it's still possible to generate needed proofs. These merkle roots may be stored
+        // within `EntryBatch::Incomplete`.
+        // The sealed chunk is read with a single lookup if available.
+        let pora_chunk = match self.flow_store.get_sealed_chunk(chunk_index)? {
+            Some(chunk) => Some(chunk),
+            None => self.flow_store.get_entries(
+                chunk_index * PORA_CHUNK_SIZE as u64,
+                (chunk_index + 1) * PORA_CHUNK_SIZE as u64,
+            )?,
+        }
+        .ok_or_else(|| {
+            anyhow!(
+                "data incomplete for generating proof of index {}",
+                flow_index
+            )
+        })?;
 
-            // Tempfix: for first chunk, its data is not complete, the hash of first entry is H256::zero()
-            let leaves =
-                if chunk_index == 0 && pora_chunk.data.len() / ENTRY_SIZE == PORA_CHUNK_SIZE - 1 {
-                    let mut leaves = vec![H256::zero()];
-                    leaves.append(&mut data_to_merkle_leaves(&pora_chunk.data)?);
-                    leaves
-                } else {
-                    data_to_merkle_leaves(&pora_chunk.data)?
-                };
-            let chunk_merkle = Merkle::new_with_depth(leaves, log2_pow2(PORA_CHUNK_SIZE) + 1, None);
-            chunk_merkle.gen_proof(flow_index as usize % PORA_CHUNK_SIZE)?
-        } else {
-            self.last_chunk_merkle
-                .gen_proof(flow_index as usize % PORA_CHUNK_SIZE)?
-        };
-        entry_proof(&top_proof, &sub_proof)
+        // Tempfix: for first chunk, its data is not complete, the hash of first entry is H256::zero()
+        let leaves =
+            if chunk_index == 0 && pora_chunk.data.len() / ENTRY_SIZE == PORA_CHUNK_SIZE - 1 {
+                let mut leaves = vec![H256::zero()];
+                leaves.append(&mut data_to_merkle_leaves(&pora_chunk.data)?);
+                leaves
+            } else {
+                data_to_merkle_leaves(&pora_chunk.data)?
+            };
+        Ok(Some(Merkle::new_with_depth(
+            leaves,
+            log2_pow2(PORA_CHUNK_SIZE) + 1,
+            None,
+        )))
     }
 
     #[instrument(skip(self))]
@@ -606,9 +1133,6 @@ impl LogManager {
     }
 }
 
-/// This represents the subtree of a chunk or the whole data merkle tree.
-pub type FileMerkleTree = MerkleTree<[u8; 32], RawLeafSha3Algorithm>;
-
 #[macro_export]
 macro_rules! try_option {
     ($r: ident) => {
@@ -625,26 +1149,6 @@ macro_rules! try_option {
     };
 }
 
-/// This should be called with input checked.
-pub fn sub_merkle_tree(leaf_data: &[u8]) -> Result<FileMerkleTree> {
-    Ok(FileMerkleTree::new(
-        data_to_merkle_leaves(leaf_data)?
-            .into_iter()
-            .map(|h| h.0)
-            .collect::<Vec<[u8; 32]>>(),
-    ))
-}
-
-pub fn data_to_merkle_leaves(leaf_data: &[u8]) -> Result<Vec<H256>> {
-    if leaf_data.len() % ENTRY_SIZE != 0 {
-        bail!("merkle_tree: unmatch data size");
-    }
-    Ok(leaf_data
-        .par_chunks_exact(ENTRY_SIZE)
-        .map(Sha3Algorithm::leaf)
-        .collect())
-}
-
 pub fn bytes_to_entries(size_bytes: u64) -> u64 {
     if size_bytes % ENTRY_SIZE as u64 == 0 {
         size_bytes / ENTRY_SIZE as u64
@@ -1,14 +1,31 @@
-use ethereum_types::H256;
-use shared_types::{Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof, DataRoot, Transaction};
+use ethereum_types::{Address, H256};
+use shared_types::{
+    Chunk, ChunkArray, ChunkArrayWithProof, ChunkWithProof, DataRoot, ErasureShard, FileMetadata,
+    Transaction,
+};
 
 use crate::error::Result;
 
+pub mod erasure;
+mod file_index;
 mod flow_store;
+pub mod local_file;
 pub mod log_manager;
+mod pending_store;
+pub mod schema;
+mod sealed_file;
 #[cfg(test)]
 mod tests;
 mod tx_store;
 
+pub use erasure::ErasureConfig;
+pub use file_index::FileQuery;
+pub use flow_store::FlowConfig;
+pub use pending_store::{PendingConfig, PendingTx};
+pub use tx_store::{
+    FileSyncPeer, FileSyncProgress, LogSyncCheckpoint, TxListFilter, MAX_LOG_SYNC_CHECKPOINTS,
+};
+
 /// The trait to read the transactions already appended to the log.
 ///
 /// Implementation Rationale:
@@ -20,6 +37,44 @@ pub trait LogStoreRead: LogStoreChunkRead {
     /// Get a transaction by the data root of its data.
     fn get_tx_seq_by_data_root(&self, data_root: &DataRoot) -> Result<Option<u64>>;
 
+    /// Get the first transaction of the data root, which is read at once along with the index.
+    fn get_tx_by_data_root(&self, data_root: &DataRoot) -> Result<Option<Transaction>>;
+
+    /// Get all the transactions of the data root in ascending order of sequence numbers, since
+    /// the same data could be submitted more than once.
+    fn get_txs_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<Transaction>>;
+
+    /// Pages through the transactions from `start_seq` in a single scan, and returns at most
+    /// `limit` ones that match the filter.
+    fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: &TxListFilter,
+    ) -> Result<Vec<Transaction>>;
+
+    /// Searches the files that match the query from `start_seq` by the file index, and returns
+    /// at most `limit` ones in ascending order of sequence numbers.
+    fn search_files(
+        &self,
+        query: &FileQuery,
+        start_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<Transaction>>;
+
+    /// Get the transactions submitted by `sender` in ascending order of sequence numbers, which
+    /// skips the first `skip` ones and returns at most `limit` ones.
+    fn get_txs_by_sender(
+        &self,
+        sender: &Address,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>>;
+
+    /// Get the transactions whose block timestamps are in `[start_time, end_time)`, in ascending
+    /// order of block timestamps.
+    fn get_txs_in_time_range(&self, start_time: u64, end_time: u64) -> Result<Vec<Transaction>>;
+
     fn get_chunk_with_proof_by_tx_and_index(
         &self,
         tx_seq: u64,
@@ -37,9 +92,51 @@ pub trait LogStoreRead: LogStoreChunkRead {
 
     fn next_tx_seq(&self) -> Result<u64>;
 
+    /// Get the number of entries in the flow, including the padding between transactions.
+    fn flow_length(&self) -> Result<u64>;
+
+    /// Get the flow root and the flow length, against which the mining proofs are generated.
+    fn get_context(&self) -> Result<(DataRoot, u64)>;
+
+    /// Get the flow root right after the transaction `tx_seq` is appended, which is only kept for
+    /// the transactions appended since the store is opened.
+    fn get_flow_root_by_tx_seq(&self, tx_seq: u64) -> Result<Option<DataRoot>>;
+
+    /// Get the latest block synced from the log contract.
     fn get_sync_progress(&self) -> Result<Option<(u64, H256)>>;
 
+    /// Get the checkpoints of the recently synced blocks in ascending order.
+    fn get_log_sync_checkpoints(&self) -> Result<Vec<LogSyncCheckpoint>>;
+
     fn validate_range_proof(&self, tx_seq: u64, data: &ChunkArrayWithProof) -> Result<bool>;
+
+    /// Whether `root` is a root of the flow, against which the proof roots returned by
+    /// [`ChunkArrayWithProof::validate`] are checked.
+    fn check_flow_root(&self, root: &DataRoot) -> bool;
+
+    /// Get the persisted progress of all files being synced from peers.
+    fn get_all_file_sync_progress(&self) -> Result<Vec<(u64, FileSyncProgress)>>;
+
+    /// Get the user metadata of a file set at upload time.
+    fn get_file_metadata(&self, tx_seq: u64) -> Result<Option<FileMetadata>>;
+
+    /// Whether the file of the data root is pinned, which should never be pruned.
+    fn is_pinned(&self, data_root: &DataRoot) -> Result<bool>;
+
+    /// Get the data roots of all the pinned files.
+    fn get_pinned_files(&self) -> Result<Vec<DataRoot>>;
+
+    /// Get the file of the data root staged before its transaction is synced.
+    fn get_pending_tx(&self, data_root: &DataRoot) -> Result<Option<PendingTx>>;
+
+    /// Get the staged chunks of a file in the range (`index_end` excluded), which are not proved
+    /// until the whole file is staged. Returns `None` if any of them is not staged.
+    fn get_pending_chunks(
+        &self,
+        data_root: &DataRoot,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArray>>;
 }
 
 pub trait LogStoreChunkRead {
@@ -68,7 +165,52 @@ pub trait LogStoreChunkRead {
         index_end: usize,
     ) -> Result<Option<ChunkArray>>;
 
+    /// Hint that a range of chunks (`index_end` excluded) will be read soon, e.g. the next pages
+    /// of a streaming download, so that they are loaded into the caches ahead. The chunks that
+    /// are not available are ignored.
+    fn prefetch_chunks(&self, tx_seq: u64, index_start: usize, index_end: usize) -> Result<()>;
+
+    /// Get a list of continuous chunks by a flow index range (`index_end` excluded), which may
+    /// span multiple transactions. Used by mining to read the recall chunks.
+    fn get_chunks_by_flow_index_range(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArray>>;
+
+    /// Get a list of continuous chunks by a flow index range (`index_end` excluded) along with
+    /// the range proof against the flow root.
+    fn get_chunks_with_proof_by_flow_index_range(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArrayWithProof>>;
+
+    /// Get the PoRA chunk at `chunk_index` in the sealed layout, which is read at once rather than
+    /// reassembled from entry batches. Returns `None` if the chunk is not sealed.
+    fn get_sealed_chunk(&self, chunk_index: u64) -> Result<Option<ChunkArray>>;
+
+    /// Get the erasure coding config of the complete PoRA chunks, or `None` if disabled.
+    fn get_erasure_config(&self) -> Option<ErasureConfig>;
+
+    /// Get a data or parity shard of the complete PoRA chunk at `chunk_index`, along with the
+    /// range proof of the chunk. Returns `None` if the chunk is not complete.
+    fn get_erasure_shard(
+        &self,
+        chunk_index: u64,
+        shard_index: usize,
+    ) -> Result<Option<ErasureShard>>;
+
     fn get_chunk_index_list(&self, tx_seq: u64) -> Result<Vec<usize>>;
+
+    /// Get the chunk index ranges (`end` excluded) of a transaction that are stored, in increasing
+    /// order. Return `None` if the transaction does not exist.
+    fn get_chunk_ranges(&self, tx_seq: u64) -> Result<Option<Vec<(usize, usize)>>>;
+
+    /// Get the chunk index ranges (`end` excluded) of a transaction that could be served to peers
+    /// with proofs, in increasing order. This is the whole file if finalized, or the stored ranges
+    /// aligned to complete PoRA chunks otherwise. Return `None` if the transaction does not exist.
+    fn get_available_ranges(&self, tx_seq: u64) -> Result<Option<Vec<(usize, usize)>>>;
 }
 
 pub trait LogStoreWrite: LogStoreChunkWrite {
@@ -83,8 +225,41 @@ pub trait LogStoreWrite: LogStoreChunkWrite {
     /// the caller is supposed to track chunk statuses and call this after storing all the chunks.
     fn finalize_tx(&self, tx_seq: u64) -> Result<()>;
 
-    /// Store the progress of synced block number and its hash.
-    fn put_sync_progress(&self, progress: (u64, H256)) -> Result<()>;
+    /// Store the checkpoint of the latest synced block, which replaces the checkpoints of later
+    /// blocks.
+    fn put_log_sync_checkpoint(&self, checkpoint: LogSyncCheckpoint) -> Result<()>;
+
+    /// Store the progress of syncing a file from peers.
+    fn put_file_sync_progress(&self, tx_seq: u64, progress: &FileSyncProgress) -> Result<()>;
+
+    /// Delete the progress of syncing a file, e.g. when the sync completed.
+    fn delete_file_sync_progress(&self, tx_seq: u64) -> Result<()>;
+
+    /// Store the user metadata of a file, which replaces the old one if any.
+    fn put_file_metadata(&self, tx_seq: u64, metadata: &FileMetadata) -> Result<()>;
+
+    /// Pin the file of the data root to protect it from pruning, regardless of the retention
+    /// policy. Returns `false` if already pinned.
+    fn pin_file(&self, data_root: &DataRoot) -> Result<bool>;
+
+    /// Unpin the file of the data root. Returns `false` if not pinned.
+    fn unpin_file(&self, data_root: &DataRoot) -> Result<bool>;
+
+    /// Start to stage the file of the data root, which is uploaded before its transaction is
+    /// synced from the blockchain, or `announced` by peers. Returns `false` if already staged.
+    fn put_tx_pending(&self, data_root: &DataRoot, size: u64, announced: bool) -> Result<bool>;
+
+    /// Remove the staged files not promoted in time. Returns the number of removed files.
+    fn prune_pending_txs(&self) -> Result<usize>;
+
+    /// Stage the chunks of a file started with `put_tx_pending`, which is merkleized and checked
+    /// against the data root once all chunks are staged. Returns whether the file is completely
+    /// staged and verified.
+    fn put_pending_chunks(&self, data_root: &DataRoot, chunks: ChunkArray) -> Result<bool>;
+
+    /// Splice the staged file of the transaction into the flow and finalize the transaction,
+    /// once the transaction is synced. Returns `false` if the file is not completely staged.
+    fn promote_tx(&mut self, tx_seq: u64) -> Result<bool>;
 
     /// Revert the log state to a given tx seq.
     /// This is needed when transactions are reverted because of chain reorg.
@@ -98,8 +273,16 @@ pub trait LogStoreChunkWrite {
     /// Store data chunks of a data entry.
     fn put_chunks(&mut self, tx_seq: u64, chunks: ChunkArray) -> Result<()>;
 
-    /// Delete all chunks of a tx.
+    /// Delete all chunks of a tx, which fails if the file is pinned.
     fn remove_all_chunks(&self, tx_seq: u64) -> Result<()>;
+
+    /// Remove the stored entries of a file that do not match the subtree roots of its tx, so that
+    /// they can be downloaded again. Return the removed chunk ranges (`end` excluded) of the file.
+    fn invalidate_corrupted_chunks(&self, tx_seq: u64) -> Result<Vec<(u64, u64)>>;
+
+    /// Reconstruct a PoRA chunk from the erasure shards of the same chunk, and store it after the
+    /// reconstructed data is validated against the range proof of the shards.
+    fn recover_chunk(&mut self, shards: Vec<ErasureShard>) -> Result<()>;
 }
 
 pub trait LogChunkStore: LogStoreChunkRead + LogStoreChunkWrite + Send + Sync + 'static {}
@@ -112,6 +295,23 @@ pub trait FlowRead {
     fn get_entries(&self, index_start: u64, index_end: u64) -> Result<Option<ChunkArray>>;
 
     fn get_chunk_root_list(&self) -> Result<Vec<(usize, DataRoot)>>;
+
+    /// Load the entries in the range (`index_end` excluded) into the caches ahead of reads.
+    fn prefetch_entries(&self, index_start: u64, index_end: u64) -> Result<()>;
+
+    /// Get the sealed data of a complete PoRA chunk, if sealing is enabled.
+    fn get_sealed_chunk(&self, chunk_index: u64) -> Result<Option<ChunkArray>>;
+
+    /// Get a data or parity shard of a complete PoRA chunk, if erasure coding is enabled. The
+    /// shards with index less than `data_shards` are the data shards.
+    fn get_shard(&self, chunk_index: u64, shard_index: usize) -> Result<Option<Vec<u8>>>;
+
+    /// Return the ranges (`index_end` excluded) of the available entries in the given range.
+    fn get_available_entry_ranges(
+        &self,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Vec<(u64, u64)>>;
 }
 
 pub trait FlowWrite {
@@ -123,6 +323,10 @@ pub trait FlowWrite {
     /// Remove all the entries after `start_index`.
     /// This is used to remove deprecated data in case of chain reorg.
     fn truncate(&self, start_index: u64) -> Result<()>;
+
+    /// Remove the entries in the range (`end_index` excluded), so that they can be written again.
+    /// This is used to remove corrupted data.
+    fn remove_entries(&self, start_index: u64, end_index: u64) -> Result<()>;
 }
 
 pub trait Flow: FlowRead + FlowWrite {}
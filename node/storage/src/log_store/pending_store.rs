@@ -0,0 +1,395 @@
+//! Staging area of the files uploaded before their transactions are synced from the log contract.
+//! The data of a file is staged by its data root, and verified against the data root once all
+//! its entries are staged, so that it could be spliced into the flow at once when the transaction
+//! is confirmed.
+//!
+//! The staged files take the disk space before any fee is paid on chain, so the total size of the
+//! staged files is capped, and the files not promoted in time are dropped.
+
+use crate::error::Error;
+use crate::log_store::log_manager::{
+    bytes_to_entries, sub_merkle_tree, COL_PENDING_DATA, COL_PENDING_TX, ENTRY_SIZE,
+};
+use crate::{try_option, IonianKeyValueDB};
+use anyhow::{anyhow, bail, Result};
+use shared_types::{timestamp_now, ChunkArray, DataRoot};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use std::sync::Arc;
+use tracing::debug;
+
+#[derive(Clone, Debug)]
+pub struct PendingConfig {
+    /// Maximum total size in bytes of the staged files, including the ones partially staged.
+    pub max_staged_bytes: u64,
+    /// Seconds since a file is last staged, after which it is dropped if still not promoted.
+    pub expiration_secs: u32,
+}
+
+impl Default for PendingConfig {
+    fn default() -> Self {
+        Self {
+            max_staged_bytes: 4 * 1024 * 1024 * 1024,
+            expiration_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// A file staged before its transaction is synced.
+#[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct PendingTx {
+    /// File size in bytes.
+    pub size: u64,
+    /// Staged entry index ranges (`end` excluded), which are ascending and disjoint.
+    pub staged: Vec<(u64, u64)>,
+    /// Whether all the entries are staged and verified against the data root.
+    pub verified: bool,
+    /// Unix timestamp in seconds when the file is last staged, from which it expires.
+    pub updated_at: u32,
+    /// Whether the size is only announced by peers rather than given by a local upload, which
+    /// is not trusted and replaced by a local upload of a different size.
+    pub announced: bool,
+}
+
+impl PendingTx {
+    fn num_entries(&self) -> u64 {
+        bytes_to_entries(self.size)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.staged == [(0, self.num_entries())]
+    }
+
+    fn is_expired(&self, now: u32, expiration_secs: u32) -> bool {
+        self.updated_at.saturating_add(expiration_secs) < now
+    }
+
+    /// Inserts the range, which is merged with the adjacent ones.
+    fn insert(&mut self, start: u64, end: u64) {
+        let (mut start, mut end) = (start, end);
+        let mut staged = Vec::with_capacity(self.staged.len() + 1);
+        for &(s, e) in &self.staged {
+            if e < start || s > end {
+                staged.push((s, e));
+            } else {
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
+        let position = staged.partition_point(|&(s, _)| s < start);
+        staged.insert(position, (start, end));
+        self.staged = staged;
+    }
+}
+
+pub struct PendingStore {
+    kvdb: Arc<dyn IonianKeyValueDB>,
+    config: PendingConfig,
+}
+
+impl PendingStore {
+    pub fn new(kvdb: Arc<dyn IonianKeyValueDB>, config: PendingConfig) -> Self {
+        Self { kvdb, config }
+    }
+
+    /// Starts to stage the file of `data_root`, and returns `false` if already staged with the
+    /// same size. The size `announced` by peers is replaced by a local upload of a different
+    /// size, while the other mismatches fail.
+    ///
+    /// Fails if the staged files would exceed the size limit, for which the files only announced
+    /// by peers are dropped first to make room for a local upload.
+    pub fn put_tx(&self, data_root: &DataRoot, size: u64, announced: bool) -> Result<bool> {
+        if size == 0 {
+            bail!("put pending tx with empty file: data_root={:?}", data_root);
+        }
+        if let Some(mut pending) = self.get_tx(data_root)? {
+            if pending.size == size {
+                if pending.announced && !announced {
+                    pending.announced = false;
+                    self.put_pending(data_root, &pending)?;
+                }
+                return Ok(false);
+            }
+            if announced || !pending.announced {
+                bail!(
+                    "put pending tx with size mismatch: data_root={:?} expected={} actual={}",
+                    data_root,
+                    pending.size,
+                    size
+                );
+            }
+            debug!(
+                "replace pending tx size announced by peers: data_root={:?} announced={} uploaded={}",
+                data_root, pending.size, size
+            );
+            self.delete_tx(data_root)?;
+        }
+
+        self.prune(timestamp_now())?;
+        let staged = self.get_txs()?;
+        let mut staged_bytes = staged.iter().fold(0u64, |total, (_, pending)| {
+            total.saturating_add(pending.size)
+        });
+        if !announced && staged_bytes.saturating_add(size) > self.config.max_staged_bytes {
+            let mut evictable: Vec<_> = staged
+                .into_iter()
+                .filter(|(_, pending)| pending.announced)
+                .collect();
+            evictable.sort_by_key(|(_, pending)| pending.updated_at);
+            for (root, pending) in evictable {
+                if staged_bytes.saturating_add(size) <= self.config.max_staged_bytes {
+                    break;
+                }
+                self.delete_tx(&root)?;
+                staged_bytes -= pending.size;
+            }
+        }
+        if staged_bytes.saturating_add(size) > self.config.max_staged_bytes {
+            bail!(
+                "put pending tx with staging area full: data_root={:?} size={} staged={} max={}",
+                data_root,
+                size,
+                staged_bytes,
+                self.config.max_staged_bytes
+            );
+        }
+
+        let pending = PendingTx {
+            size,
+            updated_at: timestamp_now(),
+            announced,
+            ..Default::default()
+        };
+        self.put_pending(data_root, &pending)?;
+        Ok(true)
+    }
+
+    fn put_pending(&self, data_root: &DataRoot, pending: &PendingTx) -> Result<()> {
+        Ok(self.kvdb.put(
+            COL_PENDING_TX,
+            data_root.as_bytes(),
+            &pending.as_ssz_bytes(),
+        )?)
+    }
+
+    /// Returns all the staged files.
+    fn get_txs(&self) -> Result<Vec<(DataRoot, PendingTx)>> {
+        self.kvdb
+            .iter(COL_PENDING_TX)
+            .map(|(key, value)| {
+                Ok((
+                    DataRoot::from_slice(&key),
+                    PendingTx::from_ssz_bytes(&value).map_err(Error::from)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Removes the files not staged for `expiration_secs` before `now`, and returns the number
+    /// of removed files.
+    pub fn prune(&self, now: u32) -> Result<usize> {
+        let expired: Vec<DataRoot> = self
+            .get_txs()?
+            .into_iter()
+            .filter(|(_, pending)| pending.is_expired(now, self.config.expiration_secs))
+            .map(|(data_root, _)| data_root)
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        let mut db_tx = self.kvdb.transaction();
+        for data_root in &expired {
+            db_tx.delete(COL_PENDING_TX, data_root.as_bytes());
+            db_tx.delete_prefix(COL_PENDING_DATA, data_root.as_bytes());
+        }
+        self.kvdb.write(db_tx)?;
+        debug!("pruned expired pending txs: count={}", expired.len());
+        Ok(expired.len())
+    }
+
+    pub fn get_tx(&self, data_root: &DataRoot) -> Result<Option<PendingTx>> {
+        let value = try_option!(self.kvdb.get(COL_PENDING_TX, data_root.as_bytes())?);
+        Ok(Some(
+            PendingTx::from_ssz_bytes(&value).map_err(Error::from)?,
+        ))
+    }
+
+    /// Stages the entries of a file, and verifies the whole file against `data_root` once all
+    /// the entries are staged. Returns whether the file is completely staged and verified.
+    ///
+    /// The entries are stored as they are, so they must not partially overlap the staged ones,
+    /// which is the case if uploaded in segments. All the staged entries are dropped if the file
+    /// fails the verification.
+    pub fn put_chunks(&self, data_root: &DataRoot, chunks: ChunkArray) -> Result<bool> {
+        let mut pending = self.get_tx(data_root)?.ok_or_else(|| {
+            anyhow!(
+                "put pending chunks with missing tx: data_root={:?}",
+                data_root
+            )
+        })?;
+        if pending.verified {
+            return Ok(true);
+        }
+        if chunks.data.is_empty() || chunks.data.len() % ENTRY_SIZE != 0 {
+            bail!(
+                "put pending chunks with invalid data length: data_root={:?} data_len={}",
+                data_root,
+                chunks.data.len()
+            );
+        }
+        let start = chunks.start_index;
+        let end = start.saturating_add((chunks.data.len() / ENTRY_SIZE) as u64);
+        if end > pending.num_entries() {
+            bail!(
+                "put pending chunks with data out of tx range: data_root={:?} start_index={} data_len={}",
+                data_root,
+                start,
+                chunks.data.len()
+            );
+        }
+        for &(s, e) in &pending.staged {
+            if s <= start && end <= e {
+                // already staged
+                return Ok(false);
+            }
+            if s < end && start < e {
+                bail!(
+                    "put pending chunks overlapping staged ones: data_root={:?} start_index={} end_index={}",
+                    data_root,
+                    start,
+                    end
+                );
+            }
+        }
+
+        pending.insert(start, end);
+        pending.updated_at = timestamp_now();
+        let mut db_tx = self.kvdb.transaction();
+        db_tx.put(COL_PENDING_DATA, &data_key(data_root, start), &chunks.data);
+        db_tx.put(
+            COL_PENDING_TX,
+            data_root.as_bytes(),
+            &pending.as_ssz_bytes(),
+        );
+        self.kvdb.write(db_tx)?;
+
+        if !pending.is_complete() {
+            return Ok(false);
+        }
+
+        let mut data = Vec::with_capacity(pending.num_entries() as usize * ENTRY_SIZE);
+        for segment in self.get_segments(data_root)? {
+            data.extend_from_slice(&segment.data);
+        }
+        let root: DataRoot = sub_merkle_tree(&data)?.root().into();
+        let mut db_tx = self.kvdb.transaction();
+        if root == *data_root {
+            pending.verified = true;
+            db_tx.put(
+                COL_PENDING_TX,
+                data_root.as_bytes(),
+                &pending.as_ssz_bytes(),
+            );
+        } else {
+            db_tx.delete_prefix(COL_PENDING_DATA, data_root.as_bytes());
+            if pending.announced {
+                // The size announced by peers may be wrong, so the file is dropped to be staged
+                // again with another size.
+                db_tx.delete(COL_PENDING_TX, data_root.as_bytes());
+            } else {
+                pending.staged.clear();
+                db_tx.put(
+                    COL_PENDING_TX,
+                    data_root.as_bytes(),
+                    &pending.as_ssz_bytes(),
+                );
+            }
+        }
+        self.kvdb.write(db_tx)?;
+
+        if !pending.verified {
+            bail!(
+                "pending file with data root mismatch: expected={:?} computed={:?}",
+                data_root,
+                root
+            );
+        }
+        Ok(true)
+    }
+
+    /// Returns the staged segments of the file in ascending order of their start entry indices.
+    pub fn get_segments(&self, data_root: &DataRoot) -> Result<Vec<ChunkArray>> {
+        self.kvdb
+            .iter_with_prefix(COL_PENDING_DATA, data_root.as_bytes())
+            .map(|(key, value)| {
+                Ok(ChunkArray {
+                    data: value.to_vec(),
+                    start_index: decode_start_index(&key)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the staged entries in the range (`index_end` excluded), or `None` if any of them
+    /// is not staged.
+    pub fn get_chunks(
+        &self,
+        data_root: &DataRoot,
+        index_start: u64,
+        index_end: u64,
+    ) -> Result<Option<ChunkArray>> {
+        if index_start >= index_end {
+            bail!(
+                "invalid entry index: start={} end={}",
+                index_start,
+                index_end
+            );
+        }
+        let pending = try_option!(self.get_tx(data_root)?);
+        if !pending
+            .staged
+            .iter()
+            .any(|&(s, e)| s <= index_start && index_end <= e)
+        {
+            return Ok(None);
+        }
+
+        let mut data = Vec::with_capacity((index_end - index_start) as usize * ENTRY_SIZE);
+        for segment in self.get_segments(data_root)? {
+            let start = segment.start_index;
+            let end = start + (segment.data.len() / ENTRY_SIZE) as u64;
+            if end > index_start && start < index_end {
+                let from = (index_start.max(start) - start) as usize * ENTRY_SIZE;
+                let to = (index_end.min(end) - start) as usize * ENTRY_SIZE;
+                data.extend_from_slice(&segment.data[from..to]);
+            }
+        }
+        Ok(Some(ChunkArray {
+            data,
+            start_index: index_start,
+        }))
+    }
+
+    /// Removes the file and all its staged entries.
+    pub fn delete_tx(&self, data_root: &DataRoot) -> Result<()> {
+        let mut db_tx = self.kvdb.transaction();
+        db_tx.delete(COL_PENDING_TX, data_root.as_bytes());
+        db_tx.delete_prefix(COL_PENDING_DATA, data_root.as_bytes());
+        Ok(self.kvdb.write(db_tx)?)
+    }
+}
+
+/// The key of a staged segment, so that the segments of a file are adjacent and sorted by start
+/// entry indices.
+fn data_key(data_root: &DataRoot, start_index: u64) -> Vec<u8> {
+    let mut key = data_root.as_bytes().to_vec();
+    key.extend_from_slice(&start_index.to_be_bytes());
+    key
+}
+
+fn decode_start_index(key: &[u8]) -> Result<u64> {
+    let bytes = key
+        .get(DataRoot::len_bytes()..)
+        .ok_or_else(|| anyhow!("invalid pending data key {:?}", key))?;
+    Ok(u64::from_be_bytes(bytes.try_into()?))
+}
@@ -0,0 +1,337 @@
+use crate::error::Error;
+use crate::log_store::file_index;
+use crate::log_store::log_manager::{
+    COL_FILE_METADATA, COL_MISC, COL_PENDING_TX, COL_TX, COL_TX_BY_DATA_ROOT,
+};
+use crate::log_store::pending_store::PendingTx;
+use crate::log_store::tx_store::data_root_key;
+use crate::IonianKeyValueDB;
+use anyhow::{bail, Result};
+use ethereum_types::{Address, U256};
+use kvdb::DBTransaction;
+use shared_types::{timestamp_now, DataRoot, FileMetadata, Transaction};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
+use tracing::info;
+
+/// Key of the schema version of the database.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Key of the progress of the ongoing migration, i.e. its version followed by the last migrated
+/// key.
+const MIGRATION_PROGRESS_KEY: &str = "schema_migration_progress";
+
+/// The version of the databases created before the schema version is recorded.
+const BASE_SCHEMA_VERSION: u64 = 1;
+
+/// Number of entries migrated in a database transaction, to bound the memory usage.
+pub(crate) const MIGRATION_BATCH_SIZE: usize = 1000;
+
+/// Writes the changes of an entry, given its key and value, to the transaction.
+pub type MigrateFn = fn(&dyn IonianKeyValueDB, &mut DBTransaction, &[u8], &[u8]) -> Result<()>;
+
+/// Upgrades the database from `version - 1` to `version` by migrating each entry of `column`.
+///
+/// The entries are migrated in the key order and committed in batches along with the last
+/// migrated key, so an interrupted migration resumes after that key and each entry is migrated
+/// exactly once. The version is only bumped after the last batch.
+pub struct Migration {
+    pub version: u64,
+    pub description: &'static str,
+    pub column: u32,
+    pub migrate: MigrateFn,
+}
+
+/// The migrations in the ascending order of versions, to which a migration should be appended
+/// once the column layouts or the encodings change.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "index transactions by data root",
+        column: COL_TX,
+        migrate: index_txs_by_data_root,
+    },
+    Migration {
+        version: 3,
+        description: "add senders to transactions",
+        column: COL_TX,
+        migrate: add_tx_senders,
+    },
+    Migration {
+        version: 4,
+        description: "index files for search",
+        column: COL_TX,
+        migrate: index_files,
+    },
+    Migration {
+        version: 5,
+        description: "add block timestamps to transactions",
+        column: COL_TX,
+        migrate: add_tx_block_timestamps,
+    },
+    Migration {
+        version: 6,
+        description: "add timestamps to pending txs",
+        column: COL_PENDING_TX,
+        migrate: add_pending_tx_timestamps,
+    },
+];
+
+/// The encoding of transactions before the senders are recorded.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub(crate) struct TransactionV1 {
+    pub stream_ids: Vec<U256>,
+    pub data: Vec<u8>,
+    pub data_merkle_root: DataRoot,
+    pub merkle_nodes: Vec<(usize, DataRoot)>,
+    pub start_entry_index: u64,
+    pub size: u64,
+    pub seq: u64,
+}
+
+/// The encoding of transactions before the block timestamps are recorded.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub(crate) struct TransactionV2 {
+    pub stream_ids: Vec<U256>,
+    pub data: Vec<u8>,
+    pub data_merkle_root: DataRoot,
+    pub merkle_nodes: Vec<(usize, DataRoot)>,
+    pub start_entry_index: u64,
+    pub size: u64,
+    pub seq: u64,
+    pub sender: Address,
+}
+
+impl From<TransactionV2> for Transaction {
+    fn from(tx: TransactionV2) -> Self {
+        Self {
+            stream_ids: tx.stream_ids,
+            data: tx.data,
+            data_merkle_root: tx.data_merkle_root,
+            merkle_nodes: tx.merkle_nodes,
+            start_entry_index: tx.start_entry_index,
+            size: tx.size,
+            seq: tx.seq,
+            sender: tx.sender,
+            block_timestamp: 0,
+        }
+    }
+}
+
+/// The encoding of pending transactions before they expire.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub(crate) struct PendingTxV1 {
+    pub size: u64,
+    pub staged: Vec<(u64, u64)>,
+    pub verified: bool,
+}
+
+/// The schema version of the databases created or upgraded by this build.
+pub fn schema_version() -> u64 {
+    MIGRATIONS
+        .last()
+        .map_or(BASE_SCHEMA_VERSION, |migration| migration.version)
+}
+
+/// Copies the transactions to `COL_TX_BY_DATA_ROOT`, to look up transactions by data roots in a
+/// single read.
+fn index_txs_by_data_root(
+    _db: &dyn IonianKeyValueDB,
+    db_tx: &mut DBTransaction,
+    _key: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    let tx = TransactionV1::from_ssz_bytes(value).map_err(Error::from)?;
+    db_tx.put(
+        COL_TX_BY_DATA_ROOT,
+        &data_root_key(&tx.data_merkle_root, tx.seq),
+        value,
+    );
+    Ok(())
+}
+
+/// Re-encodes the transactions with the sender, which is unknown for the transactions synced
+/// before and left as zero, so they are not in the sender index.
+fn add_tx_senders(
+    _db: &dyn IonianKeyValueDB,
+    db_tx: &mut DBTransaction,
+    key: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    let tx = TransactionV1::from_ssz_bytes(value).map_err(Error::from)?;
+    let tx = TransactionV2 {
+        stream_ids: tx.stream_ids,
+        data: tx.data,
+        data_merkle_root: tx.data_merkle_root,
+        merkle_nodes: tx.merkle_nodes,
+        start_entry_index: tx.start_entry_index,
+        size: tx.size,
+        seq: tx.seq,
+        sender: Address::zero(),
+    };
+    let encoded = tx.as_ssz_bytes();
+    db_tx.put(COL_TX, key, &encoded);
+    db_tx.put(
+        COL_TX_BY_DATA_ROOT,
+        &data_root_key(&tx.data_merkle_root, tx.seq),
+        &encoded,
+    );
+    Ok(())
+}
+
+/// Adds the transactions and file metadata to the file index. The upload time of the files synced
+/// before is unknown, and set to the time of the migration, which never decreases along the tx
+/// seqs since the transactions are migrated in order.
+fn index_files(
+    db: &dyn IonianKeyValueDB,
+    db_tx: &mut DBTransaction,
+    key: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    let tx: Transaction = TransactionV2::from_ssz_bytes(value)
+        .map_err(Error::from)?
+        .into();
+    file_index::put_tx(db_tx, &tx, timestamp_now());
+    if let Some(value) = db.get(COL_FILE_METADATA, key)? {
+        let metadata = FileMetadata::from_ssz_bytes(&value).map_err(Error::from)?;
+        file_index::put_tags(db_tx, tx.seq, &metadata.tags);
+    }
+    Ok(())
+}
+
+/// Re-encodes the transactions with the block timestamp, which is unknown for the transactions
+/// synced before and left as zero, so they are not in the time index.
+fn add_tx_block_timestamps(
+    _db: &dyn IonianKeyValueDB,
+    db_tx: &mut DBTransaction,
+    key: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    let tx: Transaction = TransactionV2::from_ssz_bytes(value)
+        .map_err(Error::from)?
+        .into();
+    let encoded = tx.as_ssz_bytes();
+    db_tx.put(COL_TX, key, &encoded);
+    db_tx.put(
+        COL_TX_BY_DATA_ROOT,
+        &data_root_key(&tx.data_merkle_root, tx.seq),
+        &encoded,
+    );
+    Ok(())
+}
+
+/// Re-encodes the pending transactions with the time of the migration, from which they expire.
+/// The sizes of the files staged before may be announced by peers, so they are not trusted.
+fn add_pending_tx_timestamps(
+    _db: &dyn IonianKeyValueDB,
+    db_tx: &mut DBTransaction,
+    key: &[u8],
+    value: &[u8],
+) -> Result<()> {
+    let pending = PendingTxV1::from_ssz_bytes(value).map_err(Error::from)?;
+    let pending = PendingTx {
+        size: pending.size,
+        staged: pending.staged,
+        verified: pending.verified,
+        updated_at: timestamp_now(),
+        announced: true,
+    };
+    db_tx.put(COL_PENDING_TX, key, &pending.as_ssz_bytes());
+    Ok(())
+}
+
+/// Returns the last key migrated by the interrupted migration to `version`, if any.
+fn get_migration_progress(db: &dyn IonianKeyValueDB, version: u64) -> Result<Option<Vec<u8>>> {
+    match db.get(COL_MISC, MIGRATION_PROGRESS_KEY.as_bytes())? {
+        Some(value) if value.len() >= 8 && value[..8] == version.to_be_bytes() => {
+            Ok(Some(value[8..].to_vec()))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn run_migration(db: &dyn IonianKeyValueDB, migration: &Migration) -> Result<()> {
+    let last_key = get_migration_progress(db, migration.version)?;
+    if let Some(last_key) = &last_key {
+        info!(version = %migration.version, last_key = %hex::encode(last_key), "Resuming interrupted migration");
+    }
+
+    let mut db_tx = db.transaction();
+    let mut batch_len = 0;
+    // The entries are iterated in the key order, so the migrated ones are all before the last key.
+    for (key, value) in db
+        .iter(migration.column)
+        .skip_while(|(key, _)| matches!(&last_key, Some(last_key) if **key <= **last_key))
+    {
+        (migration.migrate)(db, &mut db_tx, &key, &value)?;
+        batch_len += 1;
+        if batch_len == MIGRATION_BATCH_SIZE {
+            let mut progress = migration.version.to_be_bytes().to_vec();
+            progress.extend_from_slice(&key);
+            db_tx.put(COL_MISC, MIGRATION_PROGRESS_KEY.as_bytes(), &progress);
+            db.write(std::mem::replace(&mut db_tx, db.transaction()))?;
+            batch_len = 0;
+        }
+    }
+
+    db_tx.delete(COL_MISC, MIGRATION_PROGRESS_KEY.as_bytes());
+    db_tx.put(
+        COL_MISC,
+        SCHEMA_VERSION_KEY.as_bytes(),
+        &migration.version.as_ssz_bytes(),
+    );
+    db.write(db_tx)?;
+    Ok(())
+}
+
+pub fn get_schema_version(db: &dyn IonianKeyValueDB) -> Result<Option<u64>> {
+    match db.get(COL_MISC, SCHEMA_VERSION_KEY.as_bytes())? {
+        Some(value) => Ok(Some(u64::from_ssz_bytes(&value).map_err(Error::from)?)),
+        None => Ok(None),
+    }
+}
+
+/// Upgrades the database to the latest schema version, or refuses to open a database of a newer
+/// version than this build supports.
+pub fn migrate(db: &dyn IonianKeyValueDB) -> Result<()> {
+    migrate_with(db, MIGRATIONS)
+}
+
+pub(crate) fn migrate_with(db: &dyn IonianKeyValueDB, migrations: &[Migration]) -> Result<()> {
+    let latest = migrations
+        .last()
+        .map_or(BASE_SCHEMA_VERSION, |migration| migration.version);
+    let mut version = get_schema_version(db)?.unwrap_or(BASE_SCHEMA_VERSION);
+    if version > latest {
+        bail!(
+            "database schema version {} is newer than {} supported by this node, please upgrade the node",
+            version,
+            latest
+        );
+    }
+
+    for migration in migrations.iter().filter(move |m| m.version > version) {
+        if migration.version != version + 1 {
+            bail!(
+                "missing migration from schema version {} to {}",
+                version,
+                migration.version
+            );
+        }
+
+        info!(from = %version, to = %migration.version, description = %migration.description, "Migrating database schema");
+        run_migration(db, migration)?;
+        version = migration.version;
+    }
+
+    // the version of the databases created before the versioning
+    if get_schema_version(db)?.is_none() {
+        db.put(
+            COL_MISC,
+            SCHEMA_VERSION_KEY.as_bytes(),
+            &version.as_ssz_bytes(),
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Sealed PoRA chunks in a flat file, where the chunk `i` is at the offset `i * SEALED_CHUNK_SIZE`
+//! so that a chunk is read at once by its offset. With the `mmap` feature, the file is memory
+//! mapped to avoid a syscall per read in proof generation and mining, and the hot regions are left
+//! to the page cache of the OS.
+//!
+//! The file never shrinks, since reading a mapped region beyond the end of the file faults. The
+//! chunks reverted by a chain reorg are left in place, but are no longer marked as sealed in the
+//! database.
+
+use crate::log_store::log_manager::{ENTRY_SIZE, PORA_CHUNK_SIZE};
+use anyhow::{bail, Result};
+use parking_lot::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[cfg(not(feature = "mmap"))]
+use std::io::Read;
+
+/// Size of a sealed PoRA chunk in bytes.
+pub const SEALED_CHUNK_SIZE: usize = PORA_CHUNK_SIZE * ENTRY_SIZE;
+
+pub struct SealedFile {
+    file: Mutex<File>,
+    /// The mapping of the file, which is remapped once a chunk beyond it is read.
+    #[cfg(feature = "mmap")]
+    mmap: parking_lot::RwLock<Option<memmap2::Mmap>>,
+}
+
+impl SealedFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            #[cfg(feature = "mmap")]
+            mmap: parking_lot::RwLock::new(None),
+        })
+    }
+
+    pub fn write(&self, chunk_index: u64, data: &[u8]) -> Result<()> {
+        if data.len() != SEALED_CHUNK_SIZE {
+            bail!(
+                "invalid sealed chunk size: chunk_index={} len={}",
+                chunk_index,
+                data.len()
+            );
+        }
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::Start(chunk_offset(chunk_index)))?;
+        file.write_all(data)?;
+        Ok(())
+    }
+
+    /// Flushes the written chunks to the disk, which should be done before the chunks are marked
+    /// as sealed in the database.
+    pub fn sync(&self) -> Result<()> {
+        Ok(self.file.lock().sync_data()?)
+    }
+
+    /// Advises the OS to read the chunks in the range (`chunk_end` excluded) into the page cache
+    /// in the background. This is only a hint, so the failures are ignored.
+    pub fn prefetch(&self, chunk_start: u64, chunk_end: u64) {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let file = self.file.lock();
+            // Safety: the file descriptor is valid while the file is locked.
+            unsafe {
+                libc::posix_fadvise(
+                    file.as_raw_fd(),
+                    chunk_offset(chunk_start) as libc::off_t,
+                    (chunk_offset(chunk_end) - chunk_offset(chunk_start)) as libc::off_t,
+                    libc::POSIX_FADV_WILLNEED,
+                );
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (chunk_start, chunk_end);
+    }
+
+    /// Returns the sealed chunk, or `None` if the file does not cover it.
+    #[cfg(feature = "mmap")]
+    pub fn read(&self, chunk_index: u64) -> Result<Option<Vec<u8>>> {
+        let start = chunk_offset(chunk_index) as usize;
+        let end = start + SEALED_CHUNK_SIZE;
+        if let Some(mmap) = self.mmap.read().as_ref() {
+            if end <= mmap.len() {
+                return Ok(Some(mmap[start..end].to_vec()));
+            }
+        }
+
+        // The file may have grown since it is mapped.
+        let mut mmap = self.mmap.write();
+        let file = self.file.lock();
+        if file.metadata()?.len() < end as u64 {
+            return Ok(None);
+        }
+        // Safety: the file is only written by the store, which never truncates it.
+        let remapped = unsafe { memmap2::Mmap::map(&*file)? };
+        let data = remapped[start..end].to_vec();
+        *mmap = Some(remapped);
+        Ok(Some(data))
+    }
+
+    /// Returns the sealed chunk, or `None` if the file does not cover it.
+    #[cfg(not(feature = "mmap"))]
+    pub fn read(&self, chunk_index: u64) -> Result<Option<Vec<u8>>> {
+        let start = chunk_offset(chunk_index);
+        let mut file = self.file.lock();
+        if file.metadata()?.len() < start + SEALED_CHUNK_SIZE as u64 {
+            return Ok(None);
+        }
+        let mut data = vec![0u8; SEALED_CHUNK_SIZE];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+}
+
+fn chunk_offset(chunk_index: u64) -> u64 {
+    chunk_index * SEALED_CHUNK_SIZE as u64
+}
@@ -1,20 +1,58 @@
+use crate::error::Error;
+use crate::log_store::flow_store::FlowStore;
+use crate::log_store::local_file::{export_file, import_file};
 use crate::log_store::log_manager::{
-    bytes_to_entries, data_to_merkle_leaves, sub_merkle_tree, LogConfig, LogManager, ENTRY_SIZE,
+    bytes_to_entries, data_to_merkle_leaves, sub_merkle_tree, LogConfig, LogManager,
+    COL_ENTRY_BATCH, COL_FILE_METADATA, COL_MISC, COL_NUM, COL_PENDING_TX, COL_TX, ENTRY_SIZE,
     PORA_CHUNK_SIZE,
 };
-use crate::log_store::{LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite};
+use crate::log_store::pending_store::{PendingConfig, PendingStore};
+use crate::log_store::schema::{
+    self, get_schema_version, Migration, PendingTxV1, TransactionV1, MIGRATION_BATCH_SIZE,
+};
+use crate::log_store::tx_store::TransactionStore;
+use crate::log_store::{
+    ErasureConfig, FileQuery, FileSyncPeer, FileSyncProgress, FlowConfig, FlowRead, FlowWrite,
+    LogStoreChunkRead, LogStoreChunkWrite, LogStoreRead, LogStoreWrite, LogSyncCheckpoint,
+    TxListFilter, MAX_LOG_SYNC_CHECKPOINTS,
+};
+use crate::IonianKeyValueDB;
+use anyhow::bail;
 use append_merkle::{Algorithm, AppendMerkleTree, Sha3Algorithm};
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
+use kvdb::KeyValueDB;
 use merkle_light::merkle::{log2_pow2, next_pow2};
 use rand::random;
-use shared_types::{ChunkArray, DataRoot, Transaction, CHUNK_SIZE};
+use shared_types::{ChunkArray, DataRoot, FileMetadata, Transaction, CHUNK_SIZE};
+use ssz::Encode;
 use std::cmp;
+use std::sync::Arc;
+use tempdir::TempDir;
 
 #[test]
 fn test_put_get() {
-    let config = LogConfig::default();
-    let mut store = LogManager::memorydb(config.clone()).unwrap();
-    let chunk_count = config.flow.batch_size + config.flow.batch_size / 2 - 1;
+    put_get(LogConfig::default());
+}
+
+#[test]
+fn test_put_get_small_entry_batches() {
+    let mut config = LogConfig::default();
+    config.flow.batch_size = 64;
+    put_get(config);
+}
+
+#[test]
+fn test_put_get_concurrent_proofs() {
+    let config = LogConfig {
+        proof_threads: 4,
+        ..Default::default()
+    };
+    put_get(config);
+}
+
+fn put_get(config: LogConfig) {
+    let mut store = LogManager::memorydb(config).unwrap();
+    let chunk_count = PORA_CHUNK_SIZE + PORA_CHUNK_SIZE / 2 - 1;
     // Aligned with size.
     let start_offset = 1024;
     let data_size = CHUNK_SIZE * chunk_count;
@@ -36,8 +74,11 @@ fn test_put_get() {
         start_entry_index: start_offset as u64,
         // TODO: This can come from `tx_merkle`.
         merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
     };
     store.put_tx(tx.clone()).unwrap();
+    assert_eq!(store.get_chunk_ranges(tx.seq).unwrap(), Some(vec![]));
     for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
         let end = cmp::min((start_index + PORA_CHUNK_SIZE) * CHUNK_SIZE, data.len());
         let chunk_array = ChunkArray {
@@ -45,8 +86,22 @@ fn test_put_get() {
             start_index: start_index as u64,
         };
         store.put_chunks(tx.seq, chunk_array.clone()).unwrap();
+        assert_eq!(
+            store.get_chunk_ranges(tx.seq).unwrap(),
+            Some(vec![(0, end / CHUNK_SIZE)])
+        );
+        // the incomplete PoRA chunk is not available until finalized
+        assert_eq!(
+            store.get_available_ranges(tx.seq).unwrap(),
+            Some(vec![(0, PORA_CHUNK_SIZE)])
+        );
     }
     store.finalize_tx(tx.seq).unwrap();
+    assert_eq!(
+        store.get_available_ranges(tx.seq).unwrap(),
+        Some(vec![(0, chunk_count)])
+    );
+    assert_eq!(store.get_chunk_ranges(tx.seq + 1).unwrap(), None);
 
     let chunk_array = ChunkArray {
         data,
@@ -108,6 +163,32 @@ fn test_put_get() {
             )
             .is_ok());
     }
+
+    let (flow_root, flow_length) = store.get_context().unwrap();
+    assert_eq!(flow_length, (start_offset + chunk_count) as u64);
+    let flow_start = start_offset + PORA_CHUNK_SIZE;
+    let chunk_array_with_proof = store
+        .get_chunks_with_proof_by_flow_index_range(
+            flow_start as u64,
+            (flow_start + PORA_CHUNK_SIZE) as u64,
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        chunk_array_with_proof.chunks.data,
+        chunk_array
+            .sub_array(PORA_CHUNK_SIZE as u64, 2 * PORA_CHUNK_SIZE as u64)
+            .unwrap()
+            .data
+    );
+    assert!(chunk_array_with_proof
+        .proof
+        .validate::<Sha3Algorithm>(
+            &data_to_merkle_leaves(&chunk_array_with_proof.chunks.data).unwrap(),
+            flow_start
+        )
+        .is_ok());
+    assert_eq!(chunk_array_with_proof.proof.root(), flow_root);
 }
 
 #[test]
@@ -128,12 +209,81 @@ fn test_root() {
     }
 }
 
+#[test]
+fn test_file_sync_progress() {
+    let store = LogManager::memorydb(LogConfig::default()).unwrap();
+    assert!(store.get_all_file_sync_progress().unwrap().is_empty());
+
+    let progress = FileSyncProgress {
+        downloaded: vec![(0, 1024), (2048, 4096)],
+        peers: vec![FileSyncPeer {
+            peer_id: vec![1, 2, 3],
+            addr: vec![4, 5, 6],
+        }],
+        attempts: 2,
+    };
+    store.put_file_sync_progress(3, &progress).unwrap();
+    store
+        .put_file_sync_progress(1, &FileSyncProgress::default())
+        .unwrap();
+    assert_eq!(
+        store.get_all_file_sync_progress().unwrap(),
+        vec![(1, FileSyncProgress::default()), (3, progress)]
+    );
+
+    store.delete_file_sync_progress(1).unwrap();
+    store.delete_file_sync_progress(3).unwrap();
+    assert!(store.get_all_file_sync_progress().unwrap().is_empty());
+}
+
+#[test]
+fn test_log_sync_checkpoints() {
+    let store = LogManager::memorydb(LogConfig::default()).unwrap();
+    assert_eq!(store.get_sync_progress().unwrap(), None);
+
+    let checkpoint = |block_number: u64| LogSyncCheckpoint {
+        block_number,
+        block_hash: H256::from_low_u64_be(block_number),
+        next_tx_seq: block_number / 10,
+    };
+    for block_number in 0..MAX_LOG_SYNC_CHECKPOINTS as u64 + 2 {
+        store
+            .put_log_sync_checkpoint(checkpoint(block_number))
+            .unwrap();
+    }
+
+    // the oldest checkpoints are pruned
+    let checkpoints = store.get_log_sync_checkpoints().unwrap();
+    assert_eq!(checkpoints.len(), MAX_LOG_SYNC_CHECKPOINTS);
+    assert_eq!(checkpoints[0], checkpoint(2));
+    let last = MAX_LOG_SYNC_CHECKPOINTS as u64 + 1;
+    assert_eq!(
+        store.get_sync_progress().unwrap(),
+        Some((last, H256::from_low_u64_be(last)))
+    );
+
+    // revert to an earlier block
+    store.put_log_sync_checkpoint(checkpoint(100)).unwrap();
+    let checkpoints = store.get_log_sync_checkpoints().unwrap();
+    assert_eq!(checkpoints.len(), 99);
+    assert_eq!(checkpoints.last(), Some(&checkpoint(100)));
+}
+
 #[test]
 fn test_multi_tx() {
     let mut store = create_store();
     put_tx(&mut store, 3, 0, 2);
     put_tx(&mut store, 3, 1, 6);
+    let root = store.get_context().unwrap().0;
     put_tx(&mut store, 5, 2, 12);
+
+    // the flow roots after each transaction are kept
+    assert_eq!(store.get_flow_root_by_tx_seq(1).unwrap(), Some(root));
+    assert_eq!(
+        store.get_flow_root_by_tx_seq(2).unwrap(),
+        Some(store.get_context().unwrap().0)
+    );
+    assert_eq!(store.get_flow_root_by_tx_seq(3).unwrap(), None);
 }
 
 #[test]
@@ -147,6 +297,512 @@ fn test_revert() {
     put_tx(&mut store, 1, 1, 2);
 }
 
+#[test]
+fn test_sealed_chunks() {
+    let mut config = LogConfig::default();
+    config.flow.seal_chunks = true;
+    let mut store = LogManager::memorydb(config).unwrap();
+
+    // the first chunk is padding, and the second chunk is complete
+    put_tx(&mut store, PORA_CHUNK_SIZE, 0, PORA_CHUNK_SIZE as u64);
+    assert_eq!(store.get_sealed_chunk(0).unwrap(), None);
+    let sealed = store.get_sealed_chunk(1).unwrap().unwrap();
+    assert_eq!(sealed.start_index, PORA_CHUNK_SIZE as u64);
+    assert_eq!(
+        Some(sealed),
+        store
+            .get_chunks_by_flow_index_range(PORA_CHUNK_SIZE as u64, 2 * PORA_CHUNK_SIZE as u64)
+            .unwrap()
+    );
+
+    store.revert_to(0u64.wrapping_sub(1)).unwrap();
+    assert_eq!(store.get_sealed_chunk(1).unwrap(), None);
+
+    // not sealed by default
+    let mut store = create_store();
+    put_tx(&mut store, PORA_CHUNK_SIZE, 0, PORA_CHUNK_SIZE as u64);
+    assert_eq!(store.get_sealed_chunk(1).unwrap(), None);
+}
+
+#[test]
+fn test_sealed_file() {
+    let dir = TempDir::new("sealed_file").unwrap();
+    let mut config = LogConfig::default();
+    config.flow.seal_chunks = true;
+    config.flow.sealed_file = Some(dir.path().join("sealed"));
+    let mut store = LogManager::memorydb(config).unwrap();
+
+    put_tx(&mut store, 2 * PORA_CHUNK_SIZE, 0, PORA_CHUNK_SIZE as u64);
+    // the ranges out of the file or of a missing file are ignored
+    store.prefetch_chunks(0, 0, 3 * PORA_CHUNK_SIZE).unwrap();
+    store.prefetch_chunks(1, 0, PORA_CHUNK_SIZE).unwrap();
+    for chunk_index in 1..3 {
+        let start_index = chunk_index * PORA_CHUNK_SIZE as u64;
+        let sealed = store.get_sealed_chunk(chunk_index).unwrap().unwrap();
+        assert_eq!(
+            Some(sealed),
+            store
+                .get_chunks_by_flow_index_range(start_index, start_index + PORA_CHUNK_SIZE as u64)
+                .unwrap()
+        );
+        // proofs are generated from the sealed chunks
+        let chunk_array_with_proof = store
+            .get_chunks_with_proof_by_flow_index_range(
+                start_index,
+                start_index + PORA_CHUNK_SIZE as u64,
+            )
+            .unwrap()
+            .unwrap();
+        assert!(chunk_array_with_proof
+            .proof
+            .validate::<Sha3Algorithm>(
+                &data_to_merkle_leaves(&chunk_array_with_proof.chunks.data).unwrap(),
+                start_index as usize
+            )
+            .is_ok());
+    }
+    assert_eq!(store.get_sealed_chunk(3).unwrap(), None);
+
+    store.revert_to(0u64.wrapping_sub(1)).unwrap();
+    assert_eq!(store.get_sealed_chunk(1).unwrap(), None);
+}
+
+#[test]
+fn test_entry_batch_size() {
+    let mut config = LogConfig::default();
+    config.flow.batch_size = 64;
+    config.flow.seal_chunks = true;
+    let mut store = LogManager::memorydb(config.clone()).unwrap();
+
+    // the complete PoRA chunks are assembled from the entry batches
+    put_tx(&mut store, 3, 0, 2);
+    put_tx(&mut store, PORA_CHUNK_SIZE + 100, 1, PORA_CHUNK_SIZE as u64);
+    let sealed = store.get_sealed_chunk(1).unwrap().unwrap();
+    assert_eq!(
+        Some(sealed),
+        store
+            .get_chunks_by_flow_index_range(PORA_CHUNK_SIZE as u64, 2 * PORA_CHUNK_SIZE as u64)
+            .unwrap()
+    );
+    assert_eq!(store.get_sealed_chunk(2).unwrap(), None);
+
+    // invalid batch sizes
+    for batch_size in [0, 1, 100, 2 * PORA_CHUNK_SIZE] {
+        config.flow.batch_size = batch_size;
+        assert!(LogManager::memorydb(config.clone()).is_err());
+    }
+
+    // the batch size could not be changed once the database is created
+    let dir = TempDir::new("entry_batch_size").unwrap();
+    config.flow.batch_size = 64;
+    drop(LogManager::rocksdb(config.clone(), dir.path()).unwrap());
+    assert!(LogManager::rocksdb(LogConfig::default(), dir.path()).is_err());
+    assert!(LogManager::rocksdb(config, dir.path()).is_ok());
+}
+
+#[test]
+fn test_entry_batch_checksum() {
+    let db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let store = FlowStore::new(db.clone(), FlowConfig::default()).unwrap();
+    let data: Vec<u8> = (0..2 * PORA_CHUNK_SIZE * ENTRY_SIZE)
+        .map(|_| random())
+        .collect();
+    store
+        .append_entries(ChunkArray {
+            data,
+            start_index: PORA_CHUNK_SIZE as u64,
+        })
+        .unwrap();
+    let range = (PORA_CHUNK_SIZE as u64, 2 * PORA_CHUNK_SIZE as u64);
+    assert!(store.get_entries(range.0, range.1).unwrap().is_some());
+
+    // flip a bit of the first batch
+    let key = 1u64.to_be_bytes();
+    let mut raw = db.get(COL_ENTRY_BATCH, &key).unwrap().unwrap();
+    raw[100] ^= 1;
+    let mut db_tx = db.transaction();
+    db_tx.put(COL_ENTRY_BATCH, &key, &raw);
+    db.write(db_tx).unwrap();
+
+    let err = store.get_entries(range.0, range.1).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::Corrupted(_))
+    ));
+    assert!(store
+        .get_entries(range.1, range.1 + PORA_CHUNK_SIZE as u64)
+        .unwrap()
+        .is_some());
+
+    // not verified if disabled
+    let config = FlowConfig {
+        verify_checksum: false,
+        ..Default::default()
+    };
+    let store = FlowStore::new(db, config).unwrap();
+    assert!(store.get_entries(range.0, range.1).unwrap().is_some());
+}
+
+#[test]
+fn test_write_once() {
+    let db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let store = FlowStore::new(db.clone(), FlowConfig::default()).unwrap();
+    let start_index = PORA_CHUNK_SIZE as u64;
+    let data: Vec<u8> = (0..PORA_CHUNK_SIZE * ENTRY_SIZE)
+        .map(|_| random())
+        .collect();
+    let sub_array = |start: usize, end: usize| ChunkArray {
+        data: data[start * ENTRY_SIZE..end * ENTRY_SIZE].to_vec(),
+        start_index: start_index + start as u64,
+    };
+    store.append_entries(sub_array(100, 200)).unwrap();
+
+    // the same data could be written again, which also fills the gaps around
+    store.append_entries(sub_array(100, 200)).unwrap();
+    store.append_entries(sub_array(50, 150)).unwrap();
+    assert_eq!(
+        store
+            .get_available_entry_ranges(start_index, start_index + PORA_CHUNK_SIZE as u64)
+            .unwrap(),
+        vec![(start_index + 50, start_index + 200)]
+    );
+
+    // the stored entries could not be changed by partial or complete writes
+    let mut conflict = sub_array(150, 250);
+    conflict.data[0] ^= 1;
+    assert!(store.append_entries(conflict).is_err());
+    let mut conflict = sub_array(0, PORA_CHUNK_SIZE);
+    conflict.data[60 * ENTRY_SIZE] ^= 1;
+    assert!(store.append_entries(conflict.clone()).is_err());
+    assert_eq!(
+        store
+            .get_entries(start_index + 50, start_index + 200)
+            .unwrap(),
+        Some(sub_array(50, 200))
+    );
+
+    store.append_entries(sub_array(0, PORA_CHUNK_SIZE)).unwrap();
+    assert_eq!(
+        store
+            .get_entries(start_index, start_index + PORA_CHUNK_SIZE as u64)
+            .unwrap(),
+        Some(sub_array(0, PORA_CHUNK_SIZE))
+    );
+    assert!(store.append_entries(conflict.clone()).is_err());
+
+    // the entries could be repaired with overwriting allowed
+    let config = FlowConfig {
+        allow_overwrite: true,
+        ..Default::default()
+    };
+    let store = FlowStore::new(db, config).unwrap();
+    store.append_entries(conflict.clone()).unwrap();
+    assert_eq!(
+        store
+            .get_entries(start_index, start_index + PORA_CHUNK_SIZE as u64)
+            .unwrap(),
+        Some(conflict)
+    );
+}
+
+#[test]
+fn test_invalidate_corrupted_chunks() {
+    let mut store = create_store();
+    let chunk_count = 7;
+    let data: Vec<u8> = (0..chunk_count * CHUNK_SIZE).map(|_| random()).collect();
+    let tx = Transaction {
+        stream_ids: vec![],
+        size: data.len() as u64,
+        data_merkle_root: sub_merkle_tree(&data).unwrap().root().into(),
+        seq: 0,
+        data: vec![],
+        start_entry_index: 4,
+        merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    store.put_tx(tx.clone()).unwrap();
+
+    // a chunk of the second subtree is corrupted
+    let mut corrupted = data.clone();
+    corrupted[5 * CHUNK_SIZE] ^= 1;
+    store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: corrupted,
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    assert!(store.finalize_tx(tx.seq).is_err());
+
+    // only the subtree with the corrupted chunk is removed
+    assert_eq!(
+        store.invalidate_corrupted_chunks(tx.seq).unwrap(),
+        vec![(4, 6)]
+    );
+    assert_eq!(
+        store.get_chunk_ranges(tx.seq).unwrap(),
+        Some(vec![(0, 4), (6, chunk_count)])
+    );
+    assert!(store
+        .invalidate_corrupted_chunks(tx.seq)
+        .unwrap()
+        .is_empty());
+
+    // the removed chunks could be downloaded again without conflicts
+    store
+        .put_chunks(
+            tx.seq,
+            ChunkArray {
+                data: data[4 * CHUNK_SIZE..6 * CHUNK_SIZE].to_vec(),
+                start_index: 4,
+            },
+        )
+        .unwrap();
+    store.finalize_tx(tx.seq).unwrap();
+}
+
+#[test]
+fn test_erasure_recover_chunk() {
+    let mut config = LogConfig::default();
+    config.flow.erasure = Some(ErasureConfig::new(4, 2).unwrap());
+    let mut store = LogManager::memorydb(config.clone()).unwrap();
+    let mut peer_store = LogManager::memorydb(config).unwrap();
+
+    // the first chunk is padding, and the second chunk is complete
+    let mut data = vec![0u8; PORA_CHUNK_SIZE * CHUNK_SIZE];
+    for i in 0..PORA_CHUNK_SIZE {
+        data[i * CHUNK_SIZE] = random();
+    }
+    let tx = Transaction {
+        stream_ids: vec![],
+        size: data.len() as u64,
+        data_merkle_root: sub_merkle_tree(&data).unwrap().root().into(),
+        seq: 0,
+        data: vec![],
+        start_entry_index: PORA_CHUNK_SIZE as u64,
+        merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    peer_store.put_tx(tx.clone()).unwrap();
+    peer_store
+        .put_chunks(
+            0,
+            ChunkArray {
+                data: data.clone(),
+                start_index: 0,
+            },
+        )
+        .unwrap();
+    peer_store.finalize_tx(0).unwrap();
+    store.put_tx(tx).unwrap();
+
+    assert_eq!(peer_store.get_erasure_shard(0, 0).unwrap(), None);
+    assert!(peer_store.get_erasure_shard(1, 6).is_err());
+    // lose a data shard and a parity shard
+    let shards: Vec<_> = [1, 2, 3, 4]
+        .into_iter()
+        .map(|i| peer_store.get_erasure_shard(1, i).unwrap().unwrap())
+        .collect();
+
+    let mut invalid_shards = shards.clone();
+    invalid_shards[0].data[0] ^= 1;
+    assert!(store.recover_chunk(invalid_shards).is_err());
+    assert!(store.recover_chunk(shards[1..].to_vec()).is_err());
+
+    store.recover_chunk(shards).unwrap();
+    assert_eq!(
+        store
+            .get_chunks_by_flow_index_range(PORA_CHUNK_SIZE as u64, 2 * PORA_CHUNK_SIZE as u64)
+            .unwrap()
+            .unwrap()
+            .data,
+        data
+    );
+    store.finalize_tx(0).unwrap();
+    assert!(store.check_tx_completed(0).unwrap());
+}
+
+#[test]
+fn test_promote_pending_tx() {
+    let mut store = create_store();
+    let mut data = vec![0u8; 10 * CHUNK_SIZE];
+    for i in 0..10 {
+        data[i * CHUNK_SIZE] = random();
+    }
+    // the last chunk is padded
+    let size = data.len() as u64 - 100;
+    let data_root: DataRoot = sub_merkle_tree(&data).unwrap().root().into();
+    let segment = |start: usize, end: usize| ChunkArray {
+        data: data[start * CHUNK_SIZE..end * CHUNK_SIZE].to_vec(),
+        start_index: start as u64,
+    };
+
+    assert!(store.put_pending_chunks(&data_root, segment(0, 4)).is_err());
+    assert!(store.put_tx_pending(&data_root, size, false).unwrap());
+    assert!(!store.put_tx_pending(&data_root, size, false).unwrap());
+    assert!(store.put_tx_pending(&data_root, size + 1, false).is_err());
+    assert!(store.put_tx_pending(&data_root, size + 1, true).is_err());
+
+    assert!(!store.put_pending_chunks(&data_root, segment(4, 8)).unwrap());
+    // already staged, partially overlapping, or out of range
+    assert!(!store.put_pending_chunks(&data_root, segment(4, 8)).unwrap());
+    assert!(store.put_pending_chunks(&data_root, segment(2, 6)).is_err());
+    let out_of_range = ChunkArray {
+        data: vec![0; 4 * CHUNK_SIZE],
+        start_index: 8,
+    };
+    assert!(store.put_pending_chunks(&data_root, out_of_range).is_err());
+    assert!(!store.put_pending_chunks(&data_root, segment(0, 4)).unwrap());
+    let pending = store.get_pending_tx(&data_root).unwrap().unwrap();
+    assert_eq!(pending.staged, vec![(0, 8)]);
+    assert!(!pending.verified);
+    // the staged chunks are served across segments
+    assert_eq!(
+        store.get_pending_chunks(&data_root, 2, 6).unwrap(),
+        Some(segment(2, 6))
+    );
+    assert_eq!(store.get_pending_chunks(&data_root, 6, 9).unwrap(), None);
+
+    // not promoted until all the chunks are staged
+    let tx = Transaction {
+        stream_ids: vec![],
+        size,
+        data_merkle_root: data_root,
+        seq: 0,
+        data: vec![],
+        start_entry_index: PORA_CHUNK_SIZE as u64,
+        merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    store.put_tx(tx).unwrap();
+    assert!(!store.promote_tx(0).unwrap());
+    assert!(!store.check_tx_completed(0).unwrap());
+
+    assert!(store
+        .put_pending_chunks(&data_root, segment(8, 10))
+        .unwrap());
+    assert!(store.get_pending_tx(&data_root).unwrap().unwrap().verified);
+    assert!(store.promote_tx(0).unwrap());
+    assert!(store.check_tx_completed(0).unwrap());
+    assert_eq!(
+        store
+            .get_chunks_by_tx_and_index_range(0, 0, 10)
+            .unwrap()
+            .unwrap()
+            .data,
+        data
+    );
+    assert_eq!(store.get_pending_tx(&data_root).unwrap(), None);
+    assert!(!store.promote_tx(0).unwrap());
+
+    // the staged chunks are dropped if the data root mismatches
+    let corrupted_root = DataRoot::repeat_byte(1);
+    assert!(store.put_tx_pending(&corrupted_root, size, false).unwrap());
+    assert!(store
+        .put_pending_chunks(&corrupted_root, segment(0, 10))
+        .is_err());
+    assert_eq!(
+        store
+            .get_pending_tx(&corrupted_root)
+            .unwrap()
+            .unwrap()
+            .staged,
+        vec![]
+    );
+}
+
+#[test]
+fn test_replace_announced_pending_tx() {
+    let mut store = create_store();
+    let data = vec![1u8; 4 * CHUNK_SIZE];
+    let size = data.len() as u64;
+    let data_root: DataRoot = sub_merkle_tree(&data).unwrap().root().into();
+    let segment = ChunkArray {
+        data: data.clone(),
+        start_index: 0,
+    };
+
+    // the size announced by peers is replaced by a local upload
+    assert!(store.put_tx_pending(&data_root, size + 1, true).unwrap());
+    assert!(!store.put_tx_pending(&data_root, size + 1, true).unwrap());
+    assert!(store.put_tx_pending(&data_root, size, false).unwrap());
+    let pending = store.get_pending_tx(&data_root).unwrap().unwrap();
+    assert_eq!(pending.size, size);
+    assert!(!pending.announced);
+    assert!(store.put_tx_pending(&data_root, size + 1, false).is_err());
+
+    // the file staged with a wrong announced size is dropped once complete
+    let other_root = DataRoot::repeat_byte(2);
+    assert!(store
+        .put_tx_pending(&other_root, size + CHUNK_SIZE as u64, true)
+        .unwrap());
+    assert!(store
+        .put_pending_chunks(
+            &other_root,
+            ChunkArray {
+                data: vec![1u8; 5 * CHUNK_SIZE],
+                start_index: 0,
+            }
+        )
+        .is_err());
+    assert_eq!(store.get_pending_tx(&other_root).unwrap(), None);
+
+    // the staged file mismatching the transaction is dropped rather than promoted
+    let tx = Transaction {
+        stream_ids: vec![],
+        size: size - 1,
+        data_merkle_root: data_root,
+        seq: 0,
+        data: vec![],
+        start_entry_index: PORA_CHUNK_SIZE as u64,
+        merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    assert!(store.put_pending_chunks(&data_root, segment).unwrap());
+    store.put_tx(tx.clone()).unwrap();
+    assert!(!store.promote_tx(0).unwrap());
+    assert_eq!(store.get_pending_tx(&data_root).unwrap(), None);
+    assert!(!store.check_tx_completed(0).unwrap());
+}
+
+#[test]
+fn test_pending_tx_limits() {
+    let db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let config = PendingConfig {
+        max_staged_bytes: 3000,
+        expiration_secs: 60,
+    };
+    let store = PendingStore::new(db, config);
+    let root = |i: u64| DataRoot::from_low_u64_be(i);
+
+    assert!(store.put_tx(&root(0), 1000, true).unwrap());
+    assert!(store.put_tx(&root(1), 1000, false).unwrap());
+    assert!(store.put_tx(&root(2), 1000, true).unwrap());
+    // full for the files announced by peers
+    assert!(store.put_tx(&root(3), 1000, true).is_err());
+    // while the announced ones are dropped for a local upload, oldest first
+    assert!(store.put_tx(&root(3), 1500, false).unwrap());
+    assert_eq!(store.get_tx(&root(0)).unwrap(), None);
+    assert_eq!(store.get_tx(&root(2)).unwrap(), None);
+    assert!(store.get_tx(&root(1)).unwrap().is_some());
+    // but not the local uploads
+    assert!(store.put_tx(&root(4), 1000, false).is_err());
+
+    // the files not staged in time expire
+    let first = store.get_tx(&root(1)).unwrap().unwrap().updated_at;
+    let last = store.get_tx(&root(3)).unwrap().unwrap().updated_at;
+    assert_eq!(store.prune(first + 60).unwrap(), 0);
+    assert_eq!(store.prune(last + 61).unwrap(), 2);
+    assert_eq!(store.get_tx(&root(1)).unwrap(), None);
+    assert_eq!(store.get_tx(&root(3)).unwrap(), None);
+}
+
 fn tx_subtree_root_list(data: &[u8]) -> Vec<(usize, DataRoot)> {
     let mut root_list = Vec::new();
     let mut start_index = 0;
@@ -194,6 +850,8 @@ fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64, start_entry_inde
         start_entry_index,
         // TODO: This can come from `tx_merkle`.
         merkle_nodes: tx_subtree_root_list(&data),
+        sender: Default::default(),
+        block_timestamp: 0,
     };
     store.put_tx(tx.clone()).unwrap();
     for start_index in (0..chunk_count).step_by(PORA_CHUNK_SIZE) {
@@ -206,3 +864,496 @@ fn put_tx(store: &mut LogManager, chunk_count: usize, seq: u64, start_entry_inde
     }
     store.finalize_tx(tx.seq).unwrap();
 }
+
+#[test]
+fn test_txs_by_data_root() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64, root: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(root),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1,
+        seq,
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+
+    // the same data is submitted twice
+    for (seq, root) in [(0, 1), (1, 2), (2, 1)] {
+        tx_store.put_tx(tx(seq, root)).unwrap();
+    }
+    let root = DataRoot::from_low_u64_be(1);
+    assert_eq!(
+        tx_store.get_txs_by_data_root(&root).unwrap(),
+        vec![tx(0, 1), tx(2, 1)]
+    );
+    assert_eq!(tx_store.get_tx_seq_by_data_root(&root).unwrap(), Some(0));
+    assert!(tx_store
+        .get_txs_by_data_root(&DataRoot::from_low_u64_be(3))
+        .unwrap()
+        .is_empty());
+
+    // replaced after a chain reorg
+    tx_store.put_tx(tx(2, 3)).unwrap();
+    assert_eq!(
+        tx_store.get_txs_by_data_root(&root).unwrap(),
+        vec![tx(0, 1)]
+    );
+    assert_eq!(
+        tx_store
+            .get_txs_by_data_root(&DataRoot::from_low_u64_be(3))
+            .unwrap(),
+        vec![tx(2, 3)]
+    );
+}
+
+#[test]
+fn test_file_metadata() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64, root: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(root),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1,
+        seq,
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    let metadata = FileMetadata {
+        name: Some("a.txt".into()),
+        mime_type: Some("text/plain".into()),
+        tags: vec!["a".into()],
+    };
+
+    for seq in 0..2 {
+        tx_store.put_tx(tx(seq, seq)).unwrap();
+        tx_store.put_file_metadata(seq, &metadata).unwrap();
+    }
+    assert_eq!(
+        tx_store.get_file_metadata(0).unwrap(),
+        Some(metadata.clone())
+    );
+    assert_eq!(tx_store.get_file_metadata(2).unwrap(), None);
+
+    // kept if the same file is submitted again after a chain reorg
+    tx_store.put_tx(tx(0, 0)).unwrap();
+    assert_eq!(tx_store.get_file_metadata(0).unwrap(), Some(metadata));
+    // removed if replaced by another file
+    tx_store.put_tx(tx(1, 2)).unwrap();
+    assert_eq!(tx_store.get_file_metadata(1).unwrap(), None);
+}
+
+#[test]
+fn test_search_files() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64, sender: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(seq),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1 << seq,
+        seq,
+        sender: Address::from_low_u64_be(sender),
+        block_timestamp: 0,
+    };
+    for seq in 0..10 {
+        tx_store.put_tx(tx(seq, seq % 2)).unwrap();
+        let tags = match seq % 3 {
+            0 => vec!["Image".to_string(), "public".to_string()],
+            _ => vec!["image".to_string()],
+        };
+        let metadata = FileMetadata {
+            tags,
+            ..Default::default()
+        };
+        tx_store.put_file_metadata(seq, &metadata).unwrap();
+    }
+    let seqs = |query: FileQuery, start_seq: u64, limit: usize| -> Vec<u64> {
+        tx_store
+            .search_files(&query, start_seq, limit)
+            .unwrap()
+            .iter()
+            .map(|tx| tx.seq)
+            .collect()
+    };
+    let tags = |tags: &[&str]| FileQuery {
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..Default::default()
+    };
+
+    assert_eq!(seqs(FileQuery::default(), 0, 3), vec![0, 1, 2]);
+    assert_eq!(seqs(tags(&["IMAGE"]), 8, 10), vec![8, 9]);
+    assert_eq!(seqs(tags(&["public", "image"]), 0, 10), vec![0, 3, 6, 9]);
+    assert_eq!(seqs(tags(&["public"]), 1, 2), vec![3, 6]);
+    assert!(seqs(tags(&["imag"]), 0, 10).is_empty());
+
+    let query = FileQuery {
+        sender: Some(Address::from_low_u64_be(1)),
+        min_size: Some(3),
+        max_size: Some(1 << 7),
+        ..Default::default()
+    };
+    assert_eq!(seqs(query, 0, 10), vec![3, 5, 7]);
+    let query = FileQuery {
+        min_size: Some(3),
+        max_size: Some(1 << 4),
+        ..Default::default()
+    };
+    assert_eq!(seqs(query, 0, 10), vec![2, 3, 4]);
+
+    // the files are uploaded in the same hour
+    let time = tx_store.get_upload_time(0).unwrap().unwrap();
+    let query = FileQuery {
+        start_time: Some(time),
+        end_time: Some(time + 3600),
+        ..Default::default()
+    };
+    assert_eq!(seqs(query.clone(), 0, 10), (0..10).collect::<Vec<_>>());
+    let query = FileQuery {
+        tags: vec!["public".into()],
+        ..query
+    };
+    assert_eq!(seqs(query, 0, 10), vec![0, 3, 6, 9]);
+    let query = FileQuery {
+        end_time: Some(time - 1),
+        ..Default::default()
+    };
+    assert!(seqs(query, 0, 10).is_empty());
+
+    // the tags are replaced with the metadata
+    tx_store
+        .put_file_metadata(0, &FileMetadata::default())
+        .unwrap();
+    assert_eq!(seqs(tags(&["public"]), 0, 10), vec![3, 6, 9]);
+    // and removed if the file is replaced after a chain reorg
+    tx_store.put_tx(tx(3, 0)).unwrap();
+    assert_eq!(seqs(tags(&["public"]), 0, 10), vec![3, 6, 9]);
+    tx_store
+        .put_tx(Transaction {
+            data_merkle_root: DataRoot::from_low_u64_be(100),
+            ..tx(3, 0)
+        })
+        .unwrap();
+    assert_eq!(seqs(tags(&["public"]), 0, 10), vec![6, 9]);
+}
+
+#[test]
+fn test_pin_files() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let (root1, root2) = (DataRoot::from_low_u64_be(1), DataRoot::from_low_u64_be(2));
+
+    assert!(tx_store.pin_file(&root1).unwrap());
+    assert!(!tx_store.pin_file(&root1).unwrap());
+    assert!(tx_store.pin_file(&root2).unwrap());
+    assert!(tx_store.is_pinned(&root1).unwrap());
+    assert_eq!(tx_store.get_pinned_files().unwrap(), vec![root1, root2]);
+
+    assert!(tx_store.unpin_file(&root1).unwrap());
+    assert!(!tx_store.unpin_file(&root1).unwrap());
+    assert!(!tx_store.is_pinned(&root1).unwrap());
+    assert_eq!(tx_store.get_pinned_files().unwrap(), vec![root2]);
+}
+
+#[test]
+fn test_tx_list() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(seq),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: seq * 100,
+        seq,
+        sender: Default::default(),
+        block_timestamp: 0,
+    };
+    for seq in 0..10 {
+        tx_store.put_tx(tx(seq)).unwrap();
+        if seq % 2 == 0 {
+            tx_store.finalize_tx(seq).unwrap();
+        }
+    }
+    let seqs = |start_seq: u64, limit: usize, filter: TxListFilter| -> Vec<u64> {
+        tx_store
+            .get_tx_list(start_seq, limit, &filter)
+            .unwrap()
+            .iter()
+            .map(|tx| tx.seq)
+            .collect()
+    };
+
+    assert_eq!(seqs(0, 3, TxListFilter::default()), vec![0, 1, 2]);
+    assert_eq!(seqs(8, 3, TxListFilter::default()), vec![8, 9]);
+    assert!(seqs(10, 3, TxListFilter::default()).is_empty());
+    assert!(seqs(0, 0, TxListFilter::default()).is_empty());
+
+    let filter = TxListFilter {
+        finalized_only: true,
+        min_size: Some(200),
+        max_size: Some(800),
+    };
+    assert_eq!(seqs(0, 10, filter.clone()), vec![2, 4, 6, 8]);
+    assert_eq!(seqs(3, 2, filter), vec![4, 6]);
+}
+
+#[test]
+fn test_txs_by_sender() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64, sender: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(seq),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1,
+        seq,
+        sender: Address::from_low_u64_be(sender),
+        block_timestamp: 0,
+    };
+    for seq in 0..6 {
+        tx_store.put_tx(tx(seq, seq % 2)).unwrap();
+    }
+    let seqs = |sender: u64, skip: usize, limit: usize| -> Vec<u64> {
+        tx_store
+            .get_txs_by_sender(&Address::from_low_u64_be(sender), skip, limit)
+            .unwrap()
+            .iter()
+            .map(|tx| tx.seq)
+            .collect()
+    };
+
+    assert_eq!(seqs(1, 0, 10), vec![1, 3, 5]);
+    assert_eq!(seqs(1, 1, 1), vec![3]);
+    assert!(seqs(1, 3, 10).is_empty());
+    assert!(seqs(2, 0, 10).is_empty());
+
+    // replaced after a chain reorg
+    tx_store.put_tx(tx(3, 2)).unwrap();
+    assert_eq!(seqs(1, 0, 10), vec![1, 5]);
+    assert_eq!(seqs(2, 0, 10), vec![3]);
+
+    let filter = TxListFilter {
+        sender: Some(Address::from_low_u64_be(0)),
+        ..Default::default()
+    };
+    let txs = tx_store.get_tx_list(1, 10, &filter).unwrap();
+    assert_eq!(txs, vec![tx(2, 0), tx(4, 0)]);
+}
+
+#[test]
+fn test_txs_in_time_range() {
+    let tx_store = TransactionStore::new(Arc::new(kvdb_memorydb::create(COL_NUM)));
+    let tx = |seq: u64, block_timestamp: u64| Transaction {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(seq),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1,
+        seq,
+        sender: Default::default(),
+        block_timestamp,
+    };
+    // seq 0 is synced without the block timestamp, and 3 shares the block of 2
+    for (seq, time) in [(0, 0), (1, 100), (2, 250), (3, 250), (4, 256), (5, 1000)] {
+        tx_store.put_tx(tx(seq, time)).unwrap();
+    }
+    let seqs = |start: u64, end: u64| -> Vec<u64> {
+        tx_store
+            .get_txs_in_time_range(start, end)
+            .unwrap()
+            .iter()
+            .map(|tx| tx.seq)
+            .collect()
+    };
+
+    assert_eq!(seqs(0, u64::MAX), vec![1, 2, 3, 4, 5]);
+    assert_eq!(seqs(100, 256), vec![1, 2, 3]);
+    assert_eq!(seqs(250, 251), vec![2, 3]);
+    assert_eq!(seqs(101, 1001), vec![2, 3, 4, 5]);
+    assert!(seqs(0, 100).is_empty());
+    assert!(seqs(256, 256).is_empty());
+
+    // replaced after a chain reorg
+    tx_store.put_tx(tx(2, 2000)).unwrap();
+    assert_eq!(seqs(250, 251), vec![3]);
+    assert_eq!(seqs(1000, 3000), vec![5, 2]);
+}
+
+#[test]
+fn test_migrate_legacy_txs() {
+    let db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let legacy_tx = |seq: u64| TransactionV1 {
+        stream_ids: vec![],
+        data: vec![],
+        data_merkle_root: DataRoot::from_low_u64_be(seq % 2),
+        merkle_nodes: vec![],
+        start_entry_index: seq,
+        size: 1,
+        seq,
+    };
+    let mut db_tx = db.transaction();
+    for seq in 0..3u64 {
+        db_tx.put(COL_TX, &seq.to_be_bytes(), &legacy_tx(seq).as_ssz_bytes());
+    }
+    db.write(db_tx).unwrap();
+    schema::migrate(db.as_ref()).unwrap();
+
+    let tx_store = TransactionStore::new(db);
+    let txs = tx_store
+        .get_txs_by_data_root(&DataRoot::from_low_u64_be(0))
+        .unwrap();
+    assert_eq!(txs.iter().map(|tx| tx.seq).collect::<Vec<_>>(), vec![0, 2]);
+    let tx = tx_store.get_tx_by_seq_number(1).unwrap().unwrap();
+    assert_eq!(tx.data_merkle_root, DataRoot::from_low_u64_be(1));
+    assert_eq!(tx.sender, Address::zero());
+    assert!(tx_store
+        .get_txs_by_sender(&Address::zero(), 0, 10)
+        .unwrap()
+        .is_empty());
+    assert_eq!(tx.block_timestamp, 0);
+    assert!(tx_store
+        .get_txs_in_time_range(0, u64::MAX)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_migrate_pending_txs() {
+    let db = Arc::new(kvdb_memorydb::create(COL_NUM));
+    let legacy = PendingTxV1 {
+        size: 1000,
+        staged: vec![(0, 2)],
+        verified: false,
+    };
+    let data_root = DataRoot::from_low_u64_be(1);
+    let mut db_tx = db.transaction();
+    db_tx.put(COL_PENDING_TX, data_root.as_bytes(), &legacy.as_ssz_bytes());
+    db.write(db_tx).unwrap();
+    schema::migrate(db.as_ref()).unwrap();
+
+    let store = PendingStore::new(db, PendingConfig::default());
+    let pending = store.get_tx(&data_root).unwrap().unwrap();
+    assert_eq!(pending.size, 1000);
+    assert_eq!(pending.staged, vec![(0, 2)]);
+    assert!(pending.updated_at > 0);
+    // not trusted since it may be staged from peers
+    assert!(pending.announced);
+}
+
+#[test]
+fn test_schema_migration() {
+    let db = kvdb_memorydb::create(COL_NUM);
+    schema::migrate(&db).unwrap();
+    assert_eq!(
+        get_schema_version(&db).unwrap(),
+        Some(schema::schema_version())
+    );
+
+    let num_entries = MIGRATION_BATCH_SIZE as u64 * 2 + 500;
+    let mut db_tx = db.transaction();
+    for i in 0..num_entries {
+        db_tx.put(COL_FILE_METADATA, &i.to_be_bytes(), &[]);
+    }
+    db.write(db_tx).unwrap();
+
+    // the migrations are applied in order, along with the versions
+    let migrations = [
+        Migration {
+            version: schema::schema_version() + 1,
+            description: "first",
+            column: COL_FILE_METADATA,
+            migrate: |db, db_tx, key, value| {
+                if key == (MIGRATION_BATCH_SIZE as u64 + 1).to_be_bytes()
+                    && db.get(COL_MISC, b"interrupt")?.is_some()
+                {
+                    bail!("interrupted");
+                }
+                db_tx.put(COL_FILE_METADATA, key, &[value, b"1"].concat());
+                Ok(())
+            },
+        },
+        Migration {
+            version: schema::schema_version() + 2,
+            description: "second",
+            column: COL_FILE_METADATA,
+            migrate: |_, db_tx, key, value| {
+                assert_eq!(value, b"1");
+                db_tx.put(COL_FILE_METADATA, key, &[value, b"2"].concat());
+                Ok(())
+            },
+        },
+    ];
+    let migrated = |value: &[u8]| -> usize {
+        db.iter(COL_FILE_METADATA)
+            .filter(|(_, v)| v.as_ref() == value)
+            .count()
+    };
+
+    // only the batches before the interruption are committed
+    db.put(COL_MISC, b"interrupt", &[]).unwrap();
+    assert!(schema::migrate_with(&db, &migrations).is_err());
+    assert_eq!(
+        get_schema_version(&db).unwrap(),
+        Some(schema::schema_version())
+    );
+    assert_eq!(migrated(b"1"), MIGRATION_BATCH_SIZE);
+
+    // resumed after the last committed batch, so each entry is migrated exactly once
+    db.delete(COL_MISC, b"interrupt").unwrap();
+    schema::migrate_with(&db, &migrations).unwrap();
+    assert_eq!(
+        get_schema_version(&db).unwrap(),
+        Some(schema::schema_version() + 2)
+    );
+    assert_eq!(migrated(b"12"), num_entries as usize);
+
+    // a newer database is refused
+    assert!(schema::migrate(&db).is_err());
+}
+
+#[test]
+fn test_export_import_file() {
+    let dir = TempDir::new("local_file").unwrap();
+    let path = dir.path().join("file");
+    let chunk_count = PORA_CHUNK_SIZE + 3;
+
+    let mut source = create_store();
+    put_tx(&mut source, chunk_count, 0, 1);
+    export_file(&source, 0, &path).unwrap();
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().len(),
+        (chunk_count * CHUNK_SIZE) as u64
+    );
+
+    let tx = source.get_tx_by_seq_number(0).unwrap().unwrap();
+    let mut target = create_store();
+    target.put_tx(tx).unwrap();
+    // not finalized yet
+    assert!(export_file(&target, 0, &path).is_err());
+
+    // the data that mismatches the data root is rejected
+    let mut data = std::fs::read(&path).unwrap();
+    data[0] = !data[0];
+    let corrupted = dir.path().join("corrupted");
+    std::fs::write(&corrupted, &data).unwrap();
+    assert!(import_file(&mut target, 0, &corrupted).is_err());
+    assert_eq!(target.get_chunk_ranges(0).unwrap(), Some(vec![]));
+
+    import_file(&mut target, 0, &path).unwrap();
+    target.finalize_tx(0).unwrap();
+    assert_eq!(
+        target
+            .get_chunks_by_tx_and_index_range(0, 0, chunk_count)
+            .unwrap(),
+        source
+            .get_chunks_by_tx_and_index_range(0, 0, chunk_count)
+            .unwrap()
+    );
+}
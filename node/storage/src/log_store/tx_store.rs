@@ -1,17 +1,75 @@
 use crate::error::Error;
+use crate::log_store::file_index::{self, FileQuery};
 use crate::log_store::log_manager::{
-    sub_merkle_tree, COL_MISC, COL_TX, COL_TX_COMPLETED, COL_TX_DATA_ROOT_INDEX, ENTRY_SIZE,
+    sub_merkle_tree, COL_FILE_METADATA, COL_FILE_SYNC_PROGRESS, COL_LOG_SYNC_CHECKPOINT, COL_MISC,
+    COL_PINNED_FILE, COL_TX, COL_TX_BY_DATA_ROOT, COL_TX_BY_SENDER, COL_TX_BY_TIME,
+    COL_TX_COMPLETED, COL_TX_DATA_ROOT_INDEX, ENTRY_SIZE,
 };
 use crate::{try_option, IonianKeyValueDB};
-use anyhow::{anyhow, Result};
-use ethereum_types::H256;
-use shared_types::{DataRoot, Transaction};
+use anyhow::{anyhow, bail, Result};
+use ethereum_types::{Address, H256};
+use shared_types::{timestamp_now, DataRoot, FileMetadata, Transaction};
 use ssz::{Decode, Encode};
+use ssz_derive::{Decode as DeriveDecode, Encode as DeriveEncode};
 use std::sync::Arc;
 use tracing::instrument;
 
+/// Key of the log sync progress before the checkpoints are introduced.
 const LOG_SYNC_PROGRESS_KEY: &str = "log_sync_progress";
 
+/// Maximum number of log sync checkpoints kept, to revert the log sync upon a chain reorg.
+pub const MAX_LOG_SYNC_CHECKPOINTS: usize = 256;
+
+/// A block whose transactions are all synced from the log contract, which is persisted to resume
+/// the log sync after restart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct LogSyncCheckpoint {
+    pub block_number: u64,
+    pub block_hash: H256,
+    /// The sequence number of the first transaction after the block.
+    pub next_tx_seq: u64,
+}
+
+/// A peer that file chunks have been downloaded from.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct FileSyncPeer {
+    /// Encoded peer id.
+    pub peer_id: Vec<u8>,
+    /// Encoded multiaddr.
+    pub addr: Vec<u8>,
+}
+
+/// Progress of syncing a file from peers, which is persisted to resume the sync after restart.
+#[derive(Clone, Debug, Default, PartialEq, Eq, DeriveEncode, DeriveDecode)]
+pub struct FileSyncProgress {
+    /// Downloaded chunk index ranges (`end` excluded).
+    pub downloaded: Vec<(u64, u64)>,
+    /// Peers that chunks have been downloaded from.
+    pub peers: Vec<FileSyncPeer>,
+    /// Number of attempts to sync the file, which increases on each retry after failure.
+    pub attempts: u32,
+}
+
+/// Filters of the transactions listed by `get_tx_list`, which all apply if set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxListFilter {
+    /// Only lists the finalized transactions.
+    pub finalized_only: bool,
+    /// Minimum file size in bytes (included).
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes (included).
+    pub max_size: Option<u64>,
+    /// Only lists the transactions submitted by the account.
+    pub sender: Option<Address>,
+}
+
+impl TxListFilter {
+    fn matches_size(&self, size: u64) -> bool {
+        self.min_size.map_or(true, |min_size| size >= min_size)
+            && self.max_size.map_or(true, |max_size| size <= max_size)
+    }
+}
+
 pub struct TransactionStore {
     kvdb: Arc<dyn IonianKeyValueDB>,
 }
@@ -36,7 +94,47 @@ impl TransactionStore {
             tx.data_merkle_root = data_root.into();
         }
 
-        db_tx.put(COL_TX, &tx.seq.to_be_bytes(), &tx.as_ssz_bytes());
+        // The transaction reverted by a chain reorg is replaced.
+        if let Some(old_tx) = self.get_tx_by_seq_number(tx.seq)? {
+            db_tx.delete(
+                COL_TX_BY_DATA_ROOT,
+                &data_root_key(&old_tx.data_merkle_root, old_tx.seq),
+            );
+            db_tx.delete(COL_TX_BY_SENDER, &sender_key(&old_tx.sender, old_tx.seq));
+            db_tx.delete(
+                COL_TX_BY_TIME,
+                &time_key(old_tx.block_timestamp, old_tx.seq),
+            );
+            file_index::delete_tx(&mut db_tx, &old_tx, self.get_upload_time(old_tx.seq)?);
+            // The metadata is of the file uploaded for the old transaction.
+            if old_tx.data_merkle_root != tx.data_merkle_root {
+                if let Some(metadata) = self.get_file_metadata(old_tx.seq)? {
+                    file_index::delete_tags(&mut db_tx, old_tx.seq, &metadata.tags);
+                }
+                db_tx.delete(COL_FILE_METADATA, &old_tx.seq.to_be_bytes());
+            }
+        }
+
+        // The upload time never decreases along the tx seqs, even if the clock goes backwards.
+        let prev_time = match tx.seq.checked_sub(1) {
+            Some(prev_seq) => self.get_upload_time(prev_seq)?,
+            None => None,
+        };
+        let upload_time = prev_time.map_or(timestamp_now(), |time| time.max(timestamp_now()));
+        file_index::put_tx(&mut db_tx, &tx, upload_time);
+
+        let encoded = tx.as_ssz_bytes();
+        db_tx.put(COL_TX, &tx.seq.to_be_bytes(), &encoded);
+        db_tx.put(
+            COL_TX_BY_DATA_ROOT,
+            &data_root_key(&tx.data_merkle_root, tx.seq),
+            &encoded,
+        );
+        db_tx.put(COL_TX_BY_SENDER, &sender_key(&tx.sender, tx.seq), &[]);
+        // The block timestamp is unknown for the transactions synced before it is recorded.
+        if tx.block_timestamp != 0 {
+            db_tx.put(COL_TX_BY_TIME, &time_key(tx.block_timestamp, tx.seq), &[]);
+        }
         if self
             .get_tx_seq_by_data_root(&tx.data_merkle_root)?
             .is_none()
@@ -65,6 +163,169 @@ impl TransactionStore {
         Ok(Some(decode_tx_seq(&value)?))
     }
 
+    /// Returns the transactions of the data root in ascending order of sequence numbers, which
+    /// are read in a single prefix scan.
+    pub fn get_txs_by_data_root(&self, data_root: &DataRoot) -> Result<Vec<Transaction>> {
+        self.kvdb
+            .iter_with_prefix(COL_TX_BY_DATA_ROOT, data_root.as_bytes())
+            .map(|(_, v)| Ok(Transaction::from_ssz_bytes(&v).map_err(Error::from)?))
+            .collect()
+    }
+
+    /// Returns the transactions submitted by `sender` in ascending order of sequence numbers,
+    /// which are looked up by the sender index after skipping the first `skip` ones.
+    pub fn get_txs_by_sender(
+        &self,
+        sender: &Address,
+        skip: usize,
+        limit: usize,
+    ) -> Result<Vec<Transaction>> {
+        let mut txs = vec![];
+        for (key, _) in self
+            .kvdb
+            .iter_with_prefix(COL_TX_BY_SENDER, sender.as_bytes())
+            .skip(skip)
+            .take(limit)
+        {
+            let tx_seq = decode_tx_seq(&key[Address::len_bytes()..])?;
+            let tx = self
+                .get_tx_by_seq_number(tx_seq)?
+                .ok_or_else(|| anyhow!("missing tx of sender index: tx_seq={}", tx_seq))?;
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+
+    /// Returns the transactions whose block timestamps are in `[start_time, end_time)`, in
+    /// ascending order of block timestamps and then sequence numbers. The transactions without
+    /// a known block timestamp are not included.
+    pub fn get_txs_in_time_range(
+        &self,
+        start_time: u64,
+        end_time: u64,
+    ) -> Result<Vec<Transaction>> {
+        let mut txs = vec![];
+        if start_time >= end_time {
+            return Ok(txs);
+        }
+        // Only the keys sharing the common prefix of the range bounds are scanned.
+        let (start, last) = (start_time.to_be_bytes(), (end_time - 1).to_be_bytes());
+        let prefix_len = start.iter().zip(&last).take_while(|(a, b)| a == b).count();
+        for (key, _) in self
+            .kvdb
+            .iter_with_prefix(COL_TX_BY_TIME, &start[..prefix_len])
+        {
+            let (time, tx_seq) = decode_time_key(&key)?;
+            if time < start_time {
+                continue;
+            }
+            if time >= end_time {
+                break;
+            }
+            let tx = self
+                .get_tx_by_seq_number(tx_seq)?
+                .ok_or_else(|| anyhow!("missing tx of time index: tx_seq={}", tx_seq))?;
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+
+    /// Returns at most `limit` transactions from `start_seq` that match the filter, in ascending
+    /// order of sequence numbers.
+    pub fn get_tx_list(
+        &self,
+        start_seq: u64,
+        limit: usize,
+        filter: &TxListFilter,
+    ) -> Result<Vec<Transaction>> {
+        let mut txs = vec![];
+        if limit == 0 {
+            return Ok(txs);
+        }
+
+        // TODO: Seek to `start_seq` once `kvdb` supports it, and only the keys are skipped now.
+        for (key, value) in self.kvdb.iter(COL_TX) {
+            if decode_tx_seq(&key)? < start_seq {
+                continue;
+            }
+
+            let tx = Transaction::from_ssz_bytes(&value).map_err(Error::from)?;
+            if !filter.matches_size(tx.size)
+                || filter.sender.map_or(false, |sender| sender != tx.sender)
+                || (filter.finalized_only && !self.check_tx_completed(tx.seq)?)
+            {
+                continue;
+            }
+
+            txs.push(tx);
+            if txs.len() >= limit {
+                break;
+            }
+        }
+
+        Ok(txs)
+    }
+
+    /// Returns at most `limit` files from `start_seq` that match the query, in ascending order of
+    /// sequence numbers. The files are scanned by the index that narrows down the query the most,
+    /// and checked against the other filters.
+    pub fn search_files(
+        &self,
+        query: &FileQuery,
+        start_seq: u64,
+        limit: usize,
+    ) -> Result<Vec<Transaction>> {
+        let mut txs = vec![];
+        if limit == 0 {
+            return Ok(txs);
+        }
+
+        file_index::scan(self.kvdb.as_ref(), query, |tx_seq| {
+            // TODO: Seek to `start_seq` once `kvdb` supports it, and only the keys are skipped now.
+            if tx_seq < start_seq {
+                return Ok(true);
+            }
+
+            let tx = self
+                .get_tx_by_seq_number(tx_seq)?
+                .ok_or_else(|| anyhow!("missing tx of file index: tx_seq={}", tx_seq))?;
+            if self.matches_query(query, &tx)? {
+                txs.push(tx);
+            }
+            Ok(txs.len() < limit)
+        })?;
+
+        Ok(txs)
+    }
+
+    /// Returns the time when the transaction is synced by the node, in seconds since the Unix
+    /// epoch.
+    pub fn get_upload_time(&self, tx_seq: u64) -> Result<Option<u32>> {
+        file_index::get_upload_time(self.kvdb.as_ref(), tx_seq)
+    }
+
+    fn matches_query(&self, query: &FileQuery, tx: &Transaction) -> Result<bool> {
+        if !query.matches_size(tx.size) || query.sender.map_or(false, |sender| sender != tx.sender)
+        {
+            return Ok(false);
+        }
+
+        for tag in &query.tags {
+            if !file_index::has_tag(self.kvdb.as_ref(), tag, tx.seq)? {
+                return Ok(false);
+            }
+        }
+
+        if query.has_time_filter() {
+            match self.get_upload_time(tx.seq)? {
+                Some(time) if query.matches_time(time) => {}
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(true)
+    }
+
     #[instrument(skip(self))]
     pub fn finalize_tx(&self, tx_seq: u64) -> Result<()> {
         Ok(self
@@ -86,17 +347,48 @@ impl TransactionStore {
             .unwrap_or(Ok(0))
     }
 
+    /// Stores the checkpoint of the latest synced block, which removes the checkpoints of the
+    /// later blocks reverted by a chain reorg, and the oldest checkpoints beyond
+    /// `MAX_LOG_SYNC_CHECKPOINTS`.
     #[instrument(skip(self))]
-    pub fn put_progress(&self, progress: (u64, H256)) -> Result<()> {
-        Ok(self.kvdb.put(
-            COL_MISC,
-            LOG_SYNC_PROGRESS_KEY.as_bytes(),
-            &progress.as_ssz_bytes(),
-        )?)
+    pub fn put_log_sync_checkpoint(&self, checkpoint: &LogSyncCheckpoint) -> Result<()> {
+        let (earlier, later): (Vec<u64>, Vec<u64>) = self
+            .get_log_sync_checkpoints()?
+            .into_iter()
+            .map(|checkpoint| checkpoint.block_number)
+            .filter(|block_number| *block_number != checkpoint.block_number)
+            .partition(|block_number| *block_number < checkpoint.block_number);
+        let num_pruned = (earlier.len() + 1).saturating_sub(MAX_LOG_SYNC_CHECKPOINTS);
+
+        let mut db_tx = self.kvdb.transaction();
+        for block_number in earlier.iter().take(num_pruned).chain(later.iter()) {
+            db_tx.delete(COL_LOG_SYNC_CHECKPOINT, &block_number.to_be_bytes());
+        }
+        db_tx.put(
+            COL_LOG_SYNC_CHECKPOINT,
+            &checkpoint.block_number.to_be_bytes(),
+            &checkpoint.as_ssz_bytes(),
+        );
+        self.kvdb.write(db_tx)?;
+        Ok(())
     }
 
+    /// Returns the log sync checkpoints in the ascending order of block numbers.
+    pub fn get_log_sync_checkpoints(&self) -> Result<Vec<LogSyncCheckpoint>> {
+        self.kvdb
+            .iter(COL_LOG_SYNC_CHECKPOINT)
+            .map(|(_, v)| Ok(LogSyncCheckpoint::from_ssz_bytes(&v).map_err(Error::from)?))
+            .collect()
+    }
+
+    /// Returns the latest synced block, or the progress persisted before the checkpoints are
+    /// introduced.
     #[instrument(skip(self))]
     pub fn get_progress(&self) -> Result<Option<(u64, H256)>> {
+        if let Some(checkpoint) = self.get_log_sync_checkpoints()?.last() {
+            return Ok(Some((checkpoint.block_number, checkpoint.block_hash)));
+        }
+
         Ok(Some(
             <(u64, H256)>::from_ssz_bytes(&try_option!(self
                 .kvdb
@@ -104,6 +396,118 @@ impl TransactionStore {
             .map_err(Error::from)?,
         ))
     }
+
+    pub fn put_file_sync_progress(&self, tx_seq: u64, progress: &FileSyncProgress) -> Result<()> {
+        Ok(self.kvdb.put(
+            COL_FILE_SYNC_PROGRESS,
+            &tx_seq.to_be_bytes(),
+            &progress.as_ssz_bytes(),
+        )?)
+    }
+
+    pub fn delete_file_sync_progress(&self, tx_seq: u64) -> Result<()> {
+        Ok(self
+            .kvdb
+            .delete(COL_FILE_SYNC_PROGRESS, &tx_seq.to_be_bytes())?)
+    }
+
+    pub fn get_all_file_sync_progress(&self) -> Result<Vec<(u64, FileSyncProgress)>> {
+        self.kvdb
+            .iter(COL_FILE_SYNC_PROGRESS)
+            .map(|(k, v)| {
+                let progress = FileSyncProgress::from_ssz_bytes(&v).map_err(Error::from)?;
+                Ok((decode_tx_seq(k.as_ref())?, progress))
+            })
+            .collect()
+    }
+
+    /// Stores the metadata of a file, and replaces its tags in the file index.
+    pub fn put_file_metadata(&self, tx_seq: u64, metadata: &FileMetadata) -> Result<()> {
+        let mut db_tx = self.kvdb.transaction();
+        if let Some(old_metadata) = self.get_file_metadata(tx_seq)? {
+            file_index::delete_tags(&mut db_tx, tx_seq, &old_metadata.tags);
+        }
+        file_index::put_tags(&mut db_tx, tx_seq, &metadata.tags);
+        db_tx.put(
+            COL_FILE_METADATA,
+            &tx_seq.to_be_bytes(),
+            &metadata.as_ssz_bytes(),
+        );
+        Ok(self.kvdb.write(db_tx)?)
+    }
+
+    pub fn get_file_metadata(&self, tx_seq: u64) -> Result<Option<FileMetadata>> {
+        let value = try_option!(self.kvdb.get(COL_FILE_METADATA, &tx_seq.to_be_bytes())?);
+        Ok(Some(
+            FileMetadata::from_ssz_bytes(&value).map_err(Error::from)?,
+        ))
+    }
+
+    /// Pins the file of `data_root`, and returns `false` if already pinned. The file need not be
+    /// stored yet.
+    pub fn pin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        if self.is_pinned(data_root)? {
+            return Ok(false);
+        }
+        self.kvdb.put(COL_PINNED_FILE, data_root.as_bytes(), &[])?;
+        Ok(true)
+    }
+
+    /// Unpins the file of `data_root`, and returns `false` if not pinned.
+    pub fn unpin_file(&self, data_root: &DataRoot) -> Result<bool> {
+        if !self.is_pinned(data_root)? {
+            return Ok(false);
+        }
+        self.kvdb.delete(COL_PINNED_FILE, data_root.as_bytes())?;
+        Ok(true)
+    }
+
+    pub fn is_pinned(&self, data_root: &DataRoot) -> Result<bool> {
+        Ok(self.kvdb.has_key(COL_PINNED_FILE, data_root.as_bytes())?)
+    }
+
+    pub fn get_pinned_files(&self) -> Result<Vec<DataRoot>> {
+        self.kvdb
+            .iter(COL_PINNED_FILE)
+            .map(|(key, _)| {
+                if key.len() != DataRoot::len_bytes() {
+                    bail!("invalid pinned file key {:?}", key);
+                }
+                Ok(DataRoot::from_slice(&key))
+            })
+            .collect()
+    }
+}
+
+/// The key of a transaction in `COL_TX_BY_DATA_ROOT`, so that the transactions of a data root
+/// are adjacent and sorted by sequence numbers.
+pub(crate) fn data_root_key(data_root: &DataRoot, tx_seq: u64) -> Vec<u8> {
+    let mut key = data_root.as_bytes().to_vec();
+    key.extend_from_slice(&tx_seq.to_be_bytes());
+    key
+}
+
+/// The key of a transaction in `COL_TX_BY_SENDER`, so that the transactions of a sender are
+/// adjacent and sorted by sequence numbers.
+fn sender_key(sender: &Address, tx_seq: u64) -> Vec<u8> {
+    let mut key = sender.as_bytes().to_vec();
+    key.extend_from_slice(&tx_seq.to_be_bytes());
+    key
+}
+
+/// The key of a transaction in `COL_TX_BY_TIME`, so that the transactions are sorted by block
+/// timestamps and then sequence numbers.
+fn time_key(block_timestamp: u64, tx_seq: u64) -> Vec<u8> {
+    let mut key = block_timestamp.to_be_bytes().to_vec();
+    key.extend_from_slice(&tx_seq.to_be_bytes());
+    key
+}
+
+fn decode_time_key(key: &[u8]) -> Result<(u64, u64)> {
+    if key.len() != 16 {
+        bail!("invalid time index key {:?}", key);
+    }
+    Ok((decode_tx_seq(&key[..8])?, decode_tx_seq(&key[8..])?))
 }
 
 fn decode_tx_seq(data: &[u8]) -> Result<u64> {
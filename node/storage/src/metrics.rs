@@ -0,0 +1,36 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref PUT_TX_TIMES: Result<Histogram> = try_create_histogram(
+        "store_put_tx_seconds",
+        "Time taken to store a new transaction"
+    );
+    pub static ref PUT_CHUNKS_TIMES: Result<Histogram> = try_create_histogram(
+        "store_put_chunks_seconds",
+        "Time taken to store a batch of file chunks"
+    );
+    pub static ref PUT_CHUNKS_BYTES: Result<IntCounter> = try_create_int_counter(
+        "store_put_chunks_bytes_total",
+        "Count of file chunk bytes written to the store"
+    );
+    pub static ref FINALIZED_TX_COUNT: Result<IntCounter> = try_create_int_counter(
+        "store_finalized_tx_total",
+        "Count of transactions finalized with complete file data"
+    );
+    pub static ref PROMOTED_TX_COUNT: Result<IntCounter> = try_create_int_counter(
+        "store_promoted_tx_total",
+        "Count of transactions finalized with the file data staged before they are synced"
+    );
+    pub static ref NEXT_TX_SEQ: Result<IntGauge> = try_create_int_gauge(
+        "store_next_tx_seq",
+        "Sequence number of the next transaction to store"
+    );
+    pub static ref STARTUP_CHUNK_ROOTS: Result<IntGauge> = try_create_int_gauge(
+        "store_startup_chunk_roots",
+        "Number of PoRA chunk roots loaded to reconstruct the flow merkle tree upon startup"
+    );
+    pub static ref STARTUP_MERKLE_TIMES: Result<Histogram> = try_create_histogram(
+        "store_startup_merkle_seconds",
+        "Time taken to reconstruct the flow merkle tree upon startup"
+    );
+}
@@ -0,0 +1,322 @@
+use crate::context::SyncNetworkContext;
+use crate::controllers::PeerReputation;
+use crate::metrics;
+use file_location_cache::FileLocationCache;
+use network::{rpc::GetChunksRequest, NetworkMessage, PeerAction, PeerId, SyncId as RequestId};
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use shared_types::{bytes_to_chunks, ChunkArrayWithProof, DataRoot, CHUNK_SIZE};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::error::Result;
+use storage_async::Store;
+use tokio::time::Instant;
+
+/// Timeout of an audit request.
+const AUDIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of random transactions to try for a finalized one in each audit.
+const MAX_TX_PICKS: usize = 8;
+
+struct PendingAudit {
+    peer_id: PeerId,
+    tx_seq: u64,
+    index: u64,
+    since: Instant,
+}
+
+/// Periodically audits a random chunk of a random finalized file from a peer that announced the
+/// file, which validates the chunk proof and updates the reputation of the peer, so that peers
+/// claiming to store files without doing so are found out.
+pub(crate) struct Auditor {
+    /// Interval between audits.
+    interval: Duration,
+
+    last_audit: Instant,
+
+    /// At most one audit is in progress.
+    pending: Option<PendingAudit>,
+
+    /// Peers connected to the sync service, which could be audited without dialing.
+    connected_peers: HashSet<PeerId>,
+
+    ctx: Arc<SyncNetworkContext>,
+    store: Store,
+    file_location_cache: Arc<FileLocationCache>,
+    reputation: Arc<PeerReputation>,
+}
+
+impl Auditor {
+    pub fn new(
+        interval: Duration,
+        ctx: Arc<SyncNetworkContext>,
+        store: Store,
+        file_location_cache: Arc<FileLocationCache>,
+        reputation: Arc<PeerReputation>,
+    ) -> Self {
+        Auditor {
+            interval,
+            last_audit: Instant::now(),
+            pending: None,
+            connected_peers: Default::default(),
+            ctx,
+            store,
+            file_location_cache,
+            reputation,
+        }
+    }
+
+    pub fn on_peer_connected(&mut self, peer_id: PeerId) {
+        self.connected_peers.insert(peer_id);
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer_id: PeerId) {
+        self.connected_peers.remove(&peer_id);
+    }
+
+    /// Starts a new audit if the interval elapsed, and fails the pending audit upon timeout.
+    pub async fn on_heartbeat(&mut self) {
+        if let Some(pending) = &self.pending {
+            if pending.since.elapsed() < AUDIT_TIMEOUT {
+                return;
+            }
+
+            let peer_id = pending.peer_id;
+            warn!(%peer_id, tx_seq = %pending.tx_seq, "Audit request timeout");
+            self.pending = None;
+            self.reputation.on_timeout(peer_id);
+            metrics::inc_counter(&metrics::SYNC_AUDITS_FAILED);
+        }
+
+        if self.interval.is_zero() || self.last_audit.elapsed() < self.interval {
+            return;
+        }
+
+        self.last_audit = Instant::now();
+
+        if let Err(err) = self.start_audit().await {
+            warn!(%err, "Failed to start audit");
+        }
+    }
+
+    async fn start_audit(&mut self) -> Result<()> {
+        let next_tx_seq = self.store.next_tx_seq().await?;
+        if next_tx_seq == 0 {
+            return Ok(());
+        }
+
+        for _ in 0..MAX_TX_PICKS {
+            let tx_seq = crate::rng().gen_range(0..next_tx_seq);
+            if !self.store.check_tx_completed(tx_seq).await? {
+                continue;
+            }
+
+            let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+                Some(tx) => tx,
+                None => continue,
+            };
+
+            let peer_id = match self.random_provider(tx_seq, &tx.data_merkle_root) {
+                Some(peer_id) => peer_id,
+                None => continue,
+            };
+
+            let num_chunks = bytes_to_chunks(tx.size as usize).max(1);
+            let index = crate::rng().gen_range(0..num_chunks) as u64;
+
+            debug!(%peer_id, %tx_seq, %index, "Audit chunk of peer");
+
+            self.ctx.send(NetworkMessage::SendRequest {
+                peer_id,
+                request_id: network::RequestId::Sync(RequestId::Audit { tx_seq }),
+                request: network::Request::GetChunks(GetChunksRequest {
+                    tx_seq,
+                    index_start: index,
+                    index_end: index + 1,
+                }),
+            });
+
+            self.pending = Some(PendingAudit {
+                peer_id,
+                tx_seq,
+                index,
+                since: Instant::now(),
+            });
+
+            return Ok(());
+        }
+
+        debug!("No finalized file with connected providers to audit");
+
+        Ok(())
+    }
+
+    /// Returns a random connected peer that announced to store the file.
+    fn random_provider(&self, tx_seq: u64, data_root: &DataRoot) -> Option<PeerId> {
+        let providers = self
+            .file_location_cache
+            .get_providers(data_root)
+            .into_iter()
+            .map(|(peer_id, _)| peer_id);
+
+        let announcers = self
+            .file_location_cache
+            .get_all(tx_seq)
+            .into_iter()
+            .map(|announcement| -> PeerId { announcement.peer_id.clone().into() });
+
+        providers
+            .chain(announcers)
+            .filter(|peer_id| self.connected_peers.contains(peer_id))
+            .filter(|peer_id| !self.reputation.is_banned(peer_id))
+            .choose(&mut crate::rng())
+    }
+
+    /// Takes the pending audit of `peer_id` for `tx_seq`, if any.
+    fn take_pending(&mut self, peer_id: PeerId, tx_seq: u64) -> Option<PendingAudit> {
+        match &self.pending {
+            Some(pending) if pending.peer_id == peer_id && pending.tx_seq == tx_seq => {
+                self.pending.take()
+            }
+            _ => None,
+        }
+    }
+
+    pub async fn on_response(
+        &mut self,
+        peer_id: PeerId,
+        tx_seq: u64,
+        response: ChunkArrayWithProof,
+    ) {
+        let pending = match self.take_pending(peer_id, tx_seq) {
+            Some(pending) => pending,
+            None => {
+                debug!(%peer_id, %tx_seq, "Received audit response for unknown request");
+                return;
+            }
+        };
+
+        let valid = if response.chunks.start_index != pending.index
+            || response.chunks.data.len() != CHUNK_SIZE
+        {
+            false
+        } else {
+            match self.store.validate_range_proof(tx_seq, response).await {
+                Ok(true) => true,
+                Ok(false) => {
+                    // the proof root may not be synced locally yet
+                    debug!(%peer_id, %tx_seq, "Unable to validate audit response: no root found");
+                    return;
+                }
+                Err(err) => {
+                    debug!(%err, "Invalid audit response");
+                    false
+                }
+            }
+        };
+
+        if valid {
+            debug!(%peer_id, %tx_seq, "Peer passed audit");
+            self.reputation
+                .on_response(peer_id, pending.since.elapsed());
+            metrics::inc_counter(&metrics::SYNC_AUDITS_PASSED);
+        } else {
+            info!(%peer_id, %tx_seq, index = %pending.index, "Peer failed audit with invalid chunk");
+            self.reputation.on_invalid_proof(peer_id);
+            self.ctx.report_peer(
+                peer_id,
+                PeerAction::LowToleranceError,
+                "Invalid audit response",
+            );
+            metrics::inc_counter(&metrics::SYNC_AUDITS_FAILED);
+        }
+    }
+
+    /// Peers that announced the file but fail to serve it are penalized as well.
+    pub fn on_request_failed(&mut self, peer_id: PeerId, tx_seq: u64) {
+        if self.take_pending(peer_id, tx_seq).is_some() {
+            info!(%peer_id, %tx_seq, "Peer failed audit with RPC error");
+            self.reputation.on_timeout(peer_id);
+            metrics::inc_counter(&metrics::SYNC_AUDITS_FAILED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::{create_2_store, create_file_location_cache};
+    use libp2p::identity;
+    use network::Request;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_audit_peer() {
+        let runtime = TestRuntime::default();
+
+        // the local store has the file finalized
+        let (_, store, _, _) = create_2_store(vec![1535]);
+        let store = Store::new(store, runtime.task_executor.clone());
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let file_location_cache = create_file_location_cache(peer_id, 1);
+        let reputation: Arc<PeerReputation> = Default::default();
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let mut auditor = Auditor::new(
+            Duration::from_millis(1),
+            Arc::new(SyncNetworkContext::new(network_send)),
+            store.clone(),
+            file_location_cache,
+            reputation.clone(),
+        );
+
+        // no connected peer to audit
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        auditor.on_heartbeat().await;
+        assert!(network_recv.try_recv().is_err());
+
+        auditor.on_peer_connected(peer_id);
+
+        for valid in [true, false] {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            auditor.on_heartbeat().await;
+
+            let (to, request) = match network_recv.try_recv().unwrap() {
+                NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::GetChunks(request),
+                    ..
+                } => (peer_id, request),
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            assert_eq!(to, peer_id);
+            assert_eq!(request.index_end, request.index_start + 1);
+
+            let mut response = store
+                .get_chunks_with_proof_by_tx_and_index_range(
+                    request.tx_seq,
+                    request.index_start as usize,
+                    request.index_end as usize,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            if !valid {
+                response.chunks.data[0] ^= 1;
+            }
+
+            let score = reputation.score(&peer_id);
+            auditor.on_response(peer_id, request.tx_seq, response).await;
+            assert!(auditor.pending.is_none());
+
+            if valid {
+                assert!(reputation.score(&peer_id) <= score);
+            } else {
+                assert!(reputation.score(&peer_id) < score);
+            }
+        }
+    }
+}
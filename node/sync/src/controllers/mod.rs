@@ -1,4 +1,12 @@
 mod peers;
+mod reputation;
+mod request_limiter;
 mod serial;
+mod verify_queue;
+mod write_queue;
 
+pub use reputation::PeerReputation;
+pub use request_limiter::PeerRequestLimiter;
 pub use serial::{SerialSyncController, SyncState};
+pub use verify_queue::{ChunkVerifyQueue, VerifyResult};
+pub use write_queue::{ChunkWriteQueue, WriteResult};
@@ -1,7 +1,8 @@
 use network::{Multiaddr, PeerId};
 use rand::seq::IteratorRandom;
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use tokio::time::Instant;
 
 const PEER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 const PEER_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
@@ -22,6 +23,9 @@ struct PeerInfo {
     /// The current state of the peer.
     pub state: PeerState,
 
+    /// Chunk ranges available on the peer, or `None` if the peer has the whole file.
+    pub ranges: Option<Vec<(u64, u64)>>,
+
     /// Timestamp of the last state change.
     pub since: Instant,
 }
@@ -40,7 +44,19 @@ pub struct SyncPeers {
 
 impl SyncPeers {
     pub fn add_new_peer(&mut self, peer_id: PeerId, addr: Multiaddr) -> bool {
-        if self.peers.contains_key(&peer_id) {
+        self.add_new_peer_with_ranges(peer_id, addr, None)
+    }
+
+    /// Adds a peer that may only have some chunk `ranges` of the file. If the peer exists
+    /// already, the available ranges are updated instead.
+    pub fn add_new_peer_with_ranges(
+        &mut self,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        ranges: Option<Vec<(u64, u64)>>,
+    ) -> bool {
+        if let Some(info) = self.peers.get_mut(&peer_id) {
+            info.ranges = ranges;
             return false;
         }
 
@@ -49,6 +65,7 @@ impl SyncPeers {
             PeerInfo {
                 addr,
                 state: PeerState::Found,
+                ranges,
                 since: Instant::now(),
             },
         );
@@ -83,12 +100,34 @@ impl SyncPeers {
         self.peers.get(peer_id).map(|info| info.state)
     }
 
+    pub fn peer_addr(&self, peer_id: &PeerId) -> Option<Multiaddr> {
+        self.peers.get(peer_id).map(|info| info.addr.clone())
+    }
+
+    /// Returns the chunk ranges available on the peer, or `None` if the peer has the whole file.
+    pub fn available_ranges(&self, peer_id: &PeerId) -> Option<Vec<(u64, u64)>> {
+        self.peers.get(peer_id).and_then(|info| info.ranges.clone())
+    }
+
     pub fn random_peer(&self, state: PeerState) -> Option<(PeerId, Multiaddr)> {
-        self.peers
+        let mut peers: Vec<(PeerId, Multiaddr)> = self
+            .peers
             .iter()
             .filter(|(_, info)| info.state == state)
             .map(|(peer_id, info)| (*peer_id, info.addr.clone()))
-            .choose(&mut rand::thread_rng())
+            .collect();
+
+        // independent of the hash map order, so that a seeded rng picks the same peer
+        peers.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        peers.into_iter().choose(&mut crate::rng())
+    }
+
+    pub fn filter_peers(&self, state: PeerState) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, info)| info.state == state)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
     }
 
     pub fn count(&self, states: &[PeerState]) -> usize {
@@ -136,6 +175,20 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_add_new_peer_with_ranges() {
+        let mut sync_peers: SyncPeers = Default::default();
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+
+        assert!(sync_peers.add_new_peer_with_ranges(peer_id, addr.clone(), Some(vec![(0, 1)])));
+        assert_eq!(sync_peers.available_ranges(&peer_id), Some(vec![(0, 1)]));
+
+        // the whole file is available later
+        assert!(!sync_peers.add_new_peer(peer_id, addr));
+        assert_eq!(sync_peers.available_ranges(&peer_id), None);
+    }
+
     #[test]
     fn test_add_new_peer() {
         let mut sync_peers: SyncPeers = Default::default();
@@ -0,0 +1,160 @@
+use futures::{FutureExt, StreamExt};
+use hashset_delay::HashSetDelay;
+use network::PeerId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Peers are temporarily banned from sync after too many invalid proofs or timeouts.
+const MAX_INVALID_PROOFS: u32 = 3;
+const MAX_TIMEOUTS: u32 = 5;
+const BAN_DURATION: Duration = Duration::from_secs(10 * 60);
+
+const INVALID_PROOF_PENALTY: f64 = 20.0;
+const TIMEOUT_PENALTY: f64 = 5.0;
+/// Penalty of each second of the average response latency.
+const LATENCY_PENALTY: f64 = 1.0;
+
+/// Weight of the latest latency in the exponentially weighted average latency.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Default, Clone)]
+struct PeerScore {
+    /// Exponentially weighted average latency of chunks responses.
+    latency: Option<Duration>,
+    invalid_proofs: u32,
+    timeouts: u32,
+}
+
+impl PeerScore {
+    fn score(&self) -> f64 {
+        let latency = self.latency.map_or(0.0, |latency| latency.as_secs_f64());
+
+        -(self.invalid_proofs as f64 * INVALID_PROOF_PENALTY
+            + self.timeouts as f64 * TIMEOUT_PENALTY
+            + latency * LATENCY_PENALTY)
+    }
+}
+
+/// Reputation of peers shared by all file sync controllers, which tracks the response latency,
+/// invalid proofs and timeouts of chunks requests, to prefer good peers and temporarily ban the
+/// misbehaving ones.
+pub struct PeerReputation {
+    scores: Mutex<HashMap<PeerId, PeerScore>>,
+    banned: Mutex<HashSetDelay<PeerId>>,
+}
+
+impl Default for PeerReputation {
+    fn default() -> Self {
+        PeerReputation {
+            scores: Default::default(),
+            banned: Mutex::new(HashSetDelay::new(BAN_DURATION)),
+        }
+    }
+}
+
+impl PeerReputation {
+    /// Returns the score of the peer, the higher the better. Peers without any record have the
+    /// highest score of 0.
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        self.scores
+            .lock()
+            .expect("not poisoned")
+            .get(peer_id)
+            .map_or(0.0, |score| score.score())
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        match self.banned.lock().expect("not poisoned").get(peer_id) {
+            Some(until) => *until > Instant::now(),
+            None => false,
+        }
+    }
+
+    pub fn on_response(&self, peer_id: PeerId, latency: Duration) {
+        let mut scores = self.scores.lock().expect("not poisoned");
+        let score = scores.entry(peer_id).or_default();
+
+        score.latency = Some(match score.latency {
+            Some(avg) => avg.mul_f64(1.0 - LATENCY_WEIGHT) + latency.mul_f64(LATENCY_WEIGHT),
+            None => latency,
+        });
+    }
+
+    pub fn on_invalid_proof(&self, peer_id: PeerId) {
+        let mut scores = self.scores.lock().expect("not poisoned");
+        let score = scores.entry(peer_id).or_default();
+
+        score.invalid_proofs += 1;
+        if score.invalid_proofs >= MAX_INVALID_PROOFS {
+            score.invalid_proofs = 0;
+            self.ban(peer_id, "Too many invalid proofs");
+        }
+    }
+
+    pub fn on_timeout(&self, peer_id: PeerId) {
+        let mut scores = self.scores.lock().expect("not poisoned");
+        let score = scores.entry(peer_id).or_default();
+
+        score.timeouts += 1;
+        if score.timeouts >= MAX_TIMEOUTS {
+            score.timeouts = 0;
+            self.ban(peer_id, "Too many timeouts");
+        }
+    }
+
+    fn ban(&self, peer_id: PeerId, reason: &'static str) {
+        info!(%peer_id, %reason, "Peer banned from sync temporarily");
+        self.banned.lock().expect("not poisoned").insert(peer_id);
+    }
+
+    /// Removes the expired bans, which should be called periodically.
+    pub fn prune(&self) {
+        let mut banned = self.banned.lock().expect("not poisoned");
+
+        while let Some(Some(result)) = banned.next().now_or_never() {
+            match result {
+                Ok(peer_id) => debug!(%peer_id, "Peer unbanned from sync"),
+                Err(e) => warn!(%e, "Failed to unban peer"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    #[tokio::test]
+    async fn test_ban_on_invalid_proofs() {
+        let reputation = PeerReputation::default();
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+
+        for _ in 0..MAX_INVALID_PROOFS - 1 {
+            reputation.on_invalid_proof(peer_id);
+        }
+        assert!(!reputation.is_banned(&peer_id));
+        assert!(reputation.score(&peer_id) < 0.0);
+
+        reputation.on_invalid_proof(peer_id);
+        assert!(reputation.is_banned(&peer_id));
+
+        reputation.prune();
+        assert!(reputation.is_banned(&peer_id));
+    }
+
+    #[test]
+    fn test_score_by_latency() {
+        let reputation = PeerReputation::default();
+        let fast = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let slow = identity::Keypair::generate_ed25519().public().to_peer_id();
+
+        reputation.on_response(fast, Duration::from_millis(100));
+        reputation.on_response(slow, Duration::from_secs(2));
+
+        assert!(reputation.score(&fast) > reputation.score(&slow));
+        assert!(reputation.score(&fast) < 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use network::PeerId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Limits the number of chunks requests in flight to each peer among all file sync controllers,
+/// so that a peer storing many files is not overwhelmed when they are synced at the same time.
+#[derive(Debug)]
+pub struct PeerRequestLimiter {
+    max_requests_per_peer: usize,
+    inflight: Arc<Mutex<HashMap<PeerId, usize>>>,
+}
+
+/// Permit of a chunks request in flight, which is released when dropped.
+#[derive(Debug)]
+pub struct RequestPermit {
+    peer_id: PeerId,
+    inflight: Arc<Mutex<HashMap<PeerId, usize>>>,
+}
+
+impl PeerRequestLimiter {
+    pub fn new(max_requests_per_peer: usize) -> Self {
+        assert!(max_requests_per_peer > 0);
+
+        PeerRequestLimiter {
+            max_requests_per_peer,
+            inflight: Default::default(),
+        }
+    }
+
+    /// Returns a permit to request chunks from the peer, or `None` if too many requests in
+    /// flight to the peer.
+    pub fn try_acquire(&self, peer_id: PeerId) -> Option<RequestPermit> {
+        let mut inflight = self.inflight.lock().expect("not poisoned");
+        let count = inflight.entry(peer_id).or_default();
+        if *count >= self.max_requests_per_peer {
+            return None;
+        }
+
+        *count += 1;
+
+        Some(RequestPermit {
+            peer_id,
+            inflight: self.inflight.clone(),
+        })
+    }
+
+    /// Returns the number of requests in flight to the peer.
+    pub fn inflight(&self, peer_id: &PeerId) -> usize {
+        let inflight = self.inflight.lock().expect("not poisoned");
+        inflight.get(peer_id).copied().unwrap_or_default()
+    }
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        let mut inflight = self.inflight.lock().expect("not poisoned");
+        if let Some(count) = inflight.get_mut(&self.peer_id) {
+            *count -= 1;
+            if *count == 0 {
+                inflight.remove(&self.peer_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::identity;
+
+    #[test]
+    fn test_max_requests_per_peer() {
+        let limiter = PeerRequestLimiter::new(2);
+        let peer1 = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let peer2 = identity::Keypair::generate_ed25519().public().to_peer_id();
+
+        let permit1 = limiter.try_acquire(peer1).unwrap();
+        let _permit2 = limiter.try_acquire(peer1).unwrap();
+        assert!(limiter.try_acquire(peer1).is_none());
+        assert!(limiter.try_acquire(peer2).is_some());
+
+        // released when dropped
+        drop(permit1);
+        assert_eq!(limiter.inflight(&peer1), 1);
+        assert!(limiter.try_acquire(peer1).is_some());
+        assert_eq!(limiter.inflight(&peer2), 0);
+    }
+}
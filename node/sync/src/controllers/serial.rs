@@ -1,21 +1,32 @@
 use crate::context::SyncNetworkContext;
 use crate::controllers::peers::{PeerState, SyncPeers};
+use crate::controllers::reputation::PeerReputation;
+use crate::controllers::request_limiter::{PeerRequestLimiter, RequestPermit};
+use crate::controllers::verify_queue::ChunkVerifyQueue;
+use crate::controllers::write_queue::ChunkWriteQueue;
+use crate::retry::{RequestFailure, Retry, RetryConfig};
 use file_location_cache::FileLocationCache;
 use network::{
     multiaddr::Protocol, rpc::GetChunksRequest, types::FindFile, Multiaddr, NetworkMessage,
     PeerAction, PeerId, PubsubMessage, SyncId as RequestId,
 };
-use shared_types::{timestamp_now, ChunkArrayWithProof, DataRoot, CHUNK_SIZE};
+use shared_types::{timestamp_now, ChunkArray, ChunkArrayWithProof, DataRoot, CHUNK_SIZE};
 use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
     sync::Arc,
-    time::{Duration, Instant},
+    time::Duration,
 };
+use storage::log_store::{FileSyncPeer, FileSyncProgress};
 use storage_async::Store;
+use tokio::time::Instant;
 
 const MAX_CHUNKS_TO_REQUEST: u64 = 2 * 1024;
-const MAX_REQUEST_FAILURES: usize = 3;
 const PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of peers to download chunks from in parallel.
+const MAX_PARALLEL_PEERS: usize = 8;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SyncState {
@@ -26,34 +37,93 @@ pub enum SyncState {
     FoundPeers,
     ConnectingPeers,
     AwaitingDownload,
-    Downloading {
-        peer_id: PeerId,
-        from_chunk: u64,
-        to_chunk: u64,
-        since: Instant,
-    },
+    /// Chunks are being downloaded from one or more peers.
+    Downloading,
     Completed,
     Failed {
         reason: String,
     },
 }
 
+/// A chunk range requested from a peer.
+#[derive(Debug)]
+struct InflightRequest {
+    from_chunk: u64,
+    to_chunk: u64,
+    since: Instant,
+    /// Released when the request is completed or cancelled.
+    _permit: RequestPermit,
+}
+
+/// A downloaded chunk range being verified.
+#[derive(Debug)]
+struct VerifyingRange {
+    peer_id: PeerId,
+    from_chunk: u64,
+    to_chunk: u64,
+    /// Time to download the range from the peer.
+    elapsed: Duration,
+}
+
+/// Download statistics of a peer, which are used to prefer faster peers.
+#[derive(Debug, Default)]
+struct PeerStats {
+    downloaded_bytes: u64,
+    download_time: Duration,
+    /// Failed chunks requests, e.g. the peer responded busy, and should not be requested until
+    /// the backoff elapsed.
+    retry: Retry,
+}
+
+impl PeerStats {
+    /// Returns the average download rate in bytes per second, or `None` if nothing downloaded.
+    fn rate(&self) -> Option<f64> {
+        if self.downloaded_bytes == 0 {
+            return None;
+        }
+
+        Some(self.downloaded_bytes as f64 / self.download_time.as_secs_f64().max(0.001))
+    }
+}
+
+/// Syncs a file by splitting its missing chunks into ranges, which are downloaded from multiple
+/// peers in parallel and stored as they arrive.
 pub struct SerialSyncController {
     /// The transaction sequence number.
     tx_seq: u64,
 
-    #[allow(unused)]
     /// The transaction data root.
     data_root: DataRoot,
 
     /// The size of the file to be synced.
     num_chunks: u64,
 
-    /// The next chunk id that we need to retrieve.
-    next_chunk: u64,
+    /// Chunk ranges that have not been requested yet, in ascending order.
+    missing: VecDeque<(u64, u64)>,
+
+    /// Chunk ranges being downloaded, at most one for each peer.
+    inflight: HashMap<PeerId, InflightRequest>,
+
+    /// Downloaded chunk ranges queued to verify.
+    verifying: Vec<VerifyingRange>,
 
-    /// Continuous RPC failures to request chunks.
-    failures: usize,
+    /// Downloaded chunk ranges queued to write into store.
+    writing: Vec<(u64, u64)>,
+
+    /// Download statistics of peers.
+    stats: HashMap<PeerId, PeerStats>,
+
+    /// Peers that chunks have been downloaded from, which are persisted with the sync progress.
+    sources: HashMap<PeerId, Multiaddr>,
+
+    /// Number of attempts to sync the file, which increases on each reset after failure.
+    attempts: u32,
+
+    /// Policies to retry the failed chunks requests and provider lookups.
+    retry: RetryConfig,
+
+    /// Failed lookups to find the providers of the file.
+    lookup: Retry,
 
     /// Current state of this request.
     state: SyncState,
@@ -61,6 +131,18 @@ pub struct SerialSyncController {
     /// Sync peer manager.
     peers: SyncPeers,
 
+    /// Reputation of peers shared among files.
+    reputation: Arc<PeerReputation>,
+
+    /// Queue to verify downloaded chunks, shared among files.
+    verifier: Arc<ChunkVerifyQueue>,
+
+    /// Queue to write downloaded chunks into store, shared among files.
+    writer: Arc<ChunkWriteQueue>,
+
+    /// Limits the chunks requests in flight to each peer among files.
+    request_limiter: Arc<PeerRequestLimiter>,
+
     /// A network context to contact the network service.
     ctx: Arc<SyncNetworkContext>,
 
@@ -72,6 +154,7 @@ pub struct SerialSyncController {
 }
 
 impl SerialSyncController {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tx_seq: u64,
         data_root: DataRoot,
@@ -79,15 +162,31 @@ impl SerialSyncController {
         ctx: Arc<SyncNetworkContext>,
         store: Store,
         file_location_cache: Arc<FileLocationCache>,
+        reputation: Arc<PeerReputation>,
+        verifier: Arc<ChunkVerifyQueue>,
+        writer: Arc<ChunkWriteQueue>,
+        request_limiter: Arc<PeerRequestLimiter>,
+        retry: RetryConfig,
     ) -> Self {
         SerialSyncController {
             tx_seq,
             data_root,
             num_chunks,
-            next_chunk: 0,
-            failures: 0,
+            missing: VecDeque::from([(0, num_chunks)]),
+            inflight: Default::default(),
+            verifying: Default::default(),
+            writing: Default::default(),
+            stats: Default::default(),
+            sources: Default::default(),
+            attempts: 0,
+            retry,
+            lookup: Default::default(),
             state: SyncState::Idle,
             peers: Default::default(),
+            reputation,
+            verifier,
+            writer,
+            request_limiter,
             ctx,
             store,
             file_location_cache,
@@ -98,10 +197,124 @@ impl SerialSyncController {
         &self.state
     }
 
+    pub fn data_root(&self) -> &DataRoot {
+        &self.data_root
+    }
+
+    pub fn num_chunks(&self) -> u64 {
+        self.num_chunks
+    }
+
+    /// Excludes the chunk ranges already in store, e.g. partially synced before the node restarts.
+    /// Returns whether there are still chunks to download.
+    pub fn skip_stored_chunks(&mut self, stored: &[(usize, usize)]) -> bool {
+        let mut missing = VecDeque::new();
+        let mut next_chunk = 0;
+
+        for &(start, end) in stored {
+            let (start, end) = (start as u64, end as u64);
+            if start > next_chunk {
+                missing.push_back((next_chunk, start.min(self.num_chunks)));
+            }
+            next_chunk = next_chunk.max(end);
+        }
+
+        if next_chunk < self.num_chunks {
+            missing.push_back((next_chunk, self.num_chunks));
+        }
+
+        self.missing = missing;
+        !self.missing.is_empty()
+    }
+
+    /// Restores the sync progress persisted before the node restarts. Note, the downloaded ranges
+    /// are not restored, since the missing chunks are retrieved from store instead.
+    pub fn restore_progress(&mut self, progress: FileSyncProgress) {
+        self.attempts = progress.attempts;
+
+        for peer in progress.peers {
+            let peer_id = match PeerId::from_bytes(&peer.peer_id) {
+                Ok(peer_id) => peer_id,
+                Err(e) => {
+                    warn!(%self.tx_seq, ?e, "Invalid peer id in sync progress");
+                    continue;
+                }
+            };
+
+            let addr = match Multiaddr::try_from(peer.addr) {
+                Ok(addr) => addr,
+                Err(e) => {
+                    warn!(%self.tx_seq, %peer_id, ?e, "Invalid peer address in sync progress");
+                    continue;
+                }
+            };
+
+            self.on_peer_found(peer_id, addr);
+        }
+    }
+
+    /// Returns the chunk ranges that have been downloaded and stored, in ascending order.
+    fn downloaded_ranges(&self) -> Vec<(u64, u64)> {
+        let mut pending: Vec<(u64, u64)> = self
+            .missing
+            .iter()
+            .copied()
+            .chain(self.inflight.values().map(|r| (r.from_chunk, r.to_chunk)))
+            .chain(self.verifying.iter().map(|r| (r.from_chunk, r.to_chunk)))
+            .chain(self.writing.iter().copied())
+            .collect();
+        pending.sort_unstable();
+
+        let mut downloaded = vec![];
+        let mut next_chunk = 0;
+        for (start, end) in pending {
+            if start > next_chunk {
+                downloaded.push((next_chunk, start));
+            }
+            next_chunk = next_chunk.max(end);
+        }
+
+        if next_chunk < self.num_chunks {
+            downloaded.push((next_chunk, self.num_chunks));
+        }
+
+        downloaded
+    }
+
+    fn progress(&self) -> FileSyncProgress {
+        FileSyncProgress {
+            downloaded: self.downloaded_ranges(),
+            peers: self
+                .sources
+                .iter()
+                .map(|(peer_id, addr)| FileSyncPeer {
+                    peer_id: peer_id.to_bytes(),
+                    addr: addr.to_vec(),
+                })
+                .collect(),
+            attempts: self.attempts,
+        }
+    }
+
+    /// Persists the sync progress, so that the sync could be resumed after the node restarts.
+    async fn persist_progress(&self) {
+        if let Err(e) = self
+            .store
+            .put_file_sync_progress(self.tx_seq, self.progress())
+            .await
+        {
+            warn!(%self.tx_seq, ?e, "Failed to persist sync progress");
+        }
+    }
+
     /// Resets the status to re-sync file when failed.
     pub fn reset(&mut self) {
-        self.next_chunk = 0;
-        self.failures = 0;
+        for (_, request) in self.inflight.drain() {
+            Self::requeue(&mut self.missing, request.from_chunk, request.to_chunk);
+        }
+        self.stats.clear();
+        self.lookup = Default::default();
+        self.attempts += 1;
         self.state = SyncState::Idle;
         // remove disconnected peers
         self.peers.transition();
@@ -113,13 +326,22 @@ impl SerialSyncController {
         // try from cache
         let mut found_new_peer = false;
 
+        for (peer_id, mut addr) in self.file_location_cache.get_providers(&self.data_root) {
+            // make sure peer_id is part of the address
+            addr.push(Protocol::P2p(peer_id.into()));
+
+            found_new_peer = self.on_peer_found(peer_id, addr) || found_new_peer;
+        }
+
         for announcement in self.file_location_cache.get_all(self.tx_seq) {
             // make sure peer_id is part of the address
             let peer_id: PeerId = announcement.peer_id.clone().into();
             let mut addr: Multiaddr = announcement.at.clone().into();
             addr.push(Protocol::P2p(peer_id.into()));
 
-            found_new_peer = self.on_peer_found(peer_id, addr) || found_new_peer;
+            found_new_peer =
+                self.on_peer_found_with_ranges(peer_id, addr, announcement.available_ranges())
+                    || found_new_peer;
         }
 
         if !found_new_peer {
@@ -134,16 +356,11 @@ impl SerialSyncController {
         };
     }
 
-    fn try_connect(&mut self) {
-        // select a random peer
+    /// Dials a random found peer, and returns `false` if there is no peer to connect.
+    fn connect_random_peer(&mut self) -> bool {
         let (peer_id, address) = match self.peers.random_peer(PeerState::Found) {
             Some((peer_id, address)) => (peer_id, address),
-            None => {
-                // peer may be disconnected by remote node and need to find peers again
-                warn!(%self.tx_seq, "No peers available to connect");
-                self.state = SyncState::Idle;
-                return;
-            }
+            None => return false,
         };
 
         // connect to peer
@@ -153,24 +370,160 @@ impl SerialSyncController {
         self.peers
             .update_state(&peer_id, PeerState::Found, PeerState::Connecting);
 
+        true
+    }
+
+    fn try_connect(&mut self) {
+        if !self.connect_random_peer() {
+            // peer may be disconnected by remote node and need to find peers again
+            warn!(%self.tx_seq, "No peers available to connect");
+            self.state = SyncState::Idle;
+            return;
+        }
+
         self.state = SyncState::ConnectingPeers;
     }
 
+    /// Connects to more found peers while downloading, so as to download from them in parallel.
+    fn try_connect_more(&mut self) {
+        use PeerState::*;
+
+        if self.peers.count(&[Connecting]) == 0
+            && self.peers.count(&[Connecting, Connected]) < MAX_PARALLEL_PEERS
+            && !self.missing.is_empty()
+        {
+            self.connect_random_peer();
+        }
+    }
+
+    /// Returns connected peers without request in flight, ordered by reputation score and then
+    /// download rate. Peers without download statistics are preferred to measure their rates.
+    fn idle_peers(&self) -> Vec<PeerId> {
+        let mut peers: Vec<(PeerId, f64, f64)> = self
+            .peers
+            .filter_peers(PeerState::Connected)
+            .into_iter()
+            .filter(|peer_id| !self.inflight.contains_key(peer_id))
+            .filter(|peer_id| !self.reputation.is_banned(peer_id))
+            .map(|peer_id| {
+                let rate = self.stats.get(&peer_id).and_then(|s| s.rate());
+                (
+                    peer_id,
+                    self.reputation.score(&peer_id),
+                    rate.unwrap_or(f64::INFINITY),
+                )
+            })
+            .collect();
+
+        peers.sort_by(|(_, score_a, rate_a), (_, score_b, rate_b)| {
+            score_b
+                .partial_cmp(score_a)
+                .unwrap_or(Ordering::Equal)
+                .then(rate_b.partial_cmp(rate_a).unwrap_or(Ordering::Equal))
+        });
+
+        peers.into_iter().map(|(peer_id, _, _)| peer_id).collect()
+    }
+
+    /// Takes at most `MAX_CHUNKS_TO_REQUEST` chunks from the first missing range that is
+    /// `available` on the peer, where `None` means the peer has the whole file.
+    fn next_range(&mut self, available: Option<&[(u64, u64)]>) -> Option<(u64, u64)> {
+        let (pos, from_chunk, end) =
+            self.missing
+                .iter()
+                .enumerate()
+                .find_map(|(pos, &(start, end))| match available {
+                    None => Some((pos, start, end)),
+                    Some(ranges) => ranges.iter().find_map(|&(avail_start, avail_end)| {
+                        let (start, end) = (start.max(avail_start), end.min(avail_end));
+                        (start < end).then(|| (pos, start, end))
+                    }),
+                })?;
+
+        let (missing_start, missing_end) = self.missing.remove(pos)?;
+        let to_chunk = std::cmp::min(from_chunk + MAX_CHUNKS_TO_REQUEST, end);
+
+        // put back the rest of the missing range in order
+        if to_chunk < missing_end {
+            self.missing.insert(pos, (to_chunk, missing_end));
+        }
+        if missing_start < from_chunk {
+            self.missing.insert(pos, (missing_start, from_chunk));
+        }
+
+        Some((from_chunk, to_chunk))
+    }
+
+    /// Puts back the range of a failed request, which will be requested again in order.
+    fn requeue(missing: &mut VecDeque<(u64, u64)>, from_chunk: u64, to_chunk: u64) {
+        let range = (from_chunk, to_chunk);
+        let pos = missing.partition_point(|&(start, _)| start < range.0);
+        missing.insert(pos, range);
+    }
+
     fn try_request_next(&mut self) {
-        // select a random peer
-        let peer_id = match self.peers.random_peer(PeerState::Connected) {
-            Some((peer_id, _)) => peer_id,
-            None => {
-                warn!(%self.tx_seq, "No peers available to request chunks");
-                self.state = SyncState::Idle;
-                return;
+        if self.verifier.is_congested() {
+            debug!(%self.tx_seq, depth = %self.verifier.depth(), "Pause to request chunks due to verify queue congested");
+            return;
+        }
+
+        if self.writer.is_congested() {
+            debug!(%self.tx_seq, depth = %self.writer.depth(), "Pause to request chunks due to write queue congested");
+            return;
+        }
+
+        let mut limited = false;
+
+        for peer_id in self.idle_peers() {
+            if self.inflight.len() >= MAX_PARALLEL_PEERS || self.missing.is_empty() {
+                break;
             }
-        };
 
-        // request next chunk array
-        let from_chunk = self.next_chunk;
-        let to_chunk = std::cmp::min(from_chunk + MAX_CHUNKS_TO_REQUEST, self.num_chunks);
+            if self
+                .stats
+                .get(&peer_id)
+                .map_or(false, |s| !s.retry.is_ready())
+            {
+                limited = true;
+                continue;
+            }
+
+            let permit = match self.request_limiter.try_acquire(peer_id) {
+                Some(permit) => permit,
+                None => {
+                    limited = true;
+                    continue;
+                }
+            };
+
+            // the peer may only have some ranges of the file
+            let available = self.peers.available_ranges(&peer_id);
+            let (from_chunk, to_chunk) = match self.next_range(available.as_deref()) {
+                Some(range) => range,
+                None => continue,
+            };
 
+            self.request_chunks(peer_id, from_chunk, to_chunk, permit);
+        }
+
+        if !self.inflight.is_empty() {
+            self.state = SyncState::Downloading;
+        } else if limited {
+            debug!(%self.tx_seq, "Pause to request chunks due to too many requests to peers or peers in backoff");
+            self.state = SyncState::AwaitingDownload;
+        } else {
+            warn!(%self.tx_seq, "No peers available to request chunks");
+            self.state = SyncState::Idle;
+        }
+    }
+
+    fn request_chunks(
+        &mut self,
+        peer_id: PeerId,
+        from_chunk: u64,
+        to_chunk: u64,
+        permit: RequestPermit,
+    ) {
         let request_id = network::RequestId::Sync(RequestId::SerialSync {
             tx_seq: self.tx_seq,
         });
@@ -187,12 +540,23 @@ impl SerialSyncController {
             request,
         });
 
-        self.state = SyncState::Downloading {
+        self.inflight.insert(
             peer_id,
-            from_chunk,
-            to_chunk,
-            since: Instant::now(),
-        };
+            InflightRequest {
+                from_chunk,
+                to_chunk,
+                since: Instant::now(),
+                _permit: permit,
+            },
+        );
+    }
+
+    /// Cancels the request in flight to the peer if any, so that the range could be requested
+    /// from other peers.
+    fn cancel_request(&mut self, peer_id: &PeerId) {
+        if let Some(request) = self.inflight.remove(peer_id) {
+            Self::requeue(&mut self.missing, request.from_chunk, request.to_chunk);
+        }
     }
 
     fn ban_peer(&mut self, peer_id: PeerId, reason: &'static str) {
@@ -202,8 +566,35 @@ impl SerialSyncController {
             .update_state(&peer_id, PeerState::Connected, PeerState::Disconnecting);
     }
 
+    /// Bans the peer that responded invalid chunks, and finds new peers if no other downloads.
+    fn ban_peer_on_response(&mut self, peer_id: PeerId, reason: &'static str) {
+        self.ban_peer(peer_id, reason);
+
+        if self.inflight.is_empty() {
+            self.state = SyncState::Idle;
+        }
+    }
+
     pub fn on_peer_found(&mut self, peer_id: PeerId, addr: Multiaddr) -> bool {
-        if self.peers.add_new_peer(peer_id, addr.clone()) {
+        self.on_peer_found_with_ranges(peer_id, addr, None)
+    }
+
+    /// Adds the found peer that has the chunk `ranges` of the file, or the whole file if `None`.
+    pub fn on_peer_found_with_ranges(
+        &mut self,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        ranges: Option<Vec<(u64, u64)>>,
+    ) -> bool {
+        if self.reputation.is_banned(&peer_id) {
+            debug!(%self.tx_seq, %peer_id, "Ignore found peer that is banned");
+            return false;
+        }
+
+        if self
+            .peers
+            .add_new_peer_with_ranges(peer_id, addr.clone(), ranges)
+        {
             info!(%self.tx_seq, %peer_id, %addr, "Found new peer");
             true
         } else {
@@ -219,7 +610,11 @@ impl SerialSyncController {
                 .update_state(&peer_id, PeerState::Connecting, PeerState::Disconnected)
         {
             info!(%self.tx_seq, %peer_id, "Failed to dail peer");
-            self.state = SyncState::Idle;
+
+            // other peers may be still downloading
+            if self.state != SyncState::Downloading {
+                self.state = SyncState::Idle;
+            }
         }
 
         // TODO(qhz): handle different kinds of DailError.
@@ -248,21 +643,23 @@ impl SerialSyncController {
             }
             None => {}
         }
+
+        self.cancel_request(&peer_id);
     }
 
     /// Handle the case that got an unexpected response:
     /// 1. not in `Downloading` sync state.
-    /// 2. from unexpected peer.
+    /// 2. from a peer without request in flight.
     fn handle_on_response_mismatch(&self, from_peer_id: PeerId) -> bool {
         match self.state {
-            SyncState::Downloading { peer_id, .. } => {
-                if from_peer_id == peer_id {
+            SyncState::Downloading => {
+                if self.inflight.contains_key(&from_peer_id) {
                     return false;
                 }
 
                 // got response from wrong peer
                 // this can happen if we get a response for a timeout request
-                warn!(%self.tx_seq, %from_peer_id, %peer_id, "Got response from unexpected peer");
+                warn!(%self.tx_seq, %from_peer_id, "Got response from unexpected peer");
                 self.ctx.report_peer(
                     from_peer_id,
                     PeerAction::LowToleranceError,
@@ -287,15 +684,11 @@ impl SerialSyncController {
             return;
         }
 
-        let (from_chunk, to_chunk) = match self.state {
-            SyncState::Downloading {
-                peer_id: _peer_id,
-                from_chunk,
-                to_chunk,
-                ..
-            } => (from_chunk, to_chunk),
-            _ => return,
+        let request = match self.inflight.remove(&from_peer_id) {
+            Some(request) => request,
+            None => return,
         };
+        let (from_chunk, to_chunk) = (request.from_chunk, request.to_chunk);
 
         debug_assert!(from_chunk < to_chunk, "Invalid chunk boundaries");
 
@@ -303,59 +696,127 @@ impl SerialSyncController {
         let data_len = response.chunks.data.len();
         if data_len == 0 || data_len % CHUNK_SIZE > 0 {
             warn!(%from_peer_id, %self.tx_seq, %data_len, "Invalid chunk response data length");
-            self.ban_peer(from_peer_id, "Invalid chunk response data length");
-            self.state = SyncState::Idle;
+            Self::requeue(&mut self.missing, from_chunk, to_chunk);
+            self.ban_peer_on_response(from_peer_id, "Invalid chunk response data length");
             return;
         }
 
         // invalid chunk range: ban and re-request
         let start_index = response.chunks.start_index;
-        let end_index = start_index + (data_len / CHUNK_SIZE) as u64;
+        // overflowed end index is invalid as well
+        let end_index = response.chunks.end_index().unwrap_or(u64::MAX);
         if start_index != from_chunk || end_index != to_chunk {
             warn!(%self.tx_seq, "Invalid chunk response range, expected={from_chunk}..{to_chunk}, actual={start_index}..{end_index}");
-            self.ban_peer(from_peer_id, "Invalid chunk response range");
-            self.state = SyncState::Idle;
+            Self::requeue(&mut self.missing, from_chunk, to_chunk);
+            self.ban_peer_on_response(from_peer_id, "Invalid chunk response range");
             return;
         }
 
-        // validate Merkle proofs
-        let validation_result = self
-            .store
-            .get_store()
-            .read()
+        // queue to verify Merkle proofs
+        if !self
+            .verifier
+            .push(self.tx_seq, from_peer_id, from_chunk, to_chunk, response)
             .await
-            .validate_range_proof(self.tx_seq, &response);
+        {
+            warn!(%self.tx_seq, "Verify queue is full, drop the downloaded chunks");
+            Self::requeue(&mut self.missing, from_chunk, to_chunk);
+            return;
+        }
+
+        self.verifying.push(VerifyingRange {
+            peer_id: from_peer_id,
+            from_chunk,
+            to_chunk,
+            elapsed: request.since.elapsed(),
+        });
+    }
+
+    /// Handles the result of verifying downloaded chunks, and queues the valid chunks to write
+    /// into store.
+    pub fn on_chunks_verified(
+        &mut self,
+        from_peer_id: PeerId,
+        from_chunk: u64,
+        to_chunk: u64,
+        chunks: ChunkArray,
+        result: Result<bool, String>,
+    ) {
+        let position = self.verifying.iter().position(|r| {
+            r.peer_id == from_peer_id && r.from_chunk == from_chunk && r.to_chunk == to_chunk
+        });
+        let elapsed = match position {
+            Some(position) => self.verifying.swap_remove(position).elapsed,
+            None => return,
+        };
 
-        match validation_result {
+        match result {
             Ok(true) => {}
             Ok(false) => {
                 info!("Failed to validate chunks response due to no root found");
-                self.state = SyncState::AwaitingDownload;
+                Self::requeue(&mut self.missing, from_chunk, to_chunk);
                 return;
             }
             Err(err) => {
                 warn!(%err, "Failed to validate chunks response");
-                self.ban_peer(from_peer_id, "Chunk array validation failed");
-                self.state = SyncState::Idle;
+                self.reputation.on_invalid_proof(from_peer_id);
+                Self::requeue(&mut self.missing, from_chunk, to_chunk);
+                self.ban_peer_on_response(from_peer_id, "Chunk array validation failed");
                 return;
             }
         }
 
-        self.failures = 0;
+        self.reputation.on_response(from_peer_id, elapsed);
+
+        let data_len = chunks.data.len();
+        let stats = self.stats.entry(from_peer_id).or_default();
+        stats.retry.on_success();
+        stats.downloaded_bytes += data_len as u64;
+        stats.download_time += elapsed;
 
-        // store in db
-        if let Err(e) = self.store.put_chunks(self.tx_seq, response.chunks).await {
-            let err = format!("Unexpected DB error while storing chunks: {:?}", e);
+        if let Some(addr) = self.peers.peer_addr(&from_peer_id) {
+            self.sources.insert(from_peer_id, addr);
+        }
+
+        // queue to write into store
+        if !self.writer.push(self.tx_seq, from_chunk, to_chunk, chunks) {
+            warn!(%self.tx_seq, "Write queue is full, drop the downloaded chunks");
+            Self::requeue(&mut self.missing, from_chunk, to_chunk);
+            return;
+        }
+
+        self.writing.push((from_chunk, to_chunk));
+    }
+
+    /// Handles the result of writing downloaded chunks into store, and finalizes the file if all
+    /// chunks have been stored.
+    pub async fn on_chunks_written(
+        &mut self,
+        from_chunk: u64,
+        to_chunk: u64,
+        result: Result<(), String>,
+    ) {
+        self.writing
+            .retain(|range| *range != (from_chunk, to_chunk));
+
+        if let Err(e) = result {
+            let err = format!("Unexpected DB error while storing chunks: {}", e);
             error!("{}", err);
+            Self::requeue(&mut self.missing, from_chunk, to_chunk);
             self.state = SyncState::Failed { reason: err };
             return;
         }
 
-        self.next_chunk = to_chunk;
+        if matches!(self.state, SyncState::Failed { .. }) {
+            return;
+        }
 
-        // prepare to download next
-        if self.next_chunk < self.num_chunks {
-            self.state = SyncState::Idle;
+        // wait for other ranges to download and write
+        if !self.missing.is_empty()
+            || !self.inflight.is_empty()
+            || !self.verifying.is_empty()
+            || !self.writing.is_empty()
+        {
+            self.persist_progress().await;
             return;
         }
 
@@ -367,33 +828,74 @@ impl SerialSyncController {
             return;
         }
 
+        if let Err(e) = self.store.delete_file_sync_progress(self.tx_seq).await {
+            warn!(%self.tx_seq, ?e, "Failed to delete sync progress");
+        }
+
         self.state = SyncState::Completed;
     }
 
-    pub fn on_request_failed(&mut self, peer_id: PeerId) {
+    pub fn on_request_failed(&mut self, peer_id: PeerId, failure: RequestFailure) {
+        if failure == RequestFailure::Busy {
+            self.on_peer_busy(peer_id);
+            return;
+        }
+
+        if self.handle_on_response_mismatch(peer_id) {
+            return;
+        }
+
+        self.handle_response_failure(peer_id, failure, "RPC Error");
+    }
+
+    /// The peer responded busy due to its bandwidth quota, which is not a fault of the peer. The
+    /// range is requested from other peers, or from the same peer after a while.
+    fn on_peer_busy(&mut self, peer_id: PeerId) {
         if self.handle_on_response_mismatch(peer_id) {
             return;
         }
 
-        self.handle_response_failure(peer_id, "RPC Error");
+        debug!(%peer_id, %self.tx_seq, "Peer is busy to serve chunks");
+
+        self.cancel_request(&peer_id);
+        self.stats
+            .entry(peer_id)
+            .or_default()
+            .retry
+            .on_failure(&self.retry.chunks, RequestFailure::Busy);
+
+        if self.inflight.is_empty() {
+            self.state = SyncState::AwaitingDownload;
+        }
     }
 
-    fn handle_response_failure(&mut self, peer_id: PeerId, reason: &'static str) {
-        info!(%peer_id, %self.tx_seq, %reason, "Chunks request failed");
+    fn handle_response_failure(
+        &mut self,
+        peer_id: PeerId,
+        failure: RequestFailure,
+        reason: &'static str,
+    ) {
+        info!(%peer_id, %self.tx_seq, ?failure, %reason, "Chunks request failed");
+
+        self.cancel_request(&peer_id);
 
         // ban peer on too many failures
         self.ctx
             .report_peer(peer_id, PeerAction::LowToleranceError, reason);
 
-        self.failures += 1;
+        let stats = self.stats.entry(peer_id).or_default();
 
-        if self.failures <= MAX_REQUEST_FAILURES {
-            // try again
-            self.state = SyncState::AwaitingDownload;
+        if stats.retry.on_failure(&self.retry.chunks, failure) {
+            // try again after the backoff
+            if self.inflight.is_empty() {
+                self.state = SyncState::AwaitingDownload;
+            }
         } else {
             // ban and find new peer to download
             self.ban_peer(peer_id, reason);
-            self.state = SyncState::Idle;
+            if self.inflight.is_empty() {
+                self.state = SyncState::Idle;
+            }
         }
     }
 
@@ -410,17 +912,31 @@ impl SerialSyncController {
                         self.state = SyncState::FindingPeers {
                             since: Instant::now(),
                         };
-                    } else {
+                    } else if self.lookup.is_ready() {
                         self.try_find_peers();
+                    } else {
+                        // wait for the backoff of the failed lookups
+                        return;
                     }
                 }
 
                 SyncState::FindingPeers { since } => {
                     if self.peers.count(&[Found, Connecting, Connected]) > 0 {
+                        self.lookup.on_success();
                         self.state = SyncState::FoundPeers;
                     } else if since.elapsed() >= PEER_REQUEST_TIMEOUT {
-                        warn!(%self.tx_seq, "Peer request timeout");
-                        self.state = SyncState::Idle;
+                        warn!(%self.tx_seq, failures = %self.lookup.failures(), "Peer request timeout");
+                        if self
+                            .lookup
+                            .on_failure(&self.retry.find_peers, RequestFailure::NotFound)
+                        {
+                            self.state = SyncState::Idle;
+                        } else {
+                            // sync again when the file is announced
+                            self.state = SyncState::Failed {
+                                reason: "No peers found".into(),
+                            };
+                        }
                     } else {
                         return;
                     }
@@ -446,20 +962,68 @@ impl SerialSyncController {
                 }
 
                 SyncState::AwaitingDownload => {
+                    // wait for the downloaded chunks to be verified and written, or the queues to
+                    // drain
+                    if self.missing.is_empty()
+                        || self.verifier.is_congested()
+                        || self.writer.is_congested()
+                    {
+                        return;
+                    }
+
                     self.try_request_next();
-                }
 
-                SyncState::Downloading { peer_id, since, .. } => {
-                    if !matches!(self.peers.peer_state(&peer_id), Some(PeerState::Connected)) {
-                        // e.g. peer disconnected by remote node
-                        self.state = SyncState::Idle;
-                    } else if since.elapsed() >= DOWNLOAD_TIMEOUT {
-                        self.handle_response_failure(peer_id, "RPC timeout");
-                    } else {
+                    // wait for the request permits or peers in backoff, and meanwhile connect to other
+                    // peers in case all the connected ones are busy
+                    if self.state == SyncState::AwaitingDownload {
+                        self.try_connect_more();
                         return;
                     }
                 }
 
+                SyncState::Downloading => {
+                    // e.g. peer disconnected by remote node
+                    let disconnected: Vec<PeerId> = self
+                        .inflight
+                        .keys()
+                        .filter(|peer_id| self.peers.peer_state(peer_id) != Some(Connected))
+                        .copied()
+                        .collect();
+                    for peer_id in disconnected {
+                        self.cancel_request(&peer_id);
+                    }
+
+                    let timeout: Vec<PeerId> = self
+                        .inflight
+                        .iter()
+                        .filter(|(_, request)| request.since.elapsed() >= DOWNLOAD_TIMEOUT)
+                        .map(|(peer_id, _)| *peer_id)
+                        .collect();
+                    for peer_id in timeout {
+                        self.reputation.on_timeout(peer_id);
+                        self.handle_response_failure(
+                            peer_id,
+                            RequestFailure::Timeout,
+                            "RPC timeout",
+                        );
+                    }
+
+                    if self.state != SyncState::Downloading {
+                        continue;
+                    }
+
+                    // all requested chunks received or cancelled
+                    if self.inflight.is_empty() {
+                        self.state = SyncState::AwaitingDownload;
+                        continue;
+                    }
+
+                    // download the remaining ranges from idle peers
+                    self.try_request_next();
+                    self.try_connect_more();
+                    return;
+                }
+
                 SyncState::Completed | SyncState::Failed { .. } => return,
             }
         }
@@ -482,6 +1046,7 @@ mod tests {
     use crate::test_util::tests::create_2_store;
 
     use super::*;
+    use crate::controllers::{VerifyResult, WriteResult};
 
     #[test]
     fn test_status() {
@@ -648,6 +1213,7 @@ mod tests {
                             network::SyncId::SerialSync { tx_seq } => {
                                 assert_eq!(tx_seq, 0);
                             }
+                            sync_id => panic!("Unexpected sync id {:?}", sync_id),
                         },
                         _ => {
                             panic!("Not expected message: network::RequestId::Sync");
@@ -660,10 +1226,7 @@ mod tests {
             }
         }
 
-        assert!(matches!(
-            *controller.get_status(),
-            SyncState::Downloading { .. }
-        ));
+        assert_eq!(*controller.get_status(), SyncState::Downloading);
     }
 
     #[tokio::test]
@@ -861,12 +1424,7 @@ mod tests {
 
         let peer_id_1 = identity::Keypair::generate_ed25519().public().to_peer_id();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: 1,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, 1);
         assert_eq!(controller.handle_on_response_mismatch(peer_id_1), true);
         if let Some(msg) = network_recv.recv().await {
             match msg {
@@ -912,7 +1470,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, _) = create_controller(
+        let (mut controller, _, _, _) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -928,12 +1486,7 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: 0,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, 0);
         controller.on_response(peer_id, chunks).await;
     }
 
@@ -947,7 +1500,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, _, _) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -963,12 +1516,7 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: chunk_count as u64,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, chunk_count as u64);
 
         chunks.chunks.data = Vec::new();
         controller.on_response(peer_id, chunks).await;
@@ -1015,7 +1563,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, _, _) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -1031,12 +1579,7 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 1,
-            to_chunk: chunk_count as u64,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 1, chunk_count as u64);
 
         controller.on_response(peer_id, chunks).await;
         assert_eq!(*controller.get_status(), SyncState::Idle);
@@ -1082,7 +1625,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, mut verify_recv, _) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -1098,16 +1641,12 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: chunk_count as u64,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, chunk_count as u64);
 
         controller.tx_seq = 1;
 
         controller.on_response(peer_id, chunks).await;
+        wait_chunks_verified(&mut controller, &mut verify_recv).await;
         assert_eq!(*controller.get_status(), SyncState::Idle);
         if let Some(msg) = network_recv.recv().await {
             match msg {
@@ -1151,7 +1690,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, mut verify_recv, mut write_recv) = create_controller(
             task_executor,
             Some(peer_id),
             peer_store.clone(),
@@ -1167,14 +1706,11 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: chunk_count as u64,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, chunk_count as u64);
 
         controller.on_response(peer_id, chunks).await;
+        wait_chunks_verified(&mut controller, &mut verify_recv).await;
+        wait_chunks_written(&mut controller, &mut write_recv).await;
         match controller.get_status() {
             SyncState::Failed { reason } => {
                 assert!(reason.starts_with("Unexpected DB error while storing chunks: "));
@@ -1197,7 +1733,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, mut verify_recv, mut write_recv) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -1213,16 +1749,11 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: 2048,
-            since: Instant::now(),
-        };
-
-        controller.num_chunks = 2048;
+        set_downloading(&mut controller, peer_id, 0, 2048);
 
         controller.on_response(peer_id, chunks).await;
+        wait_chunks_verified(&mut controller, &mut verify_recv).await;
+        wait_chunks_written(&mut controller, &mut write_recv).await;
         match controller.get_status() {
             SyncState::Failed { reason } => {
                 assert!(reason.starts_with("Unexpected error during finalize_tx: "));
@@ -1245,7 +1776,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, mut verify_recv, mut write_recv) = create_controller(
             task_executor,
             Some(peer_id),
             store,
@@ -1261,14 +1792,11 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        controller.state = SyncState::Downloading {
-            peer_id,
-            from_chunk: 0,
-            to_chunk: chunk_count as u64,
-            since: Instant::now(),
-        };
+        set_downloading(&mut controller, peer_id, 0, chunk_count as u64);
 
         controller.on_response(peer_id, chunks).await;
+        wait_chunks_verified(&mut controller, &mut verify_recv).await;
+        wait_chunks_written(&mut controller, &mut write_recv).await;
         assert_eq!(*controller.get_status(), SyncState::Completed);
         assert_eq!(network_recv.try_recv().is_err(), true);
     }
@@ -1283,7 +1811,7 @@ mod tests {
 
         let runtime = TestRuntime::default();
         let task_executor = runtime.task_executor.clone();
-        let (mut controller, mut network_recv) = create_controller(
+        let (mut controller, mut network_recv, _, _) = create_controller(
             task_executor,
             Some(init_peer_id),
             store,
@@ -1292,8 +1820,9 @@ mod tests {
             chunk_count,
         );
 
-        for i in 0..(MAX_REQUEST_FAILURES + 1) {
-            controller.handle_response_failure(init_peer_id, "unit test");
+        let max_attempts = controller.retry.chunks.max_attempts;
+        for i in 0..max_attempts {
+            controller.handle_response_failure(init_peer_id, RequestFailure::Rpc, "unit test");
             if let Some(msg) = network_recv.recv().await {
                 match msg {
                     NetworkMessage::ReportPeer {
@@ -1325,8 +1854,8 @@ mod tests {
                 }
             }
 
-            assert_eq!(controller.failures, i + 1);
-            if i == MAX_REQUEST_FAILURES {
+            assert_eq!(controller.stats[&init_peer_id].retry.failures(), i + 1);
+            if i + 1 == max_attempts {
                 assert_eq!(*controller.get_status(), SyncState::Idle);
 
                 if let Some(msg) = network_recv.recv().await {
@@ -1365,6 +1894,198 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_peer_busy() {
+        let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+
+        let tx_seq = 0;
+        let chunk_count = 123;
+        let (store, _, txs, _) = create_2_store(vec![chunk_count]);
+
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv, _, _) = create_controller(
+            task_executor,
+            Some(init_peer_id),
+            store,
+            txs[0].data_merkle_root,
+            tx_seq,
+            chunk_count,
+        );
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        controller.peers.add_new_peer(init_peer_id, addr);
+        controller
+            .peers
+            .update_state_force(&init_peer_id, PeerState::Connected);
+
+        controller.state = SyncState::AwaitingDownload;
+        controller.try_request_next();
+        assert_eq!(*controller.get_status(), SyncState::Downloading);
+        assert!(matches!(
+            network_recv.try_recv().unwrap(),
+            NetworkMessage::SendRequest { .. }
+        ));
+
+        // the busy peer is neither penalized nor requested again for a while
+        controller.on_peer_busy(init_peer_id);
+        assert!(controller.inflight.is_empty());
+        assert_eq!(*controller.get_status(), SyncState::AwaitingDownload);
+
+        controller.try_request_next();
+        assert_eq!(*controller.get_status(), SyncState::AwaitingDownload);
+        assert!(network_recv.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_skip_stored_chunks() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, _) = create_default_controller(task_executor, None);
+
+        assert!(controller.skip_stored_chunks(&[(0, 10), (20, 30)]));
+        assert_eq!(controller.missing, VecDeque::from([(10, 20), (30, 123)]));
+
+        assert!(!controller.skip_stored_chunks(&[(0, 123)]));
+        assert!(controller.missing.is_empty());
+    }
+
+    #[test]
+    fn test_next_range_available() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, _) = create_default_controller(task_executor, None);
+        controller.missing = VecDeque::from([(0, 10), (20, 30)]);
+
+        // the peer does not have any missing chunks
+        assert_eq!(controller.next_range(Some(&[(10, 20)])), None);
+
+        // split the missing range with the available one
+        assert_eq!(
+            controller.next_range(Some(&[(5, 8), (22, 25)])),
+            Some((5, 8))
+        );
+        assert_eq!(
+            controller.missing,
+            VecDeque::from([(0, 5), (8, 10), (20, 30)])
+        );
+
+        // the peer has the whole file
+        assert_eq!(controller.next_range(None), Some((0, 5)));
+        assert_eq!(controller.missing, VecDeque::from([(8, 10), (20, 30)]));
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, _) = create_default_controller(task_executor, None);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        controller.missing = VecDeque::from([(10, 20), (50, 60)]);
+        let permit = controller.request_limiter.try_acquire(peer_id).unwrap();
+        controller.inflight.insert(
+            peer_id,
+            InflightRequest {
+                from_chunk: 30,
+                to_chunk: 40,
+                since: Instant::now(),
+                _permit: permit,
+            },
+        );
+        controller.sources.insert(peer_id, addr.clone());
+        controller.reset();
+
+        let progress = controller.progress();
+        assert_eq!(
+            progress.downloaded,
+            vec![(0, 10), (20, 30), (40, 50), (60, 123)]
+        );
+        assert_eq!(progress.attempts, 1);
+
+        let (mut restored, _) = create_default_controller(runtime.task_executor.clone(), None);
+        restored.restore_progress(progress);
+        assert_eq!(restored.attempts, 1);
+        assert_eq!(restored.peers.peer_addr(&peer_id), Some(addr));
+        assert_eq!(restored.peers.peer_state(&peer_id), Some(PeerState::Found));
+    }
+
+    #[tokio::test]
+    async fn test_request_chunks_in_parallel() {
+        let runtime = TestRuntime::default();
+        let task_executor = runtime.task_executor.clone();
+        let (mut controller, mut network_recv) = create_default_controller(task_executor, None);
+
+        controller.num_chunks = MAX_CHUNKS_TO_REQUEST * 2 + 1;
+        controller.missing = VecDeque::from([(0, controller.num_chunks)]);
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let mut peers = vec![];
+        for _ in 0..2 {
+            let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+            controller.peers.add_new_peer(peer_id, addr.clone());
+            controller
+                .peers
+                .update_state_force(&peer_id, PeerState::Connected);
+            peers.push(peer_id);
+        }
+
+        controller.try_request_next();
+        assert_eq!(*controller.get_status(), SyncState::Downloading);
+        assert_eq!(controller.inflight.len(), 2);
+        assert_eq!(
+            controller.missing,
+            VecDeque::from([(MAX_CHUNKS_TO_REQUEST * 2, controller.num_chunks)])
+        );
+
+        let mut ranges = vec![];
+        for _ in 0..2 {
+            match network_recv.recv().await {
+                Some(NetworkMessage::SendRequest {
+                    request: Request::GetChunks(request),
+                    ..
+                }) => ranges.push((request.index_start, request.index_end)),
+                _ => panic!("Not expected message: NetworkMessage::SendRequest"),
+            }
+        }
+        ranges.sort_unstable();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, MAX_CHUNKS_TO_REQUEST),
+                (MAX_CHUNKS_TO_REQUEST, MAX_CHUNKS_TO_REQUEST * 2)
+            ]
+        );
+
+        // the range of a disconnected peer is requested again in order
+        let from_chunk = controller.inflight[&peers[0]].from_chunk;
+        controller.on_peer_disconnected(peers[0]);
+        assert_eq!(controller.inflight.len(), 1);
+        assert_eq!(controller.missing.front().unwrap().0, from_chunk);
+    }
+
+    /// Sets the controller to download the range from the peer, and skips other missing chunks.
+    fn set_downloading(
+        controller: &mut SerialSyncController,
+        peer_id: PeerId,
+        from_chunk: u64,
+        to_chunk: u64,
+    ) {
+        controller.missing.clear();
+        let permit = controller.request_limiter.try_acquire(peer_id).unwrap();
+        controller.inflight.insert(
+            peer_id,
+            InflightRequest {
+                from_chunk,
+                to_chunk,
+                since: Instant::now(),
+                _permit: permit,
+            },
+        );
+        controller.state = SyncState::Downloading;
+    }
+
     fn create_test_announcement(tx_seq: u64, peer_id: PeerId) -> SignedAnnounceFile {
         let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
         let msg = AnnounceFile {
@@ -1372,6 +2093,7 @@ mod tests {
             peer_id: peer_id.into(),
             at: address.into(),
             timestamp: timestamp_now(),
+            ranges: vec![],
         };
 
         let local_private_key = identity::Keypair::generate_secp256k1();
@@ -1401,13 +2123,22 @@ mod tests {
         let file_location_cache: Arc<FileLocationCache> = Default::default();
         file_location_cache.insert(create_test_announcement(tx_seq, peer_id));
 
+        let store = Store::new(store, task_executor.clone());
+        let (verifier, _) = ChunkVerifyQueue::new(1, store.clone()).unwrap();
+        let (writer, _) = ChunkWriteQueue::spawn(&task_executor, store.clone());
+
         let controller = SerialSyncController::new(
             tx_seq,
             data_merkle_root,
             num_chunks,
             ctx,
-            Store::new(store, task_executor),
+            store,
             file_location_cache.clone(),
+            Default::default(),
+            verifier,
+            writer,
+            Arc::new(PeerRequestLimiter::new(MAX_PARALLEL_PEERS)),
+            Default::default(),
         );
 
         (controller, network_recv)
@@ -1420,7 +2151,12 @@ mod tests {
         data_merkle_root: DataRoot,
         tx_seq: u64,
         num_chunks: usize,
-    ) -> (SerialSyncController, UnboundedReceiver<NetworkMessage>) {
+    ) -> (
+        SerialSyncController,
+        UnboundedReceiver<NetworkMessage>,
+        UnboundedReceiver<VerifyResult>,
+        UnboundedReceiver<WriteResult>,
+    ) {
         let (network_send, network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
         let ctx = Arc::new(SyncNetworkContext::new(network_send));
 
@@ -1432,15 +2168,50 @@ mod tests {
         let file_location_cache: Arc<FileLocationCache> = Default::default();
         file_location_cache.insert(create_test_announcement(tx_seq, peer_id));
 
+        let store = Store::new(store, task_executor.clone());
+        let (verifier, verify_recv) = ChunkVerifyQueue::new(1, store.clone()).unwrap();
+        let (writer, write_recv) = ChunkWriteQueue::spawn(&task_executor, store.clone());
+
         let controller = SerialSyncController::new(
             tx_seq,
             data_merkle_root,
             num_chunks as u64,
             ctx,
-            Store::new(store, task_executor),
+            store,
             file_location_cache.clone(),
+            Default::default(),
+            verifier,
+            writer,
+            Arc::new(PeerRequestLimiter::new(MAX_PARALLEL_PEERS)),
+            Default::default(),
         );
 
-        (controller, network_recv)
+        (controller, network_recv, verify_recv, write_recv)
+    }
+
+    async fn wait_chunks_verified(
+        controller: &mut SerialSyncController,
+        verify_recv: &mut UnboundedReceiver<VerifyResult>,
+    ) {
+        let result = verify_recv.recv().await.unwrap();
+        assert_eq!(result.tx_seq, controller.tx_seq);
+        controller.on_chunks_verified(
+            result.peer_id,
+            result.from_chunk,
+            result.to_chunk,
+            result.chunks,
+            result.result,
+        );
+    }
+
+    async fn wait_chunks_written(
+        controller: &mut SerialSyncController,
+        write_recv: &mut UnboundedReceiver<WriteResult>,
+    ) {
+        let result = write_recv.recv().await.unwrap();
+        assert_eq!(result.tx_seq, controller.tx_seq);
+        controller
+            .on_chunks_written(result.from_chunk, result.to_chunk, result.result)
+            .await;
     }
 }
@@ -0,0 +1,236 @@
+use crate::metrics;
+use anyhow::{anyhow, Result};
+use network::PeerId;
+use shared_types::{ChunkArray, ChunkArrayWithProof};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use storage::log_store::LogStoreRead;
+use storage_async::Store;
+use tokio::sync::mpsc;
+
+/// Maximum number of chunk arrays queued or being verified.
+const QUEUE_CAPACITY: usize = 64;
+
+/// File sync controllers stop requesting chunks from peers when the number of queued chunk
+/// arrays reaches this watermark. The rest capacity is reserved for the requests in flight.
+const HIGH_WATERMARK: usize = 32;
+
+/// Result of verifying the downloaded chunks of a file against the flow root.
+#[derive(Debug)]
+pub struct VerifyResult {
+    pub tx_seq: u64,
+    pub peer_id: PeerId,
+    pub from_chunk: u64,
+    pub to_chunk: u64,
+    pub chunks: ChunkArray,
+    /// `Ok(false)` if the proof is valid, but its root is not found in the flow.
+    pub result: Result<bool, String>,
+}
+
+/// Bounded queue of downloaded chunks to verify, which is shared by all file sync controllers.
+/// Chunks are verified on a thread pool, since hashing the leaves is CPU bound, and the results
+/// are sent back to the sync service.
+pub struct ChunkVerifyQueue {
+    /// Chunks are verified on the sync service's task if `None`.
+    pool: Option<rayon::ThreadPool>,
+
+    store: Store,
+
+    result_send: mpsc::UnboundedSender<VerifyResult>,
+
+    /// Number of chunk arrays queued or being verified.
+    depth: Arc<AtomicUsize>,
+
+    capacity: usize,
+
+    high_watermark: usize,
+}
+
+impl ChunkVerifyQueue {
+    /// Creates the queue with `threads` to verify chunks, or to verify them on the caller's task
+    /// if zero, and returns the queue along with the receiver of verify results.
+    pub fn new(
+        threads: usize,
+        store: Store,
+    ) -> Result<(Arc<Self>, mpsc::UnboundedReceiver<VerifyResult>)> {
+        Self::with_capacity(threads, store, QUEUE_CAPACITY, HIGH_WATERMARK)
+    }
+
+    pub fn with_capacity(
+        threads: usize,
+        store: Store,
+        capacity: usize,
+        high_watermark: usize,
+    ) -> Result<(Arc<Self>, mpsc::UnboundedReceiver<VerifyResult>)> {
+        assert!(high_watermark <= capacity);
+
+        let pool = if threads == 0 {
+            None
+        } else {
+            Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|i| format!("sync-verify-{}", i))
+                    .build()?,
+            )
+        };
+        let (result_send, result_recv) = mpsc::unbounded_channel();
+
+        let queue = Arc::new(ChunkVerifyQueue {
+            pool,
+            store,
+            result_send,
+            depth: Default::default(),
+            capacity,
+            high_watermark,
+        });
+
+        Ok((queue, result_recv))
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the queue reaches the high watermark, in which case no more chunks
+    /// should be requested from peers.
+    pub fn is_congested(&self) -> bool {
+        self.depth() >= self.high_watermark
+    }
+
+    /// Queues the chunks downloaded from the peer to verify. Returns `false` if the queue is full.
+    pub async fn push(
+        &self,
+        tx_seq: u64,
+        peer_id: PeerId,
+        from_chunk: u64,
+        to_chunk: u64,
+        response: ChunkArrayWithProof,
+    ) -> bool {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        if depth > self.capacity {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+
+        metrics::set_gauge(&metrics::SYNC_VERIFY_QUEUE_DEPTH, depth as i64);
+
+        let pool = match &self.pool {
+            Some(pool) => pool,
+            None => {
+                let result = self
+                    .store
+                    .get_store()
+                    .read()
+                    .await
+                    .validate_range_proof(tx_seq, &response)
+                    .map_err(|e| format!("{:?}", e));
+                let result = VerifyResult {
+                    tx_seq,
+                    peer_id,
+                    from_chunk,
+                    to_chunk,
+                    chunks: response.chunks,
+                    result,
+                };
+                complete(&self.depth, &self.result_send, result);
+                return true;
+            }
+        };
+
+        let store = self.store.clone();
+        let result_send = self.result_send.clone();
+        let depth = self.depth.clone();
+        pool.spawn(move || {
+            let result = verify(&store, tx_seq, &response).map_err(|e| format!("{:?}", e));
+            let result = VerifyResult {
+                tx_seq,
+                peer_id,
+                from_chunk,
+                to_chunk,
+                chunks: response.chunks,
+                result,
+            };
+            complete(&depth, &result_send, result);
+        });
+
+        true
+    }
+}
+
+/// Releases the slot of the verified chunks, and sends the result back to the sync service.
+fn complete(
+    depth: &AtomicUsize,
+    result_send: &mpsc::UnboundedSender<VerifyResult>,
+    result: VerifyResult,
+) {
+    let depth = depth.fetch_sub(1, Ordering::Relaxed) - 1;
+    metrics::set_gauge(&metrics::SYNC_VERIFY_QUEUE_DEPTH, depth as i64);
+
+    if result_send.send(result).is_err() {
+        warn!("Unable to send chunks verify result: the receiver dropped");
+    }
+}
+
+/// Verifies the chunks against the flow root. The leaves are hashed without holding the store
+/// lock, so that the chunks of other responses could be written meanwhile.
+fn verify(store: &Store, tx_seq: u64, response: &ChunkArrayWithProof) -> Result<bool> {
+    let tx = store
+        .get_store()
+        .blocking_read()
+        .get_tx_by_seq_number(tx_seq)?
+        .ok_or_else(|| anyhow!("tx missing"))?;
+    let root = response.validate(tx.start_entry_index)?;
+    Ok(store.get_store().blocking_read().check_flow_root(&root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::create_2_store;
+    use task_executor::test_utils::TestRuntime;
+
+    #[tokio::test]
+    async fn test_verify_result() {
+        // verified on the thread pool, or on the caller's task
+        for threads in [2, 0] {
+            let runtime = TestRuntime::default();
+            let chunk_count = 123;
+            let (_, peer_store, _, _) = create_2_store(vec![chunk_count]);
+            let response = peer_store
+                .read()
+                .await
+                .get_chunks_with_proof_by_tx_and_index_range(0, 0, chunk_count)
+                .unwrap()
+                .unwrap();
+            let store = Store::new(peer_store, runtime.task_executor.clone());
+            let (queue, mut results) =
+                ChunkVerifyQueue::with_capacity(threads, store, 2, 1).unwrap();
+            let peer_id = PeerId::random();
+
+            assert!(
+                queue
+                    .push(0, peer_id, 0, chunk_count as u64, response.clone())
+                    .await
+            );
+            let result = results.recv().await.unwrap();
+            assert_eq!(
+                (result.tx_seq, result.peer_id, result.from_chunk),
+                (0, peer_id, 0)
+            );
+            assert_eq!(result.chunks, response.chunks);
+            assert_eq!(result.result, Ok(true));
+
+            // proved for another tx
+            assert!(
+                queue
+                    .push(1, peer_id, 0, chunk_count as u64, response)
+                    .await
+            );
+            assert!(results.recv().await.unwrap().result.is_err());
+
+            assert_eq!(queue.depth(), 0);
+            assert!(!queue.is_congested());
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::metrics;
+use shared_types::ChunkArray;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use storage_async::Store;
+use task_executor::TaskExecutor;
+use tokio::sync::mpsc;
+
+/// Maximum number of chunk arrays queued to write into store.
+const QUEUE_CAPACITY: usize = 64;
+
+/// File sync controllers stop requesting chunks from peers when the number of queued chunk
+/// arrays reaches this watermark. The rest capacity is reserved for the requests in flight.
+const HIGH_WATERMARK: usize = 32;
+
+/// Result of writing the downloaded chunks of a file into store.
+#[derive(Debug)]
+pub struct WriteResult {
+    pub tx_seq: u64,
+    pub from_chunk: u64,
+    pub to_chunk: u64,
+    pub result: Result<(), String>,
+}
+
+struct WriteTask {
+    tx_seq: u64,
+    from_chunk: u64,
+    to_chunk: u64,
+    chunks: ChunkArray,
+}
+
+/// Bounded queue of downloaded chunks to write into store, which is shared by all file sync
+/// controllers. Chunks are written by a background task one by one, and the results are sent
+/// back to the sync service.
+pub struct ChunkWriteQueue {
+    sender: mpsc::Sender<WriteTask>,
+
+    /// Number of chunk arrays queued or being written.
+    depth: Arc<AtomicUsize>,
+
+    /// Number of chunk bytes queued or being written.
+    bytes: Arc<AtomicUsize>,
+
+    high_watermark: usize,
+}
+
+impl ChunkWriteQueue {
+    /// Spawns the task to write queued chunks, and returns the queue along with the receiver of
+    /// write results.
+    pub fn spawn(
+        executor: &TaskExecutor,
+        store: Store,
+    ) -> (Arc<Self>, mpsc::UnboundedReceiver<WriteResult>) {
+        Self::spawn_with_capacity(executor, store, QUEUE_CAPACITY, HIGH_WATERMARK)
+    }
+
+    pub fn spawn_with_capacity(
+        executor: &TaskExecutor,
+        store: Store,
+        capacity: usize,
+        high_watermark: usize,
+    ) -> (Arc<Self>, mpsc::UnboundedReceiver<WriteResult>) {
+        assert!(high_watermark <= capacity);
+
+        let (sender, mut receiver) = mpsc::channel::<WriteTask>(capacity);
+        let (result_send, result_recv) = mpsc::unbounded_channel();
+
+        let queue = Arc::new(ChunkWriteQueue {
+            sender,
+            depth: Default::default(),
+            bytes: Default::default(),
+            high_watermark,
+        });
+
+        let depth = queue.depth.clone();
+        let bytes = queue.bytes.clone();
+        executor.spawn(
+            async move {
+                while let Some(task) = receiver.recv().await {
+                    let size = task.chunks.data.len();
+                    let result = store
+                        .put_chunks(task.tx_seq, task.chunks)
+                        .await
+                        .map_err(|e| format!("{:?}", e));
+
+                    let depth = depth.fetch_sub(1, Ordering::Relaxed) - 1;
+                    let bytes = bytes.fetch_sub(size, Ordering::Relaxed) - size;
+                    metrics::set_gauge(&metrics::SYNC_WRITE_QUEUE_DEPTH, depth as i64);
+                    metrics::set_gauge(&metrics::SYNC_WRITE_QUEUE_BYTES, bytes as i64);
+
+                    let result = WriteResult {
+                        tx_seq: task.tx_seq,
+                        from_chunk: task.from_chunk,
+                        to_chunk: task.to_chunk,
+                        result,
+                    };
+
+                    if result_send.send(result).is_err() {
+                        warn!("Unable to send chunks write result: the receiver dropped");
+                    }
+                }
+            },
+            "sync_write_queue",
+        );
+
+        (queue, result_recv)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the queue reaches the high watermark, in which case no more chunks
+    /// should be requested from peers.
+    pub fn is_congested(&self) -> bool {
+        self.depth() >= self.high_watermark
+    }
+
+    /// Queues the chunks to write into store. Returns `false` if the queue is full.
+    pub fn push(&self, tx_seq: u64, from_chunk: u64, to_chunk: u64, chunks: ChunkArray) -> bool {
+        let size = chunks.data.len();
+        let task = WriteTask {
+            tx_seq,
+            from_chunk,
+            to_chunk,
+            chunks,
+        };
+
+        // count before sending, since the task may be written before `try_send` returns
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes = self.bytes.fetch_add(size, Ordering::Relaxed) + size;
+
+        if self.sender.try_send(task).is_err() {
+            self.depth.fetch_sub(1, Ordering::Relaxed);
+            self.bytes.fetch_sub(size, Ordering::Relaxed);
+            return false;
+        }
+
+        metrics::set_gauge(&metrics::SYNC_WRITE_QUEUE_DEPTH, depth as i64);
+        metrics::set_gauge(&metrics::SYNC_WRITE_QUEUE_BYTES, bytes as i64);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::CHUNK_SIZE;
+    use storage::log_store::log_manager::{LogConfig, LogManager};
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_write_result() {
+        let runtime = TestRuntime::default();
+        let store = LogManager::memorydb(LogConfig::default()).unwrap();
+        let store = Store::new(Arc::new(RwLock::new(store)), runtime.task_executor.clone());
+        let (queue, mut results) =
+            ChunkWriteQueue::spawn_with_capacity(&runtime.task_executor, store, 2, 1);
+
+        let chunks = ChunkArray {
+            data: vec![0; CHUNK_SIZE],
+            start_index: 0,
+        };
+        assert!(queue.push(3, 0, 1, chunks));
+
+        let result = results.recv().await.unwrap();
+        assert_eq!(
+            (result.tx_seq, result.from_chunk, result.to_chunk),
+            (3, 0, 1)
+        );
+        // transaction not found
+        assert!(result.result.is_err());
+
+        assert_eq!(queue.depth(), 0);
+        assert!(!queue.is_congested());
+    }
+}
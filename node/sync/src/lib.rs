@@ -1,9 +1,74 @@
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate tracing;
 
+mod auditor;
 mod context;
 mod controllers;
+mod metrics;
+mod priority;
+mod proof_cache;
+mod provisional;
+mod recovery;
+mod retry;
+mod seeder;
 mod service;
+#[cfg(feature = "simulation")]
+pub mod simulation;
 mod test_util;
 
+pub use priority::SyncPriority;
+pub use retry::{RetryConfig, RetryPolicy};
 pub use service::{SyncMessage, SyncRequest, SyncResponse, SyncSender, SyncService};
+
+use shared_types::{DataRoot, StreamFilter};
+use std::time::Duration;
+
+#[cfg(feature = "simulation")]
+use simulation::rng;
+
+/// Source of randomness of the sync scheduler, which is seeded in simulation instead.
+#[cfg(not(feature = "simulation"))]
+fn rng() -> rand::rngs::ThreadRng {
+    rand::thread_rng()
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Policy to decide which files get bandwidth first.
+    pub priority: SyncPriority,
+    /// Files that are always synced before others, regardless of the priority policy.
+    pub pinned_files: Vec<DataRoot>,
+    /// Interval between random chunk audits of peers that announced files. Zero to disable.
+    pub audit_interval: Duration,
+    /// Maximum number of files synced at the same time, and the others are queued.
+    pub max_concurrent_syncs: usize,
+    /// Maximum number of chunks requests in flight to a single peer among all files.
+    pub max_requests_per_peer: usize,
+    /// Number of random peers to offer each newly finalized file. Zero to disable seeding.
+    pub seed_peers: usize,
+    /// Only the files of the subscribed streams are synced automatically upon announcements,
+    /// while the files requested explicitly are always synced.
+    pub stream_filter: StreamFilter,
+    /// Number of threads to verify downloaded chunks, or zero to verify them on the sync task.
+    pub verify_threads: usize,
+    /// Policies to retry the failed requests to peers.
+    pub retry: RetryConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            priority: Default::default(),
+            pinned_files: vec![],
+            audit_interval: Duration::ZERO,
+            max_concurrent_syncs: 16,
+            max_requests_per_peer: 4,
+            seed_peers: 0,
+            stream_filter: Default::default(),
+            verify_threads: 4,
+            retry: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+pub use lighthouse_metrics::*;
+
+lazy_static! {
+    pub static ref SYNC_CONTROLLERS: Result<IntGauge> = try_create_int_gauge(
+        "sync_controllers",
+        "Number of files being synchronized from peers"
+    );
+    pub static ref SYNC_FAILED_CONTROLLERS: Result<IntGauge> = try_create_int_gauge(
+        "sync_failed_controllers",
+        "Number of files whose synchronization failed and awaits retry"
+    );
+    pub static ref SYNC_QUEUED_FILES: Result<IntGauge> = try_create_int_gauge(
+        "sync_queued_files",
+        "Number of files waiting to sync due to the concurrency limit"
+    );
+    pub static ref SYNC_COMPLETED_FILES: Result<IntCounter> = try_create_int_counter(
+        "sync_completed_files_total",
+        "Count of files synchronized from peers"
+    );
+    pub static ref SYNC_RECEIVED_BYTES: Result<IntCounter> = try_create_int_counter(
+        "sync_received_bytes_total",
+        "Count of chunk bytes received in responses from peers"
+    );
+    pub static ref SYNC_WRITE_QUEUE_DEPTH: Result<IntGauge> = try_create_int_gauge(
+        "sync_write_queue_depth",
+        "Number of downloaded chunk arrays queued to write into store"
+    );
+    pub static ref SYNC_WRITE_QUEUE_BYTES: Result<IntGauge> = try_create_int_gauge(
+        "sync_write_queue_bytes",
+        "Size in bytes of downloaded chunks queued to write into store"
+    );
+    pub static ref SYNC_VERIFY_QUEUE_DEPTH: Result<IntGauge> = try_create_int_gauge(
+        "sync_verify_queue_depth",
+        "Number of downloaded chunk arrays queued to verify"
+    );
+    pub static ref SYNC_AUDITS_PASSED: Result<IntCounter> = try_create_int_counter(
+        "sync_audits_passed_total",
+        "Count of random chunk audits passed by peers"
+    );
+    pub static ref SYNC_AUDITS_FAILED: Result<IntCounter> = try_create_int_counter(
+        "sync_audits_failed_total",
+        "Count of random chunk audits failed by peers, including timeouts"
+    );
+    pub static ref SYNC_FILE_OFFERS_SENT: Result<IntCounter> = try_create_int_counter(
+        "sync_file_offers_sent_total",
+        "Count of newly finalized files offered to peers"
+    );
+    pub static ref SYNC_FILE_OFFERS_RECEIVED: Result<IntCounter> = try_create_int_counter(
+        "sync_file_offers_received_total",
+        "Count of files offered by peers"
+    );
+    pub static ref SYNC_RECOVERED_CHUNKS: Result<IntCounter> = try_create_int_counter(
+        "sync_recovered_chunks_total",
+        "Count of PoRA chunks recovered from erasure shards of peers"
+    );
+    pub static ref SYNC_RECOVERY_FAILED_CHUNKS: Result<IntCounter> = try_create_int_counter(
+        "sync_recovery_failed_chunks_total",
+        "Count of PoRA chunks failed to recover from erasure shards of peers"
+    );
+    pub static ref SYNC_PROVISIONAL_DOWNLOADS: Result<IntGauge> = try_create_int_gauge(
+        "sync_provisional_downloads",
+        "Number of files being downloaded before the transactions are synced"
+    );
+    pub static ref SYNC_PROVISIONAL_FILES: Result<IntCounter> = try_create_int_counter(
+        "sync_provisional_files_total",
+        "Count of files downloaded and staged before the transactions are synced"
+    );
+    pub static ref SYNC_PROVISIONAL_FAILED_FILES: Result<IntCounter> = try_create_int_counter(
+        "sync_provisional_failed_files_total",
+        "Count of files failed to download before the transactions are synced"
+    );
+    pub static ref SYNC_PROOF_CACHE_HITS: Result<IntCounter> = try_create_int_counter(
+        "sync_proof_cache_hits_total",
+        "Count of chunks requests served with cached range proofs"
+    );
+    pub static ref SYNC_PROOF_CACHE_MISSES: Result<IntCounter> = try_create_int_counter(
+        "sync_proof_cache_misses_total",
+        "Count of chunks requests served with newly generated range proofs"
+    );
+    pub static ref SYNC_SERVE_CHUNKS_TIMES: Result<Histogram> = try_create_histogram(
+        "sync_serve_chunks_seconds",
+        "Time taken to handle a chunks request from a peer"
+    );
+}
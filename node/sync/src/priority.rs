@@ -0,0 +1,89 @@
+use shared_types::DataRoot;
+use std::str::FromStr;
+
+/// Policy to decide which pending files get bandwidth first when many files are synced at the
+/// same time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPriority {
+    /// Files submitted earlier are synced first.
+    OldestFirst,
+    /// Files submitted later are synced first.
+    NewestFirst,
+    /// Smaller files are synced first, so that more files become available quickly.
+    SmallestFirst,
+}
+
+impl Default for SyncPriority {
+    fn default() -> Self {
+        SyncPriority::OldestFirst
+    }
+}
+
+impl FromStr for SyncPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest_first" => Ok(SyncPriority::OldestFirst),
+            "newest_first" => Ok(SyncPriority::NewestFirst),
+            "smallest_first" => Ok(SyncPriority::SmallestFirst),
+            _ => Err(format!("Unknown sync priority policy: {}", s)),
+        }
+    }
+}
+
+/// A file being synced, with the properties that the priority policy depends on.
+#[derive(Clone, Debug)]
+pub(crate) struct PendingFile {
+    pub tx_seq: u64,
+    pub data_root: DataRoot,
+    pub num_chunks: u64,
+}
+
+/// Sorts files in descending order of priority, where the pinned files always come first.
+pub(crate) fn sort_by_priority(
+    files: &mut [PendingFile],
+    policy: SyncPriority,
+    pinned: &[DataRoot],
+) {
+    files.sort_by_key(|file| {
+        let unpinned = !pinned.contains(&file.data_root);
+        let key = match policy {
+            SyncPriority::OldestFirst => (0, file.tx_seq),
+            SyncPriority::NewestFirst => (0, u64::MAX - file.tx_seq),
+            SyncPriority::SmallestFirst => (file.num_chunks, file.tx_seq),
+        };
+        (unpinned, key)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(policy: SyncPriority, pinned: &[DataRoot]) -> Vec<u64> {
+        let mut files: Vec<PendingFile> = [(1, 30), (2, 10), (3, 20)]
+            .iter()
+            .map(|&(tx_seq, num_chunks)| PendingFile {
+                tx_seq,
+                data_root: DataRoot::from_low_u64_be(tx_seq),
+                num_chunks,
+            })
+            .collect();
+
+        sort_by_priority(&mut files, policy, pinned);
+
+        files.into_iter().map(|file| file.tx_seq).collect()
+    }
+
+    #[test]
+    fn test_sort_by_priority() {
+        assert_eq!(sorted(SyncPriority::OldestFirst, &[]), vec![1, 2, 3]);
+        assert_eq!(sorted(SyncPriority::NewestFirst, &[]), vec![3, 2, 1]);
+        assert_eq!(sorted(SyncPriority::SmallestFirst, &[]), vec![2, 3, 1]);
+
+        let pinned = [DataRoot::from_low_u64_be(1)];
+        assert_eq!(sorted(SyncPriority::NewestFirst, &pinned), vec![1, 3, 2]);
+        assert_eq!(sorted(SyncPriority::SmallestFirst, &pinned), vec![1, 2, 3]);
+    }
+}
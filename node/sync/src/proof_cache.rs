@@ -0,0 +1,72 @@
+use crate::metrics;
+use hashlink::LruCache;
+use shared_types::FlowRangeProof;
+
+/// Maximum number of proofs cached.
+const MAX_CACHED_PROOFS: usize = 1024;
+
+/// Cache of the range proofs recently served to peers, keyed by `(tx_seq, index_start,
+/// index_end)`. Popular new files are requested by many peers within seconds, and generating the
+/// merkle proofs is much more expensive than reading the chunks.
+///
+/// Proofs remain valid when more data is appended to the log, but the cache must be cleared when
+/// the log is reverted.
+pub(crate) struct ProofCache {
+    proofs: LruCache<(u64, u64, u64), FlowRangeProof>,
+}
+
+impl Default for ProofCache {
+    fn default() -> Self {
+        ProofCache::new(MAX_CACHED_PROOFS)
+    }
+}
+
+impl ProofCache {
+    pub fn new(capacity: usize) -> Self {
+        ProofCache {
+            proofs: LruCache::new(capacity),
+        }
+    }
+
+    pub fn get(&mut self, tx_seq: u64, index_start: u64, index_end: u64) -> Option<FlowRangeProof> {
+        let proof = self.proofs.get(&(tx_seq, index_start, index_end)).cloned();
+
+        match proof {
+            Some(_) => metrics::inc_counter(&metrics::SYNC_PROOF_CACHE_HITS),
+            None => metrics::inc_counter(&metrics::SYNC_PROOF_CACHE_MISSES),
+        }
+
+        proof
+    }
+
+    pub fn insert(&mut self, tx_seq: u64, index_start: u64, index_end: u64, proof: FlowRangeProof) {
+        self.proofs.insert((tx_seq, index_start, index_end), proof);
+    }
+
+    pub fn clear(&mut self) {
+        self.proofs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let mut cache = ProofCache::new(2);
+
+        cache.insert(0, 0, 1, FlowRangeProof::new_empty());
+        cache.insert(0, 1, 2, FlowRangeProof::new_empty());
+        assert!(cache.get(0, 0, 1).is_some());
+
+        // the least recently used one is evicted
+        cache.insert(1, 0, 1, FlowRangeProof::new_empty());
+        assert!(cache.get(0, 1, 2).is_none());
+        assert!(cache.get(0, 0, 1).is_some());
+        assert!(cache.get(1, 0, 1).is_some());
+
+        cache.clear();
+        assert!(cache.get(0, 0, 1).is_none());
+    }
+}
@@ -0,0 +1,561 @@
+use crate::context::SyncNetworkContext;
+use crate::metrics;
+use network::{
+    rpc::{GetPendingChunksRequest, RPCResponseErrorCode},
+    Multiaddr, NetworkMessage, PeerAction, PeerId, PeerRequestId, SyncId as RequestId,
+};
+use shared_types::{bytes_to_chunks, ChunkArray, DataRoot, ProvisionalChunks, CHUNK_SIZE};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use storage::error::Result;
+use storage_async::Store;
+use tokio::time::Instant;
+
+/// Maximum number of chunks to request or serve at a time.
+const MAX_CHUNKS_TO_REQUEST: u64 = 2 * 1024;
+
+/// Maximum number of files to download provisionally at the same time.
+const MAX_DOWNLOADS: usize = 16;
+
+/// Timeout to dial a peer or to wait for the response of a peer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Interval to drop the staged files not promoted in time.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DownloadState {
+    Idle,
+    Dialing {
+        since: Instant,
+    },
+    Requesting {
+        index_start: u64,
+        index_end: u64,
+        since: Instant,
+    },
+}
+
+struct Download {
+    data_root: DataRoot,
+    num_chunks: u64,
+    /// Staged chunk ranges of the file, `end` excluded.
+    staged: Vec<(u64, u64)>,
+    /// Peers that announced the file, and the first one is downloaded from.
+    peers: VecDeque<(PeerId, Multiaddr)>,
+    state: DownloadState,
+}
+
+impl Download {
+    /// Returns the peer to download from.
+    fn current_peer(&self) -> Option<PeerId> {
+        self.peers.front().map(|(peer_id, _)| *peer_id)
+    }
+
+    /// Returns the next range to download, which is the first range not staged yet.
+    fn next_range(&self) -> (u64, u64) {
+        let mut from = 0;
+        for &(start, end) in &self.staged {
+            if start > from {
+                return (from, start.min(from + MAX_CHUNKS_TO_REQUEST));
+            }
+            from = from.max(end);
+        }
+        (from, self.num_chunks.min(from + MAX_CHUNKS_TO_REQUEST))
+    }
+}
+
+/// Downloads the files announced by peers before the transactions are synced from the blockchain
+/// into the local staging area, where the chunks are provisional until the whole file is verified
+/// against its data root. The staged file is promoted into the log flow as soon as the log sync
+/// catches up, rather than starting to sync the file only then.
+///
+/// Also serves the staged files, and the finalized ones by data root, to peers.
+pub(crate) struct Provisional {
+    /// Peers connected to the sync service, which could be requested chunks without dialing.
+    connected_peers: HashSet<PeerId>,
+
+    /// Files in download by `tx_seq` announced by peers.
+    downloads: HashMap<u64, Download>,
+
+    /// When the expired staged files are last pruned.
+    last_pruned: Instant,
+
+    ctx: Arc<SyncNetworkContext>,
+    store: Store,
+}
+
+impl Provisional {
+    pub fn new(ctx: Arc<SyncNetworkContext>, store: Store) -> Self {
+        Provisional {
+            connected_peers: Default::default(),
+            downloads: Default::default(),
+            last_pruned: Instant::now(),
+            ctx,
+            store,
+        }
+    }
+
+    pub fn on_peer_connected(&mut self, peer_id: PeerId) {
+        self.connected_peers.insert(peer_id);
+
+        let dialed: Vec<u64> = self
+            .downloads
+            .iter()
+            .filter(|(_, download)| matches!(download.state, DownloadState::Dialing { .. }))
+            .filter(|(_, download)| download.current_peer() == Some(peer_id))
+            .map(|(tx_seq, _)| *tx_seq)
+            .collect();
+
+        for tx_seq in dialed {
+            self.set_idle(tx_seq, false);
+            self.request_next(tx_seq);
+        }
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer_id: PeerId) {
+        self.connected_peers.remove(&peer_id);
+    }
+
+    pub fn on_dail_failed(&mut self, peer_id: PeerId) {
+        let failed: Vec<u64> = self
+            .downloads
+            .iter()
+            .filter(|(_, download)| matches!(download.state, DownloadState::Dialing { .. }))
+            .filter(|(_, download)| download.current_peer() == Some(peer_id))
+            .map(|(tx_seq, _)| *tx_seq)
+            .collect();
+
+        for tx_seq in failed {
+            self.set_idle(tx_seq, true);
+            self.request_next(tx_seq);
+        }
+    }
+
+    /// Starts to download the file of a transaction not synced from the blockchain yet from the
+    /// announcing peer, or adds the peer as a fallback if the file is in download already.
+    pub async fn on_file_announced(
+        &mut self,
+        tx_seq: u64,
+        data_root: DataRoot,
+        size: u64,
+        peer_id: PeerId,
+        addr: Multiaddr,
+    ) {
+        if let Some(download) = self.downloads.get_mut(&tx_seq) {
+            if !download.peers.iter().any(|(peer, _)| *peer == peer_id) {
+                download.peers.push_back((peer_id, addr));
+            }
+            return;
+        }
+
+        if self.downloads.len() >= MAX_DOWNLOADS {
+            debug!(%tx_seq, "Too many files in provisional download");
+            return;
+        }
+
+        let staged = match self.start_download(&data_root, size).await {
+            Ok(Some(staged)) => staged,
+            Ok(None) => return,
+            Err(err) => {
+                warn!(%tx_seq, ?data_root, %err, "Failed to start provisional download");
+                return;
+            }
+        };
+
+        info!(%tx_seq, ?data_root, %peer_id, "Start to download file provisionally");
+
+        self.downloads.insert(
+            tx_seq,
+            Download {
+                data_root,
+                num_chunks: bytes_to_chunks(size as usize) as u64,
+                staged,
+                peers: VecDeque::from([(peer_id, addr)]),
+                state: DownloadState::Idle,
+            },
+        );
+        self.request_next(tx_seq);
+    }
+
+    /// Returns the staged ranges of the file, or `None` if the file is staged completely.
+    async fn start_download(
+        &self,
+        data_root: &DataRoot,
+        size: u64,
+    ) -> Result<Option<Vec<(u64, u64)>>> {
+        self.store.put_tx_pending(data_root, size, true).await?;
+
+        Ok(match self.store.get_pending_tx(data_root).await? {
+            Some(pending) if !pending.verified => Some(pending.staged),
+            _ => None,
+        })
+    }
+
+    /// Requests the next range of the file from the first announced peer, which is dialed first
+    /// if not connected.
+    fn request_next(&mut self, tx_seq: u64) {
+        let download = match self.downloads.get_mut(&tx_seq) {
+            Some(download) if download.state == DownloadState::Idle => download,
+            _ => return,
+        };
+
+        let (peer_id, address) = match download.peers.front() {
+            Some((peer_id, address)) => (*peer_id, address.clone()),
+            None => {
+                warn!(%tx_seq, "No peer to download file provisionally");
+                metrics::inc_counter(&metrics::SYNC_PROVISIONAL_FAILED_FILES);
+                self.downloads.remove(&tx_seq);
+                return;
+            }
+        };
+
+        if !self.connected_peers.contains(&peer_id) {
+            self.ctx.send(NetworkMessage::DialPeer { address, peer_id });
+            download.state = DownloadState::Dialing {
+                since: Instant::now(),
+            };
+            return;
+        }
+
+        let (index_start, index_end) = download.next_range();
+        self.ctx.send(NetworkMessage::SendRequest {
+            peer_id,
+            request_id: network::RequestId::Sync(RequestId::Provisional { tx_seq }),
+            request: network::Request::GetPendingChunks(GetPendingChunksRequest {
+                data_root: download.data_root,
+                index_start,
+                index_end,
+            }),
+        });
+        download.state = DownloadState::Requesting {
+            index_start,
+            index_end,
+            since: Instant::now(),
+        };
+    }
+
+    /// Resets the download to request again, from the next announced peer if `next_peer`.
+    fn set_idle(&mut self, tx_seq: u64, next_peer: bool) {
+        if let Some(download) = self.downloads.get_mut(&tx_seq) {
+            if next_peer {
+                download.peers.pop_front();
+            }
+            download.state = DownloadState::Idle;
+        }
+    }
+
+    pub async fn on_response(&mut self, peer_id: PeerId, tx_seq: u64, response: ProvisionalChunks) {
+        let download = match self.downloads.get(&tx_seq) {
+            Some(download) => download,
+            None => {
+                debug!(%peer_id, %tx_seq, "Received pending chunks for unknown file");
+                return;
+            }
+        };
+
+        let (index_start, index_end) = match download.state {
+            DownloadState::Requesting {
+                index_start,
+                index_end,
+                ..
+            } if download.current_peer() == Some(peer_id) => (index_start, index_end),
+            _ => {
+                debug!(%peer_id, %tx_seq, "Received pending chunks not requested");
+                return;
+            }
+        };
+
+        let data_root = download.data_root;
+        let ProvisionalChunks {
+            data_root: response_root,
+            chunks,
+        } = response;
+        if response_root != data_root
+            || chunks.start_index != index_start
+            || chunks.data.len() as u64 != (index_end - index_start) * CHUNK_SIZE as u64
+        {
+            self.ctx.report_peer(
+                peer_id,
+                PeerAction::LowToleranceError,
+                "Invalid pending chunks",
+            );
+            self.set_idle(tx_seq, true);
+            self.request_next(tx_seq);
+            return;
+        }
+
+        match self.stage_chunks(&data_root, chunks).await {
+            Ok(Some(staged)) => {
+                if let Some(download) = self.downloads.get_mut(&tx_seq) {
+                    download.staged = staged;
+                }
+                self.set_idle(tx_seq, false);
+                self.request_next(tx_seq);
+            }
+            Ok(None) => {
+                info!(%tx_seq, ?data_root, "Downloaded file provisionally");
+                metrics::inc_counter(&metrics::SYNC_PROVISIONAL_FILES);
+                self.downloads.remove(&tx_seq);
+            }
+            Err(err) => {
+                // the file fails the verification, and it is unknown which peer is to blame
+                warn!(%tx_seq, ?data_root, %err, "Failed to stage pending chunks");
+                metrics::inc_counter(&metrics::SYNC_PROVISIONAL_FAILED_FILES);
+                self.downloads.remove(&tx_seq);
+            }
+        }
+    }
+
+    /// Returns the staged ranges of the file, or `None` if the file is staged completely, in
+    /// which case it may be promoted at once.
+    async fn stage_chunks(
+        &self,
+        data_root: &DataRoot,
+        chunks: ChunkArray,
+    ) -> Result<Option<Vec<(u64, u64)>>> {
+        self.store.put_pending_chunks(data_root, chunks).await?;
+
+        Ok(match self.store.get_pending_tx(data_root).await? {
+            Some(pending) if !pending.verified => Some(pending.staged),
+            _ => None,
+        })
+    }
+
+    pub fn on_request_failed(&mut self, peer_id: PeerId, tx_seq: u64) {
+        let requested = match self.downloads.get(&tx_seq) {
+            Some(download) => {
+                matches!(download.state, DownloadState::Requesting { .. })
+                    && download.current_peer() == Some(peer_id)
+            }
+            None => false,
+        };
+
+        if requested {
+            debug!(%peer_id, %tx_seq, "Failed to get pending chunks from peer");
+            self.set_idle(tx_seq, true);
+            self.request_next(tx_seq);
+        }
+    }
+
+    /// Drops the downloads of transactions no longer `pending` the log sync, which are synced
+    /// from peers as usual if not staged completely, and retries the timed out requests with
+    /// other peers.
+    pub fn on_heartbeat(&mut self, pending: impl Fn(u64) -> bool) {
+        self.downloads.retain(|tx_seq, _| pending(*tx_seq));
+
+        let expired: Vec<u64> = self
+            .downloads
+            .iter()
+            .filter(|(_, download)| match download.state {
+                DownloadState::Idle => false,
+                DownloadState::Dialing { since } | DownloadState::Requesting { since, .. } => {
+                    since.elapsed() >= REQUEST_TIMEOUT
+                }
+            })
+            .map(|(tx_seq, _)| *tx_seq)
+            .collect();
+
+        for tx_seq in expired {
+            debug!(%tx_seq, "Provisional download timeout");
+            self.set_idle(tx_seq, true);
+            self.request_next(tx_seq);
+        }
+
+        metrics::set_gauge(
+            &metrics::SYNC_PROVISIONAL_DOWNLOADS,
+            self.downloads.len() as i64,
+        );
+    }
+
+    /// Drops the staged files not promoted in time, e.g. the ones announced by peers but never
+    /// submitted to the blockchain.
+    pub async fn prune_staged(&mut self) {
+        if self.last_pruned.elapsed() < PRUNE_INTERVAL {
+            return;
+        }
+        self.last_pruned = Instant::now();
+
+        match self.store.prune_pending_txs().await {
+            Ok(0) => {}
+            Ok(count) => debug!(%count, "Pruned expired staged files"),
+            Err(err) => warn!(%err, "Failed to prune staged files"),
+        }
+    }
+
+    /// Serves the chunks staged locally, or of the finalized file, to the peer.
+    pub async fn on_request(
+        &self,
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetPendingChunksRequest,
+    ) {
+        debug!(?request, %peer_id, ?request_id, "Received GetPendingChunks request");
+
+        if request.index_start >= request.index_end
+            || request.index_end - request.index_start > MAX_CHUNKS_TO_REQUEST
+        {
+            self.ctx.send(NetworkMessage::SendErrorResponse {
+                peer_id,
+                error: RPCResponseErrorCode::InvalidRequest,
+                reason: "Invalid chunk indices".into(),
+                id: request_id,
+            });
+            return;
+        }
+
+        let (error, reason) = match self.get_chunks(&request).await {
+            Ok(Some(chunks)) => {
+                self.ctx.send(NetworkMessage::SendResponse {
+                    peer_id,
+                    id: request_id,
+                    response: network::Response::PendingChunks(ProvisionalChunks {
+                        data_root: request.data_root,
+                        chunks,
+                    }),
+                });
+                return;
+            }
+            Ok(None) => (
+                RPCResponseErrorCode::ResourceUnavailable,
+                "Chunks not found",
+            ),
+            Err(err) => {
+                debug!(?request, %err, "Failed to get pending chunks");
+                (RPCResponseErrorCode::InvalidRequest, "Chunks unavailable")
+            }
+        };
+
+        self.ctx.send(NetworkMessage::SendErrorResponse {
+            peer_id,
+            error,
+            reason: reason.into(),
+            id: request_id,
+        });
+    }
+
+    async fn get_chunks(&self, request: &GetPendingChunksRequest) -> Result<Option<ChunkArray>> {
+        let data_root = request.data_root;
+        if let Some(chunks) = self
+            .store
+            .get_pending_chunks(&data_root, request.index_start, request.index_end)
+            .await?
+        {
+            return Ok(Some(chunks));
+        }
+
+        // the staged file may have been promoted
+        let tx_seq = match self.store.get_tx_seq_by_data_root(&data_root).await? {
+            Some(tx_seq) => tx_seq,
+            None => return Ok(None),
+        };
+        if !self.store.check_tx_completed(tx_seq).await? {
+            return Ok(None);
+        }
+
+        self.store
+            .get_chunks_by_tx_and_index_range(
+                tx_seq,
+                request.index_start as usize,
+                request.index_end as usize,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::create_2_store;
+    use libp2p::identity;
+    use network::discovery::ConnectionId;
+    use network::rpc::SubstreamId;
+    use network::Request;
+    use storage::log_store::log_manager::LogConfig;
+    use storage::LogManager;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::{mpsc, RwLock};
+
+    #[tokio::test]
+    async fn test_provisional_download() {
+        let runtime = TestRuntime::default();
+
+        let num_chunks = 3 * 1024;
+        let (_, peer_store, txs, data) = create_2_store(vec![num_chunks]);
+        let peer_store = Store::new(peer_store, runtime.task_executor.clone());
+        // the transaction is not synced locally yet
+        let store = LogManager::memorydb(LogConfig::default()).unwrap();
+        let store = Store::new(Arc::new(RwLock::new(store)), runtime.task_executor.clone());
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let mut provisional = Provisional::new(ctx.clone(), store.clone());
+        let peer_provisional = Provisional::new(ctx, peer_store);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        let data_root = txs[0].data_merkle_root;
+        provisional
+            .on_file_announced(0, data_root, txs[0].size, peer_id, addr)
+            .await;
+        assert!(matches!(
+            network_recv.try_recv().unwrap(),
+            NetworkMessage::DialPeer { .. }
+        ));
+        provisional.on_peer_connected(peer_id);
+
+        let mut requests = 0;
+        while let Ok(msg) = network_recv.try_recv() {
+            let request = match msg {
+                NetworkMessage::SendRequest {
+                    request: Request::GetPendingChunks(request),
+                    ..
+                } => request,
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            requests += 1;
+
+            // served from the finalized file of the peer
+            peer_provisional
+                .on_request(peer_id, (ConnectionId::new(0), SubstreamId(0)), request)
+                .await;
+            let response = match network_recv.try_recv().unwrap() {
+                NetworkMessage::SendResponse {
+                    response: network::Response::PendingChunks(response),
+                    ..
+                } => response,
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            provisional.on_response(peer_id, 0, response).await;
+        }
+
+        assert_eq!(requests, 2);
+        assert!(provisional.downloads.is_empty());
+        assert!(
+            store
+                .get_pending_tx(&data_root)
+                .await
+                .unwrap()
+                .unwrap()
+                .verified
+        );
+
+        // the staged file is served to other peers
+        let request = GetPendingChunksRequest {
+            data_root,
+            index_start: 1,
+            index_end: 3,
+        };
+        provisional
+            .on_request(peer_id, (ConnectionId::new(0), SubstreamId(0)), request)
+            .await;
+        match network_recv.try_recv().unwrap() {
+            NetworkMessage::SendResponse {
+                response: network::Response::PendingChunks(response),
+                ..
+            } => assert_eq!(response.chunks.data, data[0][CHUNK_SIZE..3 * CHUNK_SIZE]),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+    }
+}
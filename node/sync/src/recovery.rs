@@ -0,0 +1,439 @@
+use crate::context::SyncNetworkContext;
+use crate::metrics;
+use network::{
+    rpc::{GetErasureShardRequest, RPCResponseErrorCode},
+    NetworkMessage, PeerAction, PeerId, PeerRequestId, SyncId as RequestId,
+};
+use rand::seq::IteratorRandom;
+use shared_types::{bytes_to_chunks, ErasureShard};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use storage::error::Result;
+use storage::log_store::log_manager::PORA_CHUNK_SIZE;
+use storage_async::Store;
+use tokio::time::Instant;
+
+/// Timeout to collect enough shards of a chunk from peers.
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of chunks in recovery at the same time.
+const MAX_PENDING_CHUNKS: usize = 64;
+
+struct PendingChunk {
+    tx_seq: u64,
+    /// Number of shards required to reconstruct the chunk.
+    data_shards: usize,
+    /// Shards received so far, at most one for each shard index.
+    shards: Vec<ErasureShard>,
+    /// Number of shard requests not responded yet.
+    in_flight: usize,
+    since: Instant,
+}
+
+/// Recovers the missing PoRA chunks of files that failed to sync, by collecting enough erasure
+/// shards of the chunks from connected peers, even if none of the peers stores the whole file.
+/// Once all chunks of a file in recovery are done, the file is synced again to finalize.
+pub(crate) struct Recovery {
+    /// Peers connected to the sync service, which could be requested shards without dialing.
+    connected_peers: HashSet<PeerId>,
+
+    /// Chunks in recovery by chunk index in the log flow.
+    pending: HashMap<u64, PendingChunk>,
+
+    /// Files that were tried to recover, which are not recovered again until synced.
+    attempted: HashSet<u64>,
+
+    ctx: Arc<SyncNetworkContext>,
+    store: Store,
+}
+
+impl Recovery {
+    pub fn new(ctx: Arc<SyncNetworkContext>, store: Store) -> Self {
+        Recovery {
+            connected_peers: Default::default(),
+            pending: Default::default(),
+            attempted: Default::default(),
+            ctx,
+            store,
+        }
+    }
+
+    pub fn on_peer_connected(&mut self, peer_id: PeerId) {
+        self.connected_peers.insert(peer_id);
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer_id: PeerId) {
+        self.connected_peers.remove(&peer_id);
+    }
+
+    /// Starts to recover the missing chunks of a file that failed to sync from the announced
+    /// peers, if erasure coding is enabled.
+    pub async fn on_file_failed(&mut self, tx_seq: u64) {
+        if self.connected_peers.is_empty() || self.attempted.contains(&tx_seq) {
+            return;
+        }
+
+        self.attempted.insert(tx_seq);
+
+        if let Err(err) = self.recover_file(tx_seq).await {
+            warn!(%tx_seq, %err, "Failed to start chunk recovery");
+        }
+    }
+
+    /// Allows to recover the file again if it fails to sync later.
+    pub fn on_file_synced(&mut self, tx_seq: u64) {
+        self.attempted.remove(&tx_seq);
+    }
+
+    async fn recover_file(&mut self, tx_seq: u64) -> Result<()> {
+        let erasure = match self.store.get_erasure_config().await? {
+            Some(erasure) => erasure,
+            None => return Ok(()),
+        };
+
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => tx,
+            None => return Ok(()),
+        };
+
+        let num_chunks = bytes_to_chunks(tx.size as usize) as u64;
+        let stored = self
+            .store
+            .get_chunk_ranges(tx_seq)
+            .await?
+            .unwrap_or_default();
+
+        for chunk_index in missing_pora_chunks(tx.start_entry_index, num_chunks, &stored) {
+            if self.pending.len() >= MAX_PENDING_CHUNKS {
+                debug!(%tx_seq, "Too many chunks in recovery");
+                break;
+            }
+
+            if self.pending.contains_key(&chunk_index) {
+                continue;
+            }
+
+            info!(%tx_seq, %chunk_index, "Recover chunk from erasure shards");
+
+            let in_flight = self.request_shards(chunk_index, erasure.num_shards());
+            self.pending.insert(
+                chunk_index,
+                PendingChunk {
+                    tx_seq,
+                    data_shards: erasure.data_shards,
+                    shards: vec![],
+                    in_flight,
+                    since: Instant::now(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Requests all shards of the chunk from connected peers in a round-robin manner, and returns
+    /// the number of requests sent.
+    fn request_shards(&self, chunk_index: u64, num_shards: usize) -> usize {
+        let peers = self
+            .connected_peers
+            .iter()
+            .choose_multiple(&mut crate::rng(), num_shards);
+
+        for shard_index in 0..num_shards {
+            self.ctx.send(NetworkMessage::SendRequest {
+                peer_id: *peers[shard_index % peers.len()],
+                request_id: network::RequestId::Sync(RequestId::Recovery { chunk_index }),
+                request: network::Request::GetErasureShard(GetErasureShardRequest {
+                    chunk_index,
+                    shard_index: shard_index as u32,
+                }),
+            });
+        }
+
+        num_shards
+    }
+
+    /// Returns the file to sync again once all its chunks in recovery are done.
+    pub async fn on_response(
+        &mut self,
+        peer_id: PeerId,
+        chunk_index: u64,
+        shard: ErasureShard,
+    ) -> Option<u64> {
+        let pending = match self.pending.get_mut(&chunk_index) {
+            Some(pending) => pending,
+            None => {
+                debug!(%peer_id, %chunk_index, "Received erasure shard for unknown chunk");
+                return None;
+            }
+        };
+
+        pending.in_flight = pending.in_flight.saturating_sub(1);
+
+        if shard.chunk_index != chunk_index {
+            self.ctx.report_peer(
+                peer_id,
+                PeerAction::LowToleranceError,
+                "Erasure shard of unexpected chunk",
+            );
+        } else if !pending
+            .shards
+            .iter()
+            .any(|received| received.shard_index == shard.shard_index)
+        {
+            pending.shards.push(shard);
+        }
+
+        self.try_recover(chunk_index).await
+    }
+
+    /// Returns the file to sync again once all its chunks in recovery are done.
+    pub async fn on_request_failed(&mut self, peer_id: PeerId, chunk_index: u64) -> Option<u64> {
+        let pending = self.pending.get_mut(&chunk_index)?;
+
+        debug!(%peer_id, %chunk_index, "Failed to get erasure shard from peer");
+        pending.in_flight = pending.in_flight.saturating_sub(1);
+
+        self.try_recover(chunk_index).await
+    }
+
+    /// Recovers the chunk if enough shards received, or gives up if not enough shards could be
+    /// received any more.
+    async fn try_recover(&mut self, chunk_index: u64) -> Option<u64> {
+        let pending = self.pending.get(&chunk_index)?;
+
+        if pending.shards.len() < pending.data_shards {
+            if pending.shards.len() + pending.in_flight >= pending.data_shards {
+                return None;
+            }
+
+            warn!(%chunk_index, received = %pending.shards.len(), "Not enough erasure shards to recover chunk");
+            metrics::inc_counter(&metrics::SYNC_RECOVERY_FAILED_CHUNKS);
+            return self.remove_pending(chunk_index);
+        }
+
+        let tx_seq = pending.tx_seq;
+        let shards = std::mem::take(&mut self.pending.get_mut(&chunk_index)?.shards);
+
+        match self.store.recover_chunk(shards).await {
+            Ok(()) => {
+                info!(%tx_seq, %chunk_index, "Recovered chunk from erasure shards");
+                metrics::inc_counter(&metrics::SYNC_RECOVERED_CHUNKS);
+            }
+            Err(err) => {
+                warn!(%tx_seq, %chunk_index, %err, "Failed to recover chunk from erasure shards");
+                metrics::inc_counter(&metrics::SYNC_RECOVERY_FAILED_CHUNKS);
+            }
+        }
+
+        self.remove_pending(chunk_index)
+    }
+
+    /// Removes the chunk in recovery, and returns its file if no other chunk of the file is in
+    /// recovery.
+    fn remove_pending(&mut self, chunk_index: u64) -> Option<u64> {
+        let tx_seq = self.pending.remove(&chunk_index)?.tx_seq;
+
+        if self
+            .pending
+            .values()
+            .any(|pending| pending.tx_seq == tx_seq)
+        {
+            None
+        } else {
+            Some(tx_seq)
+        }
+    }
+
+    /// Gives up the chunks that timed out, and returns the files to sync again.
+    pub fn on_heartbeat(&mut self) -> Vec<u64> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.since.elapsed() >= RECOVERY_TIMEOUT)
+            .map(|(chunk_index, _)| *chunk_index)
+            .collect();
+
+        let mut files = vec![];
+        for chunk_index in expired {
+            warn!(%chunk_index, "Chunk recovery timeout");
+            metrics::inc_counter(&metrics::SYNC_RECOVERY_FAILED_CHUNKS);
+
+            if let Some(tx_seq) = self.remove_pending(chunk_index) {
+                files.push(tx_seq);
+            }
+        }
+
+        files
+    }
+
+    /// Serves the erasure shard stored locally to the peer.
+    pub async fn on_request(
+        &self,
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetErasureShardRequest,
+    ) {
+        debug!(?request, %peer_id, ?request_id, "Received GetErasureShard request");
+
+        let (error, reason) = match self
+            .store
+            .get_erasure_shard(request.chunk_index, request.shard_index as usize)
+            .await
+        {
+            Ok(Some(shard)) => {
+                self.ctx.send(NetworkMessage::SendResponse {
+                    peer_id,
+                    id: request_id,
+                    response: network::Response::ErasureShard(shard),
+                });
+                return;
+            }
+            Ok(None) => (RPCResponseErrorCode::ResourceUnavailable, "Shard not found"),
+            Err(err) => {
+                debug!(?request, %err, "Failed to get erasure shard");
+                (RPCResponseErrorCode::InvalidRequest, "Shard unavailable")
+            }
+        };
+
+        self.ctx.send(NetworkMessage::SendErrorResponse {
+            peer_id,
+            error,
+            reason: reason.into(),
+            id: request_id,
+        });
+    }
+}
+
+/// Returns the indices of the PoRA chunks that overlap the chunks of a file not `stored`, where
+/// the file starts at `start_entry_index` in the log flow.
+fn missing_pora_chunks(
+    start_entry_index: u64,
+    num_chunks: u64,
+    stored: &[(usize, usize)],
+) -> Vec<u64> {
+    let mut missing = vec![];
+    let mut next = 0;
+    for &(start, end) in stored
+        .iter()
+        .chain(&[(num_chunks as usize, num_chunks as usize)])
+    {
+        let (start, end) = (start as u64, end as u64);
+        if next < start {
+            let first = (start_entry_index + next) / PORA_CHUNK_SIZE as u64;
+            let last = (start_entry_index + start - 1) / PORA_CHUNK_SIZE as u64;
+            for chunk_index in first..=last {
+                if missing.last() != Some(&chunk_index) {
+                    missing.push(chunk_index);
+                }
+            }
+        }
+        next = next.max(end);
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::create_2_store_with_config;
+    use libp2p::identity;
+    use network::discovery::ConnectionId;
+    use network::rpc::SubstreamId;
+    use network::Request;
+    use storage::log_store::log_manager::LogConfig;
+    use storage::log_store::ErasureConfig;
+    use task_executor::test_utils::TestRuntime;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_missing_pora_chunks() {
+        let size = PORA_CHUNK_SIZE as u64;
+        assert_eq!(missing_pora_chunks(size, 2 * size, &[]), vec![1, 2]);
+        assert_eq!(
+            missing_pora_chunks(size, 2 * size, &[(0, 2 * size as usize)]),
+            Vec::<u64>::new()
+        );
+        assert_eq!(
+            missing_pora_chunks(size, 3 * size, &[(0, 10), (20, size as usize + 1)]),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            missing_pora_chunks(size, 3 * size, &[(0, size as usize)]),
+            vec![2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recover_file() {
+        let runtime = TestRuntime::default();
+
+        let mut config = LogConfig::default();
+        config.flow.erasure = Some(ErasureConfig::new(4, 2).unwrap());
+        let (store, peer_store, _, _) =
+            create_2_store_with_config(config, vec![2 * PORA_CHUNK_SIZE]);
+        let store = Store::new(store, runtime.task_executor.clone());
+        let peer_store = Store::new(peer_store, runtime.task_executor.clone());
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let mut recovery = Recovery::new(ctx.clone(), store.clone());
+        let peer_recovery = Recovery::new(ctx, peer_store);
+
+        let peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        recovery.on_peer_connected(peer_id);
+        recovery.on_file_failed(0).await;
+
+        let requests: Vec<GetErasureShardRequest> =
+            std::iter::from_fn(|| network_recv.try_recv().ok())
+                .map(|msg| match msg {
+                    NetworkMessage::SendRequest {
+                        request: Request::GetErasureShard(request),
+                        ..
+                    } => request,
+                    msg => panic!("Unexpected message {:?}", msg),
+                })
+                .collect();
+        // all shards of the two chunks of the file
+        assert_eq!(requests.len(), 12);
+
+        let mut synced = vec![];
+        for request in requests {
+            let chunk_index = request.chunk_index;
+
+            // lose the first data shard and the last parity shard
+            if request.shard_index == 0 || request.shard_index == 5 {
+                if let Some(tx_seq) = recovery.on_request_failed(peer_id, chunk_index).await {
+                    synced.push(tx_seq);
+                }
+                continue;
+            }
+
+            peer_recovery
+                .on_request(peer_id, (ConnectionId::new(0), SubstreamId(0)), request)
+                .await;
+            let shard = match network_recv.try_recv().unwrap() {
+                NetworkMessage::SendResponse {
+                    response: network::Response::ErasureShard(shard),
+                    ..
+                } => shard,
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            if let Some(tx_seq) = recovery.on_response(peer_id, chunk_index, shard).await {
+                synced.push(tx_seq);
+            }
+        }
+
+        assert_eq!(synced, vec![0]);
+        assert!(recovery.pending.is_empty());
+        assert_eq!(
+            store.get_chunk_ranges(0).await.unwrap(),
+            Some(vec![(0, 2 * PORA_CHUNK_SIZE)])
+        );
+
+        // not recovered again until synced
+        recovery.on_file_failed(0).await;
+        assert!(network_recv.try_recv().is_err());
+    }
+}
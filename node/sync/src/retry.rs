@@ -0,0 +1,198 @@
+//! Retries of the failed requests to peers, with exponential backoff and jitter so that the
+//! retries of many files or peers do not burst at the same time.
+
+use network::rpc::{RPCError, RPCResponseErrorCode};
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Policy to retry a kind of requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of continuous failed attempts before giving up.
+    pub max_attempts: u32,
+    /// Backoff after the first failure, which doubles on each continuous failure.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff.
+    pub max_backoff: Duration,
+    /// Fraction of the backoff that is randomized, between 0 and 1.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Returns whether to retry after the continuous `failures`.
+    pub fn should_retry(&self, failures: u32) -> bool {
+        failures < self.max_attempts
+    }
+
+    /// Returns the randomized backoff after the continuous `failures`.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        backoff.mul_f64(1.0 - jitter * crate::rng().gen::<f64>())
+    }
+}
+
+/// Policies to retry the failed requests of the sync service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryConfig {
+    /// Chunks requests to a peer, which is banned from the file sync after the attempts.
+    pub chunks: RetryPolicy,
+    /// `FindFile` queries to look up the providers of a file, which fails the file sync after
+    /// the attempts until the file is announced again.
+    pub find_peers: RetryPolicy,
+    /// File offers to a peer, which are best effort.
+    pub offer_file: RetryPolicy,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            chunks: RetryPolicy {
+                max_attempts: 4,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+            find_peers: RetryPolicy {
+                max_attempts: 10,
+                initial_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(60),
+                jitter: 0.5,
+            },
+            offer_file: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.5,
+            },
+        }
+    }
+}
+
+/// Failures of requests to peers, which are classified to decide whether to retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestFailure {
+    /// No response in time.
+    Timeout,
+    /// The request failed in the RPC layer, e.g. the peer disconnected.
+    Rpc,
+    /// The peer is busy to serve, e.g. due to its bandwidth quota, which is not a fault of the
+    /// peer. Busy peers are always retried after the backoff.
+    Busy,
+    /// The peer does not support the request or responded malformed data, which is not retried.
+    Invalid,
+    /// No peer responded, e.g. to a provider lookup.
+    NotFound,
+}
+
+impl From<&RPCError> for RequestFailure {
+    fn from(error: &RPCError) -> Self {
+        match error {
+            RPCError::ErrorResponse(RPCResponseErrorCode::Busy, _) => RequestFailure::Busy,
+            RPCError::StreamTimeout | RPCError::NegotiationTimeout => RequestFailure::Timeout,
+            RPCError::SSZDecodeError(_)
+            | RPCError::InvalidData(_)
+            | RPCError::UnsupportedProtocol => RequestFailure::Invalid,
+            _ => RequestFailure::Rpc,
+        }
+    }
+}
+
+/// Retry state of a kind of requests, e.g. to a peer.
+#[derive(Debug, Default)]
+pub struct Retry {
+    /// Continuous failures counted towards the maximum attempts.
+    failures: u32,
+    /// Continuous failures of any kind, which determine the backoff.
+    backoffs: u32,
+    /// Requests should not be retried until then.
+    retry_at: Option<Instant>,
+}
+
+impl Retry {
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Returns whether the backoff after the last failure has elapsed.
+    pub fn is_ready(&self) -> bool {
+        self.retry_at.map_or(true, |at| at <= Instant::now())
+    }
+
+    /// Records the failure, and returns whether to retry after the backoff, or `false` to give up.
+    pub fn on_failure(&mut self, policy: &RetryPolicy, failure: RequestFailure) -> bool {
+        match failure {
+            RequestFailure::Invalid => return false,
+            RequestFailure::Busy => {}
+            _ => {
+                self.failures += 1;
+                if !policy.should_retry(self.failures) {
+                    return false;
+                }
+            }
+        }
+
+        self.backoffs += 1;
+        self.retry_at = Some(Instant::now() + policy.backoff(self.backoffs));
+        true
+    }
+
+    pub fn on_success(&mut self) {
+        *self = Default::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(3),
+        jitter: 0.5,
+    };
+
+    #[test]
+    fn test_backoff() {
+        for (failures, max) in [(1, 1), (2, 2), (3, 3), (10, 3), (100, 3)] {
+            let backoff = POLICY.backoff(failures);
+            let max = Duration::from_secs(max);
+            assert!(backoff <= max && backoff >= max / 2, "{:?}", backoff);
+        }
+
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..POLICY
+        };
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let mut retry = Retry::default();
+        assert!(retry.is_ready());
+
+        assert!(retry.on_failure(&POLICY, RequestFailure::Timeout));
+        assert!(!retry.is_ready());
+
+        // busy peers are retried regardless of the attempts
+        for _ in 0..POLICY.max_attempts {
+            assert!(retry.on_failure(&POLICY, RequestFailure::Busy));
+        }
+        assert_eq!(retry.failures(), 1);
+
+        assert!(retry.on_failure(&POLICY, RequestFailure::Rpc));
+        assert!(!retry.on_failure(&POLICY, RequestFailure::Rpc));
+
+        retry.on_success();
+        assert!(retry.is_ready());
+        assert!(!retry.on_failure(&POLICY, RequestFailure::Invalid));
+    }
+}
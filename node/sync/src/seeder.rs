@@ -0,0 +1,250 @@
+use crate::context::SyncNetworkContext;
+use crate::metrics;
+use crate::retry::{RequestFailure, RetryPolicy};
+use file_location_cache::FileLocationCache;
+use network::{
+    rpc::{OfferFileRequest, RPCError},
+    NetworkMessage, PeerId, SyncId as RequestId,
+};
+use rand::seq::IteratorRandom;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::time::Instant;
+
+/// A failed file offer, which is sent again after the backoff.
+struct PendingOffer {
+    peer_id: PeerId,
+    tx_seq: u64,
+    /// Number of times the file has been offered to the peer.
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// Offers newly finalized files to randomly selected peers, which then sync the files from this
+/// node. This accelerates the replication of fresh uploads, rather than waiting for peers to
+/// receive the `AnnounceFile` gossip.
+pub(crate) struct Seeder {
+    /// Number of peers to offer each file, zero to disable seeding.
+    num_peers: usize,
+
+    /// Peers connected to the sync service, which could be offered files without dialing.
+    connected_peers: HashSet<PeerId>,
+
+    /// Policy to offer files again upon failures.
+    retry: RetryPolicy,
+
+    /// Failed offers awaiting the backoff.
+    pending: Vec<PendingOffer>,
+
+    ctx: Arc<SyncNetworkContext>,
+    file_location_cache: Arc<FileLocationCache>,
+}
+
+impl Seeder {
+    pub fn new(
+        num_peers: usize,
+        retry: RetryPolicy,
+        ctx: Arc<SyncNetworkContext>,
+        file_location_cache: Arc<FileLocationCache>,
+    ) -> Self {
+        Seeder {
+            num_peers,
+            connected_peers: Default::default(),
+            retry,
+            pending: Default::default(),
+            ctx,
+            file_location_cache,
+        }
+    }
+
+    pub fn on_peer_connected(&mut self, peer_id: PeerId) {
+        self.connected_peers.insert(peer_id);
+    }
+
+    pub fn on_peer_disconnected(&mut self, peer_id: PeerId) {
+        self.connected_peers.remove(&peer_id);
+        self.pending.retain(|offer| offer.peer_id != peer_id);
+    }
+
+    /// Offers the finalized file to random connected peers, except the ones that announced to
+    /// have the file already.
+    pub fn on_file_finalized(&self, tx_seq: u64) {
+        if self.num_peers == 0 {
+            return;
+        }
+
+        let announcers: HashSet<PeerId> = self
+            .file_location_cache
+            .get_all(tx_seq)
+            .into_iter()
+            .map(|announcement| announcement.peer_id.clone().into())
+            .collect();
+
+        let peers = self
+            .connected_peers
+            .iter()
+            .filter(|peer_id| !announcers.contains(peer_id))
+            .choose_multiple(&mut crate::rng(), self.num_peers);
+
+        for peer_id in peers {
+            self.offer(*peer_id, tx_seq, 1);
+        }
+    }
+
+    /// Schedules to offer the file again after the backoff, unless the offer is not retryable or
+    /// has been attempted too many times.
+    pub fn on_offer_failed(
+        &mut self,
+        peer_id: PeerId,
+        tx_seq: u64,
+        attempts: u32,
+        error: &RPCError,
+    ) {
+        let failure = RequestFailure::from(error);
+        if failure == RequestFailure::Invalid || !self.retry.should_retry(attempts) {
+            debug!(%peer_id, %tx_seq, %attempts, ?failure, "Give up offering file to peer");
+            return;
+        }
+
+        if !self.connected_peers.contains(&peer_id) {
+            return;
+        }
+
+        self.pending.push(PendingOffer {
+            peer_id,
+            tx_seq,
+            attempts,
+            retry_at: Instant::now() + self.retry.backoff(attempts),
+        });
+    }
+
+    /// Offers the files again whose backoff elapsed.
+    pub fn on_heartbeat(&mut self) {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|offer| offer.retry_at <= now);
+        self.pending = pending;
+
+        for offer in due {
+            self.offer(offer.peer_id, offer.tx_seq, offer.attempts + 1);
+        }
+    }
+
+    fn offer(&self, peer_id: PeerId, tx_seq: u64, attempts: u32) {
+        debug!(%peer_id, %tx_seq, %attempts, "Offer file to peer");
+
+        self.ctx.send(NetworkMessage::SendRequest {
+            peer_id,
+            request_id: network::RequestId::Sync(RequestId::Seed { tx_seq, attempts }),
+            request: network::Request::OfferFile(OfferFileRequest { tx_seq }),
+        });
+
+        metrics::inc_counter(&metrics::SYNC_FILE_OFFERS_SENT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::RetryConfig;
+    use crate::test_util::tests::create_file_location_cache;
+    use libp2p::identity;
+    use network::Request;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn random_peer() -> PeerId {
+        identity::Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn test_offer_file() {
+        // the announcer has the file already
+        let announcer = random_peer();
+        let file_location_cache = create_file_location_cache(announcer, 1);
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let mut seeder = Seeder::new(
+            2,
+            RetryConfig::default().offer_file,
+            Arc::new(SyncNetworkContext::new(network_send)),
+            file_location_cache,
+        );
+
+        seeder.on_peer_connected(announcer);
+        let peers: HashSet<PeerId> = (0..3).map(|_| random_peer()).collect();
+        for peer_id in peers.iter() {
+            seeder.on_peer_connected(*peer_id);
+        }
+
+        seeder.on_file_finalized(0);
+
+        for _ in 0..2 {
+            match network_recv.try_recv().unwrap() {
+                NetworkMessage::SendRequest {
+                    peer_id,
+                    request: Request::OfferFile(request),
+                    ..
+                } => {
+                    assert!(peers.contains(&peer_id));
+                    assert_eq!(request.tx_seq, 0);
+                }
+                msg => panic!("Unexpected message {:?}", msg),
+            }
+        }
+        assert!(network_recv.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_offer_file_retry() {
+        let peer_id = random_peer();
+        let file_location_cache = create_file_location_cache(random_peer(), 1);
+        let retry = RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            jitter: 0.0,
+        };
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let mut seeder = Seeder::new(
+            1,
+            retry,
+            Arc::new(SyncNetworkContext::new(network_send)),
+            file_location_cache,
+        );
+        seeder.on_peer_connected(peer_id);
+
+        seeder.on_file_finalized(0);
+        assert!(matches!(
+            network_recv.try_recv().unwrap(),
+            NetworkMessage::SendRequest {
+                request_id: network::RequestId::Sync(RequestId::Seed {
+                    tx_seq: 0,
+                    attempts: 1
+                }),
+                ..
+            }
+        ));
+
+        // offered again after the backoff
+        seeder.on_offer_failed(peer_id, 0, 1, &RPCError::StreamTimeout);
+        seeder.on_heartbeat();
+        assert!(matches!(
+            network_recv.try_recv().unwrap(),
+            NetworkMessage::SendRequest {
+                request_id: network::RequestId::Sync(RequestId::Seed {
+                    tx_seq: 0,
+                    attempts: 2
+                }),
+                ..
+            }
+        ));
+
+        // give up after the max attempts
+        seeder.on_offer_failed(peer_id, 0, 2, &RPCError::StreamTimeout);
+        seeder.on_heartbeat();
+        assert!(network_recv.try_recv().is_err());
+    }
+}
@@ -1,23 +1,49 @@
+use crate::auditor::Auditor;
 use crate::context::SyncNetworkContext;
-use crate::controllers::{SerialSyncController, SyncState};
+use crate::controllers::{
+    ChunkVerifyQueue, ChunkWriteQueue, PeerReputation, PeerRequestLimiter, SerialSyncController,
+    SyncState, VerifyResult, WriteResult,
+};
+use crate::metrics;
+use crate::priority::{sort_by_priority, PendingFile};
+use crate::proof_cache::ProofCache;
+use crate::provisional::Provisional;
+use crate::recovery::Recovery;
+use crate::retry::RequestFailure;
+use crate::seeder::Seeder;
+use crate::Config;
 use anyhow::{bail, Result};
 use file_location_cache::FileLocationCache;
 use network::{
-    rpc::GetChunksRequest, rpc::RPCResponseErrorCode, Multiaddr, NetworkMessage, PeerAction,
-    PeerId, PeerRequestId, SyncId as RequestId,
+    rpc::GetChunksRequest, rpc::GetErasureShardRequest, rpc::GetPendingChunksRequest,
+    rpc::RPCError, rpc::RPCResponseErrorCode, types::FindFile, Multiaddr, NetworkMessage,
+    PeerAction, PeerId, PeerRequestId, PubsubMessage, SyncId as RequestId,
+};
+use shared_types::{
+    bytes_to_chunks, timestamp_now, ChunkArrayWithProof, DataRoot, ErasureShard, ProvisionalChunks,
 };
-use shared_types::{bytes_to_chunks, ChunkArrayWithProof};
 use std::{
     collections::{hash_map::Entry, HashMap},
     sync::Arc,
+    time::Duration,
 };
 use storage::error::Result as StorageResult;
 use storage::log_store::Store as LogStore;
-use storage_async::Store;
-use tokio::sync::{mpsc, RwLock};
+use storage_async::{Store, StoreEvent};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::time::Instant;
 
 const HEARTBEAT_INTERVAL_SEC: u64 = 5;
 
+/// Maximum number of times to re-sync a file that failed to finalize.
+const MAX_REPAIR_ATTEMPTS: usize = 3;
+
+/// Maximum number of transactions announced by peers to wait for the log sync.
+const MAX_PENDING_TXS: usize = 1024;
+
+/// Timeout to wait for the log sync to catch up with a transaction announced by peers.
+const PENDING_TX_TIMEOUT: Duration = Duration::from_secs(600);
+
 pub type SyncSender = channel::Sender<SyncMessage, SyncRequest, SyncResponse>;
 
 #[derive(Debug)]
@@ -41,14 +67,53 @@ pub enum SyncMessage {
         request_id: RequestId,
         response: ChunkArrayWithProof,
     },
+    RequestErasureShard {
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetErasureShardRequest,
+    },
+    ErasureShardResponse {
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: ErasureShard,
+    },
+    RequestPendingChunks {
+        peer_id: PeerId,
+        request_id: PeerRequestId,
+        request: GetPendingChunksRequest,
+    },
+    PendingChunksResponse {
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: ProvisionalChunks,
+    },
     RpcError {
         peer_id: PeerId,
         request_id: RequestId,
+        error: RPCError,
     },
     AnnounceFileGossip {
         tx_seq: u64,
         peer_id: PeerId,
         addr: Multiaddr,
+        /// Chunk ranges available on the peer, or `None` if the peer has the whole file.
+        ranges: Option<Vec<(u64, u64)>>,
+    },
+    OfferFile {
+        tx_seq: u64,
+        peer_id: PeerId,
+        addr: Multiaddr,
+    },
+    AnnounceStorageGossip {
+        data_roots: Vec<DataRoot>,
+        peer_id: PeerId,
+        addr: Multiaddr,
+    },
+    /// A new transaction announced by peers, which is not synced from the blockchain yet.
+    NewTxGossip {
+        tx_seq: u64,
+        data_root: DataRoot,
+        size: u64,
     },
 }
 
@@ -64,7 +129,16 @@ pub enum SyncResponse {
     SyncFile { err: String },
 }
 
+/// A transaction announced by peers but not synced from the blockchain yet.
+struct AnnouncedTx {
+    data_root: DataRoot,
+    size: u64,
+    since: Instant,
+}
+
 pub struct SyncService {
+    config: Config,
+
     /// A receiving channel sent by the message processor thread.
     msg_recv: channel::Receiver<SyncMessage, SyncRequest, SyncResponse>,
 
@@ -80,6 +154,52 @@ pub struct SyncService {
     /// A collection of file sync controllers.
     controllers: HashMap<u64, SerialSyncController>,
 
+    /// Files waiting to sync due to too many files in sync.
+    queued: Vec<PendingFile>,
+
+    /// Transactions announced by peers but not synced from the blockchain yet, whose files are
+    /// synced once the log sync catches up.
+    pending_txs: HashMap<u64, AnnouncedTx>,
+
+    /// Reputation of peers shared by all file sync controllers.
+    reputation: Arc<PeerReputation>,
+
+    /// Limits the chunks requests to each peer, shared by all file sync controllers.
+    request_limiter: Arc<PeerRequestLimiter>,
+
+    /// Queue to verify downloaded chunks, shared by all file sync controllers.
+    verifier: Arc<ChunkVerifyQueue>,
+
+    /// Results of verifying downloaded chunks.
+    verify_results: mpsc::UnboundedReceiver<VerifyResult>,
+
+    /// Queue to write downloaded chunks into store, shared by all file sync controllers.
+    writer: Arc<ChunkWriteQueue>,
+
+    /// Results of writing downloaded chunks into store.
+    write_results: mpsc::UnboundedReceiver<WriteResult>,
+
+    /// Events of store operations, e.g. files failed to finalize.
+    store_events: broadcast::Receiver<StoreEvent>,
+
+    /// Number of re-syncs of files that failed to finalize.
+    repairs: HashMap<u64, usize>,
+
+    /// Range proofs recently served to peers.
+    proof_cache: ProofCache,
+
+    /// Random chunk auditor of peers that announced files.
+    auditor: Auditor,
+
+    /// Offers newly finalized files to peers.
+    seeder: Seeder,
+
+    /// Recovers the missing chunks of failed files from erasure shards of peers.
+    recovery: Recovery,
+
+    /// Downloads the files of pending transactions before the log sync catches up.
+    provisional: Provisional,
+
     /// Heartbeat interval for executing periodic tasks.
     heartbeat: tokio::time::Interval,
 }
@@ -90,20 +210,79 @@ impl SyncService {
         network_send: mpsc::UnboundedSender<NetworkMessage>,
         store: Arc<RwLock<dyn LogStore>>,
         file_location_cache: Arc<FileLocationCache>,
+    ) -> SyncSender {
+        let store = Store::new(store, executor.clone());
+        Self::spawn_with_config(
+            Config::default(),
+            executor,
+            network_send,
+            store,
+            file_location_cache,
+        )
+    }
+
+    /// Spawns the sync service with an async store shared with other components, so that the
+    /// files failed to finalize by any component will be re-synced.
+    pub fn spawn_with_config(
+        config: Config,
+        executor: task_executor::TaskExecutor,
+        network_send: mpsc::UnboundedSender<NetworkMessage>,
+        store: Store,
+        file_location_cache: Arc<FileLocationCache>,
     ) -> SyncSender {
         let (sync_send, sync_recv) = channel::Channel::unbounded();
 
         let heartbeat =
             tokio::time::interval(tokio::time::Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
 
-        let store = Store::new(store, executor.clone());
+        let store_events = store.subscribe_events();
+        let (verifier, verify_results) =
+            ChunkVerifyQueue::new(config.verify_threads, store.clone())
+                .expect("Failed to create the chunk verification pool");
+        let (writer, write_results) = ChunkWriteQueue::spawn(&executor, store.clone());
+
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let reputation: Arc<PeerReputation> = Default::default();
+        let auditor = Auditor::new(
+            config.audit_interval,
+            ctx.clone(),
+            store.clone(),
+            file_location_cache.clone(),
+            reputation.clone(),
+        );
+        let seeder = Seeder::new(
+            config.seed_peers,
+            config.retry.offer_file,
+            ctx.clone(),
+            file_location_cache.clone(),
+        );
+        let recovery = Recovery::new(ctx.clone(), store.clone());
+        let provisional = Provisional::new(ctx.clone(), store.clone());
+
+        let request_limiter = Arc::new(PeerRequestLimiter::new(config.max_requests_per_peer));
 
         let mut sync = SyncService {
+            config,
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx,
             store,
             file_location_cache,
             controllers: Default::default(),
+            queued: Default::default(),
+            pending_txs: Default::default(),
+            reputation,
+            request_limiter,
+            verifier,
+            verify_results,
+            writer,
+            write_results,
+            store_events,
+            repairs: Default::default(),
+            proof_cache: Default::default(),
+            auditor,
+            seeder,
+            recovery,
+            provisional,
             heartbeat,
         };
 
@@ -114,6 +293,8 @@ impl SyncService {
     }
 
     async fn main(&mut self) {
+        self.resume_file_syncs().await;
+
         loop {
             tokio::select! {
                 // received sync message
@@ -124,8 +305,60 @@ impl SyncService {
                     }
                 }
 
+                // downloaded chunks verified
+                Some(result) = self.verify_results.recv() => self.on_chunks_verified(result),
+
+                // downloaded chunks written into store
+                Some(result) = self.write_results.recv() => self.on_chunks_written(result).await,
+
+                // store events
+                event = self.store_events.recv() => match event {
+                    Ok(event) => self.on_store_event(event).await,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(%n, "Store events lagged behind");
+                        // the log may be reverted
+                        self.proof_cache.clear();
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {}
+                },
+
                 // heartbeat
-                _ = self.heartbeat.tick() => self.on_heartbeat(),
+                _ = self.heartbeat.tick() => self.on_heartbeat().await,
+            }
+        }
+    }
+
+    /// Resumes the file syncs interrupted by the node restart, whose missing chunks are retrieved
+    /// from store, and the persisted peers are tried first.
+    async fn resume_file_syncs(&mut self) {
+        let progresses = match self.store.get_all_file_sync_progress().await {
+            Ok(progresses) => progresses,
+            Err(err) => {
+                error!(%err, "Failed to load file sync progress");
+                return;
+            }
+        };
+
+        for (tx_seq, progress) in progresses {
+            info!(%tx_seq, attempts = %progress.attempts, "Resume to sync file");
+
+            if let Err(err) = self.on_start_sync_file(tx_seq, None).await {
+                warn!(%tx_seq, %err, "Failed to resume file sync");
+            }
+
+            match self.controllers.get_mut(&tx_seq) {
+                Some(controller) => {
+                    controller.restore_progress(progress);
+                    controller.transition();
+                }
+                // progress is kept until the queued file starts to sync
+                None if self.queued.iter().any(|file| file.tx_seq == tx_seq) => {}
+                // e.g. file already finalized or transaction reverted
+                None => {
+                    if let Err(err) = self.store.delete_file_sync_progress(tx_seq).await {
+                        error!(%tx_seq, %err, "Failed to delete file sync progress");
+                    }
+                }
             }
         }
     }
@@ -162,19 +395,79 @@ impl SyncService {
                 self.on_chunks_response(peer_id, request_id, response).await;
             }
 
+            SyncMessage::RequestErasureShard {
+                peer_id,
+                request_id,
+                request,
+            } => {
+                self.recovery.on_request(peer_id, request_id, request).await;
+            }
+
+            SyncMessage::ErasureShardResponse {
+                peer_id,
+                request_id,
+                response,
+            } => {
+                self.on_erasure_shard_response(peer_id, request_id, response)
+                    .await;
+            }
+
+            SyncMessage::RequestPendingChunks {
+                peer_id,
+                request_id,
+                request,
+            } => {
+                self.provisional
+                    .on_request(peer_id, request_id, request)
+                    .await;
+            }
+
+            SyncMessage::PendingChunksResponse {
+                peer_id,
+                request_id,
+                response,
+            } => {
+                self.on_pending_chunks_response(peer_id, request_id, response)
+                    .await;
+            }
+
             SyncMessage::RpcError {
                 peer_id,
                 request_id,
+                error,
             } => {
-                self.on_rpc_error(peer_id, request_id);
+                self.on_rpc_error(peer_id, request_id, error).await;
             }
 
             SyncMessage::AnnounceFileGossip {
                 tx_seq,
                 peer_id,
                 addr,
+                ranges,
             } => {
-                self.on_announce_file_gossip(tx_seq, peer_id, addr).await;
+                self.on_announce_file_gossip(tx_seq, peer_id, addr, ranges)
+                    .await;
+            }
+            SyncMessage::OfferFile {
+                tx_seq,
+                peer_id,
+                addr,
+            } => {
+                self.on_offer_file(tx_seq, peer_id, addr).await;
+            }
+            SyncMessage::AnnounceStorageGossip {
+                data_roots,
+                peer_id,
+                addr,
+            } => {
+                self.on_announce_storage_gossip(data_roots, peer_id, addr);
+            }
+            SyncMessage::NewTxGossip {
+                tx_seq,
+                data_root,
+                size,
+            } => {
+                self.on_new_tx_gossip(tx_seq, data_root, size);
             }
         }
     }
@@ -188,6 +481,9 @@ impl SyncService {
             SyncRequest::SyncStatus { tx_seq } => {
                 let status = match self.controllers.get_mut(&tx_seq) {
                     Some(controller) => format!("{:?}", controller.get_status()),
+                    None if self.queued.iter().any(|file| file.tx_seq == tx_seq) => {
+                        "Queued".to_string()
+                    }
                     None => "unknown".to_string(),
                 };
 
@@ -208,6 +504,8 @@ impl SyncService {
     fn on_dail_failed(&mut self, peer_id: PeerId) {
         info!(%peer_id, "Dail to peer failed");
 
+        self.provisional.on_dail_failed(peer_id);
+
         for controller in self.controllers.values_mut() {
             controller.on_dail_failed(peer_id);
             controller.transition();
@@ -217,6 +515,11 @@ impl SyncService {
     fn on_peer_connected(&mut self, peer_id: PeerId) {
         info!(%peer_id, "Peer connected");
 
+        self.auditor.on_peer_connected(peer_id);
+        self.seeder.on_peer_connected(peer_id);
+        self.recovery.on_peer_connected(peer_id);
+        self.provisional.on_peer_connected(peer_id);
+
         for controller in self.controllers.values_mut() {
             controller.on_peer_connected(peer_id);
             controller.transition();
@@ -226,6 +529,11 @@ impl SyncService {
     fn on_peer_disconnected(&mut self, peer_id: PeerId) {
         info!(%peer_id, "Peer disconnected");
 
+        self.auditor.on_peer_disconnected(peer_id);
+        self.seeder.on_peer_disconnected(peer_id);
+        self.recovery.on_peer_disconnected(peer_id);
+        self.provisional.on_peer_disconnected(peer_id);
+
         for controller in self.controllers.values_mut() {
             controller.on_peer_disconnected(peer_id);
             controller.transition();
@@ -239,6 +547,7 @@ impl SyncService {
         request: GetChunksRequest,
     ) {
         info!(?request, %peer_id, ?request_id, "Received GetChunks request");
+        let _timer = metrics::start_timer(&metrics::SYNC_SERVE_CHUNKS_TIMES);
 
         if let Err(err) = self
             .handle_chunks_request_with_db_err(peer_id, request_id, request)
@@ -285,7 +594,7 @@ impl SyncService {
 
         // file may be removed, but remote peer still find one from the file location cache
         let finalized = self.store.check_tx_completed(request.tx_seq).await?;
-        if !finalized {
+        if !finalized && !self.is_range_available(&request).await? {
             info!(%request.tx_seq, "Failed to handle chunks request due to tx not finalized");
             self.ctx
                 .report_peer(peer_id, PeerAction::MidToleranceError, "Tx not finalized");
@@ -298,14 +607,17 @@ impl SyncService {
             return Ok(());
         }
 
-        let result = self
-            .store
-            .get_chunks_with_proof_by_tx_and_index_range(
+        let result = self.get_chunks_with_proof(&request).await?;
+
+        // peers download a file by consecutive ranges of the same size
+        if result.is_some() {
+            let len = request.index_end - request.index_start;
+            self.store.prefetch_chunks(
                 request.tx_seq,
-                request.index_start as usize,
                 request.index_end as usize,
-            )
-            .await?;
+                request.index_end.saturating_add(len) as usize,
+            );
+        }
 
         match result {
             Some(chunks) => {
@@ -330,6 +642,53 @@ impl SyncService {
         Ok(())
     }
 
+    /// Returns whether the requested chunks of an unfinalized file are available to serve, which
+    /// were announced to peers as partial file.
+    async fn is_range_available(&self, request: &GetChunksRequest) -> StorageResult<bool> {
+        let ranges = match self.store.get_available_ranges(request.tx_seq).await? {
+            Some(ranges) => ranges,
+            None => return Ok(false),
+        };
+
+        Ok(ranges.into_iter().any(|(start, end)| {
+            start as u64 <= request.index_start && request.index_end <= end as u64
+        }))
+    }
+
+    /// Returns the requested chunks along with the range proof, which is cached since popular
+    /// files are requested by many peers.
+    async fn get_chunks_with_proof(
+        &mut self,
+        request: &GetChunksRequest,
+    ) -> StorageResult<Option<ChunkArrayWithProof>> {
+        let (tx_seq, index_start, index_end) =
+            (request.tx_seq, request.index_start, request.index_end);
+
+        if let Some(proof) = self.proof_cache.get(tx_seq, index_start, index_end) {
+            let chunks = self
+                .store
+                .get_chunks_by_tx_and_index_range(tx_seq, index_start as usize, index_end as usize)
+                .await?;
+            return Ok(chunks.map(|chunks| ChunkArrayWithProof { chunks, proof }));
+        }
+
+        let result = self
+            .store
+            .get_chunks_with_proof_by_tx_and_index_range(
+                tx_seq,
+                index_start as usize,
+                index_end as usize,
+            )
+            .await?;
+
+        if let Some(chunks) = &result {
+            self.proof_cache
+                .insert(tx_seq, index_start, index_end, chunks.proof.clone());
+        }
+
+        Ok(result)
+    }
+
     async fn on_chunks_response(
         &mut self,
         peer_id: PeerId,
@@ -337,9 +696,30 @@ impl SyncService {
         response: ChunkArrayWithProof,
     ) {
         info!(%response.chunks, %peer_id, ?request_id, "Received chunks response");
+        metrics::inc_counter_by(
+            &metrics::SYNC_RECEIVED_BYTES,
+            response.chunks.data.len() as u64,
+        );
 
         let tx_seq = match request_id {
             RequestId::SerialSync { tx_seq } => tx_seq,
+            RequestId::Audit { tx_seq } => {
+                self.auditor.on_response(peer_id, tx_seq, response).await;
+                return;
+            }
+            // file offers have no response
+            RequestId::Seed { tx_seq, .. } => {
+                warn!(%peer_id, %tx_seq, "Received chunks response to file offer");
+                return;
+            }
+            RequestId::Recovery { chunk_index } => {
+                warn!(%peer_id, %chunk_index, "Received chunks response to erasure shard request");
+                return;
+            }
+            RequestId::Provisional { tx_seq } => {
+                warn!(%peer_id, %tx_seq, "Received chunks response to pending chunks request");
+                return;
+            }
         };
 
         match self.controllers.get_mut(&tx_seq) {
@@ -353,16 +733,139 @@ impl SyncService {
         }
     }
 
-    fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId) {
-        info!(%peer_id, ?request_id, "Received RPC error");
+    fn on_chunks_verified(&mut self, result: VerifyResult) {
+        let VerifyResult {
+            tx_seq,
+            peer_id,
+            from_chunk,
+            to_chunk,
+            chunks,
+            result,
+        } = result;
+
+        match self.controllers.get_mut(&tx_seq) {
+            Some(controller) => {
+                controller.on_chunks_verified(peer_id, from_chunk, to_chunk, chunks, result);
+                controller.transition();
+            }
+            None => {
+                warn!(%tx_seq, "Chunks verified for non-existent controller");
+            }
+        }
+    }
+
+    async fn on_chunks_written(&mut self, result: WriteResult) {
+        let WriteResult {
+            tx_seq,
+            from_chunk,
+            to_chunk,
+            result,
+        } = result;
+
+        match self.controllers.get_mut(&tx_seq) {
+            Some(controller) => {
+                controller
+                    .on_chunks_written(from_chunk, to_chunk, result)
+                    .await;
+                controller.transition();
+            }
+            None => {
+                warn!(%tx_seq, "Chunks written for non-existent controller");
+            }
+        }
+    }
+
+    async fn on_erasure_shard_response(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: ErasureShard,
+    ) {
+        debug!(%peer_id, ?request_id, shard_index = %response.shard_index, "Received erasure shard response");
+        metrics::inc_counter_by(&metrics::SYNC_RECEIVED_BYTES, response.data.len() as u64);
+
+        let chunk_index = match request_id {
+            RequestId::Recovery { chunk_index } => chunk_index,
+            _ => {
+                warn!(%peer_id, ?request_id, "Received erasure shard response to unexpected request");
+                return;
+            }
+        };
+
+        if let Some(tx_seq) = self
+            .recovery
+            .on_response(peer_id, chunk_index, response)
+            .await
+        {
+            self.on_chunks_recovered(tx_seq).await;
+        }
+    }
+
+    async fn on_pending_chunks_response(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        response: ProvisionalChunks,
+    ) {
+        debug!(%peer_id, ?request_id, %response.chunks, "Received pending chunks response");
+        metrics::inc_counter_by(
+            &metrics::SYNC_RECEIVED_BYTES,
+            response.chunks.data.len() as u64,
+        );
+
+        match request_id {
+            RequestId::Provisional { tx_seq } => {
+                self.provisional
+                    .on_response(peer_id, tx_seq, response)
+                    .await;
+            }
+            _ => {
+                warn!(%peer_id, ?request_id, "Received pending chunks response to unexpected request");
+            }
+        }
+    }
+
+    /// Re-syncs the file whose chunks in recovery are done, which is finalized if all chunks are
+    /// stored now, or otherwise downloads the remaining chunks from peers again.
+    async fn on_chunks_recovered(&mut self, tx_seq: u64) {
+        info!(%tx_seq, "Re-sync file after chunk recovery");
+
+        // the new controller only downloads the chunks not in store
+        self.controllers.remove(&tx_seq);
+        if let Err(err) = self.on_start_sync_file(tx_seq, None).await {
+            warn!(%tx_seq, %err, "Failed to re-sync file after chunk recovery");
+        }
+    }
+
+    async fn on_rpc_error(&mut self, peer_id: PeerId, request_id: RequestId, error: RPCError) {
+        info!(%peer_id, ?request_id, %error, "Received RPC error");
 
         let tx_seq = match request_id {
             RequestId::SerialSync { tx_seq } => tx_seq,
+            RequestId::Audit { tx_seq } => {
+                self.auditor.on_request_failed(peer_id, tx_seq);
+                return;
+            }
+            RequestId::Seed { tx_seq, attempts } => {
+                self.seeder
+                    .on_offer_failed(peer_id, tx_seq, attempts, &error);
+                return;
+            }
+            RequestId::Recovery { chunk_index } => {
+                if let Some(tx_seq) = self.recovery.on_request_failed(peer_id, chunk_index).await {
+                    self.on_chunks_recovered(tx_seq).await;
+                }
+                return;
+            }
+            RequestId::Provisional { tx_seq } => {
+                self.provisional.on_request_failed(peer_id, tx_seq);
+                return;
+            }
         };
 
         match self.controllers.get_mut(&tx_seq) {
             Some(controller) => {
-                controller.on_request_failed(peer_id);
+                controller.on_request_failed(peer_id, RequestFailure::from(&error));
                 controller.transition();
             }
             None => {
@@ -374,10 +877,12 @@ impl SyncService {
     async fn on_start_sync_file(
         &mut self,
         tx_seq: u64,
-        maybe_peer: Option<(PeerId, Multiaddr)>,
+        maybe_peer: Option<(PeerId, Multiaddr, Option<Vec<(u64, u64)>>)>,
     ) -> Result<()> {
         info!(%tx_seq, "Start to sync file");
 
+        let at_capacity = self.active_syncs() >= self.config.max_concurrent_syncs;
+
         let controller = match self.controllers.entry(tx_seq) {
             Entry::Occupied(entry) => entry.into_mut(),
             Entry::Vacant(entry) => {
@@ -392,79 +897,416 @@ impl SyncService {
                         error!(%tx_seq, "Unexpected transaction size: {}", tx.size);
                         bail!("Unexpected transaction size");
                     }
-                };
+                };
+
+                // file already exists
+                if self.store.check_tx_completed(tx_seq).await? {
+                    bail!("File already exists");
+                }
+
+                // the files in sync fail to write chunks likewise, and are retried later
+                if self.store.is_write_protected() {
+                    bail!("Storage full");
+                }
+
+                // the announced peer will be found again from the file location cache
+                if at_capacity {
+                    if !self.queued.iter().any(|file| file.tx_seq == tx_seq) {
+                        info!(%tx_seq, "Too many files in sync, queue the file");
+                        self.queued.push(PendingFile {
+                            tx_seq,
+                            data_root: tx.data_merkle_root,
+                            num_chunks: num_chunks as u64,
+                        });
+                    }
+                    return Ok(());
+                }
+
+                let mut controller = SerialSyncController::new(
+                    tx_seq,
+                    tx.data_merkle_root,
+                    num_chunks as u64,
+                    self.ctx.clone(),
+                    self.store.clone(),
+                    self.file_location_cache.clone(),
+                    self.reputation.clone(),
+                    self.verifier.clone(),
+                    self.writer.clone(),
+                    self.request_limiter.clone(),
+                    self.config.retry,
+                );
+
+                // only download the chunks not in store, e.g. partially synced before restart
+                if let Some(stored) = self.store.get_chunk_ranges(tx_seq).await? {
+                    if !controller.skip_stored_chunks(&stored) {
+                        info!(%tx_seq, "All chunks already stored, finalize file directly");
+                        self.store.finalize_tx(tx_seq).await?;
+                        return Ok(());
+                    }
+                }
+
+                entry.insert(controller)
+            }
+        };
+
+        // trigger retry after failure
+        if let SyncState::Failed { .. } = controller.get_status() {
+            controller.reset();
+        }
+
+        if let Some((peer_id, addr, ranges)) = maybe_peer {
+            controller.on_peer_found_with_ranges(peer_id, addr, ranges);
+        }
+
+        controller.transition();
+
+        Ok(())
+    }
+
+    async fn on_announce_file_gossip(
+        &mut self,
+        tx_seq: u64,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        ranges: Option<Vec<(u64, u64)>>,
+    ) {
+        info!(%tx_seq, %peer_id, %addr, ?ranges, "Received AnnounceFile gossip");
+        self.on_file_announced(tx_seq, peer_id, addr, ranges).await;
+    }
+
+    /// Syncs the file offered by a seeding peer, which is handled the same as `AnnounceFile`.
+    async fn on_offer_file(&mut self, tx_seq: u64, peer_id: PeerId, addr: Multiaddr) {
+        info!(%tx_seq, %peer_id, %addr, "Received file offer");
+        metrics::inc_counter(&metrics::SYNC_FILE_OFFERS_RECEIVED);
+        self.on_file_announced(tx_seq, peer_id, addr, None).await;
+    }
+
+    /// Syncs the announced file from the peer, which may only have some chunk `ranges` of the file.
+    async fn on_file_announced(
+        &mut self,
+        tx_seq: u64,
+        peer_id: PeerId,
+        addr: Multiaddr,
+        ranges: Option<Vec<(u64, u64)>>,
+    ) {
+        // File already in sync
+        if let Some(controller) = self.controllers.get_mut(&tx_seq) {
+            // e.g. gave up finding peers before
+            if let SyncState::Failed { .. } = controller.get_status() {
+                controller.reset();
+            }
+
+            controller.on_peer_found_with_ranges(peer_id, addr, ranges);
+            controller.transition();
+            return;
+        }
+
+        // The file is downloaded provisionally, and promoted or synced once the log sync catches
+        // up. The stream of the transaction is unknown yet, so only if all streams are subscribed.
+        if let Some(tx) = self.pending_txs.get(&tx_seq) {
+            debug!(%tx_seq, "Transaction not synced from the blockchain yet");
+            if ranges.is_none() && !self.config.stream_filter.is_enabled() {
+                self.provisional
+                    .on_file_announced(tx_seq, tx.data_root, tx.size, peer_id, addr)
+                    .await;
+            }
+            return;
+        }
+
+        // File already exists and ignore the AnnounceFile message
+        match self.store.check_tx_completed(tx_seq).await {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => {
+                error!(%tx_seq, %err, "Failed to check if file finalized");
+                return;
+            }
+        }
+
+        match self.is_subscribed(tx_seq).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(%tx_seq, "Ignore the announced file of unsubscribed streams");
+                return;
+            }
+            Err(err) => {
+                error!(%tx_seq, %err, "Failed to check if file subscribed");
+                return;
+            }
+        }
+
+        if let Err(err) = self
+            .on_start_sync_file(tx_seq, Some((peer_id, addr, ranges)))
+            .await
+        {
+            error!(%tx_seq, %err, "Failed to sync file");
+        }
+    }
+
+    /// Returns whether the file of `tx_seq` belongs to the subscribed streams. The transaction is
+    /// not subscribed if it is not synced from the blockchain yet.
+    async fn is_subscribed(&self, tx_seq: u64) -> Result<bool> {
+        if !self.config.stream_filter.is_enabled() {
+            return Ok(true);
+        }
+
+        Ok(match self.store.get_tx_by_seq_number(tx_seq).await? {
+            Some(tx) => self.config.stream_filter.matches(&tx),
+            None => false,
+        })
+    }
+
+    /// Adds the announced peer to the syncing files it stores. Note, unlike `AnnounceFile`, the
+    /// announced files are not synced automatically.
+    fn on_announce_storage_gossip(
+        &mut self,
+        data_roots: Vec<DataRoot>,
+        peer_id: PeerId,
+        addr: Multiaddr,
+    ) {
+        debug!(%peer_id, %addr, num_data_roots = %data_roots.len(), "Received AnnounceStorage gossip");
+
+        for controller in self.controllers.values_mut() {
+            if data_roots.contains(controller.data_root()) {
+                controller.on_peer_found(peer_id, addr.clone());
+                controller.transition();
+            }
+        }
+    }
+
+    /// Locates the providers of the new transaction in advance, which is not synced from the
+    /// blockchain yet, so that the file sync starts as soon as the log sync catches up.
+    fn on_new_tx_gossip(&mut self, tx_seq: u64, data_root: DataRoot, size: u64) {
+        if self.pending_txs.contains_key(&tx_seq) || self.pending_txs.len() >= MAX_PENDING_TXS {
+            return;
+        }
+
+        debug!(%tx_seq, ?data_root, "Received NewTx gossip ahead of log sync");
+        self.pending_txs.insert(
+            tx_seq,
+            AnnouncedTx {
+                data_root,
+                size,
+                since: Instant::now(),
+            },
+        );
+
+        // the announced providers are cached in the file location cache
+        self.ctx.publish(PubsubMessage::FindFile(FindFile {
+            tx_seq,
+            timestamp: timestamp_now(),
+        }));
+    }
+
+    /// Starts to sync the files of the pending transactions that have been synced from the
+    /// blockchain, and drops the ones that timed out.
+    async fn sync_pending_txs(&mut self) {
+        if self.pending_txs.is_empty() {
+            return;
+        }
+
+        let next_tx_seq = match self.store.next_tx_seq().await {
+            Ok(next_tx_seq) => next_tx_seq,
+            Err(err) => {
+                error!(%err, "Failed to get next tx seq");
+                return;
+            }
+        };
+
+        let mut synced = vec![];
+        self.pending_txs.retain(|&tx_seq, tx| {
+            if tx_seq < next_tx_seq {
+                synced.push((tx_seq, tx.data_root));
+                false
+            } else {
+                tx.since.elapsed() < PENDING_TX_TIMEOUT
+            }
+        });
+
+        for (tx_seq, data_root) in synced {
+            match self.store.get_tx_by_seq_number(tx_seq).await {
+                Ok(Some(tx)) if tx.data_merkle_root == data_root => {
+                    if !self.config.stream_filter.matches(&tx) {
+                        continue;
+                    }
+                }
+                Ok(Some(tx)) => {
+                    warn!(%tx_seq, ?data_root, local_data_root = ?tx.data_merkle_root, "NewTx gossip mismatches the synced log");
+                    continue;
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    error!(%tx_seq, %err, "Failed to get transaction");
+                    continue;
+                }
+            }
 
-                // file already exists
-                if self.store.check_tx_completed(tx_seq).await? {
-                    bail!("File already exists");
+            match self.store.check_tx_completed(tx_seq).await {
+                Ok(false) => {}
+                Ok(true) => continue,
+                Err(err) => {
+                    error!(%tx_seq, %err, "Failed to check if file finalized");
+                    continue;
                 }
+            }
 
-                entry.insert(SerialSyncController::new(
-                    tx_seq,
-                    tx.data_merkle_root,
-                    num_chunks as u64,
-                    self.ctx.clone(),
-                    self.store.clone(),
-                    self.file_location_cache.clone(),
-                ))
+            if let Err(err) = self.on_start_sync_file(tx_seq, None).await {
+                warn!(%tx_seq, %err, "Failed to sync file of new transaction");
             }
-        };
+        }
+    }
 
-        // trigger retry after failure
-        if let SyncState::Failed { .. } = controller.get_status() {
-            controller.reset();
+    async fn on_store_event(&mut self, event: StoreEvent) {
+        match event {
+            StoreEvent::Finalized { tx_seq } => self.seeder.on_file_finalized(tx_seq),
+            StoreEvent::FinalizeFailed { tx_seq } => self.on_finalize_failed(tx_seq).await,
+            StoreEvent::Reverted { tx_seq } => {
+                debug!(%tx_seq, "Clear cached proofs due to log reverted");
+                self.proof_cache.clear();
+            }
+            StoreEvent::ChunksWritten { .. } => {}
         }
+    }
 
-        if let Some((peer_id, addr)) = maybe_peer {
-            controller.on_peer_found(peer_id, addr);
+    /// Re-syncs the file that failed to finalize, so that the missing or invalid ranges are
+    /// downloaded from peers again.
+    async fn on_finalize_failed(&mut self, tx_seq: u64) {
+        let attempts = self.repairs.entry(tx_seq).or_default();
+        *attempts += 1;
+        if *attempts > MAX_REPAIR_ATTEMPTS {
+            error!(%tx_seq, "Failed to finalize file after {} re-syncs", MAX_REPAIR_ATTEMPTS);
+            return;
         }
 
-        controller.transition();
+        info!(%tx_seq, attempts = %*attempts, "Re-sync file that failed to finalize");
 
-        Ok(())
+        // The corrupted chunks are removed, so that they are downloaded again rather than
+        // conflicting with the stored ones.
+        match self.store.invalidate_corrupted_chunks(tx_seq).await {
+            Ok(removed) if removed.is_empty() => {
+                if self.is_file_stored(tx_seq).await {
+                    error!(%tx_seq, "Failed to finalize file with all chunks stored and valid");
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                warn!(%tx_seq, %err, "Failed to remove corrupted chunks of file");
+            }
+        }
+
+        // the new controller only downloads the chunks not in store
+        self.controllers.remove(&tx_seq);
+        if let Err(err) = self.on_start_sync_file(tx_seq, None).await {
+            warn!(%tx_seq, %err, "Failed to re-sync file");
+        }
     }
 
-    async fn on_announce_file_gossip(&mut self, tx_seq: u64, peer_id: PeerId, addr: Multiaddr) {
-        info!(%tx_seq, %peer_id, %addr, "Received AnnounceFile gossip");
+    /// Returns whether all chunks of the file are stored.
+    async fn is_file_stored(&self, tx_seq: u64) -> bool {
+        let tx = match self.store.get_tx_by_seq_number(tx_seq).await {
+            Ok(Some(tx)) => tx,
+            _ => return false,
+        };
+        match self.store.get_chunk_ranges(tx_seq).await {
+            Ok(Some(ranges)) => ranges == [(0, bytes_to_chunks(tx.size as usize))],
+            _ => false,
+        }
+    }
 
-        // File already in sync
-        if let Some(controller) = self.controllers.get_mut(&tx_seq) {
-            controller.on_peer_found(peer_id, addr);
-            controller.transition();
+    /// Returns the number of files in sync, excluding the failed ones awaiting retry.
+    fn active_syncs(&self) -> usize {
+        self.controllers
+            .values()
+            .filter(|controller| !matches!(controller.get_status(), SyncState::Failed { .. }))
+            .count()
+    }
+
+    /// Starts to sync the queued files in order of priority, until the concurrency limit is
+    /// reached.
+    async fn schedule_queued_files(&mut self) {
+        if self.queued.is_empty() {
             return;
         }
 
-        // File already exists and ignore the AnnounceFile message
-        match self.store.check_tx_completed(tx_seq).await {
-            Ok(true) => return,
-            Ok(false) => {}
-            Err(err) => {
-                error!(%tx_seq, %err, "Failed to check if file finalized");
-                return;
+        sort_by_priority(
+            &mut self.queued,
+            self.config.priority,
+            &self.config.pinned_files,
+        );
+
+        while !self.queued.is_empty() && self.active_syncs() < self.config.max_concurrent_syncs {
+            let file = self.queued.remove(0);
+            if let Err(err) = self.on_start_sync_file(file.tx_seq, None).await {
+                warn!(tx_seq = %file.tx_seq, %err, "Failed to start queued file sync");
             }
         }
+    }
 
-        // Now, always sync files among all nodes
-        if let Err(err) = self.on_start_sync_file(tx_seq, Some((peer_id, addr))).await {
-            error!(%tx_seq, %err, "Failed to sync file");
-        }
+    /// Returns the syncing files in descending order of priority.
+    fn prioritized_files(&self) -> Vec<u64> {
+        let mut files: Vec<PendingFile> = self
+            .controllers
+            .iter()
+            .map(|(&tx_seq, controller)| PendingFile {
+                tx_seq,
+                data_root: *controller.data_root(),
+                num_chunks: controller.num_chunks(),
+            })
+            .collect();
+
+        sort_by_priority(&mut files, self.config.priority, &self.config.pinned_files);
+
+        files.into_iter().map(|file| file.tx_seq).collect()
     }
 
-    fn on_heartbeat(&mut self) {
+    async fn on_heartbeat(&mut self) {
         let mut completed = vec![];
+        let mut failed = vec![];
+
+        // files with higher priority request chunks first, before the write queue is congested
+        for tx_seq in self.prioritized_files() {
+            let controller = match self.controllers.get_mut(&tx_seq) {
+                Some(controller) => controller,
+                None => continue,
+            };
 
-        for (&tx_seq, controller) in self.controllers.iter_mut() {
             controller.transition();
 
-            if let SyncState::Completed = controller.get_status() {
-                completed.push(tx_seq);
+            match controller.get_status() {
+                SyncState::Completed => completed.push(tx_seq),
+                SyncState::Failed { .. } => failed.push(tx_seq),
+                _ => {}
             }
         }
 
+        metrics::inc_counter_by(&metrics::SYNC_COMPLETED_FILES, completed.len() as u64);
         for tx_seq in completed {
             self.controllers.remove(&tx_seq);
+            self.repairs.remove(&tx_seq);
+            self.recovery.on_file_synced(tx_seq);
+        }
+
+        metrics::set_gauge(&metrics::SYNC_FAILED_CONTROLLERS, failed.len() as i64);
+        for tx_seq in failed {
+            self.recovery.on_file_failed(tx_seq).await;
+        }
+        for tx_seq in self.recovery.on_heartbeat() {
+            self.on_chunks_recovered(tx_seq).await;
         }
 
+        self.sync_pending_txs().await;
+        self.provisional
+            .on_heartbeat(|tx_seq| self.pending_txs.contains_key(&tx_seq));
+        self.provisional.prune_staged().await;
+        self.schedule_queued_files().await;
+        self.reputation.prune();
+        self.auditor.on_heartbeat().await;
+        self.seeder.on_heartbeat();
+
+        metrics::set_gauge(&metrics::SYNC_CONTROLLERS, self.controllers.len() as i64);
+        metrics::set_gauge(&metrics::SYNC_QUEUED_FILES, self.queued.len() as i64);
+
         // TODO(qhz): serial controller removed, but the peers are not disconnected.
         // If there are enough peers, the outgoing connections limitation will be reached
         // over time. So, sync service requires to say goodbye to some peers after file sync
@@ -480,7 +1322,7 @@ mod tests {
     use network::discovery::ConnectionId;
     use network::rpc::SubstreamId;
     use network::ReportSource;
-    use shared_types::ChunkArray;
+    use shared_types::{ChunkArray, StreamFilter, CHUNK_SIZE};
     use std::time::Duration;
     use std::time::Instant;
     use storage::log_store::log_manager::LogConfig;
@@ -489,6 +1331,7 @@ mod tests {
     use task_executor::test_utils::TestRuntime;
     use tokio::sync::mpsc::UnboundedReceiver;
 
+    use crate::retry::RetryConfig;
     use crate::test_util::tests::{create_2_store, create_file_location_cache};
 
     use super::*;
@@ -509,13 +1352,48 @@ mod tests {
         let (_, sync_recv) = channel::Channel::unbounded();
 
         let heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
+        let (verifier, verify_results) = ChunkVerifyQueue::new(1, store.clone()).unwrap();
+        let (writer, write_results) = ChunkWriteQueue::spawn(&runtime.task_executor, store.clone());
+        let store_events = store.subscribe_events();
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let auditor = Auditor::new(
+            Duration::ZERO,
+            ctx.clone(),
+            store.clone(),
+            file_location_cache.clone(),
+            Default::default(),
+        );
+        let seeder = Seeder::new(
+            0,
+            RetryConfig::default().offer_file,
+            ctx.clone(),
+            file_location_cache.clone(),
+        );
+        let recovery = Recovery::new(ctx.clone(), store.clone());
+        let provisional = Provisional::new(ctx.clone(), store.clone());
 
         let mut sync = SyncService {
+            config: Default::default(),
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx,
             store,
             file_location_cache,
             controllers: Default::default(),
+            queued: Default::default(),
+            pending_txs: Default::default(),
+            reputation: Default::default(),
+            request_limiter: Arc::new(PeerRequestLimiter::new(4)),
+            verifier,
+            verify_results,
+            writer,
+            write_results,
+            store_events,
+            repairs: Default::default(),
+            proof_cache: Default::default(),
+            auditor,
+            seeder,
+            recovery,
+            provisional,
             heartbeat,
         };
 
@@ -539,13 +1417,48 @@ mod tests {
         let (_, sync_recv) = channel::Channel::unbounded();
 
         let heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
+        let (verifier, verify_results) = ChunkVerifyQueue::new(1, store.clone()).unwrap();
+        let (writer, write_results) = ChunkWriteQueue::spawn(&runtime.task_executor, store.clone());
+        let store_events = store.subscribe_events();
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let auditor = Auditor::new(
+            Duration::ZERO,
+            ctx.clone(),
+            store.clone(),
+            file_location_cache.clone(),
+            Default::default(),
+        );
+        let seeder = Seeder::new(
+            0,
+            RetryConfig::default().offer_file,
+            ctx.clone(),
+            file_location_cache.clone(),
+        );
+        let recovery = Recovery::new(ctx.clone(), store.clone());
+        let provisional = Provisional::new(ctx.clone(), store.clone());
 
         let mut sync = SyncService {
+            config: Default::default(),
             msg_recv: sync_recv,
-            ctx: Arc::new(SyncNetworkContext::new(network_send)),
+            ctx,
             store,
             file_location_cache,
             controllers: Default::default(),
+            queued: Default::default(),
+            pending_txs: Default::default(),
+            reputation: Default::default(),
+            request_limiter: Arc::new(PeerRequestLimiter::new(4)),
+            verifier,
+            verify_results,
+            writer,
+            write_results,
+            store_events,
+            repairs: Default::default(),
+            proof_cache: Default::default(),
+            auditor,
+            seeder,
+            recovery,
+            provisional,
             heartbeat,
         };
 
@@ -553,6 +1466,146 @@ mod tests {
         assert_eq!(network_recv.try_recv().is_err(), true);
     }
 
+    fn create_sync_service(
+        runtime: &TestRuntime,
+        store: Store,
+    ) -> (SyncService, UnboundedReceiver<NetworkMessage>) {
+        let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let file_location_cache: Arc<FileLocationCache> =
+            create_file_location_cache(init_peer_id, 1);
+
+        let (network_send, network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+        let (_, sync_recv) = channel::Channel::unbounded();
+
+        let heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SEC));
+        let (verifier, verify_results) = ChunkVerifyQueue::new(1, store.clone()).unwrap();
+        let (writer, write_results) = ChunkWriteQueue::spawn(&runtime.task_executor, store.clone());
+        let store_events = store.subscribe_events();
+        let ctx = Arc::new(SyncNetworkContext::new(network_send));
+        let auditor = Auditor::new(
+            Duration::ZERO,
+            ctx.clone(),
+            store.clone(),
+            file_location_cache.clone(),
+            Default::default(),
+        );
+        let seeder = Seeder::new(
+            0,
+            RetryConfig::default().offer_file,
+            ctx.clone(),
+            file_location_cache.clone(),
+        );
+        let recovery = Recovery::new(ctx.clone(), store.clone());
+        let provisional = Provisional::new(ctx.clone(), store.clone());
+
+        let sync = SyncService {
+            config: Default::default(),
+            msg_recv: sync_recv,
+            ctx,
+            store,
+            file_location_cache,
+            controllers: Default::default(),
+            queued: Default::default(),
+            pending_txs: Default::default(),
+            reputation: Default::default(),
+            request_limiter: Arc::new(PeerRequestLimiter::new(4)),
+            verifier,
+            verify_results,
+            writer,
+            write_results,
+            store_events,
+            repairs: Default::default(),
+            proof_cache: Default::default(),
+            auditor,
+            seeder,
+            recovery,
+            provisional,
+            heartbeat,
+        };
+
+        (sync, network_recv)
+    }
+
+    #[tokio::test]
+    async fn test_resync_on_finalize_failed() {
+        let runtime = TestRuntime::default();
+
+        let chunk_count = 1535;
+        let (store, _, _, _) = create_2_store(vec![chunk_count]);
+        let store = Store::new(store, runtime.task_executor.clone());
+        let (mut sync, _network_recv) = create_sync_service(&runtime, store);
+
+        // finalize fails since no chunks stored
+        let tx_seq = 0;
+        assert!(sync.store.finalize_tx(tx_seq).await.is_err());
+
+        let event = sync.store_events.recv().await.unwrap();
+        assert_eq!(event, StoreEvent::FinalizeFailed { tx_seq });
+
+        sync.on_store_event(event).await;
+        assert!(sync.controllers.contains_key(&tx_seq));
+        assert_eq!(sync.repairs.get(&tx_seq), Some(&1));
+
+        // give up after too many re-syncs
+        for _ in 1..MAX_REPAIR_ATTEMPTS {
+            sync.on_finalize_failed(tx_seq).await;
+        }
+        sync.controllers.clear();
+        sync.on_finalize_failed(tx_seq).await;
+        assert!(!sync.controllers.contains_key(&tx_seq));
+    }
+
+    #[tokio::test]
+    async fn test_repair_corrupted_file() {
+        let runtime = TestRuntime::default();
+
+        let chunk_count = 1535;
+        let (store, _, _, data) = create_2_store(vec![chunk_count]);
+        let store = Store::new(store, runtime.task_executor.clone());
+        let (mut sync, _network_recv) = create_sync_service(&runtime, store);
+
+        // all chunks are stored, but one of them is corrupted
+        let tx_seq = 0;
+        let mut corrupted = data[0].clone();
+        corrupted[1100 * CHUNK_SIZE] ^= 1;
+        sync.store
+            .put_chunks(
+                tx_seq,
+                ChunkArray {
+                    data: corrupted,
+                    start_index: 0,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(sync.store.finalize_tx(tx_seq).await.is_err());
+
+        let event = sync.store_events.recv().await.unwrap();
+        assert_eq!(event, StoreEvent::FinalizeFailed { tx_seq });
+
+        // the subtree with the corrupted chunk is removed, and downloaded again
+        sync.on_store_event(event).await;
+        assert!(sync.controllers.contains_key(&tx_seq));
+        let ranges = sync.store.get_chunk_ranges(tx_seq).await.unwrap();
+        assert_eq!(ranges, Some(vec![(0, 1024), (1280, chunk_count)]));
+
+        // nothing to download if all chunks are stored and valid, which is not retried
+        sync.store
+            .put_chunks(
+                tx_seq,
+                ChunkArray {
+                    data: data[0][1024 * CHUNK_SIZE..1280 * CHUNK_SIZE].to_vec(),
+                    start_index: 1024,
+                },
+            )
+            .await
+            .unwrap();
+        sync.controllers.clear();
+        sync.on_finalize_failed(tx_seq).await;
+        assert!(!sync.controllers.contains_key(&tx_seq));
+        assert_eq!(sync.repairs.get(&tx_seq), Some(&2));
+    }
+
     #[tokio::test]
     async fn test_request_chunks() {
         let runtime = TestRuntime::default();
@@ -574,59 +1627,107 @@ mod tests {
             file_location_cache,
         );
 
-        let request = GetChunksRequest {
-            tx_seq: 0,
-            index_start: 0,
-            index_end: chunk_count as u64,
-        };
-
-        sync_send
-            .notify(SyncMessage::RequestChunks {
-                request_id: (ConnectionId::new(0), SubstreamId(0)),
-                peer_id: init_peer_id,
-                request,
-            })
-            .unwrap();
+        // the latter request is served with the cached proof
+        for substream in 0..2 {
+            let request = GetChunksRequest {
+                tx_seq: 0,
+                index_start: 0,
+                index_end: chunk_count as u64,
+            };
 
-        if let Some(msg) = network_recv.recv().await {
-            match msg {
-                NetworkMessage::SendResponse {
-                    peer_id,
-                    response,
-                    id,
-                } => match response {
-                    network::Response::Chunks(response) => {
-                        assert_eq!(peer_id, init_peer_id);
-                        assert_eq!(id.0, ConnectionId::new(0));
-                        assert_eq!(id.1 .0, 0);
-
-                        let chunk_array = ChunkArray {
-                            data,
-                            start_index: 0,
-                        };
-
-                        assert_eq!(
-                            response.chunks,
-                            chunk_array.sub_array(0, chunk_count as u64).unwrap()
-                        );
-
-                        store
-                            .read()
-                            .await
-                            .validate_range_proof(0, &response)
-                            .expect("validate proof");
-                    }
+            sync_send
+                .notify(SyncMessage::RequestChunks {
+                    request_id: (ConnectionId::new(0), SubstreamId(substream)),
+                    peer_id: init_peer_id,
+                    request,
+                })
+                .unwrap();
+
+            if let Some(msg) = network_recv.recv().await {
+                match msg {
+                    NetworkMessage::SendResponse {
+                        peer_id,
+                        response,
+                        id,
+                    } => match response {
+                        network::Response::Chunks(response) => {
+                            assert_eq!(peer_id, init_peer_id);
+                            assert_eq!(id.0, ConnectionId::new(0));
+                            assert_eq!(id.1 .0, substream);
+
+                            let chunk_array = ChunkArray {
+                                data: data.clone(),
+                                start_index: 0,
+                            };
+
+                            assert_eq!(
+                                response.chunks,
+                                chunk_array.sub_array(0, chunk_count as u64).unwrap()
+                            );
+
+                            store
+                                .read()
+                                .await
+                                .validate_range_proof(0, &response)
+                                .expect("validate proof");
+                        }
+                        _ => {
+                            panic!("Not expected message: Response::Chunks");
+                        }
+                    },
                     _ => {
-                        panic!("Not expected message: Response::Chunks");
+                        panic!("Not expected message: NetworkMessage::SendResponse");
                     }
-                },
-                _ => {
-                    panic!("Not expected message: NetworkMessage::SendResponse");
                 }
             }
         }
     }
 
+    #[tokio::test]
+    async fn test_max_concurrent_syncs() {
+        let runtime = TestRuntime::default();
+
+        let (store, _, _, _) = create_2_store(vec![1535, 1535]);
+        let store = Store::new(store, runtime.task_executor.clone());
+
+        let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let file_location_cache: Arc<FileLocationCache> =
+            create_file_location_cache(init_peer_id, 2);
+
+        let (network_send, _network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+
+        let config = Config {
+            max_concurrent_syncs: 1,
+            ..Default::default()
+        };
+        let sync_send = SyncService::spawn_with_config(
+            config,
+            runtime.task_executor.clone(),
+            network_send,
+            store,
+            file_location_cache,
+        );
+
+        for tx_seq in 0..2 {
+            let response = sync_send
+                .request(SyncRequest::SyncFile { tx_seq })
+                .await
+                .unwrap();
+            assert_eq!(response, SyncResponse::SyncFile { err: "".into() });
+        }
+
+        let response = sync_send
+            .request(SyncRequest::SyncStatus { tx_seq: 1 })
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            SyncResponse::SyncStatus {
+                status: "Queued".into()
+            }
+        );
+    }
+
     #[tokio::test]
     async fn test_request_chunks_invalid_indices() {
         let runtime = TestRuntime::default();
@@ -1250,6 +2351,7 @@ mod tests {
             .notify(SyncMessage::RpcError {
                 request_id: network::SyncId::SerialSync { tx_seq: 0 },
                 peer_id: init_peer_id,
+                error: RPCError::StreamTimeout,
             })
             .unwrap();
 
@@ -1283,6 +2385,7 @@ mod tests {
                 tx_seq,
                 peer_id: init_peer_id,
                 addr: address,
+                ranges: None,
             })
             .unwrap();
 
@@ -1339,6 +2442,7 @@ mod tests {
                 tx_seq,
                 peer_id: init_peer_id,
                 addr: address,
+                ranges: None,
             })
             .unwrap();
 
@@ -1390,9 +2494,113 @@ mod tests {
                 tx_seq,
                 peer_id: init_peer_id,
                 addr: address,
+                ranges: None,
+            })
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(1000));
+        assert_eq!(network_recv.try_recv().is_err(), true);
+    }
+
+    #[tokio::test]
+    async fn test_announce_file_unsubscribed() {
+        let runtime = TestRuntime::default();
+
+        let chunk_count = 1535;
+        let (store, _, _, _) = create_2_store(vec![chunk_count]);
+
+        let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let file_location_cache: Arc<FileLocationCache> = Default::default();
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+
+        let config = Config {
+            stream_filter: StreamFilter::new(vec![1.into()]),
+            ..Default::default()
+        };
+        let sync_send = SyncService::spawn_with_config(
+            config,
+            runtime.task_executor.clone(),
+            network_send,
+            store.clone(),
+            file_location_cache,
+        );
+
+        let tx_seq = 0u64;
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        sync_send
+            .notify(SyncMessage::AnnounceFileGossip {
+                tx_seq,
+                peer_id: init_peer_id,
+                addr: address,
+                ranges: None,
+            })
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(1000));
+        assert_eq!(network_recv.try_recv().is_err(), true);
+
+        // the file is still synced upon request
+        let response = sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .unwrap();
+        assert_eq!(response, SyncResponse::SyncFile { err: "".into() });
+    }
+
+    #[tokio::test]
+    async fn test_new_tx_gossip_ahead_of_log_sync() {
+        let runtime = TestRuntime::default();
+
+        let chunk_count = 1535;
+        let (store, _, _, _) = create_2_store(vec![chunk_count]);
+
+        let init_peer_id = identity::Keypair::generate_ed25519().public().to_peer_id();
+        let file_location_cache: Arc<FileLocationCache> = Default::default();
+
+        let (network_send, mut network_recv) = mpsc::unbounded_channel::<NetworkMessage>();
+
+        let sync_send = SyncService::spawn(
+            runtime.task_executor.clone(),
+            network_send,
+            store.clone(),
+            file_location_cache,
+        );
+
+        // the transaction is not synced from the blockchain yet
+        let tx_seq = 1u64;
+        sync_send
+            .notify(SyncMessage::NewTxGossip {
+                tx_seq,
+                data_root: DataRoot::from_low_u64_be(1),
+                size: 1024,
+            })
+            .unwrap();
+
+        match network_recv.recv().await.unwrap() {
+            NetworkMessage::Publish { messages } => match &messages[..] {
+                [PubsubMessage::FindFile(msg)] => assert_eq!(msg.tx_seq, tx_seq),
+                _ => panic!("Unexpected messages {:?}", messages),
+            },
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+
+        // the announced file is downloaded provisionally while waiting for the log sync
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+        sync_send
+            .notify(SyncMessage::AnnounceFileGossip {
+                tx_seq,
+                peer_id: init_peer_id,
+                addr: address,
+                ranges: None,
             })
             .unwrap();
 
+        match network_recv.recv().await.unwrap() {
+            NetworkMessage::DialPeer { peer_id, .. } => assert_eq!(peer_id, init_peer_id),
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+
         thread::sleep(Duration::from_millis(1000));
         assert_eq!(network_recv.try_recv().is_err(), true);
     }
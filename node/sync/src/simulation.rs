@@ -0,0 +1,569 @@
+//! Deterministic simulation of the sync service, which runs against scripted peers instead of the
+//! network service, and on the virtual clock of tokio instead of the wall clock.
+//!
+//! The simulation must run on a current thread runtime with the clock paused, e.g. in
+//! `#[tokio::test(start_paused = true)]`, so that the clock is only advanced when all tasks are
+//! idle, and the timeouts, retries and heartbeats of the sync service are triggered without
+//! waiting. Together with the scheduler rng seeded by `seed_rng`, a scenario is reproducible from
+//! its seed.
+//!
+//! ```ignore
+//! seed_rng(seed);
+//! let peers = vec![
+//!     SimulatedPeer::new(0, PeerBehavior::Silent, Duration::from_millis(100)),
+//!     SimulatedPeer::new(1, PeerBehavior::Honest, Duration::from_millis(300)),
+//! ];
+//! let mut sim = Simulation::new(Config::default(), store, peer_store, peers);
+//! let elapsed = sim.sync_file(tx_seq, Duration::from_secs(600)).await?;
+//! ```
+
+use crate::{Config, SyncMessage, SyncRequest, SyncResponse, SyncSender, SyncService};
+use file_location_cache::FileLocationCache;
+use libp2p::identity;
+use network::rpc::{GetChunksRequest, RPCError, RPCResponseErrorCode};
+use network::{
+    Multiaddr, NetworkMessage, PeerAction, PeerId, PubsubMessage, Request, SyncId as RequestId,
+};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::Store as LogStore;
+use storage_async::{Store, StoreEvent};
+use task_executor::TaskExecutor;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::time::Instant;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
+/// Seeds the rng of the sync scheduler on the current thread, e.g. to pick peers to connect.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Handle to the thread local rng seeded by `seed_rng`.
+pub struct SimulationRng;
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+pub(crate) fn rng() -> SimulationRng {
+    SimulationRng
+}
+
+/// How a simulated peer responds to the sync service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerBehavior {
+    /// Serves the requested chunks.
+    Honest,
+    /// Accepts connections, but never responds to chunks requests.
+    Silent,
+    /// Always responds busy due to its bandwidth quota.
+    Busy,
+    /// Responds RPC errors to the first `n` chunks requests, and serves the others.
+    Flaky(usize),
+    /// Serves chunks with corrupted data, which fail the proof validation.
+    Malicious,
+    /// Announces the file, but could not be dialed.
+    Unreachable,
+}
+
+pub struct SimulatedPeer {
+    pub peer_id: PeerId,
+    pub addr: Multiaddr,
+    pub behavior: PeerBehavior,
+    /// Delay of every message from the peer.
+    pub latency: Duration,
+    /// Number of chunks requests received.
+    requests: usize,
+}
+
+impl SimulatedPeer {
+    /// Creates the `index`-th peer, whose id is derived from the index instead of randomly.
+    pub fn new(index: u64, behavior: PeerBehavior, latency: Duration) -> Self {
+        let mut secret = [0u8; 32];
+        secret[..8].copy_from_slice(&(index + 1).to_be_bytes());
+        let secret = identity::ed25519::SecretKey::from_bytes(&mut secret)
+            .expect("any 32 bytes is a valid ed25519 secret key");
+        let peer_id = identity::Keypair::Ed25519(secret.into())
+            .public()
+            .to_peer_id();
+
+        SimulatedPeer {
+            peer_id,
+            addr: format!("/ip4/127.0.0.1/tcp/{}", 10000 + index)
+                .parse()
+                .expect("valid multiaddr"),
+            behavior,
+            latency,
+            requests: 0,
+        }
+    }
+}
+
+/// Network messages sent by the sync service, in the order handled by the simulated peers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimulationEvent {
+    FindFile {
+        tx_seq: u64,
+    },
+    Dial {
+        peer_id: PeerId,
+    },
+    Request {
+        peer_id: PeerId,
+        index_start: u64,
+        index_end: u64,
+    },
+    Ban {
+        peer_id: PeerId,
+    },
+}
+
+pub struct Simulation {
+    sync_send: SyncSender,
+    network_recv: mpsc::UnboundedReceiver<NetworkMessage>,
+    store_events: broadcast::Receiver<StoreEvent>,
+
+    /// Storage of all the simulated peers, which have the whole files.
+    peer_store: Arc<RwLock<dyn LogStore>>,
+    peers: Vec<SimulatedPeer>,
+    /// Peers banned by the sync service, which are disconnected and never reconnected.
+    banned: HashSet<PeerId>,
+
+    /// Events with the virtual time elapsed since the simulation started.
+    trace: Vec<(Duration, SimulationEvent)>,
+    started_at: Instant,
+
+    executor: TaskExecutor,
+    // the sync service stops once dropped
+    _exit: exit_future::Signal,
+}
+
+impl Simulation {
+    /// Spawns the sync service on the current runtime, which syncs files into `store` from the
+    /// `peers` serving the files in `peer_store`.
+    ///
+    /// Chunks are always verified on the sync service's task, since the clock could be advanced
+    /// while they are verified on other threads.
+    pub fn new(
+        mut config: Config,
+        store: Arc<RwLock<dyn LogStore>>,
+        peer_store: Arc<RwLock<dyn LogStore>>,
+        peers: Vec<SimulatedPeer>,
+    ) -> Self {
+        config.verify_threads = 0;
+        let (exit_signal, exit) = exit_future::signal();
+        let (shutdown_send, _) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(tokio::runtime::Handle::current(), exit, shutdown_send);
+
+        let store = Store::new(store, executor.clone());
+        let store_events = store.subscribe_events();
+        let (network_send, network_recv) = mpsc::unbounded_channel();
+        let sync_send = SyncService::spawn_with_config(
+            config,
+            executor.clone(),
+            network_send,
+            store,
+            Arc::new(FileLocationCache::default()),
+        );
+
+        Simulation {
+            sync_send,
+            network_recv,
+            store_events,
+            peer_store,
+            peers,
+            banned: Default::default(),
+            trace: vec![],
+            started_at: Instant::now(),
+            executor,
+            _exit: exit_signal,
+        }
+    }
+
+    pub fn trace(&self) -> &[(Duration, SimulationEvent)] {
+        &self.trace
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.banned.contains(peer_id)
+    }
+
+    /// Syncs the file, and returns the virtual time elapsed until the file is finalized.
+    pub async fn sync_file(&mut self, tx_seq: u64, timeout: Duration) -> Result<Duration, String> {
+        let started_at = Instant::now();
+
+        match self
+            .sync_send
+            .request(SyncRequest::SyncFile { tx_seq })
+            .await
+            .map_err(|e| format!("Failed to send sync request: {:?}", e))?
+        {
+            SyncResponse::SyncFile { err } if err.is_empty() => {}
+            SyncResponse::SyncFile { err } => return Err(err),
+            resp => return Err(format!("Unexpected sync response: {:?}", resp)),
+        }
+
+        let deadline = started_at + timeout;
+
+        loop {
+            tokio::select! {
+                Some(msg) = self.network_recv.recv() => self.on_network_msg(msg).await,
+
+                event = self.store_events.recv() => match event {
+                    Ok(StoreEvent::Finalized { tx_seq: seq }) if seq == tx_seq => {
+                        return Ok(started_at.elapsed());
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Err("Store closed".into()),
+                },
+
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(format!("File not finalized in {:?}", timeout));
+                }
+            }
+        }
+    }
+
+    async fn on_network_msg(&mut self, msg: NetworkMessage) {
+        match msg {
+            NetworkMessage::Publish { messages } => {
+                for msg in messages {
+                    if let PubsubMessage::FindFile(find_file) = msg {
+                        self.on_find_file(find_file.tx_seq);
+                    }
+                }
+            }
+            NetworkMessage::DialPeer { peer_id, .. } => self.on_dial(peer_id),
+            NetworkMessage::SendRequest {
+                peer_id,
+                request_id: network::RequestId::Sync(request_id),
+                request: Request::GetChunks(request),
+            } => self.on_chunks_request(peer_id, request_id, request).await,
+            NetworkMessage::ReportPeer {
+                peer_id,
+                action: PeerAction::Fatal,
+                ..
+            } => self.on_ban(peer_id),
+            // e.g. the penalties other than bans
+            _ => {}
+        }
+    }
+
+    fn record(&mut self, event: SimulationEvent) {
+        self.trace.push((self.started_at.elapsed(), event));
+    }
+
+    /// Sends the message from a peer to the sync service after the latency.
+    fn deliver(&self, latency: Duration, msg: SyncMessage) {
+        let sync_send = self.sync_send.clone();
+        self.executor.spawn(
+            async move {
+                tokio::time::sleep(latency).await;
+                let _ = sync_send.notify(msg);
+            },
+            "simulated_peer",
+        );
+    }
+
+    /// All the peers not banned announce the file.
+    fn on_find_file(&mut self, tx_seq: u64) {
+        self.record(SimulationEvent::FindFile { tx_seq });
+
+        for peer in self
+            .peers
+            .iter()
+            .filter(|p| !self.banned.contains(&p.peer_id))
+        {
+            self.deliver(
+                peer.latency,
+                SyncMessage::AnnounceFileGossip {
+                    tx_seq,
+                    peer_id: peer.peer_id,
+                    addr: peer.addr.clone(),
+                    ranges: None,
+                },
+            );
+        }
+    }
+
+    fn on_dial(&mut self, peer_id: PeerId) {
+        self.record(SimulationEvent::Dial { peer_id });
+
+        let peer = match self.peers.iter().find(|p| p.peer_id == peer_id) {
+            Some(peer) => peer,
+            None => return,
+        };
+
+        let msg = if peer.behavior == PeerBehavior::Unreachable || self.banned.contains(&peer_id) {
+            SyncMessage::DailFailed { peer_id }
+        } else {
+            SyncMessage::PeerConnected { peer_id }
+        };
+
+        self.deliver(peer.latency, msg);
+    }
+
+    async fn on_chunks_request(
+        &mut self,
+        peer_id: PeerId,
+        request_id: RequestId,
+        request: GetChunksRequest,
+    ) {
+        self.record(SimulationEvent::Request {
+            peer_id,
+            index_start: request.index_start,
+            index_end: request.index_end,
+        });
+
+        // disconnected
+        if self.banned.contains(&peer_id) {
+            return;
+        }
+
+        let peer = match self.peers.iter_mut().find(|p| p.peer_id == peer_id) {
+            Some(peer) => peer,
+            None => return,
+        };
+        peer.requests += 1;
+        let (behavior, requests, latency) = (peer.behavior, peer.requests, peer.latency);
+
+        let error = |code, msg: &str| SyncMessage::RpcError {
+            peer_id,
+            request_id,
+            error: RPCError::ErrorResponse(code, msg.into()),
+        };
+
+        let msg = match behavior {
+            PeerBehavior::Silent | PeerBehavior::Unreachable => return,
+            PeerBehavior::Busy => error(RPCResponseErrorCode::Busy, "Bandwidth quota exceeded"),
+            PeerBehavior::Flaky(n) if requests <= n => {
+                error(RPCResponseErrorCode::ServerError, "Flaky peer")
+            }
+            PeerBehavior::Honest | PeerBehavior::Flaky(_) | PeerBehavior::Malicious => {
+                match self.read_chunks(&request).await {
+                    Some(mut response) => {
+                        if behavior == PeerBehavior::Malicious {
+                            response.chunks.data[0] ^= 0xff;
+                        }
+
+                        SyncMessage::ChunksResponse {
+                            peer_id,
+                            request_id,
+                            response,
+                        }
+                    }
+                    None => error(RPCResponseErrorCode::ServerError, "Chunks not found"),
+                }
+            }
+        };
+
+        self.deliver(latency, msg);
+    }
+
+    async fn read_chunks(
+        &self,
+        request: &GetChunksRequest,
+    ) -> Option<shared_types::ChunkArrayWithProof> {
+        self.peer_store
+            .read()
+            .await
+            .get_chunks_with_proof_by_tx_and_index_range(
+                request.tx_seq,
+                request.index_start as usize,
+                request.index_end as usize,
+            )
+            .ok()
+            .flatten()
+    }
+
+    /// The banned peer is disconnected.
+    fn on_ban(&mut self, peer_id: PeerId) {
+        self.record(SimulationEvent::Ban { peer_id });
+
+        let latency = match self.peers.iter().find(|p| p.peer_id == peer_id) {
+            Some(peer) => peer.latency,
+            None => return,
+        };
+
+        if self.banned.insert(peer_id) {
+            self.deliver(latency, SyncMessage::PeerDisconnected { peer_id });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::tests::create_2_store;
+    use rand::Rng;
+    use storage::log_store::LogStoreRead;
+    use storage::LogManager;
+
+    /// More than one request of chunks.
+    const NUM_CHUNKS: usize = 5000;
+
+    const TIMEOUT: Duration = Duration::from_secs(3600);
+
+    fn random_behavior(rng: &mut StdRng) -> PeerBehavior {
+        match rng.gen_range(0..6) {
+            0 => PeerBehavior::Honest,
+            1 => PeerBehavior::Silent,
+            2 => PeerBehavior::Busy,
+            3 => PeerBehavior::Flaky(rng.gen_range(1..4)),
+            4 => PeerBehavior::Malicious,
+            _ => PeerBehavior::Unreachable,
+        }
+    }
+
+    /// Random peers of which at least one is honest.
+    fn random_peers(rng: &mut StdRng) -> Vec<SimulatedPeer> {
+        let num_peers = rng.gen_range(1..=5);
+        let honest = rng.gen_range(0..num_peers);
+
+        (0..num_peers)
+            .map(|i| {
+                let behavior = if i == honest {
+                    PeerBehavior::Honest
+                } else {
+                    random_behavior(rng)
+                };
+                let latency = Duration::from_millis(rng.gen_range(10..2000));
+                SimulatedPeer::new(i as u64, behavior, latency)
+            })
+            .collect()
+    }
+
+    fn new_simulation(peers: Vec<SimulatedPeer>) -> (Simulation, Arc<RwLock<LogManager>>, Vec<u8>) {
+        let (store, peer_store, _, mut data) = create_2_store(vec![NUM_CHUNKS]);
+        let sim = Simulation::new(Config::default(), store.clone(), peer_store, peers);
+        (sim, store, data.remove(0))
+    }
+
+    async fn assert_synced(store: &RwLock<LogManager>, data: &[u8]) {
+        let chunks = store
+            .read()
+            .await
+            .get_chunks_by_tx_and_index_range(0, 0, NUM_CHUNKS)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunks.data, data);
+    }
+
+    fn banned_peers(sim: &Simulation) -> Vec<PeerId> {
+        sim.trace()
+            .iter()
+            .filter_map(|(_, event)| match event {
+                SimulationEvent::Ban { peer_id } => Some(*peer_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sync_with_random_peers() {
+        for seed in 0..16 {
+            seed_rng(seed);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let peers = random_peers(&mut rng);
+            let honest: Vec<PeerId> = peers
+                .iter()
+                .filter(|p| p.behavior == PeerBehavior::Honest)
+                .map(|p| p.peer_id)
+                .collect();
+
+            let (mut sim, store, data) = new_simulation(peers);
+            let result = sim.sync_file(0, TIMEOUT).await;
+            assert!(result.is_ok(), "seed {}: {:?}", seed, result);
+            assert_synced(&store, &data).await;
+
+            // honest peers are never banned
+            for peer_id in banned_peers(&sim) {
+                assert!(!honest.contains(&peer_id), "seed {}", seed);
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_flaky_peer() {
+        let peer = SimulatedPeer::new(0, PeerBehavior::Flaky(2), Duration::from_millis(100));
+        let peer_id = peer.peer_id;
+        let (mut sim, store, data) = new_simulation(vec![peer]);
+
+        sim.sync_file(0, TIMEOUT).await.unwrap();
+        assert_synced(&store, &data).await;
+
+        // the failed requests are retried from the same peer without ban
+        let requests = sim
+            .trace()
+            .iter()
+            .filter(|(_, event)| matches!(event, SimulationEvent::Request { .. }))
+            .count();
+        assert!(requests > 2);
+        assert!(!sim.is_banned(&peer_id));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_busy_peer_not_blocking() {
+        // either peer may be connected first, depending on the seed
+        for seed in 0..8 {
+            seed_rng(seed);
+            let busy = SimulatedPeer::new(0, PeerBehavior::Busy, Duration::from_millis(100));
+            let honest = SimulatedPeer::new(1, PeerBehavior::Honest, Duration::from_millis(100));
+            let busy_id = busy.peer_id;
+            let (mut sim, store, data) = new_simulation(vec![busy, honest]);
+
+            sim.sync_file(0, TIMEOUT).await.unwrap();
+            assert_synced(&store, &data).await;
+            assert!(!sim.is_banned(&busy_id));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ban_bad_peers() {
+        let latency = Duration::from_millis(100);
+        let silent = SimulatedPeer::new(0, PeerBehavior::Silent, latency);
+        let malicious = SimulatedPeer::new(1, PeerBehavior::Malicious, latency);
+        let bad_peers = [silent.peer_id, malicious.peer_id];
+        let (mut sim, _, _) = new_simulation(vec![silent, malicious]);
+
+        // requests to the silent peer time out
+        assert!(sim.sync_file(0, Duration::from_secs(600)).await.is_err());
+        for peer_id in bad_peers {
+            assert!(sim.is_banned(&peer_id));
+        }
+
+        // never requested once banned
+        for peer_id in bad_peers {
+            let banned_at = sim
+                .trace()
+                .iter()
+                .position(|(_, event)| *event == SimulationEvent::Ban { peer_id })
+                .unwrap();
+            assert!(!sim.trace()[banned_at..].iter().any(|(_, event)| matches!(
+                event,
+                SimulationEvent::Request { peer_id: p, .. } if *p == peer_id
+            )));
+        }
+    }
+}
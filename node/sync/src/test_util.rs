@@ -27,7 +27,18 @@ pub mod tests {
         Vec<Transaction>,
         Vec<Vec<u8>>,
     ) {
-        let config = LogConfig::default();
+        create_2_store_with_config(LogConfig::default(), chunk_count)
+    }
+
+    pub fn create_2_store_with_config(
+        config: LogConfig,
+        chunk_count: Vec<usize>,
+    ) -> (
+        Arc<RwLock<LogManager>>,
+        Arc<RwLock<LogManager>>,
+        Vec<Transaction>,
+        Vec<Vec<u8>>,
+    ) {
         let mut store = LogManager::memorydb(config.clone()).unwrap();
         let mut peer_store = LogManager::memorydb(config.clone()).unwrap();
 
@@ -87,6 +98,8 @@ pub mod tests {
             data: vec![],
             start_entry_index: start_offset,
             merkle_nodes: merkel_nodes,
+            sender: Default::default(),
+            block_timestamp: 0,
         };
         store.put_tx(tx.clone()).unwrap();
         peer_store.put_tx(tx.clone()).unwrap();
@@ -147,6 +160,7 @@ pub mod tests {
                 peer_id: peer_id.into(),
                 at: address.into(),
                 timestamp: timestamp_now(),
+                ranges: vec![],
             };
 
             let local_private_key = identity::Keypair::generate_secp256k1();
@@ -0,0 +1,87 @@
+use crate::TestNode;
+use anyhow::Result;
+use merkle_light::merkle::{log2_pow2, next_pow2};
+use shared_types::{DataRoot, Transaction};
+use std::cmp;
+use storage::log_store::log_manager::{bytes_to_entries, sub_merkle_tree, ENTRY_SIZE};
+
+/// Appends the log entries of files to the stores of nodes in the same way as the log contract,
+/// in place of syncing the log from blockchain.
+pub struct SimulatedChain {
+    next_tx_seq: u64,
+    /// The first entry of the flow is reserved.
+    next_entry_index: u64,
+}
+
+impl Default for SimulatedChain {
+    fn default() -> Self {
+        SimulatedChain {
+            next_tx_seq: 0,
+            next_entry_index: 1,
+        }
+    }
+}
+
+impl SimulatedChain {
+    /// Submits the file, whose entries start from the next boundary of its first subtree.
+    pub fn submit(&mut self, data: &[u8]) -> Result<Transaction> {
+        let merkle_nodes = subtree_root_list(data)?;
+        let first_subtree_size = 1u64 << (merkle_nodes[0].0 - 1);
+        let start_entry_index = (self.next_entry_index + first_subtree_size - 1)
+            / first_subtree_size
+            * first_subtree_size;
+
+        let tx = Transaction {
+            stream_ids: vec![],
+            size: data.len() as u64,
+            data_merkle_root: sub_merkle_tree(data)?.root().into(),
+            seq: self.next_tx_seq,
+            data: vec![],
+            start_entry_index,
+            merkle_nodes,
+            sender: Default::default(),
+            block_timestamp: 0,
+        };
+
+        self.next_tx_seq += 1;
+        self.next_entry_index = start_entry_index + bytes_to_entries(tx.size);
+
+        Ok(tx)
+    }
+
+    /// Submits the file, and appends the log entry to the stores of `nodes`.
+    pub async fn submit_to(&mut self, data: &[u8], nodes: &[&TestNode]) -> Result<Transaction> {
+        let tx = self.submit(data)?;
+        for node in nodes {
+            node.store.put_tx(tx.clone()).await?;
+        }
+        Ok(tx)
+    }
+}
+
+/// Splits the file into subtrees of descending sizes in powers of two, and returns the height
+/// and root of each subtree.
+fn subtree_root_list(data: &[u8]) -> Result<Vec<(usize, DataRoot)>> {
+    let mut root_list = Vec::new();
+    let mut start_index = 0;
+    let num_entries = bytes_to_entries(data.len() as u64) as usize;
+
+    while start_index != num_entries {
+        let next = next_subtree_size(num_entries - start_index);
+        let end = cmp::min((start_index + next) * ENTRY_SIZE, data.len());
+        let root = sub_merkle_tree(&data[start_index * ENTRY_SIZE..end])?.root();
+        root_list.push((log2_pow2(next) + 1, root.into()));
+        start_index += next;
+    }
+
+    Ok(root_list)
+}
+
+fn next_subtree_size(tree_size: usize) -> usize {
+    let next = next_pow2(tree_size);
+    if next == tree_size {
+        tree_size
+    } else {
+        next >> 1
+    }
+}
@@ -0,0 +1,212 @@
+//! Harness of the end-to-end tests, which runs multiple nodes in process with in-memory stores,
+//! connected by libp2p on the local host. The log entries are appended to the stores by
+//! `SimulatedChain`, in place of the log sync from blockchain.
+//!
+//! ```ignore
+//! let uploader = TestNode::start(vec![]).await?;
+//! let downloader = TestNode::start(vec![uploader.multiaddr.clone()]).await?;
+//! SimulatedChain::default().submit_to(&data, &[&uploader, &downloader]).await?;
+//! ```
+
+mod chain;
+
+use anyhow::{anyhow, bail, Result};
+use file_location_cache::FileLocationCache;
+use ionian_client::{Client, DEFAULT_CHUNKS_PER_SEGMENT};
+use jsonrpsee::http_server::HttpServerHandle;
+use miner::MinerService;
+use network::{Multiaddr, NetworkConfig, NetworkGlobals, Service as LibP2PService};
+use router::RouterService;
+use rpc::RPCConfig;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use storage::log_store::log_manager::LogConfig;
+use storage::log_store::Store;
+use storage::LogManager;
+use sync::SyncService;
+use task_executor::TaskExecutor;
+use tempfile::TempDir;
+use tokio::sync::{mpsc, RwLock};
+use unused_port::unused_tcp_port;
+
+pub use chain::SimulatedChain;
+
+/// Interval to poll the conditions to wait for.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// A node with the services to upload, announce, sync and download files, except the log sync
+/// and the miner.
+pub struct TestNode {
+    pub store: storage_async::Store,
+    pub network_globals: Arc<NetworkGlobals>,
+    /// The libp2p address for other nodes to dial.
+    pub multiaddr: Multiaddr,
+    /// Client of the RPC server of the node.
+    pub client: Client,
+
+    // the services stop once dropped
+    _exit: exit_future::Signal,
+    _rpc: HttpServerHandle,
+    _network_dir: TempDir,
+}
+
+impl TestNode {
+    /// Starts a node, which dials `peers` on startup. Must be called within a tokio runtime.
+    pub async fn start(peers: Vec<Multiaddr>) -> Result<TestNode> {
+        let (exit_signal, exit) = exit_future::signal();
+        let (shutdown_send, _) = futures::channel::mpsc::channel(1);
+        let executor = TaskExecutor::new(tokio::runtime::Handle::current(), exit, shutdown_send);
+
+        let store: Arc<RwLock<dyn Store>> =
+            Arc::new(RwLock::new(LogManager::memorydb(LogConfig::default())?));
+        let async_store = storage_async::Store::new(store.clone(), executor.clone());
+        let file_location_cache = Arc::new(FileLocationCache::default());
+
+        let network_dir = tempfile::tempdir()?;
+        let port = unused_tcp_port().map_err(|e| anyhow!(e))?;
+        let config = network_config(port, network_dir.path(), peers);
+        let (network_globals, keypair, libp2p) = LibP2PService::new(
+            executor.clone(),
+            network::Context {
+                config: &config,
+                keypair: None,
+            },
+        )
+        .await
+        .map_err(|e| anyhow!("Failed to start network service: {:?}", e))?;
+        let (network_send, network_recv) = mpsc::unbounded_channel();
+
+        let sync_send = SyncService::spawn_with_config(
+            Default::default(),
+            executor.clone(),
+            network_send.clone(),
+            async_store.clone(),
+            file_location_cache.clone(),
+        );
+        let miner_send = MinerService::spawn(
+            executor.clone(),
+            network_send.clone(),
+            None,
+            async_store.clone(),
+        )
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+        RouterService::spawn(
+            executor.clone(),
+            libp2p,
+            network_globals.clone(),
+            network_recv,
+            network_send.clone(),
+            sync_send.clone(),
+            miner_send.clone(),
+            store,
+            file_location_cache,
+            keypair,
+            &config,
+        );
+
+        let (chunk_pool, chunk_pool_handler) = chunk_pool::unbounded(
+            chunk_pool_config(),
+            async_store.clone(),
+            network_send.clone(),
+        );
+        executor.spawn(chunk_pool_handler.run(), "chunk_pool_handler");
+
+        let rpc_config = rpc_config(unused_tcp_port().map_err(|e| anyhow!(e))?);
+        let rpc_url = format!("http://{}", rpc_config.listen_address);
+        let (rpc_handle, _) = rpc::run_server(rpc::Context {
+            config: rpc_config,
+            network_globals: Some(network_globals.clone()),
+            network_send: Some(network_send),
+            sync_send: Some(sync_send),
+            miner_send: Some(miner_send),
+            chain_head: None,
+            chunk_pool,
+            log_store: async_store.clone(),
+            log_filter: None,
+            shutdown_sender: executor.shutdown_sender(),
+            executor,
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to start RPC server: {:?}", e))?;
+
+        Ok(TestNode {
+            store: async_store,
+            network_globals,
+            multiaddr: format!("/ip4/{}/tcp/{}", LOCALHOST, port).parse()?,
+            client: Client::new(&rpc_url)?,
+            _exit: exit_signal,
+            _rpc: rpc_handle,
+            _network_dir: network_dir,
+        })
+    }
+
+    /// Waits until the node connects to at least `num_peers` peers.
+    pub async fn wait_for_peers(&self, num_peers: usize, timeout: Duration) -> Result<()> {
+        wait_until(timeout, || {
+            self.network_globals.connected_peers() >= num_peers
+        })
+        .await
+    }
+}
+
+/// Polls `condition` until it holds, or fails after `timeout`.
+pub async fn wait_until(timeout: Duration, condition: impl Fn() -> bool) -> Result<()> {
+    let polling = async {
+        while !condition() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    };
+
+    if tokio::time::timeout(timeout, polling).await.is_err() {
+        bail!("condition not met in {:?}", timeout);
+    }
+
+    Ok(())
+}
+
+fn network_config(port: u16, network_dir: &Path, peers: Vec<Multiaddr>) -> NetworkConfig {
+    let mut config = NetworkConfig::default();
+    config.network_dir = network_dir.into();
+    config.listen_address = LOCALHOST;
+    config.libp2p_port = port;
+    config.discovery_port = port;
+    config.enr_address = Some(LOCALHOST);
+    config.enr_tcp_port = Some(port);
+    config.enr_udp_port = Some(port);
+    // peers are dialed directly instead
+    config.disable_discovery = true;
+    config.upnp_enabled = false;
+    config.libp2p_nodes = peers;
+    config
+}
+
+fn rpc_config(port: u16) -> RPCConfig {
+    RPCConfig {
+        enabled: true,
+        listen_address: SocketAddr::new(LOCALHOST, port),
+        ws_listen_address: None,
+        cors_allowed_origins: None,
+        max_request_body_size: 10 * 1024 * 1024,
+        chunks_per_segment: DEFAULT_CHUNKS_PER_SEGMENT,
+        max_response_chunks: 16 * 1024,
+        max_batch_size: 1024,
+        upload_session_timeout: Duration::from_secs(600),
+        rate_limit: None,
+        auth: None,
+    }
+}
+
+fn chunk_pool_config() -> chunk_pool::Config {
+    chunk_pool::Config {
+        max_cached_chunks_per_file: 4 * 1024,
+        max_cached_chunks_all: 4 * 1024 * 1024,
+        max_writings: 16,
+        expiration_time_secs: 300,
+    }
+}
@@ -0,0 +1,87 @@
+use integration_tests::{SimulatedChain, TestNode};
+use rand::RngCore;
+use shared_types::CHUNK_SIZE;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The announcements are published only after the gossipsub mesh is built by the heartbeats.
+const GOSSIP_MESH_DELAY: Duration = Duration::from_secs(5);
+
+fn random_data(num_chunks: usize) -> Vec<u8> {
+    let mut data = vec![0u8; num_chunks * CHUNK_SIZE];
+    rand::thread_rng().fill_bytes(&mut data);
+    data
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_upload_sync_download() {
+    let uploader = TestNode::start(vec![]).await.unwrap();
+    let downloader = TestNode::start(vec![uploader.multiaddr.clone()])
+        .await
+        .unwrap();
+    downloader.wait_for_peers(1, TIMEOUT).await.unwrap();
+    tokio::time::sleep(GOSSIP_MESH_DELAY).await;
+
+    // more than one segment
+    let data = random_data(1500);
+    let tx = SimulatedChain::default()
+        .submit_to(&data, &[&uploader, &downloader])
+        .await
+        .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file");
+    std::fs::write(&file, &data).unwrap();
+
+    // upload and finalize
+    let root = uploader.client.upload_file(&file).await.unwrap();
+    assert_eq!(root, tx.data_merkle_root);
+    tokio::time::timeout(TIMEOUT, uploader.client.wait_finalized(root))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // announced once finalized, and then synced by the peer
+    let info = tokio::time::timeout(TIMEOUT, downloader.client.wait_finalized(root))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.tx.seq, tx.seq);
+
+    let copy = dir.path().join("copy");
+    downloader.client.download_file(root, &copy).await.unwrap();
+    assert_eq!(std::fs::read(&copy).unwrap(), data);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sync_multiple_files() {
+    let uploader = TestNode::start(vec![]).await.unwrap();
+    let downloader = TestNode::start(vec![uploader.multiaddr.clone()])
+        .await
+        .unwrap();
+    downloader.wait_for_peers(1, TIMEOUT).await.unwrap();
+    tokio::time::sleep(GOSSIP_MESH_DELAY).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut chain = SimulatedChain::default();
+    let mut roots = vec![];
+    for (i, num_chunks) in [1, 3, 1024].into_iter().enumerate() {
+        let data = random_data(num_chunks);
+        chain
+            .submit_to(&data, &[&uploader, &downloader])
+            .await
+            .unwrap();
+
+        let file = dir.path().join(i.to_string());
+        std::fs::write(&file, &data).unwrap();
+        roots.push(uploader.client.upload_file(&file).await.unwrap());
+    }
+
+    for root in roots {
+        tokio::time::timeout(TIMEOUT, downloader.client.wait_finalized(root))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
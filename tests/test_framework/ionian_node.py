@@ -70,15 +70,45 @@ class IonianNode(TestNode):
     def ionian_get_status(self):
         return self.rpc.ionian_getStatus()["connectedPeers"]
 
+    def ionian_begin_upload(self, data_root, size, metadata=None):
+        return self.rpc.ionian_beginUpload([data_root, size, metadata])
+
     def ionian_upload_segment(self, segment):
         return self.rpc.ionian_uploadSegment([segment])
 
     def ionian_download_segment(self, data_root, start_index, end_index):
         return self.rpc.ionian_downloadSegment([data_root, start_index, end_index])
 
+    def ionian_download_range(self, data_root, start_index, end_index):
+        return self.rpc.ionian_downloadRange([data_root, start_index, end_index])
+
+    def ionian_verify_proof(self, root, proof, data):
+        return self.rpc.ionian_verifyProof([root, proof, data])
+
+    def ionian_request_file_sync(self, file):
+        return self.rpc.ionian_requestFileSync([file])
+
     def ionian_get_file_info(self, data_root):
         return self.rpc.ionian_getFileInfo([data_root])
 
+    def ionian_get_file_info_batch(self, data_roots):
+        return self.rpc.ionian_getFileInfoBatch([data_roots])
+
+    def ionian_search_files(self, start_seq, limit, query=None):
+        return self.rpc.ionian_searchFiles([start_seq, limit, query])
+
+    def ionian_get_replication_count(self, data_root):
+        return self.rpc.ionian_getReplicationCount([data_root])
+
+    def ionian_pin_file(self, data_root):
+        return self.rpc.ionian_pinFile([data_root])
+
+    def ionian_unpin_file(self, data_root):
+        return self.rpc.ionian_unpinFile([data_root])
+
+    def ionian_check_tx_completed_batch(self, tx_seqs):
+        return self.rpc.ionian_checkTxCompletedBatch([tx_seqs])
+
     def shutdown(self):
         self.rpc.admin_shutdown()
         self.wait_until_stopped()
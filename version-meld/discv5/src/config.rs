@@ -34,6 +34,10 @@ pub struct Discv5Config {
     /// The maximum number of established sessions to maintain. Default: 1000.
     pub session_cache_capacity: usize,
 
+    /// The maximum number of raw requests awaiting a response. When exceeded, the request closest
+    /// to time out is failed. Default: 1000.
+    pub active_requests_capacity: usize,
+
     /// Updates the local ENR IP and port based on PONG responses from peers. Default: true.
     pub enr_update: bool,
 
@@ -117,6 +121,7 @@ impl Default for Discv5Config {
             request_retries: 1,
             session_timeout: Duration::from_secs(86400),
             session_cache_capacity: 1000,
+            active_requests_capacity: 1000,
             enr_update: true,
             max_nodes_response: 16,
             enr_peer_update_min: 10,
@@ -198,6 +203,12 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// The maximum number of raw requests awaiting a response.
+    pub fn active_requests_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.config.active_requests_capacity = capacity;
+        self
+    }
+
     /// Disables the auto-update of the local ENR IP and port based on PONG responses from peers.
     pub fn disable_enr_update(&mut self) -> &mut Self {
         self.config.enr_update = false;
@@ -324,6 +335,7 @@ impl std::fmt::Debug for Discv5Config {
         let _ = builder.field("request_retries", &self.request_retries);
         let _ = builder.field("session_timeout", &self.session_timeout);
         let _ = builder.field("session_cache_capacity", &self.session_cache_capacity);
+        let _ = builder.field("active_requests_capacity", &self.active_requests_capacity);
         let _ = builder.field("enr_update", &self.enr_update);
         let _ = builder.field("query_parallelism", &self.query_parallelism);
         let _ = builder.field("report_discovered_peers", &self.report_discovered_peers);
@@ -26,7 +26,7 @@ use enr::{CombinedKey, EnrError, EnrKey, NodeId};
 use parking_lot::RwLock;
 use std::{
     future::Future,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -41,8 +41,12 @@ use crate::metrics::{Metrics, METRICS};
 lazy_static! {
     pub static ref PERMIT_BAN_LIST: RwLock<crate::PermitBanList> =
         RwLock::new(crate::PermitBanList::default());
+    pub static ref IP_FILTER: RwLock<Option<IpFilter>> = RwLock::new(None);
 }
 
+/// A predicate of the source IPs whose unsolicited packets are accepted.
+pub type IpFilter = Arc<dyn Fn(&IpAddr) -> bool + Send + Sync>;
+
 mod test;
 
 /// Events that can be produced by the `Discv5` event stream.
@@ -341,6 +345,12 @@ impl Discv5 {
         PERMIT_BAN_LIST.write().permit_ips.remove(ip);
     }
 
+    /// Sets the predicate of the source IPs whose unsolicited packets are accepted. Packets from
+    /// the IPs rejected by the predicate are dropped, regardless of the permit list.
+    pub fn set_ip_filter(&self, filter: Option<IpFilter>) {
+        *IP_FILTER.write() = filter;
+    }
+
     /// Updates the local ENR TCP/UDP socket.
     pub fn update_local_enr_socket(&self, socket_addr: SocketAddr, is_tcp: bool) -> bool {
         let local_socket = self.local_enr.read().udp_socket();
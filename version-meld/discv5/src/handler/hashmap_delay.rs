@@ -2,6 +2,9 @@
 //! fixed time.
 //!
 //! A `HashMapDelay` implements `Stream` which removes expired items from the map.
+//!
+//! The map can optionally be bounded, in which case the entry closest to expiry is evicted to make
+//! room for a new entry, and handed to an eviction callback.
 
 /// The default delay for entries, in seconds. This is only used when `insert()` is used to add
 /// entries.
@@ -12,7 +15,7 @@ use std::{
     collections::HashMap,
     pin::Pin,
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio_util::time::delay_queue::{self, DelayQueue};
 
@@ -26,12 +29,19 @@ where
     expirations: DelayQueue<K>,
     /// The default expiration timeout of an entry.
     default_entry_timeout: Duration,
+    /// The maximum number of entries, along with the callback invoked for evicted entries.
+    capacity: Option<(usize, EvictionCallback<K, V>)>,
 }
 
+/// The callback invoked for an entry evicted when the map is full.
+type EvictionCallback<K, V> = Box<dyn FnMut(K, V) + Send + Sync>;
+
 /// A wrapping around entries that adds the link to the entry's expiration, via a `delay_queue` key.
 struct MapEntry<V> {
     /// The expiration key for the entry.
     key: delay_queue::Key,
+    /// The instant at which the entry expires.
+    deadline: Instant,
     /// The actual entry.
     value: V,
 }
@@ -55,6 +65,21 @@ where
             entries: HashMap::new(),
             expirations: DelayQueue::new(),
             default_entry_timeout,
+            capacity: None,
+        }
+    }
+
+    /// Creates a new instance of `HashMapDelay` which holds at most `max_capacity` entries. When
+    /// the map is full, inserting a new key evicts the entry closest to expiry, which is passed to
+    /// `on_evict`.
+    pub fn with_capacity(
+        default_entry_timeout: Duration,
+        max_capacity: usize,
+        on_evict: impl FnMut(K, V) + Send + Sync + 'static,
+    ) -> Self {
+        HashMapDelay {
+            capacity: Some((max_capacity, Box::new(on_evict))),
+            ..HashMapDelay::new(default_entry_timeout)
         }
     }
 
@@ -69,9 +94,11 @@ where
             // update the timeout
             self.update_timeout(&key, value, entry_duration);
         } else {
+            self.evict_if_full();
             let delay_key = self.expirations.insert(key.clone(), entry_duration);
             let entry = MapEntry {
                 key: delay_key,
+                deadline: Instant::now() + entry_duration,
                 value,
             };
             self.entries.insert(key, entry);
@@ -84,6 +111,7 @@ where
     pub fn update_timeout(&mut self, key: &K, value: V, timeout: Duration) -> bool {
         if let Some(entry) = self.entries.get_mut(key) {
             entry.value = value;
+            entry.deadline = Instant::now() + timeout;
             self.expirations.reset(&entry.key, timeout);
             true
         } else {
@@ -91,6 +119,32 @@ where
         }
     }
 
+    /// Evicts the entry closest to expiry if the map is bounded and full.
+    fn evict_if_full(&mut self) {
+        let max_capacity = match self.capacity {
+            Some((max_capacity, _)) => max_capacity,
+            None => return,
+        };
+
+        while !self.entries.is_empty() && self.entries.len() >= max_capacity {
+            let key = match self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.deadline)
+                .map(|(key, _)| key.clone())
+            {
+                Some(key) => key,
+                None => return,
+            };
+
+            if let Some(value) = self.remove(&key) {
+                if let Some((_, on_evict)) = self.capacity.as_mut() {
+                    on_evict(key, value);
+                }
+            }
+        }
+    }
+
     /// Gets a reference to an entry if it exists.
     ///
     /// Returns None if the entry does not exist.
@@ -105,6 +159,23 @@ where
         self.entries.get_mut(key).map(|entry| &mut entry.value)
     }
 
+    /// Returns the instant at which an entry expires.
+    ///
+    /// Returns None if the entry does not exist.
+    pub fn deadline(&self, key: &K) -> Option<Instant> {
+        self.entries.get(key).map(|entry| entry.deadline)
+    }
+
+    /// An iterator visiting all the live entries in arbitrary order, without removing them.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    /// An iterator visiting all the keys of live entries in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
     /// Returns true if the key exists, false otherwise.
     pub fn contains_key(&self, key: &K) -> bool {
         self.entries.contains_key(key)
@@ -168,3 +239,58 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn test_evict_closest_to_expiry() {
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let evicted_clone = evicted.clone();
+        let mut map = HashMapDelay::with_capacity(Duration::from_secs(10), 2, move |key, value| {
+            evicted_clone.lock().unwrap().push((key, value))
+        });
+
+        map.insert_at(1, "a", Duration::from_secs(20));
+        map.insert_at(2, "b", Duration::from_secs(5));
+
+        // updating an existing key never evicts
+        map.insert_at(1, "c", Duration::from_secs(30));
+        assert!(evicted.lock().unwrap().is_empty());
+
+        map.insert(3, "d");
+        assert_eq!(*evicted.lock().unwrap(), vec![(2, "b")]);
+        assert_eq!(map._len(), 2);
+        assert!(!map.contains_key(&2));
+        assert_eq!(map.get(&1), Some(&"c"));
+        assert_eq!(map.get(&3), Some(&"d"));
+    }
+
+    #[tokio::test]
+    async fn test_iter_and_deadline() {
+        let mut map = HashMapDelay::new(Duration::from_secs(10));
+        let now = Instant::now();
+        map.insert(1, "a");
+        map.insert_at(2, "b", Duration::from_secs(20));
+
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![(1, "a"), (2, "b")]);
+
+        let mut keys: Vec<_> = map.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, vec![1, 2]);
+
+        let deadline = map.deadline(&2).unwrap();
+        assert!(deadline >= now + Duration::from_secs(20));
+        assert!(deadline < Instant::now() + Duration::from_secs(20) + Duration::from_millis(1));
+        assert!(map.deadline(&1).unwrap() < deadline);
+        assert_eq!(map.deadline(&3), None);
+
+        // updating the timeout moves the deadline
+        map.update_timeout(&1, "c", Duration::from_secs(30));
+        assert!(map.deadline(&1).unwrap() > deadline);
+    }
+}
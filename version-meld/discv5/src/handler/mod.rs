@@ -58,7 +58,7 @@ pub use crate::node_info::{NodeAddress, NodeContact};
 use crate::metrics::METRICS;
 
 use crate::lru_time_cache::LruTimeCache;
-use hashmap_delay::HashMapDelay;
+pub use hashmap_delay::HashMapDelay;
 use session::Session;
 
 // The time interval to check banned peer timeouts and unban peers when the timeout has elapsed (in
@@ -200,6 +200,8 @@ pub struct Handler {
     key: Arc<RwLock<CombinedKey>>,
     /// Pending raw requests. A list of raw messages we are awaiting a response from the remote.
     active_requests: HashMapDelay<NodeAddress, RequestCall>,
+    /// Requests evicted from `active_requests` when it is full, which are failed.
+    evicted_requests: mpsc::UnboundedReceiver<(NodeAddress, RequestCall)>,
     // WHOAREYOU messages do not include the source node id. We therefore maintain another
     // mapping of active_requests via message_nonce. This allows us to match WHOAREYOU
     // requests with active requests sent.
@@ -242,6 +244,7 @@ impl Handler {
         // create the channels to send/receive messages from the application
         let (inbound_send, inbound_channel) = mpsc::unbounded_channel();
         let (outbound_channel, outbound_recv) = mpsc::channel(50);
+        let (evicted_send, evicted_requests) = mpsc::unbounded_channel();
 
         // Creates a SocketConfig to pass to the underlying UDP socket tasks.
 
@@ -290,7 +293,14 @@ impl Handler {
                     node_id,
                     enr,
                     key,
-                    active_requests: HashMapDelay::new(config.request_timeout),
+                    active_requests: HashMapDelay::with_capacity(
+                        config.request_timeout,
+                        config.active_requests_capacity,
+                        move |node_address, request_call| {
+                            let _ = evicted_send.send((node_address, request_call));
+                        },
+                    ),
+                    evicted_requests,
                     active_requests_nonce_mapping: HashMap::new(),
                     pending_requests: HashMap::new(),
                     filter_expected_responses,
@@ -337,6 +347,9 @@ impl Handler {
                 Some(Ok((node_address, pending_request))) = self.active_requests.next() => {
                     self.handle_request_timeout(node_address, pending_request).await;
                 }
+                Some((node_address, request_call)) = self.evicted_requests.recv() => {
+                    self.handle_request_evicted(node_address, request_call).await;
+                }
                 _ = banned_nodes_check.tick() => self.unban_nodes_check(), // Unban nodes that are past the timeout
                 _ = &mut self.exit => {
                     return;
@@ -448,6 +461,19 @@ impl Handler {
         }
     }
 
+    /// Fails a request evicted because too many requests are awaiting a response.
+    async fn handle_request_evicted(
+        &mut self,
+        node_address: NodeAddress,
+        request_call: RequestCall,
+    ) {
+        debug!("Request evicted with {}", node_address);
+        self.remove_expected_response(node_address.socket_addr);
+        // The request is failed as timed out, keeping any established session.
+        self.fail_request(request_call, RequestError::Timeout, false)
+            .await;
+    }
+
     /// Sends a `Request` to a node.
     async fn send_request(
         &mut self,
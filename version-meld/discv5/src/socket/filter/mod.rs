@@ -1,6 +1,11 @@
 //! A filter which decides whether to accept/reject incoming UDP packets.
 
-use crate::{discv5::PERMIT_BAN_LIST, metrics::METRICS, node_info::NodeAddress, packet::Packet};
+use crate::{
+    discv5::{IP_FILTER, PERMIT_BAN_LIST},
+    metrics::METRICS,
+    node_info::NodeAddress,
+    packet::Packet,
+};
 use cache::ReceivedPacketCache;
 use enr::NodeId;
 use lru::LruCache;
@@ -78,6 +83,13 @@ impl Filter {
     /// The first check. This determines if a new UDP packet should be decoded or dropped.
     /// Only unsolicited packets arrive here.
     pub fn initial_pass(&mut self, src: &SocketAddr) -> bool {
+        if let Some(ip_filter) = IP_FILTER.read().as_ref() {
+            if !ip_filter(&src.ip()) {
+                debug!("Dropped unsolicited packet from filtered src: {:?}", src);
+                return false;
+            }
+        }
+
         if PERMIT_BAN_LIST.read().permit_ips.get(&src.ip()).is_some() {
             return true;
         }
@@ -1,6 +1,6 @@
 use super::*;
 use core::num::NonZeroUsize;
-use ethereum_types::{H256, U128, U256};
+use ethereum_types::{H160, H256, U128, U256};
 use smallvec::SmallVec;
 use std::sync::Arc;
 
@@ -277,6 +277,27 @@ impl Decode for H256 {
     }
 }
 
+impl Decode for H160 {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        20
+    }
+
+    fn from_ssz_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let len = bytes.len();
+        let expected = <Self as Decode>::ssz_fixed_len();
+
+        if len != expected {
+            Err(DecodeError::InvalidByteLength { len, expected })
+        } else {
+            Ok(H160::from_slice(bytes))
+        }
+    }
+}
+
 impl Decode for U256 {
     fn is_ssz_fixed_len() -> bool {
         true
@@ -494,6 +515,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_h160() {
+        assert_eq!(
+            H160::from_ssz_bytes(&[0; 21]),
+            Err(DecodeError::InvalidByteLength {
+                len: 21,
+                expected: 20
+            })
+        );
+
+        assert_eq!(H160::from_ssz_bytes(&[1; 20]), Ok(H160::from([1; 20])));
+    }
+
     #[test]
     fn invalid_h256() {
         assert_eq!(
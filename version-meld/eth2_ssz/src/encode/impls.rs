@@ -1,6 +1,6 @@
 use super::*;
 use core::num::NonZeroUsize;
-use ethereum_types::{H256, U128, U256};
+use ethereum_types::{H160, H256, U128, U256};
 use smallvec::SmallVec;
 use std::sync::Arc;
 
@@ -323,6 +323,24 @@ impl Encode for H256 {
     }
 }
 
+impl Encode for H160 {
+    fn is_ssz_fixed_len() -> bool {
+        true
+    }
+
+    fn ssz_fixed_len() -> usize {
+        20
+    }
+
+    fn ssz_bytes_len(&self) -> usize {
+        20
+    }
+
+    fn ssz_append(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
 impl Encode for U256 {
     fn is_ssz_fixed_len() -> bool {
         true
@@ -488,6 +506,12 @@ mod tests {
         assert_eq!(H256::from_slice(&bytes).as_ssz_bytes(), bytes);
     }
 
+    #[test]
+    fn ssz_encode_h160() {
+        assert_eq!(H160::from(&[0; 20]).as_ssz_bytes(), vec![0; 20]);
+        assert_eq!(H160::from(&[1; 20]).as_ssz_bytes(), vec![1; 20]);
+    }
+
     #[test]
     fn ssz_encode_u8_array_4() {
         assert_eq!([0, 0, 0, 0].as_ssz_bytes(), vec![0; 4]);